@@ -219,6 +219,46 @@ pub async fn refresh_all_quotas(
 
     Ok(stats)
 }
+/// 获取各账号的配额消耗预测 (account_id -> forecast)
+#[tauri::command]
+pub async fn get_quota_forecasts(
+) -> Result<std::collections::HashMap<String, modules::quota_forecast::AccountForecast>, String> {
+    let data_dir = modules::account::get_data_dir()?;
+    let history = modules::quota_forecast::load_history(&data_dir);
+    Ok(modules::quota_forecast::forecast_all(&history, chrono::Utc::now().timestamp()))
+}
+
+#[derive(serde::Serialize)]
+pub struct UsageReportResult {
+    pub date: String,
+    pub content: String,
+    pub delivered: Vec<String>,
+}
+
+/// 生成用量报告 (默认昨天)，deliver 为 true 时按配置推送 / 保存
+#[tauri::command]
+pub async fn generate_usage_report(
+    config: crate::models::UsageReportConfig,
+    date: Option<String>,
+    deliver: bool,
+) -> Result<UsageReportResult, String> {
+    let date = match date.filter(|d| !d.trim().is_empty()) {
+        Some(d) => chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").map_err(|e| format!("无效日期: {}", e))?,
+        None => chrono::Local::now().date_naive() - chrono::Duration::days(1),
+    };
+    let report = modules::usage_report::generate(date, &config)?;
+    let delivered = if deliver {
+        modules::usage_report::deliver(&config, &report).await?
+    } else {
+        Vec::new()
+    };
+    Ok(UsageReportResult {
+        date: report.date.clone(),
+        content: modules::usage_report::render(&report, &config.format),
+        delivered,
+    })
+}
+
 /// 获取设备指纹（当前 storage.json + 账号绑定）
 #[tauri::command]
 pub async fn get_device_profiles(
@@ -334,6 +374,23 @@ pub async fn save_config(
         instance.axum_server.update_security(&config.proxy).await;
         // 更新 z.ai 配置
         instance.axum_server.update_zai(&config.proxy).await;
+        // 更新路由信息暴露开关
+        instance.axum_server.update_routing_info(&config.proxy);
+        // 更新内容过滤规则
+        instance.axum_server.update_content_filter(&config.proxy).await;
+        // 更新客户端兼容性配置
+        instance.axum_server.update_client_profiles(&config.proxy).await;
+        // 更新实验性功能配置 (功能开关)
+        instance.axum_server.update_experimental(&config.proxy.experimental).await;
+        // 更新每日用量上限
+        instance
+            .token_manager
+            .update_usage_limits(config.proxy.usage_limits.clone())
+            .await;
+        // 更新配额分组规则
+        instance
+            .token_manager
+            .update_quota_groups(config.proxy.quota_groups.clone());
         tracing::debug!("已同步热更新反代服务配置");
     }
 
@@ -582,6 +639,21 @@ pub async fn clear_log_cache() -> Result<(), String> {
     modules::logger::clear_logs()
 }
 
+/// 获取运行时日志设置
+#[tauri::command]
+pub async fn get_log_settings() -> Result<modules::logger::LogSettings, String> {
+    Ok(modules::logger::log_settings())
+}
+
+/// 运行时调整日志过滤指令 (如 `proxy::mappers=debug`) 与原始请求体日志，无需重启
+#[tauri::command]
+pub async fn set_log_settings(
+    filter: Option<String>,
+    raw_body_logging: Option<bool>,
+) -> Result<modules::logger::LogSettings, String> {
+    modules::logger::update_log_settings(filter.as_deref(), raw_body_logging)
+}
+
 /// 打开数据目录
 #[tauri::command]
 pub async fn open_data_folder() -> Result<(), String> {
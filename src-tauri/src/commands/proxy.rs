@@ -14,6 +14,9 @@ pub struct ProxyStatus {
     pub port: u16,
     pub base_url: String,
     pub active_accounts: usize,
+    /// 离线检测判定上游不可达
+    #[serde(default)]
+    pub offline: bool,
 }
 
 /// 反代服务全局状态
@@ -39,6 +42,37 @@ impl ProxyServiceState {
     }
 }
 
+/// 按配置启动 Axum 服务器
+async fn start_axum_server(
+    config: &ProxyConfig,
+    host: &str,
+    token_manager: Arc<TokenManager>,
+    monitor: Arc<ProxyMonitor>,
+) -> Result<(crate::proxy::AxumServer, tokio::task::JoinHandle<()>), String> {
+    crate::proxy::AxumServer::start(
+        host.to_string(),
+        config.port,
+        token_manager,
+        config.custom_mapping.clone(),
+        config.model_aliases.clone(),
+        config.request_timeout,
+        config.upstream_proxy.clone(),
+        crate::proxy::ProxySecurityConfig::from_proxy_config(config),
+        config.zai.clone(),
+        monitor,
+        config.experimental.clone(),
+        config.fixtures.clone(),
+        config.chaos.clone(),
+        config.expose_routing_info,
+        config.content_filter.clone(),
+        config.client_profiles.clone(),
+        config.upload_limits.clone(),
+        config.local_socket.clone(),
+        config.protocol_listeners.clone(),
+    )
+    .await
+}
+
 /// 启动反代服务
 #[tauri::command]
 pub async fn start_proxy_service(
@@ -76,6 +110,10 @@ pub async fn start_proxy_service(
     let token_manager = Arc::new(TokenManager::new(accounts_dir));
     // 同步 UI 传递的调度配置
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    // 同步每日用量上限配置
+    token_manager.update_usage_limits(config.usage_limits.clone()).await;
+    // 同步配额分组规则
+    token_manager.update_quota_groups(config.quota_groups.clone());
     
     // 3. 加载账号
     let active_accounts = token_manager.load_accounts().await
@@ -91,23 +129,14 @@ pub async fn start_proxy_service(
     
     // 启动 Axum 服务器
     let (axum_server, server_handle) =
-        match crate::proxy::AxumServer::start(
-            config.get_bind_address().to_string(),
-            config.port,
-            token_manager.clone(),
-            config.custom_mapping.clone(),
-            config.request_timeout,
-            config.upstream_proxy.clone(),
-            crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
-            config.zai.clone(),
-            monitor.clone(),
-            config.experimental.clone(),
-
-        ).await {
+        match start_axum_server(&config, config.get_bind_address(), token_manager.clone(), monitor.clone()).await {
             Ok((server, handle)) => (server, handle),
             Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
         };
     
+    // 账号统计定期写入 account_stats.json
+    token_manager.start_stats_persistence();
+
     // 创建服务实例
     let instance = ProxyServiceInstance {
         config: config.clone(),
@@ -129,6 +158,7 @@ pub async fn start_proxy_service(
         port: config.port,
         base_url: format!("http://127.0.0.1:{}", config.port),
         active_accounts,
+        offline: false,
     })
 }
 
@@ -148,6 +178,8 @@ pub async fn stop_proxy_service(
         instance.axum_server.stop();
         // 等待服务器任务完成
         instance.server_handle.await.ok();
+        // 写入最后一次统计与运行时状态快照
+        instance.token_manager.persist_state();
     }
     
     Ok(())
@@ -166,12 +198,14 @@ pub async fn get_proxy_status(
             port: instance.config.port,
             base_url: format!("http://127.0.0.1:{}", instance.config.port),
             active_accounts: instance.token_manager.len(),
+            offline: instance.axum_server.connectivity.is_offline(),
         }),
         None => Ok(ProxyStatus {
             running: false,
             port: 0,
             base_url: String::new(),
             active_accounts: 0,
+            offline: false,
         }),
     }
 }
@@ -248,6 +282,64 @@ pub async fn get_proxy_log_detail(
     crate::modules::proxy_db::get_log_detail(&log_id)
 }
 
+/// 获取 A/B 路由实验汇总报告 (基于最近的带标签请求日志)
+#[tauri::command]
+pub async fn get_experiment_report(
+    limit: Option<usize>,
+) -> Result<Vec<crate::proxy::experiments::ExperimentArmSummary>, String> {
+    let logs = crate::modules::proxy_db::get_experiment_logs(limit.unwrap_or(5000))?;
+    Ok(crate::proxy::experiments::summarize(&logs))
+}
+
+/// 协议一致性自检：以官方 SDK 的请求形态调用反代并校验响应结构
+/// 服务运行中时直接使用当前实例；否则按已保存的配置在随机端口上临时启动一个实例，自检结束后关闭
+#[tauri::command]
+pub async fn run_conformance_self_test(
+    state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::conformance::ConformanceReport, String> {
+    if let Some(instance) = state.instance.read().await.as_ref() {
+        let base_url = format!("http://127.0.0.1:{}", instance.config.port);
+        let api_key = instance.config.api_key.clone();
+        return Ok(crate::proxy::conformance::run(&base_url, Some(&api_key)).await);
+    }
+
+    let mut config = crate::modules::config::load_app_config()?.proxy;
+    config.port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .map_err(|e| format!("无法分配临时端口: {}", e))?
+        .port();
+    // 临时实例只监听主端口
+    config.local_socket.enabled = false;
+    config.protocol_listeners.clear();
+
+    let token_manager = Arc::new(TokenManager::new(crate::modules::account::get_data_dir()?));
+    token_manager.update_sticky_config(config.scheduling.clone()).await;
+    token_manager.load_accounts().await.map_err(|e| format!("加载账号失败: {}", e))?;
+    let monitor = Arc::new(ProxyMonitor::new(100, None));
+
+    let (server, handle) = start_axum_server(&config, "127.0.0.1", token_manager, monitor).await?;
+    let base_url = format!("http://127.0.0.1:{}", config.port);
+    let report = crate::proxy::conformance::run(&base_url, Some(&config.api_key)).await;
+    server.stop();
+    handle.await.ok();
+    Ok(report)
+}
+
+/// 导出会话对话记录 (format: "markdown" | "json")
+#[tauri::command]
+pub async fn export_session_transcript(
+    session_id: String,
+    format: Option<String>,
+) -> Result<String, String> {
+    let format = crate::proxy::transcript::TranscriptFormat::parse(format.as_deref().unwrap_or_default())?;
+    let logs = crate::modules::proxy_db::get_session_logs(&session_id)?;
+    if logs.is_empty() {
+        return Err(format!("No requests recorded for session {}", session_id));
+    }
+    let transcript = crate::proxy::transcript::build(&session_id, &logs);
+    crate::proxy::transcript::render(&transcript, format)
+}
+
 /// 生成 API Key
 #[tauri::command]
 pub fn generate_api_key() -> String {
@@ -289,6 +381,7 @@ pub async fn update_model_mapping(
     // 2. 无论是否运行，都保存到全局配置持久化
     let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
     app_config.proxy.custom_mapping = config.custom_mapping;
+    app_config.proxy.model_aliases = config.model_aliases;
     crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
     
     Ok(())
@@ -392,7 +485,7 @@ pub async fn fetch_zai_models(
     let text = resp.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
 
     if !status.is_success() {
-        let preview = if text.len() > 4000 { &text[..4000] } else { &text };
+        let preview = crate::proxy::common::text_preview::truncate_bytes(&text, 4000);
         return Err(format!("Upstream returned {}: {}", status, preview));
     }
 
@@ -405,6 +498,67 @@ pub async fn fetch_zai_models(
     Ok(models)
 }
 
+/// 获取账号使用统计 (account_id -> stats)
+/// 服务运行时返回内存中的最新值，否则读取 account_stats.json
+#[tauri::command]
+pub async fn get_account_stats(
+    state: State<'_, ProxyServiceState>,
+) -> Result<std::collections::HashMap<String, crate::proxy::account_stats::AccountStats>, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        return Ok(instance.token_manager.account_stats());
+    }
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(crate::proxy::account_stats::read_stats_file(
+        &data_dir.join(crate::proxy::account_stats::STATS_FILE),
+    ))
+}
+
+/// 列出实验性功能开关 (类型 / 默认值 / 当前值 / 说明)
+#[tauri::command]
+pub async fn list_feature_flags() -> Result<Vec<crate::proxy::feature_flags::FlagInfo>, String> {
+    let app_config = crate::modules::config::load_app_config()?;
+    Ok(crate::proxy::feature_flags::list(&app_config.proxy.experimental))
+}
+
+/// 修改实验性功能开关 (持久化，服务运行时立即生效)
+#[tauri::command]
+pub async fn set_feature_flag(
+    key: String,
+    value: serde_json::Value,
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::feature_flags::FlagInfo>, String> {
+    let mut app_config = crate::modules::config::load_app_config()?;
+    crate::proxy::feature_flags::set(&mut app_config.proxy.experimental, &key, &value)?;
+    crate::modules::config::save_app_config(&app_config)?;
+
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        instance.axum_server.update_experimental(&app_config.proxy.experimental).await;
+    }
+    Ok(crate::proxy::feature_flags::list(&app_config.proxy.experimental))
+}
+
+/// 压测：按目标 RPS 回放最近录制的请求，报告延迟分位数、错误率与账号轮换情况 (会真实消耗配额)
+#[tauri::command]
+pub async fn run_load_test(
+    options: crate::proxy::load_test::LoadTestOptions,
+    state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::load_test::LoadTestReport, String> {
+    let (port, api_key) = match state.instance.read().await.as_ref() {
+        Some(instance) => (instance.config.port, instance.config.api_key.clone()),
+        None => return Err("服务未运行，请先启动反代服务".to_string()),
+    };
+    let sample_size = options.sample_size.max(1);
+    let rows = tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_replay_samples(sample_size))
+        .await
+        .map_err(|e| e.to_string())??;
+    let samples = crate::proxy::load_test::select_samples(rows, options.path_prefix.as_deref());
+
+    let base_url = format!("http://127.0.0.1:{}", port);
+    crate::proxy::load_test::run(&base_url, Some(&api_key), samples, &options).await
+}
+
 /// 获取当前调度配置
 #[tauri::command]
 pub async fn get_proxy_scheduling_config(
@@ -447,3 +601,67 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+/// 单个账号当前持有的会话绑定
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountSessionBindings {
+    pub account_id: String,
+    pub email: String,
+    pub bindings: Vec<crate::proxy::session_bindings::SessionBindingInfo>,
+}
+
+/// 会话绑定列表与流转统计
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SessionBindingsReport {
+    pub stats: crate::proxy::session_bindings::SessionBindingStats,
+    pub accounts: Vec<AccountSessionBindings>,
+}
+
+/// 获取当前会话粘性绑定 (按账号分组) 及绑定流转统计
+#[tauri::command]
+pub async fn get_proxy_session_bindings(
+    state: State<'_, ProxyServiceState>,
+) -> Result<SessionBindingsReport, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        let token_manager = &instance.token_manager;
+        let accounts = token_manager
+            .session_bindings_by_account()
+            .into_iter()
+            .map(|(account_id, email, bindings)| AccountSessionBindings { account_id, email, bindings })
+            .collect();
+        Ok(SessionBindingsReport {
+            stats: token_manager.session_binding_stats(),
+            accounts,
+        })
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+
+/// 列出当前正在输出的流式请求
+#[tauri::command]
+pub async fn get_inflight_requests(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::inflight::InflightRequest>, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.axum_server.inflight.list())
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+/// 强制终止指定追踪 ID 的流式请求，返回终止的数量
+#[tauri::command]
+pub async fn kill_inflight_request(
+    trace_id: String,
+    state: State<'_, ProxyServiceState>,
+) -> Result<usize, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.axum_server.inflight.kill(&trace_id))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
@@ -66,6 +66,8 @@ pub fn run() {
             
             // 启动智能调度器
             modules::scheduler::start_scheduler(app.handle().clone());
+            // 每日用量报告
+            modules::usage_report::start_usage_report_scheduler();
             
             Ok(())
         })
@@ -104,6 +106,8 @@ pub fn run() {
             // 配额命令
             commands::fetch_account_quota,
             commands::refresh_all_quotas,
+            commands::get_quota_forecasts,
+            commands::generate_usage_report,
             // 配置命令
             commands::load_config,
             commands::save_config,
@@ -118,6 +122,8 @@ pub fn run() {
             commands::sync_account_from_db,
             commands::save_text_file,
             commands::clear_log_cache,
+            commands::get_log_settings,
+            commands::set_log_settings,
             commands::open_data_folder,
             commands::get_data_dir_path,
             commands::show_main_window,
@@ -136,6 +142,9 @@ pub fn run() {
             commands::proxy::get_proxy_stats,
             commands::proxy::get_proxy_logs,
             commands::proxy::get_proxy_logs_paginated,
+            commands::proxy::get_experiment_report,
+            commands::proxy::export_session_transcript,
+            commands::proxy::run_conformance_self_test,
             commands::proxy::get_proxy_log_detail,
             commands::proxy::set_proxy_monitor_enabled,
             commands::proxy::clear_proxy_logs,
@@ -146,6 +155,13 @@ pub fn run() {
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::get_proxy_session_bindings,
+            commands::proxy::get_inflight_requests,
+            commands::proxy::kill_inflight_request,
+            commands::proxy::get_account_stats,
+            commands::proxy::list_feature_flags,
+            commands::proxy::set_feature_flag,
+            commands::proxy::run_load_test,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
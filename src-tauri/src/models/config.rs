@@ -21,6 +21,8 @@ pub struct AppConfig {
     pub scheduled_warmup: ScheduledWarmupConfig, // [NEW] 定时预热配置
     #[serde(default)]
     pub quota_protection: QuotaProtectionConfig, // [NEW] 配额保护配置
+    #[serde(default)]
+    pub usage_report: UsageReportConfig, // [NEW] 每日用量报告
 }
 
 /// 定时预热配置
@@ -92,6 +94,58 @@ impl Default for QuotaProtectionConfig {
     }
 }
 
+/// 每日用量报告配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReportConfig {
+    /// 是否每天自动生成昨日报告
+    pub enabled: bool,
+
+    /// 生成时间 (本地时间的小时，0-23)
+    #[serde(default = "default_report_hour")]
+    pub hour: u32,
+
+    /// 输出格式: markdown / html
+    #[serde(default = "default_report_format")]
+    pub format: String,
+
+    /// Webhook 地址 (留空则不推送)
+    #[serde(default)]
+    pub webhook_url: String,
+
+    /// 保存目录 (留空则不保存到磁盘)
+    #[serde(default)]
+    pub save_dir: String,
+
+    /// 报告中列出的会话数
+    #[serde(default = "default_top_sessions")]
+    pub top_sessions: usize,
+}
+
+fn default_report_hour() -> u32 {
+    8
+}
+
+fn default_report_format() -> String {
+    "markdown".to_string()
+}
+
+fn default_top_sessions() -> usize {
+    5
+}
+
+impl Default for UsageReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            hour: default_report_hour(),
+            format: default_report_format(),
+            webhook_url: String::new(),
+            save_dir: String::new(),
+            top_sessions: default_top_sessions(),
+        }
+    }
+}
+
 impl AppConfig {
     pub fn new() -> Self {
         Self {
@@ -108,6 +162,7 @@ impl AppConfig {
             auto_launch: false,
             scheduled_warmup: ScheduledWarmupConfig::default(),
             quota_protection: QuotaProtectionConfig::default(),
+            usage_report: UsageReportConfig::default(),
         }
     }
 }
@@ -621,6 +621,10 @@ pub fn set_current_account_id(account_id: &str) -> Result<(), String> {
 /// 更新账号配额
 pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), String> {
     let mut account = load_account(account_id)?;
+    // 记录配额采样，用于消耗预测
+    if let Err(e) = crate::modules::quota_forecast::record_quota(&get_data_dir()?, account_id, &quota) {
+        crate::modules::logger::log_warn(&format!("记录配额历史失败: {}", e));
+    }
     account.update_quota(quota);
 
     // --- 配额保护逻辑开始 ---
@@ -1,7 +1,9 @@
 use tracing::{info, warn, error};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use crate::modules::account::get_data_dir;
 
 // 自定义本地时区时间格式化器
@@ -14,6 +16,90 @@ impl tracing_subscriber::fmt::time::FormatTime for LocalTimer {
     }
 }
 
+/// 运行时可替换的过滤层句柄 (init_logger 之后可用)
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+/// 是否记录上游请求 / 响应原文
+static RAW_BODY_LOGGING: AtomicBool = AtomicBool::new(false);
+
+/// 本 crate 的顶层模块，过滤指令中可省略 crate 名 (如 `proxy::mappers=debug`)
+const CRATE_MODULES: &[&str] = &["proxy", "modules", "commands", "models", "utils", "error"];
+
+/// 补全省略了 crate 名的过滤指令
+pub fn expand_filter_directives(directives: &str) -> String {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|directive| {
+            let first = directive.split(['=', ':', '[']).next().unwrap_or_default();
+            if CRATE_MODULES.contains(&first) {
+                format!("{}::{}", env!("CARGO_CRATE_NAME"), directive)
+            } else {
+                directive.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 当前生效的过滤指令
+pub fn current_log_filter() -> Option<String> {
+    FILTER_HANDLE.get()?.with_current(|f| f.to_string()).ok()
+}
+
+/// 运行时替换过滤指令 (无需重启)，返回生效后的指令
+pub fn set_log_filter(directives: &str) -> Result<String, String> {
+    let expanded = expand_filter_directives(directives);
+    if expanded.is_empty() {
+        return Err("日志过滤指令不能为空".to_string());
+    }
+    let filter = EnvFilter::try_new(&expanded).map_err(|e| format!("无效的日志过滤指令: {}", e))?;
+    let handle = FILTER_HANDLE.get().ok_or("日志系统尚未初始化")?;
+    handle.reload(filter).map_err(|e| format!("更新日志过滤失败: {}", e))?;
+    info!("日志过滤已更新为: {}", expanded);
+    Ok(expanded)
+}
+
+pub fn raw_body_logging() -> bool {
+    RAW_BODY_LOGGING.load(Ordering::Relaxed)
+}
+
+pub fn set_raw_body_logging(enabled: bool) {
+    if RAW_BODY_LOGGING.swap(enabled, Ordering::Relaxed) != enabled {
+        info!("原始请求体日志已{}", if enabled { "开启" } else { "关闭" });
+    }
+}
+
+/// 运行时日志设置 (Tauri 命令与 /internal/logging 共用)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LogSettings {
+    /// 当前过滤指令，日志系统未初始化时为空
+    pub filter: Option<String>,
+    pub raw_body_logging: bool,
+}
+
+pub fn log_settings() -> LogSettings {
+    LogSettings { filter: current_log_filter(), raw_body_logging: raw_body_logging() }
+}
+
+/// 按需更新过滤指令与原始请求体日志开关，返回更新后的设置
+pub fn update_log_settings(filter: Option<&str>, raw_body: Option<bool>) -> Result<LogSettings, String> {
+    if let Some(filter) = filter {
+        set_log_filter(filter)?;
+    }
+    if let Some(enabled) = raw_body {
+        set_raw_body_logging(enabled);
+    }
+    Ok(log_settings())
+}
+
+/// 开启原始请求体日志时记录上游请求 / 响应原文 (target = raw_body)
+pub fn log_raw_body(label: &str, body: &str) {
+    if raw_body_logging() {
+        info!(target: "raw_body", "[RawBody] {}: {}", label, body);
+    }
+}
+
 pub fn get_log_dir() -> Result<PathBuf, String> {
     let data_dir = get_data_dir()?;
     let log_dir = data_dir.join("logs");
@@ -61,6 +147,9 @@ pub fn init_logger() {
     // 4. 设置过滤层 (默认使用 INFO 级别以减少日志体积)
     let filter_layer = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info"));
+    // [NEW] 包装为可重载层，支持运行时调整 (见 set_log_filter)
+    let (filter_layer, filter_handle) = reload::Layer::new(filter_layer);
+    let _ = FILTER_HANDLE.set(filter_handle);
 
     // 5. 初始化全局订阅器 (使用 try_init 避免重复初始化崩溃)
     let _ = tracing_subscriber::registry()
@@ -182,3 +271,17 @@ pub fn log_warn(message: &str) {
 pub fn log_error(message: &str) {
     error!("{}", message);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_filter_directives() {
+        assert_eq!(
+            expand_filter_directives("info, proxy::mappers=debug,hyper=warn"),
+            format!("info,{}::proxy::mappers=debug,hyper=warn", env!("CARGO_CRATE_NAME"))
+        );
+        assert_eq!(expand_filter_directives(" , "), "");
+    }
+}
@@ -13,6 +13,8 @@ pub mod proxy_db;
 pub mod device;
 pub mod update_checker;
 pub mod scheduler;
+pub mod quota_forecast;
+pub mod usage_report;
 
 use crate::models;
 
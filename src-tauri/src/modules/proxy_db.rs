@@ -1,5 +1,6 @@
 use rusqlite::{params, Connection};
 use std::path::PathBuf;
+use crate::proxy::common::timing::RequestTiming;
 use crate::proxy::monitor::ProxyRequestLog;
 
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
@@ -32,12 +33,24 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN client TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN experiment TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN session_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN thinking_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN transform_ms INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN upstream_ttfb_ms INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN stream_ms INTEGER", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
         [],
     ).map_err(|e| e.to_string())?;
 
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_session ON request_logs (session_id)",
+        [],
+    ).map_err(|e| e.to_string())?;
+
     // Add status index for faster stats queries
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_status ON request_logs (status)",
@@ -50,10 +63,11 @@ pub fn init_db() -> Result<(), String> {
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let timing = log.timing.clone().unwrap_or_default();
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, client, experiment, session_id, thinking_tokens, transform_ms, upstream_ttfb_ms, stream_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             log.id,
             log.timestamp,
@@ -69,12 +83,29 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.client,
+            log.experiment,
+            log.session_id,
+            log.thinking_tokens,
+            timing.transform_ms,
+            timing.upstream_ttfb_ms,
+            timing.stream_ms,
         ],
     ).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// 读取耗时分解列 (transform_ms, upstream_ttfb_ms, stream_ms)，全部为空时返回 None
+fn timing_from_row(row: &rusqlite::Row, start: usize) -> Option<RequestTiming> {
+    let timing = RequestTiming {
+        transform_ms: row.get(start).unwrap_or(None),
+        upstream_ttfb_ms: row.get(start + 1).unwrap_or(None),
+        stream_ms: row.get(start + 2).unwrap_or(None),
+    };
+    (!timing.is_empty()).then_some(timing)
+}
+
 /// Get logs summary (without large request_body and response_body fields) with pagination
 pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let db_path = get_proxy_db_path()?;
@@ -83,7 +114,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model
+                input_tokens, output_tokens, account_email, mapped_model, client, experiment, session_id, thinking_tokens,
+                transform_ms, upstream_ttfb_ms, stream_ms
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -105,6 +137,11 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             response_body: None, // Don't query large fields for list view
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            client: row.get(14).unwrap_or(None),
+            experiment: row.get(15).unwrap_or(None),
+            session_id: row.get(16).unwrap_or(None),
+            thinking_tokens: row.get(17).unwrap_or(None),
+            timing: timing_from_row(row, 18),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -115,6 +152,46 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     Ok(logs)
 }
 
+/// 时间范围内的日志摘要 (不含请求/响应体)，用于用量报告
+pub fn get_logs_between(start_ms: i64, end_ms: i64) -> Result<Vec<ProxyRequestLog>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, method, url, status, duration, model, error,
+                input_tokens, output_tokens, account_email, mapped_model, client, experiment, session_id, thinking_tokens
+         FROM request_logs
+         WHERE timestamp >= ?1 AND timestamp < ?2
+         ORDER BY timestamp ASC"
+    ).map_err(|e| e.to_string())?;
+
+    let logs_iter = stmt.query_map([start_ms, end_ms], |row| {
+        Ok(ProxyRequestLog {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            method: row.get(2)?,
+            url: row.get(3)?,
+            status: row.get(4)?,
+            duration: row.get(5)?,
+            model: row.get(6)?,
+            error: row.get(7)?,
+            request_body: None,
+            response_body: None,
+            input_tokens: row.get(8).unwrap_or(None),
+            output_tokens: row.get(9).unwrap_or(None),
+            account_email: row.get(10).unwrap_or(None),
+            mapped_model: row.get(11).unwrap_or(None),
+            client: row.get(12).unwrap_or(None),
+            experiment: row.get(13).unwrap_or(None),
+            session_id: row.get(14).unwrap_or(None),
+            thinking_tokens: row.get(15).unwrap_or(None),
+            timing: None,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    logs_iter.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
 /// Get logs (backward compatible, calls get_logs_summary)
 pub fn get_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
     get_logs_summary(limit, 0)
@@ -150,7 +227,8 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model
+                account_email, mapped_model, client, experiment, session_id, thinking_tokens,
+                transform_ms, upstream_ttfb_ms, stream_ms
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -171,10 +249,125 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            client: row.get(14).unwrap_or(None),
+            experiment: row.get(15).unwrap_or(None),
+            session_id: row.get(16).unwrap_or(None),
+            thinking_tokens: row.get(17).unwrap_or(None),
+            timing: timing_from_row(row, 18),
         })
     }).map_err(|e| e.to_string())
 }
 
+/// Get all logs of a session in chronological order (with request_body and response_body, used for transcript export)
+pub fn get_session_logs(session_id: &str) -> Result<Vec<ProxyRequestLog>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, method, url, status, duration, model, error,
+                request_body, response_body, input_tokens, output_tokens,
+                account_email, mapped_model, client, experiment, session_id, thinking_tokens,
+                transform_ms, upstream_ttfb_ms, stream_ms
+         FROM request_logs
+         WHERE session_id = ?1
+         ORDER BY timestamp ASC"
+    ).map_err(|e| e.to_string())?;
+
+    let logs_iter = stmt.query_map([session_id], |row| {
+        Ok(ProxyRequestLog {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            method: row.get(2)?,
+            url: row.get(3)?,
+            status: row.get(4)?,
+            duration: row.get(5)?,
+            model: row.get(6)?,
+            mapped_model: row.get(13).unwrap_or(None),
+            account_email: row.get(12).unwrap_or(None),
+            error: row.get(7)?,
+            request_body: row.get(8).unwrap_or(None),
+            response_body: row.get(9).unwrap_or(None),
+            input_tokens: row.get(10).unwrap_or(None),
+            output_tokens: row.get(11).unwrap_or(None),
+            client: row.get(14).unwrap_or(None),
+            experiment: row.get(15).unwrap_or(None),
+            session_id: row.get(16).unwrap_or(None),
+            thinking_tokens: row.get(17).unwrap_or(None),
+            timing: timing_from_row(row, 18),
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut logs = Vec::new();
+    for log in logs_iter {
+        logs.push(log.map_err(|e| e.to_string())?);
+    }
+    Ok(logs)
+}
+
+/// Get the most recent logs tagged with an A/B experiment (with request_body, used for the experiment report)
+pub fn get_experiment_logs(limit: usize) -> Result<Vec<ProxyRequestLog>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, method, url, status, duration, model, error,
+                request_body, input_tokens, output_tokens, account_email, mapped_model, client, experiment
+         FROM request_logs
+         WHERE experiment IS NOT NULL
+         ORDER BY timestamp DESC
+         LIMIT ?1"
+    ).map_err(|e| e.to_string())?;
+
+    let logs_iter = stmt.query_map([limit], |row| {
+        Ok(ProxyRequestLog {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            method: row.get(2)?,
+            url: row.get(3)?,
+            status: row.get(4)?,
+            duration: row.get(5)?,
+            model: row.get(6)?,
+            mapped_model: row.get(12).unwrap_or(None),
+            account_email: row.get(11).unwrap_or(None),
+            error: row.get(7)?,
+            request_body: row.get(8).unwrap_or(None),
+            response_body: None,
+            input_tokens: row.get(9).unwrap_or(None),
+            output_tokens: row.get(10).unwrap_or(None),
+            client: row.get(13).unwrap_or(None),
+            experiment: row.get(14).unwrap_or(None),
+            session_id: None,
+            thinking_tokens: None,
+            timing: None,
+        })
+    }).map_err(|e| e.to_string())?;
+
+    let mut logs = Vec::new();
+    for log in logs_iter {
+        logs.push(log.map_err(|e| e.to_string())?);
+    }
+    Ok(logs)
+}
+
+/// 最近成功的 POST 请求 (含请求体)，供压测回放
+/// 只返回 (method, url, request_body)，跳过请求体未记录或被替换为占位文本的日志
+pub fn get_replay_samples(limit: usize) -> Result<Vec<(String, String, String)>, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT method, url, request_body
+         FROM request_logs
+         WHERE method = 'POST' AND status < 400 AND request_body LIKE '{%'
+         ORDER BY timestamp DESC
+         LIMIT ?1"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt.query_map([limit], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
 /// Cleanup old logs (keep last N days)
 pub fn cleanup_old_logs(days: i64) -> Result<usize, String> {
     let db_path = get_proxy_db_path()?;
@@ -0,0 +1,199 @@
+// 配额消耗预测 (Quota Forecast)
+// 每次刷新配额时记录各模型剩余百分比的采样 (sidecar 文件 quota_history.json，不改动账号 JSON)，
+// 根据本次重置周期内的消耗速度估算 "还能用多少小时"，并与配额重置时间比较。
+
+use crate::models::QuotaData;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const HISTORY_FILE: &str = "quota_history.json";
+
+/// 采样保留时长 (秒)
+const HISTORY_RETENTION_SECS: i64 = 48 * 3600;
+/// 每个模型最多保留的采样数
+const MAX_SAMPLES_PER_MODEL: usize = 200;
+/// 采样跨度不足该值 (秒) 时不做预测，避免短时间波动导致的极端结果
+const MIN_FORECAST_SPAN_SECS: i64 = 10 * 60;
+
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QuotaSample {
+    /// 采样时间 (unix 秒)
+    pub ts: i64,
+    pub percentage: i32,
+    #[serde(default)]
+    pub reset_time: String,
+}
+
+/// account_id -> model -> 采样 (按时间升序)
+pub type QuotaHistory = HashMap<String, HashMap<String, Vec<QuotaSample>>>;
+
+/// 单个账号的预测结果 (取最先耗尽的模型)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AccountForecast {
+    pub model: String,
+    pub remaining_percentage: i32,
+    /// 每小时消耗的百分比
+    pub rate_per_hour: f64,
+    pub hours_until_exhaustion: f64,
+    pub hours_until_reset: Option<f64>,
+    /// 在耗尽之前配额就会重置
+    pub resets_before_exhaustion: bool,
+}
+
+impl AccountForecast {
+    /// 预计在重置前耗尽，且剩余时间少于 hours
+    pub fn exhausts_within(&self, hours: f64) -> bool {
+        !self.resets_before_exhaustion && self.hours_until_exhaustion < hours
+    }
+}
+
+pub fn load_history(data_dir: &Path) -> QuotaHistory {
+    std::fs::read_to_string(data_dir.join(HISTORY_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// 记录一次配额刷新结果
+pub fn record_quota(data_dir: &Path, account_id: &str, quota: &QuotaData) -> Result<(), String> {
+    record_quota_at(data_dir, account_id, quota, chrono::Utc::now().timestamp())
+}
+
+pub fn record_quota_at(data_dir: &Path, account_id: &str, quota: &QuotaData, now: i64) -> Result<(), String> {
+    if quota.is_forbidden || quota.models.is_empty() {
+        return Ok(());
+    }
+    let _guard = HISTORY_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut history = load_history(data_dir);
+    let models = history.entry(account_id.to_string()).or_default();
+    for model in &quota.models {
+        let samples = models.entry(model.name.clone()).or_default();
+        samples.retain(|s| now - s.ts <= HISTORY_RETENTION_SECS && s.ts < now);
+        samples.push(QuotaSample {
+            ts: now,
+            percentage: model.percentage,
+            reset_time: model.reset_time.clone(),
+        });
+        if samples.len() > MAX_SAMPLES_PER_MODEL {
+            let excess = samples.len() - MAX_SAMPLES_PER_MODEL;
+            samples.drain(..excess);
+        }
+    }
+    // 移除本次配额中已不存在的模型
+    models.retain(|name, _| quota.models.iter().any(|m| &m.name == name));
+
+    let content = serde_json::to_string(&history).map_err(|e| e.to_string())?;
+    std::fs::write(data_dir.join(HISTORY_FILE), content).map_err(|e| format!("写入 {} 失败: {}", HISTORY_FILE, e))
+}
+
+/// 根据单个模型的采样预测 (只使用最近一次重置之后、剩余量单调不增的那一段)
+fn forecast_model(model: &str, samples: &[QuotaSample], now: i64) -> Option<AccountForecast> {
+    let last = samples.last()?;
+    let mut start = samples.len() - 1;
+    while start > 0 && samples[start - 1].percentage >= samples[start].percentage {
+        start -= 1;
+    }
+    let first = &samples[start];
+    let span = last.ts - first.ts;
+    if span < MIN_FORECAST_SPAN_SECS {
+        return None;
+    }
+    let consumed = (first.percentage - last.percentage) as f64;
+    if consumed <= 0.0 {
+        return None;
+    }
+
+    let rate_per_hour = consumed / (span as f64 / 3600.0);
+    // 从最后一次采样起算
+    let elapsed_hours = (now - last.ts).max(0) as f64 / 3600.0;
+    let hours_until_exhaustion = (last.percentage as f64 / rate_per_hour - elapsed_hours).max(0.0);
+    let hours_until_reset = chrono::DateTime::parse_from_rfc3339(&last.reset_time)
+        .ok()
+        .map(|reset| ((reset.timestamp() - now) as f64 / 3600.0).max(0.0));
+
+    Some(AccountForecast {
+        model: model.to_string(),
+        remaining_percentage: last.percentage,
+        rate_per_hour,
+        hours_until_exhaustion,
+        hours_until_reset,
+        resets_before_exhaustion: hours_until_reset.is_some_and(|reset| reset <= hours_until_exhaustion),
+    })
+}
+
+/// 预测账号最先耗尽的模型 (优先考虑重置前就会耗尽的模型)
+pub fn forecast_account(models: &HashMap<String, Vec<QuotaSample>>, now: i64) -> Option<AccountForecast> {
+    models
+        .iter()
+        .filter_map(|(name, samples)| forecast_model(name, samples, now))
+        .min_by(|a, b| {
+            a.resets_before_exhaustion
+                .cmp(&b.resets_before_exhaustion)
+                .then(a.hours_until_exhaustion.total_cmp(&b.hours_until_exhaustion))
+        })
+}
+
+pub fn forecast_all(history: &QuotaHistory, now: i64) -> HashMap<String, AccountForecast> {
+    history
+        .iter()
+        .filter_map(|(id, models)| forecast_account(models, now).map(|f| (id.clone(), f)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota(percentage: i32, reset_time: &str) -> QuotaData {
+        let mut q = QuotaData::new();
+        q.add_model("gemini-3-pro-high".to_string(), percentage, reset_time.to_string());
+        q
+    }
+
+    #[test]
+    fn test_forecast_from_recorded_history() {
+        let dir = std::env::temp_dir().join(format!("quota-forecast-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let t0 = 1_700_000_000;
+        let reset_far = chrono::DateTime::from_timestamp(t0 + 24 * 3600, 0).unwrap().to_rfc3339();
+
+        // 上一个周期的采样 (重置后回到 100) 不参与计算
+        record_quota_at(&dir, "acc", &quota(10, &reset_far), t0 - 3600).unwrap();
+        record_quota_at(&dir, "acc", &quota(100, &reset_far), t0).unwrap();
+        record_quota_at(&dir, "acc", &quota(80, &reset_far), t0 + 3600).unwrap();
+        record_quota_at(&dir, "acc", &quota(60, &reset_far), t0 + 2 * 3600).unwrap();
+
+        let forecasts = forecast_all(&load_history(&dir), t0 + 2 * 3600);
+        let f = &forecasts["acc"];
+        assert_eq!(f.remaining_percentage, 60);
+        assert!((f.rate_per_hour - 20.0).abs() < 1e-9);
+        assert!((f.hours_until_exhaustion - 3.0).abs() < 1e-9);
+        assert!(!f.resets_before_exhaustion);
+        assert!(f.exhausts_within(4.0) && !f.exhausts_within(2.0));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_no_forecast_without_consumption_or_when_reset_comes_first() {
+        let t0 = 1_700_000_000;
+        let flat = vec![
+            QuotaSample { ts: t0, percentage: 50, reset_time: String::new() },
+            QuotaSample { ts: t0 + 3600, percentage: 50, reset_time: String::new() },
+        ];
+        assert!(forecast_model("m", &flat, t0 + 3600).is_none());
+
+        let reset_soon = chrono::DateTime::from_timestamp(t0 + 3600 + 1800, 0).unwrap().to_rfc3339();
+        let draining = vec![
+            QuotaSample { ts: t0, percentage: 100, reset_time: reset_soon.clone() },
+            QuotaSample { ts: t0 + 3600, percentage: 90, reset_time: reset_soon },
+        ];
+        let f = forecast_model("m", &draining, t0 + 3600).unwrap();
+        assert!(f.resets_before_exhaustion);
+        assert!(!f.exhausts_within(100.0));
+    }
+}
@@ -0,0 +1,387 @@
+// 每日用量报告 (Daily Usage Report)
+// 面向小团队共用反代的场景：每天在设定时间汇总前一天的请求日志，
+// 按账号 / 模型 / 调用方统计请求数、错误数与 token 消耗，并列出消耗最多的会话。
+// 报告渲染为 Markdown 或 HTML，可推送到 Webhook (Slack / Discord / 企业微信等通用 JSON) 或保存到磁盘。
+
+use crate::models::UsageReportConfig;
+use crate::modules::{config, logger, proxy_db};
+use crate::proxy::monitor::ProxyRequestLog;
+use chrono::{Duration as ChronoDuration, Local, NaiveDate, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::time::{self, Duration};
+
+/// 记录最近一次已生成报告的日期，避免重启后重复推送
+const STATE_FILE: &str = "usage_report.last";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UsageRow {
+    pub key: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub thinking_tokens: u64,
+}
+
+impl UsageRow {
+    fn add(&mut self, log: &ProxyRequestLog) {
+        self.requests += 1;
+        if log.status >= 400 {
+            self.errors += 1;
+        }
+        self.input_tokens += log.input_tokens.unwrap_or(0) as u64;
+        self.output_tokens += log.output_tokens.unwrap_or(0) as u64;
+        self.thinking_tokens += log.thinking_tokens.unwrap_or(0) as u64;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.thinking_tokens
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UsageReport {
+    /// 统计日期 (本地时间 YYYY-MM-DD)
+    pub date: String,
+    pub totals: UsageRow,
+    pub by_account: Vec<UsageRow>,
+    pub by_model: Vec<UsageRow>,
+    pub by_client: Vec<UsageRow>,
+    /// 按 token 消耗排序的会话 (key 为会话指纹)
+    pub top_sessions: Vec<UsageRow>,
+}
+
+fn group_by<F>(logs: &[ProxyRequestLog], key: F) -> Vec<UsageRow>
+where
+    F: Fn(&ProxyRequestLog) -> Option<String>,
+{
+    let mut groups: HashMap<String, UsageRow> = HashMap::new();
+    for log in logs {
+        let name = key(log).filter(|k| !k.is_empty()).unwrap_or_else(|| "unknown".to_string());
+        groups
+            .entry(name.clone())
+            .or_insert_with(|| UsageRow { key: name, ..Default::default() })
+            .add(log);
+    }
+    let mut rows: Vec<UsageRow> = groups.into_values().collect();
+    rows.sort_by(|a, b| {
+        b.total_tokens()
+            .cmp(&a.total_tokens())
+            .then(b.requests.cmp(&a.requests))
+            .then(a.key.cmp(&b.key))
+    });
+    rows
+}
+
+/// 汇总一天的日志
+pub fn build_report(date: NaiveDate, logs: &[ProxyRequestLog], top_sessions: usize) -> UsageReport {
+    let mut totals = UsageRow { key: "total".to_string(), ..Default::default() };
+    logs.iter().for_each(|log| totals.add(log));
+
+    let with_session: Vec<ProxyRequestLog> = logs.iter().filter(|l| l.session_id.is_some()).cloned().collect();
+    let mut sessions = group_by(&with_session, |l| l.session_id.clone());
+    sessions.truncate(top_sessions);
+
+    UsageReport {
+        date: date.format("%Y-%m-%d").to_string(),
+        totals,
+        by_account: group_by(logs, |l| l.account_email.clone()),
+        by_model: group_by(logs, |l| l.mapped_model.clone().or_else(|| l.model.clone())),
+        by_client: group_by(logs, |l| l.client.clone()),
+        top_sessions: sessions,
+    }
+}
+
+fn markdown_table(title: &str, key_header: &str, rows: &[UsageRow]) -> String {
+    let mut out = format!("## {}\n\n", title);
+    if rows.is_empty() {
+        out.push_str("_No data_\n\n");
+        return out;
+    }
+    out.push_str(&format!("| {} | Requests | Errors | Input | Output | Thinking |\n", key_header));
+    out.push_str("|---|---:|---:|---:|---:|---:|\n");
+    for row in rows {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            row.key.replace('|', "\\|"),
+            row.requests,
+            row.errors,
+            row.input_tokens,
+            row.output_tokens,
+            row.thinking_tokens
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+pub fn render_markdown(report: &UsageReport) -> String {
+    let t = &report.totals;
+    let mut out = format!("# Antigravity Proxy Usage Report — {}\n\n", report.date);
+    out.push_str(&format!(
+        "- Requests: **{}** ({} errors)\n- Tokens: **{}** (input {}, output {}, thinking {})\n\n",
+        t.requests,
+        t.errors,
+        t.total_tokens(),
+        t.input_tokens,
+        t.output_tokens,
+        t.thinking_tokens
+    ));
+    out.push_str(&markdown_table("By Account", "Account", &report.by_account));
+    out.push_str(&markdown_table("By Model", "Model", &report.by_model));
+    out.push_str(&markdown_table("By Client", "Client", &report.by_client));
+    out.push_str(&markdown_table("Top Sessions", "Session", &report.top_sessions));
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn html_table(title: &str, key_header: &str, rows: &[UsageRow]) -> String {
+    let mut out = format!("<h2>{}</h2>\n", title);
+    if rows.is_empty() {
+        out.push_str("<p><em>No data</em></p>\n");
+        return out;
+    }
+    out.push_str(&format!(
+        "<table>\n<tr><th>{}</th><th>Requests</th><th>Errors</th><th>Input</th><th>Output</th><th>Thinking</th></tr>\n",
+        key_header
+    ));
+    for row in rows {
+        out.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&row.key),
+            row.requests,
+            row.errors,
+            row.input_tokens,
+            row.output_tokens,
+            row.thinking_tokens
+        ));
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+pub fn render_html(report: &UsageReport) -> String {
+    let t = &report.totals;
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Usage Report {date}</title>\n\
+         <style>body{{font-family:sans-serif;margin:24px}}table{{border-collapse:collapse;margin-bottom:16px}}\
+         th,td{{border:1px solid #ddd;padding:4px 8px}}td:not(:first-child){{text-align:right}}</style></head><body>\n\
+         <h1>Antigravity Proxy Usage Report — {date}</h1>\n",
+        date = report.date
+    );
+    out.push_str(&format!(
+        "<ul><li>Requests: <b>{}</b> ({} errors)</li><li>Tokens: <b>{}</b> (input {}, output {}, thinking {})</li></ul>\n",
+        t.requests,
+        t.errors,
+        t.total_tokens(),
+        t.input_tokens,
+        t.output_tokens,
+        t.thinking_tokens
+    ));
+    out.push_str(&html_table("By Account", "Account", &report.by_account));
+    out.push_str(&html_table("By Model", "Model", &report.by_model));
+    out.push_str(&html_table("By Client", "Client", &report.by_client));
+    out.push_str(&html_table("Top Sessions", "Session", &report.top_sessions));
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn is_html(format: &str) -> bool {
+    format.eq_ignore_ascii_case("html")
+}
+
+pub fn render(report: &UsageReport, format: &str) -> String {
+    if is_html(format) {
+        render_html(report)
+    } else {
+        render_markdown(report)
+    }
+}
+
+/// 本地日期对应的毫秒时间范围 [start, end)
+fn day_range_ms(date: NaiveDate) -> Result<(i64, i64), String> {
+    let start = |d: NaiveDate| {
+        d.and_hms_opt(0, 0, 0)
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.timestamp_millis())
+            .ok_or_else(|| format!("无效日期: {}", d))
+    };
+    Ok((start(date)?, start(date + ChronoDuration::days(1))?))
+}
+
+/// 读取日志并生成指定日期的报告
+pub fn generate(date: NaiveDate, config: &UsageReportConfig) -> Result<UsageReport, String> {
+    let (start, end) = day_range_ms(date)?;
+    let logs = proxy_db::get_logs_between(start, end)?;
+    Ok(build_report(date, &logs, config.top_sessions))
+}
+
+/// 保存到磁盘，返回文件路径
+fn save_to_disk(dir: &Path, report: &UsageReport, format: &str) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("创建报告目录失败: {}", e))?;
+    let ext = if is_html(format) { "html" } else { "md" };
+    let path = dir.join(format!("usage-report-{}.{}", report.date, ext));
+    std::fs::write(&path, render(report, format)).map_err(|e| format!("写入报告失败: {}", e))?;
+    Ok(path)
+}
+
+/// 推送到 Webhook：text / content 字段兼容常见聊天工具，report 字段为结构化数据
+async fn post_webhook(url: &str, report: &UsageReport, format: &str) -> Result<(), String> {
+    let markdown = render_markdown(report);
+    let mut payload = serde_json::json!({
+        "date": report.date,
+        "text": markdown,
+        "content": markdown,
+        "report": report,
+    });
+    if is_html(format) {
+        payload["html"] = serde_json::Value::String(render_html(report));
+    }
+    let response = reqwest::Client::new()
+        .post(url)
+        .timeout(std::time::Duration::from_secs(15))
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Webhook 请求失败: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Webhook 返回 HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+/// 按配置投递报告，返回投递结果说明
+pub async fn deliver(config: &UsageReportConfig, report: &UsageReport) -> Result<Vec<String>, String> {
+    let mut delivered = Vec::new();
+    if !config.save_dir.trim().is_empty() {
+        let path = save_to_disk(Path::new(config.save_dir.trim()), report, &config.format)?;
+        delivered.push(format!("saved: {}", path.display()));
+    }
+    if !config.webhook_url.trim().is_empty() {
+        post_webhook(config.webhook_url.trim(), report, &config.format).await?;
+        delivered.push("webhook".to_string());
+    }
+    Ok(delivered)
+}
+
+fn state_path() -> Result<PathBuf, String> {
+    Ok(crate::modules::account::get_data_dir()?.join(STATE_FILE))
+}
+
+fn last_reported_date() -> Option<NaiveDate> {
+    let text = std::fs::read_to_string(state_path().ok()?).ok()?;
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok()
+}
+
+/// 是否到了生成 `yesterday` 报告的时间
+fn is_due(config: &UsageReportConfig, now_hour: u32, yesterday: NaiveDate, last: Option<NaiveDate>) -> bool {
+    config.enabled && now_hour >= config.hour.min(23) && last.is_none_or(|d| d < yesterday)
+}
+
+/// 启动后台任务：每 10 分钟检查一次是否需要生成昨日报告
+pub fn start_usage_report_scheduler() {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+
+            let Ok(app_config) = config::load_app_config() else {
+                continue;
+            };
+            let report_config = app_config.usage_report;
+            let now = Local::now();
+            let yesterday = now.date_naive() - ChronoDuration::days(1);
+            if !is_due(&report_config, now.hour(), yesterday, last_reported_date()) {
+                continue;
+            }
+
+            let result = match generate(yesterday, &report_config) {
+                Ok(report) => deliver(&report_config, &report).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(delivered) => {
+                    logger::log_info(&format!("[Usage-Report] {} 报告已生成: {:?}", yesterday, delivered));
+                }
+                Err(e) => {
+                    // 投递失败同样记录日期，避免每 10 分钟重复推送；可在设置页手动重新发送
+                    logger::log_warn(&format!("[Usage-Report] {} 报告投递失败: {}", yesterday, e));
+                }
+            }
+            if let Ok(path) = state_path() {
+                let _ = std::fs::write(path, yesterday.format("%Y-%m-%d").to_string());
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(account: &str, model: &str, client: Option<&str>, session: Option<&str>, status: u16, tokens: (u32, u32)) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration: 10,
+            model: Some(model.to_string()),
+            mapped_model: Some(model.to_string()),
+            account_email: Some(account.to_string()),
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: Some(tokens.0),
+            output_tokens: Some(tokens.1),
+            thinking_tokens: None,
+            client: client.map(str::to_string),
+            experiment: None,
+            session_id: session.map(str::to_string),
+            timing: None,
+        }
+    }
+
+    #[test]
+    fn test_build_and_render_report() {
+        let logs = vec![
+            log("a@x.com", "gemini-3-flash", Some("cline"), Some("s1"), 200, (100, 50)),
+            log("a@x.com", "claude-sonnet-4-5", Some("claude_code"), Some("s2"), 200, (1000, 500)),
+            log("b@x.com", "gemini-3-flash", None, Some("s1"), 429, (0, 0)),
+        ];
+        let date = NaiveDate::from_ymd_opt(2026, 1, 2).unwrap();
+        let report = build_report(date, &logs, 1);
+
+        assert_eq!(report.totals.requests, 3);
+        assert_eq!(report.totals.errors, 1);
+        assert_eq!(report.totals.total_tokens(), 1650);
+        assert_eq!(report.by_account[0].key, "a@x.com");
+        assert_eq!(report.by_account[0].requests, 2);
+        assert_eq!(report.by_model[0].key, "claude-sonnet-4-5");
+        assert!(report.by_client.iter().any(|r| r.key == "unknown" && r.errors == 1));
+        assert_eq!(report.top_sessions.len(), 1);
+        assert_eq!(report.top_sessions[0].key, "s2");
+
+        let markdown = render_markdown(&report);
+        assert!(markdown.contains("# Antigravity Proxy Usage Report — 2026-01-02"));
+        assert!(markdown.contains("| a@x.com | 2 | 0 | 1100 | 550 | 0 |"));
+        let html = render(&report, "html");
+        assert!(html.contains("<td>b@x.com</td><td>1</td><td>1</td>"));
+    }
+
+    #[test]
+    fn test_is_due_once_per_day_after_hour() {
+        let config = UsageReportConfig { enabled: true, hour: 8, ..Default::default() };
+        let yesterday = NaiveDate::from_ymd_opt(2026, 1, 2).unwrap();
+        assert!(!is_due(&config, 7, yesterday, None));
+        assert!(is_due(&config, 9, yesterday, NaiveDate::from_ymd_opt(2026, 1, 1)));
+        assert!(!is_due(&config, 9, yesterday, Some(yesterday)));
+        assert!(!is_due(&UsageReportConfig::default(), 9, yesterday, None));
+    }
+}
@@ -0,0 +1,161 @@
+// 账号使用统计 (Account Stats)
+// 按账号累计请求数、错误数、Token 用量、最后使用时间与平均响应延迟，
+// 定期写入数据目录下的 account_stats.json (sidecar 文件，不改动账号 JSON)，重启后继续累计。
+
+use crate::proxy::runtime_state::write_atomic;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+pub const STATS_FILE: &str = "account_stats.json";
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 单个账号的累计统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub tokens: u64,
+    /// 最后一次请求的时间 (unix 毫秒)
+    pub last_used: Option<i64>,
+    /// 响应延迟 (到响应头) 累计值，用于计算平均值
+    #[serde(default)]
+    pub total_latency_ms: u64,
+    /// 平均响应延迟 (毫秒)
+    #[serde(default)]
+    pub avg_latency_ms: u64,
+}
+
+/// 账号统计存储 (key 为账号 ID)
+pub struct AccountStatsStore {
+    path: PathBuf,
+    stats: DashMap<String, AccountStats>,
+    dirty: AtomicBool,
+}
+
+impl AccountStatsStore {
+    /// 从数据目录加载已有统计 (文件不存在或损坏时从零开始)
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(STATS_FILE);
+        let stats = DashMap::new();
+        for (id, entry) in read_stats_file(&path) {
+            stats.insert(id, entry);
+        }
+        Self {
+            path,
+            stats,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 记录一次请求结果
+    pub fn record_request(&self, account_id: &str, success: bool, latency_ms: u64) {
+        let mut entry = self.stats.entry(account_id.to_string()).or_default();
+        entry.requests += 1;
+        if !success {
+            entry.errors += 1;
+        }
+        entry.last_used = Some(chrono::Utc::now().timestamp_millis());
+        entry.total_latency_ms += latency_ms;
+        entry.avg_latency_ms = entry.total_latency_ms / entry.requests;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 累加 Token 用量
+    pub fn record_tokens(&self, account_id: &str, tokens: u64) {
+        if tokens == 0 {
+            return;
+        }
+        self.stats.entry(account_id.to_string()).or_default().tokens += tokens;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, AccountStats> {
+        self.stats
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect()
+    }
+
+    /// 有变更时写入文件
+    pub fn flush(&self) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_string_pretty(&self.snapshot()).map_err(|e| e.to_string())?;
+        write_atomic(&self.path, content.as_bytes()).map_err(|e| {
+            self.dirty.store(true, Ordering::Relaxed);
+            e
+        })
+    }
+
+    /// 启动定期持久化任务；存储被释放后任务自动退出
+    pub fn start_persistence(self: &Arc<Self>) {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PERSIST_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(store) = weak.upgrade() else { break };
+                if let Err(e) = store.flush() {
+                    tracing::warn!("[Account-Stats] {}", e);
+                }
+            }
+        });
+    }
+}
+
+impl Drop for AccountStatsStore {
+    fn drop(&mut self) {
+        // 服务停止时写入最后一批统计
+        if let Err(e) = self.flush() {
+            tracing::warn!("[Account-Stats] {}", e);
+        }
+    }
+}
+
+/// 读取统计文件 (反代服务未运行时供 UI 查询)
+pub fn read_stats_file(path: &Path) -> HashMap<String, AccountStats> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_and_persists_across_reload() {
+        let dir = std::env::temp_dir().join(format!("account-stats-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        {
+            let store = AccountStatsStore::load(&dir);
+            store.record_request("acc-1", true, 100);
+            store.record_request("acc-1", false, 300);
+            store.record_tokens("acc-1", 1200);
+            store.record_tokens("acc-2", 0);
+            // Drop 时写入文件
+        }
+
+        let reloaded = AccountStatsStore::load(&dir);
+        let stats = reloaded.snapshot();
+        assert_eq!(stats.len(), 1);
+        let acc = &stats["acc-1"];
+        assert_eq!((acc.requests, acc.errors, acc.tokens), (2, 1, 1200));
+        assert_eq!(acc.avg_latency_ms, 200);
+        assert!(acc.last_used.is_some());
+
+        reloaded.record_request("acc-1", true, 200);
+        assert_eq!(reloaded.snapshot()["acc-1"].requests, 3);
+        drop(reloaded);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
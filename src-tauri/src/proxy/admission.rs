@@ -0,0 +1,317 @@
+// 准入控制 (Admission Control)
+// 限制同时转发到上游的请求数，超出的请求按优先级排队：high > normal > low，同一优先级内先到先得。
+// 客户端通过 X-Priority: high|normal|low 声明优先级 (默认 normal)，交互式请求因此可以插队到
+// 同一用户脚本发起的批量任务之前。队列已满时优先淘汰排队中优先级更低的请求 (load shedding)，
+// 没有可淘汰的请求时直接拒绝新请求；排队超时同样拒绝。
+
+use crate::proxy::config::AdmissionConfig;
+use axum::http::HeaderMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+pub const HEADER_PRIORITY: &str = "x-priority";
+
+/// 请求优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High = 0,
+    Normal = 1,
+    Low = 2,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers
+            .get(HEADER_PRIORITY)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("high" | "interactive" | "urgent") => Priority::High,
+            Some("low" | "bulk" | "batch" | "background") => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+}
+
+/// 未获准入的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// 队列已满且没有更低优先级的请求可淘汰
+    QueueFull,
+    /// 排队中被更高优先级的请求挤出
+    Shed,
+    /// 排队超时
+    Timeout,
+}
+
+impl Rejection {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Rejection::QueueFull => "Proxy is at capacity and the request queue is full",
+            Rejection::Shed => "Request was shed from the queue in favor of higher-priority requests",
+            Rejection::Timeout => "Request timed out waiting in the admission queue",
+        }
+    }
+}
+
+/// 按优先级统计
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PriorityCounts {
+    pub high: u64,
+    pub normal: u64,
+    pub low: u64,
+}
+
+impl PriorityCounts {
+    fn bump(&mut self, priority: Priority) {
+        match priority {
+            Priority::High => self.high += 1,
+            Priority::Normal => self.normal += 1,
+            Priority::Low => self.low += 1,
+        }
+    }
+}
+
+/// 准入控制状态快照 (/status)
+#[derive(Debug, Clone, Serialize)]
+pub struct AdmissionSnapshot {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    /// 各优先级当前排队数
+    pub queued: PriorityCounts,
+    pub admitted: PriorityCounts,
+    pub rejected: PriorityCounts,
+    pub timed_out: PriorityCounts,
+}
+
+struct Waiter {
+    id: u64,
+    tx: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct Inner {
+    max_concurrent: usize,
+    in_flight: usize,
+    queues: [VecDeque<Waiter>; 3],
+    next_id: u64,
+    admitted: PriorityCounts,
+    rejected: PriorityCounts,
+    timed_out: PriorityCounts,
+}
+
+impl Inner {
+    fn queued(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    /// 有空闲名额时按优先级放行排队的请求 (接收端已放弃的跳过)
+    fn dispatch(&mut self) {
+        while self.in_flight < self.max_concurrent {
+            let Some(priority) = Priority::ALL.into_iter().find(|p| !self.queues[*p as usize].is_empty()) else {
+                return;
+            };
+            let waiter = self.queues[priority as usize].pop_front().expect("non-empty queue");
+            if waiter.tx.send(()).is_ok() {
+                self.in_flight += 1;
+                self.admitted.bump(priority);
+            }
+        }
+    }
+
+    /// 淘汰一个优先级低于 `priority` 的排队请求 (最低优先级中最晚到达的)
+    fn shed_below(&mut self, priority: Priority) -> bool {
+        for victim in Priority::ALL.into_iter().rev().filter(|p| *p > priority) {
+            if let Some(waiter) = self.queues[victim as usize].pop_back() {
+                // 丢弃发送端，等待方收到 Shed
+                drop(waiter);
+                self.rejected.bump(victim);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[derive(Default)]
+pub struct AdmissionController {
+    inner: Mutex<Inner>,
+}
+
+/// 准入许可，释放时放行下一个排队请求
+pub struct AdmissionPermit {
+    controller: Arc<AdmissionController>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.controller.inner.lock() {
+            inner.in_flight = inner.in_flight.saturating_sub(1);
+            inner.dispatch();
+        }
+    }
+}
+
+impl AdmissionController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 申请准入：有空闲名额立即放行，否则按优先级排队等待
+    pub async fn acquire(
+        self: &Arc<Self>,
+        priority: Priority,
+        config: &AdmissionConfig,
+    ) -> Result<AdmissionPermit, Rejection> {
+        let permit = || AdmissionPermit { controller: self.clone() };
+        let (id, mut rx) = {
+            let mut inner = self.inner.lock().map_err(|_| Rejection::QueueFull)?;
+            inner.max_concurrent = config.max_concurrent.max(1);
+            // 上限调大后先放行已在排队的请求
+            inner.dispatch();
+            if inner.in_flight < inner.max_concurrent && inner.queued() == 0 {
+                inner.in_flight += 1;
+                inner.admitted.bump(priority);
+                return Ok(permit());
+            }
+            if inner.queued() >= config.max_queue && !inner.shed_below(priority) {
+                inner.rejected.bump(priority);
+                return Err(Rejection::QueueFull);
+            }
+            let (tx, rx) = oneshot::channel();
+            inner.next_id += 1;
+            let id = inner.next_id;
+            inner.queues[priority as usize].push_back(Waiter { id, tx });
+            (id, rx)
+        };
+
+        let wait = std::time::Duration::from_secs(config.queue_timeout_secs.max(1));
+        match tokio::time::timeout(wait, &mut rx).await {
+            Ok(Ok(())) => Ok(permit()),
+            Ok(Err(_)) => Err(Rejection::Shed),
+            Err(_) => {
+                let mut inner = self.inner.lock().map_err(|_| Rejection::Timeout)?;
+                let queue = &mut inner.queues[priority as usize];
+                match queue.iter().position(|w| w.id == id) {
+                    Some(pos) => {
+                        queue.remove(pos);
+                        inner.timed_out.bump(priority);
+                        Err(Rejection::Timeout)
+                    }
+                    // 超时的同时已被放行 (名额已计入 in_flight)
+                    None if rx.try_recv().is_ok() => Ok(permit()),
+                    None => Err(Rejection::Shed),
+                }
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Option<AdmissionSnapshot> {
+        let inner = self.inner.lock().ok()?;
+        let depth = |p: Priority| inner.queues[p as usize].len() as u64;
+        Some(AdmissionSnapshot {
+            max_concurrent: inner.max_concurrent,
+            in_flight: inner.in_flight,
+            queued: PriorityCounts {
+                high: depth(Priority::High),
+                normal: depth(Priority::Normal),
+                low: depth(Priority::Low),
+            },
+            admitted: inner.admitted,
+            rejected: inner.rejected,
+            timed_out: inner.timed_out,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_concurrent: usize, max_queue: usize) -> AdmissionConfig {
+        AdmissionConfig { enabled: true, max_concurrent, max_queue, queue_timeout_secs: 5 }
+    }
+
+    #[test]
+    fn test_priority_from_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Priority::from_headers(&headers), Priority::Normal);
+        headers.insert(HEADER_PRIORITY, "High".parse().unwrap());
+        assert_eq!(Priority::from_headers(&headers), Priority::High);
+        headers.insert(HEADER_PRIORITY, "low".parse().unwrap());
+        assert_eq!(Priority::from_headers(&headers), Priority::Low);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_jumps_queue() {
+        let controller = Arc::new(AdmissionController::new());
+        let cfg = config(1, 8);
+        let running = controller.acquire(Priority::Normal, &cfg).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for priority in [Priority::Low, Priority::Low, Priority::High] {
+            let controller = controller.clone();
+            let order = order.clone();
+            let cfg = cfg.clone();
+            tasks.push(tokio::spawn(async move {
+                let permit = controller.acquire(priority, &cfg).await.unwrap();
+                order.lock().unwrap().push(priority);
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                drop(permit);
+            }));
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let snapshot = controller.snapshot().unwrap();
+        assert_eq!((snapshot.queued.high, snapshot.queued.low), (1, 2));
+
+        drop(running);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![Priority::High, Priority::Low, Priority::Low]);
+        assert_eq!(controller.snapshot().unwrap().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_lower_priority() {
+        let controller = Arc::new(AdmissionController::new());
+        let cfg = config(1, 1);
+        let running = controller.acquire(Priority::Normal, &cfg).await.unwrap();
+
+        let low = {
+            let controller = controller.clone();
+            let cfg = cfg.clone();
+            tokio::spawn(async move { controller.acquire(Priority::Low, &cfg).await.map(|_| ()) })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        // 队列已满：同优先级的新请求被拒绝，更高优先级的请求挤出低优先级请求
+        assert_eq!(controller.acquire(Priority::Low, &cfg).await.err(), Some(Rejection::QueueFull));
+        let high = {
+            let controller = controller.clone();
+            let cfg = cfg.clone();
+            tokio::spawn(async move { controller.acquire(Priority::High, &cfg).await.map(|_| ()) })
+        };
+        assert_eq!(low.await.unwrap(), Err(Rejection::Shed));
+
+        drop(running);
+        assert_eq!(high.await.unwrap(), Ok(()));
+        let snapshot = controller.snapshot().unwrap();
+        assert_eq!(snapshot.rejected.low, 2);
+        assert_eq!(snapshot.admitted.high, 1);
+    }
+}
@@ -0,0 +1,316 @@
+// 后台小请求合并 (Micro-Batching)
+// 标题生成、简短摘要等后台任务体积小但数量多，每个都单独消耗一次请求配额。
+// 第一个请求到达后开启收集窗口，窗口内的同类请求合并为一个多段 prompt 发往上游，
+// 再按分隔符拆分响应分发给各请求。任何一段缺失或上游失败时，对应请求回退到单独发送。
+
+use crate::proxy::config::BackgroundBatchConfig;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 分隔符前缀，形如 `<<<TASK 1>>>`
+const TASK_MARKER: &str = "<<<TASK ";
+const TASK_MARKER_END: &str = ">>>";
+
+/// 一次合并请求的上游结果
+#[derive(Debug, Clone)]
+pub struct BatchOutput {
+    pub text: String,
+    pub account_email: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+/// 单个请求拆分后的结果
+#[derive(Debug, Clone)]
+pub struct BatchAnswer {
+    pub text: String,
+    pub account_email: String,
+    /// 按批次大小均摊的用量
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub batch_size: usize,
+}
+
+/// 提交结果
+#[derive(Debug)]
+pub enum BatchOutcome {
+    Answered(BatchAnswer),
+    /// 未能合并 (批次只有自己 / 上游失败 / 拆分失败)，调用方应按普通请求发送
+    Fallback(String),
+}
+
+struct PendingBatch {
+    id: u64,
+    prompts: Vec<String>,
+    senders: Vec<oneshot::Sender<BatchOutcome>>,
+}
+
+#[derive(Default)]
+struct BatcherState {
+    next_id: u64,
+    pending: Option<PendingBatch>,
+}
+
+/// 后台请求合并器
+#[derive(Default)]
+pub struct BackgroundBatcher {
+    state: Arc<Mutex<BatcherState>>,
+}
+
+impl BackgroundBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 提交一个后台 prompt 并等待结果
+    ///
+    /// `execute` 负责把合并后的 prompt 发往上游；只有负责发送该批次的请求的 `execute` 会被调用
+    pub async fn submit<F, Fut>(
+        &self,
+        prompt: String,
+        config: &BackgroundBatchConfig,
+        execute: F,
+    ) -> BatchOutcome
+    where
+        F: FnOnce(String) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<BatchOutput, String>> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let max_size = config.max_batch_size.max(1);
+
+        // 加入当前批次；第一个请求负责定时发送，凑满的请求立即发送
+        let (leader_id, full_batch) = {
+            let mut state = self.state.lock().unwrap();
+            let is_new = state.pending.is_none();
+            if is_new {
+                state.next_id += 1;
+                let id = state.next_id;
+                state.pending = Some(PendingBatch {
+                    id,
+                    prompts: Vec::new(),
+                    senders: Vec::new(),
+                });
+            }
+            let batch = state.pending.as_mut().unwrap();
+            batch.prompts.push(prompt);
+            batch.senders.push(tx);
+            let leader_id = if is_new { Some(batch.id) } else { None };
+            let full_batch = if batch.prompts.len() >= max_size {
+                state.pending.take()
+            } else {
+                None
+            };
+            (leader_id, full_batch)
+        };
+
+        if let Some(batch) = full_batch {
+            tokio::spawn(flush(batch, execute));
+        } else if let Some(id) = leader_id {
+            // 在独立任务中等待窗口结束，避免客户端断开导致整批挂起
+            let state = self.state.clone();
+            let window = Duration::from_millis(config.window_ms);
+            tokio::spawn(async move {
+                tokio::time::sleep(window).await;
+                let batch = {
+                    let mut state = state.lock().unwrap();
+                    match &state.pending {
+                        Some(b) if b.id == id => state.pending.take(),
+                        _ => None,
+                    }
+                };
+                if let Some(batch) = batch {
+                    flush(batch, execute).await;
+                }
+            });
+        }
+
+        rx.await
+            .unwrap_or_else(|_| BatchOutcome::Fallback("batch dropped".to_string()))
+    }
+}
+
+async fn flush<F, Fut>(batch: PendingBatch, execute: F)
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<BatchOutput, String>>,
+{
+    let n = batch.prompts.len();
+    if n < 2 {
+        for tx in batch.senders {
+            let _ = tx.send(BatchOutcome::Fallback("single request in batch".to_string()));
+        }
+        return;
+    }
+
+    tracing::info!("[BackgroundBatch] Sending {} background requests as one upstream call", n);
+    match execute(build_batch_prompt(&batch.prompts)).await {
+        Ok(output) => {
+            let parts = split_batch_response(&output.text, n);
+            let missing = parts.iter().filter(|p| p.is_none()).count();
+            if missing > 0 {
+                tracing::warn!("[BackgroundBatch] {} of {} answers missing, falling back for them", missing, n);
+            }
+            let n32 = n as u32;
+            for (part, tx) in parts.into_iter().zip(batch.senders) {
+                let outcome = match part {
+                    Some(text) => BatchOutcome::Answered(BatchAnswer {
+                        text,
+                        account_email: output.account_email.clone(),
+                        input_tokens: output.input_tokens.div_ceil(n32),
+                        output_tokens: output.output_tokens.div_ceil(n32),
+                        batch_size: n,
+                    }),
+                    None => BatchOutcome::Fallback("answer missing in batch response".to_string()),
+                };
+                let _ = tx.send(outcome);
+            }
+        }
+        Err(e) => {
+            tracing::warn!("[BackgroundBatch] Batch request failed, falling back: {}", e);
+            for tx in batch.senders {
+                let _ = tx.send(BatchOutcome::Fallback(e.clone()));
+            }
+        }
+    }
+}
+
+/// 构建多段 prompt
+pub fn build_batch_prompt(prompts: &[String]) -> String {
+    let mut out = format!(
+        "You will receive {n} independent tasks. Complete each task on its own, without referring to the others.\n\
+         For every task, first output its marker line exactly as given (e.g. {m}1{e}), then the answer on the following lines.\n\
+         Output all {n} tasks in order and nothing else.\n",
+        n = prompts.len(),
+        m = TASK_MARKER,
+        e = TASK_MARKER_END
+    );
+    for (i, prompt) in prompts.iter().enumerate() {
+        out.push_str(&format!("\n{}{}{}\n{}\n", TASK_MARKER, i + 1, TASK_MARKER_END, prompt.trim()));
+    }
+    out
+}
+
+/// 按分隔符拆分响应，返回与请求一一对应的答案 (缺失的为 None)
+pub fn split_batch_response(text: &str, n: usize) -> Vec<Option<String>> {
+    let mut answers: Vec<Option<String>> = vec![None; n];
+    let mut rest = text;
+    let mut current: Option<usize> = None;
+
+    loop {
+        let next = rest.find(TASK_MARKER);
+        let (body, tail) = match next {
+            Some(pos) => (&rest[..pos], Some(&rest[pos + TASK_MARKER.len()..])),
+            None => (rest, None),
+        };
+        if let Some(idx) = current {
+            let answer = body.trim();
+            if !answer.is_empty() && answers[idx].is_none() {
+                answers[idx] = Some(answer.to_string());
+            }
+        }
+        let Some(tail) = tail else { break };
+        let Some(end) = tail.find(TASK_MARKER_END) else { break };
+        current = tail[..end]
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|i| (1..=n).contains(i))
+            .map(|i| i - 1);
+        rest = &tail[end + TASK_MARKER_END.len()..];
+    }
+
+    answers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config(window_ms: u64, max_batch_size: usize) -> BackgroundBatchConfig {
+        BackgroundBatchConfig {
+            enabled: true,
+            window_ms,
+            max_batch_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prompt_roundtrip() {
+        let prompt = build_batch_prompt(&["title A".to_string(), "title B".to_string()]);
+        assert!(prompt.contains("<<<TASK 1>>>\ntitle A"));
+        assert!(prompt.contains("<<<TASK 2>>>\ntitle B"));
+
+        let answers = split_batch_response("<<<TASK 1>>>\nFirst\n\n<<<TASK 2>>>\nSecond line\nmore", 2);
+        assert_eq!(answers[0].as_deref(), Some("First"));
+        assert_eq!(answers[1].as_deref(), Some("Second line\nmore"));
+    }
+
+    #[test]
+    fn test_split_tolerates_missing_and_bogus_markers() {
+        let answers = split_batch_response("preamble\n<<<TASK 3>>>\nThird\n<<<TASK 9>>>\nignored", 3);
+        assert_eq!(answers, vec![None, None, Some("Third".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_one_upstream_call() {
+        let batcher = Arc::new(BackgroundBatcher::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cfg = config(50, 8);
+
+        let mut handles = Vec::new();
+        for i in 0..3 {
+            let batcher = batcher.clone();
+            let calls = calls.clone();
+            let cfg = cfg.clone();
+            handles.push(tokio::spawn(async move {
+                batcher
+                    .submit(format!("prompt {}", i), &cfg, move |combined| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        let text = (1..=3)
+                            .map(|k| format!("<<<TASK {}>>>\nanswer {}", k, k))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        assert!(combined.contains("prompt 0"));
+                        Ok(BatchOutput {
+                            text,
+                            account_email: "a@example.com".to_string(),
+                            input_tokens: 30,
+                            output_tokens: 9,
+                        })
+                    })
+                    .await
+            }));
+            // 保证提交顺序
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let mut texts = Vec::new();
+        for h in handles {
+            match h.await.unwrap() {
+                BatchOutcome::Answered(a) => {
+                    assert_eq!(a.batch_size, 3);
+                    assert_eq!(a.output_tokens, 3);
+                    texts.push(a.text);
+                }
+                BatchOutcome::Fallback(e) => panic!("unexpected fallback: {}", e),
+            }
+        }
+        assert_eq!(texts, vec!["answer 1", "answer 2", "answer 3"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_single_request_falls_back() {
+        let batcher = BackgroundBatcher::new();
+        let outcome = batcher
+            .submit("only".to_string(), &config(10, 8), |_| async {
+                Err::<BatchOutput, String>("must not be called".to_string())
+            })
+            .await;
+        assert!(matches!(outcome, BatchOutcome::Fallback(reason) if reason.contains("single")));
+    }
+}
@@ -0,0 +1,162 @@
+// 客户端识别与兼容性配置 (Client Profiles)
+// 根据请求头 / User-Agent / 请求路径识别调用方 (Claude Code、Cline、Cherry Studio、Codex CLI、Continue)，
+// 为不同客户端应用各自的兼容性开关。识别结果写入请求日志，客户端也可通过 X-Client-Profile 显式指定。
+
+use crate::proxy::common::output_locale::Locale;
+use crate::proxy::config::{ClientCompatProfile, ClientProfilesConfig};
+use axum::http::HeaderMap;
+
+pub const HEADER_CLIENT_PROFILE: &str = "x-client-profile";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientKind {
+    ClaudeCode,
+    Cline,
+    CherryStudio,
+    CodexCli,
+    Continue,
+    Unknown,
+}
+
+impl ClientKind {
+    pub const ALL: [ClientKind; 5] = [
+        ClientKind::ClaudeCode,
+        ClientKind::Cline,
+        ClientKind::CherryStudio,
+        ClientKind::CodexCli,
+        ClientKind::Continue,
+    ];
+
+    /// 配置与日志中使用的标识
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientKind::ClaudeCode => "claude_code",
+            ClientKind::Cline => "cline",
+            ClientKind::CherryStudio => "cherry_studio",
+            ClientKind::CodexCli => "codex_cli",
+            ClientKind::Continue => "continue",
+            ClientKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == id)
+    }
+}
+
+fn header_lower(headers: &HeaderMap, name: &str) -> String {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// 识别调用方
+pub fn detect(headers: &HeaderMap, path: &str) -> ClientKind {
+    if let Some(kind) = headers
+        .get(HEADER_CLIENT_PROFILE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| ClientKind::from_id(v.trim()))
+    {
+        return kind;
+    }
+
+    let user_agent = header_lower(headers, "user-agent");
+    // OpenRouter 风格的应用标识头 (Cline / Cherry Studio / Continue 均会发送)
+    let app = format!("{} {}", header_lower(headers, "x-title"), header_lower(headers, "http-referer"));
+
+    if user_agent.starts_with("claude-cli/")
+        || header_lower(headers, "x-app") == "cli"
+        || header_lower(headers, "anthropic-beta").contains("claude-code-")
+    {
+        ClientKind::ClaudeCode
+    } else if user_agent.contains("codex_cli") || header_lower(headers, "originator").starts_with("codex") {
+        ClientKind::CodexCli
+    } else if app.contains("cline") || user_agent.contains("cline") {
+        ClientKind::Cline
+    } else if app.contains("cherry") || user_agent.contains("cherrystudio") {
+        ClientKind::CherryStudio
+    } else if app.contains("continue") || user_agent.contains("continue") {
+        ClientKind::Continue
+    } else if path.starts_with("/v1/responses") {
+        // Responses API 目前只有 Codex CLI 在用
+        ClientKind::CodexCli
+    } else {
+        ClientKind::Unknown
+    }
+}
+
+/// 中间件写回请求头的客户端标识
+pub fn client_id(headers: &HeaderMap) -> &str {
+    headers
+        .get(HEADER_CLIENT_PROFILE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(ClientKind::Unknown.as_str())
+}
+
+/// 读取中间件写回请求头的识别结果，返回生效的兼容性配置
+pub fn profile_from_headers(headers: &HeaderMap, config: &ClientProfilesConfig) -> ClientCompatProfile {
+    if !config.enabled {
+        return ClientCompatProfile::default();
+    }
+    config.profiles.get(client_id(headers)).cloned().unwrap_or_default()
+}
+
+/// 注入文本所用语言 (不受 enabled 开关影响)
+pub fn output_locale(headers: &HeaderMap, config: &ClientProfilesConfig) -> Locale {
+    Locale::resolve(&config.output_language, headers)
+}
+
+/// 兼容性配置与注入文本语言
+pub fn profile_and_locale(headers: &HeaderMap, config: &ClientProfilesConfig) -> (ClientCompatProfile, Locale) {
+    (profile_from_headers(headers, config), output_locale(headers, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_detects_known_clients() {
+        let cases = [
+            (headers(&[("user-agent", "claude-cli/2.0.14 (external, cli)")]), "/v1/messages", ClientKind::ClaudeCode),
+            (headers(&[("user-agent", "codex_cli_rs/0.46.0")]), "/v1/chat/completions", ClientKind::CodexCli),
+            (headers(&[]), "/v1/responses", ClientKind::CodexCli),
+            (headers(&[("x-title", "Cline"), ("http-referer", "https://cline.bot")]), "/v1/chat/completions", ClientKind::Cline),
+            (headers(&[("user-agent", "Mozilla/5.0 CherryStudio/1.5.3 Chrome/134")]), "/v1/chat/completions", ClientKind::CherryStudio),
+            (headers(&[("x-title", "Continue")]), "/v1/chat/completions", ClientKind::Continue),
+            (headers(&[("user-agent", "curl/8.5.0")]), "/v1/chat/completions", ClientKind::Unknown),
+            // 显式指定优先
+            (headers(&[("user-agent", "curl/8.5.0"), ("x-client-profile", "cline")]), "/v1/messages", ClientKind::Cline),
+        ];
+        for (headers, path, expected) in cases {
+            assert_eq!(detect(&headers, path), expected, "{:?}", headers);
+        }
+    }
+
+    #[test]
+    fn test_profile_lookup_falls_back_to_default() {
+        let mut config = ClientProfilesConfig::default();
+        config.profiles.insert(
+            "continue".to_string(),
+            ClientCompatProfile { reasoning_content: false, ..Default::default() },
+        );
+
+        let continue_profile = profile_from_headers(&headers(&[("x-client-profile", "continue")]), &config);
+        assert!(!continue_profile.reasoning_content);
+        assert!(profile_from_headers(&headers(&[]), &config).reasoning_content);
+
+        config.enabled = false;
+        assert!(profile_from_headers(&headers(&[("x-client-profile", "continue")]), &config).reasoning_content);
+    }
+}
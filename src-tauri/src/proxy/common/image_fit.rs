@@ -0,0 +1,231 @@
+// 图片尺寸适配
+// Gemini 图片模型只接受固定的宽高比，这里将任意 WxH 映射到最接近的比例，
+// 并在本地把返回的图片裁剪 / 留边缩放到客户端请求的精确像素尺寸，按需转码为 PNG / JPEG / WebP。
+
+use base64::Engine;
+use image::{imageops::FilterType, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+
+/// Gemini imageConfig.aspectRatio 支持的比例
+const SUPPORTED_RATIOS: &[(&str, f64)] = &[
+    ("1:1", 1.0),
+    ("2:3", 2.0 / 3.0),
+    ("3:2", 3.0 / 2.0),
+    ("3:4", 3.0 / 4.0),
+    ("4:3", 4.0 / 3.0),
+    ("4:5", 4.0 / 5.0),
+    ("5:4", 5.0 / 4.0),
+    ("9:16", 9.0 / 16.0),
+    ("16:9", 16.0 / 9.0),
+    ("21:9", 21.0 / 9.0),
+];
+
+/// 单边最大像素 (防止恶意尺寸导致内存暴涨)
+const MAX_DIMENSION: u32 = 8192;
+
+/// 解析 "WxH" (也接受 "W*H" / "W×H")
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let normalized = size.trim().to_lowercase().replace(['*', '×'], "x");
+    let (w, h) = normalized.split_once('x')?;
+    let w: u32 = w.trim().parse().ok()?;
+    let h: u32 = h.trim().parse().ok()?;
+    if w == 0 || h == 0 || w > MAX_DIMENSION || h > MAX_DIMENSION {
+        return None;
+    }
+    Some((w, h))
+}
+
+/// 选择与 W:H 最接近的支持比例 (按对数距离比较，横竖对称)
+pub fn closest_aspect_ratio(width: u32, height: u32) -> &'static str {
+    let target = (width as f64 / height as f64).ln();
+    SUPPORTED_RATIOS
+        .iter()
+        .min_by(|a, b| {
+            (a.1.ln() - target)
+                .abs()
+                .total_cmp(&(b.1.ln() - target).abs())
+        })
+        .map(|(name, _)| *name)
+        .unwrap_or("1:1")
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "webp" => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// 尺寸适配方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// 居中裁剪填满目标尺寸
+    #[default]
+    Crop,
+    /// 完整保留画面，不足部分留边 (PNG/WebP 透明，JPEG 黑色)
+    Letterbox,
+}
+
+impl FitMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "crop" | "cover" | "fill" => Some(Self::Crop),
+            "letterbox" | "contain" | "pad" => Some(Self::Letterbox),
+            _ => None,
+        }
+    }
+}
+
+/// 图片后处理参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageFit {
+    /// 精确输出尺寸 (None 时保持原尺寸)
+    pub size: Option<(u32, u32)>,
+    pub mode: FitMode,
+    /// 输出格式 (None 时保持上游格式)
+    pub format: Option<OutputFormat>,
+    /// JPEG 质量 (1-100)
+    pub quality: u8,
+}
+
+impl ImageFit {
+    pub fn is_noop(&self) -> bool {
+        self.size.is_none() && self.format.is_none()
+    }
+
+    /// 处理 base64 图片，返回 (base64, mime_type)
+    pub fn apply(&self, data_b64: &str, mime_type: &str) -> Result<(String, String), String> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let bytes = engine
+            .decode(data_b64)
+            .map_err(|e| format!("Invalid base64 image: {}", e))?;
+        let img = image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode image: {}", e))?;
+
+        let resized = match self.size {
+            Some((w, h)) if (img.width(), img.height()) != (w, h) => self.resize(img, w, h),
+            _ => img,
+        };
+
+        let format = self
+            .format
+            .or_else(|| OutputFormat::parse(mime_type.trim_start_matches("image/")))
+            .unwrap_or(OutputFormat::Png);
+        let encoded = encode(resized, format, self.quality)?;
+        Ok((engine.encode(encoded), format.mime_type().to_string()))
+    }
+
+    fn resize(&self, img: DynamicImage, width: u32, height: u32) -> DynamicImage {
+        match self.mode {
+            FitMode::Crop => img.resize_to_fill(width, height, FilterType::Lanczos3),
+            FitMode::Letterbox => {
+                let fitted = img.resize(width, height, FilterType::Lanczos3);
+                let background = match self.format {
+                    Some(OutputFormat::Jpeg) => Rgba([0, 0, 0, 255]),
+                    _ => Rgba([0, 0, 0, 0]),
+                };
+                let mut canvas = RgbaImage::from_pixel(width, height, background);
+                let x = (width - fitted.width()) / 2;
+                let y = (height - fitted.height()) / 2;
+                image::imageops::overlay(&mut canvas, &fitted.to_rgba8(), x as i64, y as i64);
+                DynamicImage::ImageRgba8(canvas)
+            }
+        }
+    }
+}
+
+fn encode(img: DynamicImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut out = Cursor::new(Vec::new());
+    match format {
+        OutputFormat::Png => img.write_to(&mut out, ImageFormat::Png),
+        OutputFormat::Webp => DynamicImage::ImageRgba8(img.to_rgba8()).write_to(&mut out, ImageFormat::WebP),
+        OutputFormat::Jpeg => {
+            // JPEG 不支持 alpha 通道
+            let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100));
+            rgb.write_with_encoder(encoder)
+        }
+    }
+    .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_png(w: u32, h: u32) -> String {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(w, h, Rgba([200, 10, 10, 255])));
+        base64::engine::general_purpose::STANDARD.encode(encode(img, OutputFormat::Png, 90).unwrap())
+    }
+
+    fn decode(b64: &str) -> DynamicImage {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(b64).unwrap();
+        image::load_from_memory(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_parse_size_and_closest_ratio() {
+        assert_eq!(parse_size("1536x1024"), Some((1536, 1024)));
+        assert_eq!(parse_size(" 800 * 600 "), Some((800, 600)));
+        assert_eq!(parse_size("auto"), None);
+        assert_eq!(parse_size("0x10"), None);
+        assert_eq!(parse_size("100000x10"), None);
+
+        assert_eq!(closest_aspect_ratio(1024, 1024), "1:1");
+        assert_eq!(closest_aspect_ratio(1536, 1024), "3:2");
+        assert_eq!(closest_aspect_ratio(1920, 1080), "16:9");
+        assert_eq!(closest_aspect_ratio(1080, 1920), "9:16");
+        assert_eq!(closest_aspect_ratio(2560, 1080), "21:9");
+        assert_eq!(closest_aspect_ratio(1000, 1230), "4:5");
+    }
+
+    #[test]
+    fn test_crop_to_exact_size_and_convert() {
+        let fit = ImageFit {
+            size: Some((300, 200)),
+            mode: FitMode::Crop,
+            format: Some(OutputFormat::Jpeg),
+            quality: 80,
+        };
+        let (out, mime) = fit.apply(&sample_png(320, 240), "image/png").unwrap();
+        assert_eq!(mime, "image/jpeg");
+        let img = decode(&out);
+        assert_eq!((img.width(), img.height()), (300, 200));
+    }
+
+    #[test]
+    fn test_letterbox_keeps_transparent_padding() {
+        let fit = ImageFit {
+            size: Some((200, 100)),
+            mode: FitMode::Letterbox,
+            format: None,
+            quality: 90,
+        };
+        let (out, mime) = fit.apply(&sample_png(100, 100), "image/png").unwrap();
+        assert_eq!(mime, "image/png");
+        let img = decode(&out).to_rgba8();
+        assert_eq!(img.dimensions(), (200, 100));
+        assert_eq!(img.get_pixel(0, 50)[3], 0);
+        assert_eq!(img.get_pixel(100, 50)[3], 255);
+    }
+}
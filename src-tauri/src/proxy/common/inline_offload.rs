@@ -0,0 +1,429 @@
+// 超大 inlineData 附件处理
+// 大附件以 base64 内联会把请求推过 v1internal 的大小上限，上游只回一个含糊的 400。
+// 发送前检查每个 inlineData:
+// - 图片: 逐步缩小边长并以 JPEG 重编码，直到低于阈值
+// - 文本类 (text/*, JSON, XML...): 按 UTF-8 字符边界拆分为多个 inlineData part
+// - 其余类型 (PDF / 音视频等) 无法在本地安全处理，原样转发 (与未开启时行为一致)
+// 目前未接入文件上传接口，所以该功能默认关闭；开启后由 handler 在重试循环外创建 InlineOffloader，
+// 每个附件只处理一次，后续重试复用结果。
+
+use crate::proxy::config::InlineOffloadConfig;
+use base64::Engine;
+use image::{imageops::FilterType, DynamicImage};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+
+/// 缩放的最小边长，再小就失去意义
+const MIN_IMAGE_SIDE: u32 = 256;
+
+/// 重编码时的初始最大边长 (超过此尺寸对模型理解几乎无增益)
+const START_IMAGE_SIDE: u32 = 3072;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// 处理结果 (用于日志)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OffloadReport {
+    /// 重编码的图片数
+    pub images_shrunk: usize,
+    /// 拆分的文本附件数
+    pub texts_split: usize,
+    /// 节省的 base64 字节数
+    pub saved_bytes: usize,
+    /// 无法本地处理、原样转发的附件数
+    pub passed_through: usize,
+}
+
+impl OffloadReport {
+    pub fn is_empty(&self) -> bool {
+        self.images_shrunk == 0 && self.texts_split == 0 && self.passed_through == 0
+    }
+}
+
+/// base64 长度 -> 解码后字节数 (近似)
+fn decoded_len(b64_len: usize) -> usize {
+    b64_len / 4 * 3
+}
+
+fn format_mb(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// 可按 UTF-8 文本处理的附件类型
+pub fn is_text_like(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml" | "application/javascript"
+        )
+}
+
+/// 请求中所有 parts 数组 (contents[*].parts 与 systemInstruction.parts)
+fn parts_arrays_mut(body: &mut Value) -> Vec<&mut Vec<Value>> {
+    let request = match body.get_mut("request") {
+        Some(r) => r,
+        None => return Vec::new(),
+    };
+    let mut arrays = Vec::new();
+    if let Some(obj) = request.as_object_mut() {
+        for (key, value) in obj.iter_mut() {
+            match key.as_str() {
+                "contents" => {
+                    if let Some(contents) = value.as_array_mut() {
+                        for content in contents {
+                            if let Some(parts) = content.get_mut("parts").and_then(|p| p.as_array_mut()) {
+                                arrays.push(parts);
+                            }
+                        }
+                    }
+                }
+                "systemInstruction" => {
+                    if let Some(parts) = value.get_mut("parts").and_then(|p| p.as_array_mut()) {
+                        arrays.push(parts);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    arrays
+}
+
+fn inline_sizes(body: &Value) -> impl Iterator<Item = usize> + '_ {
+    let request = &body["request"];
+    request["contents"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .chain(std::iter::once(&request["systemInstruction"]))
+        .filter_map(|c| c["parts"].as_array())
+        .flatten()
+        .filter_map(|p| p["inlineData"]["data"].as_str())
+        .map(str::len)
+}
+
+/// 是否存在需要处理的附件 (廉价检查，不解码)
+pub fn needs_offload(body: &Value, config: &InlineOffloadConfig) -> bool {
+    config.enabled && inline_sizes(body).any(|len| decoded_len(len) > config.threshold_bytes)
+}
+
+/// 缩放图片直到编码后低于阈值，返回 JPEG 字节
+fn shrink_image(bytes: &[u8], threshold: usize) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(bytes).map_err(|e| format!("failed to decode image: {}", e))?;
+    // JPEG 不支持 alpha 通道
+    let img = DynamicImage::ImageRgb8(img.to_rgb8());
+    let mut side = img.width().max(img.height()).min(START_IMAGE_SIDE);
+
+    loop {
+        let resized = if img.width().max(img.height()) > side {
+            img.resize(side, side, FilterType::Lanczos3)
+        } else {
+            img.clone()
+        };
+        let mut out = Cursor::new(Vec::new());
+        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+        resized
+            .write_with_encoder(encoder)
+            .map_err(|e| format!("failed to encode image: {}", e))?;
+        let encoded = out.into_inner();
+        if encoded.len() <= threshold {
+            return Ok(encoded);
+        }
+        if side <= MIN_IMAGE_SIDE {
+            return Err(format!(
+                "image is still {} after downscaling to {}px",
+                format_mb(encoded.len()),
+                side
+            ));
+        }
+        side = (side * 3 / 4).max(MIN_IMAGE_SIDE);
+    }
+}
+
+/// 按 UTF-8 字符边界切分文本
+fn split_text(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut cut = max_bytes;
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        // 尽量在换行处断开
+        if let Some(pos) = rest[..cut].rfind('\n') {
+            if pos > cut / 2 {
+                cut = pos + 1;
+            }
+        }
+        let (head, tail) = rest.split_at(cut);
+        chunks.push(head);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+/// 尝试在本地缩小单个超限附件 (图片重编码 / 文本拆分)
+fn shrink_part(part: &Value, mime: &str, data: &str, threshold: usize, report: &mut OffloadReport) -> Result<Vec<Value>, String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    if mime.starts_with("image/") {
+        let bytes = engine.decode(data).map_err(|e| format!("invalid base64 ({})", e))?;
+        let shrunk = shrink_image(&bytes, threshold)?;
+        let encoded = engine.encode(&shrunk);
+        report.images_shrunk += 1;
+        report.saved_bytes += data.len().saturating_sub(encoded.len());
+        let mut new_part = part.clone();
+        new_part["inlineData"] = json!({ "mimeType": "image/jpeg", "data": encoded });
+        return Ok(vec![new_part]);
+    }
+
+    if is_text_like(mime) {
+        let bytes = engine.decode(data).map_err(|e| format!("invalid base64 ({})", e))?;
+        let text = String::from_utf8(bytes).map_err(|_| "content is not valid UTF-8".to_string())?;
+        let chunks = split_text(&text, threshold);
+        report.texts_split += 1;
+        return Ok(chunks
+            .into_iter()
+            .map(|chunk| json!({ "inlineData": { "mimeType": mime, "data": engine.encode(chunk) } }))
+            .collect());
+    }
+
+    Err("no local conversion for this type".to_string())
+}
+
+/// 处理单个超限附件，返回替换用的 parts；无法处理时原样返回 (交由上游判断)
+fn offload_part(part: &Value, threshold: usize, report: &mut OffloadReport) -> Vec<Value> {
+    let mime = part["inlineData"]["mimeType"].as_str().unwrap_or("application/octet-stream");
+    let data = part["inlineData"]["data"].as_str().unwrap_or("");
+    shrink_part(part, mime, data, threshold, report).unwrap_or_else(|reason| {
+        tracing::warn!(
+            "[InlineOffload] {} attachment of {} exceeds {} ({}), forwarding unchanged",
+            mime,
+            format_mb(decoded_len(data.len())),
+            format_mb(threshold),
+            reason
+        );
+        report.passed_through += 1;
+        vec![part.clone()]
+    })
+}
+
+fn part_key(part: &Value) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    part["inlineData"]["mimeType"].as_str().hash(&mut hasher);
+    part["inlineData"]["data"].as_str().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 处理请求体中所有超限附件 (同步，可能耗时，调用方应放到阻塞线程)
+/// cache 以附件内容为键保存处理结果，同一请求的重试不会重复缩放 / 拆分
+fn offload_inline_data_cached(
+    body: &mut Value,
+    config: &InlineOffloadConfig,
+    cache: &mut HashMap<u64, Vec<Value>>,
+) -> Result<OffloadReport, String> {
+    let mut report = OffloadReport::default();
+    if !config.enabled {
+        return Ok(report);
+    }
+
+    for parts in parts_arrays_mut(body) {
+        let oversized = parts.iter().any(|p| {
+            p["inlineData"]["data"]
+                .as_str()
+                .is_some_and(|d| decoded_len(d.len()) > config.threshold_bytes)
+        });
+        if !oversized {
+            continue;
+        }
+        let mut rebuilt = Vec::with_capacity(parts.len());
+        for part in parts.drain(..) {
+            let over = part["inlineData"]["data"]
+                .as_str()
+                .is_some_and(|d| decoded_len(d.len()) > config.threshold_bytes);
+            if !over {
+                rebuilt.push(part);
+                continue;
+            }
+            let key = part_key(&part);
+            if let Some(replacement) = cache.get(&key) {
+                rebuilt.extend(replacement.iter().cloned());
+                continue;
+            }
+            let replacement = offload_part(&part, config.threshold_bytes, &mut report);
+            rebuilt.extend(replacement.iter().cloned());
+            cache.insert(key, replacement);
+        }
+        *parts = rebuilt;
+    }
+
+    check_total_size(body, config)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+fn offload_inline_data(body: &mut Value, config: &InlineOffloadConfig) -> Result<OffloadReport, String> {
+    offload_inline_data_cached(body, config, &mut HashMap::new())
+}
+
+/// 所有附件合计大小检查
+fn check_total_size(body: &Value, config: &InlineOffloadConfig) -> Result<(), String> {
+    let total: usize = inline_sizes(body).sum();
+    if total > config.max_request_bytes {
+        return Err(format!(
+            "Attachments total {} which exceeds the request limit of {}. Send fewer or smaller files.",
+            format_mb(total),
+            format_mb(config.max_request_bytes)
+        ));
+    }
+    Ok(())
+}
+
+/// 单个客户端请求的附件处理器：在重试循环之前创建，各次尝试转换出的请求体都交给它处理，
+/// 每个超限附件只在第一次出现时缩放 / 拆分，之后的重试直接复用缓存结果
+pub struct InlineOffloader {
+    config: InlineOffloadConfig,
+    cache: HashMap<u64, Vec<Value>>,
+}
+
+impl InlineOffloader {
+    pub fn new(config: InlineOffloadConfig) -> Self {
+        Self { config, cache: HashMap::new() }
+    }
+
+    /// 发送前的附件处理入口：无需处理时原样返回，否则在阻塞线程中处理
+    /// 返回的错误只来自合计大小超限，调用方以各协议的结构化错误 (413) 返回
+    pub async fn prepare(&mut self, body: Value) -> Result<Value, String> {
+        if !self.config.enabled {
+            return Ok(body);
+        }
+        if !needs_offload(&body, &self.config) {
+            check_total_size(&body, &self.config)?;
+            return Ok(body);
+        }
+
+        let config = self.config.clone();
+        let mut cache = std::mem::take(&mut self.cache);
+        let (body, cache, result) = tokio::task::spawn_blocking(move || {
+            let mut body = body;
+            let result = offload_inline_data_cached(&mut body, &config, &mut cache);
+            (body, cache, result)
+        })
+        .await
+        .map_err(|e| format!("Attachment processing task failed: {}", e))?;
+        self.cache = cache;
+
+        let report = result?;
+        if !report.is_empty() {
+            tracing::info!(
+                "[InlineOffload] Shrunk {} image(s), split {} text attachment(s), forwarded {} unchanged, saved {}",
+                report.images_shrunk,
+                report.texts_split,
+                report.passed_through,
+                format_mb(report.saved_bytes)
+            );
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn config(threshold: usize) -> InlineOffloadConfig {
+        InlineOffloadConfig {
+            enabled: true,
+            threshold_bytes: threshold,
+            max_request_bytes: 64 * 1024 * 1024,
+        }
+    }
+
+    fn wrap(parts: Vec<Value>) -> Value {
+        json!({ "request": { "contents": [{ "role": "user", "parts": parts }] } })
+    }
+
+    fn noisy_png(side: u32) -> String {
+        // 伪随机噪声，避免压缩后过小
+        let img = RgbImage::from_fn(side, side, |x, y| {
+            let v = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761) >> 24;
+            Rgb([v as u8, (v >> 1) as u8, (v >> 2) as u8])
+        });
+        let mut out = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut out, image::ImageFormat::Png).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(out.into_inner())
+    }
+
+    #[test]
+    fn test_large_image_is_downscaled_below_threshold() {
+        let png = noisy_png(1024);
+        let threshold = 200 * 1024;
+        assert!(decoded_len(png.len()) > threshold);
+        let mut body = wrap(vec![
+            json!({ "text": "what is this?" }),
+            json!({ "inlineData": { "mimeType": "image/png", "data": png } }),
+        ]);
+        assert!(needs_offload(&body, &config(threshold)));
+
+        let report = offload_inline_data(&mut body, &config(threshold)).unwrap();
+        assert_eq!(report.images_shrunk, 1);
+        let part = &body["request"]["contents"][0]["parts"][1]["inlineData"];
+        assert_eq!(part["mimeType"], "image/jpeg");
+        assert!(decoded_len(part["data"].as_str().unwrap().len()) <= threshold);
+        assert_eq!(body["request"]["contents"][0]["parts"][0]["text"], "what is this?");
+    }
+
+    #[test]
+    fn test_text_attachment_is_split_and_pdf_passed_through() {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let text = "第一行 line\n".repeat(200);
+        let mut body = wrap(vec![json!({ "inlineData": { "mimeType": "text/plain", "data": engine.encode(&text) } })]);
+        let report = offload_inline_data(&mut body, &config(1024)).unwrap();
+        assert_eq!(report.texts_split, 1);
+
+        let parts = body["request"]["contents"][0]["parts"].as_array().unwrap();
+        assert!(parts.len() > 1);
+        let joined: String = parts
+            .iter()
+            .map(|p| String::from_utf8(engine.decode(p["inlineData"]["data"].as_str().unwrap()).unwrap()).unwrap())
+            .collect();
+        assert_eq!(joined, text);
+
+        // PDF 等无法本地处理的类型原样转发
+        let pdf_part = json!({ "inlineData": { "mimeType": "application/pdf", "data": "A".repeat(4096) } });
+        let mut pdf = wrap(vec![pdf_part.clone()]);
+        let report = offload_inline_data(&mut pdf, &config(1024)).unwrap();
+        assert_eq!(report.passed_through, 1);
+        assert_eq!(pdf["request"]["contents"][0]["parts"][0], pdf_part);
+    }
+
+    #[tokio::test]
+    async fn test_offloader_reuses_results_across_attempts() {
+        let png = noisy_png(512);
+        let body = wrap(vec![json!({ "inlineData": { "mimeType": "image/png", "data": png } })]);
+        let mut offloader = InlineOffloader::new(config(64 * 1024));
+
+        let first = offloader.prepare(body.clone()).await.unwrap();
+        assert_eq!(offloader.cache.len(), 1);
+        let second = offloader.prepare(body).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(offloader.cache.len(), 1);
+    }
+
+    #[test]
+    fn test_total_limit_and_disabled() {
+        let mut cfg = config(1024 * 1024);
+        cfg.max_request_bytes = 1000;
+        let mut body = wrap(vec![json!({ "inlineData": { "mimeType": "image/png", "data": "A".repeat(2000) } })]);
+        assert!(offload_inline_data(&mut body, &cfg).unwrap_err().contains("request limit"));
+
+        cfg.enabled = false;
+        assert!(offload_inline_data(&mut body, &cfg).unwrap().is_empty());
+        assert!(!needs_offload(&body, &cfg));
+    }
+}
@@ -0,0 +1,66 @@
+// SSE 保活 (Keep-Alive)
+// 上游在长时间思考或客户端执行耗时工具期间可能长时间没有输出，客户端 / 中间代理会把空闲的流当作断开。
+// 在两个事件之间超过间隔时插入 SSE 注释行 (`: keep-alive`)，符合规范的解析器会直接忽略。
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+
+pub const KEEP_ALIVE_COMMENT: &str = ": keep-alive\n\n";
+
+/// 为流插入保活注释 (interval 为 0 时原样透传)
+pub fn with_keep_alive<S, E>(stream: S, interval: Duration) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    async_stream::stream! {
+        let mut stream = std::pin::pin!(stream);
+        loop {
+            if interval.is_zero() {
+                match stream.next().await {
+                    Some(item) => yield item,
+                    None => break,
+                }
+                continue;
+            }
+            match tokio::time::timeout(interval, stream.next()).await {
+                Ok(Some(item)) => yield item,
+                Ok(None) => break,
+                Err(_) => {
+                    tracing::debug!("[Keep-Alive] Upstream idle for {:?}, sending keep-alive", interval);
+                    yield Ok(Bytes::from_static(KEEP_ALIVE_COMMENT.as_bytes()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keep_alive_emitted_only_while_idle() {
+        let upstream = async_stream::stream! {
+            yield Ok::<Bytes, String>(Bytes::from_static(b"data: first\n\n"));
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            yield Ok(Bytes::from_static(b"data: second\n\n"));
+        };
+        let events: Vec<String> = with_keep_alive(upstream, Duration::from_millis(100))
+            .map(|item| String::from_utf8(item.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+        assert_eq!(events.first().unwrap(), "data: first\n\n");
+        assert_eq!(events.last().unwrap(), "data: second\n\n");
+        let keep_alives = &events[1..events.len() - 1];
+        assert!(!keep_alives.is_empty());
+        assert!(keep_alives.iter().all(|e| e == KEEP_ALIVE_COMMENT));
+
+        // 间隔为 0 时不插入任何事件
+        let quiet = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            yield Ok::<Bytes, String>(Bytes::from_static(b"data: only\n\n"));
+        };
+        assert_eq!(with_keep_alive(quiet, Duration::ZERO).collect::<Vec<_>>().await.len(), 1);
+    }
+}
@@ -5,3 +5,17 @@
 pub mod model_mapping;
 pub mod utils;
 pub mod json_schema;
+pub mod routing_info;
+pub mod image_fit;
+pub mod inline_offload;
+pub mod trace_id;
+pub mod token_estimate;
+pub mod sse_split;
+pub mod time_context;
+pub mod keep_alive;
+pub mod ratelimit_headers;
+pub mod text_preview;
+pub mod output_locale;
+pub mod text_sanitize;
+pub mod usage_heartbeat;
+pub mod timing;
@@ -1,6 +1,10 @@
 // 模型名称映射
 use std::collections::HashMap;
 use once_cell::sync::Lazy;
+use crate::proxy::config::ModelAlias;
+
+/// 别名链的最大解析深度 (防止 a -> b -> a 循环)
+const MAX_ALIAS_DEPTH: usize = 8;
 
 static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     let mut m = HashMap::new();
@@ -70,6 +74,11 @@ pub fn map_claude_model_to_gemini(input: &str) -> String {
     "claude-sonnet-4-5".to_string()
 }
 
+/// 是否为内置映射的目标模型 (能力已知，无需探测)
+pub fn is_builtin_target(model: &str) -> bool {
+    CLAUDE_TO_GEMINI.values().any(|target| *target == model)
+}
+
 /// 获取所有内置支持的模型列表关键字
 pub fn get_supported_models() -> Vec<String> {
     CLAUDE_TO_GEMINI.keys().map(|s| s.to_string()).collect()
@@ -78,6 +87,7 @@ pub fn get_supported_models() -> Vec<String> {
 /// 动态获取所有可用模型列表 (包含内置与用户自定义)
 pub async fn get_all_dynamic_models(
     custom_mapping: &tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    model_aliases: &tokio::sync::RwLock<Vec<ModelAlias>>,
 ) -> Vec<String> {
     use std::collections::HashSet;
     let mut model_ids = HashSet::new();
@@ -95,6 +105,11 @@ pub async fn get_all_dynamic_models(
         }
     }
 
+    // 3. 用户定义的模型别名
+    for alias in model_aliases.read().await.iter() {
+        model_ids.insert(alias.name.clone());
+    }
+
     // 5. 确保包含常用的 Gemini/画画模型 ID
     model_ids.insert("gemini-3-pro-low".to_string());
     
@@ -111,6 +126,8 @@ pub async fn get_all_dynamic_models(
             model_ids.insert(id);
         }
     }
+    // 图文混合输出 (文字与图片交替，保留 tools / system)
+    model_ids.insert(format!("{}{}", base, crate::proxy::mappers::common_utils::MIXED_OUTPUT_SUFFIX));
 
     model_ids.insert("gemini-2.0-flash-exp".to_string());
     model_ids.insert("gemini-2.5-flash".to_string());
@@ -132,7 +149,7 @@ pub async fn get_all_dynamic_models(
 /// - `gpt-4*` 匹配 `gpt-4`, `gpt-4-turbo`, `gpt-4-0613` 等
 /// - `claude-3-5-sonnet-*` 匹配所有 3.5 sonnet 版本
 /// - `*-thinking` 匹配所有以 `-thinking` 结尾的模型
-fn wildcard_match(pattern: &str, text: &str) -> bool {
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
     if let Some(star_pos) = pattern.find('*') {
         let prefix = &pattern[..star_pos];
         let suffix = &pattern[star_pos + 1..];
@@ -177,6 +194,35 @@ pub fn resolve_model_route(
     result
 }
 
+/// 解析模型别名 (支持别名指向别名)，非别名返回 None
+pub fn resolve_alias<'a>(model: &str, aliases: &'a [ModelAlias]) -> Option<&'a str> {
+    let mut current: Option<&'a str> = None;
+    for _ in 0..MAX_ALIAS_DEPTH {
+        let name = current.unwrap_or(model);
+        match aliases.iter().find(|a| a.name == name && !a.target.is_empty()) {
+            Some(alias) => current = Some(&alias.target),
+            None => return current,
+        }
+    }
+    tracing::warn!("[Router] 模型别名 {} 存在循环引用，按原名处理", model);
+    None
+}
+
+/// 先解析模型别名，再按 resolve_model_route 映射目标模型
+pub fn resolve_model_route_with_aliases(
+    original_model: &str,
+    aliases: &[ModelAlias],
+    custom_mapping: &std::collections::HashMap<String, String>,
+) -> String {
+    match resolve_alias(original_model, aliases) {
+        Some(target) => {
+            crate::modules::logger::log_info(&format!("[Router] 模型别名: {} -> {}", original_model, target));
+            resolve_model_route(target, custom_mapping)
+        }
+        None => resolve_model_route(original_model, custom_mapping),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,4 +247,28 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    #[test]
+    fn test_alias_resolution() {
+        let alias = |name: &str, target: &str| ModelAlias {
+            name: name.to_string(),
+            target: target.to_string(),
+            description: String::new(),
+        };
+        let aliases = vec![
+            alias("fast", "gemini-3-flash"),
+            alias("default", "fast"),
+            alias("smart", "gpt-4"),
+            alias("loop-a", "loop-b"),
+            alias("loop-b", "loop-a"),
+        ];
+        let mapping = HashMap::from([("gpt-4".to_string(), "gemini-3-pro-high".to_string())]);
+
+        assert_eq!(resolve_alias("default", &aliases), Some("gemini-3-flash"));
+        assert_eq!(resolve_alias("gemini-3-flash", &aliases), None);
+        assert_eq!(resolve_alias("loop-a", &aliases), None);
+        // 别名目标继续经过自定义映射
+        assert_eq!(resolve_model_route_with_aliases("smart", &aliases, &mapping), "gemini-3-pro-high");
+        assert_eq!(resolve_model_route_with_aliases("fast", &aliases, &mapping), "gemini-3-flash");
+    }
 }
@@ -0,0 +1,206 @@
+// 代理注入输出内容的本地化
+// 代理会向模型输出中追加少量自有文本 (联网搜索词 / 来源引文、流解析异常提示)。
+// 这些文本按配置的语言或客户端的 Accept-Language 选择，新增语言只需增加一个 Locale 变体与对应的文案表。
+// 未配置且无法从 Accept-Language 判断时保持原有的中文输出。
+
+use axum::http::{header, HeaderMap};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    En,
+    #[default]
+    Zh,
+    Ja,
+}
+
+/// 注入到模型输出中的文案
+pub struct OutputStrings {
+    pub searched_for: &'static str,
+    pub sources: &'static str,
+    /// 来源缺少标题时的占位
+    pub untitled_source: &'static str,
+    pub stream_unstable: &'static str,
+    pub stream_unstable_suggestion: &'static str,
+}
+
+const EN: OutputStrings = OutputStrings {
+    searched_for: "🔍 Searched for:",
+    sources: "🌐 Sources:",
+    untitled_source: "Web source",
+    stream_unstable: "The network connection is unstable. Please check your network or proxy settings.",
+    stream_unstable_suggestion: "Try: 1) check your network connection 2) switch proxy node 3) retry later",
+};
+
+const ZH: OutputStrings = OutputStrings {
+    searched_for: "🔍 已为您搜索：",
+    sources: "🌐 来源引文：",
+    untitled_source: "网页来源",
+    stream_unstable: "网络连接不稳定,请检查您的网络或代理设置。",
+    stream_unstable_suggestion: "请尝试: 1) 检查网络连接 2) 更换代理节点 3) 稍后重试",
+};
+
+const JA: OutputStrings = OutputStrings {
+    searched_for: "🔍 検索したキーワード：",
+    sources: "🌐 参照元：",
+    untitled_source: "ウェブ上の情報源",
+    stream_unstable: "ネットワーク接続が不安定です。ネットワークまたはプロキシの設定を確認してください。",
+    stream_unstable_suggestion: "次をお試しください: 1) ネットワーク接続を確認 2) プロキシノードを変更 3) しばらくしてから再試行",
+};
+
+impl Locale {
+    /// 解析语言标签 (en / en-US / zh-CN / ja-JP ...)，不支持的语言返回 None
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "zh" => Some(Locale::Zh),
+            "ja" => Some(Locale::Ja),
+            _ => None,
+        }
+    }
+
+    /// 按 Accept-Language 的权重选出第一个支持的语言
+    pub fn from_accept_language(value: &str) -> Option<Self> {
+        let mut candidates: Vec<(f32, Locale)> = value
+            .split(',')
+            .filter_map(|item| {
+                let mut fields = item.split(';').map(str::trim);
+                let locale = Locale::parse(fields.next()?)?;
+                let q = fields
+                    .find_map(|f| f.strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (q > 0.0).then_some((q, locale))
+            })
+            .collect();
+        // 稳定排序：权重相同时保持客户端给出的顺序
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.first().map(|(_, locale)| *locale)
+    }
+
+    /// 配置的语言优先 ("auto" 或留空时读取 Accept-Language)
+    pub fn resolve(configured: &str, headers: &HeaderMap) -> Self {
+        let configured = configured.trim();
+        if !configured.is_empty() && !configured.eq_ignore_ascii_case("auto") {
+            if let Some(locale) = Locale::parse(configured) {
+                return locale;
+            }
+        }
+        headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Locale::from_accept_language)
+            .unwrap_or_default()
+    }
+
+    pub fn strings(self) -> &'static OutputStrings {
+        match self {
+            Locale::En => &EN,
+            Locale::Zh => &ZH,
+            Locale::Ja => &JA,
+        }
+    }
+}
+
+/// 一条联网搜索来源 (index 为其在 groundingChunks 中的位置)
+pub struct GroundingSource<'a> {
+    pub index: usize,
+    pub title: Option<&'a str>,
+    pub uri: Option<&'a str>,
+}
+
+/// 将搜索词与来源渲染为追加在正文后的 Markdown
+pub fn grounding_markdown(locale: Locale, queries: &[&str], sources: &[GroundingSource]) -> String {
+    let strings = locale.strings();
+    let mut text = String::new();
+    if !queries.is_empty() {
+        text.push_str(&format!("\n\n---\n**{}** ", strings.searched_for));
+        text.push_str(&queries.join(", "));
+    }
+    if !sources.is_empty() {
+        let links: Vec<String> = sources
+            .iter()
+            .map(|s| {
+                format!(
+                    "[{}] [{}]({})",
+                    s.index + 1,
+                    s.title.unwrap_or(strings.untitled_source),
+                    s.uri.unwrap_or("#")
+                )
+            })
+            .collect();
+        text.push_str(&format!("\n\n**{}**\n", strings.sources));
+        text.push_str(&links.join("\n"));
+    }
+    text
+}
+
+/// 同 `grounding_markdown`，直接读取上游 JSON 中的 groundingMetadata
+pub fn grounding_markdown_from_json(locale: Locale, grounding: &Value) -> String {
+    let queries: Vec<&str> = grounding
+        .get("webSearchQueries")
+        .and_then(|q| q.as_array())
+        .map(|q| q.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let sources: Vec<GroundingSource> = grounding
+        .get("groundingChunks")
+        .and_then(|c| c.as_array())
+        .map(|chunks| {
+            chunks
+                .iter()
+                .enumerate()
+                .filter_map(|(index, chunk)| {
+                    let web = chunk.get("web")?;
+                    Some(GroundingSource {
+                        index,
+                        title: web.get("title").and_then(|v| v.as_str()),
+                        uri: web.get("uri").and_then(|v| v.as_str()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    grounding_markdown(locale, &queries, &sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_locale() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Locale::resolve("auto", &headers), Locale::Zh);
+        headers.insert(header::ACCEPT_LANGUAGE, "fr-FR, ja;q=0.8, en;q=0.9".parse().unwrap());
+        assert_eq!(Locale::resolve("auto", &headers), Locale::En);
+        assert_eq!(Locale::resolve("", &headers), Locale::En);
+        // 配置优先于 Accept-Language
+        assert_eq!(Locale::resolve("ja", &headers), Locale::Ja);
+        headers.insert(header::ACCEPT_LANGUAGE, "zh-TW, en;q=0".parse().unwrap());
+        assert_eq!(Locale::resolve("auto", &headers), Locale::Zh);
+        assert_eq!(Locale::from_accept_language("de, fr"), None);
+    }
+
+    #[test]
+    fn test_grounding_markdown() {
+        let grounding = json!({
+            "webSearchQueries": ["rust axum", "tokio"],
+            "groundingChunks": [
+                { "retrievedContext": {} },
+                { "web": { "title": "Axum docs", "uri": "https://docs.rs/axum" } },
+                { "web": { "uri": "https://tokio.rs" } }
+            ]
+        });
+        let en = grounding_markdown_from_json(Locale::En, &grounding);
+        assert_eq!(
+            en,
+            "\n\n---\n**🔍 Searched for:** rust axum, tokio\n\n**🌐 Sources:**\n\
+             [2] [Axum docs](https://docs.rs/axum)\n[3] [Web source](https://tokio.rs)"
+        );
+        let zh = grounding_markdown_from_json(Locale::Zh, &grounding);
+        assert!(zh.contains("**🔍 已为您搜索：** rust axum") && zh.contains("[网页来源]"));
+        assert!(grounding_markdown_from_json(Locale::Ja, &json!({})).is_empty());
+    }
+}
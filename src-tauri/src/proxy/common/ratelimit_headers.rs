@@ -0,0 +1,127 @@
+// 限流状态响应头
+// 以账号池为单位计算限流状态，按 Anthropic (anthropic-ratelimit-*) 与 OpenAI (x-ratelimit-*) 的约定回显，
+// 便于客户端调度器 (如 Claude Code 的退避逻辑) 感知代理的真实可用容量，而不是盲目重试。
+// 这里的 "requests" 维度表示当前可用 (未被限流) 的账号数，reset 为最早恢复的被限流账号的解锁时间。
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::Response;
+
+/// 账号池中没有限流记录 (无法推算恢复时间) 时建议的重试间隔 (秒)
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
+/// 账号池限流概况
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolRateLimitState {
+    /// 账号池中的账号总数
+    pub total: usize,
+    /// 当前未被限流的账号数
+    pub available: usize,
+    /// 被限流账号中最早恢复的剩余秒数
+    pub soonest_reset_secs: Option<u64>,
+}
+
+impl PoolRateLimitState {
+    /// 距离出现可用账号的秒数 (已有可用账号时为 0)
+    pub fn reset_secs(&self) -> u64 {
+        if self.available > 0 {
+            0
+        } else {
+            self.soonest_reset_secs.unwrap_or(0)
+        }
+    }
+
+    /// 所有尝试均失败后建议客户端等待的秒数：被限流账号中最早恢复的剩余锁定时间
+    pub fn retry_after_secs(&self) -> u64 {
+        self.soonest_reset_secs.filter(|s| *s > 0).unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+    }
+}
+
+fn set(headers: &mut HeaderMap, name: &'static str, value: String) {
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+/// 写入限流响应头；上游 (如 z.ai 透传) 已返回限流头时保持原样
+pub fn apply(headers: &mut HeaderMap, status: StatusCode, state: &PoolRateLimitState, now: chrono::DateTime<chrono::Utc>) {
+    if headers.contains_key("anthropic-ratelimit-requests-limit") || headers.contains_key("x-ratelimit-limit-requests") {
+        return;
+    }
+    let reset_secs = state.reset_secs();
+    let reset_at = now + chrono::Duration::seconds(reset_secs as i64);
+
+    set(headers, "anthropic-ratelimit-requests-limit", state.total.to_string());
+    set(headers, "anthropic-ratelimit-requests-remaining", state.available.to_string());
+    set(
+        headers,
+        "anthropic-ratelimit-requests-reset",
+        reset_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    );
+    set(headers, "x-ratelimit-limit-requests", state.total.to_string());
+    set(headers, "x-ratelimit-remaining-requests", state.available.to_string());
+    set(headers, "x-ratelimit-reset-requests", format!("{}s", reset_secs));
+
+    // 账号全部被限流时，告诉客户端何时重试有意义
+    let throttled = matches!(status.as_u16(), 429 | 503 | 529);
+    if throttled && state.available == 0 && reset_secs > 0 && !headers.contains_key("retry-after") {
+        set(headers, "retry-after", reset_secs.to_string());
+    }
+}
+
+/// 最终失败响应的重试提示：Retry-After 与 retry-after-ms (Anthropic / OpenAI SDK 均会读取)，
+/// 以及 Anthropic SDK 使用的 x-should-retry
+pub fn with_retry_hint(mut response: Response, retry_after_secs: u64) -> Response {
+    let headers = response.headers_mut();
+    set(headers, "retry-after", retry_after_secs.to_string());
+    set(headers, "retry-after-ms", retry_after_secs.saturating_mul(1000).to_string());
+    set(headers, "x-should-retry", "true".to_string());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_reflect_pool_state() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+
+        let mut headers = HeaderMap::new();
+        let healthy = PoolRateLimitState { total: 3, available: 2, soonest_reset_secs: Some(40) };
+        apply(&mut headers, StatusCode::OK, &healthy, now);
+        assert_eq!(headers["anthropic-ratelimit-requests-limit"], "3");
+        assert_eq!(headers["anthropic-ratelimit-requests-remaining"], "2");
+        assert_eq!(headers["anthropic-ratelimit-requests-reset"], "2026-01-02T03:04:05Z");
+        assert_eq!(headers["x-ratelimit-reset-requests"], "0s");
+        assert!(headers.get("retry-after").is_none());
+
+        let mut headers = HeaderMap::new();
+        let exhausted = PoolRateLimitState { total: 2, available: 0, soonest_reset_secs: Some(90) };
+        apply(&mut headers, StatusCode::TOO_MANY_REQUESTS, &exhausted, now);
+        assert_eq!(headers["anthropic-ratelimit-requests-reset"], "2026-01-02T03:05:35Z");
+        assert_eq!(headers["x-ratelimit-remaining-requests"], "0");
+        assert_eq!(headers["retry-after"], "90");
+
+        // 上游已返回的限流头不被覆盖
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-requests-limit", HeaderValue::from_static("50"));
+        apply(&mut headers, StatusCode::OK, &healthy, now);
+        assert_eq!(headers["anthropic-ratelimit-requests-limit"], "50");
+        assert!(headers.get("x-ratelimit-limit-requests").is_none());
+    }
+
+    #[test]
+    fn test_retry_hint_uses_soonest_lockout() {
+        // 仍有未限流账号时，同样以最早恢复的锁定时间作为重试提示
+        let partial = PoolRateLimitState { total: 3, available: 1, soonest_reset_secs: Some(42) };
+        assert_eq!(partial.retry_after_secs(), 42);
+        let unknown = PoolRateLimitState { total: 3, available: 3, soonest_reset_secs: None };
+        assert_eq!(unknown.retry_after_secs(), DEFAULT_RETRY_AFTER_SECS);
+
+        use axum::response::IntoResponse;
+        let response = with_retry_hint((StatusCode::TOO_MANY_REQUESTS, "busy").into_response(), 42);
+        assert_eq!(response.headers()["retry-after"], "42");
+        assert_eq!(response.headers()["retry-after-ms"], "42000");
+        assert_eq!(response.headers()["x-should-retry"], "true");
+    }
+}
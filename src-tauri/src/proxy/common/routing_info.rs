@@ -0,0 +1,278 @@
+// 路由信息 (账号 / 映射模型 / 尝试次数 / 重试原因)
+// 统一通过响应头下发，流式响应额外写入最终事件的 `proxy_metadata` 字段。
+// 出于隐私考虑可通过 `expose_routing_info` 配置关闭 (监控日志仍可读取)。
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::response::Response;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+
+use super::timing::{RequestTiming, HEADER_TRANSFORM_MS, HEADER_UPSTREAM_TTFB_MS};
+
+pub const HEADER_ACCOUNT_EMAIL: &str = "X-Account-Email";
+pub const HEADER_MAPPED_MODEL: &str = "X-Mapped-Model";
+pub const HEADER_ATTEMPTS: &str = "X-Attempt-Count";
+pub const HEADER_RETRY_REASONS: &str = "X-Retry-Reasons";
+pub const HEADER_EXPERIMENT: &str = "X-Experiment";
+pub const HEADER_SESSION_ID: &str = "X-Session-Id";
+pub const HEADER_SUBAGENT: &str = "X-Subagent";
+
+/// 写入最终流式事件的字段名
+pub const METADATA_FIELD: &str = "proxy_metadata";
+
+/// 流式协议 (决定哪个事件被视为最终事件)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamProtocol {
+    /// Anthropic Messages: `message_stop`
+    Claude,
+    /// OpenAI Chat Completions: 携带 finish_reason 的 chunk
+    OpenAIChat,
+    /// OpenAI Responses (Codex): `response.completed`
+    Codex,
+    /// Gemini 原生: 携带 finishReason 的 chunk
+    Gemini,
+}
+
+/// 单个客户端请求的路由信息
+#[derive(Debug, Clone, Default)]
+pub struct RoutingInfo {
+    pub account_email: Option<String>,
+    pub mapped_model: Option<String>,
+    pub attempts: usize,
+    pub retry_reasons: Vec<String>,
+    /// A/B 实验分组标签 (实验名:分组)
+    pub experiment: Option<String>,
+    /// 会话指纹 (用于按会话导出对话记录)
+    pub session_id: Option<String>,
+    /// 命中的子代理类别 (见 subagent_routing)
+    pub subagent: Option<String>,
+    /// 转换 / 上游首字节耗时 (写入请求日志的耗时汇总)
+    pub timing: RequestTiming,
+}
+
+impl RoutingInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 开始一次新的上游尝试
+    pub fn start_attempt(&mut self, account_email: &str, mapped_model: &str) {
+        self.attempts += 1;
+        self.account_email = Some(account_email.to_string());
+        self.mapped_model = Some(mapped_model.to_string());
+    }
+
+    /// 记录一次重试原因 (e.g. "429", "503", "network", "empty_stream")
+    pub fn record_retry(&mut self, reason: impl Into<String>) {
+        self.retry_reasons.push(reason.into());
+    }
+
+    /// 写入响应头 (覆盖已存在的同名头)
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        let mut set = |name: &'static str, value: &str| {
+            if let (Ok(n), Ok(v)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.insert(n, v);
+            }
+        };
+        if let Some(email) = &self.account_email {
+            set(HEADER_ACCOUNT_EMAIL, email);
+        }
+        if let Some(model) = &self.mapped_model {
+            set(HEADER_MAPPED_MODEL, model);
+        }
+        if self.attempts > 0 {
+            set(HEADER_ATTEMPTS, &self.attempts.to_string());
+        }
+        if !self.retry_reasons.is_empty() {
+            set(HEADER_RETRY_REASONS, &self.retry_reasons.join(", "));
+        }
+        if let Some(tag) = &self.experiment {
+            set(HEADER_EXPERIMENT, tag);
+        }
+        if let Some(session_id) = &self.session_id {
+            set(HEADER_SESSION_ID, session_id);
+        }
+        if let Some(subagent) = &self.subagent {
+            set(HEADER_SUBAGENT, subagent);
+        }
+        if let Some(ms) = self.timing.transform_ms {
+            set(HEADER_TRANSFORM_MS, &ms.to_string());
+        }
+        if let Some(ms) = self.timing.upstream_ttfb_ms {
+            set(HEADER_UPSTREAM_TTFB_MS, &ms.to_string());
+        }
+    }
+
+    /// 为响应附加路由头
+    pub fn attach(&self, mut response: Response) -> Response {
+        self.apply_headers(response.headers_mut());
+        response
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "account_email": self.account_email,
+            "mapped_model": self.mapped_model,
+            "attempts": self.attempts,
+            "retry_reasons": self.retry_reasons,
+            "experiment": self.experiment,
+            "subagent": self.subagent,
+        })
+    }
+
+    /// 将路由信息写入流的最终事件 (只写入一次)
+    pub fn inject_into_stream<S, E>(
+        &self,
+        stream: S,
+        protocol: StreamProtocol,
+    ) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        let metadata = self.to_json();
+        let mut injected = false;
+        stream.map(move |item| match item {
+            Ok(chunk) if !injected => match inject_into_chunk(&chunk, protocol, &metadata) {
+                Some(rewritten) => {
+                    injected = true;
+                    Ok(rewritten)
+                }
+                None => Ok(chunk),
+            },
+            other => other,
+        })
+    }
+}
+
+/// 从响应中移除路由头 (expose_routing_info 关闭时使用)
+pub fn strip_headers(headers: &mut HeaderMap) {
+    for name in [
+        HEADER_ACCOUNT_EMAIL,
+        HEADER_MAPPED_MODEL,
+        HEADER_ATTEMPTS,
+        HEADER_RETRY_REASONS,
+        HEADER_EXPERIMENT,
+        HEADER_SESSION_ID,
+        HEADER_SUBAGENT,
+        HEADER_TRANSFORM_MS,
+        HEADER_UPSTREAM_TTFB_MS,
+    ] {
+        headers.remove(name);
+    }
+}
+
+fn is_final_event(protocol: StreamProtocol, event: &Value) -> bool {
+    match protocol {
+        StreamProtocol::Claude => event["type"] == "message_stop",
+        StreamProtocol::Codex => event["type"] == "response.completed",
+        StreamProtocol::OpenAIChat => event["choices"]
+            .as_array()
+            .is_some_and(|choices| choices.iter().any(|c| c["finish_reason"].is_string())),
+        StreamProtocol::Gemini => event["candidates"]
+            .as_array()
+            .is_some_and(|cands| cands.iter().any(|c| c["finishReason"].is_string())),
+    }
+}
+
+/// 若 chunk 中包含最终事件，返回写入 metadata 后的新 chunk
+fn inject_into_chunk(chunk: &Bytes, protocol: StreamProtocol, metadata: &Value) -> Option<Bytes> {
+    let text = std::str::from_utf8(chunk).ok()?;
+    let mut found = false;
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            if found {
+                return line.to_string();
+            }
+            let Some(payload) = line.strip_prefix("data: ") else {
+                return line.to_string();
+            };
+            match serde_json::from_str::<Value>(payload) {
+                Ok(mut event) if event.is_object() && is_final_event(protocol, &event) => {
+                    found = true;
+                    event[METADATA_FIELD] = metadata.clone();
+                    format!("data: {}", event)
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+
+    if found {
+        Some(Bytes::from(lines.join("\n")))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> RoutingInfo {
+        let mut info = RoutingInfo::new();
+        info.session_id = Some("sid-0123456789abcdef".to_string());
+        info.subagent = Some("reviewer".to_string());
+        info.start_attempt("a@example.com", "gemini-2.5-flash");
+        info.record_retry("429");
+        info.start_attempt("b@example.com", "gemini-2.5-flash");
+        info.timing.transform_ms = Some(4);
+        info
+    }
+
+    #[test]
+    fn test_headers_roundtrip_and_strip() {
+        let mut headers = HeaderMap::new();
+        info().apply_headers(&mut headers);
+        assert_eq!(headers.get("x-account-email").unwrap(), "b@example.com");
+        assert_eq!(headers.get("x-mapped-model").unwrap(), "gemini-2.5-flash");
+        assert_eq!(headers.get("x-attempt-count").unwrap(), "2");
+        assert_eq!(headers.get("x-retry-reasons").unwrap(), "429");
+        assert_eq!(headers.get("x-session-id").unwrap(), "sid-0123456789abcdef");
+        assert_eq!(headers.get("x-subagent").unwrap(), "reviewer");
+        assert_eq!(headers.get("x-transform-ms").unwrap(), "4");
+        assert!(headers.get("x-upstream-ttfb-ms").is_none());
+
+        strip_headers(&mut headers);
+        assert!(headers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inject_into_final_claude_event_only_once() {
+        let chunks = vec![
+            Ok::<_, String>(Bytes::from("event: message_delta\ndata: {\"type\":\"message_delta\"}\n\n")),
+            Ok(Bytes::from("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n")),
+            Ok(Bytes::from("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n")),
+        ];
+        let out: Vec<String> = info()
+            .inject_into_stream(futures::stream::iter(chunks), StreamProtocol::Claude)
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect()
+            .await;
+
+        assert!(!out[0].contains(METADATA_FIELD));
+        assert!(out[1].starts_with("event: message_stop\ndata: "));
+        assert!(out[1].ends_with("\n\n"));
+        let data: Value = serde_json::from_str(out[1].lines().nth(1).unwrap().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(data[METADATA_FIELD]["attempts"], 2);
+        assert_eq!(data[METADATA_FIELD]["retry_reasons"][0], "429");
+        assert!(!out[2].contains(METADATA_FIELD));
+    }
+
+    #[test]
+    fn test_final_event_detection_per_protocol() {
+        let meta = json!({});
+        let openai = Bytes::from("data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n");
+        assert!(inject_into_chunk(&openai, StreamProtocol::OpenAIChat, &meta).is_some());
+        let openai_mid = Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"x\"},\"finish_reason\":null}]}\n\n");
+        assert!(inject_into_chunk(&openai_mid, StreamProtocol::OpenAIChat, &meta).is_none());
+
+        let codex = Bytes::from("data: {\"type\":\"response.completed\",\"response\":{}}\n\n");
+        assert!(inject_into_chunk(&codex, StreamProtocol::Codex, &meta).is_some());
+
+        let gemini = Bytes::from("data: {\"candidates\":[{\"finishReason\":\"STOP\"}]}\n\n");
+        assert!(inject_into_chunk(&gemini, StreamProtocol::Gemini, &meta).is_some());
+        assert!(inject_into_chunk(&Bytes::from("data: [DONE]\n\n"), StreamProtocol::OpenAIChat, &meta).is_none());
+    }
+}
@@ -0,0 +1,344 @@
+// 超大 SSE 事件拆分 (SSE Chunk Splitting)
+// 部分客户端无法处理单行数 MB 的 SSE data (例如 base64 图片)。转换后的事件中，增量文本超过上限时，
+// 按各协议规则拆成多个同类增量事件：文本在 UTF-8 字符边界切分，结束标记 / 用量只保留在最后一个事件上。
+// 无法拆分的内容 (Claude image 块、Gemini inlineData) 原样透传。
+
+use super::routing_info::StreamProtocol;
+use crate::proxy::config::SseChunkingConfig;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::Value;
+
+impl SseChunkingConfig {
+    /// 指定协议的单事件增量上限 (0 表示不拆分)
+    pub fn limit_for(&self, protocol: StreamProtocol) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+        match protocol {
+            StreamProtocol::Claude => self.claude_max_delta_bytes,
+            StreamProtocol::OpenAIChat | StreamProtocol::Codex => self.openai_max_delta_bytes,
+            StreamProtocol::Gemini => self.gemini_max_delta_bytes,
+        }
+    }
+}
+
+/// 按字节上限在字符边界切分文本
+pub fn split_text(text: &str, max_bytes: usize) -> Vec<&str> {
+    if max_bytes == 0 || text.len() <= max_bytes {
+        return vec![text];
+    }
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut cut = max_bytes;
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        if cut == 0 {
+            // 上限小于单个字符
+            cut = rest.chars().next().map(char::len_utf8).unwrap_or(rest.len());
+        }
+        let (head, tail) = rest.split_at(cut);
+        pieces.push(head);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// 对流中的超大事件进行拆分 (max_bytes 为 0 时原样透传)
+pub fn split_stream<S, E>(
+    stream: S,
+    protocol: StreamProtocol,
+    max_bytes: usize,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    stream.flat_map(move |item| {
+        let items: Vec<Result<Bytes, E>> = match item {
+            Ok(chunk) if max_bytes > 0 && chunk.len() > max_bytes => {
+                match split_chunk(&chunk, protocol, max_bytes) {
+                    Some(pieces) => pieces.into_iter().map(Ok).collect(),
+                    None => vec![Ok(chunk)],
+                }
+            }
+            other => vec![other],
+        };
+        futures::stream::iter(items)
+    })
+}
+
+/// 拆分 chunk 中的超大事件；没有需要拆分的事件时返回 None
+fn split_chunk(chunk: &Bytes, protocol: StreamProtocol, max_bytes: usize) -> Option<Vec<Bytes>> {
+    let text = std::str::from_utf8(chunk).ok()?;
+    let mut out = Vec::new();
+    let mut changed = false;
+    for event in text.split_inclusive("\n\n") {
+        match split_event(event, protocol, max_bytes) {
+            Some(pieces) => {
+                changed = true;
+                out.extend(pieces.into_iter().map(Bytes::from));
+            }
+            None => out.push(Bytes::from(event.to_string())),
+        }
+    }
+    changed.then_some(out)
+}
+
+/// 拆分单个 SSE 事件 ("event: x\ndata: {...}\n\n")
+fn split_event(event: &str, protocol: StreamProtocol, max_bytes: usize) -> Option<Vec<String>> {
+    if event.len() <= max_bytes {
+        return None;
+    }
+    let mut prefix = String::new();
+    let mut payload = None;
+    for line in event.lines() {
+        match line.strip_prefix("data: ") {
+            Some(data) if payload.is_none() => payload = Some(data),
+            Some(_) => return None,
+            None if payload.is_none() && !line.is_empty() => {
+                prefix.push_str(line);
+                prefix.push('\n');
+            }
+            None => {}
+        }
+    }
+    let data: Value = serde_json::from_str(payload?).ok()?;
+    let pieces = match protocol {
+        StreamProtocol::Claude => split_claude(&data, max_bytes),
+        StreamProtocol::OpenAIChat => split_openai(&data, max_bytes),
+        StreamProtocol::Codex => split_codex(&data, max_bytes),
+        StreamProtocol::Gemini => split_gemini(&data, max_bytes),
+    }?;
+    Some(
+        pieces
+            .into_iter()
+            .map(|piece| format!("{}data: {}\n\n", prefix, piece))
+            .collect(),
+    )
+}
+
+/// content_block_delta: text_delta / thinking_delta / input_json_delta
+fn split_claude(event: &Value, max_bytes: usize) -> Option<Vec<Value>> {
+    if event["type"] != "content_block_delta" {
+        return None;
+    }
+    let field = match event["delta"]["type"].as_str()? {
+        "text_delta" => "text",
+        "thinking_delta" => "thinking",
+        "input_json_delta" => "partial_json",
+        _ => return None,
+    };
+    let text = event["delta"][field].as_str()?;
+    let pieces = split_text(text, max_bytes);
+    if pieces.len() < 2 {
+        return None;
+    }
+    Some(
+        pieces
+            .into_iter()
+            .map(|piece| {
+                let mut out = event.clone();
+                out["delta"][field] = Value::String(piece.to_string());
+                out
+            })
+            .collect(),
+    )
+}
+
+/// chat.completion.chunk (单 choice)：delta.content / delta.reasoning_content / text (legacy)
+/// finish_reason 与 usage 只保留在最后一个 chunk
+fn split_openai(event: &Value, max_bytes: usize) -> Option<Vec<Value>> {
+    let choices = event["choices"].as_array()?;
+    if choices.len() != 1 {
+        return None;
+    }
+    let (path, text): (&[&str], &str) = if let Some(text) = choices[0]["delta"]["content"].as_str() {
+        (&["delta", "content"], text)
+    } else if let Some(text) = choices[0]["delta"]["reasoning_content"].as_str() {
+        (&["delta", "reasoning_content"], text)
+    } else {
+        (&["text"], choices[0]["text"].as_str()?)
+    };
+    let pieces = split_text(text, max_bytes);
+    if pieces.len() < 2 {
+        return None;
+    }
+    let last = pieces.len() - 1;
+    Some(
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(i, piece)| {
+                let mut out = event.clone();
+                let choice = &mut out["choices"][0];
+                let target = path[..path.len() - 1].iter().fold(&mut *choice, |v, key| &mut v[*key]);
+                target[path[path.len() - 1]] = Value::String(piece.to_string());
+                if i < last {
+                    choice["finish_reason"] = Value::Null;
+                    if let Some(obj) = out.as_object_mut() {
+                        obj.remove("usage");
+                    }
+                }
+                out
+            })
+            .collect(),
+    )
+}
+
+/// Responses API: response.output_text.delta
+fn split_codex(event: &Value, max_bytes: usize) -> Option<Vec<Value>> {
+    if event["type"] != "response.output_text.delta" {
+        return None;
+    }
+    let pieces = split_text(event["delta"].as_str()?, max_bytes);
+    if pieces.len() < 2 {
+        return None;
+    }
+    Some(
+        pieces
+            .into_iter()
+            .map(|piece| {
+                let mut out = event.clone();
+                out["delta"] = Value::String(piece.to_string());
+                out
+            })
+            .collect(),
+    )
+}
+
+/// Gemini (单 candidate)：text part 切分后每段一个 chunk，finishReason / usageMetadata 只保留在最后
+fn split_gemini(event: &Value, max_bytes: usize) -> Option<Vec<Value>> {
+    let candidates = event["candidates"].as_array()?;
+    if candidates.len() != 1 {
+        return None;
+    }
+    let parts = candidates[0]["content"]["parts"].as_array()?;
+    let mut pieces: Vec<Value> = Vec::new();
+    for part in parts {
+        match part["text"].as_str() {
+            Some(text) if text.len() > max_bytes => {
+                for (j, piece) in split_text(text, max_bytes).into_iter().enumerate() {
+                    let mut p = part.clone();
+                    p["text"] = Value::String(piece.to_string());
+                    // thoughtSignature 只保留在第一段
+                    if j > 0 {
+                        if let Some(obj) = p.as_object_mut() {
+                            obj.remove("thoughtSignature");
+                        }
+                    }
+                    pieces.push(p);
+                }
+            }
+            _ => pieces.push(part.clone()),
+        }
+    }
+    if pieces.len() == parts.len() {
+        return None;
+    }
+    let last = pieces.len() - 1;
+    Some(
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let mut out = event.clone();
+                out["candidates"][0]["content"]["parts"] = Value::Array(vec![part]);
+                if i < last {
+                    if let Some(obj) = out["candidates"][0].as_object_mut() {
+                        obj.remove("finishReason");
+                        obj.remove("groundingMetadata");
+                    }
+                    if let Some(obj) = out.as_object_mut() {
+                        obj.remove("usageMetadata");
+                    }
+                }
+                out
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn events(chunks: Vec<Bytes>) -> Vec<Value> {
+        chunks
+            .iter()
+            .flat_map(|c| {
+                std::str::from_utf8(c)
+                    .unwrap()
+                    .lines()
+                    .filter_map(|l| l.strip_prefix("data: "))
+                    .map(|d| serde_json::from_str::<Value>(d).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    async fn run(chunk: String, protocol: StreamProtocol, max: usize) -> Vec<Bytes> {
+        split_stream(futures::stream::iter(vec![Ok::<_, String>(Bytes::from(chunk))]), protocol, max)
+            .map(|c| c.unwrap())
+            .collect()
+            .await
+    }
+
+    #[test]
+    fn test_split_text_respects_char_boundaries() {
+        assert_eq!(split_text("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(split_text("你好世界", 7), vec!["你好", "世界"]);
+        assert_eq!(split_text("你", 1), vec!["你"]);
+        assert_eq!(split_text("abc", 0), vec!["abc"]);
+    }
+
+    #[tokio::test]
+    async fn test_splits_openai_content_and_keeps_finish_on_last() {
+        let chunk = json!({
+            "id": "c", "object": "chat.completion.chunk",
+            "choices": [{ "index": 0, "delta": { "content": "x".repeat(250) }, "finish_reason": "stop" }]
+        });
+        let out = run(format!("data: {}\n\n", chunk), StreamProtocol::OpenAIChat, 100).await;
+        assert_eq!(out.len(), 3);
+        let evs = events(out);
+        let text: String = evs.iter().map(|e| e["choices"][0]["delta"]["content"].as_str().unwrap()).collect();
+        assert_eq!(text, "x".repeat(250));
+        assert!(evs[0]["choices"][0]["finish_reason"].is_null());
+        assert_eq!(evs[2]["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[tokio::test]
+    async fn test_splits_claude_delta_and_passes_other_events() {
+        let big = json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "text_delta", "text": "y".repeat(300) } });
+        let stop = json!({ "type": "message_stop" });
+        let chunk = format!("event: content_block_delta\ndata: {}\n\nevent: message_stop\ndata: {}\n\n", big, stop);
+        let out = run(chunk, StreamProtocol::Claude, 128).await;
+        assert_eq!(out.len(), 4);
+        assert!(out.iter().take(3).all(|c| c.starts_with(b"event: content_block_delta\ndata: ")));
+        let evs = events(out);
+        assert!(evs[..3].iter().all(|e| e["index"] == 1));
+        assert_eq!(evs[3]["type"], "message_stop");
+    }
+
+    #[tokio::test]
+    async fn test_gemini_inline_data_passes_through_and_disabled_limit_is_noop() {
+        let image = json!({ "candidates": [{ "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": "A".repeat(500) } }] } }] });
+        let chunk = format!("data: {}\n\n", image);
+        assert_eq!(run(chunk.clone(), StreamProtocol::Gemini, 100).await, vec![Bytes::from(chunk.clone())]);
+        assert_eq!(run(chunk.clone(), StreamProtocol::Claude, 0).await.len(), 1);
+
+        let text = json!({
+            "candidates": [{ "content": { "parts": [{ "text": "z".repeat(250) }] }, "finishReason": "STOP" }],
+            "usageMetadata": { "totalTokenCount": 5 }
+        });
+        let evs = events(run(format!("data: {}\n\n", text), StreamProtocol::Gemini, 100).await);
+        assert_eq!(evs.len(), 3);
+        assert!(evs[0].get("usageMetadata").is_none());
+        assert_eq!(evs[2]["candidates"][0]["finishReason"], "STOP");
+    }
+}
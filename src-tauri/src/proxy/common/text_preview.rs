@@ -0,0 +1,119 @@
+// 日志预览工具
+// 按字节下标截取字符串 (如 `&s[..200]`) 遇到中文 / emoji 等多字节字符边界会直接 panic，
+// 未处理的 CR/LF 又会把一条日志拆成多行。日志与错误信息中的内容预览统一走这里：
+// 截取只发生在字符边界上，换行被转义为可见的 `\n` / `\r`，保证一条预览就是一行。
+
+/// 不大于 `index` 的最近字符边界
+pub fn floor_char_boundary(s: &str, index: usize) -> usize {
+    if index >= s.len() {
+        return s.len();
+    }
+    let mut i = index;
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+/// 最多 `max_bytes` 字节的前缀 (不会截断字符)
+pub fn truncate_bytes(s: &str, max_bytes: usize) -> &str {
+    &s[..floor_char_boundary(s, max_bytes)]
+}
+
+/// 最多 `max_chars` 个字符的前缀
+pub fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+/// 转义换行与其他控制字符，使内容在日志中保持单行
+fn escape_line_breaks(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push(' '),
+            c if c.is_control() => out.push_str(&c.escape_unicode().to_string()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 单行预览：最多 `max_chars` 个字符，超出时附带总长度
+pub fn preview(s: &str, max_chars: usize) -> String {
+    let head = truncate_chars(s, max_chars);
+    if head.len() == s.len() {
+        return escape_line_breaks(s);
+    }
+    format!("{}… (total {} chars)", escape_line_breaks(head), s.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &[&str] = &[
+        "",
+        "plain ascii text",
+        "你好，世界！这是一段中文内容。",
+        "emoji 🦀🚀👨‍👩‍👧‍👦 mixed 内容 ✅",
+        "line one\r\nline two\rline three\n\n尾部",
+        "é̃ combining marks and ｆｕｌｌｗｉｄｔｈ",
+        "🇨🇳🇺🇸 flags\u{0}\u{7}control",
+    ];
+
+    #[test]
+    fn test_truncation_never_splits_characters() {
+        for sample in SAMPLES {
+            for max in 0..=sample.len() + 2 {
+                let by_bytes = truncate_bytes(sample, max);
+                assert!(by_bytes.len() <= max.min(sample.len()));
+                assert!(sample.starts_with(by_bytes));
+                let by_chars = truncate_chars(sample, max);
+                assert_eq!(by_chars.chars().count(), max.min(sample.chars().count()));
+                assert!(sample.starts_with(by_chars));
+                assert!(sample.is_char_boundary(floor_char_boundary(sample, max)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_preview_is_single_line() {
+        for sample in SAMPLES {
+            for max in 0..=sample.chars().count() + 1 {
+                let line = preview(sample, max);
+                assert!(!line.contains('\n') && !line.contains('\r'), "{:?}", line);
+            }
+        }
+        assert_eq!(preview("a\r\nb", 10), "a\\r\\nb");
+        assert_eq!(preview("你好世界", 2), "你好… (total 4 chars)");
+    }
+
+    #[test]
+    fn test_pseudo_random_inputs() {
+        // 简单的确定性模糊测试：随机拼接多字节片段并在任意位置截取
+        let pieces = ["a", "中", "🦀", "\r\n", "é", "\u{200d}", "👨‍👩‍👧", "\n", "ー"];
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+        for _ in 0..500 {
+            let mut s = String::new();
+            for _ in 0..next() % 40 {
+                s.push_str(pieces[next() % pieces.len()]);
+            }
+            let cut = next() % (s.len() + 1);
+            let _ = truncate_bytes(&s, cut);
+            let _ = &s[floor_char_boundary(&s, cut)..];
+            let line = preview(&s, cut % 16);
+            assert!(!line.contains('\n'));
+            // 从任意字节位置开始的片段 (如 SSE 尾部缓冲) 经 lossy 解码后同样安全
+            let _ = preview(&String::from_utf8_lossy(&s.as_bytes()[cut..]), 8);
+        }
+    }
+}
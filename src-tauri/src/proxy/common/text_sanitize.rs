@@ -0,0 +1,376 @@
+// 输出文本清理 (Text Sanitation)
+// 作为响应处理的第一道工序，直接作用于上游 Gemini 响应中的 text part，
+// Claude / OpenAI / Codex / Gemini 原生协议的流式与非流式输出因此得到一致的处理：
+// - smart_quotes: 将弯引号 (‘’ “” 等) 替换为直引号，部分客户端 / 下游解析器只认 ASCII 引号
+// - zero_width: 移除零宽空格 / 单词连接符 / BOM (保留 ZWJ / ZWNJ，避免破坏 emoji 序列与部分文字)
+// - undefined_markers: 移除模型复读客户端脏数据产生的 "[undefined]" 字样
+// - post_processors: 用户自定义规则 (正则替换 / 移除 AI 套话 / 移除不可见水印)，按模型与客户端生效，
+//   由 handler 通过 TextSanitizeConfig::for_request 筛选后传入
+
+use crate::proxy::config::{PostProcessAction, TextSanitizeConfig};
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use regex::Regex;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+const UNDEFINED_MARKER: &str = "[undefined]";
+
+/// "As an AI language model, ..." / "作为一个AI语言模型，..." 开场套话 (只移除套话分句本身)
+fn ai_boilerplate_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)\bas an ai(?: language model| model| assistant)?(?: developed by [^,.]+)?,\s*|作为(?:一个|一名)?(?:ai|人工智能)(?:语言模型|模型|助手)?[，,]\s*",
+        )
+        .unwrap()
+    })
+}
+
+/// 常见的不可见水印字符：零宽字符、双向控制符、不可见运算符、软连字符、Unicode 标签字符
+fn is_watermark_char(c: char) -> bool {
+    matches!(c,
+        '\u{00AD}' | '\u{180E}' | '\u{200B}' | '\u{200E}' | '\u{200F}'
+        | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}'
+        | '\u{FEFF}' | '\u{E0000}'..='\u{E007F}')
+}
+
+/// 移除水印字符；ZWJ / ZWNJ 仅在两侧均为 ASCII 时移除 (emoji 序列与部分文字依赖它们)
+fn strip_watermarks(text: &str) -> Cow<'_, str> {
+    let is_joiner = |c: char| c == '\u{200C}' || c == '\u{200D}';
+    if !text.chars().any(|c| is_watermark_char(c) || is_joiner(c)) {
+        return Cow::Borrowed(text);
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let removable = |i: usize| {
+        let c = chars[i];
+        is_watermark_char(c)
+            || (is_joiner(c)
+                && i > 0
+                && chars[i - 1].is_ascii()
+                && chars.get(i + 1).is_some_and(|n| n.is_ascii()))
+    };
+    if !(0..chars.len()).any(removable) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned((0..chars.len()).filter(|&i| !removable(i)).map(|i| chars[i]).collect())
+}
+
+/// 编译后的单条后处理规则
+enum CompiledPostProcess {
+    Replace(Regex, String),
+    StripAiBoilerplate,
+    StripWatermarks,
+}
+
+impl CompiledPostProcess {
+    fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Replace(regex, replacement) => regex.replace_all(text, replacement.as_str()),
+            Self::StripAiBoilerplate => {
+                let re = ai_boilerplate_regex();
+                match re.find(text) {
+                    None => Cow::Borrowed(text),
+                    // 去掉套话后将紧随其后的首字母大写
+                    Some(_) => Cow::Owned(capitalize_after_removal(text, re)),
+                }
+            }
+            Self::StripWatermarks => strip_watermarks(text),
+        }
+    }
+}
+
+fn capitalize_after_removal(text: &str, re: &Regex) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for m in re.find_iter(text) {
+        out.push_str(&text[last..m.start()]);
+        let rest = &text[m.end()..];
+        let at_sentence_start = out.trim_end().is_empty() || out.trim_end().ends_with(['.', '!', '?', '\n', '。', '！', '？']);
+        if let Some(first) = rest.chars().next().filter(|_| at_sentence_start) {
+            out.extend(first.to_uppercase());
+            last = m.end() + first.len_utf8();
+        } else {
+            last = m.end();
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// 预编译的清理器 (流式响应中每个事件复用同一份编译结果)
+pub struct TextSanitizer {
+    config: TextSanitizeConfig,
+    post_processors: Vec<CompiledPostProcess>,
+}
+
+impl TextSanitizer {
+    /// 编译后处理规则；无效的正则被跳过并记录警告
+    pub fn new(config: &TextSanitizeConfig) -> Self {
+        let post_processors = config
+            .post_processors
+            .iter()
+            .filter(|rule| rule.enabled)
+            .filter_map(|rule| match rule.action {
+                PostProcessAction::RegexReplace => match Regex::new(&rule.pattern) {
+                    Ok(regex) if !rule.pattern.is_empty() => {
+                        Some(CompiledPostProcess::Replace(regex, rule.replacement.clone()))
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        tracing::warn!("[TextSanitize] Skipping post-processor '{}': invalid pattern: {}", rule.name, e);
+                        None
+                    }
+                },
+                PostProcessAction::StripAiBoilerplate => Some(CompiledPostProcess::StripAiBoilerplate),
+                PostProcessAction::StripWatermarks => Some(CompiledPostProcess::StripWatermarks),
+            })
+            .collect();
+        Self { config: config.clone(), post_processors }
+    }
+
+    /// 按配置清理一段文本，无需改动时不分配
+    pub fn sanitize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.config.is_active() {
+            return Cow::Borrowed(text);
+        }
+        let mut text = sanitize_builtin(text, &self.config);
+        for processor in &self.post_processors {
+            let changed = match processor.apply(&text) {
+                Cow::Owned(changed) => Some(changed),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(changed) = changed {
+                text = Cow::Owned(changed);
+            }
+        }
+        text
+    }
+}
+
+fn needs_sanitize(text: &str, config: &TextSanitizeConfig) -> bool {
+    text.chars().any(|c| match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' => {
+            config.smart_quotes
+        }
+        '\u{200B}' | '\u{2060}' | '\u{FEFF}' => config.zero_width,
+        _ => false,
+    }) || (config.undefined_markers && text.contains(UNDEFINED_MARKER))
+}
+
+/// 内置规则 (引号 / 零宽字符 / undefined 标记)
+fn sanitize_builtin<'a>(text: &'a str, config: &TextSanitizeConfig) -> Cow<'a, str> {
+    if !needs_sanitize(text, config) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' if config.smart_quotes => out.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' if config.smart_quotes => out.push('"'),
+            '\u{200B}' | '\u{2060}' | '\u{FEFF}' if config.zero_width => {}
+            c => out.push(c),
+        }
+    }
+    if config.undefined_markers {
+        out = out.replace(UNDEFINED_MARKER, "");
+    }
+    Cow::Owned(out)
+}
+
+/// 清理 Gemini 响应 (兼容 v1internal 的 response 包装) 中所有候选的 text part，返回是否有改动
+pub fn sanitize_gemini_response(value: &mut Value, config: &TextSanitizeConfig) -> bool {
+    if !config.is_active() {
+        return false;
+    }
+    sanitize_response_with(value, &TextSanitizer::new(config))
+}
+
+fn sanitize_response_with(value: &mut Value, sanitizer: &TextSanitizer) -> bool {
+    let raw = if value.get("response").is_some() { &mut value["response"] } else { value };
+    let Some(candidates) = raw.get_mut("candidates").and_then(|c| c.as_array_mut()) else {
+        return false;
+    };
+    let mut changed = false;
+    for candidate in candidates {
+        let Some(parts) = candidate.pointer_mut("/content/parts").and_then(|p| p.as_array_mut()) else {
+            continue;
+        };
+        for part in parts {
+            if let Some(Value::String(text)) = part.get_mut("text") {
+                if let Cow::Owned(clean) = sanitizer.sanitize(text) {
+                    *text = clean;
+                    changed = true;
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// 清理一行 SSE：只改写 data 行中的 JSON，其余内容原样保留
+fn sanitize_sse_line(line: &[u8], sanitizer: &TextSanitizer) -> Option<Bytes> {
+    let text = std::str::from_utf8(line).ok()?;
+    let data = text.trim_end_matches(['\r', '\n']).strip_prefix("data:")?;
+    let mut value: Value = serde_json::from_str(data.trim()).ok()?;
+    if !sanitize_response_with(&mut value, sanitizer) {
+        return None;
+    }
+    Some(Bytes::from(format!("data: {}\n", serde_json::to_string(&value).ok()?)))
+}
+
+/// 清理上游 Gemini SSE 流 (按行缓冲，输出仍为完整的 SSE 行)
+pub fn sanitize_gemini_stream<S, E>(
+    stream: S,
+    config: TextSanitizeConfig,
+) -> std::pin::Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    if !config.is_active() {
+        return Box::pin(stream);
+    }
+    let sanitizer = TextSanitizer::new(&config);
+    Box::pin(async_stream::stream! {
+        let mut upstream = Box::pin(stream);
+        let mut buffer = BytesMut::new();
+        while let Some(item) = upstream.next().await {
+            let chunk = match item {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    yield Err(e);
+                    continue;
+                }
+            };
+            buffer.extend_from_slice(&chunk);
+            let mut out = BytesMut::new();
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line = buffer.split_to(pos + 1);
+                match sanitize_sse_line(&line, &sanitizer) {
+                    Some(clean) => out.extend_from_slice(&clean),
+                    None => out.extend_from_slice(&line),
+                }
+            }
+            if !out.is_empty() {
+                yield Ok(out.freeze());
+            }
+        }
+        if !buffer.is_empty() {
+            let rest = buffer.split();
+            yield Ok(sanitize_sse_line(&rest, &sanitizer).unwrap_or_else(|| rest.freeze()));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sanitize_text<'a>(text: &'a str, config: &TextSanitizeConfig) -> Cow<'a, str> {
+        TextSanitizer::new(config).sanitize(text)
+    }
+
+    fn all_rules() -> TextSanitizeConfig {
+        TextSanitizeConfig { enabled: true, smart_quotes: true, zero_width: true, undefined_markers: true, post_processors: Vec::new() }
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        let config = all_rules();
+        assert_eq!(sanitize_text("“Hi” it‘s\u{200B} [undefined]ok\u{FEFF}", &config), "\"Hi\" it's ok");
+        // ZWJ emoji 序列保持不变，且无需改动时不分配
+        assert!(matches!(sanitize_text("👨‍👩‍👧 fine", &config), Cow::Borrowed(_)));
+
+        let quotes_only = TextSanitizeConfig { smart_quotes: true, zero_width: false, undefined_markers: false, ..config.clone() };
+        assert_eq!(sanitize_text("“a”\u{200B}", &quotes_only), "\"a\"\u{200B}");
+        let disabled = TextSanitizeConfig { enabled: false, ..config };
+        assert_eq!(sanitize_text("“a”", &disabled), "“a”");
+    }
+
+    #[test]
+    fn test_sanitize_gemini_response() {
+        let config = all_rules();
+        let mut value = json!({
+            "response": {
+                "candidates": [{ "content": { "parts": [
+                    { "text": "“quoted”", "thought": true },
+                    { "functionCall": { "name": "f", "args": { "q": "“kept”" } } },
+                    { "text": "plain" }
+                ] } }]
+            }
+        });
+        assert!(sanitize_gemini_response(&mut value, &config));
+        let parts = &value["response"]["candidates"][0]["content"]["parts"];
+        assert_eq!(parts[0]["text"], "\"quoted\"");
+        assert_eq!(parts[1]["functionCall"]["args"]["q"], "“kept”");
+        assert!(!sanitize_gemini_response(&mut value, &config));
+    }
+
+    fn rule(action: PostProcessAction, pattern: &str, replacement: &str) -> crate::proxy::config::PostProcessRule {
+        crate::proxy::config::PostProcessRule {
+            name: format!("{:?}", action),
+            action,
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            models: Vec::new(),
+            clients: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_post_processors() {
+        let config = TextSanitizeConfig {
+            post_processors: vec![
+                rule(PostProcessAction::RegexReplace, r"(?i)\bfoo(\d)", "bar$1"),
+                rule(PostProcessAction::StripAiBoilerplate, "", ""),
+                rule(PostProcessAction::StripWatermarks, "", ""),
+                rule(PostProcessAction::RegexReplace, "(", "invalid"),
+            ],
+            ..TextSanitizeConfig::default()
+        };
+        let sanitizer = TextSanitizer::new(&config);
+        assert_eq!(sanitizer.sanitize("Foo1 and foo2"), "bar1 and bar2");
+        assert_eq!(
+            sanitizer.sanitize("As an AI language model, i can't browse. Ok."),
+            "I can't browse. Ok."
+        );
+        assert_eq!(sanitizer.sanitize("作为一个AI语言模型，我无法联网。"), "我无法联网。");
+        assert_eq!(sanitizer.sanitize("wa\u{200D}ter\u{202E}mark\u{E0041}ed"), "watermarked");
+        // emoji 中的 ZWJ 保留，无需改动时不分配
+        assert!(matches!(sanitizer.sanitize("👨‍👩‍👧 fine"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_post_processors_scoped_by_model_and_client() {
+        let mut scoped = rule(PostProcessAction::StripWatermarks, "", "");
+        scoped.models = vec!["gemini-3-*".to_string()];
+        scoped.clients = vec!["cline".to_string()];
+        let config = TextSanitizeConfig { post_processors: vec![scoped], ..TextSanitizeConfig::default() };
+
+        assert_eq!(config.for_request(&["claude-sonnet-4-5", "gemini-3-pro-high"], "cline").post_processors.len(), 1);
+        assert!(config.for_request(&["gemini-3-pro-high"], "claude_code").post_processors.is_empty());
+        assert!(config.for_request(&["gemini-2.5-flash"], "cline").post_processors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sanitize_stream_across_chunk_boundaries() {
+        let event = json!({ "candidates": [{ "content": { "parts": [{ "text": "say “hi”\u{200B}" }] } }] }).to_string();
+        let raw = format!("data: {}\n\n: keep-alive\ndata: [DONE]\n\n", event);
+        let (head, tail) = raw.as_bytes().split_at(17);
+        let upstream = futures::stream::iter(vec![
+            Ok::<Bytes, std::io::Error>(Bytes::copy_from_slice(head)),
+            Ok(Bytes::copy_from_slice(tail)),
+        ]);
+        let output: Vec<u8> = sanitize_gemini_stream(upstream, all_rules())
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await;
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(r#""text":"say \"hi\"""#), "{}", output);
+        assert!(output.ends_with("\n\n: keep-alive\ndata: [DONE]\n\n"));
+    }
+}
@@ -0,0 +1,155 @@
+// 本地时间上下文注入 (Time Context)
+// Gemini 模型在 Agent 场景下常以 UTC 或训练数据中的过时时间回答 "现在几点"。
+// 开启后按模板把用户当前本地时间、时区与区域设置追加到 systemInstruction 末尾。
+// 时间精确到分钟；放在系统提示词最后一段，尽量减少对前缀缓存的影响。
+
+use crate::proxy::config::TimeContextConfig;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde_json::{json, Value};
+
+/// 模板中可用的占位符
+const PLACEHOLDERS: [&str; 7] = ["datetime", "date", "time", "weekday", "timezone", "utc_offset", "locale"];
+
+/// 解析 "+08:00" / "-05:30" / "+8" 形式的 UTC 偏移
+fn parse_utc_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let (sign, rest) = match value.chars().next()? {
+        '+' => (1, &value[1..]),
+        '-' => (-1, &value[1..]),
+        _ => (1, value),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(sign * seconds)
+}
+
+/// 系统区域设置 (LC_ALL / LC_MESSAGES / LANG，例如 zh_CN.UTF-8 -> zh-CN)
+fn system_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .map(|v| v.split(['.', '@']).next().unwrap_or("").replace('_', "-"))
+        .find(|v| !v.is_empty() && v != "C" && v != "POSIX")
+        .unwrap_or_else(|| "en-US".to_string())
+}
+
+fn format_offset(offset: &FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    format!("{}{:02}:{:02}", sign, seconds / 3600, (seconds % 3600) / 60)
+}
+
+/// 按配置渲染模板
+pub fn render(config: &TimeContextConfig, now: DateTime<Utc>) -> String {
+    let offset = parse_utc_offset(&config.utc_offset).unwrap_or_else(|| *chrono::Local::now().offset());
+    let local = now.with_timezone(&offset);
+    let utc_offset = format_offset(&offset);
+    let timezone = if !config.timezone.trim().is_empty() {
+        config.timezone.trim().to_string()
+    } else if config.utc_offset.trim().is_empty() {
+        iana_time_zone::get_timezone().unwrap_or_else(|_| format!("UTC{}", utc_offset))
+    } else {
+        format!("UTC{}", utc_offset)
+    };
+    let locale = if config.locale.trim().is_empty() { system_locale() } else { config.locale.trim().to_string() };
+
+    let values = [
+        local.format("%Y-%m-%d %H:%M").to_string(),
+        local.format("%Y-%m-%d").to_string(),
+        local.format("%H:%M").to_string(),
+        local.format("%A").to_string(),
+        timezone,
+        utc_offset,
+        locale,
+    ];
+    PLACEHOLDERS
+        .iter()
+        .zip(values)
+        .fold(config.template.clone(), |text, (name, value)| text.replace(&format!("{{{}}}", name), &value))
+}
+
+/// 向已包装的 v1internal 请求注入时间上下文 (图片生成请求不支持 systemInstruction，跳过)
+pub fn apply_time_context(body: &mut Value, config: &TimeContextConfig) {
+    if !config.enabled || body.get("requestType").and_then(|v| v.as_str()) == Some("image_gen") {
+        return;
+    }
+    let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let Some(request) = body.get_mut("request").filter(|r| r.is_object()) else {
+        return;
+    };
+    if request.pointer("/generationConfig/imageConfig").is_some() {
+        return;
+    }
+
+    let text = render(config, Utc::now());
+    let created = request.get("systemInstruction").is_none();
+    let system = request
+        .as_object_mut()
+        .map(|obj| obj.entry("systemInstruction").or_insert_with(|| json!({ "parts": [] })));
+    if let Some(parts) = system
+        .and_then(|s| s.as_object_mut())
+        .map(|s| s.entry("parts").or_insert_with(|| json!([])))
+        .and_then(|p| p.as_array_mut())
+    {
+        parts.push(json!({ "text": text }));
+    }
+    if created {
+        crate::proxy::mappers::common_utils::apply_system_instruction_role(request, &model);
+    }
+    tracing::debug!("[Time-Context] Injected local time into systemInstruction");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(template: &str) -> TimeContextConfig {
+        TimeContextConfig {
+            enabled: true,
+            timezone: "Asia/Shanghai".to_string(),
+            utc_offset: "+08:00".to_string(),
+            locale: "zh-CN".to_string(),
+            template: template.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_template_in_configured_timezone() {
+        let now = DateTime::parse_from_rfc3339("2025-01-31T20:30:45Z").unwrap().with_timezone(&Utc);
+        let text = render(&config("{weekday} {datetime} | {date} {time} | {timezone} UTC{utc_offset} | {locale}"), now);
+        assert_eq!(text, "Saturday 2025-02-01 04:30 | 2025-02-01 04:30 | Asia/Shanghai UTC+08:00 | zh-CN");
+
+        let mut negative = config("{utc_offset} {timezone}");
+        negative.utc_offset = "-5:30".to_string();
+        negative.timezone.clear();
+        assert_eq!(render(&negative, now), "-05:30 UTC-05:30");
+    }
+
+    #[test]
+    fn test_appends_to_system_instruction_and_skips_image_requests() {
+        let cfg = config("now: {date}");
+        let mut body = json!({
+            "model": "gemini-2.5-flash",
+            "request": { "systemInstruction": { "role": "user", "parts": [{ "text": "sys" }] }, "contents": [] }
+        });
+        apply_time_context(&mut body, &cfg);
+        let parts = body["request"]["systemInstruction"]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert!(parts[1]["text"].as_str().unwrap().starts_with("now: "));
+
+        // 没有 systemInstruction 时按模型创建
+        let mut body = json!({ "model": "gemini-3-flash", "request": { "contents": [] } });
+        apply_time_context(&mut body, &cfg);
+        assert!(body["request"]["systemInstruction"].get("role").is_none());
+        assert_eq!(body["request"]["systemInstruction"]["parts"].as_array().unwrap().len(), 1);
+
+        let mut image = json!({ "model": "gemini-3-pro-image", "requestType": "image_gen", "request": { "contents": [] } });
+        apply_time_context(&mut image, &cfg);
+        assert!(image["request"].get("systemInstruction").is_none());
+
+        let mut disabled = json!({ "model": "gemini-2.5-flash", "request": { "contents": [] } });
+        apply_time_context(&mut disabled, &TimeContextConfig::default());
+        assert!(disabled["request"].get("systemInstruction").is_none());
+    }
+}
@@ -0,0 +1,104 @@
+// 请求耗时分解 (性能排查)
+// 转换阶段 (cache_control 清理 / contents / tools / schema 清洗 / SSE 映射) 包裹在 debug 级别的 span 中，
+// 记录输入规模与耗时；每个请求的汇总 (转换 / 上游首字节 / 流式传输) 通过响应头交给监控中间件写入请求日志。
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Instant;
+
+pub const HEADER_TRANSFORM_MS: &str = "X-Transform-Ms";
+pub const HEADER_UPSTREAM_TTFB_MS: &str = "X-Upstream-Ttfb-Ms";
+
+/// 单个请求的耗时汇总 (毫秒)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestTiming {
+    /// 请求协议转换耗时 (最后一次尝试)
+    #[serde(default)]
+    pub transform_ms: Option<u64>,
+    /// 发出上游请求到收到首个数据块的耗时
+    #[serde(default)]
+    pub upstream_ttfb_ms: Option<u64>,
+    /// 流式响应从开始下发到结束的耗时
+    #[serde(default)]
+    pub stream_ms: Option<u64>,
+}
+
+impl RequestTiming {
+    pub fn is_empty(&self) -> bool {
+        self.transform_ms.is_none() && self.upstream_ttfb_ms.is_none() && self.stream_ms.is_none()
+    }
+
+    /// 从 handler 写入的响应头读取
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let read = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        Self {
+            transform_ms: read(HEADER_TRANSFORM_MS),
+            upstream_ttfb_ms: read(HEADER_UPSTREAM_TTFB_MS),
+            stream_ms: None,
+        }
+    }
+}
+
+/// 移除耗时头 (仅供监控中间件读取，不下发给客户端)
+pub fn strip_headers(headers: &mut HeaderMap) {
+    headers.remove(HEADER_TRANSFORM_MS);
+    headers.remove(HEADER_UPSTREAM_TTFB_MS);
+}
+
+/// 在带耗时记录的 span 中执行一个转换阶段，`size` 为该阶段的输入规模 (条数或字节数)
+pub fn timed<T>(stage: &'static str, size: usize, f: impl FnOnce() -> T) -> T {
+    let span = tracing::debug_span!("transform_stage", stage, size, elapsed_us = tracing::field::Empty);
+    let _enter = span.enter();
+    let start = Instant::now();
+    let result = f();
+    let elapsed_us = start.elapsed().as_micros() as u64;
+    span.record("elapsed_us", elapsed_us);
+    tracing::trace!(stage, size, elapsed_us, "transform stage finished");
+    result
+}
+
+/// JSON 值序列化后的字节数，仅在开启 debug 日志时计算，避免常规路径的额外序列化
+pub fn json_size(value: &Value) -> usize {
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        value.to_string().len()
+    } else {
+        0
+    }
+}
+
+/// 耗时毫秒数 (自 `start` 起)
+pub fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(RequestTiming::from_headers(&headers).is_empty());
+
+        headers.insert(HEADER_TRANSFORM_MS, "3".parse().unwrap());
+        headers.insert(HEADER_UPSTREAM_TTFB_MS, "not-a-number".parse().unwrap());
+        let timing = RequestTiming::from_headers(&headers);
+        assert_eq!(timing.transform_ms, Some(3));
+        assert_eq!(timing.upstream_ttfb_ms, None);
+        assert!(!timing.is_empty());
+
+        strip_headers(&mut headers);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_timed_returns_stage_result() {
+        assert_eq!(timed("build_tools", 2, || 40 + 2), 42);
+    }
+}
@@ -0,0 +1,219 @@
+// 本地 token 预估 (Pre-flight Budget)
+// 在发送前对转换后的 Gemini 请求体估算输入 token 数，超出目标模型上下文窗口时记录警告或直接返回客户端错误，
+// 避免等待上游慢速返回 400。估算为启发式：ASCII 约 4 字符 / token，CJK 等非 ASCII 字符按 1 token 计，
+// 图片按 Gemini 的瓦片规则计，文本类附件按解码后的字节数计。PDF / 音视频的 token 数取决于页数与时长，
+// 无法从字节数推断，不计入估算。
+// /v1/messages/count_tokens 也使用同一套估算 (作用于转换后的请求体，含工具声明与注入的系统提示词)。
+
+use base64::Engine;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 每个图片瓦片的 token 开销 (Gemini 对两边均 ≤384px 的图片固定计 258 tokens)
+const IMAGE_TOKENS: u64 = 258;
+/// 不按瓦片切分的图片边长上限
+const SMALL_IMAGE_MAX_SIDE: u32 = 384;
+/// 每个 part 的结构开销
+const PART_OVERHEAD: u64 = 3;
+/// 读取图片尺寸时只解码 base64 的前这么多字符 (约 64 KiB，4 的倍数)
+const IMAGE_HEADER_BASE64_LEN: usize = 64 * 1024 / 3 * 4;
+
+/// 内置的上下文窗口 (按模型名前缀匹配)
+const BUILTIN_CONTEXT_WINDOWS: &[(&str, u64)] = &[
+    ("gemini-", 1_048_576),
+    ("claude-", 200_000),
+    ("gpt-oss", 131_072),
+];
+
+/// 估算一段文本的 token 数
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let (ascii, other) = text
+        .chars()
+        .fold((0u64, 0u64), |(a, o), c| if c.is_ascii() { (a + 1, o) } else { (a, o + 1) });
+    ascii.div_ceil(4) + other
+}
+
+/// 按 Gemini 的规则计算图片 token：两边均 ≤384px 计 258；更大的图片按
+/// floor(短边 / 1.5) 为边长切分瓦片，每个瓦片计 258
+pub fn image_tokens(width: u32, height: u32) -> u64 {
+    if width <= SMALL_IMAGE_MAX_SIDE && height <= SMALL_IMAGE_MAX_SIDE {
+        return IMAGE_TOKENS;
+    }
+    let crop_unit = ((width.min(height) as f64 / 1.5).floor() as u64).max(1);
+    let tiles = (width as u64).div_ceil(crop_unit) * (height as u64).div_ceil(crop_unit);
+    tiles * IMAGE_TOKENS
+}
+
+/// 内联图片的 token 数
+/// 尺寸位于图片头部，只解码 base64 开头的一段即可读取，不解码整张图片；
+/// 图片头超出该范围 (如带大块 EXIF 的 JPEG) 或无法识别时按单个瓦片计
+fn inline_image_tokens(data: &str) -> u64 {
+    let header = &data.as_bytes()[..data.len().min(IMAGE_HEADER_BASE64_LEN)];
+    let dimensions = base64::engine::general_purpose::STANDARD
+        .decode(header)
+        .ok()
+        .and_then(|bytes| {
+            image::ImageReader::new(std::io::Cursor::new(bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()
+        });
+    match dimensions {
+        Some((width, height)) => image_tokens(width, height),
+        None => IMAGE_TOKENS,
+    }
+}
+
+fn estimate_part(part: &Value) -> u64 {
+    let mut tokens = PART_OVERHEAD;
+    if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+        tokens += estimate_text_tokens(text);
+    }
+    if let Some(inline) = part.get("inlineData") {
+        let mime = inline.get("mimeType").and_then(|m| m.as_str()).unwrap_or("");
+        if mime.starts_with("image/") {
+            tokens += inline_image_tokens(inline.get("data").and_then(|d| d.as_str()).unwrap_or(""));
+        } else if super::inline_offload::is_text_like(mime) {
+            // 文本类附件：按解码后的字节数粗略估算
+            let data = inline.get("data").and_then(|d| d.as_str()).unwrap_or("");
+            tokens += (data.len() as u64 * 3 / 4) / 4;
+        }
+        // PDF (按页计) / 音视频 (按秒计) 无法从字节数推断，不计入
+    }
+    // 远程图片无法获知尺寸，按单个瓦片计
+    if part
+        .pointer("/fileData/mimeType")
+        .and_then(|m| m.as_str())
+        .is_some_and(|m| m.starts_with("image/"))
+    {
+        tokens += IMAGE_TOKENS;
+    }
+    for key in ["functionCall", "functionResponse", "executableCode", "codeExecutionResult"] {
+        if let Some(value) = part.get(key) {
+            tokens += estimate_text_tokens(&value.to_string());
+        }
+    }
+    tokens
+}
+
+fn estimate_parts(container: Option<&Value>) -> u64 {
+    container
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.as_array())
+        .map(|parts| parts.iter().map(estimate_part).sum())
+        .unwrap_or(0)
+}
+
+/// 估算 v1internal 请求体 ({"request": {...}}) 或裸 Gemini 请求的输入 token 数
+pub fn estimate_request_tokens(body: &Value) -> u64 {
+    let request = body.get("request").unwrap_or(body);
+    let contents: u64 = request
+        .get("contents")
+        .and_then(|c| c.as_array())
+        .map(|contents| contents.iter().map(|c| estimate_parts(Some(c))).sum())
+        .unwrap_or(0);
+    let system = estimate_parts(request.get("systemInstruction"));
+    let tools = request
+        .get("tools")
+        .map(|t| estimate_text_tokens(&t.to_string()))
+        .unwrap_or(0);
+    contents + system + tools
+}
+
+/// 目标模型的上下文窗口 (用户覆盖优先，支持 * 通配符；未知模型返回 None，不做检查)
+pub fn context_window(model: &str, overrides: &HashMap<String, u64>) -> Option<u64> {
+    if let Some(window) = overrides.get(model) {
+        return Some(*window);
+    }
+    if let Some((_, window)) = overrides
+        .iter()
+        .find(|(pattern, _)| pattern.contains('*') && super::model_mapping::wildcard_match(pattern, model))
+    {
+        return Some(*window);
+    }
+    BUILTIN_CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+/// 超出上下文窗口时返回 (估算值, 窗口)
+pub fn check_budget(body: &Value, model: &str, overrides: &HashMap<String, u64>) -> Option<(u64, u64)> {
+    let window = context_window(model, overrides)?;
+    let estimate = estimate_request_tokens(body);
+    (estimate > window).then_some((estimate, window))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimates_text_images_and_tools() {
+        assert_eq!(estimate_text_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+
+        let body = json!({
+            "project": "p",
+            "request": {
+                "systemInstruction": { "parts": [{ "text": "a".repeat(400) }] },
+                "contents": [
+                    { "role": "user", "parts": [
+                        { "text": "b".repeat(4000) },
+                        { "inlineData": { "mimeType": "image/png", "data": "AAAA" } }
+                    ]}
+                ]
+            }
+        });
+        // 100 + 1000 + 258 + 3 * 3 parts
+        assert_eq!(estimate_request_tokens(&body), 1367);
+    }
+
+    #[test]
+    fn test_image_tokens_follow_gemini_tiling() {
+        assert_eq!(image_tokens(384, 200), 258);
+        // 1024x1024: 瓦片边长 682，2x2 瓦片
+        assert_eq!(image_tokens(1024, 1024), 4 * 258);
+        // 1920x1080: 瓦片边长 720，3x2 瓦片
+        assert_eq!(image_tokens(1920, 1080), 6 * 258);
+
+        // 内联图片按实际尺寸计算
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(800, 800)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let data = base64::engine::general_purpose::STANDARD.encode(&png);
+        let part = serde_json::json!({ "inlineData": { "mimeType": "image/png", "data": data } });
+        assert_eq!(estimate_part(&part), PART_OVERHEAD + image_tokens(800, 800));
+
+        // 只解码图片头：头部之后的内容不参与解码
+        let truncated = format!("{}{}{}", &data[..64], "A".repeat(IMAGE_HEADER_BASE64_LEN - 64), "!".repeat(64));
+        assert_eq!(inline_image_tokens(&truncated), image_tokens(800, 800));
+    }
+
+    #[test]
+    fn test_media_attachments_are_not_counted_by_size() {
+        let data = "A".repeat(1_400_000);
+        let pdf = serde_json::json!({ "inlineData": { "mimeType": "application/pdf", "data": data } });
+        assert_eq!(estimate_part(&pdf), PART_OVERHEAD);
+        let text = serde_json::json!({ "inlineData": { "mimeType": "text/plain", "data": "A".repeat(4000) } });
+        assert_eq!(estimate_part(&text), PART_OVERHEAD + 750);
+    }
+
+    #[test]
+    fn test_context_window_overrides_and_budget_check() {
+        let mut overrides = HashMap::new();
+        assert_eq!(context_window("gemini-2.5-flash", &overrides), Some(1_048_576));
+        assert_eq!(context_window("unknown-model", &overrides), None);
+
+        overrides.insert("gemini-*-lite".to_string(), 1_000);
+        assert_eq!(context_window("gemini-2.5-flash-lite", &overrides), Some(1_000));
+
+        let body = json!({ "contents": [{ "role": "user", "parts": [{ "text": "x".repeat(8000) }] }] });
+        assert_eq!(check_budget(&body, "gemini-2.5-flash-lite", &overrides), Some((2003, 1_000)));
+        assert_eq!(check_budget(&body, "gemini-2.5-flash", &overrides), None);
+        assert_eq!(check_budget(&body, "unknown-model", &overrides), None);
+    }
+}
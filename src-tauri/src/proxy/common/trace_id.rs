@@ -0,0 +1,76 @@
+// 请求追踪 ID
+// 客户端可通过 X-Request-Id / X-Trace-Id 传入自己的追踪 ID，代理在日志、响应头与上游 requestId 中沿用，
+// 便于把代理日志拼接进客户端自身的链路追踪。未提供时生成随机的 6 位 ID。
+
+use axum::http::HeaderMap;
+
+pub const HEADER_REQUEST_ID: &str = "x-request-id";
+pub const HEADER_TRACE_ID: &str = "x-trace-id";
+
+/// 客户端追踪 ID 的最大长度
+const MAX_TRACE_ID_LEN: usize = 128;
+
+/// 校验客户端提供的追踪 ID (仅接受可安全写入日志与请求头的字符)
+fn sanitize(value: &str) -> Option<String> {
+    let value = value.trim();
+    let valid = !value.is_empty()
+        && value.len() <= MAX_TRACE_ID_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    valid.then(|| value.to_string())
+}
+
+/// 读取客户端提供的追踪 ID (X-Request-Id 优先)
+pub fn from_headers(headers: &HeaderMap) -> Option<String> {
+    [HEADER_REQUEST_ID, HEADER_TRACE_ID]
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .find_map(sanitize)
+}
+
+/// 生成随机追踪 ID
+pub fn generate() -> String {
+    rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
+        .take(6)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// 客户端追踪 ID，缺省时生成
+pub fn resolve(headers: &HeaderMap) -> String {
+    from_headers(headers).unwrap_or_else(generate)
+}
+
+/// 上游 requestId：包含追踪 ID 以便关联，附加随机后缀保证唯一 (同一 trace 下可能有多个请求)
+pub fn upstream_request_id(prefix: &str, trace_id: &str) -> String {
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}-{}-{}", prefix, trace_id, &suffix[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_resolve_prefers_client_ids_and_rejects_unsafe_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert(HEADER_TRACE_ID, HeaderValue::from_static("trace-abc"));
+        assert_eq!(from_headers(&headers).as_deref(), Some("trace-abc"));
+
+        headers.insert(HEADER_REQUEST_ID, HeaderValue::from_static("req_42"));
+        assert_eq!(resolve(&headers), "req_42");
+
+        headers.insert(HEADER_REQUEST_ID, HeaderValue::from_static("bad id with spaces"));
+        assert_eq!(resolve(&headers), "trace-abc");
+
+        let generated = resolve(&HeaderMap::new());
+        assert_eq!(generated.len(), 6);
+
+        let upstream = upstream_request_id("agent", "req_42");
+        assert!(upstream.starts_with("agent-req_42-"));
+        assert_ne!(upstream, upstream_request_id("agent", "req_42"));
+    }
+}
@@ -0,0 +1,147 @@
+// 流式用量心跳 (Usage Heartbeat)
+// 长时间生成时按配置的间隔下发阶段性用量 (已生成 token 数)，客户端与桌面端实时监控可据此展示进度。
+// 下发的事件均为协议合法的元数据：
+// - Claude：stop_reason 为 null 的 message_delta (官方 SDK 只会累加 usage)
+// - OpenAI：choices 为空、携带 usage 的 chunk (仅在客户端声明 stream_options.include_usage 时下发)
+// 上游未携带 usageMetadata 时按已输出文本估算。
+
+use super::token_estimate::estimate_text_tokens;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// 某一时刻的生成进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageProgress {
+    pub prompt_tokens: u32,
+    pub output_tokens: u32,
+    pub elapsed_ms: u64,
+}
+
+/// 单条流的用量心跳状态
+#[derive(Debug, Clone)]
+pub struct UsageHeartbeat {
+    interval: Duration,
+    started: Instant,
+    last_emit: Instant,
+    prompt_tokens: u32,
+    /// 上游 usageMetadata 报告的输出 token (含思考)
+    reported_output: Option<u32>,
+    /// 按已输出文本估算的 token
+    estimated_output: u64,
+}
+
+impl UsageHeartbeat {
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            started: now,
+            last_emit: now,
+            prompt_tokens: 0,
+            reported_output: None,
+            estimated_output: 0,
+        }
+    }
+
+    /// 由配置创建 (未启用时返回 None)
+    pub fn from_config(config: &crate::proxy::config::UsageHeartbeatConfig) -> Option<Self> {
+        (config.enabled && config.interval_secs > 0).then(|| Self::new(Duration::from_secs(config.interval_secs)))
+    }
+
+    /// 记录一个 Gemini 响应 chunk
+    pub fn observe(&mut self, chunk: &Value) {
+        if let Some(usage) = chunk.get("usageMetadata") {
+            let count = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+            if let Some(prompt) = count("promptTokenCount") {
+                self.prompt_tokens = prompt;
+            }
+            if let Some(output) = count("candidatesTokenCount") {
+                self.reported_output = Some(output + count("thoughtsTokenCount").unwrap_or(0));
+            }
+        }
+        let parts = chunk
+            .pointer("/candidates/0/content/parts")
+            .and_then(|p| p.as_array())
+            .into_iter()
+            .flatten();
+        for part in parts {
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                self.estimated_output += estimate_text_tokens(text);
+            }
+        }
+    }
+
+    pub fn progress(&self) -> UsageProgress {
+        UsageProgress {
+            prompt_tokens: self.prompt_tokens,
+            output_tokens: self
+                .reported_output
+                .unwrap_or(self.estimated_output.min(u32::MAX as u64) as u32),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// 距上次下发已超过间隔时返回当前进度
+    pub fn poll(&mut self) -> Option<UsageProgress> {
+        if self.last_emit.elapsed() < self.interval {
+            return None;
+        }
+        self.last_emit = Instant::now();
+        Some(self.progress())
+    }
+}
+
+/// 从下发给客户端的 SSE 行中识别心跳事件，返回已生成 token 数 (供桌面端实时监控)
+pub fn parse_heartbeat_line(line: &str) -> Option<u32> {
+    let json: Value = serde_json::from_str(line.strip_prefix("data: ")?.trim()).ok()?;
+    let is_claude = json.get("type").and_then(|t| t.as_str()) == Some("message_delta")
+        && json.pointer("/delta/stop_reason").is_some_and(|r| r.is_null());
+    let is_openai = json.get("object").and_then(|t| t.as_str()) == Some("chat.completion.chunk")
+        && json.get("choices").and_then(|c| c.as_array()).is_some_and(|c| c.is_empty());
+    if !is_claude && !is_openai {
+        return None;
+    }
+    json.pointer("/usage/output_tokens")
+        .or(json.pointer("/usage/completion_tokens"))
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reported_usage_takes_precedence_over_estimate() {
+        let mut heartbeat = UsageHeartbeat::new(Duration::ZERO);
+        heartbeat.observe(&json!({"candidates": [{"content": {"parts": [{"text": "hello world, this is a test"}]}}]}));
+        assert!(heartbeat.progress().output_tokens > 0);
+
+        heartbeat.observe(&json!({
+            "candidates": [{"content": {"parts": [{"text": "!"}]}}],
+            "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 40, "thoughtsTokenCount": 2}
+        }));
+        let progress = heartbeat.poll().unwrap();
+        assert_eq!(progress.prompt_tokens, 12);
+        assert_eq!(progress.output_tokens, 42);
+    }
+
+    #[test]
+    fn test_poll_respects_interval() {
+        let mut heartbeat = UsageHeartbeat::new(Duration::from_secs(3600));
+        assert!(heartbeat.poll().is_none());
+    }
+
+    #[test]
+    fn test_parse_heartbeat_line() {
+        let claude = r#"data: {"type":"message_delta","delta":{"stop_reason":null,"stop_sequence":null},"usage":{"output_tokens":17}}"#;
+        assert_eq!(parse_heartbeat_line(claude), Some(17));
+        let openai = r#"data: {"object":"chat.completion.chunk","choices":[],"usage":{"prompt_tokens":3,"completion_tokens":9,"total_tokens":12}}"#;
+        assert_eq!(parse_heartbeat_line(openai), Some(9));
+
+        // 最终的 message_delta 不是心跳
+        let final_delta = r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":20}}"#;
+        assert_eq!(parse_heartbeat_line(final_delta), None);
+    }
+}
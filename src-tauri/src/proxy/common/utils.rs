@@ -18,3 +18,29 @@ pub fn _deprecated_infer_quota_group(model: &str) -> String {
         "gemini".to_string()
     }
 }
+
+/// 将稳定的 requestId 写入已转换的 v1internal 请求体
+///
+/// 同一客户端请求的所有重试共享同一个 requestId，便于上游去重，避免网络层重试被重复计费/计配额
+pub fn apply_request_id(body: &mut serde_json::Value, request_id: &str) {
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert(
+            "requestId".to_string(),
+            serde_json::Value::String(request_id.to_string()),
+        );
+    }
+}
+
+/// 打印转换后的上游请求体 (默认 debug 级别，开启 log_transformed_requests 开关时提升为 info)
+pub fn log_transformed_body(label: &str, body: &serde_json::Value, experimental: &crate::proxy::config::ExperimentalConfig) {
+    let info = crate::proxy::feature_flags::enabled(experimental, "log_transformed_requests");
+    if !info && !tracing::enabled!(tracing::Level::DEBUG) {
+        return;
+    }
+    let text = serde_json::to_string_pretty(body).unwrap_or_default();
+    if info {
+        tracing::info!("{} Transformed Gemini Body:\n{}", label, text);
+    } else {
+        tracing::debug!("{} Transformed Gemini Body:\n{}", label, text);
+    }
+}
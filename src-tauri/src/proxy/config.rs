@@ -135,21 +135,1379 @@ pub struct ExperimentalConfig {
     /// 用于解决客户端因 Gemini 上下文过大而错误触发压缩的问题
     #[serde(default = "default_true")]
     pub enable_usage_scaling: bool,
+
+    /// 后台小请求合并 (Background Micro-Batching)
+    #[serde(default)]
+    pub background_batching: BackgroundBatchConfig,
+
+    /// 图片提示词安全预检 (Image Prompt Safety Pre-check)
+    #[serde(default)]
+    pub image_safety_precheck: ImageSafetyPrecheckConfig,
+
+    /// 图片生成结果缓存：相同请求直接返回最近的生成结果 (磁盘 LRU)
+    #[serde(default)]
+    pub image_cache: ImageCacheConfig,
+
+    /// Gemini 原生请求宽松校验模式
+    /// 开启后结构错误只记录警告并继续透传给上游，而不是直接返回 400
+    #[serde(default)]
+    pub gemini_permissive_validation: bool,
+
+    /// 超大 inlineData 附件处理 (Inline Data Offloading)
+    #[serde(default)]
+    pub inline_offload: InlineOffloadConfig,
+
+    /// 不支持流式输出的上游模型 (支持 * 通配符)
+    /// 对这些模型改用 generateContent，流式客户端收到由完整响应合成的 SSE
+    #[serde(default)]
+    pub non_streaming_models: Vec<String>,
+
+    /// Claude 工具声明护栏 (描述长度 / 数量上限)
+    #[serde(default)]
+    pub tool_guardrails: ToolGuardrailConfig,
+
+    /// 发送前的本地 token 预估 (超出上下文窗口时快速失败)
+    #[serde(default)]
+    pub preflight_budget: PreflightBudgetConfig,
+
+    /// OpenAI JSON 模式输出校验 (response_format = json_object / json_schema)
+    #[serde(default)]
+    pub json_mode: JsonModeConfig,
+
+    /// 超大 SSE 增量事件拆分 (按协议配置上限)
+    #[serde(default)]
+    pub sse_chunking: SseChunkingConfig,
+
+    /// 向系统提示词注入本地时间 / 时区 / 区域设置
+    #[serde(default)]
+    pub time_context: TimeContextConfig,
+
+    /// 影子流量：按比例将请求异步镜像到另一个模型，用于切换映射前的对比评估
+    #[serde(default)]
+    pub shadow_traffic: ShadowTrafficConfig,
+
+    /// A/B 路由实验：按会话把一部分流量路由到替代模型，并在请求日志中打标签
+    #[serde(default)]
+    pub experiments: Vec<RoutingExperiment>,
+
+    /// Claude Code 子代理路由：按系统提示词识别子代理类别，独立于主对话映射路由到指定模型
+    #[serde(default)]
+    pub subagent_routes: Vec<SubagentRoute>,
+
+    /// Codex 流保活：上游长时间无输出时发送空事件，并延长流式请求超时
+    #[serde(default)]
+    pub codex_keep_alive: CodexKeepAliveConfig,
+
+    /// 响应压缩：客户端声明 Accept-Encoding 时压缩 JSON / SSE 响应 (远程部署时节省带宽)
+    #[serde(default)]
+    pub response_compression: ResponseCompressionConfig,
+
+    /// 空闲预热：长时间空闲或系统休眠唤醒后提前刷新 token 并重建上游连接
+    #[serde(default)]
+    pub idle_warmup: IdleWarmupConfig,
+
+    /// 在响应中附带基于账号池状态的 anthropic-ratelimit-* / x-ratelimit-* 头
+    #[serde(default = "default_true")]
+    pub rate_limit_headers: bool,
+
+    /// 流中途故障转移：上游流在生成中途断开时换账号续写，客户端看到的仍是同一条流
+    #[serde(default)]
+    pub mid_stream_failover: MidStreamFailoverConfig,
+
+    /// 准入控制：限制并发转发数，超出的请求按 X-Priority 优先级排队
+    #[serde(default)]
+    pub admission: AdmissionConfig,
+
+    /// 输出文本清理 (弯引号 / 零宽字符 / [undefined] 标记)，作用于所有协议的响应
+    #[serde(default)]
+    pub text_sanitize: TextSanitizeConfig,
+
+    /// Agent 失控循环检测 (重复工具调用 / 重复提示 / 单会话请求频率)
+    #[serde(default)]
+    pub loop_detection: LoopDetectionConfig,
+
+    /// 流式用量心跳：长时间生成时定期下发已生成 token 数 (Claude message_delta / OpenAI usage chunk)
+    #[serde(default)]
+    pub usage_heartbeat: UsageHeartbeatConfig,
+
+    /// 允许注入占位思维块的目标模型 (支持 * 通配)
+    /// 开启 thinking 时历史 assistant 消息缺少思维块会触发 "must start with thinking block" 400；
+    /// 上游接受无签名思维块的模型可在此列出，由代理补一个占位思维块。默认为空 (Vertex AI 会拒绝无签名思维块)
+    #[serde(default)]
+    pub dummy_thought_models: Vec<String>,
+
+    /// 离线检测：定期经由上游 HTTP 客户端探测 v1internal 连通性，离线时快速失败而不是等待超时
+    #[serde(default)]
+    pub offline_detection: OfflineDetectionConfig,
+
+    /// 监控界面事件推送：合并批量发送并限制频率，避免高频流式请求挤占 IPC
+    #[serde(default)]
+    pub ui_events: UiEventConfig,
+
+    /// 思考内容输出规则 (按模型 / 客户端覆盖 client_profiles 中的 thinking_output，第一条匹配的规则生效)
+    #[serde(default)]
+    pub thinking_output_rules: Vec<ThinkingOutputRule>,
+
+    /// Anthropic 组织 / 用量查询端点的兼容实现 (部分企业版 SDK 初始化时会探测)
+    #[serde(default)]
+    pub organization: OrganizationStubConfig,
+
+    /// 模型能力探测：未知目标模型首次使用时探测是否支持 thinking / 工具 / systemInstruction (按账号缓存)
+    #[serde(default)]
+    pub capability_probe: CapabilityProbeConfig,
+
+    /// finishReason = RECITATION (复述已有内容被截断) 时调整温度并携带提示重试一次
+    #[serde(default)]
+    pub recitation_retry: RecitationRetryConfig,
+
+    /// 没有对应上述字段的开关值 (key 见 feature_flags::STORED_FLAGS，未设置时取默认值)
+    #[serde(default)]
+    pub flags: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+impl ExperimentalConfig {
+    pub fn is_non_streaming_model(&self, model: &str) -> bool {
+        self.non_streaming_models
+            .iter()
+            .any(|p| p == model || (p.contains('*') && crate::proxy::common::model_mapping::wildcard_match(p, model)))
+    }
+}
+
+impl Default for ExperimentalConfig {
+    fn default() -> Self {
+        Self {
+            enable_signature_cache: true,
+            enable_tool_loop_recovery: true,
+            enable_cross_model_checks: true,
+            enable_usage_scaling: true,
+            background_batching: BackgroundBatchConfig::default(),
+            image_safety_precheck: ImageSafetyPrecheckConfig::default(),
+            image_cache: ImageCacheConfig::default(),
+            gemini_permissive_validation: false,
+            inline_offload: InlineOffloadConfig::default(),
+            non_streaming_models: Vec::new(),
+            tool_guardrails: ToolGuardrailConfig::default(),
+            preflight_budget: PreflightBudgetConfig::default(),
+            json_mode: JsonModeConfig::default(),
+            sse_chunking: SseChunkingConfig::default(),
+            time_context: TimeContextConfig::default(),
+            shadow_traffic: ShadowTrafficConfig::default(),
+            experiments: Vec::new(),
+            subagent_routes: Vec::new(),
+            codex_keep_alive: CodexKeepAliveConfig::default(),
+            response_compression: ResponseCompressionConfig::default(),
+            idle_warmup: IdleWarmupConfig::default(),
+            rate_limit_headers: true,
+            mid_stream_failover: MidStreamFailoverConfig::default(),
+            admission: AdmissionConfig::default(),
+            text_sanitize: TextSanitizeConfig::default(),
+            loop_detection: LoopDetectionConfig::default(),
+            usage_heartbeat: UsageHeartbeatConfig::default(),
+            dummy_thought_models: Vec::new(),
+            offline_detection: OfflineDetectionConfig::default(),
+            ui_events: UiEventConfig::default(),
+            thinking_output_rules: Vec::new(),
+            organization: OrganizationStubConfig::default(),
+            capability_probe: CapabilityProbeConfig::default(),
+            recitation_retry: RecitationRetryConfig::default(),
+            flags: std::collections::BTreeMap::new(),
+        }
+    }
+}
+
+/// 检测到失控循环时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopAction {
+    /// 仅记录警告日志
+    #[default]
+    Warn,
+    /// 延迟 throttle_delay_ms 后继续转发
+    Throttle,
+    /// 不再转发上游，返回要求 Agent 停止的合成消息
+    Break,
+}
+
+/// Agent 失控循环检测配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LoopDetectionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub action: LoopAction,
+
+    /// 历史末尾同一工具调用 (参数相同) 连续重复的次数上限 (0 表示不检测)
+    #[serde(default = "default_loop_max_repeated_tool_calls")]
+    pub max_repeated_tool_calls: usize,
+
+    /// 一分钟内完全相同的提示次数上限 (0 表示不检测)
+    #[serde(default = "default_loop_max_identical_prompts")]
+    pub max_identical_prompts: usize,
+
+    /// 单个会话每分钟请求数上限 (0 表示不检测)
+    #[serde(default = "default_loop_max_requests_per_minute")]
+    pub max_requests_per_minute: usize,
+
+    /// throttle 动作的延迟 (毫秒)
+    #[serde(default = "default_loop_throttle_delay_ms")]
+    pub throttle_delay_ms: u64,
+}
+
+impl Default for LoopDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: LoopAction::Warn,
+            max_repeated_tool_calls: default_loop_max_repeated_tool_calls(),
+            max_identical_prompts: default_loop_max_identical_prompts(),
+            max_requests_per_minute: default_loop_max_requests_per_minute(),
+            throttle_delay_ms: default_loop_throttle_delay_ms(),
+        }
+    }
+}
+
+fn default_loop_max_repeated_tool_calls() -> usize {
+    5
+}
+
+fn default_loop_max_identical_prompts() -> usize {
+    3
+}
+
+fn default_loop_max_requests_per_minute() -> usize {
+    60
+}
+
+fn default_loop_throttle_delay_ms() -> u64 {
+    3000
+}
+
+/// 流式用量心跳配置
+/// OpenAI 协议仅在客户端声明 stream_options.include_usage 时下发
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UsageHeartbeatConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 下发间隔 (秒)
+    #[serde(default = "default_usage_heartbeat_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for UsageHeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_usage_heartbeat_interval_secs(),
+        }
+    }
+}
+
+fn default_usage_heartbeat_interval_secs() -> u64 {
+    10
+}
+
+/// 输出文本清理配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TextSanitizeConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 弯引号替换为直引号 (会影响中文引号等正常文本，默认关闭；Codex 协议始终开启以保持 JSON 兼容)
+    #[serde(default)]
+    pub smart_quotes: bool,
+
+    /// 移除零宽空格 / 单词连接符 / BOM
+    #[serde(default = "default_true")]
+    pub zero_width: bool,
+
+    /// 移除 "[undefined]" 标记 (会误删代码等正常输出中的同名字面量，默认关闭)
+    #[serde(default)]
+    pub undefined_markers: bool,
+
+    /// 用户自定义的输出后处理规则 (按模型 / 客户端生效，按顺序应用)
+    #[serde(default)]
+    pub post_processors: Vec<PostProcessRule>,
+}
+
+impl Default for TextSanitizeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            smart_quotes: false,
+            zero_width: true,
+            undefined_markers: false,
+            post_processors: Vec::new(),
+        }
+    }
+}
+
+impl TextSanitizeConfig {
+    /// 是否有任何清理规则生效
+    pub fn is_active(&self) -> bool {
+        self.enabled
+            && (self.smart_quotes || self.zero_width || self.undefined_markers || !self.post_processors.is_empty())
+    }
+
+    /// Codex (Responses) 输出始终将弯引号替换为直引号，沿用该协议原有的行为
+    pub fn for_codex(mut self, is_codex: bool) -> Self {
+        if is_codex {
+            self.smart_quotes = true;
+        }
+        self
+    }
+
+    /// 只保留对当前请求生效的后处理规则 (models 匹配请求模型或映射后的模型，clients 匹配识别出的客户端)
+    pub fn for_request(&self, models: &[&str], client: &str) -> Self {
+        let mut scoped = self.clone();
+        scoped.post_processors.retain(|rule| rule.applies_to(models, client));
+        scoped
+    }
+}
+
+/// 输出后处理动作
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessAction {
+    /// 正则替换 (pattern → replacement，支持 $1 等分组引用)
+    #[default]
+    RegexReplace,
+    /// 移除 "As an AI language model, ..." / "作为一个AI语言模型，..." 之类的开场套话
+    StripAiBoilerplate,
+    /// 移除嵌入文本的不可见水印字符 (零宽字符、双向控制符、Unicode 标签字符等)
+    StripWatermarks,
+}
+
+/// 单条输出后处理规则
+/// 作用于模型输出的 text part (工具调用参数保持不变)，流式响应按每个增量事件的文本分别处理，
+/// 跨事件拆开的匹配不会被替换
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PostProcessRule {
+    /// 规则名称 (日志中使用)
+    #[serde(default)]
+    pub name: String,
+
+    #[serde(default)]
+    pub action: PostProcessAction,
+
+    /// RegexReplace 的正则表达式
+    #[serde(default)]
+    pub pattern: String,
+
+    /// RegexReplace 的替换文本
+    #[serde(default)]
+    pub replacement: String,
+
+    /// 生效的模型 (支持 * 通配，为空表示全部)
+    #[serde(default)]
+    pub models: Vec<String>,
+
+    /// 生效的客户端 (claude_code / cline / ...，为空表示全部)
+    #[serde(default)]
+    pub clients: Vec<String>,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl PostProcessRule {
+    pub fn applies_to(&self, models: &[&str], client: &str) -> bool {
+        let model_matches = self.models.is_empty()
+            || self.models.iter().any(|p| {
+                models
+                    .iter()
+                    .any(|m| p == m || (p.contains('*') && crate::proxy::common::model_mapping::wildcard_match(p, m)))
+            });
+        let client_matches = self.clients.is_empty() || self.clients.iter().any(|c| c == client);
+        self.enabled && model_matches && client_matches
+    }
+}
+
+/// 准入控制配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdmissionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 同时转发到上游的最大请求数
+    #[serde(default = "default_admission_max_concurrent")]
+    pub max_concurrent: usize,
+
+    /// 排队请求总数上限，超出时淘汰更低优先级的排队请求或直接拒绝
+    #[serde(default = "default_admission_max_queue")]
+    pub max_queue: usize,
+
+    /// 单个请求最长排队时间 (秒)
+    #[serde(default = "default_admission_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_concurrent: default_admission_max_concurrent(),
+            max_queue: default_admission_max_queue(),
+            queue_timeout_secs: default_admission_queue_timeout_secs(),
+        }
+    }
+}
+
+fn default_admission_max_concurrent() -> usize {
+    8
+}
+
+fn default_admission_max_queue() -> usize {
+    64
+}
+
+fn default_admission_queue_timeout_secs() -> u64 {
+    30
+}
+
+/// 流中途故障转移配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MidStreamFailoverConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个请求最多续写的次数
+    #[serde(default = "default_max_failovers")]
+    pub max_failovers: usize,
+}
+
+impl Default for MidStreamFailoverConfig {
+    fn default() -> Self {
+        Self { enabled: false, max_failovers: default_max_failovers() }
+    }
+}
+
+fn default_max_failovers() -> usize {
+    1
+}
+
+/// 模型能力探测配置
+/// 只对内置映射之外的目标模型 (自定义映射 / 别名 / 实验路由指向的新模型) 生效，内置模型的能力已知；
+/// 探测请求会消耗配额 (每个账号、每个模型 4 次)，默认关闭
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CapabilityProbeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个探测请求的超时 (秒)，超时视为结果不确定 (不缓存，回退到模型名判断)
+    #[serde(default = "default_capability_probe_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 探测结果的有效期 (小时)
+    #[serde(default = "default_capability_probe_ttl_hours")]
+    pub ttl_hours: u64,
+}
+
+impl Default for CapabilityProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: default_capability_probe_timeout_secs(),
+            ttl_hours: default_capability_probe_ttl_hours(),
+        }
+    }
+}
+
+fn default_capability_probe_timeout_secs() -> u64 {
+    10
+}
+
+fn default_capability_probe_ttl_hours() -> u64 {
+    168
+}
+
+/// RECITATION 重试配置
+/// 流式响应只能在输出任何内容之前重试；已输出部分内容后才被截断的响应以 stop_details 标明原因
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecitationRetryConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 重试时在原温度 (未设置时按 1.0) 基础上增加的值，结果不超过 2.0
+    #[serde(default = "default_recitation_temperature_bump")]
+    pub temperature_bump: f64,
+
+    /// 重试时追加到 systemInstruction 的提示，留空使用内置提示
+    #[serde(default)]
+    pub instruction: String,
+}
+
+impl Default for RecitationRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            temperature_bump: default_recitation_temperature_bump(),
+            instruction: String::new(),
+        }
+    }
+}
+
+fn default_recitation_temperature_bump() -> f64 {
+    0.2
+}
+
+/// 空闲预热配置 (默认关闭：开启后空闲期间也会定期刷新 token)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdleWarmupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 无请求超过该分钟数视为空闲，空闲期间每隔该时长预热一次
+    #[serde(default = "default_idle_warmup_minutes")]
+    pub idle_minutes: u64,
+
+    /// 检测间隔 (秒)，同时用于判断系统是否刚从休眠中恢复
+    #[serde(default = "default_idle_warmup_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+impl Default for IdleWarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_minutes: default_idle_warmup_minutes(),
+            check_interval_secs: default_idle_warmup_check_interval_secs(),
+        }
+    }
+}
+
+fn default_idle_warmup_minutes() -> u64 {
+    30
+}
+
+fn default_idle_warmup_check_interval_secs() -> u64 {
+    60
+}
+
+/// 离线检测配置 (默认关闭)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OfflineDetectionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 在线时的探测间隔 (秒)，离线期间按更短的间隔探测以便尽快恢复
+    #[serde(default = "default_offline_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+
+    /// 单次探测 (经由上游 HTTP 客户端的 HEAD 请求) 的超时 (毫秒)
+    #[serde(default = "default_offline_probe_timeout_ms")]
+    pub probe_timeout_ms: u64,
+
+    /// 离线时返回给客户端的 Retry-After (秒)
+    #[serde(default = "default_offline_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+impl Default for OfflineDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_interval_secs: default_offline_probe_interval_secs(),
+            probe_timeout_ms: default_offline_probe_timeout_ms(),
+            retry_after_secs: default_offline_retry_after_secs(),
+        }
+    }
+}
+
+fn default_offline_probe_interval_secs() -> u64 {
+    15
+}
+
+fn default_offline_probe_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_offline_retry_after_secs() -> u64 {
+    10
+}
+
+/// 响应压缩配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResponseCompressionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 小于该字节数的非流式响应不压缩
+    #[serde(default = "default_compression_min_size_bytes")]
+    pub min_size_bytes: usize,
+}
+
+impl Default for ResponseCompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size_bytes: default_compression_min_size_bytes(),
+        }
+    }
+}
+
+fn default_compression_min_size_bytes() -> usize {
+    1024
+}
+
+/// Codex SSE 保活配置
+/// Codex CLI 在执行耗时较长的 local_shell_call 或等待长时间思考时，空闲的流可能被客户端 / 中间代理断开
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CodexKeepAliveConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 上游无输出多久后发送一次保活注释 (秒)
+    #[serde(default = "default_keep_alive_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Codex 流式请求的上游超时 (秒)，替代默认的 600 秒
+    #[serde(default = "default_codex_stream_timeout_secs")]
+    pub stream_timeout_secs: u64,
+}
+
+impl Default for CodexKeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_keep_alive_interval_secs(),
+            stream_timeout_secs: default_codex_stream_timeout_secs(),
+        }
+    }
+}
+
+fn default_keep_alive_interval_secs() -> u64 {
+    15
+}
+
+fn default_codex_stream_timeout_secs() -> u64 {
+    1800
+}
+
+/// A/B 路由实验
+/// 同一会话始终落在同一组 (按实验名 + 会话指纹哈希分桶)，命中 variant 组时改用 target_model
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingExperiment {
+    /// 实验名 (写入日志标签，需唯一)
+    pub name: String,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 匹配客户端请求的模型名 (支持通配符 *)
+    #[serde(default = "default_experiment_model_pattern")]
+    pub model_pattern: String,
+
+    /// 只对指定客户端生效 (如 "claude_code")，留空表示所有客户端
+    #[serde(default)]
+    pub client: String,
+
+    /// 进入 variant 组的会话比例 (0-100)
+    #[serde(default = "default_experiment_percentage")]
+    pub percentage: f64,
+
+    /// variant 组使用的模型
+    pub target_model: String,
+}
+
+/// 子代理路由规则
+/// 系统提示词包含任一关键词 (不区分大小写) 即视为该类子代理，改用 target_model；按顺序取第一条命中的规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SubagentRoute {
+    /// 子代理类别名 (写入日志与 X-Subagent 响应头)
+    pub name: String,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 系统提示词关键词，如 "You are a code reviewer"
+    pub system_patterns: Vec<String>,
+
+    /// 匹配客户端请求的模型名 (支持通配符 *)
+    #[serde(default = "default_experiment_model_pattern")]
+    pub model_pattern: String,
+
+    /// 子代理使用的模型 (同样经过模型别名与自定义映射解析)
+    pub target_model: String,
+}
+
+fn default_experiment_model_pattern() -> String {
+    "*".to_string()
+}
+
+fn default_experiment_percentage() -> f64 {
+    50.0
+}
+
+/// 影子流量配置
+/// 镜像请求在后台执行，结果不会返回给客户端；失败不影响主请求
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShadowTrafficConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 镜像比例 (0-100)
+    #[serde(default = "default_shadow_percentage")]
+    pub percentage: f64,
+
+    /// 镜像目标模型
+    #[serde(default)]
+    pub model: String,
+
+    /// 是否在请求日志中记录影子响应内容 (关闭时仅记录状态、耗时与 token 用量)
+    #[serde(default)]
+    pub log_responses: bool,
+
+    /// 影子请求超时 (毫秒)
+    #[serde(default = "default_shadow_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for ShadowTrafficConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            percentage: default_shadow_percentage(),
+            model: String::new(),
+            log_responses: false,
+            timeout_ms: default_shadow_timeout_ms(),
+        }
+    }
+}
+
+fn default_shadow_percentage() -> f64 {
+    10.0
+}
+
+fn default_shadow_timeout_ms() -> u64 {
+    120_000
+}
+
+/// 本地时间上下文注入配置
+/// 模板占位符: {datetime} {date} {time} {weekday} {timezone} {utc_offset} {locale}
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeContextConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 时区显示名 (如 "Asia/Shanghai")，留空使用系统时区
+    #[serde(default)]
+    pub timezone: String,
+
+    /// UTC 偏移 (如 "+08:00")，留空使用系统当前偏移
+    #[serde(default)]
+    pub utc_offset: String,
+
+    /// 区域设置 (如 "zh-CN")，留空读取系统 LANG
+    #[serde(default)]
+    pub locale: String,
+
+    #[serde(default = "default_time_context_template")]
+    pub template: String,
+}
+
+impl Default for TimeContextConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timezone: String::new(),
+            utc_offset: String::new(),
+            locale: String::new(),
+            template: default_time_context_template(),
+        }
+    }
+}
+
+fn default_time_context_template() -> String {
+    "Current local date and time: {weekday}, {datetime} ({timezone}, UTC{utc_offset}). User locale: {locale}. \
+     Use this for any time- or date-sensitive answers instead of assuming UTC or your training cutoff."
+        .to_string()
+}
+
+/// 后台小请求合并配置
+/// 标题生成 / 简短摘要等后台任务数量多、体积小，在时间窗口内合并为一次上游请求，
+/// 再按分隔符拆分响应，以节省按请求计数的配额
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundBatchConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 收集窗口 (毫秒)：第一个请求到达后等待其他请求加入的时长
+    #[serde(default = "default_batch_window_ms")]
+    pub window_ms: u64,
+
+    /// 单批最大请求数，达到后立即发送
+    #[serde(default = "default_batch_max_size")]
+    pub max_batch_size: usize,
+
+    /// 可参与合并的单个请求最大字符数
+    #[serde(default = "default_batch_max_prompt_chars")]
+    pub max_prompt_chars: usize,
+}
+
+impl Default for BackgroundBatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: default_batch_window_ms(),
+            max_batch_size: default_batch_max_size(),
+            max_prompt_chars: default_batch_max_prompt_chars(),
+        }
+    }
+}
+
+fn default_batch_window_ms() -> u64 {
+    300
+}
+
+fn default_batch_max_size() -> usize {
+    8
+}
+
+fn default_batch_max_prompt_chars() -> usize {
+    4_000
+}
+
+/// 图片提示词安全预检配置
+/// 提交图片生成前先用轻量模型判断提示词是否会被拦截，避免浪费 image_gen 配额；
+/// 预检自身失败或超时时放行 (fail-open)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSafetyPrecheckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 用于分类的模型
+    #[serde(default = "default_precheck_model")]
+    pub model: String,
+
+    /// 预检超时 (毫秒)
+    #[serde(default = "default_precheck_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for ImageSafetyPrecheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: default_precheck_model(),
+            timeout_ms: default_precheck_timeout_ms(),
+        }
+    }
+}
+
+fn default_precheck_model() -> String {
+    "gemini-2.5-flash-lite".to_string()
+}
+
+fn default_precheck_timeout_ms() -> u64 {
+    5_000
+}
+
+/// 图片生成结果缓存配置
+/// 客户端可通过请求体 `cache_bust: true` 或 `Cache-Control: no-cache` 跳过缓存
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImageCacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 最多缓存的请求数 (超出时淘汰最久未使用的条目)
+    #[serde(default = "default_image_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// 缓存有效期 (分钟)
+    #[serde(default = "default_image_cache_ttl_minutes")]
+    pub ttl_minutes: u64,
+}
+
+impl Default for ImageCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: default_image_cache_max_entries(),
+            ttl_minutes: default_image_cache_ttl_minutes(),
+        }
+    }
+}
+
+fn default_image_cache_max_entries() -> usize {
+    64
+}
+
+fn default_image_cache_ttl_minutes() -> u64 {
+    24 * 60
+}
+
+/// 监控界面事件推送配置
+/// 请求日志与流式进度事件先在内存中合并，按频率上限批量推送给前端；推送的日志不含请求 / 响应体
+/// (详情面板按需通过 get_proxy_log_detail 读取)。max_batches_per_sec 为 0 时逐条立即推送
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UiEventConfig {
+    /// 每秒最多推送的批次数 (每类事件)
+    #[serde(default = "default_ui_event_batches_per_sec")]
+    pub max_batches_per_sec: u32,
+
+    /// 单批最多携带的请求日志数，积压超出时丢弃最旧的条目并通知前端重新加载
+    #[serde(default = "default_ui_event_max_batch_size")]
+    pub max_batch_size: usize,
+
+    /// 推送的日志是否保留请求 / 响应体
+    #[serde(default)]
+    pub include_bodies: bool,
+}
+
+impl Default for UiEventConfig {
+    fn default() -> Self {
+        Self {
+            max_batches_per_sec: default_ui_event_batches_per_sec(),
+            max_batch_size: default_ui_event_max_batch_size(),
+            include_bodies: false,
+        }
+    }
+}
+
+fn default_ui_event_batches_per_sec() -> u32 {
+    4
+}
+
+fn default_ui_event_max_batch_size() -> usize {
+    200
+}
+
+/// Anthropic 组织端点兼容配置
+/// /v1/organizations/me 返回此处配置的组织信息，用量 / 费用报告由本地请求日志汇总生成；关闭后这些端点返回 404
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrganizationStubConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 组织 ID (UUID 格式)
+    #[serde(default = "default_organization_id")]
+    pub id: String,
+
+    /// 组织名称
+    #[serde(default = "default_organization_name")]
+    pub name: String,
+}
+
+impl Default for OrganizationStubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            id: default_organization_id(),
+            name: default_organization_name(),
+        }
+    }
+}
+
+fn default_organization_id() -> String {
+    "6f0c2a4e-8b1d-4c3a-9e7f-a5b2d8c1e904".to_string()
+}
+
+fn default_organization_name() -> String {
+    "Antigravity Manager".to_string()
+}
+
+/// 超大附件处理配置
+/// v1internal 对单个 inlineData 与整个请求体都有大小上限，超限时只返回含糊的 400。
+/// 开启后超过阈值的图片在本地缩放重编码 (有损)，文本类附件拆分为多个 part，其余类型原样转发；
+/// 所有附件合计超过 max_request_bytes 时在发送前返回 413。默认关闭
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InlineOffloadConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个附件的阈值 (解码后字节数)
+    #[serde(default = "default_inline_threshold_bytes")]
+    pub threshold_bytes: usize,
+
+    /// 所有 inlineData 合计上限 (base64 字节数)
+    #[serde(default = "default_inline_max_request_bytes")]
+    pub max_request_bytes: usize,
+}
+
+impl Default for InlineOffloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_bytes: default_inline_threshold_bytes(),
+            max_request_bytes: default_inline_max_request_bytes(),
+        }
+    }
+}
+
+fn default_inline_threshold_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_inline_max_request_bytes() -> usize {
+    20 * 1024 * 1024
+}
+
+/// Claude 工具声明护栏
+/// Gemini 会拒绝描述过长或声明过多的请求。超长描述会被截断；
+/// 设置 max_tools 后，超出部分按最近最少使用 (历史中最后一次 tool_use 的位置) 丢弃
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolGuardrailConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 单个工具描述的最大字符数 (0 表示不限制)
+    #[serde(default = "default_max_tool_description_chars")]
+    pub max_description_chars: usize,
+
+    /// 最多保留的函数声明数量 (0 表示不丢弃)
+    #[serde(default)]
+    pub max_tools: usize,
+}
+
+impl Default for ToolGuardrailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_description_chars: default_max_tool_description_chars(),
+            max_tools: 0,
+        }
+    }
+}
+
+fn default_max_tool_description_chars() -> usize {
+    16 * 1024
+}
+
+/// 预估超出上下文窗口时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightBudgetAction {
+    /// 仅记录警告日志，照常转发 (由上游决定是否超长)
+    #[default]
+    Warn,
+    /// 直接返回 "prompt is too long" 客户端错误 (Claude Code 等客户端会据此自动压缩上下文)
+    Reject,
+    /// 先丢弃最早的对话轮次再发送，仍超长时返回错误 (仅 Claude 协议)
+    Trim,
+}
+
+/// 本地 token 预估
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreflightBudgetConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub action: PreflightBudgetAction,
+
+    /// 自定义上下文窗口 (模型名 -> token 数，支持 * 通配符)，覆盖内置表
+    #[serde(default)]
+    pub context_windows: HashMap<String, u64>,
+}
+
+impl Default for PreflightBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            action: PreflightBudgetAction::Warn,
+            context_windows: HashMap::new(),
+        }
+    }
+}
+
+/// OpenAI JSON 模式
+/// 开启后流式输出经过增量校验：丢弃 JSON 前后的代码块标记与说明文字
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonModeConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 因 MAX_TOKENS 截断时补全未闭合的字符串 / 对象 / 数组
+    #[serde(default)]
+    pub repair_truncated: bool,
+}
+
+impl Default for JsonModeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            repair_truncated: false,
+        }
+    }
+}
+
+/// 超大 SSE 事件拆分
+/// 单个增量 (文本 / 思考 / 工具参数) 超过上限时拆成多个同类事件下发，0 表示该协议不拆分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SseChunkingConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// OpenAI Chat / Completions / Responses 单事件增量上限 (字节)
+    #[serde(default = "default_sse_max_delta_bytes")]
+    pub openai_max_delta_bytes: usize,
+
+    /// Claude Messages 单事件增量上限 (字节)
+    #[serde(default = "default_sse_max_delta_bytes")]
+    pub claude_max_delta_bytes: usize,
+
+    /// Gemini 原生单事件文本上限 (字节)，默认不拆分以保持透传
+    #[serde(default)]
+    pub gemini_max_delta_bytes: usize,
 }
 
-impl Default for ExperimentalConfig {
+fn default_sse_max_delta_bytes() -> usize { 64 * 1024 }
+
+impl Default for SseChunkingConfig {
     fn default() -> Self {
         Self {
-            enable_signature_cache: true,
-            enable_tool_loop_recovery: true,
-            enable_cross_model_checks: true,
-            enable_usage_scaling: true,
+            enabled: true,
+            openai_max_delta_bytes: default_sse_max_delta_bytes(),
+            claude_max_delta_bytes: default_sse_max_delta_bytes(),
+            gemini_max_delta_bytes: 0,
         }
     }
 }
 
 fn default_true() -> bool { true }
 
+/// 上游响应录制/回放模式 (开发调试用)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureMode {
+    /// 正常调用上游
+    #[default]
+    Off,
+    /// 调用上游并将 (脱敏后的) 响应写入夹具文件
+    Capture,
+    /// 不调用上游，直接回放夹具文件
+    Replay,
+}
+
+/// 夹具录制/回放配置
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FixtureConfig {
+    #[serde(default)]
+    pub mode: FixtureMode,
+
+    /// 夹具目录 (为空时使用 <数据目录>/fixtures)
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+/// 故障注入配置 (Chaos 模式，仅用于韧性测试)
+/// 各概率取值 0.0 ~ 1.0，按 429 → 503 → 超时 的顺序累计判定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChaosConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 注入 429 RESOURCE_EXHAUSTED 的概率
+    #[serde(default)]
+    pub rate_limit_probability: f64,
+
+    /// 注入 503 UNAVAILABLE 的概率
+    #[serde(default)]
+    pub unavailable_probability: f64,
+
+    /// 注入请求超时的概率
+    #[serde(default)]
+    pub timeout_probability: f64,
+
+    /// 超时注入的挂起时长 (毫秒)
+    #[serde(default = "default_chaos_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// 流式响应中每个 chunk 被延迟的概率
+    #[serde(default)]
+    pub slow_chunk_probability: f64,
+
+    /// 慢 chunk 的延迟 (毫秒)
+    #[serde(default = "default_chaos_slow_chunk_ms")]
+    pub slow_chunk_delay_ms: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate_limit_probability: 0.0,
+            unavailable_probability: 0.0,
+            timeout_probability: 0.0,
+            timeout_ms: default_chaos_timeout_ms(),
+            slow_chunk_probability: 0.0,
+            slow_chunk_delay_ms: default_chaos_slow_chunk_ms(),
+        }
+    }
+}
+
+fn default_chaos_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_chaos_slow_chunk_ms() -> u64 {
+    2_000
+}
+
+/// 单个账号的每日 Token 上限
+/// - soft: 超过后调度器尽量避开该账号 (无其他可用账号时仍会使用)
+/// - hard: 超过后严格禁止使用，直到配额重置
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenCap {
+    #[serde(default)]
+    pub soft: Option<u64>,
+    #[serde(default)]
+    pub hard: Option<u64>,
+}
+
+/// 每日用量上限配置
+/// 优先级: accounts (按邮箱) > tiers (FREE / PRO / ULTRA) > default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLimitConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 默认上限
+    #[serde(default)]
+    pub default: TokenCap,
+
+    /// 按订阅等级设置上限 (e.g. 让 FREE 账号保持低调)
+    #[serde(default)]
+    pub tiers: HashMap<String, TokenCap>,
+
+    /// 按账号邮箱设置上限
+    #[serde(default)]
+    pub accounts: HashMap<String, TokenCap>,
+}
+
+impl UsageLimitConfig {
+    /// 解析账号生效的上限
+    pub fn cap_for(&self, email: &str, tier: Option<&str>) -> TokenCap {
+        if let Some(cap) = self.accounts.get(email) {
+            return *cap;
+        }
+        if let Some(cap) = tier.and_then(|t| self.tiers.get(t)) {
+            return *cap;
+        }
+        self.default
+    }
+}
+
+/// 配额分组规则：把满足条件的请求划入自定义分组 (规则按顺序匹配，第一条命中的生效)
+/// 各条件之间为"且"关系，未填写的条件不参与匹配
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuotaGroupRule {
+    /// 命中后使用的分组名
+    pub group: String,
+
+    /// 模型名匹配 (支持 `*` 通配符，原始模型名或映射后的模型名任一命中即可)
+    #[serde(default)]
+    pub models: Vec<String>,
+
+    /// 内置请求类型匹配 (agent / web_search / image_gen)
+    #[serde(default)]
+    pub request_types: Vec<String>,
+
+    /// 是否携带工具定义
+    #[serde(default)]
+    pub has_tools: Option<bool>,
+
+    /// 工具名匹配 (支持 `*` 通配符，任一工具命中即可)
+    #[serde(default)]
+    pub tools: Vec<String>,
+
+    /// 客户端标识匹配 (claude_code / cline / cherry_studio / codex_cli / continue / unknown)
+    #[serde(default)]
+    pub clients: Vec<String>,
+}
+
+/// 配额分组绑定的账号
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuotaGroupAccounts {
+    pub group: String,
+
+    /// 该分组只使用这些账号 (email 或 account_id)
+    #[serde(default)]
+    pub accounts: Vec<String>,
+
+    /// 独占：其他分组的请求不再调度到这些账号
+    #[serde(default)]
+    pub exclusive: bool,
+}
+
+/// 配额分组配置 (默认分组为内置请求类型 agent / web_search / image_gen)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaGroupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub rules: Vec<QuotaGroupRule>,
+
+    #[serde(default)]
+    pub groups: Vec<QuotaGroupAccounts>,
+}
+
+/// 内容过滤规则命中后的动作
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFilterAction {
+    /// 拒绝整个请求
+    #[default]
+    Block,
+    /// 将命中的文本替换为 replacement 后放行
+    Redact,
+}
+
+/// 内容过滤规则的作用范围
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentFilterScope {
+    /// 请求中的所有文本
+    #[default]
+    All,
+    /// 仅工具调用结果 (tool_result / role=tool / functionResponse)
+    ToolResults,
+}
+
+/// 单条内容过滤规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContentFilterRule {
+    /// 规则名称 (出现在错误信息与审计记录中)
+    pub name: String,
+
+    /// 关键词或正则表达式
+    pub pattern: String,
+
+    /// pattern 是否为正则；否则按大小写不敏感的关键词匹配
+    #[serde(default)]
+    pub regex: bool,
+
+    #[serde(default)]
+    pub action: ContentFilterAction,
+
+    #[serde(default)]
+    pub scope: ContentFilterScope,
+
+    /// Redact 动作的替换文本
+    #[serde(default = "default_redaction")]
+    pub replacement: String,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_redaction() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// 请求内容过滤 (WAF 风格) 配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentFilterConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub rules: Vec<ContentFilterRule>,
+}
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -175,6 +1533,11 @@ pub struct ProxyConfig {
     
     /// API 密钥
     pub api_key: String,
+
+    /// 管理密钥 (X-Admin-Key)：持有者可通过 X-Account 头把请求固定到指定账号，
+    /// 并可访问 /internal 下的管理端点 (日志设置等)；为空表示禁用
+    #[serde(default)]
+    pub admin_key: String,
     
 
     /// 是否自动启动
@@ -184,6 +1547,10 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub custom_mapping: std::collections::HashMap<String, String>,
 
+    /// 模型别名 (如 fast / smart / cheap)，在 /v1/models 中列出，可运行时改指向
+    #[serde(default)]
+    pub model_aliases: Vec<ModelAlias>,
+
     /// API 请求超时时间(秒)
     #[serde(default = "default_request_timeout")]
     pub request_timeout: u64,
@@ -207,6 +1574,311 @@ pub struct ProxyConfig {
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+
+    /// 上游响应录制/回放 (确定性本地开发与 CI)
+    #[serde(default)]
+    pub fixtures: FixtureConfig,
+
+    /// 故障注入 (Chaos 模式)
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+
+    /// 向客户端暴露路由信息 (账号邮箱 / 映射模型 / 尝试次数 / 重试原因)
+    /// 默认关闭：开启后才下发 X-Account-Email 等响应头及流式事件中的 proxy_metadata
+    #[serde(default)]
+    pub expose_routing_info: bool,
+
+    /// 每个账号的每日 Token 用量上限
+    #[serde(default)]
+    pub usage_limits: UsageLimitConfig,
+
+    /// 配额分组规则与分组账号绑定
+    #[serde(default)]
+    pub quota_groups: QuotaGroupConfig,
+
+    /// 请求内容过滤规则
+    #[serde(default)]
+    pub content_filter: ContentFilterConfig,
+
+    /// 客户端识别与兼容性配置
+    #[serde(default)]
+    pub client_profiles: ClientProfilesConfig,
+
+    /// 请求体与上传文件大小限制 (修改后需重启反代服务)
+    #[serde(default)]
+    pub upload_limits: UploadLimitsConfig,
+
+    /// 本地套接字监听 (Unix domain socket / Windows 命名管道，修改后需重启反代服务)
+    #[serde(default)]
+    pub local_socket: LocalSocketConfig,
+
+    /// 按协议分离的独立端口 / 路径前缀 (端口与前缀修改后需重启反代服务，鉴权可热更新)
+    #[serde(default)]
+    pub protocol_listeners: Vec<ProtocolListenerConfig>,
+}
+
+/// 协议路由分组
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyProtocol {
+    Anthropic,
+    Openai,
+    Gemini,
+}
+
+/// 单个协议的独立监听配置
+/// 只能配置一个 base URL 的客户端可通过独立端口或路径前缀只看到自己协议的路由 (如 /v1/models 返回对应格式)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolListenerConfig {
+    pub protocol: ProxyProtocol,
+
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 独立端口 (为空时不单独监听)，绑定地址与主端口相同
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// 在主端口上挂载的路径前缀 (如 /anthropic)，为空时不挂载
+    #[serde(default)]
+    pub path_prefix: String,
+
+    /// 独立鉴权模式 (为空时沿用全局配置)
+    #[serde(default)]
+    pub auth_mode: Option<ProxyAuthMode>,
+
+    /// 独立 API 密钥 (为空时沿用全局配置)
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+impl ProtocolListenerConfig {
+    /// 规范化路径前缀 (以 / 开头、不以 / 结尾)；未配置时返回 None
+    pub fn normalized_prefix(&self) -> Option<String> {
+        let trimmed = self.path_prefix.trim().trim_matches('/');
+        (!trimmed.is_empty()).then(|| format!("/{}", trimmed))
+    }
+}
+
+/// 本地套接字监听配置 (与 TCP 端口同时提供服务)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LocalSocketConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 套接字路径 (Unix) 或管道名 (Windows，如 \\.\pipe\antigravity-proxy)；留空使用默认位置
+    #[serde(default)]
+    pub path: String,
+
+    /// Unix 套接字文件权限 (默认 0o600，仅当前用户可访问)
+    #[serde(default = "default_local_socket_mode")]
+    pub mode: u32,
+}
+
+impl Default for LocalSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: String::new(),
+            mode: default_local_socket_mode(),
+        }
+    }
+}
+
+fn default_local_socket_mode() -> u32 {
+    0o600
+}
+
+/// 请求体与 multipart 上传大小限制
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UploadLimitsConfig {
+    /// 单个请求体上限 (MB)
+    #[serde(default = "default_max_request_body_mb")]
+    pub max_request_body_mb: u64,
+
+    /// multipart 中单个文件字段上限 (MB)
+    #[serde(default = "default_max_file_mb")]
+    pub max_file_mb: u64,
+
+    /// 文件字段超过该大小 (KB) 时写入临时文件，而不是整体缓存在内存中
+    #[serde(default = "default_spill_to_disk_kb")]
+    pub spill_to_disk_kb: u64,
+}
+
+impl UploadLimitsConfig {
+    pub fn max_request_body_bytes(&self) -> usize {
+        (self.max_request_body_mb as usize).saturating_mul(1024 * 1024)
+    }
+
+    pub fn max_file_bytes(&self) -> u64 {
+        self.max_file_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn spill_to_disk_bytes(&self) -> usize {
+        (self.spill_to_disk_kb as usize).saturating_mul(1024)
+    }
+}
+
+impl Default for UploadLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_request_body_mb: default_max_request_body_mb(),
+            max_file_mb: default_max_file_mb(),
+            spill_to_disk_kb: default_spill_to_disk_kb(),
+        }
+    }
+}
+
+fn default_max_request_body_mb() -> u64 { 100 }
+fn default_max_file_mb() -> u64 { 20 }
+fn default_spill_to_disk_kb() -> u64 { 1024 }
+
+/// 思考内容 (thought part) 在客户端输出中的呈现方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingOutput {
+    /// 丢弃思考内容 (Claude 协议保留仅携带签名的空 thinking 块，工具调用链路不受影响)
+    Drop,
+    /// 以独立字段下发：OpenAI reasoning_content / Claude thinking 块
+    /// (Legacy Completions 与 Codex Responses 没有对应字段，按 Drop 处理)
+    #[default]
+    Reasoning,
+    /// 以 <think>...</think> 合并到正文
+    Inline,
+}
+
+/// 按模型 / 客户端指定思考内容输出方式的规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ThinkingOutputRule {
+    /// 生效的模型 (匹配请求模型或映射后的模型，支持 * 通配，为空表示全部)
+    #[serde(default)]
+    pub models: Vec<String>,
+
+    /// 生效的客户端 (claude_code / cline / ...，为空表示全部)
+    #[serde(default)]
+    pub clients: Vec<String>,
+
+    pub mode: ThinkingOutput,
+}
+
+impl ThinkingOutputRule {
+    pub fn applies_to(&self, models: &[&str], client: &str) -> bool {
+        let model_matches = self.models.is_empty()
+            || self.models.iter().any(|p| {
+                models
+                    .iter()
+                    .any(|m| p == m || (p.contains('*') && crate::proxy::common::model_mapping::wildcard_match(p, m)))
+            });
+        let client_matches = self.clients.is_empty() || self.clients.iter().any(|c| c == client);
+        model_matches && client_matches
+    }
+}
+
+/// 单个客户端的兼容性开关
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClientCompatProfile {
+    /// OpenAI 协议下以 reasoning_content 字段下发思考内容 (关闭后丢弃思考内容)
+    #[serde(default = "default_true")]
+    pub reasoning_content: bool,
+
+    /// OpenAI 协议下将联网搜索词与来源引文以 Markdown 追加到正文
+    #[serde(default = "default_true")]
+    pub grounding_text: bool,
+
+    /// OpenAI 协议下在 choice 的 grounding_metadata 扩展字段中附带原始联网搜索元数据，
+    /// 供客户端自行渲染引用 (可与 grounding_text 同时开启)
+    #[serde(default)]
+    pub grounding_metadata: bool,
+
+    /// OpenAI 协议下模型输出的图片放入 message.images / delta.images (OpenRouter 风格)，
+    /// 而不是以 Markdown 图片内嵌到正文；请求携带 modalities: ["text", "image"] 时自动开启
+    #[serde(default)]
+    pub structured_images: bool,
+
+    /// 思考内容的输出方式 (未设置时：OpenAI 协议按 reasoning_content 开启为 reasoning、关闭为 drop，Claude 协议为 reasoning)
+    #[serde(default)]
+    pub thinking_output: Option<ThinkingOutput>,
+}
+
+impl Default for ClientCompatProfile {
+    fn default() -> Self {
+        Self {
+            reasoning_content: true,
+            grounding_text: true,
+            grounding_metadata: false,
+            structured_images: false,
+            thinking_output: None,
+        }
+    }
+}
+
+impl ClientCompatProfile {
+    /// 实际生效的思考内容输出方式
+    pub fn thinking_mode(&self) -> ThinkingOutput {
+        self.thinking_output.unwrap_or(if self.reasoning_content {
+            ThinkingOutput::Reasoning
+        } else {
+            ThinkingOutput::Drop
+        })
+    }
+
+    /// 应用第一条匹配当前请求 (模型 / 客户端) 的思考内容输出规则
+    pub fn with_thinking_rules(mut self, rules: &[ThinkingOutputRule], models: &[&str], client: &str) -> Self {
+        if let Some(rule) = rules.iter().find(|r| r.applies_to(models, client)) {
+            self.thinking_output = Some(rule.mode);
+        }
+        self
+    }
+}
+
+/// 客户端识别与兼容性配置
+/// profiles 的键为客户端标识：claude_code / cline / cherry_studio / codex_cli / continue / unknown，
+/// 未配置的客户端使用默认开关
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientProfilesConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    #[serde(default = "default_client_profiles")]
+    pub profiles: HashMap<String, ClientCompatProfile>,
+
+    /// 代理注入到模型输出中的文本 (联网搜索引文等) 所用语言：auto (按 Accept-Language) / en / zh / ja
+    #[serde(default = "default_output_language")]
+    pub output_language: String,
+}
+
+impl Default for ClientProfilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            profiles: default_client_profiles(),
+            output_language: default_output_language(),
+        }
+    }
+}
+
+fn default_output_language() -> String {
+    "auto".to_string()
+}
+
+/// Cherry Studio 能渲染 OpenRouter 风格的 message.images，图片不再以 base64 Markdown 塞进正文
+fn default_client_profiles() -> HashMap<String, ClientCompatProfile> {
+    HashMap::from([(
+        "cherry_studio".to_string(),
+        ClientCompatProfile {
+            structured_images: true,
+            ..ClientCompatProfile::default()
+        },
+    )])
+}
+
+/// 模型别名：客户端使用 name，实际按 target 继续走模型路由
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelAlias {
+    pub name: String,
+    pub target: String,
+    #[serde(default)]
+    pub description: String,
 }
 
 /// 上游代理配置
@@ -226,14 +1898,26 @@ impl Default for ProxyConfig {
             auth_mode: ProxyAuthMode::default(),
             port: 8045,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
+            admin_key: String::new(),
             auto_start: false,
             custom_mapping: std::collections::HashMap::new(),
+            model_aliases: Vec::new(),
             request_timeout: default_request_timeout(),
             enable_logging: false, // 默认关闭，节省性能
             upstream_proxy: UpstreamProxyConfig::default(),
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             experimental: ExperimentalConfig::default(),
+            fixtures: FixtureConfig::default(),
+            chaos: ChaosConfig::default(),
+            expose_routing_info: false, // 默认不向客户端暴露账号邮箱等路由信息
+            usage_limits: UsageLimitConfig::default(),
+            quota_groups: QuotaGroupConfig::default(),
+            content_filter: ContentFilterConfig::default(),
+            client_profiles: ClientProfilesConfig::default(),
+            upload_limits: UploadLimitsConfig::default(),
+            local_socket: LocalSocketConfig::default(),
+            protocol_listeners: Vec::new(),
         }
     }
 }
@@ -0,0 +1,432 @@
+// 协议一致性自检 (Protocol Conformance Self-Test)
+// 以官方 anthropic / openai SDK (Python / Rust) 实际发出的请求形态 (录制于 conformance/*.json) 调用反代，
+// 并按 SDK 响应类型的必填字段逐项校验，尽早发现缺字段、类型不符、未知事件等会导致 SDK 反序列化失败的问题。
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// 录制的 SDK 请求
+const FIXTURES: &[&str] = &[
+    include_str!("conformance/anthropic_messages.json"),
+    include_str!("conformance/anthropic_messages_stream.json"),
+    include_str!("conformance/anthropic_tools.json"),
+    include_str!("conformance/anthropic_count_tokens.json"),
+    include_str!("conformance/openai_chat.json"),
+    include_str!("conformance/openai_chat_stream.json"),
+    include_str!("conformance/openai_models.json"),
+];
+
+/// 期望的响应类型 (对应 SDK 中的响应模型)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expectation {
+    AnthropicMessage,
+    AnthropicStream,
+    AnthropicCountTokens,
+    OpenaiChat,
+    OpenaiChatStream,
+    OpenaiModels,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixture {
+    pub name: String,
+    pub sdk: String,
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Value,
+    pub expect: Expectation,
+}
+
+/// 内置的全部用例
+pub fn fixtures() -> Vec<Fixture> {
+    FIXTURES
+        .iter()
+        .map(|raw| serde_json::from_str(raw).expect("invalid embedded conformance fixture"))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub sdk: String,
+    pub passed: bool,
+    pub status: u16,
+    pub duration_ms: u64,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConformanceReport {
+    pub base_url: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<CaseResult>,
+}
+
+/// 字段校验器 (收集全部问题而不是遇到第一个就停止)
+#[derive(Default)]
+struct Checker {
+    errors: Vec<String>,
+}
+
+impl Checker {
+    fn field<'a>(&mut self, value: &'a Value, path: &str) -> Option<&'a Value> {
+        let found = value.pointer(path);
+        if found.is_none() {
+            self.errors.push(format!("missing required field `{}`", path));
+        }
+        found
+    }
+
+    fn string(&mut self, value: &Value, path: &str) {
+        if let Some(v) = self.field(value, path) {
+            if !v.is_string() {
+                self.errors.push(format!("`{}` must be a string, got {}", path, v));
+            }
+        }
+    }
+
+    fn integer(&mut self, value: &Value, path: &str) {
+        if let Some(v) = self.field(value, path) {
+            if !v.is_u64() {
+                self.errors.push(format!("`{}` must be a non-negative integer, got {}", path, v));
+            }
+        }
+    }
+
+    fn equals(&mut self, value: &Value, path: &str, expected: &str) {
+        if let Some(v) = self.field(value, path) {
+            if v != expected {
+                self.errors.push(format!("`{}` must be \"{}\", got {}", path, expected, v));
+            }
+        }
+    }
+
+    fn nullable_string(&mut self, value: &Value, path: &str) {
+        if let Some(v) = self.field(value, path) {
+            if !v.is_string() && !v.is_null() {
+                self.errors.push(format!("`{}` must be a string or null, got {}", path, v));
+            }
+        }
+    }
+
+    fn array<'a>(&mut self, value: &'a Value, path: &str) -> &'a [Value] {
+        match self.field(value, path) {
+            Some(Value::Array(items)) => items,
+            Some(other) => {
+                self.errors.push(format!("`{}` must be an array, got {}", path, other));
+                &[]
+            }
+            None => &[],
+        }
+    }
+
+    fn object(&mut self, value: &Value, path: &str) {
+        if let Some(v) = self.field(value, path) {
+            if !v.is_object() {
+                self.errors.push(format!("`{}` must be an object, got {}", path, v));
+            }
+        }
+    }
+}
+
+/// Anthropic 内容块 (anthropic.types.ContentBlock)
+fn check_content_block(c: &mut Checker, block: &Value, path: &str) {
+    let p = |field: &str| format!("{}/{}", path, field);
+    match block["type"].as_str() {
+        Some("text") => c.string(block, "/text"),
+        Some("thinking") => {
+            c.string(block, "/thinking");
+            c.string(block, "/signature");
+        }
+        Some("redacted_thinking") => c.string(block, "/data"),
+        Some("tool_use") | Some("server_tool_use") => {
+            c.string(block, "/id");
+            c.string(block, "/name");
+            c.object(block, "/input");
+        }
+        Some("web_search_tool_result") | Some("code_execution_tool_result") => {
+            c.string(block, "/tool_use_id");
+            c.field(block, "/content");
+        }
+        Some(other) => c.errors.push(format!("`{}` has unknown content block type \"{}\"", p("type"), other)),
+        None => c.errors.push(format!("missing required field `{}`", p("type"))),
+    }
+}
+
+/// anthropic.types.Message
+fn check_anthropic_message(c: &mut Checker, message: &Value) {
+    c.string(message, "/id");
+    c.equals(message, "/type", "message");
+    c.equals(message, "/role", "assistant");
+    c.string(message, "/model");
+    c.nullable_string(message, "/stop_reason");
+    c.field(message, "/stop_sequence");
+    c.integer(message, "/usage/input_tokens");
+    c.integer(message, "/usage/output_tokens");
+    for (i, block) in c.array(message, "/content").iter().enumerate() {
+        let mut inner = Checker::default();
+        check_content_block(&mut inner, block, &format!("/content/{}", i));
+        c.errors.extend(inner.errors.into_iter().map(|e| format!("content[{}]: {}", i, e)));
+    }
+}
+
+/// 解析 SSE 事件 (event 名称, data)
+fn sse_events(body: &str) -> Vec<(Option<String>, String)> {
+    let mut events = Vec::new();
+    let mut name = None;
+    for line in body.lines() {
+        if let Some(event) = line.strip_prefix("event:") {
+            name = Some(event.trim().to_string());
+        } else if let Some(data) = line.strip_prefix("data:") {
+            events.push((name.take(), data.trim().to_string()));
+        }
+    }
+    events
+}
+
+fn check_anthropic_stream(c: &mut Checker, body: &str) {
+    let events = sse_events(body);
+    let types: Vec<String> = events
+        .iter()
+        .filter_map(|(_, data)| serde_json::from_str::<Value>(data).ok())
+        .filter_map(|v| v["type"].as_str().map(str::to_string))
+        .collect();
+    if types.first().map(String::as_str) != Some("message_start") {
+        c.errors.push("stream must start with message_start".to_string());
+    }
+    if types.last().map(String::as_str) != Some("message_stop") {
+        c.errors.push("stream must end with message_stop".to_string());
+    }
+
+    for (i, (name, data)) in events.iter().enumerate() {
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            c.errors.push(format!("event #{}: data is not valid JSON: {}", i, data));
+            continue;
+        };
+        let kind = event["type"].as_str().unwrap_or_default();
+        if let Some(name) = name {
+            if name != kind {
+                c.errors.push(format!("event #{}: `event: {}` does not match data type \"{}\"", i, name, kind));
+            }
+        }
+        let mut e = Checker::default();
+        match kind {
+            "message_start" => {
+                e.string(&event, "/message/id");
+                e.equals(&event, "/message/type", "message");
+                e.equals(&event, "/message/role", "assistant");
+                e.string(&event, "/message/model");
+                e.array(&event, "/message/content");
+                e.integer(&event, "/message/usage/input_tokens");
+                e.integer(&event, "/message/usage/output_tokens");
+            }
+            "content_block_start" => {
+                e.integer(&event, "/index");
+                if let Some(block) = e.field(&event, "/content_block") {
+                    check_content_block(&mut e, block, "/content_block");
+                }
+            }
+            "content_block_delta" => {
+                e.integer(&event, "/index");
+                match event["delta"]["type"].as_str() {
+                    Some("text_delta") => e.string(&event, "/delta/text"),
+                    Some("input_json_delta") => e.string(&event, "/delta/partial_json"),
+                    Some("thinking_delta") => e.string(&event, "/delta/thinking"),
+                    Some("signature_delta") => e.string(&event, "/delta/signature"),
+                    Some("citations_delta") => e.object(&event, "/delta/citation"),
+                    other => e.errors.push(format!("unknown delta type {:?}", other)),
+                }
+            }
+            "content_block_stop" => e.integer(&event, "/index"),
+            "message_delta" => {
+                e.object(&event, "/delta");
+                e.integer(&event, "/usage/output_tokens");
+            }
+            "message_stop" | "ping" => {}
+            "error" => e.errors.push(format!("stream returned an error event: {}", event["error"])),
+            other => e.errors.push(format!("unknown event type \"{}\"", other)),
+        }
+        c.errors.extend(e.errors.into_iter().map(|err| format!("event #{} ({}): {}", i, kind, err)));
+    }
+}
+
+/// openai.types.chat.ChatCompletion
+fn check_openai_chat(c: &mut Checker, response: &Value) {
+    c.string(response, "/id");
+    c.equals(response, "/object", "chat.completion");
+    c.integer(response, "/created");
+    c.string(response, "/model");
+    let choices = c.array(response, "/choices");
+    if choices.is_empty() {
+        c.errors.push("`/choices` must not be empty".to_string());
+    }
+    for choice in choices {
+        c.integer(choice, "/index");
+        c.string(choice, "/message/role");
+        c.nullable_string(choice, "/finish_reason");
+    }
+    if response.get("usage").is_some_and(|u| !u.is_null()) {
+        c.integer(response, "/usage/prompt_tokens");
+        c.integer(response, "/usage/completion_tokens");
+        c.integer(response, "/usage/total_tokens");
+    }
+}
+
+/// openai.types.chat.ChatCompletionChunk
+fn check_openai_chat_stream(c: &mut Checker, body: &str) {
+    let events = sse_events(body);
+    if events.last().map(|(_, d)| d.as_str()) != Some("[DONE]") {
+        c.errors.push("stream must end with `data: [DONE]`".to_string());
+    }
+    for (i, (_, data)) in events.iter().enumerate().filter(|(_, (_, d))| d != "[DONE]") {
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            c.errors.push(format!("chunk #{}: data is not valid JSON: {}", i, data));
+            continue;
+        };
+        let mut e = Checker::default();
+        e.string(&chunk, "/id");
+        e.equals(&chunk, "/object", "chat.completion.chunk");
+        e.integer(&chunk, "/created");
+        e.string(&chunk, "/model");
+        for choice in e.array(&chunk, "/choices") {
+            e.integer(choice, "/index");
+            e.object(choice, "/delta");
+        }
+        c.errors.extend(e.errors.into_iter().map(|err| format!("chunk #{}: {}", i, err)));
+    }
+}
+
+/// openai.types.Model 列表
+fn check_openai_models(c: &mut Checker, response: &Value) {
+    c.equals(response, "/object", "list");
+    for model in c.array(response, "/data") {
+        c.string(model, "/id");
+        c.equals(model, "/object", "model");
+        c.integer(model, "/created");
+        c.string(model, "/owned_by");
+    }
+}
+
+/// 按期望类型校验响应体，返回发现的问题
+pub fn validate(expect: Expectation, body: &str) -> Vec<String> {
+    let mut c = Checker::default();
+    match expect {
+        Expectation::AnthropicStream => check_anthropic_stream(&mut c, body),
+        Expectation::OpenaiChatStream => check_openai_chat_stream(&mut c, body),
+        _ => match serde_json::from_str::<Value>(body) {
+            Ok(json) => match expect {
+                Expectation::AnthropicMessage => check_anthropic_message(&mut c, &json),
+                Expectation::AnthropicCountTokens => c.integer(&json, "/input_tokens"),
+                Expectation::OpenaiChat => check_openai_chat(&mut c, &json),
+                Expectation::OpenaiModels => check_openai_models(&mut c, &json),
+                _ => unreachable!(),
+            },
+            Err(e) => c.errors.push(format!("response is not valid JSON: {}", e)),
+        },
+    }
+    c.errors
+}
+
+async fn run_case(client: &reqwest::Client, base_url: &str, api_key: Option<&str>, fixture: &Fixture) -> CaseResult {
+    let started = Instant::now();
+    let url = format!("{}{}", base_url.trim_end_matches('/'), fixture.path);
+    let method = reqwest::Method::from_bytes(fixture.method.as_bytes()).unwrap_or(reqwest::Method::POST);
+    let mut request = client.request(method, &url);
+    for (name, value) in &fixture.headers {
+        request = request.header(name, value);
+    }
+    if let Some(key) = api_key.filter(|k| !k.is_empty()) {
+        request = request.header("x-api-key", key).bearer_auth(key);
+    }
+    if !fixture.body.is_null() {
+        request = request.json(&fixture.body);
+    }
+
+    let (status, errors) = match request.send().await {
+        Ok(resp) => {
+            let status = resp.status().as_u16();
+            match resp.text().await {
+                Ok(_) if status >= 400 => (status, vec![format!("HTTP {}", status)]),
+                Ok(body) => (status, validate(fixture.expect, &body)),
+                Err(e) => (status, vec![format!("failed to read response body: {}", e)]),
+            }
+        }
+        Err(e) => (0, vec![format!("request failed: {}", e)]),
+    };
+    CaseResult {
+        name: fixture.name.clone(),
+        sdk: fixture.sdk.clone(),
+        passed: errors.is_empty(),
+        status,
+        duration_ms: started.elapsed().as_millis() as u64,
+        errors,
+    }
+}
+
+/// 依次执行全部用例 (顺序执行，避免并发请求触发限流)
+pub async fn run(base_url: &str, api_key: Option<&str>) -> ConformanceReport {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(120))
+        .build()
+        .unwrap_or_default();
+    let mut results = Vec::new();
+    for fixture in fixtures() {
+        let result = run_case(&client, base_url, api_key, &fixture).await;
+        if result.passed {
+            tracing::info!("[Conformance] PASS {} ({})", result.name, result.sdk);
+        } else {
+            tracing::warn!("[Conformance] FAIL {} ({}): {:?}", result.name, result.sdk, result.errors);
+        }
+        results.push(result);
+    }
+    let passed = results.iter().filter(|r| r.passed).count();
+    ConformanceReport {
+        base_url: base_url.to_string(),
+        passed,
+        failed: results.len() - passed,
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_fixtures_parse() {
+        let all = fixtures();
+        assert_eq!(all.len(), FIXTURES.len());
+        assert!(all.iter().all(|f| f.path.starts_with("/v1/")));
+    }
+
+    #[test]
+    fn test_schema_drift_is_reported() {
+        let good = r#"{"id":"msg_1","type":"message","role":"assistant","model":"m","content":[{"type":"text","text":"pong"}],"stop_reason":"end_turn","stop_sequence":null,"usage":{"input_tokens":1,"output_tokens":1}}"#;
+        assert!(validate(Expectation::AnthropicMessage, good).is_empty());
+
+        let drifted = r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"tool_use","id":"t","name":"x"},{"type":"mystery"}],"stop_reason":"end_turn","usage":{"input_tokens":1}}"#;
+        let errors = validate(Expectation::AnthropicMessage, drifted);
+        for expected in ["/model", "/stop_sequence", "/usage/output_tokens", "content[0]: missing required field `/input`", "unknown content block type"] {
+            assert!(errors.iter().any(|e| e.contains(expected)), "{} not in {:?}", expected, errors);
+        }
+
+        let stream = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"m\",\"type\":\"message\",\"role\":\"assistant\",\"model\":\"x\",\"content\":[],\"usage\":{\"input_tokens\":1,\"output_tokens\":0}}}\n\n\
+            event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"hi\"}}\n\n\
+            event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{}}\n\n";
+        let errors = validate(Expectation::AnthropicStream, stream);
+        assert!(errors.iter().any(|e| e.contains("must end with message_stop")));
+        assert!(errors.iter().any(|e| e.contains("message_delta") && e.contains("/usage/output_tokens")));
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+
+        let chunks = "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\n";
+        assert!(validate(Expectation::OpenaiChatStream, chunks).is_empty());
+        assert!(!validate(Expectation::OpenaiChat, r#"{"id":"c","object":"chat.completion","choices":[]}"#).is_empty());
+    }
+}
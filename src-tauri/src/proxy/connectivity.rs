@@ -0,0 +1,171 @@
+// 离线检测 (Offline / Degraded Mode)
+// 本机断网时请求要等到上游连接超时才失败，客户端只能看到缓慢的超时。开启后在后台定期通过
+// 转发请求所用的同一个 UpstreamClient (同样的上游代理 / 环境变量代理 / 系统代理) 向 v1internal 端点发送 HEAD，
+// 连续失败后标记为离线：发往 Google 上游的请求直接返回 offline_error + Retry-After，
+// /status 与 UI 显示 "offline"，直到探测恢复。z.ai 等其他上游不受影响。默认关闭。
+
+use crate::proxy::config::ExperimentalConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// 连续失败该次数后才判定为离线，避免单次抖动误判
+const OFFLINE_AFTER_FAILURES: u32 = 2;
+/// 离线期间的探测间隔上限 (秒)
+const OFFLINE_PROBE_INTERVAL_SECS: u64 = 3;
+/// 离线时请求触发即时复查的最小间隔 (毫秒)
+const RECHECK_MIN_INTERVAL_MS: i64 = 2000;
+
+/// 对外展示的连通性状态
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConnectivityStatus {
+    /// "online" | "offline"
+    pub status: &'static str,
+    /// 进入当前状态的时间 (Unix 秒)
+    pub since: i64,
+    /// 最近一次探测的时间 (Unix 毫秒，未探测过为 0)
+    pub last_probe_ms: i64,
+}
+
+pub struct Connectivity {
+    offline: AtomicBool,
+    since: AtomicI64,
+    last_probe_ms: AtomicI64,
+    failures: AtomicU32,
+    recheck_lock: tokio::sync::Mutex<()>,
+    app_handle: Option<tauri::AppHandle>,
+}
+
+impl Connectivity {
+    pub fn new(app_handle: Option<tauri::AppHandle>) -> Self {
+        Self {
+            offline: AtomicBool::new(false),
+            since: AtomicI64::new(chrono::Utc::now().timestamp()),
+            last_probe_ms: AtomicI64::new(0),
+            failures: AtomicU32::new(0),
+            recheck_lock: tokio::sync::Mutex::new(()),
+            app_handle,
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> ConnectivityStatus {
+        ConnectivityStatus {
+            status: if self.is_offline() { "offline" } else { "online" },
+            since: self.since.load(Ordering::Relaxed),
+            last_probe_ms: self.last_probe_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 记录一次探测结果，状态变化时记录日志并通知前端
+    pub fn record_probe(&self, reachable: bool) {
+        self.last_probe_ms.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+        let offline = if reachable {
+            self.failures.store(0, Ordering::Relaxed);
+            false
+        } else {
+            self.failures.fetch_add(1, Ordering::Relaxed) + 1 >= OFFLINE_AFTER_FAILURES || self.is_offline()
+        };
+        if self.offline.swap(offline, Ordering::Relaxed) == offline {
+            return;
+        }
+        self.since.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        if offline {
+            tracing::warn!("[Connectivity] 上游不可达，进入离线模式 (请求将快速失败)");
+        } else {
+            tracing::info!("[Connectivity] 网络已恢复，退出离线模式");
+        }
+        if let Some(app) = &self.app_handle {
+            let _ = app.emit("proxy://connectivity", self.snapshot());
+        }
+    }
+
+    /// 离线状态下由请求触发的即时复查 (限频)，网络恢复后首个请求无需等待后台探测周期
+    pub async fn recheck(&self, upstream: &UpstreamClient, timeout: Duration) {
+        let _guard = self.recheck_lock.lock().await;
+        let since_last = chrono::Utc::now().timestamp_millis() - self.last_probe_ms.load(Ordering::Relaxed);
+        if !self.is_offline() || since_last < RECHECK_MIN_INTERVAL_MS {
+            return;
+        }
+        self.record_probe(upstream.probe_reachable(timeout).await);
+    }
+}
+
+/// 启动后台探测任务 (服务停止时由调用方 abort)
+pub fn spawn(
+    connectivity: Arc<Connectivity>,
+    upstream: Arc<UpstreamClient>,
+    experimental: Arc<RwLock<ExperimentalConfig>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let config = experimental.read().await.offline_detection.clone();
+            if !config.enabled {
+                // 关闭检测时恢复为在线，避免停留在过期的离线状态
+                if connectivity.is_offline() {
+                    connectivity.record_probe(true);
+                }
+            } else {
+                let reachable = upstream
+                    .probe_reachable(Duration::from_millis(config.probe_timeout_ms.max(100)))
+                    .await;
+                connectivity.record_probe(reachable);
+            }
+
+            let mut interval = config.probe_interval_secs.max(1);
+            if connectivity.is_offline() {
+                interval = interval.min(OFFLINE_PROBE_INTERVAL_SECS);
+            }
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_after_consecutive_failures() {
+        let connectivity = Connectivity::new(None);
+        connectivity.record_probe(false);
+        assert!(!connectivity.is_offline());
+        connectivity.record_probe(false);
+        assert!(connectivity.is_offline());
+        assert_eq!(connectivity.snapshot().status, "offline");
+
+        connectivity.record_probe(true);
+        assert!(!connectivity.is_offline());
+        // 恢复后计数清零，单次失败不会立即离线
+        connectivity.record_probe(false);
+        assert!(!connectivity.is_offline());
+    }
+
+    #[tokio::test]
+    async fn test_probe_uses_upstream_client() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n").await;
+            }
+        });
+        let upstream = UpstreamClient::with_base_urls(None, vec![format!("http://127.0.0.1:{}/v1internal", port)]);
+        // 任意 HTTP 响应 (包括 404) 都说明网络可达
+        assert!(upstream.probe_reachable(Duration::from_secs(2)).await);
+
+        server.abort();
+        let _ = server.await;
+        assert!(!upstream.probe_reachable(Duration::from_secs(2)).await);
+    }
+}
@@ -0,0 +1,280 @@
+// A/B 路由实验 (Routing Experiments)
+// 按 "实验名 + 会话指纹" 哈希分桶，把一部分会话路由到替代模型；分组标签随 RoutingInfo 写入 X-Experiment 响应头，
+// 由监控中间件记录到请求日志。汇总报告基于带标签的日志统计各组的延迟、token 用量与工具调用成功率。
+
+use crate::proxy::common::routing_info::RoutingInfo;
+use crate::proxy::config::RoutingExperiment;
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::server::AppState;
+use axum::http::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+pub const ARM_CONTROL: &str = "control";
+pub const ARM_VARIANT: &str = "variant";
+
+/// 单个请求的实验分组结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub experiment: String,
+    pub arm: &'static str,
+    /// variant 组使用的模型 (control 组为 None，沿用原映射)
+    pub target_model: Option<String>,
+}
+
+impl Assignment {
+    /// 日志标签 (实验名:分组)
+    pub fn tag(&self) -> String {
+        format!("{}:{}", self.experiment, self.arm)
+    }
+}
+
+/// FNV-1a，保证分桶结果跨进程稳定 (同一会话重启后仍落在同一组)
+fn bucket(experiment: &str, session_key: &str) -> f64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in experiment.bytes().chain([0u8]).chain(session_key.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % 10_000) as f64 / 100.0
+}
+
+fn matches_model(pattern: &str, model: &str) -> bool {
+    pattern == model || (pattern.contains('*') && crate::proxy::common::model_mapping::wildcard_match(pattern, model))
+}
+
+/// 返回第一个匹配的实验分组 (未命中任何实验时返回 None)
+pub fn assign(experiments: &[RoutingExperiment], client_model: &str, client: &str, session_key: &str) -> Option<Assignment> {
+    let experiment = experiments.iter().find(|e| {
+        e.enabled
+            && !e.target_model.trim().is_empty()
+            && matches_model(&e.model_pattern, client_model)
+            && (e.client.is_empty() || e.client == client)
+    })?;
+    let in_variant = bucket(&experiment.name, session_key) < experiment.percentage;
+    Some(Assignment {
+        experiment: experiment.name.clone(),
+        arm: if in_variant { ARM_VARIANT } else { ARM_CONTROL },
+        target_model: in_variant.then(|| experiment.target_model.clone()),
+    })
+}
+
+/// 为请求分配实验分组：写入路由信息标签，返回生效的映射模型
+pub async fn route(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_model: &str,
+    mapped_model: String,
+    session_key: &str,
+    routing: &mut RoutingInfo,
+) -> String {
+    let experiments = &state.experimental.read().await.experiments;
+    if experiments.is_empty() {
+        return mapped_model;
+    }
+    let client = headers
+        .get(crate::proxy::client_profile::HEADER_CLIENT_PROFILE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    match assign(experiments, client_model, client, session_key) {
+        Some(assignment) => {
+            routing.experiment = Some(assignment.tag());
+            match assignment.target_model {
+                Some(target) => {
+                    tracing::debug!("[Experiment] {} -> {} ({})", client_model, target, assignment.experiment);
+                    target
+                }
+                None => mapped_model,
+            }
+        }
+        None => mapped_model,
+    }
+}
+
+/// 单个实验分组的汇总
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ExperimentArmSummary {
+    pub experiment: String,
+    pub arm: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub avg_latency_ms: f64,
+    pub p95_latency_ms: u64,
+    pub avg_input_tokens: f64,
+    pub avg_output_tokens: f64,
+    /// 客户端回传的工具结果数 (取自每个请求的最新一轮)
+    pub tool_results: u64,
+    pub tool_errors: u64,
+    /// 工具调用成功率 (没有工具结果时为 None)
+    pub tool_success_rate: Option<f64>,
+}
+
+/// 统计请求体最新一轮中的工具结果 (总数, 失败数)
+/// 只看最后一条消息，避免同一会话的历史工具结果被重复计数
+fn latest_tool_results(request_body: &str) -> (u64, u64) {
+    let Ok(body) = serde_json::from_str::<Value>(request_body) else {
+        return (0, 0);
+    };
+    // OpenAI: 末尾连续的 role=tool 消息 (协议不携带失败标记，按成功计)
+    if let Some(messages) = body.get("messages").and_then(|v| v.as_array()) {
+        let tool_messages = messages.iter().rev().take_while(|m| m["role"] == "tool").count() as u64;
+        if tool_messages > 0 {
+            return (tool_messages, 0);
+        }
+        // Claude: 最后一条 user 消息中的 tool_result 块
+        let Some(blocks) = messages.last().and_then(|m| m["content"].as_array()) else {
+            return (0, 0);
+        };
+        let results: Vec<&Value> = blocks.iter().filter(|b| b["type"] == "tool_result").collect();
+        let errors = results.iter().filter(|b| b["is_error"].as_bool().unwrap_or(false)).count();
+        return (results.len() as u64, errors as u64);
+    }
+    // Gemini: 最后一条 contents 中的 functionResponse
+    let Some(parts) = body
+        .get("contents")
+        .and_then(|v| v.as_array())
+        .and_then(|c| c.last())
+        .and_then(|c| c["parts"].as_array())
+    else {
+        return (0, 0);
+    };
+    let responses: Vec<&Value> = parts.iter().filter_map(|p| p.get("functionResponse")).collect();
+    let errors = responses.iter().filter(|r| r["response"].get("error").is_some()).count();
+    (responses.len() as u64, errors as u64)
+}
+
+/// 按 (实验, 分组) 汇总带标签的请求日志
+pub fn summarize(logs: &[ProxyRequestLog]) -> Vec<ExperimentArmSummary> {
+    let mut groups: BTreeMap<(String, String), Vec<&ProxyRequestLog>> = BTreeMap::new();
+    for log in logs {
+        let Some((experiment, arm)) = log.experiment.as_deref().and_then(|tag| tag.rsplit_once(':')) else {
+            continue;
+        };
+        groups.entry((experiment.to_string(), arm.to_string())).or_default().push(log);
+    }
+
+    groups
+        .into_iter()
+        .map(|((experiment, arm), logs)| {
+            let requests = logs.len() as u64;
+            let mut latencies: Vec<u64> = logs.iter().map(|l| l.duration).collect();
+            latencies.sort_unstable();
+            let p95_index = ((latencies.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+            let avg = |values: &mut dyn Iterator<Item = u64>| values.sum::<u64>() as f64 / requests as f64;
+            let (tool_results, tool_errors) = logs
+                .iter()
+                .filter_map(|l| l.request_body.as_deref())
+                .map(latest_tool_results)
+                .fold((0, 0), |(t, e), (dt, de)| (t + dt, e + de));
+
+            ExperimentArmSummary {
+                experiment,
+                arm,
+                requests,
+                errors: logs.iter().filter(|l| l.status >= 400).count() as u64,
+                avg_latency_ms: avg(&mut latencies.iter().copied()),
+                p95_latency_ms: latencies.get(p95_index).copied().unwrap_or(0),
+                avg_input_tokens: avg(&mut logs.iter().map(|l| l.input_tokens.unwrap_or(0) as u64)),
+                avg_output_tokens: avg(&mut logs.iter().map(|l| l.output_tokens.unwrap_or(0) as u64)),
+                tool_results,
+                tool_errors,
+                tool_success_rate: (tool_results > 0)
+                    .then(|| (tool_results - tool_errors) as f64 / tool_results as f64),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn experiment(percentage: f64) -> RoutingExperiment {
+        RoutingExperiment {
+            name: "flash-vs-pro".to_string(),
+            enabled: true,
+            model_pattern: "claude-sonnet-*".to_string(),
+            client: "claude_code".to_string(),
+            percentage,
+            target_model: "gemini-2.5-flash".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_assignment_is_sticky_per_session_and_respects_filters() {
+        let experiments = vec![experiment(50.0)];
+        let first = assign(&experiments, "claude-sonnet-4-5", "claude_code", "session-a").unwrap();
+        for _ in 0..5 {
+            assert_eq!(assign(&experiments, "claude-sonnet-4-5", "claude_code", "session-a").unwrap(), first);
+        }
+        assert!(assign(&experiments, "gpt-4o", "claude_code", "session-a").is_none());
+        assert!(assign(&experiments, "claude-sonnet-4-5", "cline", "session-a").is_none());
+
+        // 0% 全部 control，100% 全部 variant；50% 大致对半
+        assert_eq!(assign(&[experiment(0.0)], "claude-sonnet-4-5", "claude_code", "s").unwrap().arm, ARM_CONTROL);
+        let all = assign(&[experiment(100.0)], "claude-sonnet-4-5", "claude_code", "s").unwrap();
+        assert_eq!(all.arm, ARM_VARIANT);
+        assert_eq!(all.target_model.as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(all.tag(), "flash-vs-pro:variant");
+        let variants = (0..1000)
+            .filter(|i| assign(&experiments, "claude-sonnet-4-5", "claude_code", &format!("s{}", i)).unwrap().arm == ARM_VARIANT)
+            .count();
+        assert!((400..600).contains(&variants), "{}", variants);
+    }
+
+    fn log(tag: &str, status: u16, duration: u64, tokens: (u32, u32), body: Value) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status,
+            duration,
+            model: None,
+            mapped_model: None,
+            account_email: None,
+            error: None,
+            request_body: Some(body.to_string()),
+            response_body: None,
+            input_tokens: Some(tokens.0),
+            output_tokens: Some(tokens.1),
+            thinking_tokens: None,
+            client: None,
+            experiment: Some(tag.to_string()),
+            session_id: None,
+            timing: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_groups_by_arm() {
+        let tool_turn = json!({ "messages": [
+            { "role": "user", "content": [{ "type": "tool_result", "tool_use_id": "old", "is_error": true, "content": "x" }] },
+            { "role": "assistant", "content": "..." },
+            { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "a", "content": "ok" },
+                { "type": "tool_result", "tool_use_id": "b", "is_error": true, "content": "boom" }
+            ] }
+        ]});
+        let logs = vec![
+            log("exp:control", 200, 100, (10, 5), json!({ "messages": [{ "role": "user", "content": "hi" }] })),
+            log("exp:control", 500, 300, (10, 0), json!({})),
+            log("exp:variant", 200, 50, (10, 20), tool_turn),
+        ];
+        let report = summarize(&logs);
+        assert_eq!(report.len(), 2);
+
+        let control = &report[0];
+        assert_eq!((control.arm.as_str(), control.requests, control.errors), ("control", 2, 1));
+        assert_eq!(control.avg_latency_ms, 200.0);
+        assert_eq!(control.p95_latency_ms, 300);
+        assert_eq!(control.avg_output_tokens, 2.5);
+        assert!(control.tool_success_rate.is_none());
+
+        let variant = &report[1];
+        assert_eq!((variant.tool_results, variant.tool_errors), (2, 1));
+        assert_eq!(variant.tool_success_rate, Some(0.5));
+    }
+}
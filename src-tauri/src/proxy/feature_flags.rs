@@ -0,0 +1,441 @@
+// 实验性功能开关注册表 (Feature Flag Registry)
+// 过去每个实验性行为都要单独加配置字段、默认值函数、热更新与设置界面。这里集中列出所有开关
+// (类型 / 默认值 / 说明 / 影响)：
+// - 开关条目由 ExperimentalConfig 的默认值自动生成：顶层标量字段 (enable_signature_cache 等) 与
+//   嵌套配置中的标量字段 (capability_probe.enabled、recitation_retry.temperature_bump 等) 各为一个开关，
+//   值直接读写对应字段，新增配置字段无需再登记
+// - 没有对应字段的开关在 STORED_FLAGS 中登记，值持久化在 ExperimentalConfig.flags 中
+// 开关的当前值始终从 AppState.experimental 中的配置解析 (enabled)，桌面端通过
+// list_feature_flags / set_feature_flag 命令列出并修改开关。
+
+use crate::proxy::config::ExperimentalConfig;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+/// 开关值的类型
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagType {
+    Bool,
+    Integer,
+    Float,
+    String,
+}
+
+impl FlagType {
+    fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(_) => Some(FlagType::Bool),
+            Value::Number(n) if n.is_f64() => Some(FlagType::Float),
+            Value::Number(_) => Some(FlagType::Integer),
+            Value::String(_) => Some(FlagType::String),
+            _ => None,
+        }
+    }
+}
+
+/// 注册表中的一个开关
+#[derive(Debug)]
+pub struct FeatureFlag {
+    /// 嵌套配置以 "." 连接 (如 capability_probe.enabled)
+    pub key: String,
+    pub flag_type: FlagType,
+    pub default: Value,
+    /// 开关是什么
+    pub description: &'static str,
+    /// 开启 / 修改后的影响
+    pub effect: &'static str,
+    /// 值保存在 ExperimentalConfig 的对应字段中 (否则保存在 flags 中)
+    field: bool,
+}
+
+impl FeatureFlag {
+    /// 校验并规范化值 (接受字符串形式的布尔值 / 数字，便于前端输入框直接提交)
+    pub fn coerce(&self, value: &Value) -> Result<Value, String> {
+        let text = value.as_str().map(str::trim);
+        let coerced = match self.flag_type {
+            FlagType::Bool => value.as_bool().or_else(|| text.and_then(|t| t.parse().ok())).map(Value::from),
+            FlagType::Integer => value
+                .as_i64()
+                .or_else(|| value.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64))
+                .or_else(|| text.and_then(|t| t.parse().ok()))
+                .map(Value::from),
+            FlagType::Float => value.as_f64().or_else(|| text.and_then(|t| t.parse().ok())).map(Value::from),
+            FlagType::String => value.as_str().map(Value::from),
+        };
+        coerced.ok_or_else(|| format!("Feature flag '{}' expects a {:?} value, got {}", self.key, self.flag_type, value))
+    }
+
+    fn pointer(&self) -> String {
+        format!("/{}", self.key.replace('.', "/"))
+    }
+}
+
+/// 没有对应配置字段的布尔开关 (值保存在 ExperimentalConfig.flags 中)
+struct StoredFlag {
+    key: &'static str,
+    default: bool,
+    description: &'static str,
+    effect: &'static str,
+}
+
+const STORED_FLAGS: &[StoredFlag] = &[StoredFlag {
+    key: "log_transformed_requests",
+    default: false,
+    description: "Log transformed upstream request bodies",
+    effect: "Logs the converted Gemini request body at info level (normally debug only) for troubleshooting mapping issues",
+}];
+
+/// 不作为开关的字段 (映射表 / 开关值本身)
+const SKIPPED_FIELDS: &[&str] = &["flags", "preflight_budget.context_windows"];
+
+/// 开关说明：先按完整 key 匹配，再按所属配置段 (key 的第一段) 匹配
+const DESCRIPTIONS: &[(&str, &str, &str)] = &[
+    (
+        "enable_signature_cache",
+        "Two-layer cache for Claude thinking signatures",
+        "Reserved: the signature cache is currently always active regardless of this flag",
+    ),
+    (
+        "enable_tool_loop_recovery",
+        "Automatic recovery from broken tool loops",
+        "Closes tool-use loops whose thinking signatures were stripped with synthetic messages, preventing \"must start with thinking\" 400s",
+    ),
+    (
+        "enable_cross_model_checks",
+        "Cross-model compatibility checks",
+        "Reserved: not consulted by the request pipeline yet",
+    ),
+    (
+        "enable_usage_scaling",
+        "Context usage scaling",
+        "Reserved: not consulted by the request pipeline yet",
+    ),
+    (
+        "gemini_permissive_validation",
+        "Permissive validation for native Gemini requests",
+        "Structural errors are logged as warnings and forwarded upstream instead of returning 400",
+    ),
+    (
+        "rate_limit_headers",
+        "Rate-limit response headers",
+        "Adds anthropic-ratelimit-* / x-ratelimit-* headers derived from the account pool state",
+    ),
+    (
+        "background_batching",
+        "Background micro-batching",
+        "Merges small background requests that arrive within a short window into one upstream call",
+    ),
+    (
+        "image_safety_precheck",
+        "Image prompt safety pre-check",
+        "Checks image generation prompts with a small model before spending image quota",
+    ),
+    (
+        "image_cache",
+        "Image generation cache",
+        "Returns the recent result for identical image generation requests from a disk LRU",
+    ),
+    (
+        "inline_offload",
+        "Oversized inlineData handling",
+        "Offloads or rejects attachments above the size thresholds before they reach the upstream",
+    ),
+    (
+        "tool_guardrails",
+        "Claude tool declaration guardrails",
+        "Truncates long tool descriptions and caps the number of declared tools",
+    ),
+    (
+        "preflight_budget",
+        "Local token estimate before sending",
+        "Fails fast when the estimated prompt exceeds the model context window",
+    ),
+    (
+        "json_mode",
+        "OpenAI JSON mode validation",
+        "Validates (and optionally repairs) output for response_format json_object / json_schema",
+    ),
+    (
+        "sse_chunking",
+        "Oversized SSE delta splitting",
+        "Splits large streamed deltas into events no larger than the per-protocol limit",
+    ),
+    (
+        "time_context",
+        "Local time context injection",
+        "Adds the local time, timezone and locale to the system prompt",
+    ),
+    (
+        "shadow_traffic",
+        "Shadow traffic",
+        "Mirrors a percentage of requests to another model asynchronously for comparison (consumes quota)",
+    ),
+    (
+        "codex_keep_alive",
+        "Codex stream keep-alive",
+        "Sends empty events while the upstream is silent and extends the streaming timeout",
+    ),
+    (
+        "response_compression",
+        "Response compression",
+        "Compresses JSON / SSE responses when the client sends Accept-Encoding",
+    ),
+    (
+        "idle_warmup",
+        "Idle warm-up",
+        "Refreshes tokens and reconnects to the upstream after long idle periods or system sleep",
+    ),
+    (
+        "mid_stream_failover",
+        "Mid-stream failover",
+        "Continues a broken upstream stream on another account so the client sees one stream",
+    ),
+    (
+        "admission",
+        "Admission control",
+        "Limits concurrent forwarded requests and queues the rest by X-Priority",
+    ),
+    (
+        "text_sanitize",
+        "Output text sanitization",
+        "Normalizes smart quotes, strips zero-width characters and [undefined] markers in responses",
+    ),
+    (
+        "loop_detection",
+        "Agent loop detection",
+        "Detects repeated tool calls / prompts and excessive request rates per session",
+    ),
+    (
+        "usage_heartbeat",
+        "Streaming usage heartbeat",
+        "Periodically reports generated token counts during long streams",
+    ),
+    (
+        "offline_detection",
+        "Offline detection",
+        "Probes upstream connectivity and fails fast while offline instead of waiting for timeouts",
+    ),
+    (
+        "ui_events",
+        "Monitor UI event batching",
+        "Batches and rate-limits request events pushed to the desktop UI",
+    ),
+    (
+        "organization",
+        "Anthropic organization endpoint stub",
+        "Answers organization / usage queries some enterprise SDKs probe on startup",
+    ),
+    (
+        "capability_probe",
+        "Model capability probing",
+        "Probes unknown target models for thinking / tools / systemInstruction support (costs quota per account)",
+    ),
+    (
+        "recitation_retry",
+        "Recitation retry",
+        "Retries once with a higher temperature and a hint when the upstream stops with RECITATION",
+    ),
+];
+
+fn describe(key: &str) -> (&'static str, &'static str) {
+    let section = key.split('.').next().unwrap_or(key);
+    DESCRIPTIONS
+        .iter()
+        .find(|(k, _, _)| *k == key)
+        .or_else(|| DESCRIPTIONS.iter().find(|(k, _, _)| *k == section))
+        .map(|(_, description, effect)| (*description, *effect))
+        .unwrap_or(("", ""))
+}
+
+/// 递归收集配置中的标量字段 (列表与未设置的可选值不作为开关)
+fn collect_fields(prefix: &str, value: &Value, out: &mut Vec<FeatureFlag>) {
+    let Some(fields) = value.as_object() else {
+        return;
+    };
+    for (name, default) in fields {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        if SKIPPED_FIELDS.contains(&key.as_str()) {
+            continue;
+        }
+        if default.is_object() {
+            collect_fields(&key, default, out);
+            continue;
+        }
+        let Some(flag_type) = FlagType::of(default) else {
+            continue;
+        };
+        let (description, effect) = describe(&key);
+        out.push(FeatureFlag {
+            key,
+            flag_type,
+            default: default.clone(),
+            description,
+            effect,
+            field: true,
+        });
+    }
+}
+
+/// 所有开关 (由 ExperimentalConfig 默认值生成，仅包含类型与默认值，不保存当前值)
+pub fn registry() -> &'static [FeatureFlag] {
+    static REGISTRY: OnceLock<Vec<FeatureFlag>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut flags = Vec::new();
+        let defaults = serde_json::to_value(ExperimentalConfig::default()).unwrap_or_default();
+        collect_fields("", &defaults, &mut flags);
+        flags.extend(STORED_FLAGS.iter().map(|flag| FeatureFlag {
+            key: flag.key.to_string(),
+            flag_type: FlagType::Bool,
+            default: Value::Bool(flag.default),
+            description: flag.description,
+            effect: flag.effect,
+            field: false,
+        }));
+        flags
+    })
+}
+
+pub fn lookup(key: &str) -> Option<&'static FeatureFlag> {
+    registry().iter().find(|f| f.key == key)
+}
+
+/// 开关当前值的完整描述 (桌面端列表)
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagInfo {
+    pub key: &'static str,
+    #[serde(rename = "type")]
+    pub flag_type: FlagType,
+    pub default: Value,
+    pub value: Value,
+    pub description: &'static str,
+    pub effect: &'static str,
+    /// 当前值与默认值不同
+    pub overridden: bool,
+}
+
+/// 解析单个开关的当前值 (未设置或类型不符时取默认值)
+fn resolve(flag: &FeatureFlag, config: &ExperimentalConfig, fields: &Value) -> Value {
+    let raw = if flag.field { fields.pointer(&flag.pointer()) } else { config.flags.get(&flag.key) };
+    raw.and_then(|v| flag.coerce(v).ok()).unwrap_or_else(|| flag.default.clone())
+}
+
+pub fn list(config: &ExperimentalConfig) -> Vec<FlagInfo> {
+    let fields = serde_json::to_value(config).unwrap_or_default();
+    registry()
+        .iter()
+        .map(|flag| {
+            let value = resolve(flag, config, &fields);
+            FlagInfo {
+                key: &flag.key,
+                flag_type: flag.flag_type,
+                default: flag.default.clone(),
+                overridden: value != flag.default,
+                value,
+                description: flag.description,
+                effect: flag.effect,
+            }
+        })
+        .collect()
+}
+
+/// 修改配置中的开关值
+pub fn set(config: &mut ExperimentalConfig, key: &str, value: &Value) -> Result<(), String> {
+    let flag = lookup(key).ok_or_else(|| format!("Unknown feature flag '{}'", key))?;
+    let value = flag.coerce(value)?;
+    if flag.field {
+        let mut fields = serde_json::to_value(&*config).map_err(|e| e.to_string())?;
+        let slot = fields
+            .pointer_mut(&flag.pointer())
+            .ok_or_else(|| format!("Feature flag '{}' has no matching config field", key))?;
+        *slot = value;
+        *config = serde_json::from_value(fields).map_err(|e| format!("Invalid value for feature flag '{}': {}", key, e))?;
+    } else if value == flag.default {
+        config.flags.remove(key);
+    } else {
+        config.flags.insert(key.to_string(), value);
+    }
+    Ok(())
+}
+
+/// 布尔开关在给定配置 (通常为 AppState.experimental) 下的当前值 (未登记的开关为 false)
+pub fn enabled(config: &ExperimentalConfig, key: &str) -> bool {
+    let Some(flag) = lookup(key) else {
+        return false;
+    };
+    let fields = if flag.field { serde_json::to_value(config).unwrap_or_default() } else { Value::Null };
+    resolve(flag, config, &fields).as_bool().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_registry_keys_are_unique_and_defaults_match_config() {
+        let mut keys: Vec<_> = registry().iter().map(|f| f.key.as_str()).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), registry().len());
+        // 默认配置下所有开关均为默认值
+        assert!(list(&ExperimentalConfig::default()).iter().all(|f| !f.overridden));
+        // 每个开关都有说明
+        assert!(registry().iter().all(|f| !f.description.is_empty()), "missing description");
+    }
+
+    #[test]
+    fn test_nested_configs_are_registered() {
+        let probe = lookup("capability_probe.enabled").unwrap();
+        assert_eq!((probe.flag_type, &probe.default), (FlagType::Bool, &json!(false)));
+        assert_eq!(lookup("recitation_retry.temperature_bump").unwrap().flag_type, FlagType::Float);
+        assert_eq!(lookup("offline_detection.probe_interval_secs").unwrap().flag_type, FlagType::Integer);
+        assert_eq!(lookup("recitation_retry.instruction").unwrap().flag_type, FlagType::String);
+        assert!(lookup("text_sanitize.undefined_markers").is_some());
+        // 映射表与列表不是开关
+        assert!(registry().iter().all(|f| !f.key.starts_with("preflight_budget.context_windows")));
+        assert!(lookup("non_streaming_models").is_none());
+        assert!(lookup("flags").is_none());
+    }
+
+    #[test]
+    fn test_set_writes_legacy_fields_and_flag_map() {
+        let mut config = ExperimentalConfig::default();
+        set(&mut config, "enable_usage_scaling", &json!("false")).unwrap();
+        assert!(!config.enable_usage_scaling);
+        assert!(config.flags.is_empty());
+
+        set(&mut config, "log_transformed_requests", &json!(true)).unwrap();
+        assert_eq!(config.flags.get("log_transformed_requests"), Some(&json!(true)));
+        let info = list(&config).into_iter().find(|f| f.key == "log_transformed_requests").unwrap();
+        assert!(info.overridden);
+        assert!(enabled(&config, "log_transformed_requests"));
+
+        // 恢复默认值时从 flags 中移除
+        set(&mut config, "log_transformed_requests", &json!(false)).unwrap();
+        assert!(config.flags.is_empty());
+        assert!(!enabled(&config, "log_transformed_requests"));
+
+        assert!(set(&mut config, "log_transformed_requests", &json!(3)).is_err());
+        assert!(set(&mut config, "no_such_flag", &json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_set_writes_nested_fields() {
+        let mut config = ExperimentalConfig::default();
+        set(&mut config, "capability_probe.enabled", &json!("true")).unwrap();
+        assert!(config.capability_probe.enabled);
+        assert!(enabled(&config, "capability_probe.enabled"));
+        set(&mut config, "recitation_retry.temperature_bump", &json!(0.5)).unwrap();
+        assert_eq!(config.recitation_retry.temperature_bump, 0.5);
+        assert!(config.flags.is_empty());
+
+        // 超出字段类型范围的值被拒绝，配置保持不变
+        assert!(set(&mut config, "offline_detection.probe_interval_secs", &json!(-1)).is_err());
+        assert_eq!(
+            config.offline_detection.probe_interval_secs,
+            ExperimentalConfig::default().offline_detection.probe_interval_secs
+        );
+    }
+}
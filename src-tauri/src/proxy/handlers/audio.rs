@@ -11,6 +11,7 @@ use uuid::Uuid;
 use crate::proxy::{
     audio::AudioProcessor,
     server::AppState,
+    upload::SpooledUpload,
 };
 
 /// 处理音频转录请求 (OpenAI Whisper API 兼容)
@@ -24,17 +25,20 @@ pub async fn handle_audio_transcription(
     let mut prompt = "Generate a transcript of the speech.".to_string();
 
     // 1. 解析 multipart/form-data
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        (StatusCode::BAD_REQUEST, format!("解析表单失败: {}", e))
-    })? {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| crate::proxy::upload::multipart_error(e, &state.upload_limits))?
+    {
         let name = field.name().unwrap_or("").to_string();
 
         match name.as_str() {
             "file" => {
                 filename = field.file_name().map(|s| s.to_string());
-                audio_data = Some(field.bytes().await.map_err(|e| {
-                    (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e))
-                })?.to_vec());
+                let upload = SpooledUpload::from_field(field, &state.upload_limits).await?;
+                audio_data = Some(upload.into_bytes().await.map_err(|e| {
+                    (StatusCode::INTERNAL_SERVER_ERROR, format!("读取文件失败: {}", e))
+                })?);
             }
             "model" => {
                 model = field.text().await.unwrap_or(model);
@@ -101,7 +105,7 @@ pub async fn handle_audio_transcription(
     // 6. 获取 Token 和上游客户端
     let token_manager = state.token_manager;
     let (access_token, project_id, email) = token_manager
-        .get_token("text", false, None)
+        .get_token("text", "text", false, None, None)
         .await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
 
@@ -0,0 +1,256 @@
+// 协议自动识别 (兼容路由)
+// 部分客户端只能配置一个固定路径的 base URL。未匹配任何路由的 POST /v1/* 请求按请求体结构推断协议：
+// contents → Gemini，messages + 顶层 system / Anthropic 特有字段 → Anthropic，messages → OpenAI Chat，
+// input / prompt → OpenAI Completions / Responses，并分发到对应的 handler，推断结果写入日志。
+// embeddings / moderations 等非生成类接口同样携带 model + input，不做推断，避免误消耗生成配额。
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use crate::proxy::server::AppState;
+
+/// 推断出的目标协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferredProtocol {
+    Anthropic,
+    OpenaiChat,
+    OpenaiCompletions,
+    Gemini,
+}
+
+impl InferredProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InferredProtocol::Anthropic => "anthropic",
+            InferredProtocol::OpenaiChat => "openai-chat",
+            InferredProtocol::OpenaiCompletions => "openai-completions",
+            InferredProtocol::Gemini => "gemini",
+        }
+    }
+}
+
+/// Anthropic 独有的内容块类型 (OpenAI 消息中不会出现)
+const ANTHROPIC_BLOCK_TYPES: &[&str] = &["tool_use", "tool_result", "thinking", "redacted_thinking", "document"];
+
+fn has_anthropic_blocks(messages: &[Value]) -> bool {
+    messages.iter().any(|m| {
+        m.get("content")
+            .and_then(|c| c.as_array())
+            .is_some_and(|blocks| {
+                blocks.iter().any(|b| {
+                    let block_type = b.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+                    // Anthropic 图片使用 source 字段，OpenAI 使用 image_url
+                    ANTHROPIC_BLOCK_TYPES.contains(&block_type) || (block_type == "image" && b.get("source").is_some())
+                })
+            })
+    })
+}
+
+/// 非生成类 OpenAI 接口 (请求体与 Responses API 相似，不能按结构推断)
+const NON_GENERATION_PATHS: &[&str] = &[
+    "/v1/embeddings",
+    "/v1/moderations",
+    "/v1/audio",
+    "/v1/images",
+    "/v1/files",
+    "/v1/uploads",
+    "/v1/batches",
+    "/v1/fine_tuning",
+    "/v1/vector_stores",
+];
+
+fn is_non_generation_path(path: &str) -> bool {
+    NON_GENERATION_PATHS
+        .iter()
+        .any(|p| path == *p || path.strip_prefix(p).is_some_and(|rest| rest.starts_with('/')))
+}
+
+/// Responses API 特有字段 (仅有 model + input 时与 embeddings / moderations 无法区分)
+const RESPONSES_FIELDS: &[&str] = &[
+    "instructions",
+    "tools",
+    "stream",
+    "reasoning",
+    "previous_response_id",
+    "max_output_tokens",
+];
+
+/// input 为消息 / 条目数组 (带 role 或 type) 时也可确定是 Responses API
+fn has_responses_items(input: &Value) -> bool {
+    input
+        .as_array()
+        .is_some_and(|items| items.iter().any(|i| i.get("role").is_some() || i.get("type").is_some()))
+}
+
+/// 按请求头与请求体结构推断协议，返回 (协议, 推断依据)
+pub fn infer_protocol(headers: &HeaderMap, body: &Value) -> Option<(InferredProtocol, &'static str)> {
+    if body.get("contents").is_some() {
+        return Some((InferredProtocol::Gemini, "contents"));
+    }
+    if let Some(messages) = body.get("messages").and_then(|m| m.as_array()) {
+        if headers.contains_key("anthropic-version") {
+            return Some((InferredProtocol::Anthropic, "messages + anthropic-version header"));
+        }
+        if body.get("system").is_some() {
+            return Some((InferredProtocol::Anthropic, "messages + top-level system"));
+        }
+        if has_anthropic_blocks(messages) {
+            return Some((InferredProtocol::Anthropic, "messages with Anthropic content blocks"));
+        }
+        if body.get("thinking").is_some() || body.get("stop_sequences").is_some() {
+            return Some((InferredProtocol::Anthropic, "messages + Anthropic parameters"));
+        }
+        return Some((InferredProtocol::OpenaiChat, "messages"));
+    }
+    if let Some(input) = body.get("input") {
+        if RESPONSES_FIELDS.iter().any(|f| body.get(*f).is_some()) || has_responses_items(input) {
+            return Some((InferredProtocol::OpenaiCompletions, "input (Responses API)"));
+        }
+    }
+    if body.get("prompt").is_some() {
+        return Some((InferredProtocol::OpenaiCompletions, "prompt"));
+    }
+    None
+}
+
+fn not_found(message: String) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "error": {
+                "type": "not_found_error",
+                "message": message,
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 未匹配任何路由的请求 (挂载为主端口 Router 的 fallback)
+pub async fn handle_auto_protocol(
+    State(state): State<AppState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    if method != Method::POST || !path.starts_with("/v1/") {
+        return not_found(format!("No route for {} {}", method, path));
+    }
+    if is_non_generation_path(&path) {
+        return not_found(format!("No route for POST {} (endpoint is not supported by this proxy)", path));
+    }
+    let Ok(body) = serde_json::from_slice::<Value>(&body) else {
+        return not_found(format!("No route for POST {} (body is not JSON, cannot infer protocol)", path));
+    };
+    let Some((protocol, reason)) = infer_protocol(&headers, &body) else {
+        return not_found(format!(
+            "No route for POST {} and the request body matches no known protocol (expected messages, contents, input or prompt)",
+            path
+        ));
+    };
+    tracing::info!("[Auto-Protocol] POST {} inferred as {} ({})", path, protocol.as_str(), reason);
+
+    use crate::proxy::handlers;
+    match protocol {
+        InferredProtocol::Anthropic => {
+            handlers::claude::handle_messages(State(state), headers, axum::extract::RawQuery(uri.query().map(|q| q.to_string())), Json(body)).await
+        }
+        InferredProtocol::OpenaiChat => handlers::openai::handle_chat_completions(State(state), headers, Json(body))
+            .await
+            .into_response(),
+        InferredProtocol::OpenaiCompletions => handlers::openai::handle_completions(State(state), headers, Json(body))
+            .await
+            .into_response(),
+        InferredProtocol::Gemini => {
+            // Gemini 的模型与方法原本位于路径中，兼容路由从请求体 model / stream 字段读取
+            let Some(model) = body.get("model").and_then(|m| m.as_str()).map(|m| m.trim_start_matches("models/").to_string()) else {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": {
+                            "code": 400,
+                            "status": "INVALID_ARGUMENT",
+                            "message": "Gemini-style request on a compatibility path must include a 'model' field",
+                        }
+                    })),
+                )
+                    .into_response();
+            };
+            let stream = body.get("stream").and_then(|s| s.as_bool()).unwrap_or(false)
+                || uri.query().is_some_and(|q| q.contains("alt=sse"));
+            let action = if stream { "streamGenerateContent" } else { "generateContent" };
+            let mut body = body;
+            if let Some(obj) = body.as_object_mut() {
+                obj.remove("model");
+                obj.remove("stream");
+            }
+            handlers::gemini::handle_generate(State(state), Path(format!("{}:{}", model, action)), headers, Json(body))
+                .await
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(body: Value) -> Option<InferredProtocol> {
+        infer_protocol(&HeaderMap::new(), &body).map(|(p, _)| p)
+    }
+
+    #[test]
+    fn test_infer_protocol_from_body_shape() {
+        assert_eq!(infer(json!({"model": "gemini-2.5-flash", "contents": []})), Some(InferredProtocol::Gemini));
+        assert_eq!(
+            infer(json!({"model": "claude-sonnet-4-5", "system": "be brief", "messages": []})),
+            Some(InferredProtocol::Anthropic)
+        );
+        assert_eq!(
+            infer(json!({"model": "gpt-4o", "messages": [{"role": "system", "content": "be brief"}]})),
+            Some(InferredProtocol::OpenaiChat)
+        );
+        assert_eq!(
+            infer(json!({"messages": [{"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]}]})),
+            Some(InferredProtocol::Anthropic)
+        );
+        assert_eq!(infer(json!({"model": "gpt-4o", "prompt": "Once upon"})), Some(InferredProtocol::OpenaiCompletions));
+        assert_eq!(
+            infer(json!({"model": "gpt-5", "input": "hi", "instructions": "be brief"})),
+            Some(InferredProtocol::OpenaiCompletions)
+        );
+        assert_eq!(
+            infer(json!({"model": "gpt-5", "input": [{"role": "user", "content": "hi"}]})),
+            Some(InferredProtocol::OpenaiCompletions)
+        );
+        // 只有 model + input 的请求体 (embeddings / moderations) 不推断为生成请求
+        assert_eq!(infer(json!({"model": "text-embedding-3-small", "input": "hi"})), None);
+        assert_eq!(infer(json!({"model": "text-embedding-3-small", "input": ["a", "b"]})), None);
+        assert_eq!(infer(json!({"foo": 1})), None);
+    }
+
+    #[test]
+    fn test_non_generation_paths() {
+        assert!(is_non_generation_path("/v1/embeddings"));
+        assert!(is_non_generation_path("/v1/moderations"));
+        assert!(is_non_generation_path("/v1/audio/speech"));
+        assert!(!is_non_generation_path("/v1/embeddingsx"));
+        assert!(!is_non_generation_path("/v1/custom/generate"));
+    }
+
+    #[test]
+    fn test_anthropic_version_header_wins() {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
+        let inferred = infer_protocol(&headers, &json!({"messages": [{"role": "user", "content": "hi"}]}));
+        assert_eq!(inferred.map(|(p, _)| p), Some(InferredProtocol::Anthropic));
+    }
+}
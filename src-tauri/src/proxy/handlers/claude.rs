@@ -2,7 +2,7 @@
 
 use axum::{
     body::Body,
-    extract::{Json, State},
+    extract::{Json, RawQuery, State},
     http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
@@ -13,9 +13,16 @@ use tokio::time::{sleep, Duration};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::claude::{
-    transform_claude_request_in, transform_response, create_claude_sse_stream, ClaudeRequest,
-    close_tool_loop_for_thinking,
+    transform_claude_request_in, transform_claude_request_in_with, transform_response, create_claude_sse_stream, ClaudeRequest,
+    close_tool_loop_for_thinking, build_error_event,
 };
+use crate::proxy::common::routing_info::{RoutingInfo, StreamProtocol};
+use crate::proxy::common::timing::elapsed_ms;
+use crate::proxy::common::sse_split::split_stream;
+use crate::proxy::common::usage_heartbeat::UsageHeartbeat;
+use crate::proxy::mappers::upstream_error::{translate as translate_upstream_error, TranslatedError};
+use crate::proxy::mappers::malformed_call::EarlyFinish;
+use crate::proxy::background_batch::{BatchAnswer, BatchOutcome, BatchOutput};
 use crate::proxy::server::AppState;
 use axum::http::HeaderMap;
 use std::sync::atomic::Ordering;
@@ -103,7 +110,7 @@ fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>) {
                                      Content length: {} chars",
                                     thinking.len()
                                 );
-                                new_blocks.push(ContentBlock::Text { text: thinking.clone() });
+                                new_blocks.push(ContentBlock::Text { text: thinking.clone(), citations: None });
                             } else {
                                 tracing::debug!("[Claude-Handler] Dropping empty thinking block with invalid signature");
                             }
@@ -121,7 +128,8 @@ fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>) {
             // 如果过滤后为空,添加一个空文本块以保持消息有效
             if blocks.is_empty() {
                 blocks.push(ContentBlock::Text { 
-                    text: String::new() 
+                    text: String::new(),
+                    citations: None,
                 });
             }
         }
@@ -287,6 +295,22 @@ async fn apply_retry_strategy(
     }
 }
 
+/// 纠正重试后仍为 MALFORMED_FUNCTION_CALL 时返回的结构化错误
+fn malformed_call_response(call: &crate::proxy::mappers::malformed_call::MalformedCall) -> Response {
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": call.error_message(),
+                "details": call.details()
+            }
+        })),
+    )
+        .into_response()
+}
+
 /// 判断是否应该轮换账号
 fn should_rotate_account(status_code: u16) -> bool {
     match status_code {
@@ -307,15 +331,44 @@ fn should_rotate_account(status_code: u16) -> bool {
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(body): Json<Value>,
+    RawQuery(query): RawQuery,
+    Json(mut body): Json<Value>,
+) -> Response {
+    // [NEW] 工具声明护栏：截断超长描述 / 丢弃超出上限的工具，调整结果通过 X-Tool-Guardrails 回显
+    let guardrails = {
+        let experimental = state.experimental.read().await;
+        crate::proxy::mappers::claude::tool_guardrails::apply_tool_guardrails(&mut body, &experimental.tool_guardrails)
+    };
+    // [NEW] anthropic-beta 协商：影响映射行为，并在响应头中回显已接受的 beta
+    let betas = crate::proxy::mappers::claude::betas::AnthropicBetas::from_headers(&headers);
+    // [NEW] token-efficient-tools：去掉同一请求内重复的工具声明，节省量通过 X-Compatibility-Report 回显
+    let tool_dedupe = if betas.has(crate::proxy::mappers::claude::betas::TOKEN_EFFICIENT_TOOLS) {
+        crate::proxy::mappers::claude::tool_dedupe::dedupe_tools(&mut body)
+    } else {
+        Default::default()
+    };
+    // [NEW] 客户端可通过 X-Stream-Mode / ?stream= 覆盖请求体中的 stream 字段
+    let stream_override = crate::proxy::mappers::claude::stream_bridge::stream_override(&headers, query.as_deref());
+    let mut response = handle_messages_inner(state, headers, body, betas.clone(), stream_override).await;
+    betas.apply_to_headers(response.headers_mut());
+    guardrails.apply_to_headers(response.headers_mut());
+    tool_dedupe.apply_to_headers(response.headers_mut());
+    response
+}
+
+async fn handle_messages_inner(
+    state: AppState,
+    headers: HeaderMap,
+    body: Value,
+    betas: crate::proxy::mappers::claude::betas::AnthropicBetas,
+    stream_override: Option<bool>,
 ) -> Response {
     tracing::debug!("handle_messages called. Body JSON len: {}", body.to_string().len());
+    // 本次请求使用的实验配置快照 (重试期间配置热更新不影响已在处理的请求)
+    let experimental = state.experimental.read().await.clone();
     
-    // 生成随机 Trace ID 用户追踪
-    let trace_id: String = rand::Rng::sample_iter(rand::thread_rng(), &rand::distributions::Alphanumeric)
-        .take(6)
-        .map(char::from)
-        .collect::<String>().to_lowercase();
+    // Trace ID: 优先使用客户端的 X-Request-Id / X-Trace-Id，否则随机生成
+    let trace_id = crate::proxy::common::trace_id::resolve(&headers);
         
     // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
     let zai = state.zai.read().await.clone();
@@ -340,6 +393,8 @@ pub async fn handle_messages(
     };
 
     // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
+    // [NEW] metadata.google 扩展字段，合并到最终 Gemini 请求
+    let vendor_extension = crate::proxy::mappers::vendor_extension::from_claude(&body);
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
         Ok(r) => r,
         Err(e) => {
@@ -356,12 +411,22 @@ pub async fn handle_messages(
         }
     };
 
+    request.betas = betas;
+    if let Some(stream) = stream_override {
+        request.stream = stream;
+    }
+
+    // [NEW] redacted_thinking 不发往上游，在本地保留并原样回传 (需在工具循环修复之前提取)
+    let redacted_thinking = crate::proxy::mappers::claude::pending_redacted_thinking(&request.messages);
+    // [NEW] 启用引用的文档，响应中据此生成 char_location 引用
+    let citable_documents = crate::proxy::mappers::claude::citations::collect_documents(&request.messages);
+
     // [CRITICAL FIX] 过滤并修复 Thinking 块签名
     filter_invalid_thinking_blocks(&mut request.messages);
 
     // [New] Recover from broken tool loops (where signatures were stripped)
     // This prevents "Assistant message must start with thinking" errors by closing the loop with synthetic messages
-    if state.experimental.read().await.enable_tool_loop_recovery {
+    if experimental.enable_tool_loop_recovery {
         close_tool_loop_for_thinking(&mut request.messages);
     }
 
@@ -413,7 +478,7 @@ pub async fn handle_messages(
                     // 对于数组，提取所有 Text 块并拼接，忽略 ToolResult
                     arr.iter()
                         .filter_map(|block| match block {
-                            crate::proxy::mappers::claude::models::ContentBlock::Text { text } => Some(text.as_str()),
+                            crate::proxy::mappers::claude::models::ContentBlock::Text { text, .. } => Some(text.as_str()),
                             _ => None,
                         })
                         .collect::<Vec<_>>()
@@ -465,20 +530,13 @@ pub async fn handle_messages(
     debug!("[{}] Message Count: {}", trace_id, request.messages.len());
     debug!("[{}] Has Tools: {}", trace_id, request.tools.is_some());
     debug!("[{}] Has Thinking Config: {}", trace_id, request.thinking.is_some());
-    debug!("[{}] Content Preview: {:.100}...", trace_id, latest_msg);
+    debug!("[{}] Content Preview: {}", trace_id, crate::proxy::common::text_preview::preview(&latest_msg, 100));
     
     // 输出每一条消息的详细信息
     for (idx, msg) in request.messages.iter().enumerate() {
         let content_preview = match &msg.content {
             crate::proxy::mappers::claude::models::MessageContent::String(s) => {
-                let char_count = s.chars().count();
-                if char_count > 200 {
-                    // 【修复】使用 chars().take() 安全截取，避免 UTF-8 字符边界 panic
-                    let preview: String = s.chars().take(200).collect();
-                    format!("{}... (total {} chars)", preview, char_count)
-                } else {
-                    s.clone()
-                }
+                crate::proxy::common::text_preview::preview(s, 200)
             },
             crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
                 format!("[Array with {} blocks]", arr.len())
@@ -491,6 +549,16 @@ pub async fn handle_messages(
     debug!("[{}] Full Claude Request JSON: {}", trace_id, serde_json::to_string_pretty(&request).unwrap_or_default());
     debug!("========== [{}] CLAUDE REQUEST DEBUG END ==========", trace_id);
 
+    // [NEW] 失控循环检测：命中 break 时不再转发上游
+    let loop_config = experimental.loop_detection.clone();
+    if loop_config.enabled {
+        let loop_session = crate::proxy::session_manager::SessionManager::extract_session_id(&request);
+        let signals = crate::proxy::loop_guard::claude_signals(&request, &loop_session);
+        if let Some(kind) = crate::proxy::loop_guard::enforce(&signals, &loop_config, &trace_id).await {
+            return create_loop_break_response(&request, kind);
+        }
+    }
+
     // 1. 获取 会话 ID (已废弃基于内容的哈希，改用 TokenManager 内部的时间窗口锁定)
     let _session_id: Option<&str> = None;
 
@@ -499,21 +567,83 @@ pub async fn handle_messages(
     
     // 3. 准备闭包
     let mut request_for_body = request.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
     let mut last_error = String::new();
+    // [NEW] 最近一次上游错误 (翻译后，原始错误体保留在 debug 字段)
+    let mut last_upstream_error: Option<TranslatedError> = None;
     let mut retried_without_thinking = false;
-    let mut last_email: Option<String> = None;
+    // 路由信息 (账号 / 映射模型 / 尝试次数 / 重试原因)
+    let mut routing = RoutingInfo::new();
+    let expose_routing_info = state.expose_routing_info.load(Ordering::Relaxed);
+    // [NEW] 同一客户端请求的所有重试共享 requestId (而非每次转换时重新生成)，便于上游去重
+    let request_id = crate::proxy::common::trace_id::upstream_request_id("agent", &trace_id);
+    // [NEW] tool_use id 种子：同一请求的重试流共用，trace_id 被客户端复用时不同请求也不会冲突
+    let tool_id_seed = crate::proxy::mappers::claude::tool_ids::request_seed(&trace_id);
+    // [NEW] 超大附件处理器 (重试循环外创建，附件只处理一次)
+    let mut inline_offloader = crate::proxy::common::inline_offload::InlineOffloader::new(experimental.inline_offload.clone());
+    let budget_config = experimental.preflight_budget.clone();
+    let time_context = experimental.time_context.clone();
+    let recitation_config = experimental.recitation_retry.clone();
+    let dummy_thought_models = experimental.dummy_thought_models.clone();
+
+    // [NEW] 后台小请求合并：窗口内的轻量后台任务合并为一次上游请求
+    let batch_config = experimental.background_batching.clone();
+    if batch_config.enabled {
+        if let Some(prompt) = batchable_background_prompt(&request, batch_config.max_prompt_chars) {
+            let (tm, up, template) = (token_manager.clone(), upstream.clone(), request.clone());
+            let outcome = state
+                .background_batcher
+                .submit(prompt, &batch_config, move |combined| {
+                    execute_background_batch(tm, up, template, combined)
+                })
+                .await;
+            match outcome {
+                BatchOutcome::Answered(answer) => {
+                    info!(
+                        "[{}] Background request answered by a batch of {} (account: {})",
+                        trace_id, answer.batch_size, answer.account_email
+                    );
+                    routing.start_attempt(&answer.account_email, BACKGROUND_MODEL_LITE);
+                    return routing.attach(create_batched_response(&request, &answer));
+                }
+                BatchOutcome::Fallback(reason) => {
+                    debug!("[{}] Background batch fallback: {}", trace_id, reason);
+                }
+            }
+        }
+    }
     
-    for attempt in 0..max_attempts {
+    // 上下文裁剪重试不占用账号轮换的尝试次数
+    let mut attempt_limit = max_attempts;
+    let mut next_attempt = 0;
+    let mut retried_with_reduced_context = false;
+    // [NEW] 重试间复用转换结果：仅在请求被修改时 (request_revision 递增) 重新转换
+    let mut transform_cache = crate::proxy::mappers::claude::transform_cache::TransformCache::new();
+    let mut request_revision: u32 = 0;
+    // [NEW] MALFORMED_FUNCTION_CALL 纠正提示：首次出现时携带提示重试一次 (不占用尝试次数)
+    let mut malformed_hint: Option<String> = None;
+    // [NEW] RECITATION 截断：调整温度并携带提示重试一次 (不占用尝试次数)
+    let mut recitation_retried = false;
+    while next_attempt < attempt_limit {
+        let attempt = next_attempt;
+        next_attempt += 1;
         // 2. 模型路由解析
-        let mut mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &request_for_body.model,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = state.resolve_model(&request_for_body.model).await;
+        // [NEW] Claude Code 子代理路由 (独立于主对话映射)
+        let mapped_model = crate::proxy::subagent_routing::route(&state, &request_for_body, mapped_model, &mut routing).await;
+        // 0. 尝试提取 session_id 用于粘性调度 (Phase 2/3)
+        // 使用 SessionManager 生成稳定的会话指纹
+        let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
+        let session_id = Some(session_id_str.as_str());
+        routing.session_id = Some(session_id_str.clone());
+        // [NEW] A/B 路由实验
+        let mut mapped_model = crate::proxy::experiments::route(
+            &state, &headers, &request_for_body.model, mapped_model, &session_id_str, &mut routing,
+        ).await;
         
         // 将 Claude 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = request_for_body.tools.as_ref().map(|list| {
@@ -522,13 +652,16 @@ pub async fn handle_messages(
 
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, &mapped_model, &tools_val);
 
-        // 0. 尝试提取 session_id 用于粘性调度 (Phase 2/3)
-        // 使用 SessionManager 生成稳定的会话指纹
-        let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
-        let session_id = Some(session_id_str.as_str());
+        // [NEW] 配额分组 (用户规则可把请求划入绑定专用账号的分组)
+        let quota_group = token_manager.resolve_quota_group(
+            &config.request_type,
+            &[&request_for_body.model, &mapped_model],
+            &tools_val,
+            &headers,
+        );
 
         let force_rotate_token = attempt > 0;
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, force_rotate_token, session_id).await {
+        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, &quota_group, force_rotate_token, session_id, Some(&config.final_model)).await {
             Ok(t) => t,
             Err(e) => {
                 let safe_message = if e.contains("invalid_grant") {
@@ -549,7 +682,6 @@ pub async fn handle_messages(
             }
         };
 
-        last_email = Some(email.clone());
         info!("✓ Using account: {} (type: {})", email, config.request_type);
         
         
@@ -612,13 +744,51 @@ pub async fn handle_messages(
 
         
         request_with_mapped.model = mapped_model;
+        routing.start_attempt(&email, &request_with_mapped.model);
+        // [NEW] 思考内容输出方式 (Claude 协议未配置时保持 thinking 块)
+        let thinking_output = crate::proxy::client_profile::profile_from_headers(&headers, &*state.client_profiles.read().await)
+            .with_thinking_rules(&experimental.thinking_output_rules, &[&request.model, &request_with_mapped.model], crate::proxy::client_profile::client_id(&headers))
+            .thinking_output
+            .unwrap_or_default();
 
         // 生成 Trace ID (简单用时间戳后缀)
         // let _trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
 
-        let gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id) {
-            Ok(b) => {
-                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+        // [NEW] 内置映射之外的目标模型：已探测时以探测结果取代按模型名的判断，未探测时在后台探测 (按账号缓存)
+        let capabilities = state.model_capabilities.resolve(
+            &experimental.capability_probe,
+            &state.upstream,
+            &access_token,
+            &project_id,
+            &email,
+            &crate::proxy::common::model_mapping::map_claude_model_to_gemini(&request_with_mapped.model),
+        );
+
+        let transform_key = crate::proxy::mappers::claude::transform_cache::TransformKey {
+            revision: request_revision,
+            mapped_model: request_with_mapped.model.clone(),
+            thinking: request_with_mapped.thinking.as_ref().is_some_and(|t| t.type_ == "enabled"),
+            capabilities,
+        };
+        let transform_start = std::time::Instant::now();
+        let transformed = transform_cache.get_or_transform(transform_key, &project_id, || {
+            transform_claude_request_in_with(&request_with_mapped, &project_id, &dummy_thought_models, capabilities.as_ref())
+        });
+        let gemini_body = match transformed {
+            Ok(mut b) => {
+                crate::proxy::common::utils::apply_request_id(&mut b, &request_id);
+                crate::proxy::common::time_context::apply_time_context(&mut b, &time_context);
+                crate::proxy::mappers::vendor_extension::apply(&mut b, vendor_extension.as_ref());
+                if let Some(hint) = &malformed_hint {
+                    crate::proxy::mappers::malformed_call::apply_hint(&mut b, hint);
+                }
+                if recitation_retried {
+                    crate::proxy::mappers::recitation::apply_variation(&mut b, &recitation_config);
+                }
+                if attempt > 0 {
+                    debug!("[{}] Transform cache hits: {}", trace_id, transform_cache.hits());
+                }
+                crate::proxy::common::utils::log_transformed_body(&format!("[{}]", trace_id), &b, &experimental);
                 b
             },
             Err(e) => {
@@ -634,29 +804,114 @@ pub async fn handle_messages(
                 ).into_response();
             }
         };
+
+        // [NEW] 超大附件：图片本地缩放 / 文本拆分，附件合计超限时直接返回 413
+        let gemini_body = match inline_offloader.prepare(gemini_body).await {
+            Ok(b) => b,
+            Err(e) => {
+                return (
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(json!({
+                        "type": "error",
+                        "error": {
+                            "type": "request_too_large",
+                            "message": e
+                        }
+                    }))
+                ).into_response();
+            }
+        };
+
+        routing.timing.transform_ms = Some(elapsed_ms(transform_start));
+
+        // [NEW] 本地 token 预估：明显超出上下文窗口时不再等待上游慢速 400
+        if budget_config.enabled {
+            if let Some((estimate, window)) = crate::proxy::common::token_estimate::check_budget(
+                &gemini_body,
+                &request_with_mapped.model,
+                &budget_config.context_windows,
+            ) {
+                use crate::proxy::config::PreflightBudgetAction;
+                if budget_config.action == PreflightBudgetAction::Trim && !retried_with_reduced_context {
+                    retried_with_reduced_context = true;
+                    let dropped = crate::proxy::mappers::claude::context_trim::drop_oldest_turns(&mut request_for_body.messages);
+                    if dropped > 0 {
+                        request_revision += 1;
+                        tracing::warn!(
+                            "[{}] Estimated {} tokens exceeds {} window of {}, dropped {} oldest message(s)",
+                            trace_id, estimate, request_with_mapped.model, window, dropped
+                        );
+                        routing.record_retry(format!("preflight_trimmed:{}", dropped));
+                        attempt_limit += 1;
+                        continue;
+                    }
+                }
+                if budget_config.action == PreflightBudgetAction::Warn {
+                    tracing::warn!(
+                        "[{}] Estimated {} tokens exceeds {} window of {}, forwarding anyway",
+                        trace_id, estimate, request_with_mapped.model, window
+                    );
+                } else {
+                    tracing::warn!(
+                        "[{}] Rejecting request: estimated {} tokens exceeds {} window of {}",
+                        trace_id, estimate, request_with_mapped.model, window
+                    );
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "type": "error",
+                            "error": {
+                                "type": "invalid_request_error",
+                                "message": format!("prompt is too long: {} tokens > {} maximum", estimate, window)
+                            }
+                        }))
+                    ).into_response();
+                }
+            }
+        }
+
+        // [NEW] 影子流量：首次尝试时按比例镜像到对比模型
+        if attempt == 0 {
+            crate::proxy::shadow::maybe_mirror(&state, &gemini_body, &request.model, &request_with_mapped.model, "/v1/messages").await;
+        }
         
     // 4. 上游调用 - 自动转换逻辑
     let client_wants_stream = request.stream;
     // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
-    let force_stream_internally = !client_wants_stream;
-    let actual_stream = client_wants_stream || force_stream_internally;
+    // 不支持流式的模型例外：走 generateContent，流式客户端收到合成的 SSE
+    let upstream_supports_stream = !experimental.is_non_streaming_model(&request_with_mapped.model);
+    let force_stream_internally = !client_wants_stream && upstream_supports_stream;
+    let actual_stream = upstream_supports_stream;
     
     if force_stream_internally {
         info!("[{}] 🔄 Auto-converting non-stream request to stream for better quota", trace_id);
+    } else if client_wants_stream && !upstream_supports_stream {
+        info!("[{}] 🔄 Model {} does not support streaming, synthesizing SSE from a non-stream call", trace_id, request_with_mapped.model);
     }
     
     let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
     let query = if actual_stream { Some("alt=sse") } else { None };
+    // 实际发往上游的模型 (用于学习账号的模型访问能力)
+    let upstream_model = gemini_body.get("model").and_then(|m| m.as_str()).unwrap_or(&request_with_mapped.model).to_string();
+    // [NEW] 流中途故障转移需要保留请求体用于续写
+    let failover_config = experimental.mid_stream_failover.clone();
+    let failover_body = (actual_stream && failover_config.enabled).then(|| gemini_body.clone());
 
+    let upstream_start = std::time::Instant::now();
     let response = match upstream.call_v1_internal(
         method,
         &access_token,
         gemini_body,
         query
     ).await {
-            Ok(r) => r,
+            Ok(r) => {
+                routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
+                r
+            }
             Err(e) => {
                 last_error = e.clone();
+                last_upstream_error = None;
+                routing.record_retry("network");
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
                 continue;
             }
@@ -671,25 +926,77 @@ pub async fn handle_messages(
             
             // 处理流式响应
             if actual_stream {
-                let stream = response.bytes_stream();
+                // [NEW] 内容出现前即以 MALFORMED_FUNCTION_CALL / RECITATION 结束时，调整请求后重试一次
+                let retry_recitation = recitation_config.enabled && !recitation_retried;
+                let upstream_stream = match crate::proxy::mappers::malformed_call::peek_stream(response.bytes_stream(), retry_recitation).await {
+                    Ok(s) => s,
+                    Err(EarlyFinish::Recitation) => {
+                        tracing::warn!("[{}] Upstream stopped with RECITATION before any content, retrying with variation", trace_id);
+                        routing.record_retry("recitation");
+                        recitation_retried = true;
+                        attempt_limit += 1;
+                        continue;
+                    }
+                    Err(EarlyFinish::Malformed(call)) => {
+                        tracing::warn!("[{}] Upstream returned MALFORMED_FUNCTION_CALL (tool: {:?})", trace_id, call.tool);
+                        routing.record_retry("malformed_function_call");
+                        if malformed_hint.is_none() {
+                            malformed_hint = Some(call.corrective_hint());
+                            attempt_limit += 1;
+                            continue;
+                        }
+                        return routing.attach(malformed_call_response(&call));
+                    }
+                };
+                let upstream_stream = match failover_body {
+                    Some(body) => crate::proxy::upstream::stream_failover::wrap(
+                        upstream_stream,
+                        &failover_config,
+                        crate::proxy::upstream::stream_failover::FailoverRequest {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            quota_group: quota_group.clone(),
+                            session_id: Some(session_id_str.clone()),
+                            model: config.final_model.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                    ),
+                    None => upstream_stream,
+                };
+                // [NEW] 输出文本清理，旁路统计用量，计入账号每日上限
+                let sanitize = experimental.text_sanitize.for_request(&[&request.model, &request_with_mapped.model], crate::proxy::client_profile::client_id(&headers));
+                let upstream_stream = crate::proxy::common::text_sanitize::sanitize_gemini_stream(upstream_stream, sanitize);
+                let stream = token_manager.track_usage_stream(&email, upstream_stream);
                 let gemini_stream = Box::pin(stream);
                 // [v3.3.17] Pass session_id for signature caching
                 let mut claude_stream = create_claude_sse_stream(
                     gemini_stream, 
                     trace_id.clone(), 
                     email.clone(),
-                    Some(session_id_str.clone())
+                    Some(session_id_str.clone()),
+                    redacted_thinking.clone(),
+                    crate::proxy::mappers::claude::citations::CitationTracker::new(citable_documents.clone()),
+                    crate::proxy::client_profile::output_locale(&headers, &*state.client_profiles.read().await),
+                    UsageHeartbeat::from_config(&experimental.usage_heartbeat),
+                    thinking_output,
+                    tool_id_seed.clone(),
                 );
 
                 // [FIX #530/#529] Peek first chunk to detect empty response and allow retry
                 // If the stream is empty or fails immediately, we should retry instead of sending 200 OK + empty body
                 let first_chunk = claude_stream.next().await;
+                // 流式响应的首字节以首个内容块为准 (响应头通常远早于首个 token)
+                routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
 
                 match first_chunk {
                     Some(Ok(bytes)) => {
                         if bytes.is_empty() {
                             tracing::warn!("[{}] Empty first chunk received, treating as Empty Response and retrying...", trace_id);
                             last_error = "Empty response stream (0 bytes)".to_string();
+                            last_upstream_error = None;
+                            routing.record_retry("empty_stream");
                             continue;
                         }
                         
@@ -699,22 +1006,35 @@ pub async fn handle_messages(
                             .chain(stream_rest.map(|result| -> Result<Bytes, std::io::Error> {
                                 match result {
                                     Ok(b) => Ok(b),
-                                    Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+                                    // [FIX] 规范的 error 事件 + message_stop，官方 SDK 可正常解析
+                                    Err(e) => Ok(Bytes::from(
+                                        [
+                                            build_error_event("api_error", &e),
+                                            Bytes::from_static(b"event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"),
+                                        ]
+                                        .concat(),
+                                    )),
                                 }
                             })));
 
                         // 判断客户端期望的格式
                         if client_wants_stream {
                             // 客户端本就要 Stream，直接返回 SSE
-                            return Response::builder()
+                            // [NEW] 拆分超大增量事件
+                            let max_delta = experimental.sse_chunking.limit_for(StreamProtocol::Claude);
+                            let combined_stream = split_stream(combined_stream, StreamProtocol::Claude, max_delta);
+                            let body = if expose_routing_info {
+                                Body::from_stream(routing.inject_into_stream(combined_stream, StreamProtocol::Claude))
+                            } else {
+                                Body::from_stream(combined_stream)
+                            };
+                            return routing.attach(Response::builder()
                                 .status(StatusCode::OK)
                                 .header(header::CONTENT_TYPE, "text/event-stream")
                                 .header(header::CACHE_CONTROL, "no-cache")
                                 .header(header::CONNECTION, "keep-alive")
-                                .header("X-Account-Email", &email)
-                                .header("X-Mapped-Model", &request_with_mapped.model)
-                                .body(Body::from_stream(combined_stream))
-                                .unwrap();
+                                .body(body)
+                                .unwrap());
                         } else {
                             // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                             use crate::proxy::mappers::claude::collect_stream_to_json;
@@ -722,13 +1042,11 @@ pub async fn handle_messages(
                             match collect_stream_to_json(combined_stream).await {
                                 Ok(full_response) => {
                                     info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
-                                    return Response::builder()
+                                    return routing.attach(Response::builder()
                                         .status(StatusCode::OK)
                                         .header(header::CONTENT_TYPE, "application/json")
-                                        .header("X-Account-Email", &email)
-                                        .header("X-Mapped-Model", &request_with_mapped.model)
                                         .body(Body::from(serde_json::to_string(&full_response).unwrap()))
-                                        .unwrap();
+                                        .unwrap());
                                 }
                                 Err(e) => {
                                     return (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response();
@@ -739,11 +1057,15 @@ pub async fn handle_messages(
                     Some(Err(e)) => {
                         tracing::warn!("[{}] Stream error on first chunk: {}, retrying...", trace_id, e);
                         last_error = format!("Stream error: {}", e);
+                        last_upstream_error = None;
+                        routing.record_retry("stream_error");
                         continue;
                     },
                     None => {
                         tracing::warn!("[{}] Stream ended immediately (Empty Response), retrying...", trace_id);
                         last_error = "Empty response stream (None)".to_string();
+                        last_upstream_error = None;
+                        routing.record_retry("empty_stream");
                         continue;
                     }
                 }
@@ -757,12 +1079,37 @@ pub async fn handle_messages(
                 // Debug print
                 if let Ok(text) = String::from_utf8(bytes.to_vec()) {
                     debug!("Upstream Response for Claude request: {}", text);
+                    crate::modules::logger::log_raw_body("upstream response", &text);
                 }
 
-                let gemini_resp: Value = match serde_json::from_slice(&bytes) {
+                let mut gemini_resp: Value = match serde_json::from_slice(&bytes) {
                     Ok(v) => v,
                     Err(e) => return (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response(),
                 };
+                if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&gemini_resp) {
+                    token_manager.record_usage(&email, tokens);
+                }
+                // [NEW] 输出文本清理
+                crate::proxy::common::text_sanitize::sanitize_gemini_response(&mut gemini_resp, &experimental.text_sanitize.for_request(&[&request.model, &request_with_mapped.model], crate::proxy::client_profile::client_id(&headers)));
+
+                if let Some(call) = crate::proxy::mappers::malformed_call::detect(&gemini_resp) {
+                    tracing::warn!("[{}] Upstream returned MALFORMED_FUNCTION_CALL (tool: {:?})", trace_id, call.tool);
+                    routing.record_retry("malformed_function_call");
+                    if malformed_hint.is_none() {
+                        malformed_hint = Some(call.corrective_hint());
+                        attempt_limit += 1;
+                        continue;
+                    }
+                    return routing.attach(malformed_call_response(&call));
+                }
+                // 非流式响应尚未发送任何内容，被 RECITATION 截断时可直接重试
+                if recitation_config.enabled && !recitation_retried && crate::proxy::mappers::recitation::detect(&gemini_resp) {
+                    tracing::warn!("[{}] Upstream stopped with RECITATION, retrying with variation", trace_id);
+                    routing.record_retry("recitation");
+                    recitation_retried = true;
+                    attempt_limit += 1;
+                    continue;
+                }
 
                 // 解包 response 字段（v1internal 格式）
                 let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
@@ -774,10 +1121,22 @@ pub async fn handle_messages(
                 };
                 
                 // 转换
-                let claude_response = match transform_response(&gemini_response) {
+                let locale = crate::proxy::client_profile::output_locale(&headers, &*state.client_profiles.read().await);
+                let mut claude_response = match transform_response(&gemini_response, locale, thinking_output) {
                     Ok(r) => r,
                     Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Transform error: {}", e)).into_response(),
                 };
+                if let (Some(mut tracker), Some(candidate)) = (
+                    crate::proxy::mappers::claude::citations::CitationTracker::new(citable_documents.clone()),
+                    raw.get("candidates").and_then(|c| c.get(0)),
+                ) {
+                    let citations = tracker.observe(candidate);
+                    crate::proxy::mappers::claude::citations::attach_to_content(&mut claude_response.content, citations);
+                }
+                claude_response.content.splice(
+                    0..0,
+                    redacted_thinking.iter().map(|data| ContentBlock::RedactedThinking { data: data.clone() }),
+                );
 
                 // [Optimization] 记录闭环日志：消耗情况
                 let cache_info = if let Some(cached) = claude_response.usage.cache_read_input_tokens {
@@ -795,7 +1154,17 @@ pub async fn handle_messages(
                     cache_info
                 );
 
-                return (StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", request_with_mapped.model.as_str())], Json(claude_response)).into_response();
+                if client_wants_stream {
+                    let sse = crate::proxy::mappers::claude::stream_bridge::response_to_sse(&claude_response);
+                    return routing.attach(Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "text/event-stream")
+                        .header(header::CACHE_CONTROL, "no-cache")
+                        .header(header::CONNECTION, "keep-alive")
+                        .body(Body::from(sse))
+                        .unwrap());
+                }
+                return routing.attach((StatusCode::OK, Json(claude_response)).into_response());
             }
         }
         
@@ -805,8 +1174,11 @@ pub async fn handle_messages(
         
         // 2. 获取错误文本并转移 Response 所有权
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        let upstream_error = translate_upstream_error(status_code, &error_text);
+        last_error = format!("HTTP {}: {}", status_code, upstream_error.message);
+        last_upstream_error = Some(upstream_error.clone());
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
+        crate::modules::logger::log_raw_body(&format!("[{}] upstream error response", trace_id), &error_text);
         
         // 3. 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
         // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
@@ -814,6 +1186,34 @@ pub async fn handle_messages(
             token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
         }
 
+        // [NEW] 账号无权访问该模型 (404 / 模型级 403)：记录后轮换到其他账号
+        if token_manager.record_model_access_error(&email, &upstream_model, status_code, &error_text) {
+            tracing::warn!(
+                "[{}] Account {} cannot serve model {} (HTTP {}), rotating account",
+                trace_id, email, upstream_model, status_code
+            );
+            routing.record_retry(status_code.to_string());
+            continue;
+        }
+
+        // [NEW] 输入超长：丢弃最早的对话轮次后重试一次 (需在通用 INVALID_ARGUMENT 处理之前)
+        if !retried_with_reduced_context
+            && crate::proxy::mappers::claude::context_trim::is_context_length_error(status_code, &error_text)
+        {
+            retried_with_reduced_context = true;
+            let dropped = crate::proxy::mappers::claude::context_trim::drop_oldest_turns(&mut request_for_body.messages);
+            if dropped > 0 {
+                request_revision += 1;
+                tracing::warn!(
+                    "[{}] Context too long for {}, dropped {} oldest message(s) and retrying",
+                    trace_id, request_with_mapped.model, dropped
+                );
+                routing.record_retry(format!("context_trimmed:{}", dropped));
+                attempt_limit += 1;
+                continue;
+            }
+        }
+
         // 4. 处理 400 错误 (Thinking 签名失效)
         // 由于已经主动过滤,这个错误应该很少发生
         if status_code == 400
@@ -839,6 +1239,7 @@ pub async fn handle_messages(
 
             // 完全移除所有 thinking 相关内容
             request_for_body.thinking = None;
+            request_revision += 1;
             
             // 清理历史消息中的所有 Thinking Block
             for msg in request_for_body.messages.iter_mut() {
@@ -865,6 +1266,12 @@ pub async fn handle_messages(
             // 使用统一退避策略
             let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
             if apply_retry_strategy(strategy, attempt, status_code, &trace_id).await {
+                // 确实安排了重试才在错误提示中说明
+                if let Some(upstream_error) = last_upstream_error.as_mut() {
+                    upstream_error.note_retry("retry will use non-thinking mode");
+                    last_error = format!("HTTP {}: {}", status_code, upstream_error.message);
+                }
+                routing.record_retry(status_code.to_string());
                 continue;
             }
         }
@@ -883,31 +1290,53 @@ pub async fn handle_messages(
             if !should_rotate_account(status_code) {
                 debug!("[{}] Keeping same account for status {} (server-side issue)", trace_id, status_code);
             }
+            routing.record_retry(status_code.to_string());
             continue;
         } else {
             // 不可重试的错误，直接返回
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, [("X-Account-Email", email.as_str())], error_text).into_response();
+            return routing.attach((status, Json(upstream_error.to_claude())).into_response());
         }
     }
     
-    if let Some(email) = last_email {
-        (StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], Json(json!({
+    // [NEW] 仅当最后一次失败是限流 / 配额耗尽时返回 429 并告知账号池最早恢复的时间；
+    // 其他失败保留上游状态码 (网络错误 / 空响应为 502)，不提示客户端重试
+    let rate_limited = last_upstream_error.as_ref().is_some_and(|e| e.error_type == "rate_limit_error");
+    if rate_limited {
+        let retry_after = token_manager.rate_limit_state().retry_after_secs();
+        let mut body = json!({
             "type": "error",
             "error": {
                 "type": "overloaded_error",
-                "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
-            }
-        }))).into_response()
-    } else {
-        (StatusCode::TOO_MANY_REQUESTS, Json(json!({
-            "type": "error",
-            "error": {
-                "type": "overloaded_error",
-                "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
+                "message": format!(
+                    "All {} attempts failed. Last error: {}. Please retry after {} seconds.",
+                    max_attempts, last_error, retry_after
+                )
             }
-        }))).into_response()
+        });
+        if let Some(upstream_error) = &last_upstream_error {
+            body["error"]["debug"] = upstream_error.debug();
+        }
+        let response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+        return routing.attach(crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after));
+    }
+
+    let status = last_upstream_error
+        .as_ref()
+        .and_then(|e| StatusCode::from_u16(e.status).ok())
+        .filter(|s| s.is_client_error() || s.is_server_error())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut body = json!({
+        "type": "error",
+        "error": {
+            "type": last_upstream_error.as_ref().map_or("api_error", |e| e.error_type),
+            "message": format!("All {} attempts failed. Last error: {}", max_attempts, last_error)
+        }
+    });
+    if let Some(upstream_error) = &last_upstream_error {
+        body["error"]["debug"] = upstream_error.debug();
     }
+    routing.attach((status, Json(body)).into_response())
 }
 
 /// 列出可用模型
@@ -916,6 +1345,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 
     let model_ids = get_all_dynamic_models(
         &state.custom_mapping,
+        &state.model_aliases,
     ).await;
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
@@ -933,7 +1363,12 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
-/// 计算 tokens (占位符)
+/// count_tokens 转换时使用的占位项目 ID (不影响估算结果)
+const COUNT_TOKENS_PROJECT: &str = "count-tokens";
+
+/// 计算 tokens
+/// 按 /v1/messages 的同一套转换生成上游请求体后估算，计入工具声明、注入的 Antigravity 身份提示词
+/// 与按 Gemini 瓦片规则计算的图片开销，使客户端预算与实际发送的内容一致
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -953,11 +1388,33 @@ pub async fn handle_count_tokens(
         .await;
     }
 
-    Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
-    }))
-    .into_response()
+    let invalid_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "type": "error",
+                "error": {
+                    "type": "invalid_request_error",
+                    "message": message
+                }
+            })),
+        )
+            .into_response()
+    };
+    let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => return invalid_request(format!("Invalid request body: {}", e)),
+    };
+    request.model = state.resolve_model(&request.model).await;
+    let dummy_thought_models = state.experimental.read().await.dummy_thought_models.clone();
+
+    match transform_claude_request_in_with(&request, COUNT_TOKENS_PROJECT, &dummy_thought_models, None) {
+        Ok(gemini_body) => Json(json!({
+            "input_tokens": crate::proxy::common::token_estimate::estimate_request_tokens(&gemini_body)
+        }))
+        .into_response(),
+        Err(e) => invalid_request(format!("Transform error: {}", e)),
+    }
 }
 
 // 移除已失效的简单单元测试，后续将补全完整的集成测试
@@ -1090,7 +1547,7 @@ fn extract_last_user_message_for_detection(request: &ClaudeRequest) -> Option<St
                 crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
                     arr.iter()
                         .filter_map(|block| match block {
-                            crate::proxy::mappers::claude::models::ContentBlock::Text { text } => Some(text.as_str()),
+                            crate::proxy::mappers::claude::models::ContentBlock::Text { text, .. } => Some(text.as_str()),
                             _ => None,
                         })
                         .collect::<Vec<_>>()
@@ -1121,6 +1578,188 @@ fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
     }
 }
 
+// ===== 后台小请求合并 =====
+
+/// 判断请求能否参与后台合并，返回用于合并的纯文本 prompt
+///
+/// 仅限轻量后台任务 (上下文压缩等较大的任务除外)，且内容必须全部为文本
+fn batchable_background_prompt(request: &ClaudeRequest, max_chars: usize) -> Option<String> {
+    let task_type = detect_background_task_type(request)?;
+    if select_background_model(task_type) != BACKGROUND_MODEL_LITE {
+        return None;
+    }
+
+    let mut sections: Vec<String> = Vec::new();
+    match &request.system {
+        Some(crate::proxy::mappers::claude::models::SystemPrompt::String(s)) => sections.push(s.clone()),
+        Some(crate::proxy::mappers::claude::models::SystemPrompt::Array(blocks)) => {
+            sections.extend(blocks.iter().map(|b| b.text.clone()))
+        }
+        None => {}
+    }
+    for msg in &request.messages {
+        let text = match &msg.content {
+            MessageContent::String(s) => s.clone(),
+            MessageContent::Array(blocks) => {
+                let mut parts = Vec::new();
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text { text, .. } => parts.push(text.as_str()),
+                        ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+                        // 图片 / 工具等内容无法安全合并
+                        _ => return None,
+                    }
+                }
+                parts.join("\n")
+            }
+        };
+        let role = if msg.role == "assistant" { "Assistant" } else { "User" };
+        sections.push(format!("{}: {}", role, text));
+    }
+
+    let prompt = sections.join("\n\n");
+    (!prompt.trim().is_empty() && prompt.len() <= max_chars).then_some(prompt)
+}
+
+/// 发送合并后的后台请求 (非流式，固定使用 Lite 模型)
+async fn execute_background_batch(
+    token_manager: std::sync::Arc<crate::proxy::TokenManager>,
+    upstream: std::sync::Arc<crate::proxy::upstream::client::UpstreamClient>,
+    mut template: ClaudeRequest,
+    combined: String,
+) -> Result<BatchOutput, String> {
+    let (access_token, project_id, email) = token_manager
+        .get_token("agent", "agent", false, None, Some(BACKGROUND_MODEL_LITE))
+        .await?;
+
+    template.model = BACKGROUND_MODEL_LITE.to_string();
+    template.messages = vec![Message {
+        role: "user".to_string(),
+        content: MessageContent::String(combined),
+    }];
+    template.system = None;
+    template.tools = None;
+    template.thinking = None;
+    template.stream = false;
+    template.output_config = None;
+    template.output_format = None;
+    template.max_tokens = None;
+
+    let body = transform_claude_request_in(&template, &project_id)?;
+    let response = upstream
+        .call_v1_internal("generateContent", &access_token, body, None)
+        .await?;
+    let status = response.status();
+    let text = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read body: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("HTTP {}: {}", status.as_u16(), text));
+    }
+
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("Parse error: {}", e))?;
+    token_manager.mark_account_success(&email);
+    if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&value) {
+        token_manager.record_usage(&email, tokens);
+    }
+
+    let raw = value.get("response").unwrap_or(&value);
+    let answer = raw["candidates"][0]["content"]["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|p| !p["thought"].as_bool().unwrap_or(false))
+                .filter_map(|p| p["text"].as_str())
+                .collect::<String>()
+        })
+        .unwrap_or_default();
+    let usage = &raw["usageMetadata"];
+
+    Ok(BatchOutput {
+        text: answer,
+        account_email: email,
+        input_tokens: usage["promptTokenCount"].as_u64().unwrap_or(0) as u32,
+        output_tokens: usage["candidatesTokenCount"].as_u64().unwrap_or(0) as u32,
+    })
+}
+
+/// 将合并结果构造为 Claude 响应 (流式 / 非流式)
+fn create_batched_response(request: &ClaudeRequest, answer: &BatchAnswer) -> Response {
+    let message_id = format!("msg_batch_{}", uuid::Uuid::new_v4().simple());
+    let batch_size = answer.batch_size.to_string();
+
+    if request.stream {
+        let events = [
+            ("message_start", json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": request.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": answer.input_tokens, "output_tokens": 0 }
+                }
+            })),
+            ("content_block_start", json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "text", "text": "" }
+            })),
+            ("content_block_delta", json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": answer.text }
+            })),
+            ("content_block_stop", json!({ "type": "content_block_stop", "index": 0 })),
+            ("message_delta", json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                "usage": { "output_tokens": answer.output_tokens }
+            })),
+            ("message_stop", json!({ "type": "message_stop" })),
+        ];
+        let body: String = events
+            .iter()
+            .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+            .collect();
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::CONNECTION, "keep-alive")
+            .header("X-Background-Batched", batch_size)
+            .body(Body::from(body))
+            .unwrap()
+    } else {
+        let response = json!({
+            "id": message_id,
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "text", "text": answer.text }],
+            "model": request.model,
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {
+                "input_tokens": answer.input_tokens,
+                "output_tokens": answer.output_tokens
+            }
+        });
+
+        (
+            StatusCode::OK,
+            [("X-Background-Batched", batch_size)],
+            Json(response),
+        )
+            .into_response()
+    }
+}
+
 // ===== [Issue #467 Fix] Warmup 请求拦截 =====
 
 /// 检测是否为 Warmup 请求
@@ -1146,7 +1785,7 @@ fn is_warmup_request(request: &ClaudeRequest) -> bool {
                 for block in arr {
                     match block {
                         // 检查 text block 是否为 Warmup
-                        crate::proxy::mappers::claude::models::ContentBlock::Text { text } => {
+                        crate::proxy::mappers::claude::models::ContentBlock::Text { text, .. } => {
                             let trimmed = text.trim();
                             if trimmed == "Warmup" || trimmed.starts_with("Warmup\n") {
                                 return true;
@@ -1192,6 +1831,78 @@ fn is_warmup_request(request: &ClaudeRequest) -> bool {
 /// 创建 Warmup 请求的模拟响应
 /// 
 /// 返回一个简单的响应，不消耗上游配额
+/// 失控循环 break：返回一条要求 Agent 停止的助手消息 (end_turn，不含工具调用)
+fn create_loop_break_response(request: &ClaudeRequest, kind: crate::proxy::loop_guard::LoopKind) -> Response {
+    let message_id = format!("msg_loop_{}", uuid::Uuid::new_v4().simple());
+    let text = kind.message();
+
+    if request.stream {
+        let events = [
+            ("message_start", json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": request.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 0, "output_tokens": 0 }
+                }
+            })),
+            ("content_block_start", json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "text", "text": "" }
+            })),
+            ("content_block_delta", json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": text }
+            })),
+            ("content_block_stop", json!({ "type": "content_block_stop", "index": 0 })),
+            ("message_delta", json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                "usage": { "output_tokens": 0 }
+            })),
+            ("message_stop", json!({ "type": "message_stop" })),
+        ];
+        let body: String = events
+            .iter()
+            .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+            .collect();
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::CONNECTION, "keep-alive")
+            .header(crate::proxy::loop_guard::HEADER_LOOP_DETECTED, kind.as_str())
+            .body(Body::from(body))
+            .unwrap()
+    } else {
+        let response = json!({
+            "id": message_id,
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "text", "text": text }],
+            "model": request.model,
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 0, "output_tokens": 0 }
+        });
+
+        (
+            StatusCode::OK,
+            [(crate::proxy::loop_guard::HEADER_LOOP_DETECTED, kind.as_str())],
+            Json(response),
+        )
+            .into_response()
+    }
+}
+
 fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response {
     let model = &request.model;
     let message_id = format!("msg_warmup_{}", chrono::Utc::now().timestamp_millis());
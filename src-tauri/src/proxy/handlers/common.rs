@@ -1,4 +1,4 @@
-use axum::{extract::State, extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{extract::Path, extract::State, extract::Json, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use crate::proxy::server::AppState;
 
@@ -15,10 +15,7 @@ pub async fn handle_detect_model(
     }
 
     // 1. Resolve mapping
-    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-        model_name,
-        &*state.custom_mapping.read().await,
-    );
+    let mapped_model = state.resolve_model(model_name).await;
 
     // 2. Resolve capabilities
     let config = crate::proxy::mappers::common_utils::resolve_request_config(
@@ -34,7 +31,8 @@ pub async fn handle_detect_model(
         "type": config.request_type,
         "features": {
             "has_web_search": config.inject_google_search,
-            "is_image_gen": config.request_type == "image_gen"
+            "is_image_gen": config.request_type == "image_gen",
+            "mixed_output": config.response_modalities.is_some()
         }
     });
 
@@ -46,3 +44,87 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+/// 模型是否为代理可识别的 ID (内置 / 自定义映射 (含通配符) / 别名)
+async fn is_known_model(state: &AppState, model_name: &str) -> bool {
+    let known = crate::proxy::common::model_mapping::get_all_dynamic_models(
+        &state.custom_mapping,
+        &state.model_aliases,
+    ).await;
+    if known.iter().any(|id| id == model_name) {
+        return true;
+    }
+    state.custom_mapping.read().await.keys().any(|pattern| {
+        pattern.contains('*') && crate::proxy::common::model_mapping::wildcard_match(pattern, model_name)
+    })
+}
+
+/// 模型能力元数据 (两种响应格式共用)
+async fn model_capabilities(state: &AppState, model_name: &str, mapped_model: &str) -> Value {
+    let config = crate::proxy::mappers::common_utils::resolve_request_config(model_name, mapped_model, &None);
+    let is_image_gen = config.request_type == "image_gen";
+    let overrides = state.experimental.read().await.preflight_budget.context_windows.clone();
+    // 已探测过的目标模型以探测结果为准
+    let probed = state.model_capabilities.any_account(mapped_model);
+    json!({
+        "context_window": crate::proxy::common::token_estimate::context_window(mapped_model, &overrides),
+        "thinking": !is_image_gen && probed.map_or_else(
+            || mapped_model.contains("thinking") || mapped_model.starts_with("gemini-2.5") || mapped_model.starts_with("gemini-3"),
+            |caps| caps.thinking,
+        ),
+        "vision": true,
+        "tool_use": !is_image_gen && probed.map_or(true, |caps| caps.tools),
+        "web_search": config.inject_google_search,
+        "image_generation": is_image_gen,
+        "probed": probed.is_some(),
+    })
+}
+
+/// 单个模型详情
+/// GET /v1/models/:model
+/// 请求带 `anthropic-version` 头时返回 Anthropic 格式，否则返回 OpenAI 格式；未知模型返回对应格式的 404
+pub async fn handle_get_model(
+    State(state): State<AppState>,
+    Path(model_name): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let anthropic = headers.contains_key("anthropic-version");
+
+    if !is_known_model(&state, &model_name).await {
+        let message = format!("model: {}", model_name);
+        let body = if anthropic {
+            json!({ "type": "error", "error": { "type": "not_found_error", "message": message } })
+        } else {
+            json!({ "error": {
+                "message": format!("The model `{}` does not exist", model_name),
+                "type": "invalid_request_error",
+                "param": "model",
+                "code": "model_not_found"
+            } })
+        };
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    }
+
+    let mapped_model = state.resolve_model(&model_name).await;
+    let capabilities = model_capabilities(&state, &model_name, &mapped_model).await;
+    let body = if anthropic {
+        json!({
+            "type": "model",
+            "id": model_name,
+            "display_name": model_name,
+            "created_at": "2024-02-01T00:00:00Z",
+            "mapped_model": mapped_model,
+            "capabilities": capabilities
+        })
+    } else {
+        json!({
+            "id": model_name,
+            "object": "model",
+            "created": 1706745600,
+            "owned_by": "antigravity",
+            "mapped_model": mapped_model,
+            "capabilities": capabilities
+        })
+    };
+    Json(body).into_response()
+}
@@ -0,0 +1,16 @@
+// 内容过滤审计端点
+// GET /internal/content-filter/audit 返回最近触发的过滤规则 (最新的在前)
+// 审计记录会暴露过滤规则及其他客户端的命中情况，需携带 X-Admin-Key (见 middleware::auth)
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+
+pub async fn handle_content_filter_audit(State(state): State<AppState>) -> impl IntoResponse {
+    let entries = state.content_filter.audit_log();
+    Json(json!({
+        "enabled": state.content_filter.is_enabled().await,
+        "entries": entries,
+    }))
+}
@@ -1,11 +1,14 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Json, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
-use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
+use crate::proxy::mappers::gemini::{wrap_request_with, unwrap_response};
+use crate::proxy::common::routing_info::{RoutingInfo, StreamProtocol};
+use crate::proxy::common::timing::elapsed_ms;
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
+use crate::proxy::mappers::upstream_error::{translate as translate_upstream_error, TranslatedError};
  
 const MAX_RETRY_ATTEMPTS: usize = 3;
  
@@ -14,6 +17,7 @@ const MAX_RETRY_ATTEMPTS: usize = 3;
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 解析 model:method
@@ -31,21 +35,52 @@ pub async fn handle_generate(
     }
     let is_stream = method == "streamGenerateContent";
 
+    // [NEW] 调用上游前校验请求结构，给出精确到字段路径的错误
+    let issues = crate::proxy::mappers::gemini::validation::validate_request(&body);
+    if !issues.is_empty() {
+        if state.experimental.read().await.gemini_permissive_validation {
+            tracing::warn!(
+                "[Gemini] Request validation found {} issue(s), forwarding anyway (permissive mode): {}",
+                issues.len(),
+                issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("; ")
+            );
+        } else {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(crate::proxy::mappers::gemini::validation::to_error_body(&issues)),
+            )
+                .into_response());
+        }
+    }
+
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
     
     let mut last_error = String::new();
-    let mut last_email: Option<String> = None;
+    // [NEW] 最近一次上游错误 (翻译后，原始错误体保留在 debug 字段)
+    let mut last_upstream_error: Option<TranslatedError> = None;
+    let mut routing = RoutingInfo::new();
+    let expose_routing_info = state.expose_routing_info.load(std::sync::atomic::Ordering::Relaxed);
+    // [NEW] 同一客户端请求的所有重试共享 requestId，便于上游去重
+    let trace_id = crate::proxy::common::trace_id::resolve(&headers);
+    let request_id = crate::proxy::common::trace_id::upstream_request_id("agent", &trace_id);
+    // [NEW] 超大附件处理器 (重试循环外创建，附件只处理一次)
+    let mut inline_offloader = crate::proxy::common::inline_offload::InlineOffloader::new(state.experimental.read().await.inline_offload.clone());
+    let time_context = state.experimental.read().await.time_context.clone();
 
     for attempt in 0..max_attempts {
         // 3. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &model_name,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = state.resolve_model(&model_name).await;
+        // 提取 SessionId (粘性指纹)
+        let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+        routing.session_id = Some(session_id.clone());
+        // [NEW] A/B 路由实验
+        let mapped_model = crate::proxy::experiments::route(
+            &state, &headers, &model_name, mapped_model, &session_id, &mut routing,
+        ).await;
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -61,34 +96,64 @@ pub async fn handle_generate(
 
         let config = crate::proxy::mappers::common_utils::resolve_request_config(&model_name, &mapped_model, &tools_val);
 
-        // 4. 获取 Token (使用准确的 request_type)
-        // 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+        let quota_group = token_manager.resolve_quota_group(&config.request_type, &[&model_name, &mapped_model], &tools_val, &headers);
+
+        // 4. 获取 Token (使用准确的 request_type / 配额分组)
 
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, &quota_group, attempt > 0, Some(&session_id), Some(&mapped_model)).await {
             Ok(t) => t,
             Err(e) => {
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
             }
         };
 
-        last_email = Some(email.clone());
+        routing.start_attempt(&email, &mapped_model);
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 5. 包装请求 (project injection)
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        // [NEW] 内置映射之外的目标模型：已探测时按探测结果调整请求，未探测时在后台探测
+        let capabilities = state.model_capabilities.resolve(
+            &state.experimental.read().await.capability_probe,
+            &state.upstream,
+            &access_token,
+            &project_id,
+            &email,
+            &mapped_model,
+        );
+        let transform_start = std::time::Instant::now();
+        let mut wrapped_body = wrap_request_with(&body, &project_id, &mapped_model, capabilities.as_ref());
+        crate::proxy::common::utils::apply_request_id(&mut wrapped_body, &request_id);
+        crate::proxy::common::time_context::apply_time_context(&mut wrapped_body, &time_context);
+        // [NEW] 超大附件：图片本地缩放 / 文本拆分，附件合计超限时直接返回 413
+        let wrapped_body = match inline_offloader.prepare(wrapped_body).await {
+            Ok(b) => b,
+            Err(e) => {
+                let body = json!({ "error": { "code": 413, "message": e, "status": "INVALID_ARGUMENT" } });
+                return Ok(routing.attach((StatusCode::PAYLOAD_TOO_LARGE, Json(body)).into_response()));
+            }
+        };
+        routing.timing.transform_ms = Some(elapsed_ms(transform_start));
+        // [NEW] 影子流量：首次尝试时按比例镜像到对比模型
+        if attempt == 0 {
+            crate::proxy::shadow::maybe_mirror(&state, &wrapped_body, &model_name, &mapped_model, "/v1beta/models").await;
+        }
 
         // 5. 上游调用
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
+        let upstream_start = std::time::Instant::now();
         let response = match upstream
             .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
             .await {
-                Ok(r) => r,
+                Ok(r) => {
+                    routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
+                    r
+                }
                 Err(e) => {
                     last_error = e.clone();
+                    routing.record_retry("network");
                     debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
                     continue;
                 }
@@ -103,7 +168,10 @@ pub async fn handle_generate(
                 use bytes::{Bytes, BytesMut};
                 use futures::StreamExt;
                 
-                let mut response_stream = response.bytes_stream();
+                // [NEW] 输出文本清理，旁路统计用量，计入账号每日上限
+                let sanitize = state.experimental.read().await.text_sanitize.for_request(&[&model_name, &mapped_model], crate::proxy::client_profile::client_id(&headers));
+                let upstream_stream = crate::proxy::common::text_sanitize::sanitize_gemini_stream(response.bytes_stream(), sanitize);
+                let mut response_stream = Box::pin(token_manager.track_usage_stream(&email, upstream_stream));
                 let mut buffer = BytesMut::new();
 
                 let stream = async_stream::stream! {
@@ -159,32 +227,50 @@ pub async fn handle_generate(
                     }
                 };
                 
-                let body = Body::from_stream(stream);
-                return Ok(Response::builder()
+                let max_delta = state.experimental.read().await.sse_chunking.limit_for(StreamProtocol::Gemini);
+                let stream = crate::proxy::common::sse_split::split_stream(stream, StreamProtocol::Gemini, max_delta);
+                let body = if expose_routing_info {
+                    Body::from_stream(routing.inject_into_stream(stream, StreamProtocol::Gemini))
+                } else {
+                    Body::from_stream(stream)
+                };
+                return Ok(routing.attach(Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
-                    .header("X-Account-Email", &email)
-                    .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response()));
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&gemini_resp) {
+                token_manager.record_usage(&email, tokens);
+            }
+            // [NEW] 输出文本清理
+            crate::proxy::common::text_sanitize::sanitize_gemini_response(&mut gemini_resp, &state.experimental.read().await.text_sanitize.for_request(&[&model_name, &mapped_model], crate::proxy::client_profile::client_id(&headers)));
 
             let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            return Ok(routing.attach((StatusCode::OK, Json(unwrapped)).into_response()));
         }
 
         // 处理错误并重试
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        let upstream_error = translate_upstream_error(status_code, &error_text);
+        last_error = format!("HTTP {}: {}", status_code, upstream_error.message);
+        last_upstream_error = Some(upstream_error.clone());
+
+        // [NEW] 账号无权访问该模型 (404 / 模型级 403)：记录后轮换到其他账号
+        if token_manager.record_model_access_error(&email, &mapped_model, status_code, &error_text) {
+            tracing::warn!("Gemini Upstream {}: account {} cannot serve model {}, rotating account", status_code, email, mapped_model);
+            routing.record_retry(status_code.to_string());
+            continue;
+        }
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
@@ -194,23 +280,32 @@ pub async fn handle_generate(
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
             if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
                 error!("Gemini Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.", email, attempt + 1, max_attempts);
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                return Ok(routing.attach((status, Json(upstream_error.to_gemini())).into_response()));
             }
 
             tracing::warn!("Gemini Upstream {} on account {} attempt {}/{}, rotating account", status_code, email, attempt + 1, max_attempts);
+            routing.record_retry(status_code.to_string());
             continue;
         }
  
         // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!("Gemini Upstream non-retryable error {}: {}", status_code, error_text);
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        return Ok(routing.attach((status, Json(upstream_error.to_gemini())).into_response()));
     }
 
-    if let Some(email) = last_email {
-        Ok((StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], format!("All accounts exhausted. Last error: {}", last_error)).into_response())
-    } else {
-        Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+    let retry_after = token_manager.rate_limit_state().retry_after_secs();
+    let mut body = json!({
+        "error": {
+            "code": 429,
+            "message": format!("All accounts exhausted. Last error: {}. Please retry after {} seconds.", last_error, retry_after),
+            "status": "RESOURCE_EXHAUSTED"
+        }
+    });
+    if let Some(upstream_error) = &last_upstream_error {
+        body["error"]["debug"] = upstream_error.debug();
     }
+    let response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+    Ok(routing.attach(crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after)))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl IntoResponse, (StatusCode, String)> {
@@ -219,6 +314,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl In
     // 获取所有动态模型列表（与 /v1/models 一致）
     let model_ids = get_all_dynamic_models(
         &state.custom_mapping,
+        &state.model_aliases,
     ).await;
 
     // 转换为 Gemini API 格式
@@ -249,7 +345,7 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
 
 pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
     let model_group = "gemini";
-    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, false, None).await
+    let (_access_token, _project_id, _) = state.token_manager.get_token(model_group, model_group, false, None, None).await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
     
     Ok(Json(json!({"totalTokens": 0})))
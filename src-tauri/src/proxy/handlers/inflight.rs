@@ -0,0 +1,31 @@
+// 进行中流式请求端点
+// GET    /internal/inflight            列出当前正在输出的流 (trace_id、客户端、模型、账号、持续时间、已发送字节)
+// DELETE /internal/inflight/:trace_id  强制终止该追踪 ID 对应的流 (例如失控循环中的 Agent)
+// 可查看并终止其他客户端的流，需携带 X-Admin-Key (见 middleware::auth)
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+
+pub async fn handle_list_inflight(State(state): State<AppState>) -> impl IntoResponse {
+    let streams = state.inflight.list();
+    Json(json!({
+        "count": streams.len(),
+        "streams": streams,
+    }))
+}
+
+pub async fn handle_kill_inflight(
+    State(state): State<AppState>,
+    Path(trace_id): Path<String>,
+) -> impl IntoResponse {
+    let killed = state.inflight.kill(&trace_id);
+    let status = if killed > 0 { StatusCode::OK } else { StatusCode::NOT_FOUND };
+    (status, Json(json!({ "trace_id": trace_id, "terminated": killed })))
+}
@@ -0,0 +1,27 @@
+// 运行时日志设置端点
+// GET  /internal/logging 返回当前过滤指令与原始请求体日志开关
+// POST /internal/logging {"filter": "info,proxy::mappers=debug", "raw_body_logging": true}
+// 无需重启即可对单个问题请求抓取详细日志；过滤指令中可省略 crate 名。
+// 原始请求体日志会把所有客户端的提示词写入磁盘，需携带 X-Admin-Key (见 middleware::auth)。
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+
+use crate::modules::logger;
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateLogSettings {
+    pub filter: Option<String>,
+    pub raw_body_logging: Option<bool>,
+}
+
+pub async fn handle_get_log_settings() -> impl IntoResponse {
+    Json(logger::log_settings())
+}
+
+pub async fn handle_update_log_settings(Json(update): Json<UpdateLogSettings>) -> impl IntoResponse {
+    match logger::update_log_settings(update.filter.as_deref(), update.raw_body_logging) {
+        Ok(settings) => Json(settings).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
@@ -8,4 +8,10 @@ pub mod mcp;
 pub mod common;
 pub mod audio;  // 音频转录处理器 (PR #311)
 pub mod warmup; // 预热处理器
-
+pub mod scheduler; // 调度决策预演
+pub mod content_filter; // 内容过滤审计
+pub mod status; // 账号池状态与配额预测
+pub mod log_settings; // 运行时日志级别调整
+pub mod inflight; // 进行中的流与强制终止
+pub mod auto_protocol; // 未知路径的协议自动识别
+pub mod organization; // Anthropic 组织 / 用量端点兼容
@@ -1,6 +1,5 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
-use base64::Engine as _; 
+use axum::{extract::Json, extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use bytes::Bytes;
 use serde_json::{json, Value};
 use tracing::{debug, error, info}; // Import Engine trait for encode method
@@ -9,15 +8,149 @@ use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::mappers::gemini::models::{
+    Content, GenerateContentRequest, GenerateContentResponse, GenerationConfig, Part, SafetySetting, V1InternalRequest,
+};
+use crate::proxy::common::image_fit::{closest_aspect_ratio, parse_size, FitMode, ImageFit, OutputFormat};
+use crate::proxy::common::routing_info::{RoutingInfo, StreamProtocol};
+use crate::proxy::common::timing::elapsed_ms;
+use crate::proxy::common::sse_split::split_stream;
 use crate::proxy::server::AppState;
 
 const MAX_RETRY_ATTEMPTS: usize = 3;
 use crate::proxy::session_manager::SessionManager;
+use crate::proxy::mappers::upstream_error::{translate as translate_upstream_error, TranslatedError};
+use crate::proxy::mappers::malformed_call::EarlyFinish;
+use crate::proxy::stored_responses::{capture_stream, save_chat_stream, save_response_stream, StoredKind};
+
+/// 本地 token 预估超出目标模型上下文窗口时，按配置记录警告或返回与 OpenAI 一致的 context_length_exceeded 错误
+/// (Trim 只适用于 Claude 协议，这里按 Reject 处理)
+fn check_preflight_budget(
+    gemini_body: &Value,
+    model: &str,
+    config: &crate::proxy::config::PreflightBudgetConfig,
+) -> Result<(), axum::response::Response> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let Some((estimate, window)) = crate::proxy::common::token_estimate::check_budget(gemini_body, model, &config.context_windows) else {
+        return Ok(());
+    };
+    if config.action == crate::proxy::config::PreflightBudgetAction::Warn {
+        tracing::warn!(
+            "[OpenAI] Estimated {} tokens exceeds {} window of {}, forwarding anyway",
+            estimate, model, window
+        );
+        return Ok(());
+    }
+    tracing::warn!(
+        "[OpenAI] Rejecting request: estimated {} tokens exceeds {} window of {}",
+        estimate, model, window
+    );
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "error": {
+                "message": format!(
+                    "This model's maximum context length is {} tokens. However, your messages resulted in approximately {} tokens.",
+                    window, estimate
+                ),
+                "type": "invalid_request_error",
+                "code": "context_length_exceeded",
+                "param": "messages"
+            }
+        })),
+    )
+        .into_response())
+}
+
+/// 附件合计超出请求大小上限时返回的结构化错误 (OpenAI 错误格式)
+fn request_too_large_response(message: &str) -> axum::response::Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "code": "request_too_large",
+                "param": null
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 纠正重试后仍为 MALFORMED_FUNCTION_CALL 时返回的结构化错误 (OpenAI 错误格式)
+fn malformed_call_response(call: &crate::proxy::mappers::malformed_call::MalformedCall) -> axum::response::Response {
+    (
+        StatusCode::BAD_GATEWAY,
+        Json(json!({
+            "error": {
+                "message": call.error_message(),
+                "type": "upstream_error",
+                "code": "malformed_function_call",
+                "param": call.tool,
+                "details": call.details()
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 失控循环 break：返回一条要求 Agent 停止的助手消息 (finish_reason = stop，不含工具调用)
+fn loop_break_response(request: &OpenAIRequest, kind: crate::proxy::loop_guard::LoopKind) -> axum::response::Response {
+    let id = format!("chatcmpl-loop-{}", uuid::Uuid::new_v4().simple());
+    let created = chrono::Utc::now().timestamp();
+    let text = kind.message();
+
+    if request.stream {
+        let chunks = [
+            json!({
+                "id": id, "object": "chat.completion.chunk", "created": created, "model": request.model,
+                "choices": [{"index": 0, "delta": {"role": "assistant", "content": text}, "finish_reason": null}]
+            }),
+            json!({
+                "id": id, "object": "chat.completion.chunk", "created": created, "model": request.model,
+                "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]
+            }),
+        ];
+        let mut body: String = chunks.iter().map(|c| format!("data: {}\n\n", c)).collect();
+        body.push_str("data: [DONE]\n\n");
+        axum::response::Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header(crate::proxy::loop_guard::HEADER_LOOP_DETECTED, kind.as_str())
+            .body(axum::body::Body::from(body))
+            .unwrap()
+    } else {
+        (
+            StatusCode::OK,
+            [(crate::proxy::loop_guard::HEADER_LOOP_DETECTED, kind.as_str())],
+            Json(json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": created,
+                "model": request.model,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0}
+            })),
+        )
+            .into_response()
+    }
+}
 
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    // [NEW] extra_body.google 扩展字段，合并到最终 Gemini 请求
+    let vendor_extension = crate::proxy::mappers::vendor_extension::from_openai(&body);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -33,6 +166,8 @@ pub async fn handle_chat_completions(
                 )),
                 reasoning_content: None,
                 tool_calls: None,
+                refusal: None,
+                images: None,
                 tool_call_id: None,
                 name: None,
             });
@@ -40,21 +175,60 @@ pub async fn handle_chat_completions(
 
     debug!("Received OpenAI request for model: {}", openai_req.model);
 
+    // [NEW] 失控循环检测：命中 break 时不再转发上游
+    let loop_config = state.experimental.read().await.loop_detection.clone();
+    if loop_config.enabled {
+        let signals = crate::proxy::loop_guard::openai_signals(
+            &openai_req,
+            &SessionManager::extract_openai_session_id(&openai_req),
+        );
+        let trace_id = crate::proxy::common::trace_id::resolve(&headers);
+        if let Some(kind) = crate::proxy::loop_guard::enforce(&signals, &loop_config, &trace_id).await {
+            return Ok(loop_break_response(&openai_req, kind));
+        }
+    }
+
+    // [NEW] store=true：保存最终结果，供 GET /v1/chat/completions/{id} 检索
+    let store_metadata = (openai_req.store == Some(true)).then(|| openai_req.metadata.clone());
+
     // 1. 获取 UpstreamClient (Clone handle)
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
     let mut last_error = String::new();
-    let mut last_email: Option<String> = None;
-
-    for attempt in 0..max_attempts {
+    // [NEW] 最近一次上游错误 (翻译后，原始错误体保留在 debug 字段)
+    let mut last_upstream_error: Option<TranslatedError> = None;
+    // [NEW] 同一客户端请求的所有重试共享 requestId，便于上游去重
+    let trace_id = crate::proxy::common::trace_id::resolve(&headers);
+    let request_id = crate::proxy::common::trace_id::upstream_request_id("openai", &trace_id);
+    let mut routing = RoutingInfo::new();
+    let expose_routing_info = state.expose_routing_info.load(std::sync::atomic::Ordering::Relaxed);
+    // [NEW] 超大附件处理器 (重试循环外创建，附件只处理一次)
+    let mut inline_offloader = crate::proxy::common::inline_offload::InlineOffloader::new(state.experimental.read().await.inline_offload.clone());
+    let budget_config = state.experimental.read().await.preflight_budget.clone();
+    let time_context = state.experimental.read().await.time_context.clone();
+    let recitation_config = state.experimental.read().await.recitation_retry.clone();
+    // [NEW] MALFORMED_FUNCTION_CALL 纠正提示：首次出现时携带提示重试一次 (不占用尝试次数)
+    let mut malformed_hint: Option<String> = None;
+    // [NEW] RECITATION 截断：调整温度并携带提示重试一次 (不占用尝试次数)
+    let mut recitation_retried = false;
+    let mut attempt_limit = max_attempts;
+    let mut next_attempt = 0;
+
+    while next_attempt < attempt_limit {
+        let attempt = next_attempt;
+        next_attempt += 1;
         // 2. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = state.resolve_model(&openai_req.model).await;
+        // 3. 提取 SessionId (粘性指纹)
+        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+        routing.session_id = Some(session_id.clone());
+        // [NEW] A/B 路由实验
+        let mapped_model = crate::proxy::experiments::route(
+            &state, &headers, &openai_req.model, mapped_model, &session_id, &mut routing,
+        ).await;
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -66,13 +240,17 @@ pub async fn handle_chat_completions(
             &tools_val,
         );
 
-        // 3. 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+        let quota_group = token_manager.resolve_quota_group(
+            &config.request_type,
+            &[&openai_req.model, &mapped_model],
+            &tools_val,
+            &headers,
+        );
 
-        // 4. 获取 Token (使用准确的 request_type)
+        // 4. 获取 Token (使用准确的 request_type / 配额分组)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
         let (access_token, project_id, email) = match token_manager
-            .get_token(&config.request_type, attempt > 0, Some(&session_id))
+            .get_token(&config.request_type, &quota_group, attempt > 0, Some(&session_id), Some(&config.final_model))
             .await
         {
             Ok(t) => t,
@@ -84,16 +262,47 @@ pub async fn handle_chat_completions(
             }
         };
 
-        last_email = Some(email.clone());
+        routing.start_attempt(&email, &mapped_model);
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 4. 转换请求
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        // [NEW] 内置映射之外的目标模型：已探测时以探测结果取代按模型名的判断，未探测时在后台探测
+        let capabilities = state.model_capabilities.resolve(
+            &state.experimental.read().await.capability_probe,
+            &state.upstream,
+            &access_token,
+            &project_id,
+            &email,
+            &mapped_model,
+        );
+        let transform_start = std::time::Instant::now();
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model, capabilities.as_ref());
+        crate::proxy::common::utils::apply_request_id(&mut gemini_body, &request_id);
+        crate::proxy::common::time_context::apply_time_context(&mut gemini_body, &time_context);
+        crate::proxy::mappers::vendor_extension::apply(&mut gemini_body, vendor_extension.as_ref());
+        if let Some(hint) = &malformed_hint {
+            crate::proxy::mappers::malformed_call::apply_hint(&mut gemini_body, hint);
+        }
+        if recitation_retried {
+            crate::proxy::mappers::recitation::apply_variation(&mut gemini_body, &recitation_config);
+        }
+        // [NEW] 超大附件：图片本地缩放 / 文本拆分，附件合计超限时直接返回 413
+        let gemini_body = match inline_offloader.prepare(gemini_body).await {
+            Ok(b) => b,
+            Err(e) => return Ok(request_too_large_response(&e)),
+        };
+        routing.timing.transform_ms = Some(elapsed_ms(transform_start));
+        // [NEW] 本地 token 预估：明显超出上下文窗口时直接返回 context_length_exceeded
+        if let Err(response) = check_preflight_budget(&gemini_body, &mapped_model, &budget_config) {
+            return Ok(response);
+        }
+        // [NEW] 影子流量：首次尝试时按比例镜像到对比模型
+        if attempt == 0 {
+            crate::proxy::shadow::maybe_mirror(&state, &gemini_body, &openai_req.model, &mapped_model, "/v1/chat/completions").await;
+        }
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试
-        if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
-            debug!("[OpenAI-Request] Transformed Gemini Body:\n{}", body_json);
-        }
+        crate::proxy::common::utils::log_transformed_body("[OpenAI-Request]", &gemini_body, &*state.experimental.read().await);
 
         // 5. 发送请求 - 自动转换逻辑
         let client_wants_stream = openai_req.stream;
@@ -111,14 +320,22 @@ pub async fn handle_chat_completions(
             "generateContent"
         };
         let query_string = if actual_stream { Some("alt=sse") } else { None };
+        // [NEW] 流中途故障转移需要保留请求体用于续写
+        let failover_config = state.experimental.read().await.mid_stream_failover.clone();
+        let failover_body = (actual_stream && failover_config.enabled).then(|| gemini_body.clone());
 
+        let upstream_start = std::time::Instant::now();
         let response = match upstream
             .call_v1_internal(method, &access_token, gemini_body, query_string)
             .await
         {
-            Ok(r) => r,
+            Ok(r) => {
+                routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
+                r
+            }
             Err(e) => {
                 last_error = e.clone();
+                routing.record_retry("network");
                 debug!(
                     "OpenAI Request failed on attempt {}/{}: {}",
                     attempt + 1,
@@ -137,23 +354,101 @@ pub async fn handle_chat_completions(
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
-                let openai_stream =
-                    create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                // [NEW] 内容出现前即以 MALFORMED_FUNCTION_CALL / RECITATION 结束时，调整请求后重试一次
+                let retry_recitation = recitation_config.enabled && !recitation_retried;
+                let upstream_stream = match crate::proxy::mappers::malformed_call::peek_stream(response.bytes_stream(), retry_recitation).await {
+                    Ok(s) => {
+                        // 流式响应的首字节以首个内容块为准 (响应头通常远早于首个 token)
+                        routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
+                        s
+                    }
+                    Err(EarlyFinish::Recitation) => {
+                        tracing::warn!("[OpenAI] Upstream stopped with RECITATION before any content, retrying with variation");
+                        routing.record_retry("recitation");
+                        recitation_retried = true;
+                        attempt_limit += 1;
+                        continue;
+                    }
+                    Err(EarlyFinish::Malformed(call)) => {
+                        tracing::warn!("[OpenAI] Upstream returned MALFORMED_FUNCTION_CALL (tool: {:?})", call.tool);
+                        routing.record_retry("malformed_function_call");
+                        if malformed_hint.is_none() {
+                            malformed_hint = Some(call.corrective_hint());
+                            attempt_limit += 1;
+                            continue;
+                        }
+                        return Ok(routing.attach(malformed_call_response(&call)));
+                    }
+                };
+                let upstream_stream = match failover_body {
+                    Some(body) => crate::proxy::upstream::stream_failover::wrap(
+                        upstream_stream,
+                        &failover_config,
+                        crate::proxy::upstream::stream_failover::FailoverRequest {
+                            token_manager: token_manager.clone(),
+                            upstream: upstream.clone(),
+                            body,
+                            request_type: config.request_type.clone(),
+                            quota_group: quota_group.clone(),
+                            session_id: Some(session_id.clone()),
+                            model: config.final_model.clone(),
+                            trace_id: trace_id.clone(),
+                        },
+                    ),
+                    None => upstream_stream,
+                };
+                // [NEW] 输出文本清理，旁路统计用量，计入账号每日上限
+                let sanitize = state.experimental.read().await.text_sanitize.for_request(&[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers));
+                let upstream_stream = crate::proxy::common::text_sanitize::sanitize_gemini_stream(upstream_stream, sanitize);
+                let gemini_stream = token_manager.track_usage_stream(&email, upstream_stream);
+                // [NEW] JSON 模式下对输出做增量校验
+                let json_mode = if openai_req.response_format.as_ref().is_some_and(|f| f.is_json()) {
+                    Some(state.experimental.read().await.json_mode.clone()).filter(|c| c.enabled)
+                } else {
+                    None
+                };
+                // [NEW] 按调用方应用兼容性配置 (请求图片输出时图片以 delta.images 下发，思考内容按模型 / 客户端规则输出)
+                let (profile, locale) =
+                    crate::proxy::client_profile::profile_and_locale(&headers, &*state.client_profiles.read().await);
+                let mut profile = profile.with_thinking_rules(&state.experimental.read().await.thinking_output_rules, &[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers));
+                profile.structured_images |= openai_req.wants_image_output();
+                // 非流式客户端的响应由流收集而来，始终需要末尾的 usage chunk (与 OpenAI 非流式响应一致)
+                let include_usage = !client_wants_stream || openai_req.stream_options.as_ref().is_some_and(|o| o.include_usage);
+                let usage_heartbeat = crate::proxy::common::usage_heartbeat::UsageHeartbeat::from_config(
+                    &state.experimental.read().await.usage_heartbeat,
+                ).filter(|_| client_wants_stream);
+                let openai_stream = create_openai_sse_stream(
+                    Box::pin(gemini_stream),
+                    openai_req.model.clone(),
+                    json_mode,
+                    profile,
+                    locale,
+                    include_usage,
+                    usage_heartbeat,
+                );
+                let openai_stream = match store_metadata.clone().filter(|_| client_wants_stream) {
+                    Some(metadata) => capture_stream(openai_stream, save_chat_stream(state.stored_responses.clone(), metadata)),
+                    None => openai_stream,
+                };
                 
                 // 判断客户端期望的格式
                 if client_wants_stream {
                     // 客户端本就要 Stream，直接返回 SSE
-                    let body = Body::from_stream(openai_stream);
-                    return Ok(Response::builder()
+                    // [NEW] 拆分超大增量 (如 base64 图片)
+                    let max_delta = state.experimental.read().await.sse_chunking.limit_for(StreamProtocol::OpenAIChat);
+                    let openai_stream = split_stream(openai_stream, StreamProtocol::OpenAIChat, max_delta);
+                    let body = if expose_routing_info {
+                        Body::from_stream(routing.inject_into_stream(openai_stream, StreamProtocol::OpenAIChat))
+                    } else {
+                        Body::from_stream(openai_stream)
+                    };
+                    return Ok(routing.attach(Response::builder()
                         .header("Content-Type", "text/event-stream")
                         .header("Cache-Control", "no-cache")
                         .header("Connection", "keep-alive")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &mapped_model)
                         .body(body)
                         .unwrap()
-                        .into_response());
+                        .into_response()));
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::openai::collect_openai_stream_to_json;
@@ -170,7 +465,10 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            if let (Some(metadata), Ok(body)) = (store_metadata, serde_json::to_value(&full_response)) {
+                                state.stored_responses.save_in_background(StoredKind::ChatCompletion, body, metadata);
+                            }
+                            return Ok(routing.attach((StatusCode::OK, Json(full_response)).into_response()));
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -179,20 +477,42 @@ pub async fn handle_chat_completions(
                 }
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
-
-            let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&gemini_resp) {
+                token_manager.record_usage(&email, tokens);
+            }
+            // 非流式响应尚未发送任何内容，被 RECITATION 截断时可直接重试
+            if recitation_config.enabled && !recitation_retried && crate::proxy::mappers::recitation::detect(&gemini_resp) {
+                tracing::warn!("[OpenAI] Upstream stopped with RECITATION, retrying with variation");
+                routing.record_retry("recitation");
+                recitation_retried = true;
+                attempt_limit += 1;
+                continue;
+            }
+            // [NEW] 输出文本清理
+            crate::proxy::common::text_sanitize::sanitize_gemini_response(&mut gemini_resp, &state.experimental.read().await.text_sanitize.for_request(&[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers)));
+
+            let (profile, locale) =
+                crate::proxy::client_profile::profile_and_locale(&headers, &*state.client_profiles.read().await);
+            let mut profile = profile.with_thinking_rules(&state.experimental.read().await.thinking_output_rules, &[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers));
+            profile.structured_images |= openai_req.wants_image_output();
+            let openai_response = transform_openai_response(&gemini_resp, &profile, locale);
+            if let (Some(metadata), Ok(body)) = (store_metadata, serde_json::to_value(&openai_response)) {
+                state.stored_responses.save_in_background(StoredKind::ChatCompletion, body, metadata);
+            }
+            return Ok(routing.attach((StatusCode::OK, Json(openai_response)).into_response()));
         }
 
         // 处理特定错误并重试
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        let upstream_error = translate_upstream_error(status_code, &error_text);
+        last_error = format!("HTTP {}: {}", status_code, upstream_error.message);
+        last_upstream_error = Some(upstream_error.clone());
 
         // [New] 打印错误报文日志
         tracing::error!(
@@ -218,6 +538,7 @@ pub async fn handle_chat_completions(
                     actual_delay
                 );
                 tokio::time::sleep(tokio::time::Duration::from_millis(actual_delay)).await;
+                routing.record_retry(status_code.to_string());
                 continue;
             }
 
@@ -229,7 +550,7 @@ pub async fn handle_chat_completions(
                     attempt + 1,
                     max_attempts
                 );
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                return Ok(routing.attach((status, Json(upstream_error.to_openai())).into_response()));
             }
 
             // 3. 其他限流或服务器过载情况，轮换账号
@@ -240,6 +561,19 @@ pub async fn handle_chat_completions(
                 attempt + 1,
                 max_attempts
             );
+            routing.record_retry(status_code.to_string());
+            continue;
+        }
+
+        // [NEW] 账号无权访问该模型 (404 / 模型级 403)：记录后轮换到其他账号
+        if token_manager.record_model_access_error(&email, &config.final_model, status_code, &error_text) {
+            tracing::warn!(
+                "OpenAI Upstream {}: account {} cannot serve model {}, rotating account",
+                status_code,
+                email,
+                config.final_model
+            );
+            routing.record_retry(status_code.to_string());
             continue;
         }
 
@@ -252,6 +586,7 @@ pub async fn handle_chat_completions(
                 attempt + 1,
                 max_attempts
             );
+            routing.record_retry(status_code.to_string());
             continue;
         }
 
@@ -260,28 +595,41 @@ pub async fn handle_chat_completions(
             "OpenAI Upstream non-retryable error {} on account {}: {}",
             status_code, email, error_text
         );
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        return Ok(routing.attach((status, Json(upstream_error.to_openai())).into_response()));
     }
 
     // 所有尝试均失败
-    if let Some(email) = last_email {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Account-Email", email)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        ).into_response())
-    } else {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            format!("All accounts exhausted. Last error: {}", last_error),
-        ).into_response())
+    let retry_after = token_manager.rate_limit_state().retry_after_secs();
+    let response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(exhausted_error(
+            format!("All accounts exhausted. Last error: {}. Please retry after {} seconds.", last_error, retry_after),
+            last_upstream_error.as_ref(),
+        )),
+    ).into_response();
+    Ok(routing.attach(crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after)))
+}
+
+/// 所有尝试均失败时的 OpenAI 风格错误体 (附带最近一次上游错误的原始信息)
+fn exhausted_error(message: String, last_upstream_error: Option<&TranslatedError>) -> Value {
+    let mut body = json!({
+        "error": {
+            "message": message,
+            "type": "rate_limit_error",
+            "code": "all_accounts_exhausted"
+        }
+    });
+    if let Some(upstream_error) = last_upstream_error {
+        body["error"]["debug"] = upstream_error.debug();
     }
+    body
 }
 
 /// 处理 Legacy Completions API (/v1/completions)
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     info!(
@@ -538,6 +886,7 @@ pub async fn handle_completions(
     // Actually, due to SSE handling differences (Codex uses different event format), we replicate the loop here or abstract it.
     // For now, let's replicate the core loop but with Codex specific SSE mapping.
 
+    let vendor_extension = crate::proxy::mappers::vendor_extension::from_openai(&body);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body.clone())
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -552,24 +901,43 @@ pub async fn handle_completions(
                 )),
                 reasoning_content: None,
                 tool_calls: None,
+                refusal: None,
+                images: None,
                 tool_call_id: None,
                 name: None,
             });
     }
 
+    // [NEW] Responses API 的 store=true：保存最终结果，供 GET /v1/responses/{id} 检索
+    let store_metadata = (is_codex_style && openai_req.store == Some(true)).then(|| openai_req.metadata.clone());
+
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
+    let token_manager = state.token_manager.clone();
     let pool_size = token_manager.len();
     let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
 
     let mut last_error = String::new();
+    // [NEW] 最近一次上游错误 (翻译后，原始错误体保留在 debug 字段)
+    let mut last_upstream_error: Option<TranslatedError> = None;
+    // [NEW] 同一客户端请求的所有重试共享 requestId，便于上游去重
+    let trace_id = crate::proxy::common::trace_id::resolve(&headers);
+    let request_id = crate::proxy::common::trace_id::upstream_request_id("openai", &trace_id);
+    let mut routing = RoutingInfo::new();
+    let expose_routing_info = state.expose_routing_info.load(std::sync::atomic::Ordering::Relaxed);
+    // [NEW] 超大附件处理器 (重试循环外创建，附件只处理一次)
+    let mut inline_offloader = crate::proxy::common::inline_offload::InlineOffloader::new(state.experimental.read().await.inline_offload.clone());
+    let budget_config = state.experimental.read().await.preflight_budget.clone();
+    let time_context = state.experimental.read().await.time_context.clone();
+    let keep_alive = state.experimental.read().await.codex_keep_alive.clone();
 
-    for _attempt in 0..max_attempts {
+    for attempt in 0..max_attempts {
         // 1. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = state.resolve_model(&openai_req.model).await;
+        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+        routing.session_id = Some(session_id.clone());
+        let mapped_model = crate::proxy::experiments::route(
+            &state, &headers, &openai_req.model, mapped_model, &session_id, &mut routing,
+        ).await;
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -581,8 +949,15 @@ pub async fn handle_completions(
             &tools_val,
         );
 
+        let quota_group = token_manager.resolve_quota_group(
+            &config.request_type,
+            &[&openai_req.model, &mapped_model],
+            &tools_val,
+            &headers,
+        );
+
         let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, false, None).await {
+            match token_manager.get_token(&config.request_type, &quota_group, false, None, Some(&config.final_model)).await {
                 Ok(t) => t,
                 Err(e) => {
                     return Err((
@@ -593,13 +968,38 @@ pub async fn handle_completions(
             };
 
         info!("✓ Using account: {} (type: {})", email, config.request_type);
-
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        routing.start_attempt(&email, &mapped_model);
+
+        // [NEW] 内置映射之外的目标模型：已探测时以探测结果取代按模型名的判断，未探测时在后台探测
+        let capabilities = state.model_capabilities.resolve(
+            &state.experimental.read().await.capability_probe,
+            &state.upstream,
+            &access_token,
+            &project_id,
+            &email,
+            &mapped_model,
+        );
+        let transform_start = std::time::Instant::now();
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model, capabilities.as_ref());
+        crate::proxy::common::utils::apply_request_id(&mut gemini_body, &request_id);
+        crate::proxy::common::time_context::apply_time_context(&mut gemini_body, &time_context);
+        crate::proxy::mappers::vendor_extension::apply(&mut gemini_body, vendor_extension.as_ref());
+        // [NEW] 超大附件：图片本地缩放 / 文本拆分，附件合计超限时直接返回 413
+        let gemini_body = match inline_offloader.prepare(gemini_body).await {
+            Ok(b) => b,
+            Err(e) => return Ok(request_too_large_response(&e)),
+        };
+        routing.timing.transform_ms = Some(elapsed_ms(transform_start));
+        // [NEW] 本地 token 预估：明显超出上下文窗口时直接返回 context_length_exceeded
+        if let Err(response) = check_preflight_budget(&gemini_body, &mapped_model, &budget_config) {
+            return Ok(response);
+        }
+        if attempt == 0 {
+            crate::proxy::shadow::maybe_mirror(&state, &gemini_body, &openai_req.model, &mapped_model, "/v1/completions").await;
+        }
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径)
-        if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
-            debug!("[Codex-Request] Transformed Gemini Body:\n{}", body_json);
-        }
+        crate::proxy::common::utils::log_transformed_body("[Codex-Request]", &gemini_body, &*state.experimental.read().await);
 
         let list_response = openai_req.stream;
         let method = if list_response {
@@ -608,14 +1008,22 @@ pub async fn handle_completions(
             "generateContent"
         };
         let query_string = if list_response { Some("alt=sse") } else { None };
+        // [NEW] Codex 流保活：延长流式请求的上游超时
+        let stream_timeout = (list_response && is_codex_style && keep_alive.enabled)
+            .then(|| std::time::Duration::from_secs(keep_alive.stream_timeout_secs));
 
+        let upstream_start = std::time::Instant::now();
         let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+            .call_v1_internal_with_timeout(method, &access_token, gemini_body, query_string, stream_timeout)
             .await
         {
-            Ok(r) => r,
+            Ok(r) => {
+                routing.timing.upstream_ttfb_ms = Some(elapsed_ms(upstream_start));
+                r
+            }
             Err(e) => {
                 last_error = e.clone();
+                routing.record_retry("network");
                 continue;
             }
         };
@@ -626,36 +1034,66 @@ pub async fn handle_completions(
                 use axum::body::Body;
                 use axum::response::Response;
 
-                let gemini_stream = response.bytes_stream();
+                // [NEW] 输出文本清理，旁路统计用量，计入账号每日上限
+                let sanitize = state.experimental.read().await.text_sanitize.for_request(&[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers)).for_codex(is_codex_style);
+                let upstream_stream = crate::proxy::common::text_sanitize::sanitize_gemini_stream(response.bytes_stream(), sanitize);
+                let gemini_stream = token_manager.track_usage_stream(&email, upstream_stream);
+                let sse_chunking = state.experimental.read().await.sse_chunking.clone();
+                let thinking_mode = crate::proxy::client_profile::profile_from_headers(&headers, &*state.client_profiles.read().await)
+                    .with_thinking_rules(&state.experimental.read().await.thinking_output_rules, &[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers))
+                    .thinking_mode();
                 let body = if is_codex_style {
+                    use crate::proxy::common::keep_alive::with_keep_alive;
                     use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                     let s =
-                        create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_codex_sse_stream(Box::pin(gemini_stream), openai_req.model.clone(), thinking_mode);
+                    let s = match store_metadata.clone() {
+                        Some(metadata) => capture_stream(s, save_response_stream(state.stored_responses.clone(), metadata)),
+                        None => s,
+                    };
+                    let s = split_stream(s, StreamProtocol::Codex, sse_chunking.limit_for(StreamProtocol::Codex));
+                    // [NEW] 上游长时间无输出时插入保活注释
+                    let interval = std::time::Duration::from_secs(if keep_alive.enabled { keep_alive.interval_secs } else { 0 });
+                    if expose_routing_info {
+                        Body::from_stream(with_keep_alive(routing.inject_into_stream(s, StreamProtocol::Codex), interval))
+                    } else {
+                        Body::from_stream(with_keep_alive(s, interval))
+                    }
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
                     let s =
-                        create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
-                    Body::from_stream(s)
+                        create_legacy_sse_stream(Box::pin(gemini_stream), openai_req.model.clone(), thinking_mode);
+                    let s = split_stream(s, StreamProtocol::OpenAIChat, sse_chunking.limit_for(StreamProtocol::OpenAIChat));
+                    if expose_routing_info {
+                        Body::from_stream(routing.inject_into_stream(s, StreamProtocol::OpenAIChat))
+                    } else {
+                        Body::from_stream(s)
+                    }
                 };
 
-                return Ok(Response::builder()
+                return Ok(routing.attach(Response::builder()
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
-                    .header("X-Account-Email", &email)
-                    .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
-                    .into_response());
+                    .into_response()));
             }
 
-            let gemini_resp: Value = response
+            let mut gemini_resp: Value = response
                 .json()
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
+            if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&gemini_resp) {
+                token_manager.record_usage(&email, tokens);
+            }
+            // [NEW] 输出文本清理
+            crate::proxy::common::text_sanitize::sanitize_gemini_response(&mut gemini_resp, &state.experimental.read().await.text_sanitize.for_request(&[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers)).for_codex(is_codex_style));
 
-            let chat_resp = transform_openai_response(&gemini_resp);
+            let (profile, locale) =
+                crate::proxy::client_profile::profile_and_locale(&headers, &*state.client_profiles.read().await);
+            let profile = profile.with_thinking_rules(&state.experimental.read().await.thinking_output_rules, &[&openai_req.model, &mapped_model], crate::proxy::client_profile::client_id(&headers));
+            let chat_resp = transform_openai_response(&gemini_resp, &profile, locale);
 
             // Map Chat Response -> Legacy Completions Response
             let choices = chat_resp.choices.iter().map(|c| {
@@ -677,25 +1115,41 @@ pub async fn handle_completions(
                 "model": chat_resp.model,
                 "choices": choices
             });
+            if let Some(metadata) = store_metadata {
+                let response = crate::proxy::stored_responses::response_from_chat(&chat_resp);
+                state.stored_responses.save_in_background(StoredKind::Response, response, metadata);
+            }
 
-            return Ok(axum::Json(legacy_resp).into_response());
+            return Ok(routing.attach(axum::Json(legacy_resp).into_response()));
         }
 
         // Handle errors and retry
         let status_code = status.as_u16();
         let error_text = response.text().await.unwrap_or_default();
-        last_error = format!("HTTP {}: {}", status_code, error_text);
-
-        if status_code == 429 || status_code == 403 || status_code == 401 {
+        let upstream_error = translate_upstream_error(status_code, &error_text);
+        last_error = format!("HTTP {}: {}", status_code, upstream_error.message);
+        last_upstream_error = Some(upstream_error.clone());
+
+        if token_manager.record_model_access_error(&email, &config.final_model, status_code, &error_text)
+            || status_code == 429
+            || status_code == 403
+            || status_code == 401
+        {
+            routing.record_retry(status_code.to_string());
             continue;
         }
-        return Err((status, error_text));
+        return Ok(routing.attach((status, Json(upstream_error.to_openai())).into_response()));
     }
 
-    Err((
+    let retry_after = token_manager.rate_limit_state().retry_after_secs();
+    let response = (
         StatusCode::TOO_MANY_REQUESTS,
-        format!("All attempts failed. Last error: {}", last_error),
-    ))
+        Json(exhausted_error(
+            format!("All attempts failed. Last error: {}. Please retry after {} seconds.", last_error, retry_after),
+            last_upstream_error.as_ref(),
+        )),
+    ).into_response();
+    Ok(routing.attach(crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after)))
 }
 
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
@@ -703,15 +1157,25 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 
     let model_ids = get_all_dynamic_models(
         &state.custom_mapping,
+        &state.model_aliases,
     ).await;
+    let aliases = state.model_aliases.read().await.clone();
 
     let data: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
+        let mut model = json!({
             "id": id,
             "object": "model",
             "created": 1706745600,
             "owned_by": "antigravity"
-        })
+        });
+        // 别名附带当前指向，便于客户端展示
+        if let Some(alias) = aliases.iter().find(|a| a.name == id) {
+            model["alias_for"] = json!(alias.target);
+            if !alias.description.is_empty() {
+                model["description"] = json!(alias.description);
+            }
+        }
+        model
     }).collect();
 
     Json(json!({
@@ -720,10 +1184,370 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
     }))
 }
 
+/// 执行单张图片生成 (image_gen 配额组)
+///
+/// 每次尝试都重新获取账号：并发任务因此分散到账号池中，
+/// 限流 / 服务端错误 / 网络错误时标记当前账号并换号重试。`body` 中的 project 由本函数填充。
+async fn generate_image_with_rotation(
+    token_manager: std::sync::Arc<crate::proxy::TokenManager>,
+    upstream: std::sync::Arc<crate::proxy::upstream::client::UpstreamClient>,
+    mut body: V1InternalRequest,
+    quota_group: &str,
+) -> Result<GenerateContentResponse, String> {
+    let max_attempts = MAX_RETRY_ATTEMPTS.min(token_manager.len()).max(1);
+    let mut last_error = String::new();
+
+    for attempt in 0..max_attempts {
+        let (access_token, project_id, email) = token_manager
+            .get_token("image_gen", quota_group, attempt > 0, None, None)
+            .await
+            .map_err(|e| format!("Token error: {}", e))?;
+        body.project = project_id;
+        debug!("[Images] Attempt {}/{} using account {}", attempt + 1, max_attempts, email);
+
+        let response = match upstream
+            .call_v1_internal("generateContent", &access_token, body.to_value(), None)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("[Images] Network error on {}: {}, rotating account", email, e);
+                last_error = format!("Network error: {}", e);
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            let json = response
+                .json::<Value>()
+                .await
+                .map_err(|e| format!("Parse error: {}", e))?;
+            let parsed = GenerateContentResponse::from_envelope(json).map_err(|e| format!("Parse error: {}", e))?;
+            token_manager.mark_account_success(&email);
+            if let Some(tokens) = parsed.total_tokens() {
+                token_manager.record_usage(&email, tokens);
+            }
+            return Ok(parsed);
+        }
+
+        let status_code = status.as_u16();
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+        let err_text = response.text().await.unwrap_or_default();
+        last_error = format!("Upstream error {}: {}", status, err_text);
+
+        if matches!(status_code, 429 | 500 | 503 | 529) {
+            token_manager.mark_rate_limited(&email, status_code, retry_after.as_deref(), &err_text);
+            tracing::warn!(
+                "[Images] Upstream {} on {} attempt {}/{}, rotating account",
+                status_code,
+                email,
+                attempt + 1,
+                max_attempts
+            );
+            continue;
+        }
+        if matches!(status_code, 401 | 403) {
+            continue;
+        }
+        // 其他错误 (如 400) 换号也无法解决
+        break;
+    }
+
+    Err(last_error)
+}
+
+/// 一批图片任务 (n 张，每张独立发送)
+struct ImageBatch {
+    token_manager: std::sync::Arc<crate::proxy::TokenManager>,
+    upstream: std::sync::Arc<crate::proxy::upstream::client::UpstreamClient>,
+    /// 不含 project / requestId 的请求体模板
+    body: V1InternalRequest,
+    /// 配额分组 (默认 image_gen)
+    quota_group: String,
+    request_id_prefix: &'static str,
+    n: usize,
+    response_format: String,
+    /// 尺寸 / 格式后处理
+    fit: ImageFit,
+}
+
+impl ImageBatch {
+    /// 并发执行并按任务顺序收集结果；提供 job 时每完成一张即更新任务进度
+    async fn run(
+        self,
+        job: Option<(std::sync::Arc<crate::proxy::image_jobs::ImageJobStore>, String)>,
+    ) -> (Vec<Value>, Vec<String>) {
+        let mut tasks = Vec::new();
+        let pinned = crate::proxy::token_manager::pinned_account();
+        // 派生任务不随请求 future 一起丢弃：同步请求由 guard 在客户端断开时取消各任务中的 token / 配额刷新，
+        // 异步任务与原请求无关，不绑定
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let _guard = job.is_none().then(|| cancel.clone().drop_guard());
+        for _ in 0..self.n {
+            let token_manager = self.token_manager.clone();
+            let upstream = self.upstream.clone();
+            let response_format = self.response_format.clone();
+            let fit = self.fit;
+            let job = job.clone();
+            let pinned = pinned.clone();
+            let cancel = cancel.clone();
+            let quota_group = self.quota_group.clone();
+            let mut body = self.body.clone();
+            body.request_id = format!("{}-{}", self.request_id_prefix, uuid::Uuid::new_v4());
+
+            tasks.push(tokio::spawn(async move {
+                let generation = generate_image_with_rotation(token_manager, upstream, body, &quota_group);
+                let generation = crate::proxy::token_manager::with_pinned_account(pinned, generation);
+                let result = match crate::proxy::token_manager::with_cancellation(cancel, generation).await {
+                    // 解码 / 缩放 / 编码为 CPU 密集操作，放到阻塞线程池
+                    Ok(resp) => tokio::task::spawn_blocking(move || extract_images(&resp, &response_format, &fit))
+                        .await
+                        .map_err(|e| format!("Image processing task failed: {}", e)),
+                    Err(e) => Err(e),
+                };
+                if let Some((store, id)) = &job {
+                    store.record(id, &result);
+                }
+                result
+            }));
+        }
+
+        let mut images: Vec<Value> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
+        for (idx, task) in tasks.into_iter().enumerate() {
+            match task.await {
+                Ok(Ok(task_images)) => {
+                    tracing::debug!("[Images] Task {} succeeded with {} image(s)", idx, task_images.len());
+                    images.extend(task_images);
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("[Images] Task {} failed: {}", idx, e);
+                    errors.push(e);
+                }
+                Err(e) => {
+                    tracing::error!("[Images] Task {} join error: {}", idx, e);
+                    errors.push(format!("Task join error: {}", e));
+                }
+            }
+        }
+        (images, errors)
+    }
+}
+
+/// 从 generateContent 响应中提取图片 (按 fit 后处理，按 response_format 输出 url / b64_json)
+fn extract_images(gemini_resp: &GenerateContentResponse, response_format: &str, fit: &ImageFit) -> Vec<Value> {
+    gemini_resp
+        .first_parts()
+        .iter()
+        // 思考过程中的草图不作为结果返回
+        .filter(|part| !part.is_thought())
+        .filter_map(|part| part.inline_data.as_ref())
+        .filter_map(|img| {
+            let data = img.data.as_str();
+            if data.is_empty() {
+                return None;
+            }
+            let mime_type = img.mime_type.as_deref().filter(|m| !m.is_empty()).unwrap_or("image/png");
+            let (data, mime_type) = if fit.is_noop() {
+                (data.to_string(), mime_type.to_string())
+            } else {
+                fit.apply(data, mime_type).unwrap_or_else(|e| {
+                    tracing::warn!("[Images] Post-processing failed, returning original image: {}", e);
+                    (data.to_string(), mime_type.to_string())
+                })
+            };
+            if response_format == "url" {
+                Some(json!({ "url": format!("data:{};base64,{}", mime_type, data) }))
+            } else {
+                Some(json!({ "b64_json": data }))
+            }
+        })
+        .collect()
+}
+
+/// 根据请求参数构建图片后处理配置
+fn image_fit_from_params(
+    size: Option<&str>,
+    fit: Option<&str>,
+    output_format: Option<&str>,
+    output_compression: Option<u64>,
+) -> ImageFit {
+    ImageFit {
+        size: size.and_then(parse_size),
+        mode: fit.and_then(FitMode::parse).unwrap_or_default(),
+        format: output_format.and_then(OutputFormat::parse),
+        quality: output_compression.map(|q| q.min(100) as u8).unwrap_or(90),
+    }
+}
+
+/// 提示词安全预检；判定会被拦截时返回 400 响应 (附改写建议)
+async fn precheck_image_prompt(state: &AppState, prompt: &str) -> Option<axum::response::Response> {
+    let config = state.experimental.read().await.image_safety_precheck.clone();
+    if !config.enabled {
+        return None;
+    }
+    let verdict = crate::proxy::image_precheck::precheck_prompt(
+        &state.token_manager,
+        &state.upstream,
+        prompt,
+        &config,
+    )
+    .await?;
+    if !verdict.blocked {
+        return None;
+    }
+    tracing::warn!(
+        "[Images] Prompt rejected by safety pre-check: {:?} (categories: {:?})",
+        verdict.reason,
+        verdict.categories
+    );
+    Some((StatusCode::BAD_REQUEST, Json(verdict.to_error_body())).into_response())
+}
+
+/// `Prefer: respond-async` (RFC 7240)
+fn prefers_async(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.split(',').any(|p| p.trim().eq_ignore_ascii_case("respond-async")))
+}
+
+/// 创建异步任务并在后台执行，返回 202 响应
+fn spawn_image_job(state: &AppState, kind: &'static str, batch: ImageBatch) -> axum::response::Response {
+    let store = state.image_jobs.clone();
+    let id = store.create(kind, batch.n);
+    info!("[Images] Queued async {} job {} (n={})", kind, id, batch.n);
+
+    let job_id = id.clone();
+    let pinned = crate::proxy::token_manager::pinned_account();
+    tokio::spawn(crate::proxy::token_manager::with_pinned_account(pinned, async move {
+        store.mark_running(&job_id);
+        let (images, errors) = batch.run(Some((store.clone(), job_id.clone()))).await;
+        store.finish(&job_id);
+        info!(
+            "[Images] Job {} finished: {} image(s), {} error(s)",
+            job_id,
+            images.len(),
+            errors.len()
+        );
+    }));
+
+    let snapshot = state.image_jobs.snapshot(&id, 0).unwrap_or_else(|| json!({ "id": id }));
+    let mut response = (StatusCode::ACCEPTED, Json(snapshot)).into_response();
+    if let Ok(location) = axum::http::HeaderValue::from_str(&format!("/v1/images/jobs/{}", id)) {
+        response.headers_mut().insert(axum::http::header::LOCATION, location);
+    }
+    response
+}
+
+/// 查询图片任务进度；`?since=k` 只返回第 k 张之后的新结果
+pub async fn handle_image_job_status(
+    State(state): State<AppState>,
+    axum::extract::Path(job_id): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> impl IntoResponse {
+    let since = params
+        .get("since")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    match state.image_jobs.snapshot(&job_id, since) {
+        Some(snapshot) => (StatusCode::OK, Json(snapshot)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": {
+                    "message": format!("Image job '{}' not found", job_id),
+                    "type": "invalid_request_error",
+                    "code": "job_not_found"
+                }
+            })),
+        )
+            .into_response(),
+    }
+}
+
+fn stored_not_found(kind: StoredKind, id: &str) -> axum::response::Response {
+    let label = match kind {
+        StoredKind::ChatCompletion => "chat completion",
+        StoredKind::Response => "response",
+    };
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "error": {
+                "message": format!("No stored {} found with id '{}'", label, id),
+                "type": "invalid_request_error",
+                "code": "not_found"
+            }
+        })),
+    )
+        .into_response()
+}
+
+async fn get_stored(state: &AppState, kind: StoredKind, id: String) -> axum::response::Response {
+    let store = state.stored_responses.clone();
+    let lookup_id = id.clone();
+    match tokio::task::spawn_blocking(move || store.get(kind, &lookup_id)).await.ok().flatten() {
+        Some(body) => (StatusCode::OK, Json(body)).into_response(),
+        None => stored_not_found(kind, &id),
+    }
+}
+
+async fn delete_stored(state: &AppState, kind: StoredKind, id: String) -> axum::response::Response {
+    let store = state.stored_responses.clone();
+    let lookup_id = id.clone();
+    if tokio::task::spawn_blocking(move || store.delete(kind, &lookup_id)).await.unwrap_or(false) {
+        let object = match kind {
+            StoredKind::ChatCompletion => "chat.completion.deleted",
+            StoredKind::Response => "response.deleted",
+        };
+        Json(json!({ "id": id, "object": object, "deleted": true })).into_response()
+    } else {
+        stored_not_found(kind, &id)
+    }
+}
+
+/// 检索 store=true 保存的 Chat Completion
+pub async fn handle_get_chat_completion(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> axum::response::Response {
+    get_stored(&state, StoredKind::ChatCompletion, id).await
+}
+
+pub async fn handle_delete_chat_completion(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> axum::response::Response {
+    delete_stored(&state, StoredKind::ChatCompletion, id).await
+}
+
+/// 检索 store=true 保存的 Responses API 结果
+pub async fn handle_get_response(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> axum::response::Response {
+    get_stored(&state, StoredKind::Response, id).await
+}
+
+pub async fn handle_delete_response(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> axum::response::Response {
+    delete_stored(&state, StoredKind::Response, id).await
+}
+
 /// OpenAI Images API: POST /v1/images/generations
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 1. 解析请求参数
@@ -758,25 +1582,36 @@ pub async fn handle_images_generations(
         .and_then(|v| v.as_str())
         .unwrap_or("vivid");
 
+    // [NEW] 异步任务模式: `"async": true` 或 `Prefer: respond-async`
+    let async_mode = body.get("async").and_then(|v| v.as_bool()).unwrap_or(false)
+        || prefers_async(&headers);
+
     info!(
-        "[Images] Received request: model={}, prompt={:.50}..., n={}, size={}, quality={}, style={}",
+        "[Images] Received request: model={}, prompt={}, n={}, size={}, quality={}, style={}",
         model,
-        prompt,
+        crate::proxy::common::text_preview::preview(prompt, 50),
         n,
         size,
         quality,
         style
     );
 
-    // 2. 解析尺寸为宽高比
-    let aspect_ratio = match size {
-        "1792x768" | "2560x1080" => "21:9", // Ultra-wide
-        "1792x1024" | "1920x1080" => "16:9",
-        "1024x1792" | "1080x1920" => "9:16",
-        "1024x768" | "1280x960" => "4:3",
-        "768x1024" | "960x1280" => "3:4",
-        _ => "1:1", // 默认 1024x1024
-    };
+    // [NEW] 提示词安全预检 (可选)，避免明显会被拦截的请求消耗 image_gen 配额
+    if let Some(rejection) = precheck_image_prompt(&state, prompt).await {
+        return Ok(rejection);
+    }
+
+    // 2. 解析尺寸为宽高比 (任意 WxH 取最接近的支持比例)
+    let aspect_ratio = parse_size(size)
+        .map(|(w, h)| closest_aspect_ratio(w, h))
+        .unwrap_or("1:1");
+    // 显式指定尺寸 / 格式时在本地裁剪到精确像素并转码
+    let fit = image_fit_from_params(
+        body.get("size").and_then(|v| v.as_str()),
+        body.get("fit").and_then(|v| v.as_str()),
+        body.get("output_format").and_then(|v| v.as_str()),
+        body.get("output_compression").and_then(|v| v.as_u64()),
+    );
 
     // Prompt Enhancement
     let mut final_prompt = prompt.to_string();
@@ -788,135 +1623,74 @@ pub async fn handle_images_generations(
         "natural" => final_prompt.push_str(", (natural lighting, realistic, photorealistic)"),
         _ => {}
     }
-
-    // 3. 获取 Token
-    let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-
-    let (access_token, project_id, email) = match token_manager.get_token("image_gen", false, None).await
-    {
-        Ok(t) => t,
-        Err(e) => {
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                format!("Token error: {}", e),
-            ))
-        }
+    // [NEW] SD 系前端的 negative_prompt / style_preset / seed
+    let extensions = crate::proxy::mappers::openai::image_prompt::ImagePromptExtensions::from_body(&body);
+    extensions.apply_to_prompt(&mut final_prompt);
+
+    // 3. 并发发送请求 (解决 candidateCount > 1 不支持的问题)
+    // [NEW] 每个任务独立获取账号，大批量请求分散到整个账号池，失败时换号重试
+    let mut generation_config = GenerationConfig {
+        candidate_count: Some(1), // 强制单张
+        image_config: Some(json!({ "aspectRatio": aspect_ratio })),
+        ..Default::default()
     };
+    extensions.apply_to_generation_config(&mut generation_config);
+    let gemini_body = V1InternalRequest::new(
+        "gemini-3-pro-image",
+        "image_gen",
+        GenerateContentRequest {
+            contents: vec![Content::user(vec![Part::text(final_prompt)])],
+            generation_config: Some(generation_config),
+            safety_settings: Some(SafetySetting::all_off()),
+            ..Default::default()
+        },
+    );
 
-    info!("✓ Using account: {} for image generation", email);
-
-    // 4. 并发发送请求 (解决 candidateCount > 1 不支持的问题)
-    let mut tasks = Vec::new();
-
-    for _ in 0..n {
-        let upstream = upstream.clone();
-        let access_token = access_token.clone();
-        let project_id = project_id.clone();
-        let final_prompt = final_prompt.clone();
-        let aspect_ratio = aspect_ratio.to_string();
-        let _response_format = response_format.to_string();
-
-        tasks.push(tokio::spawn(async move {
-            let gemini_body = json!({
-                "project": project_id,
-                "requestId": format!("img-{}", uuid::Uuid::new_v4()),
-                "model": "gemini-3-pro-image",
-                "userAgent": "antigravity",
-                "requestType": "image_gen",
-                "request": {
-                    "contents": [{
-                        "role": "user",
-                        "parts": [{"text": final_prompt}]
-                    }],
-                    "generationConfig": {
-                        "candidateCount": 1, // 强制单张
-                        "imageConfig": {
-                            "aspectRatio": aspect_ratio
-                        }
-                    },
-                    "safetySettings": [
-                        { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-                        { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
-                    ]
-                }
-            });
+    let batch = ImageBatch {
+        token_manager: state.token_manager.clone(),
+        upstream: state.upstream.clone(),
+        quota_group: state.token_manager.resolve_quota_group("image_gen", &[model, &gemini_body.model], &None, &headers),
+        body: gemini_body,
+        request_id_prefix: "img",
+        n,
+        response_format: response_format.to_string(),
+        fit,
+    };
 
-            match upstream
-                .call_v1_internal("generateContent", &access_token, gemini_body, None)
-                .await
-            {
-                Ok(response) => {
-                    let status = response.status();
-                    if !status.is_success() {
-                        let err_text = response.text().await.unwrap_or_default();
-                        return Err(format!("Upstream error {}: {}", status, err_text));
-                    }
-                    match response.json::<Value>().await {
-                        Ok(json) => Ok(json),
-                        Err(e) => Err(format!("Parse error: {}", e)),
-                    }
-                }
-                Err(e) => Err(format!("Network error: {}", e)),
-            }
-        }));
+    // [NEW] 异步模式：立即返回 202 + job id，后台执行并记录进度
+    if async_mode {
+        return Ok(spawn_image_job(&state, "generation", batch));
     }
 
-    // 5. 收集结果
-    let mut images: Vec<Value> = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
-
-    for (idx, task) in tasks.into_iter().enumerate() {
-        match task.await {
-            Ok(result) => match result {
-                Ok(gemini_resp) => {
-                    let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
-                    if let Some(parts) = raw
-                        .get("candidates")
-                        .and_then(|c| c.get(0))
-                        .and_then(|cand| cand.get("content"))
-                        .and_then(|content| content.get("parts"))
-                        .and_then(|p| p.as_array())
-                    {
-                        for part in parts {
-                            if let Some(img) = part.get("inlineData") {
-                                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
-                                    tracing::debug!("[Images] Task {} succeeded", idx);
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("[Images] Task {} failed: {}", idx, e);
-                    errors.push(e);
-                }
-            },
-            Err(e) => {
-                let err_msg = format!("Task join error: {}", e);
-                tracing::error!("[Images] Task {} join error: {}", idx, e);
-                errors.push(err_msg);
-            }
+    // [NEW] 结果缓存：完全相同的同步请求直接返回最近的生成结果
+    let cache_config = state.experimental.read().await.image_cache.clone();
+    let cache_key = (cache_config.enabled && !image_cache_bust(&body, &headers)).then(|| {
+        crate::proxy::image_cache::ImageCache::key(&[
+            &serde_json::to_string(&batch.body).unwrap_or_default(),
+            &n.to_string(),
+            response_format,
+            &format!("{:?}", batch.fit),
+        ])
+    });
+    let cache_ttl = std::time::Duration::from_secs(cache_config.ttl_minutes.saturating_mul(60));
+    if let Some(key) = &cache_key {
+        let cache = state.image_cache.clone();
+        let lookup_key = key.clone();
+        if let Ok(Some(images)) = tokio::task::spawn_blocking(move || cache.get(&lookup_key, cache_ttl)).await {
+            tracing::info!("[Images] Cache hit, returning cached image(s) without calling upstream");
+            let mut response = Json(json!({
+                "created": chrono::Utc::now().timestamp(),
+                "data": images
+            }))
+            .into_response();
+            response.headers_mut().insert("X-Image-Cache", axum::http::HeaderValue::from_static("hit"));
+            return Ok(response);
         }
     }
 
+    // 4. 收集结果
+    let (images, errors) = batch.run(None).await;
+
     if images.is_empty() {
         let error_msg = if !errors.is_empty() {
             errors.join("; ")
@@ -943,17 +1717,44 @@ pub async fn handle_images_generations(
         n
     );
 
+    let cache_status = if cache_key.is_some() { "miss" } else { "bypass" };
+    // 仅缓存全部成功的结果，部分失败时下次仍重新生成
+    if let Some(key) = cache_key.filter(|_| errors.is_empty()) {
+        let cache = state.image_cache.clone();
+        let data = Value::Array(images.clone());
+        let max_entries = cache_config.max_entries.max(1);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = cache.put(&key, &data, max_entries) {
+                tracing::warn!("[Images] Failed to cache generated image(s): {}", e);
+            }
+        });
+    }
+
     // 6. 构建 OpenAI 格式响应
     let openai_response = json!({
         "created": chrono::Utc::now().timestamp(),
         "data": images
     });
 
-    Ok(Json(openai_response))
+    let mut response = Json(openai_response).into_response();
+    if cache_config.enabled {
+        response.headers_mut().insert("X-Image-Cache", axum::http::HeaderValue::from_static(cache_status));
+    }
+    Ok(response)
+}
+
+/// 客户端要求跳过图片结果缓存 (`cache_bust: true` 或 `Cache-Control: no-cache`)
+fn image_cache_bust(body: &Value, headers: &axum::http::HeaderMap) -> bool {
+    body.get("cache_bust").and_then(|v| v.as_bool()).unwrap_or(false)
+        || headers
+            .get(axum::http::header::CACHE_CONTROL)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("no-cache") || v.contains("no-store"))
 }
 
 pub async fn handle_images_edits(
     State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
     mut multipart: axum::extract::Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     tracing::info!("[Images] Received edit request");
@@ -965,26 +1766,33 @@ pub async fn handle_images_edits(
     let mut size = "1024x1024".to_string();
     let mut response_format = "b64_json".to_string(); // Default to b64_json for better compatibility with tools handling edits
     let mut model = "gemini-3-pro-image".to_string();
+    let mut async_mode = prefers_async(&headers);
+    let mut size_given = false;
+    let mut fit_mode: Option<String> = None;
+    let mut output_format: Option<String> = None;
+    let mut output_compression: Option<u64> = None;
 
+    let limits = state.upload_limits.clone();
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Multipart error: {}", e)))?
+        .map_err(|e| crate::proxy::upload::multipart_error(e, &limits))?
     {
         let name = field.name().unwrap_or("").to_string();
 
-        if name == "image" {
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Image read error: {}", e)))?;
-            image_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
-        } else if name == "mask" {
-            let data = field
-                .bytes()
+        // 文件字段逐块读取，超过阈值写入临时文件，超限返回 413
+        if name == "image" || name == "mask" {
+            let upload = crate::proxy::upload::SpooledUpload::from_field(field, &limits).await?;
+            tracing::debug!("[Images] Read '{}' field: {} bytes (on disk: {})", name, upload.len(), upload.is_on_disk());
+            let data = upload
+                .to_base64()
                 .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Mask read error: {}", e)))?;
-            mask_data = Some(base64::engine::general_purpose::STANDARD.encode(data));
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            if name == "image" {
+                image_data = Some(data);
+            } else {
+                mask_data = Some(data);
+            }
         } else if name == "prompt" {
             prompt = field
                 .text()
@@ -997,7 +1805,14 @@ pub async fn handle_images_edits(
         } else if name == "size" {
             if let Ok(val) = field.text().await {
                 size = val;
+                size_given = true;
             }
+        } else if name == "fit" {
+            fit_mode = field.text().await.ok();
+        } else if name == "output_format" {
+            output_format = field.text().await.ok();
+        } else if name == "output_compression" {
+            output_compression = field.text().await.ok().and_then(|v| v.trim().parse().ok());
         } else if name == "response_format" {
             if let Ok(val) = field.text().await {
                 response_format = val;
@@ -1008,6 +1823,10 @@ pub async fn handle_images_edits(
                     model = val;
                 }
             }
+        } else if name == "async" {
+            if let Ok(val) = field.text().await {
+                async_mode = matches!(val.trim(), "true" | "1");
+            }
         }
     }
 
@@ -1017,6 +1836,9 @@ pub async fn handle_images_edits(
     if prompt.is_empty() {
         return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
     }
+    if let Some(rejection) = precheck_image_prompt(&state, &prompt).await {
+        return Ok(rejection);
+    }
 
     tracing::info!(
         "[Images] Edit Request: model={}, prompt={}, n={}, size={}, mask={}, response_format={}",
@@ -1039,153 +1861,59 @@ pub async fn handle_images_edits(
     // But if users see raw text, it means client defaulted to 'url' or we defaulted to 'url'.
     // Let's keep the log to confirm.
 
-    // 1. 获取 Upstream
-    let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-    // Fix: Proper get_token call with correct signature and unwrap (using image_gen quota)
-    let (access_token, project_id, _email) = match token_manager.get_token("image_gen", false, None).await
-    {
-        Ok(t) => t,
-        Err(e) => {
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                format!("Token error: {}", e),
-            ))
-        }
-    };
+    // 1. 账号由每个任务单独获取 (见 ImageBatch)
 
     // 2. 映射配置
-    let mut contents_parts = Vec::new();
-
-    contents_parts.push(json!({
-        "text": format!("Edit this image: {}", prompt)
-    }));
-
+    let mut contents_parts = vec![Part::text(format!("Edit this image: {}", prompt))];
     if let Some(data) = image_data {
-        contents_parts.push(json!({
-            "inlineData": {
-                "mimeType": "image/png",
-                "data": data
-            }
-        }));
+        contents_parts.push(Part::inline_data("image/png", data));
     }
-
     if let Some(data) = mask_data {
-        contents_parts.push(json!({
-            "inlineData": {
-                "mimeType": "image/png",
-                "data": data
-            }
-        }));
+        contents_parts.push(Part::inline_data("image/png", data));
     }
 
     // 构造 Gemini 内网 API Body (Envelope Structure)
-    let gemini_body = json!({
-        "project": project_id,
-        "requestId": format!("img-edit-{}", uuid::Uuid::new_v4()),
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "image_gen",
-        "request": {
-            "contents": [{
-                "role": "user",
-                "parts": contents_parts
-            }],
-            "generationConfig": {
-                "candidateCount": 1,
-                "maxOutputTokens": 8192,
-                "stopSequences": [],
-                "temperature": 1.0,
-                "topP": 0.95,
-                "topK": 40
-            },
-            "safetySettings": [
-                { "category": "HARM_CATEGORY_HARASSMENT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "OFF" },
-                { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "threshold": "OFF" },
-            ]
-        }
-    });
+    let gemini_body = V1InternalRequest::new(
+        &model,
+        "image_gen",
+        GenerateContentRequest {
+            contents: vec![Content::user(contents_parts)],
+            generation_config: Some(GenerationConfig {
+                candidate_count: Some(1),
+                max_output_tokens: Some(8192),
+                stop_sequences: Some(Vec::new()),
+                temperature: Some(1.0),
+                top_p: Some(0.95),
+                top_k: Some(40),
+                ..Default::default()
+            }),
+            safety_settings: Some(SafetySetting::all_off()),
+            ..Default::default()
+        },
+    );
 
-    let mut tasks = Vec::new();
-    for _ in 0..n {
-        let upstream = upstream.clone();
-        let access_token = access_token.clone();
-        let body = gemini_body.clone();
+    let batch = ImageBatch {
+        token_manager: state.token_manager.clone(),
+        upstream: state.upstream.clone(),
+        quota_group: state.token_manager.resolve_quota_group("image_gen", &[&model], &None, &headers),
+        body: gemini_body,
+        request_id_prefix: "img-edit",
+        n,
+        response_format: response_format.clone(),
+        fit: image_fit_from_params(
+            size_given.then_some(size.as_str()),
+            fit_mode.as_deref(),
+            output_format.as_deref(),
+            output_compression,
+        ),
+    };
 
-        tasks.push(tokio::spawn(async move {
-            match upstream
-                .call_v1_internal("generateContent", &access_token, body, None)
-                .await
-            {
-                Ok(response) => {
-                    let status = response.status();
-                    if !status.is_success() {
-                        let err_text = response.text().await.unwrap_or_default();
-                        return Err(format!("Upstream error {}: {}", status, err_text));
-                    }
-                    match response.json::<Value>().await {
-                        Ok(json) => Ok(json),
-                        Err(e) => Err(format!("Parse error: {}", e)),
-                    }
-                }
-                Err(e) => Err(format!("Network error: {}", e)),
-            }
-        }));
+    // [NEW] 异步模式
+    if async_mode {
+        return Ok(spawn_image_job(&state, "edit", batch));
     }
 
-    let mut images: Vec<Value> = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
-
-    for (idx, task) in tasks.into_iter().enumerate() {
-        match task.await {
-            Ok(result) => match result {
-                Ok(gemini_resp) => {
-                    let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
-                    if let Some(parts) = raw
-                        .get("candidates")
-                        .and_then(|c| c.get(0))
-                        .and_then(|cand| cand.get("content"))
-                        .and_then(|content| content.get("parts"))
-                        .and_then(|p| p.as_array())
-                    {
-                        for part in parts {
-                            if let Some(img) = part.get("inlineData") {
-                                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                if !data.is_empty() {
-                                    if response_format == "url" {
-                                        let mime_type = img
-                                            .get("mimeType")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("image/png");
-                                        images.push(json!({
-                                            "url": format!("data:{};base64,{}", mime_type, data)
-                                        }));
-                                    } else {
-                                        images.push(json!({
-                                            "b64_json": data
-                                        }));
-                                    }
-                                    tracing::debug!("[Images] Task {} succeeded", idx);
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("[Images] Task {} failed: {}", idx, e);
-                    errors.push(e);
-                }
-            },
-            Err(e) => {
-                let err_msg = format!("Task join error: {}", e);
-                tracing::error!("[Images] Task {} join error: {}", idx, e);
-                errors.push(err_msg);
-            }
-        }
-    }
+    let (images, errors) = batch.run(None).await;
 
     if images.is_empty() {
         let error_msg = if !errors.is_empty() {
@@ -1221,5 +1949,5 @@ pub async fn handle_images_edits(
         "data": images
     });
 
-    Ok(Json(openai_response))
+    Ok(Json(openai_response).into_response())
 }
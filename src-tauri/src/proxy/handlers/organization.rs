@@ -0,0 +1,265 @@
+// Anthropic 组织端点兼容处理器
+//
+// 部分企业版 SDK 初始化时会探测 /v1/organizations/me 与用量 / 费用报告端点，经过反代时因 404 直接失败。
+// 这里提供最小的兼容实现：组织信息来自配置，用量报告由本地请求日志 (proxy_db) 按时间桶汇总，
+// 费用报告恒为空 (反代不产生账单)。
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::server::AppState;
+
+/// 时间桶宽度：(毫秒, 默认桶数, 最大桶数)，与官方 API 的 limit 默认值 / 上限一致
+fn bucket_width(width: &str) -> Option<(i64, usize, usize)> {
+    match width {
+        "1d" => Some((86_400_000, 7, 31)),
+        "1h" => Some((3_600_000, 24, 168)),
+        "1m" => Some((60_000, 60, 1440)),
+        _ => None,
+    }
+}
+
+fn rfc3339(ms: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_time(value: Option<&String>) -> Option<i64> {
+    value
+        .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+        .map(|t| t.timestamp_millis())
+}
+
+fn error_response(status: StatusCode, error_type: &str, message: &str) -> Response {
+    (status, Json(json!({ "type": "error", "error": { "type": error_type, "message": message } }))).into_response()
+}
+
+fn disabled() -> Response {
+    error_response(StatusCode::NOT_FOUND, "not_found_error", "Organization endpoints are disabled on this proxy")
+}
+
+/// 查询参数解析出的时间范围
+#[derive(Debug, PartialEq, Eq)]
+struct BucketRange {
+    start_ms: i64,
+    width_ms: i64,
+    count: usize,
+    /// 超出 limit 的下一页起点
+    next_start_ms: Option<i64>,
+}
+
+/// starting_at 缺省时取最近 limit 个桶；page 为上一页返回的 next_page (即下一页的起点)
+fn bucket_range(params: &HashMap<String, String>, now_ms: i64) -> Result<BucketRange, String> {
+    let width = params.get("bucket_width").map(String::as_str).unwrap_or("1d");
+    let (width_ms, default_limit, max_limit) =
+        bucket_width(width).ok_or_else(|| format!("bucket_width: unsupported value '{}'", width))?;
+    let limit = match params.get("limit") {
+        Some(v) => v.parse::<usize>().map_err(|_| format!("limit: invalid value '{}'", v))?.clamp(1, max_limit),
+        None => default_limit,
+    };
+    let now_bucket = now_ms - now_ms.rem_euclid(width_ms);
+    let start = parse_time(params.get("page"))
+        .or_else(|| parse_time(params.get("starting_at")))
+        .unwrap_or(now_bucket - (limit as i64 - 1) * width_ms);
+    let start_ms = start - start.rem_euclid(width_ms);
+    let end_ms = parse_time(params.get("ending_at")).unwrap_or(now_bucket + width_ms);
+    if end_ms <= start_ms {
+        return Err("ending_at must be after starting_at".to_string());
+    }
+    let total = ((end_ms - start_ms + width_ms - 1) / width_ms) as usize;
+    let count = total.min(limit);
+    Ok(BucketRange {
+        start_ms,
+        width_ms,
+        count,
+        next_start_ms: (total > limit).then(|| start_ms + limit as i64 * width_ms),
+    })
+}
+
+fn usage_result(logs: &[&ProxyRequestLog], model: Option<&str>) -> Value {
+    let input: u64 = logs.iter().map(|l| l.input_tokens.unwrap_or(0) as u64).sum();
+    // 官方的 output_tokens 包含思考 token
+    let output: u64 = logs
+        .iter()
+        .map(|l| l.output_tokens.unwrap_or(0) as u64 + l.thinking_tokens.unwrap_or(0) as u64)
+        .sum();
+    json!({
+        "uncached_input_tokens": input,
+        "cache_creation": { "ephemeral_1h_input_tokens": 0, "ephemeral_5m_input_tokens": 0 },
+        "cache_read_input_tokens": 0,
+        "output_tokens": output,
+        "server_tool_use": { "web_search_requests": 0 },
+        "api_key_id": null,
+        "workspace_id": null,
+        "model": model,
+        "service_tier": null,
+        "context_window": null,
+    })
+}
+
+/// 按时间桶 (及可选的模型) 汇总请求日志
+fn usage_buckets(logs: &[ProxyRequestLog], range: &BucketRange, by_model: bool) -> Vec<Value> {
+    (0..range.count)
+        .map(|i| {
+            let start = range.start_ms + i as i64 * range.width_ms;
+            let end = start + range.width_ms;
+            let in_bucket: Vec<&ProxyRequestLog> =
+                logs.iter().filter(|l| l.timestamp >= start && l.timestamp < end).collect();
+            let results = if by_model {
+                let mut groups: BTreeMap<String, Vec<&ProxyRequestLog>> = BTreeMap::new();
+                for log in in_bucket {
+                    let model = log.mapped_model.clone().or_else(|| log.model.clone()).unwrap_or_default();
+                    groups.entry(model).or_default().push(log);
+                }
+                groups.iter().map(|(model, logs)| usage_result(logs, Some(model))).collect()
+            } else {
+                vec![usage_result(&in_bucket, None)]
+            };
+            json!({ "starting_at": rfc3339(start), "ending_at": rfc3339(end), "results": results })
+        })
+        .collect()
+}
+
+fn page(data: Vec<Value>, range: &BucketRange) -> Value {
+    json!({
+        "data": data,
+        "has_more": range.next_start_ms.is_some(),
+        "next_page": range.next_start_ms.map(rfc3339),
+    })
+}
+
+/// GET /v1/organizations/me
+pub async fn handle_get_organization(State(state): State<AppState>) -> Response {
+    let config = state.experimental.read().await.organization.clone();
+    if !config.enabled {
+        return disabled();
+    }
+    Json(json!({ "id": config.id, "type": "organization", "name": config.name })).into_response()
+}
+
+/// GET /v1/organizations/usage_report/messages
+pub async fn handle_usage_report(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if !state.experimental.read().await.organization.enabled {
+        return disabled();
+    }
+    let range = match bucket_range(&params, Utc::now().timestamp_millis()) {
+        Ok(range) => range,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message),
+    };
+    let by_model = ["group_by[]", "group_by"]
+        .iter()
+        .any(|key| params.get(*key).is_some_and(|v| v.split(',').any(|g| g.trim() == "model")));
+
+    let (start, end) = (range.start_ms, range.start_ms + range.count as i64 * range.width_ms);
+    let logs = tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_logs_between(start, end))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+        .unwrap_or_else(|e| {
+            tracing::warn!("[Organization] Failed to read request logs for usage report: {}", e);
+            Vec::new()
+        });
+    Json(page(usage_buckets(&logs, &range, by_model), &range)).into_response()
+}
+
+/// GET /v1/organizations/cost_report (反代不产生费用，各时间桶结果为空)
+pub async fn handle_cost_report(
+    State(state): State<AppState>,
+    Query(mut params): Query<HashMap<String, String>>,
+) -> Response {
+    if !state.experimental.read().await.organization.enabled {
+        return disabled();
+    }
+    // 费用报告只支持按天统计
+    params.insert("bucket_width".to_string(), "1d".to_string());
+    let range = match bucket_range(&params, Utc::now().timestamp_millis()) {
+        Ok(range) => range,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message),
+    };
+    let data = (0..range.count)
+        .map(|i| {
+            let start = range.start_ms + i as i64 * range.width_ms;
+            json!({ "starting_at": rfc3339(start), "ending_at": rfc3339(start + range.width_ms), "results": [] })
+        })
+        .collect();
+    Json(page(data, &range)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400_000;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn log(timestamp: i64, model: &str, input: u32, output: u32, thinking: u32) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: timestamp.to_string(),
+            timestamp,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 200,
+            duration: 10,
+            model: Some(model.to_string()),
+            mapped_model: None,
+            account_email: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            thinking_tokens: Some(thinking),
+            client: None,
+            experiment: None,
+            session_id: None,
+            timing: None,
+        }
+    }
+
+    #[test]
+    fn test_bucket_range_aligns_and_paginates() {
+        let now = 10 * DAY + 5_000;
+        let range = bucket_range(&params(&[]), now).unwrap();
+        assert_eq!(range, BucketRange { start_ms: 4 * DAY, width_ms: DAY, count: 7, next_start_ms: None });
+
+        let range = bucket_range(
+            &params(&[("starting_at", "1970-01-02T12:00:00Z"), ("ending_at", "1970-01-06T00:00:00Z"), ("limit", "2")]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(range.start_ms, DAY);
+        assert_eq!(range.count, 2);
+        assert_eq!(range.next_start_ms, Some(3 * DAY));
+
+        assert!(bucket_range(&params(&[("bucket_width", "1w")]), now).is_err());
+    }
+
+    #[test]
+    fn test_usage_buckets_group_by_model() {
+        let range = BucketRange { start_ms: 0, width_ms: DAY, count: 2, next_start_ms: None };
+        let logs = vec![log(100, "claude-sonnet-4-5", 10, 5, 3), log(200, "gemini-2.5-flash", 7, 1, 0), log(DAY + 1, "claude-sonnet-4-5", 1, 1, 0)];
+
+        let data = usage_buckets(&logs, &range, false);
+        assert_eq!(data[0]["starting_at"], "1970-01-01T00:00:00Z");
+        assert_eq!(data[0]["results"][0]["uncached_input_tokens"], 17);
+        assert_eq!(data[0]["results"][0]["output_tokens"], 9);
+        assert_eq!(data[1]["results"][0]["output_tokens"], 1);
+
+        let data = usage_buckets(&logs, &range, true);
+        let models: Vec<_> = data[0]["results"].as_array().unwrap().iter().map(|r| r["model"].clone()).collect();
+        assert_eq!(models, vec![json!("claude-sonnet-4-5"), json!("gemini-2.5-flash")]);
+    }
+}
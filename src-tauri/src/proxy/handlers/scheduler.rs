@@ -0,0 +1,57 @@
+// 调度决策预演处理器
+//
+// 提供 /internal/scheduler/dry-run 端点：给定模型与可选 session_id，
+// 报告 get_token 会选择哪个账号以及原因 (粘性绑定 / 60s 锁定 / 等级优先 / 跳过的限流账号)，
+// 用于排查 "为什么总是用账号 X" 一类问题。预演不会修改任何调度状态。
+// 报告中包含账号邮箱与会话绑定，需携带 X-Admin-Key (见 middleware::auth)。
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct DryRunQuery {
+    /// 客户端请求的模型名 (会经过模型映射)
+    pub model: String,
+    pub session_id: Option<String>,
+    /// 覆盖由模型推导出的配额分组 (agent / image_gen / ...)
+    pub quota_group: Option<String>,
+}
+
+/// GET /internal/scheduler/dry-run?model=...&session_id=...
+pub async fn handle_scheduler_dry_run(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    Query(query): Query<DryRunQuery>,
+) -> impl IntoResponse {
+    if query.model.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "Missing 'model' parameter").into_response();
+    }
+
+    let mapped_model = state.resolve_model(&query.model).await;
+    let request_type =
+        crate::proxy::mappers::common_utils::resolve_request_config(&query.model, &mapped_model, &None).request_type;
+    let quota_group = query.quota_group.clone().unwrap_or_else(|| {
+        state
+            .token_manager
+            .resolve_quota_group(&request_type, &[&query.model, &mapped_model], &None, &headers)
+    });
+
+    let decision = state
+        .token_manager
+        .explain_selection(&request_type, &quota_group, query.session_id.as_deref(), Some(&mapped_model))
+        .await;
+
+    Json(json!({
+        "model": query.model,
+        "mapped_model": mapped_model,
+        "decision": decision,
+    }))
+    .into_response()
+}
@@ -0,0 +1,53 @@
+// 账号池状态处理器
+//
+// 提供 /status 端点：列出账号池中每个账号的限流状态、不可用模型、累计使用统计与配额消耗预测
+// (预计多少小时后耗尽、是否会先于耗尽重置)，便于外部监控在配额用完前发出预警。
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+
+/// GET /status
+pub async fn handle_status(State(state): State<AppState>) -> impl IntoResponse {
+    let token_manager = &state.token_manager;
+    let forecasts = token_manager.quota_forecasts();
+    let stats = token_manager.account_stats();
+    let forecast_rotate_hours = token_manager.get_sticky_config().await.forecast_rotate_hours;
+
+    let accounts: Vec<_> = token_manager
+        .list_accounts()
+        .into_iter()
+        .map(|(account_id, email, tier)| {
+            let forecast = forecasts.get(&account_id);
+            json!({
+                "account_id": account_id,
+                "email": email,
+                "subscription_tier": tier,
+                "rate_limited": token_manager.is_rate_limited(&account_id),
+                // 从 404 / 403 错误中学习到的不可用模型 (调度时跳过该账号)
+                "denied_models": token_manager.get_denied_models(&email),
+                "stats": stats.get(&account_id),
+                "forecast": forecast,
+                // 预计在配额重置前耗尽
+                "exhaustion_warning": forecast.is_some_and(|f| !f.resets_before_exhaustion),
+            })
+        })
+        .collect();
+
+    let connectivity = state.connectivity.snapshot();
+    Json(json!({
+        // 离线检测判定上游不可达时为 "offline"
+        "status": if connectivity.status == "offline" { "offline" } else { "ok" },
+        "connectivity": connectivity,
+        "account_count": accounts.len(),
+        "forecast_rotate_hours": forecast_rotate_hours,
+        // 粘性会话绑定数量与流转统计 (新建 / 换绑 / 过期 / 淘汰)
+        "session_bindings": token_manager.session_binding_stats(),
+        // 准入控制：并发数与各优先级排队深度
+        "admission": state.admission.snapshot(),
+        // 进程启动以来被隔离的 panic 次数 (handler / 流式响应体)
+        "panics": crate::proxy::middleware::panic_guard::panic_counts(),
+        "accounts": accounts,
+    }))
+}
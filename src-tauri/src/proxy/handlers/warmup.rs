@@ -97,6 +97,8 @@ pub async fn handle_warmup(
             metadata: None,
             thinking: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         match crate::proxy::mappers::claude::transform_claude_request_in(
@@ -0,0 +1,101 @@
+// 空闲预热 (Idle Warm-up)
+// 长时间空闲后的第一个请求要依次付出 token 刷新、project_id 解析、DNS + TLS 握手的延迟。
+// 这里在后台定期检测：系统从休眠中恢复 (两次检测之间的墙钟间隔远大于检测周期)，或空闲超过配置时长时，
+// 提前刷新即将过期的 token、补全缺失的 project_id，并预建立到上游端点的连接。
+
+use crate::proxy::config::{ExperimentalConfig, IdleWarmupConfig};
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// token 刷新的额外余量：保证到下一次预热之前 token 仍然有效
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmupReason {
+    /// 系统休眠唤醒
+    Resume,
+    /// 空闲超过配置时长
+    Idle,
+}
+
+/// 判断本次检测是否需要预热
+/// - `wall_gap_secs`: 距离上一次检测的墙钟秒数 (休眠期间检测循环暂停，唤醒后该值会远大于检测周期)
+/// - `idle_secs`: 距离最近一次请求的秒数
+/// - `since_warmup_secs`: 距离上一次预热的秒数
+pub fn decide(config: &IdleWarmupConfig, wall_gap_secs: i64, idle_secs: i64, since_warmup_secs: i64) -> Option<WarmupReason> {
+    if !config.enabled {
+        return None;
+    }
+    let interval = config.check_interval_secs.max(1) as i64;
+    if wall_gap_secs > interval * 2 + 30 {
+        return Some(WarmupReason::Resume);
+    }
+    let idle_threshold = (config.idle_minutes.max(1) * 60) as i64;
+    if idle_secs >= idle_threshold && since_warmup_secs >= idle_threshold {
+        return Some(WarmupReason::Idle);
+    }
+    None
+}
+
+async fn warm_up(config: &IdleWarmupConfig, reason: WarmupReason, token_manager: &TokenManager, upstream: &UpstreamClient) {
+    let refresh_within = (config.idle_minutes.max(1) * 60) as i64 + REFRESH_MARGIN_SECS;
+    let (warmed, failed) = token_manager.warm_up_idle_accounts(refresh_within).await;
+    let connected = upstream.warm_connections().await;
+    tracing::info!(
+        "[Idle-Warmup] {:?}: {} 个账号已预热, {} 个失败, {} 个上游端点已连接",
+        reason,
+        warmed,
+        failed,
+        connected
+    );
+}
+
+/// 启动后台检测任务 (服务停止时由调用方 abort)
+pub fn spawn(
+    token_manager: Arc<TokenManager>,
+    upstream: Arc<UpstreamClient>,
+    experimental: Arc<RwLock<ExperimentalConfig>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_tick = chrono::Utc::now().timestamp();
+        let mut last_warmup = last_tick;
+        loop {
+            let config = experimental.read().await.idle_warmup.clone();
+            tokio::time::sleep(std::time::Duration::from_secs(config.check_interval_secs.max(1))).await;
+
+            let now = chrono::Utc::now().timestamp();
+            let decision = decide(&config, now - last_tick, token_manager.idle_secs(), now - last_warmup);
+            last_tick = now;
+            if let Some(reason) = decision {
+                warm_up(&config, reason, &token_manager, &upstream).await;
+                last_warmup = chrono::Utc::now().timestamp();
+                last_tick = last_warmup;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decide_resume_and_idle() {
+        let config = IdleWarmupConfig { enabled: true, idle_minutes: 30, check_interval_secs: 60 };
+
+        // 正常检测周期内且活跃：无需预热
+        assert_eq!(decide(&config, 61, 10, 3600), None);
+        // 墙钟跳变 (休眠唤醒)：无论是否空闲都预热
+        assert_eq!(decide(&config, 4 * 3600, 10, 10), Some(WarmupReason::Resume));
+        // 空闲超过 30 分钟，且距上次预热也超过 30 分钟
+        assert_eq!(decide(&config, 60, 1800, 1800), Some(WarmupReason::Idle));
+        // 刚预热过，空闲期间不重复预热
+        assert_eq!(decide(&config, 60, 3000, 600), None);
+
+        let disabled = IdleWarmupConfig { enabled: false, ..config };
+        assert_eq!(decide(&disabled, 4 * 3600, 7200, 7200), None);
+    }
+}
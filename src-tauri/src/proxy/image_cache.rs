@@ -0,0 +1,122 @@
+// 图片生成结果缓存 (Image Result Cache)
+// Agent 经常对同一张示意图重复发起完全相同的生成请求，每次都消耗 image_gen 配额。
+// 这里把最近的生成结果按 (最终提示词 / 宽高比 / 模型 / 输出参数) 的哈希缓存到磁盘，
+// 容量超出时按最近使用时间淘汰 (LRU)，过期条目在读取时丢弃。缓存与账号无关，整个账号池共享。
+// 客户端可通过请求体 `cache_bust: true` 或 `Cache-Control: no-cache` 跳过缓存。
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CACHE_DIR: &str = "image_cache";
+
+pub struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn new(data_dir: &Path) -> Self {
+        Self { dir: data_dir.join(CACHE_DIR) }
+    }
+
+    /// 由生成请求的各组成部分计算缓存键
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// 读取未过期的缓存结果，命中时刷新其最近使用时间
+    pub fn get(&self, key: &str, ttl: Duration) -> Option<Value> {
+        let path = self.entry_path(key);
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if SystemTime::now().duration_since(modified).unwrap_or_default() > ttl {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+        let data: Value = serde_json::from_slice(&std::fs::read(&path).ok()?).ok()?;
+        if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    /// 写入结果并按最近使用时间淘汰超出容量的条目
+    pub fn put(&self, key: &str, data: &Value, max_entries: usize) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        std::fs::write(self.entry_path(key), data.to_string()).map_err(|e| e.to_string())?;
+        self.evict(max_entries);
+        Ok(())
+    }
+
+    fn evict(&self, max_entries: usize) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, PathBuf)> = entries
+            .flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+            .collect();
+        if files.len() <= max_entries {
+            return;
+        }
+        files.sort_by_key(|(modified, _)| *modified);
+        for (_, path) in files.iter().take(files.len() - max_entries) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_cache() -> (ImageCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("image_cache_{}", uuid::Uuid::new_v4()));
+        (ImageCache::new(&dir), dir)
+    }
+
+    #[test]
+    fn test_get_put_and_ttl() {
+        let (cache, dir) = temp_cache();
+        let key = ImageCache::key(&["a diagram", "16:9", "gemini-3-pro-image"]);
+        assert_ne!(key, ImageCache::key(&["a diagram", "1:1", "gemini-3-pro-image"]));
+
+        assert!(cache.get(&key, Duration::from_secs(60)).is_none());
+        cache.put(&key, &json!([{ "b64_json": "AAA" }]), 8).unwrap();
+        assert_eq!(cache.get(&key, Duration::from_secs(60)).unwrap()[0]["b64_json"], "AAA");
+        // 过期条目读取时丢弃
+        assert!(cache.get(&key, Duration::ZERO).is_none());
+        assert!(cache.get(&key, Duration::from_secs(60)).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let (cache, dir) = temp_cache();
+        let ttl = Duration::from_secs(60);
+        cache.put("first", &json!(1), 2).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("second", &json!(2), 2).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        // 命中刷新最近使用时间，first 不再是最旧的条目
+        assert!(cache.get("first", ttl).is_some());
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("third", &json!(3), 2).unwrap();
+
+        assert!(cache.get("first", ttl).is_some());
+        assert!(cache.get("second", ttl).is_none());
+        assert!(cache.get("third", ttl).is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,210 @@
+// 图片生成异步任务队列
+// 大批量图片生成会阻塞 HTTP 请求直到所有任务完成。异步模式下立即返回 202 + job id，
+// 客户端通过轮询端点获取进度并增量拉取结果，UI 通过 Tauri 事件 `proxy://image-job` 接收进度。
+
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::Emitter;
+
+/// 已结束任务的保留时长 (秒)
+const FINISHED_JOB_TTL_SECS: i64 = 3600;
+
+/// Tauri 进度事件名
+pub const IMAGE_JOB_EVENT: &str = "proxy://image-job";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageJobStatus {
+    Queued,
+    Running,
+    /// 至少生成了一张图片
+    Completed,
+    /// 全部失败
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+struct ImageJob {
+    kind: &'static str,
+    status: ImageJobStatus,
+    created: i64,
+    finished_at: Option<i64>,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    /// 按完成顺序追加
+    images: Vec<Value>,
+    errors: Vec<String>,
+}
+
+/// 进度事件载荷
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageJobProgress {
+    pub id: String,
+    pub kind: &'static str,
+    pub status: ImageJobStatus,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// 图片任务存储 (内存)
+pub struct ImageJobStore {
+    jobs: DashMap<String, ImageJob>,
+    app_handle: Option<tauri::AppHandle>,
+}
+
+impl ImageJobStore {
+    pub fn new(app_handle: Option<tauri::AppHandle>) -> Self {
+        Self {
+            jobs: DashMap::new(),
+            app_handle,
+        }
+    }
+
+    /// 创建任务，返回 job id (`kind` 为 "generation" / "edit")
+    pub fn create(&self, kind: &'static str, total: usize) -> String {
+        self.cleanup_finished();
+        let id = format!("imgjob_{}", uuid::Uuid::new_v4().simple());
+        self.jobs.insert(
+            id.clone(),
+            ImageJob {
+                kind,
+                status: ImageJobStatus::Queued,
+                created: chrono::Utc::now().timestamp(),
+                finished_at: None,
+                total,
+                succeeded: 0,
+                failed: 0,
+                images: Vec::new(),
+                errors: Vec::new(),
+            },
+        );
+        self.emit_progress(&id);
+        id
+    }
+
+    pub fn mark_running(&self, id: &str) {
+        if let Some(mut job) = self.jobs.get_mut(id) {
+            job.status = ImageJobStatus::Running;
+        }
+        self.emit_progress(id);
+    }
+
+    /// 记录单个子任务的结果
+    pub fn record(&self, id: &str, result: &Result<Vec<Value>, String>) {
+        if let Some(mut job) = self.jobs.get_mut(id) {
+            match result {
+                Ok(images) if !images.is_empty() => {
+                    job.succeeded += 1;
+                    job.images.extend(images.iter().cloned());
+                }
+                Ok(_) => {
+                    job.failed += 1;
+                    job.errors.push("No image in upstream response".to_string());
+                }
+                Err(e) => {
+                    job.failed += 1;
+                    job.errors.push(e.clone());
+                }
+            }
+        }
+        self.emit_progress(id);
+    }
+
+    /// 所有子任务结束
+    pub fn finish(&self, id: &str) {
+        if let Some(mut job) = self.jobs.get_mut(id) {
+            job.status = if job.images.is_empty() {
+                ImageJobStatus::Failed
+            } else {
+                ImageJobStatus::Completed
+            };
+            job.finished_at = Some(chrono::Utc::now().timestamp());
+        }
+        self.emit_progress(id);
+    }
+
+    /// 任务快照；`since` 为已拉取的图片数量，只返回之后的新结果
+    pub fn snapshot(&self, id: &str, since: usize) -> Option<Value> {
+        let job = self.jobs.get(id)?;
+        Some(json!({
+            "id": id,
+            "object": "image.job",
+            "kind": job.kind,
+            "status": job.status,
+            "created": job.created,
+            "finished_at": job.finished_at,
+            "progress": {
+                "total": job.total,
+                "succeeded": job.succeeded,
+                "failed": job.failed,
+            },
+            "offset": since.min(job.images.len()),
+            "next_offset": job.images.len(),
+            "data": job.images.iter().skip(since).cloned().collect::<Vec<_>>(),
+            "errors": job.errors,
+        }))
+    }
+
+    fn progress(&self, id: &str) -> Option<ImageJobProgress> {
+        self.jobs.get(id).map(|job| ImageJobProgress {
+            id: id.to_string(),
+            kind: job.kind,
+            status: job.status,
+            total: job.total,
+            succeeded: job.succeeded,
+            failed: job.failed,
+        })
+    }
+
+    fn emit_progress(&self, id: &str) {
+        if let (Some(app), Some(progress)) = (&self.app_handle, self.progress(id)) {
+            let _ = app.emit(IMAGE_JOB_EVENT, &progress);
+        }
+    }
+
+    fn cleanup_finished(&self) {
+        let now = chrono::Utc::now().timestamp();
+        self.jobs
+            .retain(|_, job| job.finished_at.is_none_or(|t| now - t < FINISHED_JOB_TTL_SECS));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_progress_and_incremental_fetch() {
+        let store = ImageJobStore::new(None);
+        let id = store.create("generation", 3);
+        assert_eq!(store.snapshot(&id, 0).unwrap()["status"], "queued");
+
+        store.mark_running(&id);
+        store.record(&id, &Ok(vec![json!({ "b64_json": "a" })]));
+        store.record(&id, &Err("Upstream error 500".to_string()));
+        let snap = store.snapshot(&id, 0).unwrap();
+        assert_eq!(snap["status"], "running");
+        assert_eq!(snap["progress"]["succeeded"], 1);
+        assert_eq!(snap["progress"]["failed"], 1);
+        assert_eq!(snap["next_offset"], 1);
+
+        store.record(&id, &Ok(vec![json!({ "b64_json": "b" })]));
+        store.finish(&id);
+        let snap = store.snapshot(&id, 1).unwrap();
+        assert_eq!(snap["status"], "completed");
+        assert_eq!(snap["data"], json!([{ "b64_json": "b" }]));
+        assert!(store.snapshot("imgjob_missing", 0).is_none());
+    }
+
+    #[test]
+    fn test_all_failed_job_is_failed() {
+        let store = ImageJobStore::new(None);
+        let id = store.create("edit", 1);
+        store.record(&id, &Err("boom".to_string()));
+        store.finish(&id);
+        assert_eq!(store.snapshot(&id, 0).unwrap()["status"], "failed");
+    }
+}
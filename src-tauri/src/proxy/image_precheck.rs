@@ -0,0 +1,172 @@
+// 图片提示词安全预检
+// 被上游拦截的图片请求同样消耗 image_gen 配额。开启后先用轻量模型 (flash-lite) 对提示词做一次分类，
+// 明显会被拦截的提示词直接返回 400，并在错误体中附带改写建议，而不是一个空的上游拒绝。
+
+use crate::proxy::config::ImageSafetyPrecheckConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::TokenManager;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CLASSIFIER_INSTRUCTION: &str = "You are a content-policy classifier for an image generation service. \
+Decide whether the user's image prompt would very likely be refused by a mainstream image model's safety filters \
+(sexual content involving minors, explicit sexual content, graphic violence or gore, real-person likeness abuse, \
+hateful symbols, instructions for weapons). Only flag prompts that are clearly over the line; artistic, fictional \
+or mildly edgy prompts are fine. If you flag a prompt, provide a rewritten prompt that keeps the user's intent but \
+would pass the filters.";
+
+/// 预检结论
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecheckVerdict {
+    pub blocked: bool,
+    pub reason: Option<String>,
+    pub categories: Vec<String>,
+    /// 改写建议
+    pub suggestion: Option<String>,
+}
+
+impl PrecheckVerdict {
+    /// OpenAI 风格的错误体
+    pub fn to_error_body(&self) -> Value {
+        let reason = self
+            .reason
+            .clone()
+            .unwrap_or_else(|| "The prompt is likely to be rejected by the image safety system".to_string());
+        json!({
+            "error": {
+                "message": format!("Prompt blocked by safety pre-check: {}", reason),
+                "type": "invalid_request_error",
+                "param": "prompt",
+                "code": "content_policy_violation",
+                "categories": self.categories,
+                "suggested_prompt": self.suggestion,
+            }
+        })
+    }
+}
+
+/// 构建分类请求 (v1internal generateContent 信封)
+pub fn build_precheck_body(prompt: &str, model: &str, project_id: &str) -> Value {
+    json!({
+        "project": project_id,
+        "requestId": format!("img-precheck-{}", uuid::Uuid::new_v4()),
+        "model": model,
+        "userAgent": "antigravity",
+        "requestType": "agent",
+        "request": {
+            "systemInstruction": { "role": "user", "parts": [{ "text": CLASSIFIER_INSTRUCTION }] },
+            "contents": [{ "role": "user", "parts": [{ "text": prompt }] }],
+            "generationConfig": {
+                "temperature": 0,
+                "maxOutputTokens": 512,
+                "responseMimeType": "application/json",
+                "responseSchema": {
+                    "type": "OBJECT",
+                    "properties": {
+                        "blocked": { "type": "BOOLEAN" },
+                        "reason": { "type": "STRING" },
+                        "categories": { "type": "ARRAY", "items": { "type": "STRING" } },
+                        "suggested_prompt": { "type": "STRING" }
+                    },
+                    "required": ["blocked"]
+                }
+            }
+        }
+    })
+}
+
+/// 解析分类结果 (无法解析时返回 None)
+pub fn parse_verdict(response: &Value) -> Option<PrecheckVerdict> {
+    let raw = response.get("response").unwrap_or(response);
+    let text: String = raw["candidates"][0]["content"]["parts"]
+        .as_array()?
+        .iter()
+        .filter(|p| !p["thought"].as_bool().unwrap_or(false))
+        .filter_map(|p| p["text"].as_str())
+        .collect();
+    let verdict: Value = serde_json::from_str(text.trim()).ok()?;
+    let non_empty = |v: &Value| v.as_str().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+
+    Some(PrecheckVerdict {
+        blocked: verdict["blocked"].as_bool()?,
+        reason: non_empty(&verdict["reason"]),
+        categories: verdict["categories"]
+            .as_array()
+            .map(|arr| arr.iter().filter_map(|c| c.as_str().map(str::to_string)).collect())
+            .unwrap_or_default(),
+        suggestion: non_empty(&verdict["suggested_prompt"]),
+    })
+}
+
+/// 执行预检；预检自身失败 / 超时返回 None (放行)
+pub async fn precheck_prompt(
+    token_manager: &Arc<TokenManager>,
+    upstream: &Arc<UpstreamClient>,
+    prompt: &str,
+    config: &ImageSafetyPrecheckConfig,
+) -> Option<PrecheckVerdict> {
+    let run = async {
+        let (access_token, project_id, email) = token_manager
+            .get_token("agent", "agent", false, None, Some(&config.model))
+            .await?;
+        let body = build_precheck_body(prompt, &config.model, &project_id);
+        let response = upstream
+            .call_v1_internal("generateContent", &access_token, body, None)
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("HTTP {}", status.as_u16()));
+        }
+        let value: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse error: {}", e))?;
+        if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&value) {
+            token_manager.record_usage(&email, tokens);
+        }
+        parse_verdict(&value).ok_or_else(|| "Unparseable classifier output".to_string())
+    };
+
+    match tokio::time::timeout(Duration::from_millis(config.timeout_ms), run).await {
+        Ok(Ok(verdict)) => Some(verdict),
+        Ok(Err(e)) => {
+            tracing::warn!("[Images] Safety pre-check failed, letting request through: {}", e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("[Images] Safety pre-check timed out after {}ms, letting request through", config.timeout_ms);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier_response(text: &str) -> Value {
+        json!({ "response": { "candidates": [{ "content": { "parts": [{ "text": text }] } }] } })
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        let verdict = parse_verdict(&classifier_response(
+            r#"{"blocked": true, "reason": "graphic gore", "categories": ["violence"], "suggested_prompt": "a dramatic battle scene"}"#,
+        ))
+        .unwrap();
+        assert!(verdict.blocked);
+        assert_eq!(verdict.categories, vec!["violence"]);
+
+        let body = verdict.to_error_body();
+        assert_eq!(body["error"]["code"], "content_policy_violation");
+        assert_eq!(body["error"]["suggested_prompt"], "a dramatic battle scene");
+        assert!(body["error"]["message"].as_str().unwrap().contains("graphic gore"));
+
+        let ok = parse_verdict(&classifier_response(r#"{"blocked": false, "reason": ""}"#)).unwrap();
+        assert!(!ok.blocked);
+        assert!(ok.reason.is_none());
+
+        assert!(parse_verdict(&classifier_response("not json")).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+// 进行中的流式请求 (Requests in Flight)
+// 记录当前正在向客户端输出的流：追踪 ID、客户端、模型、账号、持续时间与已发送字节数。
+// 代理一侧可以强制终止某条流 (例如 Agent 陷入失控循环持续消耗配额)：
+// 终止时向客户端补发一条 SSE error 事件后结束响应体，上游连接随响应体一同释放。
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::oneshot;
+
+/// 单条进行中的流
+struct Entry {
+    trace_id: String,
+    path: String,
+    client: Option<String>,
+    model: Option<String>,
+    account: Option<String>,
+    started: Instant,
+    started_at: i64,
+    bytes_sent: Arc<AtomicU64>,
+    kill_tx: Option<oneshot::Sender<()>>,
+}
+
+/// 进行中的流 (管理端点 / 桌面端展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct InflightRequest {
+    pub trace_id: String,
+    pub path: String,
+    pub client: Option<String>,
+    pub model: Option<String>,
+    pub account: Option<String>,
+    /// 开始时间 (毫秒时间戳)
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub bytes_sent: u64,
+}
+
+/// 新登记的流所需的描述信息
+pub struct InflightInfo {
+    pub trace_id: String,
+    pub path: String,
+    pub client: Option<String>,
+    pub model: Option<String>,
+    pub account: Option<String>,
+}
+
+/// 流的登记凭证：计入已发送字节，drop 时自动注销
+pub struct InflightGuard {
+    registry: Arc<InflightRegistry>,
+    key: u64,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl InflightGuard {
+    pub fn add_bytes(&self, n: usize) {
+        self.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut entries) = self.registry.entries.lock() {
+            entries.remove(&self.key);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InflightRegistry {
+    // 同一追踪 ID 可能被客户端复用，内部以自增序号区分
+    entries: Mutex<HashMap<u64, Entry>>,
+    next_key: AtomicU64,
+}
+
+impl InflightRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一条新的流，返回登记凭证与强制终止信号
+    pub fn register(self: &Arc<Self>, info: InflightInfo) -> (InflightGuard, oneshot::Receiver<()>) {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let (kill_tx, killed) = oneshot::channel();
+        let entry = Entry {
+            trace_id: info.trace_id,
+            path: info.path,
+            client: info.client,
+            model: info.model,
+            account: info.account,
+            started: Instant::now(),
+            started_at: chrono::Utc::now().timestamp_millis(),
+            bytes_sent: bytes_sent.clone(),
+            kill_tx: Some(kill_tx),
+        };
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, entry);
+        }
+        let guard = InflightGuard {
+            registry: self.clone(),
+            key,
+            bytes_sent,
+        };
+        (guard, killed)
+    }
+
+    /// 当前所有进行中的流 (按开始时间排序，最早的在前)
+    pub fn list(&self) -> Vec<InflightRequest> {
+        let Ok(entries) = self.entries.lock() else {
+            return Vec::new();
+        };
+        let mut list: Vec<_> = entries
+            .values()
+            .map(|e| InflightRequest {
+                trace_id: e.trace_id.clone(),
+                path: e.path.clone(),
+                client: e.client.clone(),
+                model: e.model.clone(),
+                account: e.account.clone(),
+                started_at: e.started_at,
+                duration_ms: e.started.elapsed().as_millis() as u64,
+                bytes_sent: e.bytes_sent.load(Ordering::Relaxed),
+            })
+            .collect();
+        list.sort_by_key(|r| r.started_at);
+        list
+    }
+
+    /// 强制终止指定追踪 ID 的流，返回终止的数量
+    pub fn kill(&self, trace_id: &str) -> usize {
+        let Ok(mut entries) = self.entries.lock() else {
+            return 0;
+        };
+        let mut killed = 0;
+        for entry in entries.values_mut().filter(|e| e.trace_id == trace_id) {
+            if let Some(tx) = entry.kill_tx.take() {
+                if tx.send(()).is_ok() {
+                    killed += 1;
+                }
+            }
+        }
+        if killed > 0 {
+            tracing::warn!("[Inflight] Terminated {} stream(s) with trace_id {}", killed, trace_id);
+        }
+        killed
+    }
+}
+
+/// 终止流时补发给客户端的 SSE 事件
+pub fn termination_event(trace_id: &str) -> String {
+    let payload = serde_json::json!({
+        "type": "error",
+        "error": {
+            "type": "request_terminated",
+            "message": format!("Stream {} was terminated by the proxy administrator", trace_id),
+        }
+    });
+    format!("event: error\ndata: {}\n\n", payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(trace_id: &str) -> InflightInfo {
+        InflightInfo {
+            trace_id: trace_id.to_string(),
+            path: "/v1/messages".to_string(),
+            client: Some("claude-code".to_string()),
+            model: Some("claude-sonnet-4-5".to_string()),
+            account: Some("a@example.com".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_register_list_and_drop() {
+        let registry = Arc::new(InflightRegistry::new());
+        let (guard, _killed) = registry.register(info("abc123"));
+        guard.add_bytes(42);
+
+        let list = registry.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].trace_id, "abc123");
+        assert_eq!(list[0].bytes_sent, 42);
+
+        drop(guard);
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn test_kill_signals_guard() {
+        let registry = Arc::new(InflightRegistry::new());
+        let (_guard, mut killed) = registry.register(info("stuck"));
+        let (_other, mut other_killed) = registry.register(info("healthy"));
+
+        assert_eq!(registry.kill("missing"), 0);
+        assert_eq!(registry.kill("stuck"), 1);
+        assert!(killed.try_recv().is_ok());
+        assert!(other_killed.try_recv().is_err());
+        // 同一条流不会被重复终止
+        assert_eq!(registry.kill("stuck"), 0);
+    }
+}
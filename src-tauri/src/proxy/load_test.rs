@@ -0,0 +1,342 @@
+// 压测工具 (Load Test Harness)
+// 按目标 RPS 将最近录制的真实请求 (请求日志中成功请求的请求体) 混合回放到本地反代，
+// 统计延迟分位数、错误率与账号轮换情况，便于在团队使用前评估账号池规模。
+// 回放请求与普通请求一样经过完整链路：会真实消耗上游配额，也会写入请求日志。
+// 账号分布依赖 X-Account-Email 等路由头，关闭 expose_routing_info 时报告中的账号统计为空。
+
+use crate::proxy::common::routing_info::{HEADER_ACCOUNT_EMAIL, HEADER_ATTEMPTS, HEADER_RETRY_REASONS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 可回放的协议端点 (count_tokens 不经过账号调度，不参与回放)
+const REPLAYABLE_PATHS: &[&str] = &["/v1/messages", "/v1/chat/completions", "/v1/completions", "/v1/responses", "/v1beta/models/"];
+
+/// 压测参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadTestOptions {
+    /// 目标每秒请求数
+    #[serde(default = "default_target_rps")]
+    pub target_rps: f64,
+
+    /// 持续时间 (秒)
+    #[serde(default = "default_duration_secs")]
+    pub duration_secs: u64,
+
+    /// 从请求日志中取最近多少条请求作为回放样本
+    #[serde(default = "default_sample_size")]
+    pub sample_size: usize,
+
+    /// 同时进行中的请求上限，达到上限时本次发送计为 skipped (说明反代已跟不上目标 RPS)
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+
+    /// 只回放指定路径前缀的请求 (如 "/v1/messages")，留空回放全部
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+}
+
+fn default_target_rps() -> f64 {
+    2.0
+}
+
+fn default_duration_secs() -> u64 {
+    30
+}
+
+fn default_sample_size() -> usize {
+    50
+}
+
+fn default_max_in_flight() -> usize {
+    32
+}
+
+/// 回放样本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayRequest {
+    pub path: String,
+    pub body: String,
+}
+
+/// 从请求日志 (method, url, request_body) 中筛选可回放的样本
+pub fn select_samples(rows: Vec<(String, String, String)>, path_prefix: Option<&str>) -> Vec<ReplayRequest> {
+    rows.into_iter()
+        .filter(|(method, url, _)| {
+            method.eq_ignore_ascii_case("POST")
+                && REPLAYABLE_PATHS.iter().any(|p| url.starts_with(p))
+                && !url.contains("count_tokens")
+                && !url.contains(":countTokens")
+                && path_prefix.is_none_or(|prefix| url.starts_with(prefix))
+        })
+        .map(|(_, path, body)| ReplayRequest { path, body })
+        .collect()
+}
+
+/// 单个回放请求的结果
+#[derive(Debug, Clone, Default)]
+struct Outcome {
+    /// 发送顺序 (用于统计相邻请求间的账号切换)
+    seq: usize,
+    latency_ms: u64,
+    /// None 表示连接失败 / 超时
+    status: Option<u16>,
+    account: Option<String>,
+    attempts: u32,
+    retry_reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct LatencySummary {
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+    pub mean_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccountSummary {
+    /// 账号 -> 完成的请求数
+    pub distribution: BTreeMap<String, usize>,
+    /// 按发送顺序相邻两个请求落在不同账号上的次数
+    pub rotations: usize,
+    /// 发生过重试 (换账号 / 重发) 的请求数
+    pub retried_requests: usize,
+    /// 重试原因 -> 次数 (e.g. "429", "network")
+    pub retry_reasons: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadTestReport {
+    pub base_url: String,
+    pub samples: usize,
+    pub target_rps: f64,
+    pub achieved_rps: f64,
+    pub duration_ms: u64,
+    pub sent: usize,
+    /// 因达到 max_in_flight 而未发送的次数
+    pub skipped: usize,
+    pub succeeded: usize,
+    pub error_rate: f64,
+    /// HTTP 状态码 (或 "transport") -> 次数
+    pub errors: BTreeMap<String, usize>,
+    /// 成功请求的延迟 (含完整读取响应体)
+    pub latency: LatencySummary,
+    pub accounts: AccountSummary,
+}
+
+/// 最近秩 (nearest-rank) 分位数，输入需已排序
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarize_latency(outcomes: &[Outcome]) -> LatencySummary {
+    let mut latencies: Vec<u64> = outcomes
+        .iter()
+        .filter(|o| o.status.is_some_and(|s| s < 400))
+        .map(|o| o.latency_ms)
+        .collect();
+    latencies.sort_unstable();
+    if latencies.is_empty() {
+        return LatencySummary::default();
+    }
+    LatencySummary {
+        p50_ms: percentile(&latencies, 50.0),
+        p95_ms: percentile(&latencies, 95.0),
+        p99_ms: percentile(&latencies, 99.0),
+        max_ms: *latencies.last().unwrap(),
+        mean_ms: latencies.iter().sum::<u64>() / latencies.len() as u64,
+    }
+}
+
+fn summarize_accounts(outcomes: &[Outcome]) -> AccountSummary {
+    let mut summary = AccountSummary::default();
+    let mut ordered: Vec<&Outcome> = outcomes.iter().collect();
+    ordered.sort_by_key(|o| o.seq);
+    let mut previous: Option<&str> = None;
+    for outcome in ordered {
+        if outcome.attempts > 1 || !outcome.retry_reasons.is_empty() {
+            summary.retried_requests += 1;
+        }
+        for reason in &outcome.retry_reasons {
+            *summary.retry_reasons.entry(reason.clone()).or_default() += 1;
+        }
+        let Some(account) = outcome.account.as_deref() else {
+            continue;
+        };
+        *summary.distribution.entry(account.to_string()).or_default() += 1;
+        if previous.is_some_and(|p| p != account) {
+            summary.rotations += 1;
+        }
+        previous = Some(account);
+    }
+    summary
+}
+
+fn summarize(base_url: &str, samples: usize, options: &LoadTestOptions, outcomes: &[Outcome], skipped: usize, elapsed: Duration) -> LoadTestReport {
+    let mut errors = BTreeMap::new();
+    for outcome in outcomes {
+        match outcome.status {
+            Some(status) if status < 400 => {}
+            Some(status) => *errors.entry(status.to_string()).or_default() += 1,
+            None => *errors.entry("transport".to_string()).or_default() += 1,
+        }
+    }
+    let failed: usize = errors.values().sum();
+    let secs = elapsed.as_secs_f64().max(0.001);
+    LoadTestReport {
+        base_url: base_url.to_string(),
+        samples,
+        target_rps: options.target_rps,
+        achieved_rps: (outcomes.len() as f64 / secs * 100.0).round() / 100.0,
+        duration_ms: elapsed.as_millis() as u64,
+        sent: outcomes.len(),
+        skipped,
+        succeeded: outcomes.len() - failed,
+        error_rate: if outcomes.is_empty() { 0.0 } else { failed as f64 / outcomes.len() as f64 },
+        errors,
+        latency: summarize_latency(outcomes),
+        accounts: summarize_accounts(outcomes),
+    }
+}
+
+async fn send(client: &reqwest::Client, base_url: &str, api_key: Option<&str>, request: &ReplayRequest, seq: usize) -> Outcome {
+    let started = Instant::now();
+    let mut builder = client
+        .post(format!("{}{}", base_url, request.path))
+        .header("content-type", "application/json")
+        .body(request.body.clone());
+    if let Some(key) = api_key {
+        builder = builder.bearer_auth(key);
+    }
+    let mut outcome = Outcome { seq, ..Default::default() };
+    match builder.send().await {
+        Ok(response) => {
+            let header = |name: &str| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+            outcome.account = header(HEADER_ACCOUNT_EMAIL);
+            outcome.attempts = header(HEADER_ATTEMPTS).and_then(|v| v.parse().ok()).unwrap_or(0);
+            outcome.retry_reasons = header(HEADER_RETRY_REASONS)
+                .map(|v| v.split(',').map(|r| r.trim().to_string()).filter(|r| !r.is_empty()).collect())
+                .unwrap_or_default();
+            let status = response.status().as_u16();
+            // 延迟包含完整读取响应体 (流式响应即整个生成过程)；读取中断视为传输错误
+            outcome.status = response.bytes().await.ok().map(|_| status);
+        }
+        Err(e) => tracing::debug!("[LoadTest] Request {} failed: {}", seq, e),
+    }
+    outcome.latency_ms = started.elapsed().as_millis() as u64;
+    outcome
+}
+
+/// 按目标 RPS 混合回放样本 (按顺序轮流取样本)，持续 duration_secs 后等待进行中的请求结束并汇总
+pub async fn run(base_url: &str, api_key: Option<&str>, samples: Vec<ReplayRequest>, options: &LoadTestOptions) -> Result<LoadTestReport, String> {
+    if samples.is_empty() {
+        return Err("没有可回放的请求记录 (请先通过反代发送一些请求)".to_string());
+    }
+    if !options.target_rps.is_finite() || options.target_rps <= 0.0 || options.duration_secs == 0 {
+        return Err("target_rps 与 duration_secs 必须大于 0".to_string());
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(300))
+        .build()
+        .unwrap_or_default();
+    let samples = Arc::new(samples);
+    let total = (options.target_rps * options.duration_secs as f64).ceil() as usize;
+    let permits = Arc::new(tokio::sync::Semaphore::new(options.max_in_flight.max(1)));
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / options.target_rps));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut skipped = 0;
+
+    tracing::info!(
+        "[LoadTest] Replaying {} samples at {} rps for {}s against {}",
+        samples.len(), options.target_rps, options.duration_secs, base_url
+    );
+    let started = Instant::now();
+    for seq in 0..total {
+        ticker.tick().await;
+        let Ok(permit) = permits.clone().try_acquire_owned() else {
+            skipped += 1;
+            continue;
+        };
+        let (client, base_url, api_key, samples) = (client.clone(), base_url.to_string(), api_key.map(str::to_string), samples.clone());
+        tasks.spawn(async move {
+            let outcome = send(&client, &base_url, api_key.as_deref(), &samples[seq % samples.len()], seq).await;
+            drop(permit);
+            outcome
+        });
+    }
+
+    let mut outcomes = Vec::with_capacity(total);
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(outcome) = result {
+            outcomes.push(outcome);
+        }
+    }
+    let report = summarize(base_url, samples.len(), options, &outcomes, skipped, started.elapsed());
+    tracing::info!(
+        "[LoadTest] Done: sent={} skipped={} error_rate={:.3} p50={}ms p95={}ms accounts={}",
+        report.sent, report.skipped, report.error_rate, report.latency.p50_ms, report.latency.p95_ms, report.accounts.distribution.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(seq: usize, latency_ms: u64, status: Option<u16>, account: Option<&str>, retry_reasons: &[&str]) -> Outcome {
+        Outcome {
+            seq,
+            latency_ms,
+            status,
+            account: account.map(str::to_string),
+            attempts: 1 + retry_reasons.len() as u32,
+            retry_reasons: retry_reasons.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_samples_keeps_replayable_posts() {
+        let rows = vec![
+            ("POST".to_string(), "/v1/messages".to_string(), "{}".to_string()),
+            ("POST".to_string(), "/v1/messages/count_tokens".to_string(), "{}".to_string()),
+            ("POST".to_string(), "/v1/chat/completions".to_string(), "{}".to_string()),
+            ("POST".to_string(), "/api/proxy/logs".to_string(), "{}".to_string()),
+        ];
+        let all = select_samples(rows.clone(), None);
+        assert_eq!(all.iter().map(|r| r.path.as_str()).collect::<Vec<_>>(), vec!["/v1/messages", "/v1/chat/completions"]);
+        assert_eq!(select_samples(rows, Some("/v1/chat")).len(), 1);
+    }
+
+    #[test]
+    fn test_summarize_reports_percentiles_errors_and_rotations() {
+        let options = LoadTestOptions { target_rps: 5.0, duration_secs: 1, sample_size: 10, max_in_flight: 4, path_prefix: None };
+        let mut outcomes: Vec<Outcome> = (0..20)
+            .map(|i| outcome(i, (i as u64 + 1) * 10, Some(200), Some(if i % 2 == 0 { "a@example.com" } else { "b@example.com" }), &[]))
+            .collect();
+        outcomes.push(outcome(20, 5, Some(429), Some("b@example.com"), &["429", "429"]));
+        outcomes.push(outcome(21, 5, None, None, &[]));
+
+        let report = summarize("http://127.0.0.1:8045", 3, &options, &outcomes, 2, Duration::from_secs(2));
+        assert_eq!(report.latency.p50_ms, 100);
+        assert_eq!(report.latency.p95_ms, 190);
+        assert_eq!(report.latency.max_ms, 200);
+        assert_eq!(report.succeeded, 20);
+        assert_eq!(report.errors.get("429"), Some(&1));
+        assert_eq!(report.errors.get("transport"), Some(&1));
+        assert!((report.error_rate - 2.0 / 22.0).abs() < 1e-9);
+        assert_eq!(report.accounts.distribution.get("a@example.com"), Some(&10));
+        assert_eq!(report.accounts.rotations, 19);
+        assert_eq!(report.accounts.retried_requests, 1);
+        assert_eq!(report.accounts.retry_reasons.get("429"), Some(&2));
+        assert_eq!(report.achieved_rps, 11.0);
+    }
+}
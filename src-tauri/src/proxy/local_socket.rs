@@ -0,0 +1,174 @@
+// 本地套接字监听 (Unix domain socket / Windows 命名管道)
+// 与 TCP 端口同时提供同一套路由，适用于仅本机使用的场景：不占用端口、不触发防火墙提示，
+// Unix 下通过套接字文件权限 (默认 0600) 限制可访问的用户。
+
+use crate::proxy::config::LocalSocketConfig;
+use axum::Router;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// 接受连接失败 (文件描述符耗尽 / 管道异常等) 后的重试间隔，避免错误持续时空转
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Windows 默认命名管道
+#[cfg(windows)]
+pub const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\antigravity-proxy";
+
+/// Unix 默认套接字文件名 (位于数据目录)
+#[cfg(unix)]
+pub const DEFAULT_SOCKET_FILE: &str = "proxy.sock";
+
+/// 解析监听路径 (配置留空时使用默认位置)
+pub fn resolve_path(config: &LocalSocketConfig) -> Result<String, String> {
+    if !config.path.trim().is_empty() {
+        return Ok(config.path.trim().to_string());
+    }
+    #[cfg(unix)]
+    {
+        let dir = crate::modules::account::get_data_dir()?;
+        Ok(dir.join(DEFAULT_SOCKET_FILE).to_string_lossy().to_string())
+    }
+    #[cfg(windows)]
+    {
+        Ok(DEFAULT_PIPE_NAME.to_string())
+    }
+}
+
+/// 路径上已存在的是否为套接字文件 (不存在时返回 None)
+#[cfg(unix)]
+fn existing_socket(path: &str) -> Option<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::symlink_metadata(path).ok().map(|m| m.file_type().is_socket())
+}
+
+/// 绑定本地套接字并在后台接受连接，返回监听任务与实际路径
+#[cfg(unix)]
+pub fn spawn(config: &LocalSocketConfig, app: Router) -> Result<(JoinHandle<()>, String), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = resolve_path(config)?;
+    // 清理上次异常退出遗留的套接字文件；路径上是普通文件 / 目录时拒绝启动，避免误删用户数据
+    match existing_socket(&path) {
+        Some(true) => {
+            std::fs::remove_file(&path).map_err(|e| format!("无法移除旧的套接字文件 {}: {}", path, e))?;
+        }
+        Some(false) => {
+            return Err(format!("路径 {} 已存在且不是套接字文件，请检查本地套接字配置", path));
+        }
+        None => {}
+    }
+    let listener = tokio::net::UnixListener::bind(&path).map_err(|e| format!("套接字 {} 绑定失败: {}", path, e))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(config.mode))
+        .map_err(|e| format!("设置套接字权限失败: {}", e))?;
+    tracing::info!("反代服务器同时监听本地套接字 {} (mode {:o})", path, config.mode);
+
+    let handle = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => crate::proxy::server::serve_connection(stream, app.clone()),
+                Err(e) => {
+                    tracing::error!("本地套接字接收连接失败: {:?}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                }
+            }
+        }
+    });
+    Ok((handle, path))
+}
+
+/// 绑定命名管道并在后台接受连接，返回监听任务与实际管道名
+#[cfg(windows)]
+pub fn spawn(config: &LocalSocketConfig, app: Router) -> Result<(JoinHandle<()>, String), String> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = resolve_path(config)?;
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)
+        .map_err(|e| format!("命名管道 {} 创建失败: {}", path, e))?;
+    tracing::info!("反代服务器同时监听命名管道 {}", path);
+
+    let pipe_name = path.clone();
+    let handle = tokio::spawn(async move {
+        loop {
+            if let Err(e) = server.connect().await {
+                tracing::error!("命名管道接收连接失败: {:?}", e);
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+            // 先创建下一个实例再移交当前连接，保证始终有实例在等待客户端
+            let connected = match ServerOptions::new().create(&pipe_name) {
+                Ok(next) => std::mem::replace(&mut server, next),
+                Err(e) => {
+                    tracing::error!("命名管道实例创建失败: {:?}", e);
+                    break;
+                }
+            };
+            crate::proxy::server::serve_connection(connected, app.clone());
+        }
+    });
+    Ok((handle, path))
+}
+
+/// 停止后清理套接字文件 (命名管道无需清理)
+pub fn cleanup(path: &str) {
+    #[cfg(unix)]
+    {
+        if existing_socket(path) != Some(true) {
+            return;
+        }
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::debug!("清理套接字文件 {} 失败: {}", path, e);
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = path;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_serves_http_over_unix_socket_with_permissions() {
+        let path = std::env::temp_dir().join(format!("ag-proxy-{}.sock", uuid::Uuid::new_v4()));
+        let config = LocalSocketConfig {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let app = Router::new().route("/healthz", get(|| async { "ok" }));
+
+        // 普通文件不会被删除
+        std::fs::write(&path, b"user data").unwrap();
+        assert!(spawn(&config, app.clone()).unwrap_err().contains("不是套接字"));
+        cleanup(&config.path);
+        assert_eq!(std::fs::read(&path).unwrap(), b"user data");
+        std::fs::remove_file(&path).unwrap();
+
+        // 上次异常退出遗留的套接字文件会被替换
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let (handle, bound) = spawn(&config, app).unwrap();
+        assert_eq!(std::fs::metadata(&bound).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let mut stream = tokio::net::UnixStream::connect(&bound).await.unwrap();
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("ok"));
+
+        handle.abort();
+        cleanup(&bound);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,294 @@
+// Agent 失控循环检测 (Runaway-loop Detection)
+// 按会话识别三类病态流量：
+// - 同一工具调用 (名称 + 参数完全相同) 在历史末尾连续重复 N 次
+// - 一分钟内出现多次完全相同的提示 (最后一条用户消息)
+// - 单个会话每分钟请求数超过上限
+// 命中后按配置处理：warn 仅记录日志；throttle 延迟后继续转发；
+// break 不再转发上游，直接返回一条合成的助手消息，要求 Agent 停止重复并向用户求助。
+
+use crate::proxy::config::{LoopAction, LoopDetectionConfig};
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+use crate::proxy::mappers::openai::models::OpenAIRequest;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+pub const HEADER_LOOP_DETECTED: &str = "x-loop-detected";
+
+const WINDOW: Duration = Duration::from_secs(60);
+const SESSION_IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+const SESSION_LIMIT: usize = 1000;
+
+/// 从单个请求中提取的循环特征
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopSignals {
+    pub session_id: String,
+    /// 最后一条用户消息的指纹 (没有用户消息时为 None)
+    pub prompt_hash: Option<u64>,
+    /// 历史末尾连续相同的工具调用轮数 (最近一轮没有工具调用时为 0)
+    pub repeated_tool_calls: usize,
+}
+
+/// 命中的循环类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    RepeatedToolCall(usize),
+    IdenticalPrompt(usize),
+    RequestRate(usize),
+}
+
+impl LoopKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoopKind::RepeatedToolCall(_) => "repeated_tool_call",
+            LoopKind::IdenticalPrompt(_) => "identical_prompt",
+            LoopKind::RequestRate(_) => "request_rate",
+        }
+    }
+
+    /// 返回给 Agent 的说明 (break 动作)
+    pub fn message(&self) -> String {
+        let reason = match self {
+            LoopKind::RepeatedToolCall(n) => {
+                format!("the same tool call with identical arguments was repeated {} times in a row", n)
+            }
+            LoopKind::IdenticalPrompt(n) => {
+                format!("the identical prompt was sent {} times within one minute", n)
+            }
+            LoopKind::RequestRate(n) => format!("this session sent {} requests within one minute", n),
+        };
+        format!(
+            "[Proxy loop guard] The request was not sent to the model because {}. \
+             You appear to be stuck in a loop. Stop repeating this action, summarize what you have tried so far, \
+             and ask the user how to proceed.",
+            reason
+        )
+    }
+}
+
+#[derive(Default)]
+struct SessionActivity {
+    requests: VecDeque<Instant>,
+    prompts: VecDeque<(u64, Instant)>,
+    last_seen: Option<Instant>,
+}
+
+impl SessionActivity {
+    fn prune(&mut self, now: Instant) {
+        while self.requests.front().is_some_and(|t| now.duration_since(*t) > WINDOW) {
+            self.requests.pop_front();
+        }
+        while self.prompts.front().is_some_and(|(_, t)| now.duration_since(*t) > WINDOW) {
+            self.prompts.pop_front();
+        }
+    }
+}
+
+/// 会话 -> 最近一分钟的请求活动
+pub struct LoopDetector {
+    sessions: Mutex<HashMap<String, SessionActivity>>,
+}
+
+impl LoopDetector {
+    fn new() -> Self {
+        Self { sessions: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn global() -> &'static LoopDetector {
+        static INSTANCE: OnceLock<LoopDetector> = OnceLock::new();
+        INSTANCE.get_or_init(LoopDetector::new)
+    }
+
+    /// 记录本次请求并判断是否处于失控循环
+    pub fn check(&self, signals: &LoopSignals, config: &LoopDetectionConfig) -> Option<LoopKind> {
+        if !config.enabled {
+            return None;
+        }
+        if config.max_repeated_tool_calls > 0 && signals.repeated_tool_calls >= config.max_repeated_tool_calls {
+            return Some(LoopKind::RepeatedToolCall(signals.repeated_tool_calls));
+        }
+
+        let now = Instant::now();
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return None;
+        };
+        if sessions.len() >= SESSION_LIMIT && !sessions.contains_key(&signals.session_id) {
+            sessions.retain(|_, s| s.last_seen.is_some_and(|t| now.duration_since(t) < SESSION_IDLE_TTL));
+        }
+        let activity = sessions.entry(signals.session_id.clone()).or_default();
+        activity.prune(now);
+        activity.last_seen = Some(now);
+        activity.requests.push_back(now);
+        if let Some(hash) = signals.prompt_hash {
+            activity.prompts.push_back((hash, now));
+        }
+
+        if let Some(hash) = signals.prompt_hash {
+            let identical = activity.prompts.iter().filter(|(h, _)| *h == hash).count();
+            if config.max_identical_prompts > 0 && identical >= config.max_identical_prompts {
+                return Some(LoopKind::IdenticalPrompt(identical));
+            }
+        }
+        let rate = activity.requests.len();
+        if config.max_requests_per_minute > 0 && rate > config.max_requests_per_minute {
+            return Some(LoopKind::RequestRate(rate));
+        }
+        None
+    }
+}
+
+/// 检测结果的处理方式：None 表示继续转发，Some 表示应直接返回 break 响应
+pub async fn enforce(signals: &LoopSignals, config: &LoopDetectionConfig, trace_id: &str) -> Option<LoopKind> {
+    let kind = LoopDetector::global().check(signals, config)?;
+    tracing::warn!(
+        "[{}] [LoopGuard] Session {} looks like a runaway loop ({:?}), action: {:?}",
+        trace_id,
+        signals.session_id,
+        kind,
+        config.action
+    );
+    match config.action {
+        LoopAction::Warn => None,
+        LoopAction::Throttle => {
+            tokio::time::sleep(Duration::from_millis(config.throttle_delay_ms)).await;
+            None
+        }
+        LoopAction::Break => Some(kind),
+    }
+}
+
+fn hash_str(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 末尾连续相同的调用轮数 (每一轮以该轮全部工具调用的指纹表示)
+fn trailing_repeats(rounds: impl Iterator<Item = Option<u64>>) -> usize {
+    let mut latest = None;
+    let mut count = 0;
+    for round in rounds {
+        let Some(fingerprint) = round else { break };
+        match latest {
+            None => latest = Some(fingerprint),
+            Some(l) if l != fingerprint => break,
+            _ => {}
+        }
+        count += 1;
+    }
+    count
+}
+
+pub fn claude_signals(request: &ClaudeRequest, session_id: &str) -> LoopSignals {
+    let prompt_hash = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| hash_str(&serde_json::to_string(&m.content).unwrap_or_default()));
+
+    let rounds = request.messages.iter().rev().filter(|m| m.role == "assistant").map(|m| {
+        let MessageContent::Array(blocks) = &m.content else {
+            return None;
+        };
+        let calls: Vec<String> = blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse { name, input, .. } => Some(format!("{}:{}", name, input)),
+                _ => None,
+            })
+            .collect();
+        (!calls.is_empty()).then(|| hash_str(&calls.join("\n")))
+    });
+
+    LoopSignals {
+        session_id: session_id.to_string(),
+        prompt_hash,
+        repeated_tool_calls: trailing_repeats(rounds),
+    }
+}
+
+pub fn openai_signals(request: &OpenAIRequest, session_id: &str) -> LoopSignals {
+    let prompt_hash = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| hash_str(&serde_json::to_string(&m.content).unwrap_or_default()));
+
+    let rounds = request.messages.iter().rev().filter(|m| m.role == "assistant").map(|m| {
+        let calls: Vec<String> = m
+            .tool_calls
+            .as_ref()?
+            .iter()
+            .map(|c| format!("{}:{}", c.function.name, c.function.arguments))
+            .collect();
+        (!calls.is_empty()).then(|| hash_str(&calls.join("\n")))
+    });
+
+    LoopSignals {
+        session_id: session_id.to_string(),
+        prompt_hash,
+        repeated_tool_calls: trailing_repeats(rounds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> LoopDetectionConfig {
+        LoopDetectionConfig { enabled: true, ..Default::default() }
+    }
+
+    #[test]
+    fn test_repeated_tool_calls_in_claude_history() {
+        let call = json!({"role": "assistant", "content": [
+            {"type": "tool_use", "id": "t", "name": "bash", "input": {"command": "ls"}}
+        ]});
+        let result = json!({"role": "user", "content": [
+            {"type": "tool_result", "tool_use_id": "t", "content": "a.txt"}
+        ]});
+        let mut messages = vec![json!({"role": "user", "content": "list files"})];
+        for _ in 0..5 {
+            messages.push(call.clone());
+            messages.push(result.clone());
+        }
+        let request: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": messages,
+        }))
+        .unwrap();
+
+        let signals = claude_signals(&request, "sid-loop");
+        assert_eq!(signals.repeated_tool_calls, 5);
+        let detector = LoopDetector::new();
+        assert_eq!(detector.check(&signals, &config()), Some(LoopKind::RepeatedToolCall(5)));
+        assert_eq!(detector.check(&signals, &LoopDetectionConfig::default()), None);
+    }
+
+    #[test]
+    fn test_different_arguments_break_the_streak() {
+        let rounds = [Some(1), Some(1), Some(2), Some(1)].into_iter();
+        assert_eq!(trailing_repeats(rounds), 2);
+        assert_eq!(trailing_repeats([None, Some(1)].into_iter()), 0);
+    }
+
+    #[test]
+    fn test_identical_prompts_and_request_rate() {
+        let detector = LoopDetector::new();
+        let signals = LoopSignals { session_id: "sid-a".into(), prompt_hash: Some(7), repeated_tool_calls: 0 };
+        assert_eq!(detector.check(&signals, &config()), None);
+        assert_eq!(detector.check(&signals, &config()), None);
+        assert_eq!(detector.check(&signals, &config()), Some(LoopKind::IdenticalPrompt(3)));
+
+        let rate_config = LoopDetectionConfig { max_requests_per_minute: 2, ..config() };
+        let fresh = |h| LoopSignals { session_id: "sid-b".into(), prompt_hash: Some(h), repeated_tool_calls: 0 };
+        assert_eq!(detector.check(&fresh(1), &rate_config), None);
+        assert_eq!(detector.check(&fresh(2), &rate_config), None);
+        assert_eq!(detector.check(&fresh(3), &rate_config), Some(LoopKind::RequestRate(3)));
+    }
+}
@@ -0,0 +1,120 @@
+// Anthropic beta 协商
+// 解析客户端的 anthropic-beta 请求头，按下表判断哪些 beta 可以在 Gemini 上游兑现，
+// 兑现的 beta 会影响映射行为，并通过响应头 anthropic-beta 回显，便于客户端做特性探测。
+// 无法兑现的 beta 被忽略 (不回显)，请求照常处理。
+
+use axum::http::{HeaderMap, HeaderValue};
+
+pub const INTERLEAVED_THINKING: &str = "interleaved-thinking-2025-05-14";
+pub const TOKEN_EFFICIENT_TOOLS: &str = "token-efficient-tools-2025-02-19";
+pub const FINE_GRAINED_TOOL_STREAMING: &str = "fine-grained-tool-streaming-2025-05-14";
+pub const PROMPT_CACHING: &str = "prompt-caching-2024-07-31";
+pub const EXTENDED_CACHE_TTL: &str = "extended-cache-ttl-2025-04-11";
+pub const CONTEXT_1M: &str = "context-1m-2025-08-07";
+
+/// 可兑现的 beta 及其在本代理中的含义
+pub const SUPPORTED_BETAS: &[(&str, &str)] = &[
+    (INTERLEAVED_THINKING, "thinking budget may exceed max_tokens; Gemini interleaves thoughts between tool calls natively"),
+    (TOKEN_EFFICIENT_TOOLS, "duplicate tool declarations within a request are removed; declarations are not minimized across turns (upstream is stateless)"),
+    (FINE_GRAINED_TOOL_STREAMING, "accepted; tool input is streamed as a single input_json_delta"),
+    (PROMPT_CACHING, "accepted; cache_control is stripped and Gemini implicit caching applies"),
+    (EXTENDED_CACHE_TTL, "accepted; cache TTL hints are ignored"),
+    (CONTEXT_1M, "accepted; Gemini models provide a 1M token context window"),
+];
+
+/// 已知但无法兑现的 beta (仅用于日志说明)
+const UNSUPPORTED_BETAS: &[(&str, &str)] = &[
+    ("files-api-2025-04-14", "the Files API is not available through this proxy"),
+    ("computer-use-2025-01-24", "computer use tools have no Gemini equivalent"),
+    ("mcp-client-2025-04-04", "remote MCP servers are not proxied"),
+    ("output-128k-2025-02-19", "Gemini output is capped at 64k tokens"),
+];
+
+/// 一次请求协商后的 beta 集合
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnthropicBetas {
+    /// 已接受的 beta (保持请求中的顺序)
+    pub accepted: Vec<String>,
+    /// 被忽略的 beta
+    pub ignored: Vec<String>,
+}
+
+impl AnthropicBetas {
+    /// 解析 anthropic-beta 请求头 (可出现多次，每个值为逗号分隔列表)
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut betas = Self::default();
+        let requested = headers
+            .get_all("anthropic-beta")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|b| !b.is_empty());
+
+        for beta in requested {
+            if betas.accepted.iter().chain(&betas.ignored).any(|b| b == beta) {
+                continue;
+            }
+            if SUPPORTED_BETAS.iter().any(|(name, _)| *name == beta) {
+                betas.accepted.push(beta.to_string());
+            } else {
+                let reason = UNSUPPORTED_BETAS
+                    .iter()
+                    .find(|(name, _)| *name == beta)
+                    .map(|(_, reason)| *reason)
+                    .unwrap_or("unknown beta");
+                tracing::debug!("[Claude-Betas] Ignoring '{}': {}", beta, reason);
+                betas.ignored.push(beta.to_string());
+            }
+        }
+        betas
+    }
+
+    pub fn has(&self, beta: &str) -> bool {
+        self.accepted.iter().any(|b| b == beta)
+    }
+
+    /// 在响应头中回显已接受的 beta
+    pub fn apply_to_headers(&self, headers: &mut HeaderMap) {
+        if self.accepted.is_empty() {
+            return;
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.accepted.join(",")) {
+            headers.insert("anthropic-beta", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiates_supported_betas_and_echoes_them() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "anthropic-beta",
+            HeaderValue::from_static("interleaved-thinking-2025-05-14, files-api-2025-04-14"),
+        );
+        headers.append(
+            "anthropic-beta",
+            HeaderValue::from_static("token-efficient-tools-2025-02-19,interleaved-thinking-2025-05-14,made-up-beta"),
+        );
+
+        let betas = AnthropicBetas::from_headers(&headers);
+        assert_eq!(betas.accepted, vec![INTERLEAVED_THINKING, TOKEN_EFFICIENT_TOOLS]);
+        assert_eq!(betas.ignored, vec!["files-api-2025-04-14", "made-up-beta"]);
+        assert!(betas.has(INTERLEAVED_THINKING));
+
+        let mut response_headers = HeaderMap::new();
+        betas.apply_to_headers(&mut response_headers);
+        assert_eq!(
+            response_headers.get("anthropic-beta").unwrap(),
+            "interleaved-thinking-2025-05-14,token-efficient-tools-2025-02-19"
+        );
+
+        let mut empty = HeaderMap::new();
+        AnthropicBetas::default().apply_to_headers(&mut empty);
+        assert!(empty.get("anthropic-beta").is_none());
+    }
+}
@@ -0,0 +1,287 @@
+// 文档引用 (Citations)
+// 客户端传入 `citations: {"enabled": true}` 的 document 块时，Anthropic 会在回答的文本块上附带
+// char_location 引用 (文档序号 + 字符区间)。Gemini 没有对应概念，只会在 candidate 上给出
+// citationMetadata.citationSources / groundingMetadata.groundingSupports 两类"回答片段"区间。
+// 这里把回答中被标注的片段在启用引用的文档里定位出来，转换为 char_location 引用：
+// 非流式响应附加到文本块的 citations 字段，流式响应以 citations_delta 下发。
+// 只能定位纯文本文档 (text 源或 text/* 的 base64)；PDF 等无法取得原文的文档不产生引用。
+
+use super::models::{ContentBlock, Message, MessageContent};
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// 参与定位的最短片段 (字符)，过短的片段容易误匹配
+const MIN_SEGMENT_CHARS: usize = 8;
+
+/// 启用引用且可取得原文的文档
+#[derive(Debug, Clone)]
+pub struct CitableDocument {
+    /// 在请求全部 document 块中的序号 (与 Anthropic 的 document_index 一致)
+    pub index: usize,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+fn citations_enabled(config: Option<&Value>) -> bool {
+    config.and_then(|c| c.get("enabled")).and_then(|e| e.as_bool()).unwrap_or(false)
+}
+
+fn document_text(source_type: &str, media_type: &str, data: &str) -> Option<String> {
+    match source_type {
+        "text" => Some(data.to_string()),
+        "base64" if media_type.starts_with("text/") => base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok()),
+        _ => None,
+    }
+}
+
+/// 收集请求中启用引用的文档
+pub fn collect_documents(messages: &[Message]) -> Vec<CitableDocument> {
+    let mut documents = Vec::new();
+    let mut index = 0;
+    for message in messages {
+        let MessageContent::Array(blocks) = &message.content else {
+            continue;
+        };
+        for block in blocks {
+            let ContentBlock::Document { source, title, citations, .. } = block else {
+                continue;
+            };
+            if citations_enabled(citations.as_ref()) {
+                match document_text(&source.source_type, &source.media_type, &source.data) {
+                    Some(text) => documents.push(CitableDocument { index, title: title.clone(), text }),
+                    None => tracing::debug!(
+                        "[Citations] Document {} ({}) has no extractable text, citations unavailable",
+                        index,
+                        source.media_type
+                    ),
+                }
+            }
+            index += 1;
+        }
+    }
+    documents
+}
+
+/// text 源文档转换为 Gemini 文本 part (标题 / 上下文一并提供给模型)
+pub fn document_prompt(title: Option<&str>, context: Option<&str>, text: &str) -> String {
+    let mut prompt = String::from("<document");
+    if let Some(title) = title {
+        prompt.push_str(&format!(" title=\"{}\"", title.replace('"', "'")));
+    }
+    prompt.push_str(">\n");
+    if let Some(context) = context {
+        prompt.push_str(&format!("<context>{}</context>\n", context));
+    }
+    prompt.push_str(text);
+    prompt.push_str("\n</document>");
+    prompt
+}
+
+/// 空白归一化并转小写，同时记录每个归一化字符对应的原始字符下标
+fn normalize(text: &str) -> (Vec<char>, Vec<usize>) {
+    let mut chars = Vec::new();
+    let mut offsets = Vec::new();
+    let mut last_space = true;
+    for (i, c) in text.chars().enumerate() {
+        if c.is_whitespace() {
+            if !last_space {
+                chars.push(' ');
+                offsets.push(i);
+            }
+            last_space = true;
+        } else {
+            chars.extend(c.to_lowercase());
+            offsets.resize(chars.len(), i);
+            last_space = false;
+        }
+    }
+    (chars, offsets)
+}
+
+/// 在文档中定位片段，返回原文的字符区间 [start, end)
+fn find_span(document: &str, segment: &str) -> Option<(usize, usize)> {
+    let (needle, _) = normalize(segment.trim());
+    if needle.len() < MIN_SEGMENT_CHARS {
+        return None;
+    }
+    let (haystack, offsets) = normalize(document);
+    let start = haystack.windows(needle.len()).position(|w| w == needle.as_slice())?;
+    Some((offsets[start], offsets[start + needle.len() - 1] + 1))
+}
+
+/// 回答文本中的字节区间 (Gemini 的 startIndex / endIndex 以字节计)
+fn byte_slice(text: &str, start: usize, end: usize) -> Option<&str> {
+    let end = end.min(text.len());
+    let mut start = start.min(end);
+    while start < end && !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let mut end = end;
+    while end > start && !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (start < end).then(|| &text[start..end])
+}
+
+/// candidate 中被标注为引用的回答片段
+fn cited_segments(candidate: &Value, response_text: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let sources = candidate.pointer("/citationMetadata/citationSources").and_then(|s| s.as_array());
+    for source in sources.into_iter().flatten() {
+        let start = source["startIndex"].as_u64().unwrap_or(0) as usize;
+        let Some(end) = source["endIndex"].as_u64() else {
+            continue;
+        };
+        if let Some(segment) = byte_slice(response_text, start, end as usize) {
+            segments.push(segment.to_string());
+        }
+    }
+    let supports = candidate.pointer("/groundingMetadata/groundingSupports").and_then(|s| s.as_array());
+    for support in supports.into_iter().flatten() {
+        let segment = &support["segment"];
+        let text = match segment["text"].as_str() {
+            Some(text) => Some(text.to_string()),
+            None => segment["endIndex"].as_u64().and_then(|end| {
+                let start = segment["startIndex"].as_u64().unwrap_or(0) as usize;
+                byte_slice(response_text, start, end as usize).map(str::to_string)
+            }),
+        };
+        segments.extend(text);
+    }
+    segments
+}
+
+/// 累积回答文本并把上游引用区间转换为 char_location 引用 (流式按 chunk 调用，已下发的引用不会重复)
+#[derive(Debug, Clone, Default)]
+pub struct CitationTracker {
+    documents: Vec<CitableDocument>,
+    response_text: String,
+    emitted: HashSet<(usize, usize, usize)>,
+}
+
+impl CitationTracker {
+    /// 请求中没有启用引用的文档时返回 None
+    pub fn new(documents: Vec<CitableDocument>) -> Option<Self> {
+        (!documents.is_empty()).then(|| Self { documents, ..Default::default() })
+    }
+
+    /// 处理一个 Gemini candidate，返回新发现的引用
+    pub fn observe(&mut self, candidate: &Value) -> Vec<Value> {
+        let parts = candidate.pointer("/content/parts").and_then(|p| p.as_array());
+        for part in parts.into_iter().flatten() {
+            if part["thought"].as_bool().unwrap_or(false) {
+                continue;
+            }
+            if let Some(text) = part["text"].as_str() {
+                self.response_text.push_str(text);
+            }
+        }
+
+        let mut citations = Vec::new();
+        for segment in cited_segments(candidate, &self.response_text) {
+            for document in &self.documents {
+                let Some((start, end)) = find_span(&document.text, &segment) else {
+                    continue;
+                };
+                if !self.emitted.insert((document.index, start, end)) {
+                    continue;
+                }
+                let cited_text: String = document.text.chars().skip(start).take(end - start).collect();
+                citations.push(json!({
+                    "type": "char_location",
+                    "cited_text": cited_text,
+                    "document_index": document.index,
+                    "document_title": document.title,
+                    "start_char_index": start,
+                    "end_char_index": end
+                }));
+            }
+        }
+        citations
+    }
+}
+
+/// 非流式响应：把引用附加到最后一个文本块
+pub fn attach_to_content(content: &mut [ContentBlock], citations: Vec<Value>) {
+    if citations.is_empty() {
+        return;
+    }
+    let target = content.iter_mut().rev().find_map(|block| match block {
+        ContentBlock::Text { citations, .. } => Some(citations),
+        _ => None,
+    });
+    if let Some(target) = target {
+        target.get_or_insert_with(Vec::new).extend(citations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_messages() -> Vec<Message> {
+        serde_json::from_value(json!([{
+            "role": "user",
+            "content": [
+                { "type": "document", "source": { "type": "base64", "media_type": "application/pdf", "data": "JVBERi0=" } },
+                {
+                    "type": "document",
+                    "source": { "type": "text", "media_type": "text/plain", "data": "The grass is green.\nThe sky is   blue." },
+                    "title": "Facts",
+                    "citations": { "enabled": true }
+                },
+                { "type": "text", "text": "What color is the sky?" }
+            ]
+        }]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_collects_only_enabled_text_documents() {
+        let documents = collect_documents(&request_messages());
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].index, 1);
+        assert_eq!(documents[0].title.as_deref(), Some("Facts"));
+    }
+
+    #[test]
+    fn test_maps_citation_sources_to_char_location() {
+        let mut tracker = CitationTracker::new(collect_documents(&request_messages())).unwrap();
+        let first = json!({ "content": { "parts": [{ "text": "plan", "thought": true }, { "text": "According to the document, " }] } });
+        assert!(tracker.observe(&first).is_empty());
+        let answer = "the sky is blue.";
+        let start = "According to the document, ".len();
+        let second = json!({
+            "content": { "parts": [{ "text": answer }] },
+            "citationMetadata": { "citationSources": [{ "startIndex": start, "endIndex": start + answer.len() }] }
+        });
+        let citations = tracker.observe(&second);
+        assert_eq!(citations.len(), 1);
+        let citation = &citations[0];
+        assert_eq!(citation["type"], "char_location");
+        assert_eq!(citation["document_index"], 1);
+        assert_eq!(citation["document_title"], "Facts");
+        assert_eq!(citation["cited_text"], "The sky is   blue.");
+        assert_eq!(citation["start_char_index"], 20);
+        assert_eq!(citation["end_char_index"], 38);
+
+        // 同一区间不重复下发
+        let again = json!({ "groundingMetadata": { "groundingSupports": [{ "segment": { "text": "The sky is blue." } }] } });
+        assert!(tracker.observe(&again).is_empty());
+    }
+
+    #[test]
+    fn test_attach_to_last_text_block() {
+        let mut content = vec![
+            ContentBlock::Text { text: "a".to_string(), citations: None },
+            ContentBlock::Text { text: "b".to_string(), citations: None },
+        ];
+        attach_to_content(&mut content, vec![json!({ "type": "char_location" })]);
+        assert!(matches!(&content[0], ContentBlock::Text { citations: None, .. }));
+        assert!(matches!(&content[1], ContentBlock::Text { citations: Some(c), .. } if c.len() == 1));
+    }
+}
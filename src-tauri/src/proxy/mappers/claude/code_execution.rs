@@ -0,0 +1,212 @@
+// Code Execution 工具映射
+// Claude `code_execution_*` 服务端工具 <-> Gemini `codeExecution` 内置工具
+//
+// 响应方向: executableCode -> server_tool_use, codeExecutionResult -> code_execution_tool_result
+// 请求方向: 历史中的上述两种 block 还原为 executableCode / codeExecutionResult part
+
+use super::models::{CodeExecutionResult, ContentBlock, ExecutableCode, Message, MessageContent};
+use serde_json::{json, Value};
+
+/// Claude 侧的工具名
+pub const CODE_EXECUTION_TOOL_NAME: &str = "code_execution";
+
+/// 生成 server_tool_use id (与 Anthropic 的 `srvtoolu_` 前缀保持一致)
+pub fn new_server_tool_id() -> String {
+    format!("srvtoolu_{}", crate::proxy::common::utils::generate_random_id())
+}
+
+/// executableCode -> server_tool_use.input
+pub fn executable_code_to_input(code: &ExecutableCode) -> Value {
+    let mut input = json!({ "code": code.code });
+    if let Some(lang) = &code.language {
+        input["language"] = json!(lang.to_lowercase());
+    }
+    input
+}
+
+/// codeExecutionResult -> code_execution_tool_result.content
+pub fn execution_result_to_content(result: &CodeExecutionResult) -> Value {
+    let output = result.output.clone().unwrap_or_default();
+    let ok = matches!(result.outcome.as_deref(), None | Some("OUTCOME_OK"));
+    let (stdout, stderr) = if ok {
+        (output, String::new())
+    } else {
+        let reason = match result.outcome.as_deref() {
+            Some("OUTCOME_DEADLINE_EXCEEDED") => "Execution timed out",
+            _ => "Execution failed",
+        };
+        let stderr = if output.is_empty() { reason.to_string() } else { output };
+        (String::new(), stderr)
+    };
+    json!({
+        "type": "code_execution_result",
+        "stdout": stdout,
+        "stderr": stderr,
+        "return_code": if ok { 0 } else { 1 },
+        "content": []
+    })
+}
+
+/// server_tool_use (code_execution) -> Gemini executableCode part
+pub fn server_tool_use_to_part(input: &Value) -> Value {
+    let code = input.get("code").and_then(|c| c.as_str()).unwrap_or("");
+    json!({
+        "executableCode": {
+            "language": "PYTHON",
+            "code": code
+        }
+    })
+}
+
+/// code_execution_tool_result -> Gemini codeExecutionResult part
+pub fn tool_result_to_part(content: &Value) -> Value {
+    let stdout = content.get("stdout").and_then(|s| s.as_str()).unwrap_or("");
+    let stderr = content.get("stderr").and_then(|s| s.as_str()).unwrap_or("");
+    let return_code = content.get("return_code").and_then(|c| c.as_i64()).unwrap_or(0);
+    let failed = return_code != 0 || content.get("type").and_then(|t| t.as_str()) == Some("code_execution_tool_result_error");
+
+    let output = match (stdout.is_empty(), stderr.is_empty()) {
+        (false, false) => format!("{}\n{}", stdout, stderr),
+        (false, true) => stdout.to_string(),
+        _ => stderr.to_string(),
+    };
+    json!({
+        "codeExecutionResult": {
+            "outcome": if failed { "OUTCOME_FAILED" } else { "OUTCOME_OK" },
+            "output": output
+        }
+    })
+}
+
+/// 工具列表同时包含 codeExecution 与本地函数时的取舍
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixedToolStrategy {
+    /// 分别作为独立 tool 对象一起下发 (模型支持组合使用)
+    Combined,
+    /// 本轮只下发 functionDeclarations
+    FunctionsOnly,
+    /// 本轮只下发 codeExecution
+    CodeExecutionOnly,
+}
+
+/// 是否支持在同一请求中组合内置工具与 functionDeclarations
+fn supports_combined_tools(mapped_model: &str) -> bool {
+    mapped_model.starts_with("gemini-3")
+}
+
+/// 决定混合工具的下发策略
+///
+/// v1internal 对旧模型不允许内置工具与函数声明混用。此时按对话进度选择:
+/// - 正处于客户端工具调用循环中 (最后一条用户消息携带 tool_result) -> 保留函数声明
+/// - 上一轮助手刚使用过代码执行 -> 保留 codeExecution，让模型继续分析结果
+/// - 其余情况默认保留函数声明 (客户端工具不可替代，代码执行可由模型改用文本回答)
+pub fn resolve_mixed_tools(mapped_model: &str, messages: &[Message]) -> MixedToolStrategy {
+    if supports_combined_tools(mapped_model) {
+        return MixedToolStrategy::Combined;
+    }
+
+    let last_user_has_tool_result = messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .is_some_and(|m| match &m.content {
+            MessageContent::Array(blocks) => blocks
+                .iter()
+                .any(|b| matches!(b, ContentBlock::ToolResult { .. })),
+            _ => false,
+        });
+    if last_user_has_tool_result {
+        return MixedToolStrategy::FunctionsOnly;
+    }
+
+    let last_assistant_used_code = messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "assistant")
+        .is_some_and(|m| match &m.content {
+            MessageContent::Array(blocks) => blocks.iter().any(|b| {
+                matches!(b, ContentBlock::CodeExecutionToolResult { .. })
+                    || matches!(b, ContentBlock::ServerToolUse { name, .. } if name == CODE_EXECUTION_TOOL_NAME)
+            }),
+            _ => false,
+        });
+    if last_assistant_used_code {
+        MixedToolStrategy::CodeExecutionOnly
+    } else {
+        MixedToolStrategy::FunctionsOnly
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, blocks: Vec<ContentBlock>) -> Message {
+        Message {
+            role: role.to_string(),
+            content: MessageContent::Array(blocks),
+        }
+    }
+
+    #[test]
+    fn test_result_mapping_roundtrip() {
+        let ok = execution_result_to_content(&CodeExecutionResult {
+            outcome: Some("OUTCOME_OK".to_string()),
+            output: Some("42\n".to_string()),
+        });
+        assert_eq!(ok["stdout"], "42\n");
+        assert_eq!(ok["return_code"], 0);
+        let part = tool_result_to_part(&ok);
+        assert_eq!(part["codeExecutionResult"]["outcome"], "OUTCOME_OK");
+        assert_eq!(part["codeExecutionResult"]["output"], "42\n");
+
+        let timeout = execution_result_to_content(&CodeExecutionResult {
+            outcome: Some("OUTCOME_DEADLINE_EXCEEDED".to_string()),
+            output: None,
+        });
+        assert_eq!(timeout["stderr"], "Execution timed out");
+        assert_eq!(
+            tool_result_to_part(&timeout)["codeExecutionResult"]["outcome"],
+            "OUTCOME_FAILED"
+        );
+    }
+
+    #[test]
+    fn test_mixed_tool_strategy() {
+        let text = || msg("user", vec![ContentBlock::Text { text: "hi".to_string(), citations: None }]);
+        assert_eq!(
+            resolve_mixed_tools("gemini-3-pro-preview", &[text()]),
+            MixedToolStrategy::Combined
+        );
+        assert_eq!(
+            resolve_mixed_tools("gemini-2.5-flash", &[text()]),
+            MixedToolStrategy::FunctionsOnly
+        );
+
+        let used_code = msg(
+            "assistant",
+            vec![ContentBlock::ServerToolUse {
+                id: "srvtoolu_1".to_string(),
+                name: CODE_EXECUTION_TOOL_NAME.to_string(),
+                input: json!({ "code": "print(1)" }),
+            }],
+        );
+        assert_eq!(
+            resolve_mixed_tools("gemini-2.5-flash", &[text(), used_code.clone(), text()]),
+            MixedToolStrategy::CodeExecutionOnly
+        );
+
+        let tool_result = msg(
+            "user",
+            vec![ContentBlock::ToolResult {
+                tool_use_id: "t1".to_string(),
+                content: json!("done"),
+                is_error: None,
+            }],
+        );
+        assert_eq!(
+            resolve_mixed_tools("gemini-2.5-flash", &[text(), used_code, tool_result]),
+            MixedToolStrategy::FunctionsOnly
+        );
+    }
+}
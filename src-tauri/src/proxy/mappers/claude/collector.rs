@@ -76,20 +76,26 @@ where
         content: Vec::new(),
         stop_reason: "end_turn".to_string(),
         stop_sequence: None,
+        stop_details: None,
         usage: Usage {
             input_tokens: 0,
             output_tokens: 0,
             cache_read_input_tokens: None,
             cache_creation_input_tokens: None,
             server_tool_use: None,
+            thinking_tokens: None,
         },
     };
 
     // 用于累积内容块
     let mut current_text = String::new();
+    let mut current_citations: Vec<Value> = Vec::new();
     let mut current_thinking = String::new();
+    let mut current_signature: Option<String> = None;
     let mut current_tool_use: Option<Value> = None;
     let mut current_tool_input = String::new();
+    // 服务端工具 (server_tool_use / code_execution_tool_result)
+    let mut current_server_block: Option<Value> = None;
 
     for event in events {
         match event.event_type.as_str() {
@@ -114,12 +120,23 @@ where
                 if let Some(content_block) = event.data.get("content_block") {
                     if let Some(block_type) = content_block.get("type").and_then(|v| v.as_str()) {
                         match block_type {
-                            "text" => current_text.clear(),
-                            "thinking" => current_thinking.clear(),
+                            "text" => {
+                                current_text.clear();
+                                current_citations.clear();
+                            }
+                            "thinking" => {
+                                current_thinking.clear();
+                                current_signature = None;
+                            }
                             "tool_use" => {
                                 current_tool_use = Some(content_block.clone());
                                 current_tool_input.clear();
                             }
+                            "server_tool_use" | "code_execution_tool_result" | "web_search_tool_result" | "image"
+                            | "redacted_thinking" => {
+                                current_server_block = Some(content_block.clone());
+                                current_tool_input.clear();
+                            }
                             _ => {}
                         }
                     }
@@ -135,11 +152,21 @@ where
                                     current_text.push_str(text);
                                 }
                             }
+                            "citations_delta" => {
+                                if let Some(citation) = delta.get("citation") {
+                                    current_citations.push(citation.clone());
+                                }
+                            }
                             "thinking_delta" => {
                                 if let Some(thinking) = delta.get("thinking").and_then(|v| v.as_str()) {
                                     current_thinking.push_str(thinking);
                                 }
                             }
+                            "signature_delta" => {
+                                if let Some(sig) = delta.get("signature").and_then(|v| v.as_str()) {
+                                    current_signature = Some(sig.to_string());
+                                }
+                            }
                             "input_json_delta" => {
                                 if let Some(partial_json) = delta.get("partial_json").and_then(|v| v.as_str()) {
                                     current_tool_input.push_str(partial_json);
@@ -156,15 +183,25 @@ where
                 if !current_text.is_empty() {
                     response.content.push(ContentBlock::Text {
                         text: current_text.clone(),
+                        citations: (!current_citations.is_empty()).then(|| std::mem::take(&mut current_citations)),
                     });
                     current_text.clear();
                 } else if !current_thinking.is_empty() {
                     response.content.push(ContentBlock::Thinking {
                         thinking: current_thinking.clone(),
-                        signature: None, // TODO: 从 delta 中提取签名
+                        signature: current_signature.take(),
                         cache_control: None,
                     });
                     current_thinking.clear();
+                } else if let Some(mut block) = current_server_block.take() {
+                    if !current_tool_input.is_empty() {
+                        block["input"] = serde_json::from_str(&current_tool_input).unwrap_or(json!({}));
+                        current_tool_input.clear();
+                    }
+                    match serde_json::from_value::<ContentBlock>(block) {
+                        Ok(b) => response.content.push(b),
+                        Err(e) => tracing::warn!("[Collector] Dropping malformed server tool block: {}", e),
+                    }
                 } else if let Some(tool_use) = current_tool_use.take() {
                     // 构建 tool_use 块
                     let id = tool_use.get("id").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
@@ -191,6 +228,9 @@ where
                     if let Some(stop_reason) = delta.get("stop_reason").and_then(|v| v.as_str()) {
                         response.stop_reason = stop_reason.to_string();
                     }
+                    if let Some(details) = delta.get("stop_details").filter(|d| !d.is_null()) {
+                        response.stop_details = Some(details.clone());
+                    }
                 }
                 if let Some(usage) = event.data.get("usage") {
                     if let Ok(u) = serde_json::from_value::<Usage>(usage.clone()) {
@@ -248,10 +288,33 @@ mod tests {
         assert_eq!(response.model, "claude-3-5-sonnet");
         assert_eq!(response.content.len(), 1);
         
-        if let ContentBlock::Text { text } = &response.content[0] {
+        if let ContentBlock::Text { text, .. } = &response.content[0] {
             assert_eq!(text, "Hello World");
         } else {
             panic!("Expected Text block");
         }
     }
+
+    #[tokio::test]
+    async fn test_collect_image_block() {
+        let sse_data = vec![
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_img\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"gemini-3-pro-image\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{\"input_tokens\":3,\"output_tokens\":0}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"image\",\"source\":{\"type\":\"base64\",\"media_type\":\"image/png\",\"data\":\"aGVsbG8=\"}}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ];
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_stream_to_json(byte_stream).await.unwrap();
+        assert_eq!(response.content.len(), 1);
+        match &response.content[0] {
+            ContentBlock::Image { source, .. } => {
+                assert_eq!(source.media_type, "image/png");
+                assert_eq!(source.data, "aGVsbG8=");
+            }
+            other => panic!("Expected Image block, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,112 @@
+// 上下文超长自动裁剪
+// 上游因输入超过模型上限返回 400 时，丢弃最早的一部分对话轮次后重试一次，
+// 并在保留下来的第一条用户消息前插入提示，让 agent 知道历史已被截断。
+
+use super::models::{ContentBlock, Message, MessageContent};
+
+/// 判断是否为输入超长错误
+pub fn is_context_length_error(status: u16, error_text: &str) -> bool {
+    if status != 400 && status != 413 {
+        return false;
+    }
+    let text = error_text.to_lowercase();
+    [
+        "exceeds the maximum number of tokens",
+        "input token count",
+        "prompt is too long",
+        "context length",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|needle| text.contains(needle))
+}
+
+/// 消息是否可以作为裁剪后的第一条消息 (用户消息且不含 tool_result，避免悬空的工具结果)
+fn is_valid_cut(message: &Message) -> bool {
+    if message.role != "user" {
+        return false;
+    }
+    match &message.content {
+        MessageContent::String(_) => true,
+        MessageContent::Array(blocks) => !blocks.iter().any(|b| matches!(b, ContentBlock::ToolResult { .. })),
+    }
+}
+
+/// 丢弃约一半最早的消息，返回丢弃的条数 (无法安全裁剪时返回 0)
+pub fn drop_oldest_turns(messages: &mut Vec<Message>) -> usize {
+    if messages.len() < 3 {
+        return 0;
+    }
+    let target = messages.len() / 2;
+    // 优先向后找切点 (裁得更多)，找不到再向前找
+    let cut = (target..messages.len())
+        .find(|&i| is_valid_cut(&messages[i]))
+        .or_else(|| (1..target).rev().find(|&i| is_valid_cut(&messages[i])));
+    let Some(cut) = cut else {
+        return 0;
+    };
+
+    messages.drain(..cut);
+    let notice = ContentBlock::Text {
+        text: format!(
+            "[Proxy notice: {} earlier message(s) were removed because the conversation exceeded the model's input limit. \
+             Earlier context may be missing.]",
+            cut
+        ),
+        citations: None,
+    };
+    let first = &mut messages[0];
+    first.content = match std::mem::replace(&mut first.content, MessageContent::Array(vec![])) {
+        MessageContent::String(text) => MessageContent::Array(vec![notice, ContentBlock::Text { text, citations: None }]),
+        MessageContent::Array(mut blocks) => {
+            blocks.insert(0, notice);
+            MessageContent::Array(blocks)
+        }
+    };
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detects_context_length_errors() {
+        assert!(is_context_length_error(
+            400,
+            r#"{"error":{"code":400,"message":"The input token count (1200000) exceeds the maximum number of tokens allowed (1048576).","status":"INVALID_ARGUMENT"}}"#
+        ));
+        assert!(!is_context_length_error(400, "Invalid `signature` in thinking block"));
+        assert!(!is_context_length_error(429, "too many tokens per minute"));
+    }
+
+    #[test]
+    fn test_drop_oldest_turns_keeps_tool_pairs_intact() {
+        let mut messages: Vec<Message> = serde_json::from_value(json!([
+            {"role": "user", "content": "first question"},
+            {"role": "assistant", "content": "first answer"},
+            {"role": "user", "content": "run it"},
+            {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "run", "input": {}}]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "ok"}]},
+            {"role": "assistant", "content": "done"},
+            {"role": "user", "content": "latest question"}
+        ]))
+        .unwrap();
+
+        let dropped = drop_oldest_turns(&mut messages);
+        // target = 3 (assistant tool_use)、4 为 tool_result，均不可作为切点，6 为最近的有效切点
+        assert_eq!(dropped, 6);
+        assert_eq!(messages.len(), 1);
+        match &messages[0].content {
+            MessageContent::Array(blocks) => {
+                assert!(matches!(&blocks[0], ContentBlock::Text { text, .. } if text.contains("6 earlier message(s)")));
+                assert!(matches!(&blocks[1], ContentBlock::Text { text, .. } if text == "latest question"));
+            }
+            other => panic!("unexpected content: {:?}", other),
+        }
+
+        let mut short: Vec<Message> = serde_json::from_value(json!([{"role": "user", "content": "hi"}])).unwrap();
+        assert_eq!(drop_oldest_turns(&mut short), 0);
+    }
+}
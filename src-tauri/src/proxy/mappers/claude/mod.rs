@@ -8,35 +8,95 @@ pub mod streaming;
 pub mod utils;
 pub mod thinking_utils;
 pub mod collector;
+pub mod code_execution;
+pub mod betas;
+pub mod stream_bridge;
+pub mod context_trim;
+pub mod tool_guardrails;
+pub mod tool_dedupe;
+pub mod citations;
+pub mod transform_cache;
+pub mod tool_ids;
 
 pub use models::*;
-pub use request::transform_claude_request_in;
+pub use request::{transform_claude_request_in, transform_claude_request_in_with};
 pub use response::transform_response;
-pub use streaming::{PartProcessor, StreamingState};
-pub use thinking_utils::close_tool_loop_for_thinking;
+pub use streaming::{build_error_event, PartProcessor, StreamingState};
+pub use thinking_utils::{close_tool_loop_for_thinking, pending_redacted_thinking};
 pub use collector::collect_stream_to_json;
 
 use bytes::Bytes;
 use futures::Stream;
 use std::pin::Pin;
+use std::time::Duration;
+
+/// 上游静默超过该时长时发送 ping 事件 (与 Anthropic 官方行为一致，防止中间代理断开空闲连接)
+const PING_INTERVAL: Duration = Duration::from_secs(15);
 
 /// 创建从 Gemini SSE 流到 Claude SSE 流的转换
+#[allow(clippy::too_many_arguments)]
 pub fn create_claude_sse_stream(
-    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     trace_id: String,
     email: String,
     session_id: Option<String>, // [NEW v3.3.17] Session ID for signature caching
+    redacted_thinking: Vec<String>,
+    citations: Option<citations::CitationTracker>,
+    locale: crate::proxy::common::output_locale::Locale,
+    usage_heartbeat: Option<crate::proxy::common::usage_heartbeat::UsageHeartbeat>,
+    thinking_output: crate::proxy::config::ThinkingOutput,
+    tool_id_seed: String,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    let mut state = StreamingState::new();
+    // 同一客户端请求的重试流为相同位置的工具调用分配相同的 id (见 tool_ids::request_seed)
+    state.tool_ids = tool_ids::ToolIdAllocator::new(tool_id_seed);
+    state.session_id = session_id; // Set session ID for signature caching
+    state.redacted_thinking = redacted_thinking;
+    state.citations = citations;
+    state.locale = locale;
+    state.usage_heartbeat = usage_heartbeat;
+    state.thoughts = crate::proxy::mappers::thinking_output::ThoughtRouter::new(thinking_output);
+    create_claude_sse_stream_with_ping(gemini_stream, trace_id, email, state, PING_INTERVAL)
+}
+
+/// 同 `create_claude_sse_stream`，可指定初始状态与 ping 间隔
+///
+/// - message_start 之前不发送 ping：handler 需要通过首个 chunk 判断是否重试
+/// - message_start 之后的上游错误转换为规范的 error 事件 + message_stop；
+///   之前的错误仍以 Err 返回，交由 handler 换号重试
+pub fn create_claude_sse_stream_with_ping(
+    mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+    email: String,
+    mut state: StreamingState,
+    ping_interval: Duration,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     use async_stream::stream;
     use bytes::BytesMut;
     use futures::StreamExt;
 
     Box::pin(stream! {
-        let mut state = StreamingState::new();
-        state.session_id = session_id; // Set session ID for signature caching
         let mut buffer = BytesMut::new();
 
-        while let Some(chunk_result) = gemini_stream.next().await {
+        loop {
+            let next = if state.message_start_sent && !state.message_stop_sent {
+                match tokio::time::timeout(ping_interval, gemini_stream.next()).await {
+                    Ok(item) => item,
+                    Err(_) => {
+                        tracing::debug!("[{}] Upstream idle for {:?}, sending ping", trace_id, ping_interval);
+                        yield Ok(state.emit_ping());
+                        if let Some(heartbeat) = state.emit_usage_heartbeat() {
+                            yield Ok(heartbeat);
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                gemini_stream.next().await
+            };
+
+            let Some(chunk_result) = next else { break };
+
             match chunk_result {
                 Ok(chunk) => {
                     buffer.extend_from_slice(&chunk);
@@ -48,16 +108,30 @@ pub fn create_claude_sse_stream(
                             let line = line_str.trim();
                             if line.is_empty() { continue; }
 
-                            if let Some(sse_chunks) = process_sse_line(line, &mut state, &trace_id, &email) {
+                            let mapped = crate::proxy::common::timing::timed("sse_chunk", line.len(), || {
+                                process_sse_line(line, &mut state, &trace_id, &email)
+                            });
+                            if let Some(sse_chunks) = mapped {
                                 for sse_chunk in sse_chunks {
                                     yield Ok(sse_chunk);
                                 }
                             }
                         }
                     }
+
+                    if let Some(heartbeat) = state.emit_usage_heartbeat() {
+                        yield Ok(heartbeat);
+                    }
                 }
                 Err(e) => {
-                    yield Err(format!("Stream error: {}", e));
+                    if state.message_start_sent {
+                        tracing::error!("[{}] Upstream stream failed mid-response: {}", trace_id, e);
+                        for chunk in state.emit_error("api_error", &format!("Upstream stream error: {}", e)) {
+                            yield Ok(chunk);
+                        }
+                    } else {
+                        yield Err(format!("Stream error: {}", e));
+                    }
                     break;
                 }
             }
@@ -99,6 +173,9 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
 
     // 解包 response 字段 (如果存在)
     let raw_json = json_value.get("response").unwrap_or(&json_value);
+    if let Some(heartbeat) = state.usage_heartbeat.as_mut() {
+        heartbeat.observe(raw_json);
+    }
 
     // 发送 message_start
     if !state.message_start_sent {
@@ -142,6 +219,23 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
         }
     }
 
+    // [NEW] 文档引用：回答中被标注的片段定位到文档后以 citations_delta 下发到当前文本块
+    if let (Some(tracker), Some(candidate)) = (
+        state.citations.as_mut(),
+        raw_json.get("candidates").and_then(|c| c.get(0)),
+    ) {
+        let found = tracker.observe(candidate);
+        if !found.is_empty() {
+            if state.current_block_type() == streaming::BlockType::Text {
+                for citation in found {
+                    chunks.push(state.emit_delta("citations_delta", serde_json::json!({ "citation": citation })));
+                }
+            } else {
+                tracing::debug!("[{}] Dropping {} citation(s) outside a text block", trace_id, found.len());
+            }
+        }
+    }
+
     // Process grounding metadata (googleSearch results) and append as citations
     // [DISABLED] Temporarily disabled to fix Cherry Studio compatibility
     // Cherry Studio doesn't recognize "web_search_tool_result" type, causing validation errors
@@ -159,13 +253,21 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     }
     */
 
-    // 检查是否结束
-    if let Some(finish_reason) = raw_json
+    // [NEW] 安全拦截：记录原因与类别，随 message_delta 下发
+    let safety_block = crate::proxy::mappers::safety::detect_safety_block(raw_json);
+    if let Some(block) = &safety_block {
+        tracing::warn!("[{}] {}", trace_id, block.describe());
+        state.safety_block = Some(block.clone());
+    }
+
+    // 检查是否结束 (提示词被拦截时没有 candidates，以 blockReason 作为结束原因)
+    let finish_reason = raw_json
         .get("candidates")
         .and_then(|c| c.get(0))
         .and_then(|cand| cand.get("finishReason"))
         .and_then(|f| f.as_str())
-    {
+        .or(safety_block.as_ref().map(|b| b.reason.as_str()));
+    if let Some(finish_reason) = finish_reason {
         let usage = raw_json
             .get("usageMetadata")
             .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok());
@@ -188,6 +290,7 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
              );
         }
 
+        chunks.extend(PartProcessor::new(state).close_inline_thinking());
         chunks.extend(state.emit_finish(Some(finish_reason), usage.as_ref()));
     }
 
@@ -339,6 +442,165 @@ fn process_grounding_metadata(
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_ping_emitted_while_upstream_idle() {
+        use futures::StreamExt;
+
+        let first = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}],\"modelVersion\":\"test\"}\n\n";
+        let last = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"!\"}]},\"finishReason\":\"STOP\"}]}\n\n";
+        let upstream = async_stream::stream! {
+            yield Ok::<Bytes, reqwest::Error>(Bytes::from(first));
+            // 模拟长时间工具执行 / 思考导致的上游静默
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            yield Ok(Bytes::from(last));
+        };
+
+        let output: String = create_claude_sse_stream_with_ping(
+            Box::pin(upstream),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            StreamingState::new(),
+            Duration::from_millis(50),
+        )
+        .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        let start = output.find("event: message_start").unwrap();
+        let ping = output.find("event: ping").expect("ping event");
+        let stop = output.find("event: message_stop").unwrap();
+        assert!(start < ping && ping < stop);
+        assert!(output.contains("data: {\"type\":\"ping\"}"));
+    }
+
+    #[tokio::test]
+    async fn test_thinking_output_modes() {
+        use crate::proxy::mappers::thinking_output::{ThinkingOutput, ThoughtRouter};
+        use futures::StreamExt;
+
+        async fn run(mode: ThinkingOutput) -> String {
+            let chunk = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"pondering\",\"thought\":true,\"thoughtSignature\":\"sig-1\"},{\"text\":\"Answer\"}]},\"finishReason\":\"STOP\"}],\"modelVersion\":\"test\"}\n\n";
+            let upstream = futures::stream::iter(vec![Ok::<Bytes, reqwest::Error>(Bytes::from(chunk))]);
+            let mut state = StreamingState::new();
+            state.thoughts = ThoughtRouter::new(mode);
+            create_claude_sse_stream_with_ping(Box::pin(upstream), "trace".to_string(), "test@example.com".to_string(), state, PING_INTERVAL)
+                .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+                .collect::<Vec<_>>()
+                .await
+                .concat()
+        }
+
+        let output = run(ThinkingOutput::Reasoning).await;
+        assert!(output.contains("\"thinking\":\"pondering\""));
+
+        // 丢弃思考文本，签名仍随空 thinking 块下发
+        let output = run(ThinkingOutput::Drop).await;
+        assert!(!output.contains("pondering"));
+        assert!(output.contains("\"signature\":\"sig-1\""));
+        assert!(output.contains("\"text\":\"Answer\""));
+
+        let output = run(ThinkingOutput::Inline).await;
+        assert!(output.contains("\"text\":\"<think>\\npondering\""));
+        assert!(output.contains("\\n</think>\\n\\nAnswer"));
+        assert!(!output.contains("\"thinking\":\"pondering\""));
+    }
+
+    #[tokio::test]
+    async fn test_retried_stream_keeps_tool_use_ids() {
+        use futures::StreamExt;
+
+        async fn tool_ids(seed: &str) -> Vec<String> {
+            let call = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"functionCall\":{\"name\":\"read_file\",\"args\":{\"path\":\"a\"}}},{\"functionCall\":{\"name\":\"read_file\",\"args\":{\"path\":\"b\"}}}]},\"finishReason\":\"STOP\"}],\"modelVersion\":\"test\"}\n\n";
+            let upstream = futures::stream::iter(vec![Ok::<Bytes, reqwest::Error>(Bytes::from(call))]);
+            let mut state = StreamingState::new();
+            state.tool_ids = tool_ids::ToolIdAllocator::new(seed);
+            let output: String = create_claude_sse_stream_with_ping(
+                Box::pin(upstream),
+                "trace".to_string(),
+                "test@example.com".to_string(),
+                state,
+                PING_INTERVAL,
+            )
+            .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+            output
+                .lines()
+                .filter_map(|l| l.strip_prefix("data: "))
+                .filter_map(|d| serde_json::from_str::<serde_json::Value>(d).ok())
+                .filter(|v| v["content_block"]["type"] == "tool_use")
+                .map(|v| v["content_block"]["id"].as_str().unwrap().to_string())
+                .collect()
+        }
+
+        let seed = tool_ids::request_seed("trace-1");
+        let first = tool_ids(&seed).await;
+        assert_eq!(first.len(), 2);
+        assert_ne!(first[0], first[1]);
+        // 同一请求重试得到的流分配相同的 id，复用 trace_id 的下一个请求互不相同
+        assert_eq!(tool_ids(&seed).await, first);
+        assert_ne!(tool_ids(&tool_ids::request_seed("trace-1")).await, first);
+    }
+
+    #[tokio::test]
+    async fn test_usage_heartbeat_between_start_and_stop() {
+        use crate::proxy::common::usage_heartbeat::UsageHeartbeat;
+        use futures::StreamExt;
+
+        let first = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}],\"usageMetadata\":{\"promptTokenCount\":5,\"candidatesTokenCount\":7},\"modelVersion\":\"test\"}\n\n";
+        let last = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"!\"}]},\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":5,\"candidatesTokenCount\":8}}\n\n";
+        let upstream = async_stream::stream! {
+            yield Ok::<Bytes, reqwest::Error>(Bytes::from(first));
+            yield Ok(Bytes::from(last));
+        };
+
+        let mut state = StreamingState::new();
+        state.usage_heartbeat = Some(UsageHeartbeat::new(Duration::ZERO));
+        let output: String = create_claude_sse_stream_with_ping(
+            Box::pin(upstream),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            state,
+            PING_INTERVAL,
+        )
+        .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+        let heartbeat = output.find("\"stop_reason\":null").expect("heartbeat event");
+        assert!(output.find("event: message_start").unwrap() < heartbeat);
+        assert!(heartbeat < output.find("event: message_stop").unwrap());
+        // 最终用量仍由结束时的 message_delta 下发
+        assert!(output.contains("\"stop_reason\":\"end_turn\""));
+    }
+
+    #[tokio::test]
+    async fn test_no_ping_before_message_start() {
+        use futures::StreamExt;
+
+        let upstream = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            yield Ok::<Bytes, reqwest::Error>(Bytes::from("data: [DONE]\n\n"));
+        };
+
+        let chunks: Vec<String> = create_claude_sse_stream_with_ping(
+            Box::pin(upstream),
+            "trace".to_string(),
+            "test@example.com".to_string(),
+            StreamingState::new(),
+            Duration::from_millis(20),
+        )
+        .map(|r| String::from_utf8(r.unwrap().to_vec()).unwrap())
+        .collect()
+        .await;
+
+        // 首个 chunk 必须是真实内容，handler 依赖它判断空响应重试
+        assert!(!chunks.concat().contains("event: ping"));
+    }
+
     #[test]
     fn test_process_sse_line_done() {
         let mut state = StreamingState::new();
@@ -29,6 +29,12 @@ pub struct ClaudeRequest {
     /// Output configuration for effort level (Claude API v2.0.67+)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output_config: Option<OutputConfig>,
+    /// Structured output (JSON mode) extension, mapped to Gemini responseMimeType/responseSchema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<OutputFormat>,
+    /// 由 anthropic-beta 请求头协商出的 beta 集合 (不属于请求体)
+    #[serde(skip)]
+    pub betas: super::betas::AnthropicBetas,
 }
 
 /// Thinking 配置
@@ -74,7 +80,12 @@ pub enum MessageContent {
 #[serde(tag = "type")]
 pub enum ContentBlock {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        /// [NEW] 文档引用 (char_location 等)，仅在响应中由 citations 模块填充
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<serde_json::Value>>,
+    },
 
     #[serde(rename = "thinking")]
     Thinking {
@@ -97,6 +108,13 @@ pub enum ContentBlock {
         source: DocumentSource,
         #[serde(skip_serializing_if = "Option::is_none")]
         cache_control: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        context: Option<String>,
+        /// `{"enabled": true}` 时响应中的文本块携带指向该文档的引用
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<serde_json::Value>,
     },
 
     #[serde(rename = "redacted_thinking")]
@@ -135,6 +153,13 @@ pub enum ContentBlock {
         tool_use_id: String,
         content: serde_json::Value,
     },
+
+    /// 代码执行结果 (对应 Gemini codeExecutionResult)
+    #[serde(rename = "code_execution_tool_result")]
+    CodeExecutionToolResult {
+        tool_use_id: String,
+        content: serde_json::Value,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -148,9 +173,9 @@ pub struct ImageSource {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentSource {
     #[serde(rename = "type")]
-    pub source_type: String, // "base64"
-    pub media_type: String,  // e.g. "application/pdf"
-    pub data: String,        // base64 data
+    pub source_type: String, // "base64" | "text"
+    pub media_type: String,  // e.g. "application/pdf" | "text/plain"
+    pub data: String,        // base64 data / plain text
 }
 
 /// Tool - supports both client tools (with input_schema) and server tools (like web_search)
@@ -188,6 +213,16 @@ impl Tool {
         false
     }
 
+    /// Check if this is the code execution server tool (e.g. "code_execution_20250522")
+    pub fn is_code_execution(&self) -> bool {
+        if let Some(ref t) = self.type_ {
+            if t.starts_with("code_execution") {
+                return true;
+            }
+        }
+        matches!(self.name.as_deref(), Some("code_execution")) && self.input_schema.is_none()
+    }
+
     /// Get the effective tool name
     #[allow(dead_code)]
     pub fn get_name(&self) -> String {
@@ -219,6 +254,27 @@ pub struct OutputConfig {
     pub effort: Option<String>,
 }
 
+/// Structured Output 配置 (JSON mode)
+/// 兼容 Anthropic structured-outputs 扩展: {"type": "json_schema", "schema": {...}}
+/// 同时接受 "json" / "json_object" (仅约束输出为 JSON，不限定结构)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputFormat {
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+}
+
+impl OutputFormat {
+    /// 是否请求 JSON 输出
+    pub fn is_json(&self) -> bool {
+        matches!(
+            self.type_.as_str(),
+            "json_schema" | "json" | "json_object"
+        )
+    }
+}
+
 /// Claude API 响应
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClaudeResponse {
@@ -229,8 +285,12 @@ pub struct ClaudeResponse {
     pub model: String,
     pub content: Vec<ContentBlock>,
     pub stop_reason: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// 与官方 API 一致，始终下发 (未命中时为 null)
+    #[serde(default)]
     pub stop_sequence: Option<String>,
+    /// 安全拦截等非正常结束的详情 (stop_reason 为 refusal 时)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_details: Option<serde_json::Value>,
     pub usage: Usage,
 }
 
@@ -245,6 +305,9 @@ pub struct Usage {
     pub cache_creation_input_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server_tool_use: Option<serde_json::Value>,
+    /// [NEW] 扩展字段：思考消耗的 token (Gemini thoughtsTokenCount，不计入 output_tokens)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_tokens: Option<u32>,
 }
 
 // ========== Gemini 数据模型 ==========
@@ -280,6 +343,32 @@ pub struct GeminiPart {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "inlineData")]
     pub inline_data: Option<InlineData>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "executableCode")]
+    pub executable_code: Option<ExecutableCode>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "codeExecutionResult")]
+    pub code_execution_result: Option<CodeExecutionResult>,
+}
+
+/// Gemini codeExecution 工具生成的代码
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutableCode {
+    #[serde(default)]
+    pub language: Option<String>,
+    pub code: String,
+}
+
+/// Gemini codeExecution 工具的执行结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeExecutionResult {
+    /// OUTCOME_OK / OUTCOME_FAILED / OUTCOME_DEADLINE_EXCEEDED
+    #[serde(default)]
+    pub outcome: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -320,6 +409,9 @@ pub struct GeminiResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "responseId")]
     pub response_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "promptFeedback")]
+    pub prompt_feedback: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -334,6 +426,12 @@ pub struct Candidate {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "groundingMetadata")]
     pub grounding_metadata: Option<GroundingMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "safetyRatings")]
+    pub safety_ratings: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "finishMessage")]
+    pub finish_message: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -350,6 +448,9 @@ pub struct UsageMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "cachedContentTokenCount")]
     pub cached_content_token_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "thoughtsTokenCount")]
+    pub thoughts_token_count: Option<u32>,
 }
 
 // ========== Grounding Metadata (for googleSearch results) ==========
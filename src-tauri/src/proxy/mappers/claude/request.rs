@@ -3,7 +3,9 @@
 
 use super::models::*;
 use crate::proxy::mappers::signature_store::get_thought_signature; // Deprecated, kept for fallback
+use crate::proxy::common::timing::{json_size, timed};
 use crate::proxy::session_manager::SessionManager;
+use crate::proxy::model_capabilities::ModelCapabilities;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
@@ -107,6 +109,31 @@ fn clean_cache_control_from_messages(messages: &mut [Message]) {
     }
 }
 
+/// 是否存在需要预清理的消息 (cache_control 字段或 assistant 消息中位置错误的 thinking 块)
+fn needs_message_cleanup(messages: &[Message]) -> bool {
+    messages.iter().any(|msg| {
+        let MessageContent::Array(blocks) = &msg.content else {
+            return false;
+        };
+        let has_cache_control = blocks.iter().any(|block| {
+            matches!(
+                block,
+                ContentBlock::Thinking { cache_control: Some(_), .. }
+                    | ContentBlock::Image { cache_control: Some(_), .. }
+                    | ContentBlock::Document { cache_control: Some(_), .. }
+                    | ContentBlock::ToolUse { cache_control: Some(_), .. }
+            )
+        });
+        let is_thinking = |b: &ContentBlock| matches!(b, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. });
+        let thinking_out_of_order = msg.role == "assistant"
+            && blocks
+                .iter()
+                .skip_while(|b| is_thinking(b))
+                .any(is_thinking);
+        has_cache_control || thinking_out_of_order
+    })
+}
+
 /// [FIX #564] Sort blocks in assistant messages to ensure thinking blocks are first
 /// 
 /// When context compression (kilo) reorders message blocks, thinking blocks may appear
@@ -169,18 +196,36 @@ fn sort_thinking_blocks_first(messages: &mut [Message]) {
 pub fn transform_claude_request_in(
     claude_req: &ClaudeRequest,
     project_id: &str,
+) -> Result<Value, String> {
+    transform_claude_request_in_with(claude_req, project_id, &[], None)
+}
+
+/// 同 `transform_claude_request_in`，`dummy_thought_models` 为允许注入占位思维块的目标模型 (支持 * 通配)，
+/// `capabilities` 为目标模型的探测结果 (未探测时按模型名判断)
+pub fn transform_claude_request_in_with(
+    claude_req: &ClaudeRequest,
+    project_id: &str,
+    dummy_thought_models: &[String],
+    capabilities: Option<&ModelCapabilities>,
 ) -> Result<Value, String> {
     // [CRITICAL FIX] 预先清理所有消息中的 cache_control 字段
     // 这解决了 VS Code 插件等客户端在多轮对话中将历史消息的 cache_control 字段
     // 原封不动发回导致的 "Extra inputs are not permitted" 错误
-    let mut cleaned_req = claude_req.clone();
-    clean_cache_control_from_messages(&mut cleaned_req.messages);
-    
     // [FIX #564] Pre-sort thinking blocks to be first in assistant messages
     // This handles cases where context compression (kilo) incorrectly reorders blocks
-    sort_thinking_blocks_first(&mut cleaned_req.messages);
+    // [PERF] 仅在确实需要清理时才克隆请求，大历史会话的常见路径直接借用原始数据
+    let cleaned_req: std::borrow::Cow<ClaudeRequest> = if needs_message_cleanup(&claude_req.messages) {
+        let mut owned = claude_req.clone();
+        timed("clean_cache_control", owned.messages.len(), || {
+            clean_cache_control_from_messages(&mut owned.messages);
+            sort_thinking_blocks_first(&mut owned.messages);
+        });
+        std::borrow::Cow::Owned(owned)
+    } else {
+        std::borrow::Cow::Borrowed(claude_req)
+    };
     
-    let claude_req = &cleaned_req; // 后续使用清理后的请求
+    let claude_req: &ClaudeRequest = &cleaned_req; // 后续使用清理后的请求
 
     // [NEW] Generate session ID for signature tracking
     // This enables session-isolated signature storage, preventing cross-conversation pollution
@@ -229,12 +274,20 @@ pub fn transform_claude_request_in(
     // Resolve grounding config
     let config = crate::proxy::mappers::common_utils::resolve_request_config(&claude_req.model, &mapped_model, &tools_val);
     
-    // [CRITICAL FIX] Disable dummy thought injection for Vertex AI
-    // [CRITICAL FIX] Disable dummy thought injection for Vertex AI
-    // Vertex AI rejects thinking blocks without valid signatures
-    // Even if thinking is enabled, we should NOT inject dummy blocks for historical messages
-    let allow_dummy_thought = false;
+    // [CRITICAL FIX] Disable dummy thought injection for Vertex AI by default
+    // Vertex AI rejects thinking blocks without valid signatures, so dummy blocks are only
+    // injected for target models explicitly listed in experimental.dummy_thought_models
+    let allow_dummy_thought = dummy_thought_models.iter().any(|p| {
+        p == &mapped_model
+            || (p.contains('*') && crate::proxy::common::model_mapping::wildcard_match(p, &mapped_model))
+    });
+    if allow_dummy_thought {
+        tracing::debug!("[Claude-Request] Dummy thought injection enabled for target model {}", mapped_model);
+    }
     
+    // 优先使用探测结果 (联网请求固定使用 WEB_SEARCH_FALLBACK_MODEL，探测结果不适用)
+    let capabilities = capabilities.filter(|_| !has_web_search_tool).copied();
+
     // Check if thinking is enabled in the request
     let mut is_thinking_enabled = claude_req
         .thinking
@@ -243,14 +296,14 @@ pub fn transform_claude_request_in(
         .unwrap_or_else(|| {
             // [Claude Code v2.0.67+] Default thinking enabled for Opus 4.5
             // If no thinking config is provided, enable by default for Opus models
-            should_enable_thinking_by_default(&claude_req.model)
+            should_enable_thinking_by_default(&claude_req.model, capabilities.as_ref())
         });
 
     // [NEW FIX] Check if target model supports thinking
-    // Only models with "-thinking" suffix or Claude models support thinking
-    // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT support thinking
-    let target_model_supports_thinking = mapped_model.contains("-thinking") 
-        || mapped_model.starts_with("claude-");
+    // 未探测时按模型名判断: 只有 "-thinking" 后缀或 Claude 模型支持 thinking
+    let target_model_supports_thinking = capabilities
+        .unwrap_or_else(|| ModelCapabilities::guess(&mapped_model))
+        .thinking;
     
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -323,26 +376,32 @@ pub fn transform_claude_request_in(
     let generation_config = build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled);
 
     // 2. Contents (Messages)
-    let contents = build_contents(
-        &claude_req.messages,
-        &mut tool_id_to_name,
-        is_thinking_enabled,
-        allow_dummy_thought,
-        &mapped_model,
-        &session_id,
-    )?;
+    let contents = timed("build_contents", claude_req.messages.len(), || {
+        build_contents(
+            &claude_req.messages,
+            &mut tool_id_to_name,
+            is_thinking_enabled,
+            allow_dummy_thought,
+            &mapped_model,
+            &session_id,
+        )
+    })?;
 
     // 3. Tools
-    let tools = build_tools(&claude_req.tools, has_web_search_tool)?;
+    let tool_count = claude_req.tools.as_ref().map_or(0, |t| t.len());
+    let tools = timed("build_tools", tool_count, || {
+        build_tools(&claude_req.tools, has_web_search_tool, &mapped_model, &claude_req.messages)
+    })?;
 
     // 5. Safety Settings (configurable via GEMINI_SAFETY_THRESHOLD env var)
     let safety_settings = build_safety_settings();
 
     // Build inner request
     let mut inner_request = json!({
-        "contents": contents,
         "safetySettings": safety_settings,
     });
+    // [PERF] 直接移入 contents (json! 宏会对表达式做一次完整的序列化复制)
+    inner_request["contents"] = contents;
 
     // 深度清理 [undefined] 字符串 (Cherry Studio 等客户端常见注入)
     crate::proxy::mappers::common_utils::deep_clean_undefined(&mut inner_request);
@@ -370,26 +429,17 @@ pub fn transform_claude_request_in(
         crate::proxy::mappers::common_utils::inject_google_search_tool(&mut inner_request);
     }
 
-    // Inject imageConfig if present (for image generation models)
-    if let Some(image_config) = config.image_config {
-        if let Some(obj) = inner_request.as_object_mut() {
-            // 1. Remove tools (image generation does not support tools)
-            obj.remove("tools");
-
-            // 2. Remove systemInstruction (image generation does not support system prompts)
-            obj.remove("systemInstruction");
-
-            // 3. Clean generationConfig (remove thinkingConfig, responseMimeType, responseModalities etc.)
-            let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
-            if let Some(gen_obj) = gen_config.as_object_mut() {
-                gen_obj.remove("thinkingConfig");
-                gen_obj.remove("responseMimeType");
-                gen_obj.remove("responseModalities");
-                gen_obj.insert("imageConfig".to_string(), image_config);
-            }
-        }
+    // 图片模型：纯图片模式移除不支持的字段，图文混合输出写入 responseModalities
+    crate::proxy::mappers::common_utils::apply_output_modalities(&mut inner_request, &config);
+
+    // 探测确认不支持工具 / systemInstruction 的目标模型
+    if let Some(caps) = &capabilities {
+        crate::proxy::model_capabilities::apply_to_request(&mut inner_request, caps);
     }
 
+    // [FIX] systemInstruction 的 role 按目标模型系列规范化
+    crate::proxy::mappers::common_utils::apply_system_instruction_role(&mut inner_request, &config.final_model);
+
     // 生成 requestId
     let request_id = format!("agent-{}", uuid::Uuid::new_v4());
 
@@ -447,7 +497,11 @@ fn should_disable_thinking_due_to_history(messages: &[Message]) -> bool {
 /// Claude Code v2.0.67+ enables thinking by default for Opus 4.5 models.
 /// This function determines if the model should have thinking enabled
 /// when no explicit thinking configuration is provided.
-fn should_enable_thinking_by_default(model: &str) -> bool {
+/// 目标模型已探测时以探测结果为准，不再按请求的模型名判断
+fn should_enable_thinking_by_default(model: &str, capabilities: Option<&ModelCapabilities>) -> bool {
+    if let Some(caps) = capabilities {
+        return caps.thinking;
+    }
     let model_lower = model.to_lowercase();
 
     // Enable thinking by default for Opus 4.5 variants
@@ -575,51 +629,33 @@ fn build_contents(
     mapped_model: &str,
     session_id: &str, // [NEW v3.3.17] Session ID for signature caching
 ) -> Result<Value, String> {
-    let mut contents = Vec::new();
+    // [PERF] 单次遍历：相邻同角色消息在构建时直接合并 (移动 parts，不再二次克隆)
+    let mut contents = Vec::with_capacity(messages.len());
     let mut last_thought_signature: Option<String> = None;
     // Track pending tool_use IDs for recovery
     let mut pending_tool_use_ids: Vec<String> = Vec::new();
-
-    let _msg_count = messages.len();
-    for (_i, msg) in messages.iter().enumerate() {
+    // Track tool results in the current user turn to identify missing ones
+    // [FIX] 跨越连续的 user 消息累计 (客户端可能把多个 tool_result 拆成多条消息)
+    let mut current_turn_tool_result_ids = std::collections::HashSet::new();
+
+    for (i, msg) in messages.iter().enumerate() {
+        // [FIX] 连续的同角色消息在 push_merged 中会合并为同一个 turn。
+        // 拆分成多条的 assistant 消息 (Text -> ToolUse -> Text) 不能被视为工具链中断，
+        // 否则会在中间插入合成的 functionResponse，把后面的文本挤到另一个 turn 里。
+        // 未完成的 tool_use 留到下一个 user turn 结束时统一补齐。
+        let continues_turn = i > 0 && messages[i - 1].role == msg.role;
+        let ends_turn = messages.get(i + 1).is_none_or(|next| next.role != msg.role);
         let role = if msg.role == "assistant" {
-            // Proactive Tool Chain Repair:
-            // If we are about to process an Assistant message, but we still have pending tool_use_ids,
-            // it means the previous turn was interrupted or the user ignored the tool.
-            // We MUST inject a synthetic User message with error results to close the loop.
-            if !pending_tool_use_ids.is_empty() {
-                tracing::warn!("[Elastic-Recovery] Detected interrupted tool chain (Assistant -> Assistant). Injecting synthetic User message for IDs: {:?}", pending_tool_use_ids);
-                
-                let synthetic_parts: Vec<serde_json::Value> = pending_tool_use_ids.iter().map(|id| {
-                    let name = tool_id_to_name.get(id).cloned().unwrap_or(id.clone());
-                    json!({
-                        "functionResponse": {
-                            "name": name,
-                            "response": {
-                                "result": "Tool execution interrupted. No result provided."
-                            },
-                            "id": id
-                        }
-                    })
-                }).collect();
-
-                contents.push(json!({
-                    "role": "user",
-                    "parts": synthetic_parts
-                }));
-                // Clear pending IDs as we have handled them
-                pending_tool_use_ids.clear();
-            }
             "model"
         } else {
             &msg.role
         };
+        if !continues_turn {
+            current_turn_tool_result_ids.clear();
+        }
 
         let mut parts = Vec::new();
 
-        // Track tool results in the current turn to identify missing ones
-        let mut current_turn_tool_result_ids = std::collections::HashSet::new();
-
         match &msg.content {
             MessageContent::String(text) => {
                 if text != "(no content)" {
@@ -631,7 +667,7 @@ fn build_contents(
             MessageContent::Array(blocks) => {
                 for item in blocks {
                     match item {
-                        ContentBlock::Text { text } => {
+                        ContentBlock::Text { text, .. } => {
                             if text != "(no content)" {
                                 parts.push(json!({"text": text}));
                             }
@@ -711,12 +747,10 @@ fn build_contents(
                             }
                             parts.push(part);
                         }
-                        ContentBlock::RedactedThinking { data } => {
-                            // [FIX] 将 RedactedThinking 作为普通文本处理，保留上下文
-                            tracing::debug!("[Claude-Request] Degrade RedactedThinking to text");
-                            parts.push(json!({
-                                "text": format!("[Redacted Thinking: {}]", data)
-                            }));
+                        ContentBlock::RedactedThinking { .. } => {
+                            // [FIX] 不再降级为文本：data 是上游屏蔽的内容，不能暴露给 Gemini。
+                            // 块本身由 handler 在本地保留并回传 (见 pending_redacted_thinking)
+                            tracing::debug!("[Claude-Request] Dropping RedactedThinking from upstream request");
                             continue;
                         }
                         ContentBlock::Image { source, .. } => {
@@ -729,8 +763,13 @@ fn build_contents(
                                 }));
                             }
                         }
-                        ContentBlock::Document { source, .. } => {
-                            if source.source_type == "base64" {
+                        ContentBlock::Document { source, title, context, .. } => {
+                            // [NEW] 纯文本文档以文本 part 发送，引用定位见 citations 模块
+                            if source.source_type == "text" {
+                                parts.push(json!({
+                                    "text": super::citations::document_prompt(title.as_deref(), context.as_deref(), &source.data)
+                                }));
+                            } else if source.source_type == "base64" {
                                 parts.push(json!({
                                     "inlineData": {
                                         "mimeType": source.media_type,
@@ -756,8 +795,9 @@ fn build_contents(
                             // [New] 递归清理参数中可能存在的非法校验字段
                             crate::proxy::common::json_schema::clean_json_schema(&mut part);
 
-                            // 存储 id -> name 映射
+                            // 存储 id -> name 映射 (同时按 tool_use id 全局缓存，供截断历史后的 tool_result 使用)
                             tool_id_to_name.insert(id.clone(), name.clone());
+                            crate::proxy::SignatureCache::global().cache_tool_name(id, name);
 
                             // Signature resolution logic 
                             // Priority: Client -> Context -> Session Cache -> Tool Cache -> Global Store (deprecated)
@@ -817,10 +857,11 @@ fn build_contents(
                         } => {
                             // Mark this tool ID as resolved in this turn
                             current_turn_tool_result_ids.insert(tool_use_id.clone());
-                            // 优先使用之前记录的 name，否则用 tool_use_id
+                            // 优先使用之前记录的 name，其次是 tool_use id 缓存，否则用 tool_use_id
                             let func_name = tool_id_to_name
                                 .get(tool_use_id)
                                 .cloned()
+                                .or_else(|| crate::proxy::SignatureCache::global().get_tool_name(tool_use_id))
                                 .unwrap_or_else(|| tool_use_id.clone());
 
                             // Smart Truncation: strict image removal
@@ -887,6 +928,15 @@ fn build_contents(
                             parts.push(part);
                         }
                         // ContentBlock::RedactedThinking handled above at line 583
+                        // [NEW] 代码执行历史还原为 executableCode / codeExecutionResult
+                        ContentBlock::ServerToolUse { name, input, .. }
+                            if name == super::code_execution::CODE_EXECUTION_TOOL_NAME =>
+                        {
+                            parts.push(super::code_execution::server_tool_use_to_part(input));
+                        }
+                        ContentBlock::CodeExecutionToolResult { content, .. } => {
+                            parts.push(super::code_execution::tool_result_to_part(content));
+                        }
                         ContentBlock::ServerToolUse { .. } | ContentBlock::WebSearchToolResult { .. } => {
                             // 搜索结果 block 不应由客户端发回给上游 (已由 tool_result 替代)
                             continue;
@@ -896,8 +946,8 @@ fn build_contents(
             }
         }
         
-        // If this is a User message, check if we need to inject missing tool results
-        if role == "user" && !pending_tool_use_ids.is_empty() {
+        // If this is the last User message of the turn, check if we need to inject missing tool results
+        if role == "user" && ends_turn && !pending_tool_use_ids.is_empty() {
              let missing_ids: Vec<_> = pending_tool_use_ids.iter()
                  .filter(|id| !current_turn_tool_result_ids.contains(*id))
                  .cloned()
@@ -906,7 +956,9 @@ fn build_contents(
              if !missing_ids.is_empty() {
                  tracing::warn!("[Elastic-Recovery] Injecting {} missing tool results into User message (IDs: {:?})", missing_ids.len(), missing_ids);
                  for id in missing_ids.iter().rev() { // Insert in reverse order to maintain order at index 0? No, just insert at 0.
-                     let name = tool_id_to_name.get(id).cloned().unwrap_or(id.clone());
+                     let name = tool_id_to_name.get(id).cloned()
+                         .or_else(|| crate::proxy::SignatureCache::global().get_tool_name(id))
+                         .unwrap_or(id.clone());
                      let synthetic_part = json!({
                          "functionResponse": {
                              "name": name,
@@ -927,7 +979,8 @@ fn build_contents(
         // Fix for "Thinking enabled, assistant message must start with thinking block" 400 error
         // [Optimization] Apply this to ALL assistant messages in history, not just the last one.
         // Vertex AI requires every assistant message to start with a thinking block when thinking is enabled.
-        if allow_dummy_thought && role == "model" && is_thinking_enabled {
+        // [FIX] 拆分的 assistant 消息只在 turn 的第一条上处理，避免合并后 turn 中间出现占位思维块
+        if allow_dummy_thought && role == "model" && is_thinking_enabled && !continues_turn {
             let has_thought_part = parts
                 .iter()
                 .any(|p| {
@@ -979,10 +1032,7 @@ fn build_contents(
             continue;
         }
 
-        contents.push(json!({
-            "role": role,
-            "parts": parts
-        }));
+        push_merged(&mut contents, role, parts);
     }
 
 
@@ -992,8 +1042,10 @@ fn build_contents(
     // Instead we rely on should_disable_thinking_due_to_history to prevent this state.
 
     // [FIX P3-3] Strict Role Alternation (Message Merging)
-    // Merge adjacent messages with the same role to satisfy Gemini's strict alternation rule
-    let mut merged_contents = merge_adjacent_roles(contents);
+    // Adjacent messages with the same role were merged by push_merged to satisfy Gemini's strict alternation rule
+    let mut merged_contents = contents;
+    // [FIX] 并行工具调用的结果按调用 id 分组排序，缺失的补占位结果
+    crate::proxy::mappers::function_responses::group_function_responses(&mut merged_contents);
 
     // [FIX P3-4] Deep "Un-thinking" Cleanup
     // If thinking is disabled (e.g. smart downgrade), recursively remove any stray 'thought'/'thoughtSignature'
@@ -1004,45 +1056,49 @@ fn build_contents(
         }
     }
 
-    Ok(json!(merged_contents))
+    Ok(Value::Array(merged_contents))
 }
 
-/// Merge adjacent messages with the same role
-fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
-    if contents.is_empty() {
-        return contents;
-    }
-
-    let mut merged = Vec::new();
-    let mut current_msg = contents.remove(0);
-
-    for msg in contents {
-        let current_role = current_msg["role"].as_str().unwrap_or_default();
-        let next_role = msg["role"].as_str().unwrap_or_default();
-
-        if current_role == next_role {
-            // Merge parts
-            if let Some(current_parts) = current_msg.get_mut("parts").and_then(|p| p.as_array_mut()) {
-                if let Some(next_parts) = msg.get("parts").and_then(|p| p.as_array()) {
-                    current_parts.extend(next_parts.clone());
-                }
-            }
-        } else {
-            merged.push(current_msg);
-            current_msg = msg;
-        }
+/// Append a turn, merging it into the previous turn when the role is the same
+///
+/// 按原始顺序拼接 parts，不做任何重排：拆分在多条消息中的 Text -> functionCall -> Text
+/// 合并后仍保持相同顺序 (Gemini 依赖该顺序关联 thoughtSignature 与 functionCall)
+fn push_merged(contents: &mut Vec<Value>, role: &str, parts: Vec<Value>) {
+    if let Some(last_parts) = contents
+        .last_mut()
+        .filter(|c| c["role"] == role)
+        .and_then(|c| c.get_mut("parts"))
+        .and_then(|p| p.as_array_mut())
+    {
+        last_parts.extend(parts);
+        return;
     }
-    merged.push(current_msg);
-    merged
+    let mut turn = serde_json::Map::with_capacity(2);
+    turn.insert("role".to_string(), Value::String(role.to_string()));
+    turn.insert("parts".to_string(), Value::Array(parts));
+    contents.push(Value::Object(turn));
 }
 
 /// 构建 Tools
-fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option<Value>, String> {
+fn build_tools(
+    tools: &Option<Vec<Tool>>,
+    has_web_search: bool,
+    mapped_model: &str,
+    messages: &[Message],
+) -> Result<Option<Value>, String> {
     if let Some(tools_list) = tools {
         let mut function_declarations: Vec<Value> = Vec::new();
         let mut has_google_search = has_web_search;
+        let mut has_code_execution = false;
 
         for tool in tools_list {
+            // [NEW] code_execution 服务端工具 -> Gemini codeExecution
+            if tool.is_code_execution() {
+                has_code_execution = true;
+                continue;
+            }
+
+
             // 1. Detect server tools / built-in tools like web_search
             if tool.is_web_search() {
                 has_google_search = true;
@@ -1068,7 +1124,9 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
                     "type": "object",
                     "properties": {}
                 }));
-                crate::proxy::common::json_schema::clean_json_schema(&mut input_schema);
+                timed("clean_json_schema", json_size(&input_schema), || {
+                    crate::proxy::common::json_schema::clean_json_schema(&mut input_schema)
+                });
 
                 function_declarations.push(json!({
                     "name": name,
@@ -1100,8 +1158,45 @@ fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option
             tool_obj.insert("googleSearch".to_string(), json!({}));
         }
 
-        if !tool_obj.is_empty() {
-            return Ok(Some(json!([tool_obj])));
+        // [NEW] codeExecution 与本地函数的混用按模型能力和对话进度决定，而不是直接丢弃
+        let mut tool_list: Vec<Value> = Vec::new();
+        if has_code_execution {
+            use super::code_execution::{resolve_mixed_tools, MixedToolStrategy};
+            let strategy = if function_declarations.is_empty() {
+                MixedToolStrategy::Combined
+            } else {
+                resolve_mixed_tools(mapped_model, messages)
+            };
+            tracing::debug!("[Claude-Request] code_execution tool strategy: {:?}", strategy);
+            match strategy {
+                MixedToolStrategy::Combined => {
+                    if !tool_obj.is_empty() {
+                        tool_list.push(Value::Object(tool_obj));
+                    }
+                    tool_list.push(json!({ "codeExecution": {} }));
+                }
+                MixedToolStrategy::FunctionsOnly => {
+                    tracing::info!(
+                        "[Claude-Request] Deferring codeExecution this turn: {} function declarations take priority on {}",
+                        function_declarations.len(),
+                        mapped_model
+                    );
+                    tool_list.push(Value::Object(tool_obj));
+                }
+                MixedToolStrategy::CodeExecutionOnly => {
+                    tracing::info!(
+                        "[Claude-Request] Continuing code execution: omitting {} function declarations this turn",
+                        function_declarations.len()
+                    );
+                    tool_list.push(json!({ "codeExecution": {} }));
+                }
+            }
+        } else if !tool_obj.is_empty() {
+            tool_list.push(Value::Object(tool_obj));
+        }
+
+        if !tool_list.is_empty() {
+            return Ok(Some(Value::Array(tool_list)));
         }
     }
 
@@ -1130,6 +1225,12 @@ fn build_generation_config(
                 if is_flash_model {
                     budget = budget.min(24576);
                 }
+                // 未启用 interleaved-thinking 时，Anthropic 要求 budget_tokens < max_tokens
+                if !claude_req.betas.has(super::betas::INTERLEAVED_THINKING) {
+                    if let Some(max_tokens) = claude_req.max_tokens.filter(|m| *m > 1) {
+                        budget = budget.min(max_tokens - 1);
+                    }
+                }
                 thinking_config["thinkingBudget"] = json!(budget);
             }
 
@@ -1166,6 +1267,33 @@ fn build_generation_config(
         }
     }
 
+    // [NEW] JSON mode (output_format) -> responseMimeType / responseSchema
+    // Gemini 不支持 JSON mime type 与函数调用同时使用，带工具时忽略并交给提示词约束
+    if let Some(output_format) = claude_req.output_format.as_ref().filter(|f| f.is_json()) {
+        let has_tools = has_web_search
+            || claude_req.tools.as_ref().is_some_and(|t| !t.is_empty());
+        if has_tools {
+            tracing::warn!(
+                "[Generation-Config] output_format '{}' ignored: JSON mode cannot be combined with tools",
+                output_format.type_
+            );
+        } else {
+            config["responseMimeType"] = json!("application/json");
+            if let Some(schema) = &output_format.schema {
+                let mut schema = schema.clone();
+                timed("clean_json_schema", json_size(&schema), || {
+                    crate::proxy::common::json_schema::clean_json_schema(&mut schema)
+                });
+                config["responseSchema"] = schema;
+            }
+            tracing::debug!(
+                "[Generation-Config] JSON mode enabled (type: {}, schema: {})",
+                output_format.type_,
+                output_format.schema.is_some()
+            );
+        }
+    }
+
     // web_search 强制 candidateCount=1
     /*if has_web_search {
         config["candidateCount"] = json!(1);
@@ -1252,6 +1380,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1349,6 +1479,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1392,6 +1524,7 @@ mod tests {
                         },
                         ContentBlock::Text {
                             text: "Here is my response".to_string(),
+                            citations: None,
                         },
                     ]),
                 },
@@ -1419,6 +1552,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1450,6 +1585,7 @@ mod tests {
                     content: MessageContent::Array(vec![
                         ContentBlock::Text {
                             text: "Checking...".to_string(),
+                            citations: None,
                         },
                         ContentBlock::ToolUse {
                             id: "tool_1".to_string(),
@@ -1494,6 +1630,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1529,6 +1667,7 @@ mod tests {
                     content: MessageContent::Array(vec![
                         ContentBlock::Text {
                             text: "Response".to_string(),
+                            citations: None,
                         },
                     ]),
                 },
@@ -1543,6 +1682,8 @@ mod tests {
             thinking: None, // 未启用 thinking
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1564,6 +1705,69 @@ mod tests {
         assert_eq!(parts[0]["text"], "Response");
     }
 
+    #[test]
+    fn test_dummy_thought_only_for_configured_models() {
+        // 历史 assistant 消息缺少思维块时，仅对配置的目标模型补占位思维块
+        let req = ClaudeRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            messages: vec![
+                Message {
+                    role: "user".to_string(),
+                    content: MessageContent::String("Hello".to_string()),
+                },
+                Message {
+                    role: "assistant".to_string(),
+                    content: MessageContent::Array(vec![ContentBlock::Text {
+                        text: "Response".to_string(),
+                        citations: None,
+                    }]),
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: MessageContent::String("Continue".to_string()),
+                },
+            ],
+            system: None,
+            tools: None,
+            stream: false,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            thinking: Some(ThinkingConfig {
+                type_: "enabled".to_string(),
+                budget_tokens: Some(1024),
+            }),
+            metadata: None,
+            output_config: None,
+            output_format: None,
+            betas: Default::default(),
+        };
+
+        let model_parts = |body: &Value| {
+            body["request"]["contents"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|c| c["role"] == "model")
+                .unwrap()["parts"]
+                .as_array()
+                .unwrap()
+                .clone()
+        };
+
+        let default_body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert!(model_parts(&default_body).iter().all(|p| p.get("thought").is_none()));
+
+        let other_model = transform_claude_request_in_with(&req, "test-project", &["gemini-*".to_string()], None).unwrap();
+        assert!(model_parts(&other_model).iter().all(|p| p.get("thought").is_none()));
+
+        let allowed = transform_claude_request_in_with(&req, "test-project", &["claude-*".to_string()], None).unwrap();
+        let parts = model_parts(&allowed);
+        assert_eq!(parts[0]["thought"], true);
+        assert_eq!(parts[1]["text"], "Response");
+    }
+
     #[test]
     fn test_thinking_block_empty_content_fix() {
         // [场景] 客户端发送了一个内容为空的 thinking 块
@@ -1579,7 +1783,7 @@ mod tests {
                             signature: Some("sig".to_string()),
                             cache_control: None,
                         },
-                        ContentBlock::Text { text: "Hi".to_string() }
+                        ContentBlock::Text { text: "Hi".to_string(), citations: None }
                     ]),
                 },
             ],
@@ -1596,6 +1800,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1610,9 +1816,9 @@ mod tests {
     }
 
     #[test]
-    fn test_redacted_thinking_degradation() {
+    fn test_redacted_thinking_not_sent_upstream() {
         // [场景] 客户端包含 RedactedThinking
-        // 期望: 降级为普通文本，不带 thought: true
+        // 期望: 不发送给上游 (避免泄露被屏蔽的内容)，其余内容保持不变
         let req = ClaudeRequest {
             model: "claude-sonnet-4-5".to_string(),
             messages: vec![
@@ -1622,7 +1828,7 @@ mod tests {
                         ContentBlock::RedactedThinking {
                             data: "some data".to_string(),
                         },
-                         ContentBlock::Text { text: "Hi".to_string() }
+                         ContentBlock::Text { text: "Hi".to_string(), citations: None }
                     ]),
                 },
             ],
@@ -1636,6 +1842,8 @@ mod tests {
             thinking: None,
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         let result = transform_claude_request_in(&req, "test-project");
@@ -1643,10 +1851,9 @@ mod tests {
         let body = result.unwrap();
         let parts = body["request"]["contents"][0]["parts"].as_array().unwrap();
 
-        // 验证 RedactedThinking -> Text
-        let text = parts[0]["text"].as_str().unwrap();
-        assert!(text.contains("[Redacted Thinking: some data]"));
-        assert!(parts[0].get("thought").is_none(), "Redacted thinking should NOT have thought: true");
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0]["text"], "Hi");
+        assert!(!body.to_string().contains("some data"), "Redacted data must not reach upstream");
     }
 
     // ==================================================================================
@@ -1660,13 +1867,13 @@ mod tests {
                 role: "assistant".to_string(),
                 content: MessageContent::Array(vec![
                     // Wrong order: Text before Thinking (simulates kilo compression)
-                    ContentBlock::Text { text: "Some regular text".to_string() },
+                    ContentBlock::Text { text: "Some regular text".to_string(), citations: None },
                     ContentBlock::Thinking { 
                         thinking: "My thinking process".to_string(),
                         signature: Some("valid_signature_1234567890_abcdefghij_klmnopqrstuvwxyz_test".to_string()),
                         cache_control: None,
                     },
-                    ContentBlock::Text { text: "More text".to_string() },
+                    ContentBlock::Text { text: "More text".to_string(), citations: None },
                 ]),
             }
         ];
@@ -1702,7 +1909,7 @@ mod tests {
                         signature: Some("sig123".to_string()),
                         cache_control: None,
                     },
-                    ContentBlock::Text { text: "Some text".to_string() },
+                    ContentBlock::Text { text: "Some text".to_string(), citations: None },
                 ]),
             }
         ];
@@ -1716,5 +1923,328 @@ mod tests {
             assert!(matches!(blocks[1], ContentBlock::Text { .. }), "Text should still be second");
         }
     }
-}
 
+    #[test]
+    fn test_system_instruction_role_by_target_model() {
+        let request = |model: &str| -> ClaudeRequest {
+            serde_json::from_value(json!({
+                "model": model,
+                "system": "Be brief.",
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap()
+        };
+
+        let body = transform_claude_request_in(&request("gemini-2.5-flash"), "test-project").unwrap();
+        assert_eq!(body["request"]["systemInstruction"]["role"], "user");
+
+        let body = transform_claude_request_in(&request("gemini-3-pro-high"), "test-project").unwrap();
+        let sys = &body["request"]["systemInstruction"];
+        assert!(sys.get("role").is_none());
+        assert!(sys["parts"].to_string().contains("Be brief."));
+    }
+
+    #[test]
+    fn test_output_format_json_schema_mapping() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{"role": "user", "content": "Give me a city"}],
+            "output_format": {
+                "type": "json_schema",
+                "schema": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": { "city": { "type": "string" } },
+                    "required": ["city"]
+                }
+            }
+        }))
+        .unwrap();
+
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let gen = &body["request"]["generationConfig"];
+        assert_eq!(gen["responseMimeType"], "application/json");
+        assert_eq!(gen["responseSchema"]["properties"]["city"]["type"], "string");
+        // 已按 Gemini 要求清理
+        assert!(gen["responseSchema"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn test_output_format_plain_json_and_tools_conflict() {
+        // 仅约束 JSON，不带 schema
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "output_format": { "type": "json" }
+        }))
+        .unwrap();
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["generationConfig"]["responseMimeType"], "application/json");
+        assert!(body["request"]["generationConfig"].get("responseSchema").is_none());
+
+        // 带工具时忽略 JSON mode (Gemini 不支持二者同时使用)
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{
+                "name": "get_weather",
+                "description": "Get weather",
+                "input_schema": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }],
+            "output_format": { "type": "json" }
+        }))
+        .unwrap();
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert!(body["request"]["generationConfig"].get("responseMimeType").is_none());
+
+        // 未知类型 (如 text) 不生效
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "output_format": { "type": "text" }
+        }))
+        .unwrap();
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert!(body["request"]["generationConfig"].get("responseMimeType").is_none());
+    }
+
+    #[test]
+    fn test_code_execution_tool_and_history_mapping() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                {"role": "user", "content": "compute 6*7"},
+                {"role": "assistant", "content": [
+                    {"type": "server_tool_use", "id": "srvtoolu_1", "name": "code_execution", "input": {"code": "print(6*7)"}},
+                    {"type": "code_execution_tool_result", "tool_use_id": "srvtoolu_1", "content": {
+                        "type": "code_execution_result", "stdout": "42\n", "stderr": "", "return_code": 0, "content": []
+                    }},
+                    {"type": "text", "text": "42"}
+                ]},
+                {"role": "user", "content": "and 7*8?"}
+            ],
+            "tools": [{"type": "code_execution_20250522", "name": "code_execution"}]
+        }))
+        .unwrap();
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        assert_eq!(body["request"]["tools"], json!([{ "codeExecution": {} }]));
+
+        let parts = &body["request"]["contents"][1]["parts"];
+        assert_eq!(parts[0]["executableCode"]["code"], "print(6*7)");
+        assert_eq!(parts[1]["codeExecutionResult"]["outcome"], "OUTCOME_OK");
+        assert_eq!(parts[1]["codeExecutionResult"]["output"], "42\n");
+
+        // 与本地函数混用: 上一轮使用过代码执行，本轮继续下发 codeExecution
+        let mut mixed = req.clone();
+        mixed.tools.as_mut().unwrap().push(Tool {
+            type_: None,
+            name: Some("get_weather".to_string()),
+            description: Some("Get weather".to_string()),
+            input_schema: Some(json!({"type": "object"})),
+        });
+        let body = transform_claude_request_in(&mixed, "test-project").unwrap();
+        assert_eq!(body["request"]["tools"], json!([{ "codeExecution": {} }]));
+
+        // 新对话默认保留本地函数
+        mixed.messages = vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::String("hi".to_string()),
+        }];
+        let body = transform_claude_request_in(&mixed, "test-project").unwrap();
+        let tools = body["request"]["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert!(tools[0].get("functionDeclarations").is_some());
+    }
+
+    fn split_turn_messages() -> Vec<Message> {
+        serde_json::from_value(json!([
+            {"role": "user", "content": "list the files"},
+            {"role": "assistant", "content": [{"type": "text", "text": "Let me look."}]},
+            {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "list_files", "input": {}}]},
+            {"role": "assistant", "content": [{"type": "text", "text": "Running it now."}]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "a.txt"}]}
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_adjacent_roles_preserves_part_order() {
+        let mut merged = Vec::new();
+        push_merged(&mut merged, "model", vec![json!({"text": "A"})]);
+        push_merged(&mut merged, "model", vec![json!({"functionCall": {"name": "f", "args": {}}})]);
+        push_merged(&mut merged, "model", vec![json!({"text": "B"})]);
+        push_merged(&mut merged, "user", vec![json!({"functionResponse": {"name": "f", "response": {}}})]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(
+            merged[0]["parts"],
+            json!([{"text": "A"}, {"functionCall": {"name": "f", "args": {}}}, {"text": "B"}])
+        );
+    }
+
+    #[test]
+    fn test_split_assistant_turn_keeps_text_tool_text_order() {
+        let req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": split_turn_messages(),
+            "tools": [{"name": "list_files", "input_schema": {"type": "object"}}]
+        }))
+        .unwrap();
+        let body = transform_claude_request_in(&req, "test-project").unwrap();
+        let contents = body["request"]["contents"].as_array().unwrap();
+
+        // 拆分的 assistant 消息合并为一个 model turn，中间不插入合成的 functionResponse
+        assert_eq!(contents.len(), 3);
+        let model_parts = contents[1]["parts"].as_array().unwrap();
+        assert_eq!(model_parts.len(), 3);
+        assert_eq!(model_parts[0]["text"], "Let me look.");
+        assert_eq!(model_parts[1]["functionCall"]["name"], "list_files");
+        assert_eq!(model_parts[2]["text"], "Running it now.");
+
+        let user_parts = contents[2]["parts"].as_array().unwrap();
+        assert_eq!(user_parts.len(), 1);
+        assert_eq!(user_parts[0]["functionResponse"]["id"], "t1");
+    }
+
+    #[test]
+    fn test_split_assistant_turn_gets_single_leading_thought() {
+        let mut tool_id_to_name = HashMap::new();
+        let contents = build_contents(
+            &split_turn_messages(),
+            &mut tool_id_to_name,
+            true,
+            true,
+            "claude-sonnet-4-5",
+            "session-1",
+        )
+        .unwrap();
+        let model_parts = contents[1]["parts"].as_array().unwrap();
+        let thought_positions: Vec<usize> = model_parts
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p["thought"].as_bool().unwrap_or(false))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(thought_positions, vec![0]);
+        assert_eq!(model_parts[1]["text"], "Let me look.");
+        assert_eq!(model_parts[2]["functionCall"]["name"], "list_files");
+        assert_eq!(model_parts[3]["text"], "Running it now.");
+    }
+
+    #[test]
+    fn test_split_tool_results_are_not_duplicated() {
+        let messages: Vec<Message> = serde_json::from_value(json!([
+            {"role": "user", "content": "read both"},
+            {"role": "assistant", "content": [
+                {"type": "tool_use", "id": "a", "name": "read", "input": {}},
+                {"type": "tool_use", "id": "b", "name": "read", "input": {}}
+            ]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "a", "content": "A"}]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "b", "content": "B"}]}
+        ]))
+        .unwrap();
+        let mut tool_id_to_name = HashMap::new();
+        let contents = build_contents(&messages, &mut tool_id_to_name, false, false, "gemini-2.5-flash", "s").unwrap();
+
+        let user_parts = contents[2]["parts"].as_array().unwrap();
+        let ids: Vec<&str> = user_parts
+            .iter()
+            .filter_map(|p| p["functionResponse"]["id"].as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert!(!user_parts
+            .iter()
+            .any(|p| p["functionResponse"]["response"]["result"] == "Tool execution interrupted. No result provided."));
+    }
+
+    #[test]
+    fn test_truncated_history_recovers_tool_name_by_tool_id() {
+        let full: Vec<Message> = serde_json::from_value(json!([
+            {"role": "user", "content": "list it"},
+            {"role": "assistant", "content": [
+                {"type": "tool_use", "id": "toolu_trunc_1", "name": "list_files", "input": {}}
+            ]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_trunc_1", "content": "a.txt"}]}
+        ]))
+        .unwrap();
+        let mut tool_id_to_name = HashMap::new();
+        build_contents(&full, &mut tool_id_to_name, false, false, "gemini-2.5-flash", "sid-trunc").unwrap();
+
+        // 客户端截断历史: tool_use 已不在消息中
+        let truncated: Vec<Message> = serde_json::from_value(json!([
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_trunc_1", "content": "a.txt"}]}
+        ]))
+        .unwrap();
+        // 截断后会话指纹已变化，仍按 tool_use id 还原名称
+        let mut tool_id_to_name = HashMap::new();
+        let contents = build_contents(&truncated, &mut tool_id_to_name, false, false, "gemini-2.5-flash", "sid-other").unwrap();
+        assert_eq!(contents[0]["parts"][0]["functionResponse"]["name"], "list_files");
+
+        // 未知 id 回退为 id 本身
+        let unknown: Vec<Message> = serde_json::from_value(json!([
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_trunc_unknown", "content": "a.txt"}]}
+        ]))
+        .unwrap();
+        let mut tool_id_to_name = HashMap::new();
+        let contents = build_contents(&unknown, &mut tool_id_to_name, false, false, "gemini-2.5-flash", "sid-other").unwrap();
+        assert_eq!(contents[0]["parts"][0]["functionResponse"]["name"], "toolu_trunc_unknown");
+    }
+
+    #[test]
+    fn test_thinking_budget_respects_interleaved_thinking_beta() {
+        let mut req: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 8000,
+            "thinking": {"type": "enabled", "budget_tokens": 20000},
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let config = build_generation_config(&req, false, true);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 7999);
+
+        req.betas.accepted.push(super::super::betas::INTERLEAVED_THINKING.to_string());
+        let config = build_generation_config(&req, false, true);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 20000);
+    }
+
+    /// 长会话夹具 (约 target_bytes 字节)：user 文本 / assistant 工具调用 / user 工具结果交替
+    fn large_history_request(target_bytes: usize) -> ClaudeRequest {
+        let chunk = "lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40);
+        let mut messages = Vec::new();
+        let mut size = 0;
+        let mut i = 0;
+        while size < target_bytes {
+            let id = format!("toolu_{}", i);
+            messages.push(json!({"role": "user", "content": format!("step {}: {}", i, chunk)}));
+            messages.push(json!({"role": "assistant", "content": [
+                {"type": "text", "text": chunk.clone()},
+                {"type": "tool_use", "id": id, "name": "read_file", "input": {"path": format!("src/{}.rs", i)}}
+            ]}));
+            messages.push(json!({"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": id, "content": chunk.clone()}
+            ]}));
+            size += chunk.len() * 3;
+            i += 1;
+        }
+        messages.push(json!({"role": "user", "content": "summarize"}));
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": messages,
+            "tools": [{"name": "read_file", "input_schema": {"type": "object"}}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_large_history_transforms_with_strict_alternation() {
+        let req = large_history_request(64 * 1024);
+        let body = transform_claude_request_in(&req, "p").unwrap();
+        let contents = body["request"]["contents"].as_array().unwrap();
+        for pair in contents.windows(2) {
+            assert_ne!(pair[0]["role"], pair[1]["role"]);
+        }
+        // 每个 user turn (首个除外) = 工具结果 + 下一步文本
+        assert_eq!(contents[2]["parts"][0]["functionResponse"]["id"], "toolu_0");
+        assert!(contents[2]["parts"][1]["text"].as_str().unwrap().starts_with("step 1:"));
+    }
+}
@@ -1,8 +1,11 @@
 // Claude 非流式响应转换 (Gemini → Claude)
 // 对应 NonStreamingProcessor
 
+use super::code_execution;
 use super::models::*;
 use super::utils::to_claude_usage;
+use crate::proxy::common::output_locale::{grounding_markdown, GroundingSource, Locale};
+use crate::proxy::mappers::thinking_output::{Routed, ThinkingOutput, ThoughtRouter};
 
 /// Known parameter remappings for Gemini → Claude compatibility
 /// [FIX] Gemini sometimes uses different parameter names than specified in tool schema
@@ -106,6 +109,12 @@ pub struct NonStreamingProcessor {
     thinking_signature: Option<String>,
     trailing_signature: Option<String>,
     has_tool_call: bool,
+    /// 最近一次 executableCode 对应的 server_tool_use id
+    code_execution_id: Option<String>,
+    /// 注入的联网搜索文本所用语言
+    locale: Locale,
+    /// 思考内容的输出方式
+    thoughts: ThoughtRouter,
 }
 
 impl NonStreamingProcessor {
@@ -117,6 +126,9 @@ impl NonStreamingProcessor {
             thinking_signature: None,
             trailing_signature: None,
             has_tool_call: false,
+            code_execution_id: None,
+            locale: Locale::default(),
+            thoughts: ThoughtRouter::default(),
         }
     }
 
@@ -146,6 +158,7 @@ impl NonStreamingProcessor {
 
         // 刷新剩余内容
         self.flush_thinking();
+        self.close_inline_thinking();
         self.flush_text();
 
         // 处理 trailingSignature (空 text 带签名)
@@ -184,6 +197,7 @@ impl NonStreamingProcessor {
         // 1. FunctionCall 处理
         if let Some(fc) = &part.function_call {
             self.flush_thinking();
+            self.close_inline_thinking();
             self.flush_text();
 
             // 处理 trailingSignature (B4/C3 场景)
@@ -227,9 +241,14 @@ impl NonStreamingProcessor {
             return;
         }
 
-        // 2. Text 处理
-        if let Some(text) = &part.text {
-            if part.thought.unwrap_or(false) {
+        // 2. Text 处理 (思考内容按 thinking_output 路由；丢弃时签名仍以空 thinking 块保留)
+        let routed = part.text.as_ref().and_then(|text| match self.thoughts.route(text, part.thought.unwrap_or(false)) {
+            Routed::Thinking(thinking) => Some((true, thinking)),
+            Routed::Text(text) => Some((false, text)),
+            Routed::Dropped => signature.is_some().then(|| (true, String::new())),
+        });
+        if let Some((is_thinking, text)) = &routed {
+            if *is_thinking {
                 // Thinking part
                 self.flush_text();
 
@@ -283,9 +302,36 @@ impl NonStreamingProcessor {
             }
         }
 
-        // 3. InlineData (Image) 处理
+        // 3. [NEW] Code Execution 处理
+        if let Some(code) = &part.executable_code {
+            self.flush_thinking();
+            self.close_inline_thinking();
+            self.flush_text();
+            let id = code_execution::new_server_tool_id();
+            self.content_blocks.push(ContentBlock::ServerToolUse {
+                id: id.clone(),
+                name: code_execution::CODE_EXECUTION_TOOL_NAME.to_string(),
+                input: code_execution::executable_code_to_input(code),
+            });
+            self.code_execution_id = Some(id);
+        }
+        if let Some(result) = &part.code_execution_result {
+            self.flush_thinking();
+            self.flush_text();
+            let tool_use_id = self
+                .code_execution_id
+                .take()
+                .unwrap_or_else(code_execution::new_server_tool_id);
+            self.content_blocks.push(ContentBlock::CodeExecutionToolResult {
+                tool_use_id,
+                content: code_execution::execution_result_to_content(result),
+            });
+        }
+
+        // 4. InlineData (Image) 处理
         if let Some(img) = &part.inline_data {
             self.flush_thinking();
+            self.close_inline_thinking();
 
             let mime_type = &img.mime_type;
             let data = &img.data;
@@ -299,32 +345,18 @@ impl NonStreamingProcessor {
 
     /// 处理 Grounding 元数据 (Web Search 结果)
     fn process_grounding(&mut self, grounding: &GroundingMetadata) {
-        let mut grounding_text = String::new();
-
-        // 1. 处理搜索词
-        if let Some(queries) = &grounding.web_search_queries {
-            if !queries.is_empty() {
-                grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                grounding_text.push_str(&queries.join(", "));
-            }
-        }
-
-        // 2. 处理来源链接 (Chunks)
-        if let Some(chunks) = &grounding.grounding_chunks {
-            let mut links = Vec::new();
-            for (i, chunk) in chunks.iter().enumerate() {
-                if let Some(web) = &chunk.web {
-                    let title = web.title.as_deref().unwrap_or("网页来源");
-                    let uri = web.uri.as_deref().unwrap_or("#");
-                    links.push(format!("[{}] [{}]({})", i + 1, title, uri));
-                }
-            }
-
-            if !links.is_empty() {
-                grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                grounding_text.push_str(&links.join("\n"));
-            }
-        }
+        let queries: Vec<&str> = grounding.web_search_queries.iter().flatten().map(String::as_str).collect();
+        let sources: Vec<GroundingSource> = grounding
+            .grounding_chunks
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, chunk)| {
+                let web = chunk.web.as_ref()?;
+                Some(GroundingSource { index, title: web.title.as_deref(), uri: web.uri.as_deref() })
+            })
+            .collect();
+        let grounding_text = grounding_markdown(self.locale, &queries, &sources);
 
         if !grounding_text.is_empty() {
             // 在常规内容前后刷新并插入文本
@@ -343,11 +375,19 @@ impl NonStreamingProcessor {
 
         self.content_blocks.push(ContentBlock::Text {
             text: self.text_builder.clone(),
+            citations: None,
         });
         self.text_builder.clear();
     }
 
     /// 刷新 thinking builder
+    /// 闭合 Inline 模式下未闭合的 <think>
+    fn close_inline_thinking(&mut self) {
+        if let Some(tag) = self.thoughts.close() {
+            self.text_builder.push_str(tag);
+        }
+    }
+
     fn flush_thinking(&mut self) {
         // 如果既没有内容也没有签名，直接返回
         if self.thinking_builder.is_empty() && self.thinking_signature.is_none() {
@@ -373,13 +413,16 @@ impl NonStreamingProcessor {
             .and_then(|c| c.get(0))
             .and_then(|candidate| candidate.finish_reason.as_deref());
 
-        let stop_reason = if self.has_tool_call {
-            "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
-        } else {
-            "end_turn"
-        };
+        // [NEW] 安全拦截：stop_reason 为 refusal，并附带拦截原因与类别
+        let safety_block = serde_json::to_value(gemini_response)
+            .ok()
+            .and_then(|raw| crate::proxy::mappers::safety::detect_safety_block(&raw));
+
+        let stop_reason = crate::proxy::mappers::finish_reason::to_claude_stop_reason(
+            finish_reason,
+            self.has_tool_call,
+            safety_block.is_some(),
+        );
 
         let usage = gemini_response
             .usage_metadata
@@ -391,6 +434,7 @@ impl NonStreamingProcessor {
                 cache_read_input_tokens: None,
                 cache_creation_input_tokens: None,
                 server_tool_use: None,
+                thinking_tokens: None,
             });
 
         ClaudeResponse {
@@ -403,14 +447,23 @@ impl NonStreamingProcessor {
             content: self.content_blocks.clone(),
             stop_reason: stop_reason.to_string(),
             stop_sequence: None,
+            stop_details: safety_block.map(|b| b.to_json()),
             usage,
         }
     }
 }
 
 /// 转换 Gemini 响应为 Claude 响应 (公共接口)
-pub fn transform_response(gemini_response: &GeminiResponse) -> Result<ClaudeResponse, String> {
+/// `locale` 决定注入的联网搜索文本所用语言
+/// `thinking_output` 决定思考内容输出为 thinking 块、丢弃还是合并到正文
+pub fn transform_response(
+    gemini_response: &GeminiResponse,
+    locale: Locale,
+    thinking_output: ThinkingOutput,
+) -> Result<ClaudeResponse, String> {
     let mut processor = NonStreamingProcessor::new();
+    processor.locale = locale;
+    processor.thoughts = ThoughtRouter::new(thinking_output);
     Ok(processor.process(gemini_response))
 }
 
@@ -431,23 +484,29 @@ mod tests {
                         function_call: None,
                         function_response: None,
                         inline_data: None,
+                        executable_code: None,
+                        code_execution_result: None,
                     }],
                 }),
                 finish_reason: Some("STOP".to_string()),
                 index: Some(0),
                 grounding_metadata: None,
+                safety_ratings: None,
+                finish_message: None,
             }]),
             usage_metadata: Some(UsageMetadata {
                 prompt_token_count: Some(10),
                 candidates_token_count: Some(5),
                 total_token_count: Some(15),
                 cached_content_token_count: None,
+                thoughts_token_count: None,
             }),
             model_version: Some("gemini-2.5-pro".to_string()),
             response_id: Some("resp_123".to_string()),
+            prompt_feedback: None,
         };
 
-        let result = transform_response(&gemini_resp);
+        let result = transform_response(&gemini_resp, Locale::default(), ThinkingOutput::Reasoning);
         assert!(result.is_ok());
 
         let claude_resp = result.unwrap();
@@ -456,7 +515,7 @@ mod tests {
         assert_eq!(claude_resp.content.len(), 1);
 
         match &claude_resp.content[0] {
-            ContentBlock::Text { text } => {
+            ContentBlock::Text { text, .. } => {
                 assert_eq!(text, "Hello, world!");
             }
             _ => panic!("Expected Text block"),
@@ -477,6 +536,8 @@ mod tests {
                             function_call: None,
                             function_response: None,
                             inline_data: None,
+                            executable_code: None,
+                            code_execution_result: None,
                         },
                         GeminiPart {
                             text: Some("The answer is 42".to_string()),
@@ -485,19 +546,24 @@ mod tests {
                             function_call: None,
                             function_response: None,
                             inline_data: None,
+                            executable_code: None,
+                            code_execution_result: None,
                         },
                     ],
                 }),
                 finish_reason: Some("STOP".to_string()),
                 index: Some(0),
                 grounding_metadata: None,
+                safety_ratings: None,
+                finish_message: None,
             }]),
             usage_metadata: None,
             model_version: Some("gemini-2.5-pro".to_string()),
             response_id: Some("resp_456".to_string()),
+            prompt_feedback: None,
         };
 
-        let result = transform_response(&gemini_resp);
+        let result = transform_response(&gemini_resp, Locale::default(), ThinkingOutput::Reasoning);
         assert!(result.is_ok());
 
         let claude_resp = result.unwrap();
@@ -516,10 +582,46 @@ mod tests {
         }
 
         match &claude_resp.content[1] {
-            ContentBlock::Text { text } => {
+            ContentBlock::Text { text, .. } => {
                 assert_eq!(text, "The answer is 42");
             }
             _ => panic!("Expected Text block"),
         }
     }
+
+    #[test]
+    fn test_code_execution_parts_become_server_tool_blocks() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"executableCode": {"language": "PYTHON", "code": "print(6*7)"}},
+                    {"codeExecutionResult": {"outcome": "OUTCOME_OK", "output": "42\n"}},
+                    {"text": "The answer is 42"}
+                ]},
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+
+        let claude_resp = transform_response(&gemini_resp, Locale::default(), ThinkingOutput::Reasoning).unwrap();
+        assert_eq!(claude_resp.stop_reason, "end_turn");
+        assert_eq!(claude_resp.content.len(), 3);
+        let server_id = match &claude_resp.content[0] {
+            ContentBlock::ServerToolUse { id, name, input } => {
+                assert_eq!(name, "code_execution");
+                assert_eq!(input["code"], "print(6*7)");
+                assert!(id.starts_with("srvtoolu_"));
+                id.clone()
+            }
+            other => panic!("Expected ServerToolUse, got {:?}", other),
+        };
+        match &claude_resp.content[1] {
+            ContentBlock::CodeExecutionToolResult { tool_use_id, content } => {
+                assert_eq!(tool_use_id, &server_id);
+                assert_eq!(content["stdout"], "42\n");
+                assert_eq!(content["return_code"], 0);
+            }
+            other => panic!("Expected CodeExecutionToolResult, got {:?}", other),
+        }
+    }
 }
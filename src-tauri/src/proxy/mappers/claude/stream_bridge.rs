@@ -0,0 +1,163 @@
+// 流式 / 非流式桥接
+// 客户端可通过 X-Stream-Mode 请求头 (json / sse) 或 ?stream=true|false 查询参数覆盖请求体中的 stream 字段：
+// - 上游流式 + 客户端要 JSON：由 collector 聚合 (已有逻辑)
+// - 上游非流式 (不支持流式的模型) + 客户端要 SSE：由完整响应合成标准的 Messages SSE 事件序列
+
+use super::models::{ClaudeResponse, ContentBlock};
+use axum::http::HeaderMap;
+use serde_json::{json, Value};
+
+pub const HEADER_STREAM_MODE: &str = "x-stream-mode";
+
+/// 解析客户端的流式覆盖 (请求头优先于查询参数)
+pub fn stream_override(headers: &HeaderMap, query: Option<&str>) -> Option<bool> {
+    let from_header = headers
+        .get(HEADER_STREAM_MODE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| match v.trim().to_ascii_lowercase().as_str() {
+            "sse" | "stream" | "true" => Some(true),
+            "json" | "false" => Some(false),
+            _ => None,
+        });
+    from_header.or_else(|| {
+        query?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "stream")
+            .and_then(|(_, value)| match value {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None,
+            })
+    })
+}
+
+fn event(name: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+/// 内容块的 start 事件载荷与增量事件
+fn block_events(block: &ContentBlock) -> (Value, Vec<Value>) {
+    match block {
+        ContentBlock::Text { text, citations } => {
+            let mut deltas = vec![json!({ "type": "text_delta", "text": text })];
+            for citation in citations.iter().flatten() {
+                deltas.push(json!({ "type": "citations_delta", "citation": citation }));
+            }
+            (json!({ "type": "text", "text": "" }), deltas)
+        }
+        ContentBlock::Thinking { thinking, signature, .. } => {
+            let mut deltas = vec![json!({ "type": "thinking_delta", "thinking": thinking })];
+            if let Some(sig) = signature {
+                deltas.push(json!({ "type": "signature_delta", "signature": sig }));
+            }
+            (json!({ "type": "thinking", "thinking": "" }), deltas)
+        }
+        ContentBlock::ToolUse { id, name, input, .. } => (
+            json!({ "type": "tool_use", "id": id, "name": name, "input": {} }),
+            vec![json!({ "type": "input_json_delta", "partial_json": input.to_string() })],
+        ),
+        // 其他块 (图片 / 服务端工具结果等) 在 start 事件中完整下发
+        other => (serde_json::to_value(other).unwrap_or_else(|_| json!({})), vec![]),
+    }
+}
+
+/// 由完整的非流式响应合成 SSE 事件序列
+pub fn response_to_sse(response: &ClaudeResponse) -> String {
+    let mut out = String::new();
+    let mut start_usage = serde_json::to_value(&response.usage).unwrap_or_else(|_| json!({}));
+    start_usage["output_tokens"] = json!(0);
+    out.push_str(&event(
+        "message_start",
+        json!({
+            "type": "message_start",
+            "message": {
+                "id": response.id,
+                "type": "message",
+                "role": response.role,
+                "content": [],
+                "model": response.model,
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": start_usage
+            }
+        }),
+    ));
+
+    for (index, block) in response.content.iter().enumerate() {
+        let (start, deltas) = block_events(block);
+        out.push_str(&event(
+            "content_block_start",
+            json!({ "type": "content_block_start", "index": index, "content_block": start }),
+        ));
+        for delta in deltas {
+            out.push_str(&event(
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": index, "delta": delta }),
+            ));
+        }
+        out.push_str(&event("content_block_stop", json!({ "type": "content_block_stop", "index": index })));
+    }
+
+    let mut delta = json!({ "stop_reason": response.stop_reason, "stop_sequence": response.stop_sequence });
+    if let Some(details) = &response.stop_details {
+        delta["stop_details"] = details.clone();
+    }
+    out.push_str(&event(
+        "message_delta",
+        json!({
+            "type": "message_delta",
+            "delta": delta,
+            "usage": response.usage
+        }),
+    ));
+    out.push_str(&event("message_stop", json!({ "type": "message_stop" })));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::claude::collect_stream_to_json;
+    use axum::http::HeaderValue;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_stream_override_sources() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(stream_override(&headers, None), None);
+        assert_eq!(stream_override(&headers, Some("beta=true&stream=false")), Some(false));
+
+        headers.insert(HEADER_STREAM_MODE, HeaderValue::from_static("SSE"));
+        assert_eq!(stream_override(&headers, Some("stream=false")), Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_synthesized_stream_collects_back_to_same_response() {
+        let response: ClaudeResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "gemini-2.5-flash",
+            "content": [
+                { "type": "thinking", "thinking": "hmm", "signature": "sig" },
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 12, "output_tokens": 7 }
+        }))
+        .unwrap();
+
+        let sse = response_to_sse(&response);
+        let stream = futures::stream::iter(vec![Ok::<Bytes, std::io::Error>(Bytes::from(sse))]);
+        let collected = collect_stream_to_json(Box::pin(stream)).await.unwrap();
+
+        assert_eq!(collected.stop_reason, "tool_use");
+        assert_eq!(collected.usage.output_tokens, 7);
+        assert_eq!(
+            serde_json::to_value(&collected.content).unwrap(),
+            serde_json::to_value(&response.content).unwrap()
+        );
+    }
+}
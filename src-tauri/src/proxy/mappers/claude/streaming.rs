@@ -1,8 +1,11 @@
 // Claude 流式响应转换 (Gemini SSE → Claude SSE)
 // 对应 StreamingState + PartProcessor
 
+use super::code_execution;
 use super::models::*;
 use super::utils::to_claude_usage;
+use crate::proxy::common::output_locale::{grounding_markdown, GroundingSource, Locale};
+use crate::proxy::mappers::thinking_output::{Routed, ThoughtRouter};
 // use crate::proxy::mappers::signature_store::store_thought_signature; // Deprecated
 use crate::proxy::SignatureCache;
 use bytes::Bytes;
@@ -113,6 +116,7 @@ pub enum BlockType {
     Text,
     Thinking,
     Function,
+    Image,
 }
 
 /// 签名管理器
@@ -160,6 +164,22 @@ pub struct StreamingState {
     pub model_name: Option<String>,
     // [NEW v3.3.17] Session ID for session-based signature caching
     pub session_id: Option<String>,
+    // [NEW] 安全拦截详情 (在 message_delta 中以 stop_details 下发)
+    pub safety_block: Option<crate::proxy::mappers::safety::SafetyBlock>,
+    // [NEW] 最近一次 executableCode 对应的 server_tool_use id
+    pub code_execution_id: Option<String>,
+    // [NEW] 在 message_start 之后原样回传的 redacted_thinking 块
+    pub redacted_thinking: Vec<String>,
+    // [NEW] 启用引用的文档 (citations_delta)
+    pub citations: Option<super::citations::CitationTracker>,
+    // [NEW] 注入文本 (联网搜索引文 / 错误提示) 的语言
+    pub locale: Locale,
+    // [NEW] 长时间生成时定期下发阶段性用量 (未启用时为 None)
+    pub usage_heartbeat: Option<crate::proxy::common::usage_heartbeat::UsageHeartbeat>,
+    // [NEW] 按 (trace_id, 调用序号, 工具名) 分配 tool_use id，重试 / 续写的流保持一致
+    pub tool_ids: super::tool_ids::ToolIdAllocator,
+    // [NEW] 思考内容的输出方式 (thinking 块 / 丢弃 / 以 <think> 合并到正文)
+    pub thoughts: ThoughtRouter,
 }
 
 impl StreamingState {
@@ -179,6 +199,14 @@ impl StreamingState {
             last_valid_state: None,
             model_name: None,
             session_id: None,
+            safety_block: None,
+            code_execution_id: None,
+            redacted_thinking: Vec::new(),
+            citations: None,
+            locale: Locale::default(),
+            usage_heartbeat: None,
+            tool_ids: Default::default(),
+            thoughts: ThoughtRouter::default(),
         }
     }
 
@@ -222,9 +250,11 @@ impl StreamingState {
             self.model_name = Some(m.to_string());
         }
 
-        if let Some(u) = usage {
-            message["usage"] = json!(u);
-        }
+        // [FIX] usage 为 SDK 必填字段，首个 chunk 未携带 usageMetadata 时以 0 占位
+        message["usage"] = match usage {
+            Some(u) => json!(u),
+            None => json!({ "input_tokens": 0, "output_tokens": 0 }),
+        };
 
         let result = self.emit(
             "message_start",
@@ -235,7 +265,28 @@ impl StreamingState {
         );
 
         self.message_start_sent = true;
-        result
+
+        // [NEW] redacted_thinking 块紧跟 message_start 下发 (完整块，没有 delta)
+        if self.redacted_thinking.is_empty() {
+            return result;
+        }
+        let mut out = result.to_vec();
+        for data in std::mem::take(&mut self.redacted_thinking) {
+            out.extend_from_slice(&self.emit(
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": self.block_index,
+                    "content_block": { "type": "redacted_thinking", "data": data }
+                }),
+            ));
+            out.extend_from_slice(&self.emit(
+                "content_block_stop",
+                json!({ "type": "content_block_stop", "index": self.block_index }),
+            ));
+            self.block_index += 1;
+        }
+        Bytes::from(out)
     }
 
     /// 开始新的内容块
@@ -345,32 +396,22 @@ impl StreamingState {
 
         // 处理 grounding(web search) -> 转换为 Markdown 文本块
         if self.web_search_query.is_some() || self.grounding_chunks.is_some() {
-            let mut grounding_text = String::new();
-            
-            // 1. 处理搜索词
-            if let Some(query) = &self.web_search_query {
-                if !query.is_empty() {
-                    grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                    grounding_text.push_str(query);
-                }
-            }
-
-            // 2. 处理来源链接
-            if let Some(chunks) = &self.grounding_chunks {
-                let mut links = Vec::new();
-                for (i, chunk) in chunks.iter().enumerate() {
-                    if let Some(web) = chunk.get("web") {
-                        let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
-                        let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
-                        links.push(format!("[{}] [{}]({})", i + 1, title, uri));
-                    }
-                }
-                
-                if !links.is_empty() {
-                    grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                    grounding_text.push_str(&links.join("\n"));
-                }
-            }
+            let queries: Vec<&str> = self.web_search_query.as_deref().filter(|q| !q.is_empty()).into_iter().collect();
+            let sources: Vec<GroundingSource> = self
+                .grounding_chunks
+                .iter()
+                .flatten()
+                .enumerate()
+                .filter_map(|(index, chunk)| {
+                    let web = chunk.get("web")?;
+                    Some(GroundingSource {
+                        index,
+                        title: web.get("title").and_then(|v| v.as_str()),
+                        uri: web.get("uri").and_then(|v| v.as_str()),
+                    })
+                })
+                .collect();
+            let grounding_text = grounding_markdown(self.locale, &queries, &sources);
 
             if !grounding_text.is_empty() {
                 // 发送一个新的 text 块
@@ -385,14 +426,13 @@ impl StreamingState {
             }
         }
 
-        // 确定 stop_reason
-        let stop_reason = if self.used_tool {
-            "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
-        } else {
-            "end_turn"
-        };
+        // 确定 stop_reason (安全拦截优先)
+        let stop_details = self.safety_block.as_ref().map(|b| b.to_json());
+        let stop_reason = crate::proxy::mappers::finish_reason::to_claude_stop_reason(
+            finish_reason,
+            self.used_tool,
+            stop_details.is_some(),
+        );
 
         let usage = usage_metadata
             .map(|u| to_claude_usage(u))
@@ -402,13 +442,18 @@ impl StreamingState {
                 cache_read_input_tokens: None,
                 cache_creation_input_tokens: None,
                 server_tool_use: None,
+                thinking_tokens: None,
             });
 
+        let mut delta = json!({ "stop_reason": stop_reason, "stop_sequence": null });
+        if let Some(details) = stop_details {
+            delta["stop_details"] = details;
+        }
         chunks.push(self.emit(
             "message_delta",
             json!({
                 "type": "message_delta",
-                "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                "delta": delta,
                 "usage": usage
             }),
         ));
@@ -423,6 +468,39 @@ impl StreamingState {
         chunks
     }
 
+    /// 发送 ping 事件 (保活，防止中间代理因长时间无数据断开连接)
+    pub fn emit_ping(&self) -> Bytes {
+        self.emit("ping", json!({ "type": "ping" }))
+    }
+
+    /// 到达心跳间隔时发送阶段性用量 (stop_reason 为 null 的 message_delta)
+    pub fn emit_usage_heartbeat(&mut self) -> Option<Bytes> {
+        if !self.message_start_sent || self.message_stop_sent {
+            return None;
+        }
+        let progress = self.usage_heartbeat.as_mut()?.poll()?;
+        Some(self.emit(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": null, "stop_sequence": null },
+                "usage": { "output_tokens": progress.output_tokens }
+            }),
+        ))
+    }
+
+    /// 发送符合 Anthropic 规范的 error 事件，并以 message_stop 收尾
+    pub fn emit_error(&mut self, error_type: &str, message: &str) -> Vec<Bytes> {
+        let mut chunks = vec![build_error_event(error_type, message)];
+        if !self.message_stop_sent {
+            chunks.push(Bytes::from(
+                "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+            ));
+            self.message_stop_sent = true;
+        }
+        chunks
+    }
+
     /// 标记使用了工具
     pub fn mark_tool_used(&mut self) {
         self.used_tool = true;
@@ -480,12 +558,10 @@ impl StreamingState {
         // Debug 模式下输出详细错误信息
         #[cfg(debug_assertions)]
         {
-            let preview = if raw_data.len() > 100 {
-                format!("{}...", &raw_data[..100])
-            } else {
-                raw_data.to_string()
-            };
-            tracing::debug!("[SSE-Parser] Failed chunk preview: {}", preview);
+            tracing::debug!(
+                "[SSE-Parser] Failed chunk preview: {}",
+                crate::proxy::common::text_preview::preview(raw_data, 100)
+            );
         }
 
         // 错误率过高时发出警告并尝试发送错误信号
@@ -501,11 +577,11 @@ impl StreamingState {
                 "type": "error",
                 "error": {
                     "type": "network_error",
-                    "message": "网络连接不稳定,请检查您的网络或代理设置。",
+                    "message": self.locale.strings().stream_unstable,
                     "code": "stream_decode_error",
                     "details": {
                         "error_count": self.parse_error_count,
-                        "suggestion": "请尝试: 1) 检查网络连接 2) 更换代理节点 3) 稍后重试"
+                        "suggestion": self.locale.strings().stream_unstable_suggestion
                     }
                 }
             })));
@@ -528,6 +604,24 @@ impl StreamingState {
     }
 }
 
+/// 构建 Anthropic 规范的 SSE error 事件
+///
+/// `event: error` + `{"type":"error","error":{"type":...,"message":...}}`，
+/// 可被官方 SDK 正常解析 (不同于裸的 `data: {"error": ...}`)
+pub fn build_error_event(error_type: &str, message: &str) -> Bytes {
+    let data = json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "message": message
+        }
+    });
+    Bytes::from(format!(
+        "event: error\ndata: {}\n\n",
+        serde_json::to_string(&data).unwrap_or_default()
+    ))
+}
+
 /// Part 处理器
 pub struct PartProcessor<'a> {
     state: &'a mut StreamingState,
@@ -559,6 +653,11 @@ impl<'a> PartProcessor<'a> {
              }
         });
 
+        // 非文本内容之前闭合 Inline 模式的 <think>
+        if part.function_call.is_some() || part.executable_code.is_some() || part.inline_data.is_some() {
+            chunks.extend(self.close_inline_thinking());
+        }
+
         // 1. FunctionCall 处理
         if let Some(fc) = &part.function_call {
             // 先处理 trailingSignature (B4/C3 场景)
@@ -589,30 +688,43 @@ impl<'a> PartProcessor<'a> {
             return chunks;
         }
 
-        // 2. Text 处理
+        // 2. Text 处理 (思考内容按 thinking_output 路由)
         if let Some(text) = &part.text {
-            if part.thought.unwrap_or(false) {
-                // Thinking
-                chunks.extend(self.process_thinking(text, signature));
-            } else {
-                // 普通 Text
-                chunks.extend(self.process_text(text, signature));
+            match self.state.thoughts.route(text, part.thought.unwrap_or(false)) {
+                Routed::Thinking(thinking) => chunks.extend(self.process_thinking(&thinking, signature)),
+                Routed::Text(text) => chunks.extend(self.process_text(&text, signature)),
+                // 丢弃思考文本，但签名仍以空 thinking 块下发 (工具调用链路需要回传)
+                Routed::Dropped if signature.is_some() => chunks.extend(self.process_thinking("", signature)),
+                Routed::Dropped => {}
             }
         }
 
-        // 3. InlineData (Image) 处理
+        // 3. [NEW] Code Execution 处理
+        if let Some(code) = &part.executable_code {
+            chunks.extend(self.process_executable_code(code));
+        }
+        if let Some(result) = &part.code_execution_result {
+            chunks.extend(self.process_code_execution_result(result));
+        }
+
+        // 4. InlineData (Image) 处理
         if let Some(img) = &part.inline_data {
-            let mime_type = &img.mime_type;
-            let data = &img.data;
-            if !data.is_empty() {
-                let markdown_img = format!("![image](data:{};base64,{})", mime_type, data);
-                chunks.extend(self.process_text(&markdown_img, None));
+            if !img.data.is_empty() {
+                chunks.extend(self.process_inline_image(&img.mime_type, &img.data));
             }
         }
 
         chunks
     }
 
+    /// 闭合 Inline 模式下未闭合的 <think> (非文本内容之前 / 流结束时)
+    pub fn close_inline_thinking(&mut self) -> Vec<Bytes> {
+        match self.state.thoughts.close() {
+            Some(tag) => self.process_text(tag, None),
+            None => Vec::new(),
+        }
+    }
+
     /// 处理 Thinking
     fn process_thinking(&mut self, text: &str, signature: Option<String>) -> Vec<Bytes> {
         let mut chunks = Vec::new();
@@ -766,6 +878,67 @@ impl<'a> PartProcessor<'a> {
         chunks
     }
 
+    /// executableCode -> server_tool_use (input 通过 input_json_delta 发送，与 tool_use 一致)
+    fn process_executable_code(&mut self, code: &ExecutableCode) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        let id = code_execution::new_server_tool_id();
+        chunks.extend(self.state.start_block(
+            BlockType::Function,
+            json!({
+                "type": "server_tool_use",
+                "id": id,
+                "name": code_execution::CODE_EXECUTION_TOOL_NAME,
+                "input": {}
+            }),
+        ));
+        let input = code_execution::executable_code_to_input(code);
+        chunks.push(self.state.emit_delta(
+            "input_json_delta",
+            json!({ "partial_json": serde_json::to_string(&input).unwrap_or_else(|_| "{}".to_string()) }),
+        ));
+        chunks.extend(self.state.end_block());
+        self.state.code_execution_id = Some(id);
+        chunks
+    }
+
+    /// [NEW] 图片输出作为独立的 image 内容块下发
+    /// 以前拼成 Markdown data URI 塞进 text_delta，单行可达数 MB，会卡死部分终端的渲染
+    fn process_inline_image(&mut self, mime_type: &str, data: &str) -> Vec<Bytes> {
+        let mut chunks = self.state.start_block(
+            BlockType::Image,
+            json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": mime_type,
+                    "data": data
+                }
+            }),
+        );
+        chunks.extend(self.state.end_block());
+        chunks
+    }
+
+    /// codeExecutionResult -> code_execution_tool_result (一次性下发完整内容)
+    fn process_code_execution_result(&mut self, result: &CodeExecutionResult) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        let tool_use_id = self
+            .state
+            .code_execution_id
+            .take()
+            .unwrap_or_else(code_execution::new_server_tool_id);
+        chunks.extend(self.state.start_block(
+            BlockType::Function,
+            json!({
+                "type": "code_execution_tool_result",
+                "tool_use_id": tool_use_id,
+                "content": code_execution::execution_result_to_content(result)
+            }),
+        ));
+        chunks.extend(self.state.end_block());
+        chunks
+    }
+
     /// Process FunctionCall and capture signature for global storage
     fn process_function_call(
         &mut self,
@@ -776,13 +949,9 @@ impl<'a> PartProcessor<'a> {
 
         self.state.mark_tool_used();
 
-        let tool_id = fc.id.clone().unwrap_or_else(|| {
-            format!(
-                "{}-{}",
-                fc.name,
-                crate::proxy::common::utils::generate_random_id()
-            )
-        });
+        // 上游自带 id 的调用同样占用序号，保证后续调用的 id 与位置对应
+        let allocated_id = self.state.tool_ids.allocate(&fc.name);
+        let tool_id = fc.id.clone().unwrap_or(allocated_id);
 
         // 1. 发送 content_block_start (input 为空对象)
         let mut tool_use = json!({
@@ -809,6 +978,8 @@ impl<'a> PartProcessor<'a> {
             );
         }
 
+        SignatureCache::global().cache_tool_name(&tool_id, &fc.name);
+
         chunks.extend(self.state.start_block(BlockType::Function, tool_use));
 
         // 2. 发送 input_json_delta (完整的参数 JSON 字符串)
@@ -858,6 +1029,35 @@ mod tests {
         assert!(s.contains("\"foo\":\"bar\""));
     }
 
+    #[test]
+    fn test_emit_error_followed_by_message_stop() {
+        let mut state = StreamingState::new();
+        let output: String = state
+            .emit_error("api_error", "upstream \"broke\"")
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .collect();
+
+        let error_pos = output.find("event: error").unwrap();
+        let stop_pos = output.find("event: message_stop").unwrap();
+        assert!(error_pos < stop_pos);
+
+        // data 行必须是合法 JSON (消息中的引号需转义)
+        let data_line = output.lines().find(|l| l.starts_with("data: ")).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&data_line[6..]).unwrap();
+        assert_eq!(v["type"], "error");
+        assert_eq!(v["error"]["type"], "api_error");
+        assert_eq!(v["error"]["message"], "upstream \"broke\"");
+
+        // 不重复发送 message_stop
+        let again: String = state
+            .emit_error("api_error", "x")
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .collect();
+        assert!(!again.contains("message_stop"));
+    }
+
     #[test]
     fn test_process_function_call_deltas() {
         let mut state = StreamingState::new();
@@ -874,6 +1074,8 @@ mod tests {
             text: None,
             function_call: Some(fc),
             inline_data: None,
+            executable_code: None,
+            code_execution_result: None,
             thought: None,
             thought_signature: None,
             function_response: None,
@@ -901,4 +1103,59 @@ mod tests {
         // 3. content_block_stop
         assert!(output.contains(r#""type":"content_block_stop""#));
     }
+
+    #[test]
+    fn test_inline_image_emitted_as_image_block() {
+        let mut state = StreamingState::new();
+        let mut processor = PartProcessor::new(&mut state);
+        let text_part = |text: &str| GeminiPart {
+            text: Some(text.to_string()),
+            function_call: None,
+            inline_data: None,
+            executable_code: None,
+            code_execution_result: None,
+            thought: None,
+            thought_signature: None,
+            function_response: None,
+        };
+        let image_part = GeminiPart {
+            text: None,
+            function_call: None,
+            inline_data: Some(InlineData {
+                mime_type: "image/png".to_string(),
+                data: "aGVsbG8=".to_string(),
+            }),
+            executable_code: None,
+            code_execution_result: None,
+            thought: None,
+            thought_signature: None,
+            function_response: None,
+        };
+
+        let mut chunks = processor.process(&text_part("Here it is:"));
+        chunks.extend(processor.process(&image_part));
+        chunks.extend(processor.process(&text_part("Done.")));
+        let events: Vec<serde_json::Value> = chunks
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .filter_map(|s| s.lines().find_map(|l| l.strip_prefix("data: ").map(str::to_string)))
+            .map(|d| serde_json::from_str(&d).unwrap())
+            .collect();
+
+        let image_start = events
+            .iter()
+            .find(|e| e["type"] == "content_block_start" && e["content_block"]["type"] == "image")
+            .unwrap();
+        assert_eq!(image_start["index"], 1);
+        assert_eq!(image_start["content_block"]["source"]["media_type"], "image/png");
+        assert_eq!(image_start["content_block"]["source"]["data"], "aGVsbG8=");
+        // 文本中不再出现 data URI
+        assert!(!events
+            .iter()
+            .any(|e| e["delta"]["text"].as_str().is_some_and(|t| t.contains("base64,"))));
+        // 图片之后的文本开启新的 text 块
+        assert!(events
+            .iter()
+            .any(|e| e["type"] == "content_block_start" && e["index"] == 2 && e["content_block"]["type"] == "text"));
+    }
 }
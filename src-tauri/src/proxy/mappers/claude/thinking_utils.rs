@@ -52,6 +52,45 @@ pub fn analyze_conversation_state(messages: &[Message]) -> ConversationState {
     state
 }
 
+/// [NEW] 当前助手回合中的 redacted_thinking 块 (按出现顺序去重)
+///
+/// redacted_thinking 不发送给上游 (Gemini 无法解析，降级为文本会泄露被屏蔽的内容)，
+/// 仅在本地保留：处于工具循环时 (最后一条消息为 tool_result)，响应开头原样回传这些块，
+/// 让只保留回合最终消息的客户端仍能得到完整的 transcript。
+pub fn pending_redacted_thinking(messages: &[Message]) -> Vec<String> {
+    if !analyze_conversation_state(messages).in_tool_loop {
+        return Vec::new();
+    }
+
+    let mut turn_start = messages.len();
+    for (i, msg) in messages.iter().enumerate().rev() {
+        let is_tool_result_only = match &msg.content {
+            MessageContent::Array(blocks) => {
+                !blocks.is_empty() && blocks.iter().all(|b| matches!(b, ContentBlock::ToolResult { .. }))
+            }
+            MessageContent::String(_) => false,
+        };
+        if msg.role == "user" && !is_tool_result_only {
+            break;
+        }
+        turn_start = i;
+    }
+
+    let mut redacted: Vec<String> = Vec::new();
+    for msg in messages[turn_start..].iter().filter(|m| m.role == "assistant") {
+        if let MessageContent::Array(blocks) = &msg.content {
+            for block in blocks {
+                if let ContentBlock::RedactedThinking { data } = block {
+                    if !redacted.contains(data) {
+                        redacted.push(data.clone());
+                    }
+                }
+            }
+        }
+    }
+    redacted
+}
+
 /// Recover from broken tool loops by injecting synthetic messages
 /// 
 /// When client strips valid thinking blocks (leaving only ToolUse), and we are in a tool loop,
@@ -94,13 +133,13 @@ pub fn close_tool_loop_for_thinking(messages: &mut Vec<Message>) {
         messages.push(Message {
             role: "assistant".to_string(),
             content: MessageContent::Array(vec![
-                ContentBlock::Text { text: "[System: Tool loop recovered. Previous tool execution accepted.]".to_string() }
+                ContentBlock::Text { text: "[System: Tool loop recovered. Previous tool execution accepted.]".to_string(), citations: None }
             ])
         });
         messages.push(Message {
             role: "user".to_string(),
             content: MessageContent::Array(vec![
-                ContentBlock::Text { text: "Please continue with the next step.".to_string() }
+                ContentBlock::Text { text: "Please continue with the next step.".to_string(), citations: None }
             ])
         });
     }
@@ -0,0 +1,128 @@
+// token-efficient-tools 工具声明去重
+// 客户端声明 token-efficient-tools beta 时，去掉同一请求内重复声明的相同工具 (名称与声明完全一致)，
+// 节省量通过 X-Compatibility-Report 响应头回显。
+// 范围说明：不按会话做跨轮精简。Gemini 上游不保存请求之间的状态，也没有可引用的工具缓存，
+// 每一轮都必须携带完整的工具说明；按会话去掉"未变化"的声明只会让模型丢失参数说明，
+// 因此这里既不维护会话级的工具指纹，也不改写任何保留下来的声明。
+
+use axum::http::{HeaderMap, HeaderValue};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+pub const HEADER_COMPATIBILITY_REPORT: &str = "x-compatibility-report";
+
+/// 一次去重的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolDedupeReport {
+    /// 同一请求内重复声明、已移除的工具
+    pub deduplicated: Vec<String>,
+    /// 工具声明减少的字节数
+    pub saved_bytes: usize,
+}
+
+impl ToolDedupeReport {
+    pub fn is_empty(&self) -> bool {
+        self.deduplicated.is_empty()
+    }
+
+    /// 按 ~4 字节/token 估算
+    pub fn saved_tokens(&self) -> usize {
+        self.saved_bytes.div_ceil(4)
+    }
+
+    /// 形如 `token-efficient-tools; deduplicated=1; saved_bytes=840; saved_tokens=210`
+    pub fn header_value(&self) -> String {
+        format!(
+            "token-efficient-tools; deduplicated={}; saved_bytes={}; saved_tokens={}",
+            self.deduplicated.len(),
+            self.saved_bytes,
+            self.saved_tokens()
+        )
+    }
+
+    pub fn apply_to_headers(&self, headers: &mut HeaderMap) {
+        if self.is_empty() {
+            return;
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.header_value()) {
+            headers.insert(HEADER_COMPATIBILITY_REPORT, value);
+        }
+    }
+}
+
+fn fingerprint(tool: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tool.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 去掉 Claude 请求体中重复声明的自定义工具 (服务端工具原样保留)
+pub fn dedupe_tools(body: &mut Value) -> ToolDedupeReport {
+    let mut report = ToolDedupeReport::default();
+    let Some(tools) = body.get_mut("tools").and_then(|t| t.as_array_mut()) else {
+        return report;
+    };
+    let before: usize = tools.iter().map(|t| t.to_string().len()).sum();
+
+    let mut seen = HashSet::new();
+    tools.retain(|tool| {
+        let Some(name) = tool.get("name").and_then(|n| n.as_str()) else {
+            return true;
+        };
+        if tool.get("type").and_then(|t| t.as_str()).is_some_and(|t| t != "custom") {
+            return true;
+        }
+        if !seen.insert((name.to_string(), fingerprint(tool))) {
+            report.deduplicated.push(name.to_string());
+            return false;
+        }
+        true
+    });
+
+    let after: usize = tools.iter().map(|t| t.to_string().len()).sum();
+    report.saved_bytes = before.saturating_sub(after);
+    if !report.is_empty() {
+        tracing::debug!("[Token-Efficient-Tools] {}", report.header_value());
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, extra: &str) -> Value {
+        json!({
+            "name": name,
+            "description": format!("Tool {}", name),
+            "input_schema": {
+                "type": "object",
+                "title": "Args",
+                "properties": {
+                    "path": { "type": "string", "description": format!("File path to read{}", extra), "examples": ["/tmp/a"] },
+                    "description": { "type": "string" }
+                },
+                "required": ["path"]
+            }
+        })
+    }
+
+    #[test]
+    fn test_dedupes_within_request_and_keeps_tool_docs() {
+        let mut first = json!({ "tools": [tool("read", ""), tool("write", ""), tool("read", ""), tool("read", " (v2)")] });
+        let report = dedupe_tools(&mut first);
+        assert_eq!(report.deduplicated, vec!["read"]);
+        assert!(report.saved_bytes > 0);
+        assert!(report.header_value().starts_with("token-efficient-tools; deduplicated=1"));
+        // 声明不同的同名工具不视为重复
+        assert_eq!(first["tools"].as_array().unwrap().len(), 3);
+
+        // 后续轮次仍发送完整声明 (上游不保存跨请求状态)
+        let mut second = json!({ "tools": [tool("read", ""), { "type": "web_search_20250305", "name": "web_search" }] });
+        assert!(dedupe_tools(&mut second).is_empty());
+        assert_eq!(second["tools"][0], tool("read", ""));
+    }
+}
@@ -0,0 +1,241 @@
+// 工具声明护栏 (Tool Guardrails)
+// Gemini 对超长的工具描述和过多的函数声明会直接返回 400。
+// 在转换前对原始请求体做预检：截断超长描述；可选地在超过数量上限时按“最近最少使用”丢弃工具，
+// 并通过 X-Tool-Guardrails 响应头告知客户端做了哪些调整。
+
+use crate::proxy::config::ToolGuardrailConfig;
+use axum::http::{HeaderMap, HeaderValue};
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub const HEADER_TOOL_GUARDRAILS: &str = "x-tool-guardrails";
+const TRUNCATION_MARKER: &str = " …[truncated]";
+
+/// 一次预检的调整结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolGuardrailReport {
+    /// 描述被截断的工具
+    pub trimmed: Vec<String>,
+    /// 被丢弃的工具
+    pub dropped: Vec<String>,
+}
+
+impl ToolGuardrailReport {
+    pub fn is_empty(&self) -> bool {
+        self.trimmed.is_empty() && self.dropped.is_empty()
+    }
+
+    /// 形如 `trimmed=a,b; dropped=c`
+    pub fn header_value(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.trimmed.is_empty() {
+            parts.push(format!("trimmed={}", self.trimmed.join(",")));
+        }
+        if !self.dropped.is_empty() {
+            parts.push(format!("dropped={}", self.dropped.join(",")));
+        }
+        parts.join("; ")
+    }
+
+    pub fn apply_to_headers(&self, headers: &mut HeaderMap) {
+        if self.is_empty() {
+            return;
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.header_value()) {
+            headers.insert(HEADER_TOOL_GUARDRAILS, value);
+        }
+    }
+}
+
+/// 服务端工具 (web_search 等) 不会生成函数声明，不参与计数与丢弃
+fn is_server_tool(tool: &Value) -> bool {
+    tool.get("type")
+        .and_then(|t| t.as_str())
+        .is_some_and(|t| t != "custom")
+}
+
+fn tool_name(tool: &Value) -> Option<&str> {
+    tool.get("name").and_then(|n| n.as_str())
+}
+
+/// 按字符截断，结果 (含截断标记) 不超过 max_chars
+fn truncate_description(description: &str, max_chars: usize) -> Option<String> {
+    if description.chars().count() <= max_chars {
+        return None;
+    }
+    let keep = max_chars.saturating_sub(TRUNCATION_MARKER.chars().count());
+    let mut truncated: String = description.chars().take(keep).collect();
+    truncated.push_str(TRUNCATION_MARKER);
+    Some(truncated)
+}
+
+/// 每个工具在历史中最后一次被调用的位置 (消息下标)
+fn last_used_positions(body: &Value) -> HashMap<String, usize> {
+    let mut positions = HashMap::new();
+    let Some(messages) = body.get("messages").and_then(|m| m.as_array()) else {
+        return positions;
+    };
+    for (index, message) in messages.iter().enumerate() {
+        let Some(blocks) = message.get("content").and_then(|c| c.as_array()) else {
+            continue;
+        };
+        for block in blocks {
+            if block.get("type").and_then(|t| t.as_str()) == Some("tool_use") {
+                if let Some(name) = tool_name(block) {
+                    positions.insert(name.to_string(), index);
+                }
+            }
+        }
+    }
+    positions
+}
+
+/// 对原始 Claude 请求体执行工具护栏
+pub fn apply_tool_guardrails(body: &mut Value, config: &ToolGuardrailConfig) -> ToolGuardrailReport {
+    let mut report = ToolGuardrailReport::default();
+    if !config.enabled {
+        return report;
+    }
+
+    let used = last_used_positions(body);
+    // tool_choice 显式指定的工具永远保留
+    let forced = body
+        .get("tool_choice")
+        .and_then(|c| c.get("name"))
+        .and_then(|n| n.as_str())
+        .map(str::to_string);
+
+    let Some(tools) = body.get_mut("tools").and_then(|t| t.as_array_mut()) else {
+        return report;
+    };
+
+    if config.max_description_chars > 0 {
+        for tool in tools.iter_mut().filter(|t| !is_server_tool(t)) {
+            let truncated = tool
+                .get("description")
+                .and_then(|d| d.as_str())
+                .and_then(|d| truncate_description(d, config.max_description_chars));
+            if let Some(truncated) = truncated {
+                report.trimmed.push(tool_name(tool).unwrap_or("unnamed").to_string());
+                tool["description"] = Value::String(truncated);
+            }
+        }
+    }
+
+    let function_count = tools.iter().filter(|t| !is_server_tool(t)).count();
+    if config.max_tools > 0 && function_count > config.max_tools {
+        // 排序优先级：强制工具 > 最近被调用的工具 > 从未调用的工具 (按声明顺序)
+        let mut ranked: Vec<(usize, Option<usize>, bool)> = tools
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !is_server_tool(t))
+            .map(|(i, t)| {
+                let name = tool_name(t);
+                let is_forced = name.is_some() && name.map(str::to_string) == forced;
+                (i, name.and_then(|n| used.get(n).copied()), is_forced)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+
+        let mut drop_indices: Vec<usize> = ranked[config.max_tools..].iter().map(|(i, _, _)| *i).collect();
+        drop_indices.sort_unstable();
+        for index in drop_indices.into_iter().rev() {
+            let tool = tools.remove(index);
+            report.dropped.push(tool_name(&tool).unwrap_or("unnamed").to_string());
+        }
+        report.dropped.reverse();
+    }
+
+    if !report.is_empty() {
+        tracing::warn!(
+            "[Tool-Guardrails] Trimmed {} description(s), dropped {} tool(s): {}",
+            report.trimmed.len(),
+            report.dropped.len(),
+            report.header_value()
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(max_description_chars: usize, max_tools: usize) -> ToolGuardrailConfig {
+        ToolGuardrailConfig { enabled: true, max_description_chars, max_tools }
+    }
+
+    #[test]
+    fn test_trims_long_descriptions_on_char_boundaries() {
+        let mut body = json!({
+            "tools": [
+                { "name": "short", "description": "ok", "input_schema": {} },
+                { "name": "long", "description": "描述".repeat(50), "input_schema": {} },
+                { "type": "web_search_20250305", "name": "web_search" }
+            ]
+        });
+        let report = apply_tool_guardrails(&mut body, &config(40, 0));
+
+        assert_eq!(report.trimmed, vec!["long"]);
+        assert!(report.dropped.is_empty());
+        let description = body["tools"][1]["description"].as_str().unwrap();
+        assert_eq!(description.chars().count(), 40);
+        assert!(description.ends_with("[truncated]"));
+        assert_eq!(body["tools"][0]["description"], "ok");
+        assert_eq!(report.header_value(), "trimmed=long");
+    }
+
+    #[test]
+    fn test_drops_least_recently_used_tools_past_limit() {
+        let mut body = json!({
+            "messages": [
+                { "role": "assistant", "content": [{ "type": "tool_use", "id": "t1", "name": "read", "input": {} }] },
+                { "role": "assistant", "content": [{ "type": "tool_use", "id": "t2", "name": "grep", "input": {} }] }
+            ],
+            "tool_choice": { "type": "tool", "name": "deploy" },
+            "tools": [
+                { "name": "read", "input_schema": {} },
+                { "name": "write", "input_schema": {} },
+                { "type": "web_search_20250305", "name": "web_search" },
+                { "name": "grep", "input_schema": {} },
+                { "name": "deploy", "input_schema": {} },
+                { "name": "lint", "input_schema": {} }
+            ]
+        });
+        let report = apply_tool_guardrails(&mut body, &config(0, 3));
+
+        // 保留：deploy (强制)、grep (最近使用)、read；丢弃从未使用的 write / lint
+        assert_eq!(report.dropped, vec!["write", "lint"]);
+        let names: Vec<&str> = body["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["read", "web_search", "grep", "deploy"]);
+
+        let mut headers = HeaderMap::new();
+        report.apply_to_headers(&mut headers);
+        assert_eq!(headers.get(HEADER_TOOL_GUARDRAILS).unwrap(), "dropped=write,lint");
+    }
+
+    #[test]
+    fn test_reports_trimmed_and_dropped_together_and_skips_when_disabled() {
+        let tools = json!([
+            { "name": "verbose", "description": "x".repeat(500), "input_schema": { "type": "object" } },
+            { "name": "unused", "description": "never called", "input_schema": { "type": "object" } }
+        ]);
+
+        let mut body = json!({ "tools": tools.clone() });
+        let report = apply_tool_guardrails(&mut body, &config(64, 1));
+        assert_eq!(report.header_value(), "trimmed=verbose; dropped=unused");
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(body["tools"][0]["description"].as_str().unwrap().chars().count(), 64);
+
+        // 默认关闭：声明原样保留
+        let mut body = json!({ "tools": tools.clone() });
+        assert!(apply_tool_guardrails(&mut body, &ToolGuardrailConfig::default()).is_empty());
+        assert_eq!(body["tools"], tools);
+    }
+}
@@ -0,0 +1,78 @@
+// 确定性 tool_use id 分配
+// Gemini 的 functionCall 通常不带 id，之前每次都随机生成：handler 换号重试或续写后的流重新生成的 id
+// 会与已下发给客户端的块不一致，同一调用在客户端看来变成了另一个调用。这里按 (请求种子, 调用序号, 工具名)
+// 派生 id，同一请求内重试 / 续写的流为相同位置的同名调用分配相同的 id。
+// 请求种子 = trace_id + 每个客户端请求生成一次的随机数：trace_id 可能来自客户端的 X-Request-Id / X-Trace-Id，
+// 在多轮对话中复用，只用 trace_id 会让不同轮次的调用得到相同的 id。
+
+use sha2::{Digest, Sha256};
+
+/// 单个客户端请求的 id 种子 (在重试循环之前生成一次，所有重试流共用)
+pub fn request_seed(trace_id: &str) -> String {
+    format!("{}-{}", trace_id, crate::proxy::common::utils::generate_random_id())
+}
+
+/// 由 (请求种子, 调用序号, 工具名) 派生 tool_use id (Anthropic 的 toolu_ 前缀)
+pub fn tool_use_id(seed: &str, index: usize, name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_bytes());
+    hasher.update([0]);
+    hasher.update(index.to_string().as_bytes());
+    hasher.update([0]);
+    hasher.update(name.as_bytes());
+    let digest = hasher.finalize();
+    let hex: String = digest.iter().take(12).map(|b| format!("{:02x}", b)).collect();
+    format!("toolu_{}", hex)
+}
+
+/// 单条流内按出现顺序为工具调用分配 id
+#[derive(Debug, Clone)]
+pub struct ToolIdAllocator {
+    seed: String,
+    next_index: usize,
+}
+
+impl ToolIdAllocator {
+    pub fn new(seed: impl Into<String>) -> Self {
+        Self { seed: seed.into(), next_index: 0 }
+    }
+
+    /// 为下一个工具调用分配 id (上游自带 id 的调用同样占用序号，保证序号与调用位置一一对应)
+    pub fn allocate(&mut self, name: &str) -> String {
+        let id = tool_use_id(&self.seed, self.next_index, name);
+        self.next_index += 1;
+        id
+    }
+}
+
+impl Default for ToolIdAllocator {
+    /// 未指定种子时使用随机种子，避免不同请求之间的 id 冲突
+    fn default() -> Self {
+        Self::new(crate::proxy::common::utils::generate_random_id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_stable_per_trace_and_position() {
+        let mut first = ToolIdAllocator::new("req-1");
+        let mut retried = ToolIdAllocator::new("req-1");
+        let a = (first.allocate("read_file"), first.allocate("read_file"));
+        let b = (retried.allocate("read_file"), retried.allocate("read_file"));
+        assert_eq!(a, b);
+        // 同名的第二次调用得到不同的 id
+        assert_ne!(a.0, a.1);
+        assert!(a.0.starts_with("toolu_"));
+
+        assert_ne!(tool_use_id("req-1", 0, "read_file"), tool_use_id("req-2", 0, "read_file"));
+        assert_ne!(tool_use_id("req-1", 0, "read_file"), tool_use_id("req-1", 0, "write_file"));
+
+        // 客户端复用同一 trace_id 的不同请求得到不同的种子
+        let (a, b) = (request_seed("trace-shared"), request_seed("trace-shared"));
+        assert!(a.starts_with("trace-shared-"));
+        assert_ne!(tool_use_id(&a, 0, "read_file"), tool_use_id(&b, 0, "read_file"));
+    }
+}
@@ -0,0 +1,87 @@
+// 重试间的请求转换缓存 (Transform Cache)
+// 账号轮换重试时 Claude 请求本身通常没有变化，只是换了账号 (project)。大历史会话下 transform_claude_request_in
+// 开销不小，轮换风暴时会被重复执行多次。这里按 "请求修订号 + 目标模型 + 是否启用 thinking" 缓存转换结果，
+// 命中时只替换信封中的 project；请求被修改 (上下文裁剪、移除 thinking 等) 时由调用方递增修订号使缓存失效。
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 转换结果的缓存键 (attempt-class)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransformKey {
+    /// 请求修订号 (请求被修改时递增)
+    pub revision: u32,
+    pub mapped_model: String,
+    pub thinking: bool,
+    /// 目标模型的探测结果 (不同账号的探测结果可能不同)
+    pub capabilities: Option<crate::proxy::model_capabilities::ModelCapabilities>,
+}
+
+/// 单个客户端请求生命周期内的转换缓存
+#[derive(Default)]
+pub struct TransformCache {
+    entries: HashMap<TransformKey, Value>,
+    hits: u32,
+}
+
+impl TransformCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 命中时返回已替换 project 的转换结果；未命中时执行 `transform` 并缓存
+    pub fn get_or_transform<E>(
+        &mut self,
+        key: TransformKey,
+        project_id: &str,
+        transform: impl FnOnce() -> Result<Value, E>,
+    ) -> Result<Value, E> {
+        if let Some(cached) = self.entries.get(&key) {
+            self.hits += 1;
+            let mut body = cached.clone();
+            body["project"] = Value::String(project_id.to_string());
+            return Ok(body);
+        }
+        let body = transform()?;
+        self.entries.insert(key, body.clone());
+        Ok(body)
+    }
+
+    /// 缓存命中次数
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(revision: u32, thinking: bool) -> TransformKey {
+        TransformKey { revision, mapped_model: "claude-sonnet-4-5".to_string(), thinking, capabilities: None }
+    }
+
+    #[test]
+    fn test_reuses_transform_until_request_changes() {
+        let mut cache = TransformCache::new();
+        let mut calls = 0;
+        let mut transform = |project: &str| {
+            calls += 1;
+            Ok::<_, String>(json!({ "project": project, "request": { "contents": [] } }))
+        };
+
+        let first = cache.get_or_transform(key(0, true), "p1", || transform("p1")).unwrap();
+        let second = cache.get_or_transform(key(0, true), "p2", || transform("p2")).unwrap();
+        assert_eq!(first["project"], "p1");
+        assert_eq!(second["project"], "p2");
+        assert_eq!(second["request"], first["request"]);
+        assert_eq!(cache.hits(), 1);
+
+        // thinking 关闭 / 请求修订后重新转换
+        cache.get_or_transform(key(0, false), "p3", || transform("p3")).unwrap();
+        cache.get_or_transform(key(1, true), "p4", || transform("p4")).unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(calls, 3);
+    }
+}
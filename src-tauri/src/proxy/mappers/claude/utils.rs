@@ -40,6 +40,7 @@ pub fn to_claude_usage(usage_metadata: &super::models::UsageMetadata) -> super::
         cache_read_input_tokens: reported_cache,
         cache_creation_input_tokens: Some(0),
         server_tool_use: None,
+        thinking_tokens: usage_metadata.thoughts_token_count,
     }
 }
 
@@ -62,10 +63,18 @@ mod tests {
             candidates_token_count: Some(50),
             total_token_count: Some(150),
             cached_content_token_count: None,
+            thoughts_token_count: None,
         };
 
         let claude_usage = to_claude_usage(&usage);
         assert_eq!(claude_usage.input_tokens, 100);
         assert_eq!(claude_usage.output_tokens, 50);
+        assert!(serde_json::to_value(&claude_usage).unwrap().get("thinking_tokens").is_none());
+
+        // 思考 token 单独上报，不混入 output_tokens
+        let usage = UsageMetadata { thoughts_token_count: Some(800), ..usage };
+        let claude_usage = to_claude_usage(&usage);
+        assert_eq!(claude_usage.output_tokens, 50);
+        assert_eq!(serde_json::to_value(&claude_usage).unwrap()["thinking_tokens"], 800);
     }
 }
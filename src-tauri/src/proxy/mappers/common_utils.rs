@@ -14,6 +14,17 @@ pub struct RequestConfig {
     pub final_model: String,
     /// Image generation configuration (if request_type is image_gen)
     pub image_config: Option<Value>,
+    /// 图文混合输出时写入 generationConfig.responseModalities (见 MIXED_OUTPUT_SUFFIX)
+    pub response_modalities: Option<Vec<String>>,
+}
+
+/// 请求图文混合输出的模型后缀 (例如 gemini-3-pro-image-text-image、gemini-2.5-flash-image-text-image)
+/// 与纯图片模式不同，混合输出保留 tools / systemInstruction，响应中文字与图片交替出现
+pub const MIXED_OUTPUT_SUFFIX: &str = "-text-image";
+
+/// 图文混合输出的 responseModalities
+pub fn mixed_output_modalities() -> Vec<String> {
+    vec!["TEXT".to_string(), "IMAGE".to_string()]
 }
 
 pub fn resolve_request_config(
@@ -21,6 +32,11 @@ pub fn resolve_request_config(
     mapped_model: &str,
     tools: &Option<Vec<Value>>
 ) -> RequestConfig {
+    // 0. 图文混合输出后缀 (上游模型名中去除)
+    let mixed_output = original_model.ends_with(MIXED_OUTPUT_SUFFIX) || mapped_model.ends_with(MIXED_OUTPUT_SUFFIX);
+    let mapped_model = mapped_model.trim_end_matches(MIXED_OUTPUT_SUFFIX);
+    let response_modalities = mixed_output.then(mixed_output_modalities);
+
     // 1. Image Generation Check (Priority)
     if mapped_model.starts_with("gemini-3-pro-image") {
         let (image_config, parsed_base_model) = parse_image_config(original_model);
@@ -30,6 +46,7 @@ pub fn resolve_request_config(
             inject_google_search: false,
             final_model: parsed_base_model, 
             image_config: Some(image_config),
+            response_modalities,
         };
     }
 
@@ -84,6 +101,52 @@ pub fn resolve_request_config(
         inject_google_search: enable_networking,
         final_model,
         image_config: None,
+        response_modalities,
+    }
+}
+
+/// 按请求配置写入输出模态与图片参数 (Claude / OpenAI / Gemini 三个协议共用)
+///
+/// - 图文混合输出：写入 responseModalities 与 imageConfig，保留 tools / systemInstruction，
+///   仅移除与图片输出冲突的 responseMimeType
+/// - 纯图片模型：移除不支持的 tools / systemInstruction / thinkingConfig 等字段后写入 imageConfig
+///
+/// 返回是否按纯图片模式处理
+pub fn apply_output_modalities(inner_request: &mut Value, config: &RequestConfig) -> bool {
+    let Some(obj) = inner_request.as_object_mut() else {
+        return false;
+    };
+    match (&config.response_modalities, &config.image_config) {
+        (Some(modalities), image_config) => {
+            let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
+            if let Some(gen_obj) = gen_config.as_object_mut() {
+                gen_obj.remove("responseMimeType");
+                gen_obj.insert("responseModalities".to_string(), json!(modalities));
+                if let Some(image_config) = image_config {
+                    gen_obj.insert("imageConfig".to_string(), image_config.clone());
+                }
+            }
+            false
+        }
+        (None, Some(image_config)) => {
+            // 1. Remove tools (image generation does not support tools)
+            obj.remove("tools");
+            obj.remove("toolConfig");
+
+            // 2. Remove systemInstruction (image generation does not support system prompts)
+            obj.remove("systemInstruction");
+
+            // 3. Clean generationConfig (remove thinkingConfig, responseMimeType, responseModalities etc.)
+            let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
+            if let Some(gen_obj) = gen_config.as_object_mut() {
+                gen_obj.remove("thinkingConfig");
+                gen_obj.remove("responseMimeType");
+                gen_obj.remove("responseModalities"); // Cherry Studio sends this, might conflict
+                gen_obj.insert("imageConfig".to_string(), image_config.clone());
+            }
+            true
+        }
+        (None, None) => false,
     }
 }
 
@@ -116,6 +179,58 @@ fn parse_image_config(model_name: &str) -> (Value, String) {
     (serde_json::Value::Object(config), "gemini-3-pro-image".to_string())
 }
 
+/// systemInstruction 的 role 格式
+///
+/// v1internal 历史上一直写入 `role: "user"`；公共 API 的 systemInstruction 不带 role，
+/// 部分新模型 (gemini-3 系列) 遇到 role 为 user 时会把系统提示当作用户消息处理。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemInstructionRole {
+    /// 写入 `role: "user"`
+    User,
+    /// 不写 role 字段 (公共 API 格式)
+    Omit,
+}
+
+impl SystemInstructionRole {
+    /// 按目标模型系列选择格式，可通过 GEMINI_SYSTEM_INSTRUCTION_ROLE 环境变量 (user / omit) 全局覆盖
+    pub fn for_model(model: &str) -> Self {
+        match std::env::var("GEMINI_SYSTEM_INSTRUCTION_ROLE").as_deref() {
+            Ok("user") | Ok("USER") => SystemInstructionRole::User,
+            Ok("omit") | Ok("OMIT") | Ok("none") | Ok("NONE") => SystemInstructionRole::Omit,
+            _ => Self::default_for_model(model),
+        }
+    }
+
+    /// 各模型系列的默认格式 (已针对 gemini-2.5 / gemini-3 验证)
+    pub fn default_for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        if model.starts_with("gemini-3") {
+            SystemInstructionRole::Omit
+        } else {
+            // gemini-2.5 及 Claude 等经由 v1internal 的模型保持原有格式
+            SystemInstructionRole::User
+        }
+    }
+}
+
+/// 按目标模型规范化请求中 systemInstruction 的 role 字段
+pub fn apply_system_instruction_role(inner_request: &mut Value, model: &str) {
+    let Some(sys) = inner_request
+        .get_mut("systemInstruction")
+        .and_then(|s| s.as_object_mut())
+    else {
+        return;
+    };
+    match SystemInstructionRole::for_model(model) {
+        SystemInstructionRole::User => {
+            sys.insert("role".to_string(), json!("user"));
+        }
+        SystemInstructionRole::Omit => {
+            sys.remove("role");
+        }
+    }
+}
+
 /// Inject current googleSearch tool and ensure no duplicate legacy search tools
 pub fn inject_google_search_tool(body: &mut Value) {
     if let Some(obj) = body.as_object_mut() {
@@ -267,6 +382,32 @@ pub fn contains_non_networking_tool(tools: &Option<Vec<Value>>) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_system_instruction_role_per_model_family() {
+        let request = || json!({ "systemInstruction": { "role": "user", "parts": [{ "text": "sys" }] } });
+
+        // gemini-2.5: 保持 role: user
+        let mut req = request();
+        apply_system_instruction_role(&mut req, "gemini-2.5-flash");
+        assert_eq!(req["systemInstruction"]["role"], "user");
+
+        // gemini-3: 公共 API 格式，不带 role
+        let mut req = request();
+        apply_system_instruction_role(&mut req, "gemini-3-pro-high");
+        assert!(req["systemInstruction"].get("role").is_none());
+        assert_eq!(req["systemInstruction"]["parts"][0]["text"], "sys");
+
+        // 缺失 role 时为 gemini-2.5 补全
+        let mut req = json!({ "systemInstruction": { "parts": [{ "text": "sys" }] } });
+        apply_system_instruction_role(&mut req, "gemini-2.5-pro");
+        assert_eq!(req["systemInstruction"]["role"], "user");
+
+        // 无 systemInstruction 时不做任何修改
+        let mut req = json!({ "contents": [] });
+        apply_system_instruction_role(&mut req, "gemini-3-flash");
+        assert!(req.get("systemInstruction").is_none());
+    }
+
     #[test]
     fn test_high_quality_model_auto_grounding() {
         // Auto-grounding is currently disabled by default due to conflict with image gen
@@ -307,6 +448,52 @@ mod tests {
         assert!(!config.inject_google_search);
     }
 
+    #[test]
+    fn test_mixed_output_suffix_keeps_tools_and_system() {
+        let config = resolve_request_config(
+            "gemini-3-pro-image-16x9-text-image",
+            "gemini-3-pro-image-16x9-text-image",
+            &None,
+        );
+        assert_eq!(config.request_type, "image_gen");
+        assert_eq!(config.final_model, "gemini-3-pro-image");
+        assert_eq!(config.response_modalities, Some(mixed_output_modalities()));
+
+        let mut req = json!({
+            "systemInstruction": { "parts": [{ "text": "draw diagrams" }] },
+            "tools": [{ "functionDeclarations": [] }],
+            "generationConfig": { "responseMimeType": "application/json" }
+        });
+        assert!(!apply_output_modalities(&mut req, &config));
+        assert!(req.get("tools").is_some());
+        assert!(req.get("systemInstruction").is_some());
+        assert_eq!(req["generationConfig"]["responseModalities"], json!(["TEXT", "IMAGE"]));
+        assert_eq!(req["generationConfig"]["imageConfig"]["aspectRatio"], "16:9");
+        assert!(req["generationConfig"].get("responseMimeType").is_none());
+
+        // 非图片模型同样可请求混合输出，上游模型名去除后缀
+        let config = resolve_request_config("gemini-2.5-flash-image-text-image", "gemini-2.5-flash-image-text-image", &None);
+        assert_eq!(config.request_type, "agent");
+        assert_eq!(config.final_model, "gemini-2.5-flash-image");
+        assert!(config.response_modalities.is_some());
+    }
+
+    #[test]
+    fn test_pure_image_mode_strips_unsupported_fields() {
+        let config = resolve_request_config("gemini-3-pro-image", "gemini-3-pro-image", &None);
+        assert!(config.response_modalities.is_none());
+        let mut req = json!({
+            "systemInstruction": { "parts": [{ "text": "x" }] },
+            "tools": [{ "functionDeclarations": [] }],
+            "generationConfig": { "responseModalities": ["TEXT", "IMAGE"], "thinkingConfig": {} }
+        });
+        assert!(apply_output_modalities(&mut req, &config));
+        assert!(req.get("tools").is_none());
+        assert!(req.get("systemInstruction").is_none());
+        assert!(req["generationConfig"].get("responseModalities").is_none());
+        assert_eq!(req["generationConfig"]["imageConfig"]["aspectRatio"], "1:1");
+    }
+
     #[test]
     fn test_image_2k_and_ultrawide_config() {
         // Test 2K
@@ -0,0 +1,84 @@
+// 结束原因映射 (Finish Reason Mapping)
+// Gemini 的 finishReason 远多于 STOP / MAX_TOKENS / SAFETY，这里统一映射为
+// Claude stop_reason 与 OpenAI finish_reason，避免把上游原始字符串直接透传给客户端。
+
+use crate::proxy::mappers::safety::is_safety_finish_reason;
+
+/// 模型尝试调用工具但调用格式无效 (没有产生可用的工具调用)
+const TOOL_CALL_FAILURE_REASONS: &[&str] = &["MALFORMED_FUNCTION_CALL", "UNEXPECTED_TOOL_CALL", "TOO_MANY_TOOL_CALLS"];
+
+pub fn is_tool_call_failure(reason: &str) -> bool {
+    TOOL_CALL_FAILURE_REASONS.contains(&reason)
+}
+
+/// 映射为 OpenAI finish_reason (stop / length / content_filter)
+pub fn to_openai_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        r if is_safety_finish_reason(r) => "content_filter",
+        r => {
+            if is_tool_call_failure(r) {
+                tracing::warn!("[Finish-Reason] Upstream ended with {} (no usable tool call)", r);
+            } else if r != "STOP" {
+                tracing::debug!("[Finish-Reason] Mapping {} to stop", r);
+            }
+            "stop"
+        }
+    }
+}
+
+/// 映射为 Claude stop_reason (安全拦截优先，其次工具调用)
+pub fn to_claude_stop_reason(reason: Option<&str>, used_tool: bool, safety_blocked: bool) -> &'static str {
+    if safety_blocked || reason.is_some_and(is_safety_finish_reason) {
+        return "refusal";
+    }
+    if used_tool {
+        return "tool_use";
+    }
+    match reason {
+        Some("MAX_TOKENS") => "max_tokens",
+        Some(r) if is_tool_call_failure(r) => {
+            tracing::warn!("[Finish-Reason] Upstream ended with {} (no usable tool call)", r);
+            "end_turn"
+        }
+        _ => "end_turn",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_finish_reasons() {
+        let cases = [
+            ("STOP", "stop"),
+            ("MAX_TOKENS", "length"),
+            ("SAFETY", "content_filter"),
+            ("RECITATION", "content_filter"),
+            ("BLOCKLIST", "content_filter"),
+            ("PROHIBITED_CONTENT", "content_filter"),
+            ("SPII", "content_filter"),
+            ("MALFORMED_FUNCTION_CALL", "stop"),
+            ("FINISH_REASON_UNSPECIFIED", "stop"),
+            ("OTHER", "stop"),
+        ];
+        for (gemini, openai) in cases {
+            assert_eq!(to_openai_finish_reason(gemini), openai, "{}", gemini);
+        }
+    }
+
+    #[test]
+    fn test_claude_stop_reasons() {
+        assert_eq!(to_claude_stop_reason(Some("STOP"), false, false), "end_turn");
+        assert_eq!(to_claude_stop_reason(None, false, false), "end_turn");
+        assert_eq!(to_claude_stop_reason(Some("MAX_TOKENS"), false, false), "max_tokens");
+        assert_eq!(to_claude_stop_reason(Some("STOP"), true, false), "tool_use");
+        // 非类别型拦截即使没有解析出详情也是 refusal
+        for reason in ["RECITATION", "BLOCKLIST", "PROHIBITED_CONTENT", "SAFETY"] {
+            assert_eq!(to_claude_stop_reason(Some(reason), true, false), "refusal", "{}", reason);
+        }
+        assert_eq!(to_claude_stop_reason(Some("STOP"), false, true), "refusal");
+        assert_eq!(to_claude_stop_reason(Some("MALFORMED_FUNCTION_CALL"), false, false), "end_turn");
+    }
+}
@@ -0,0 +1,187 @@
+// functionResponse 分组与排序 (Parallel Tool Result Grouping)
+// Gemini 要求并行工具调用的全部 functionResponse 位于紧随其后的同一个 user turn 中，且与 functionCall 一一对应。
+// 客户端常把工具结果拆成多条消息、打乱顺序或遗漏部分结果；相邻同角色合并只负责拼接，
+// 这里在合并之后按 id (缺少 id 时按名称) 将结果与前一个 model turn 的调用配对、按调用顺序排列，
+// 缺失的结果补占位响应，无法配对的孤立结果降级为文本，避免上游 400。
+
+use serde_json::{json, Value};
+
+/// 缺失工具结果的占位内容 (与 Claude 映射器的 Elastic-Recovery 保持一致)
+pub const MISSING_RESULT_PLACEHOLDER: &str = "Tool execution interrupted. No result provided.";
+
+struct Call {
+    id: Option<String>,
+    name: String,
+}
+
+fn function_calls(content: &Value) -> Vec<Call> {
+    content["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p.get("functionCall"))
+                .map(|fc| Call {
+                    id: fc["id"].as_str().map(str::to_string),
+                    name: fc["name"].as_str().unwrap_or_default().to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 为调用找到对应的结果下标：优先按 id，其次按名称 (仅匹配没有 id 的结果，或调用本身没有 id)
+fn find_response(call: &Call, responses: &[Value], used: &[bool]) -> Option<usize> {
+    let available = |i: &usize| !used[*i];
+    if let Some(id) = &call.id {
+        if let Some(i) = (0..responses.len())
+            .filter(available)
+            .find(|&i| responses[i]["functionResponse"]["id"].as_str() == Some(id))
+        {
+            return Some(i);
+        }
+    }
+    (0..responses.len()).filter(available).find(|&i| {
+        let resp = &responses[i]["functionResponse"];
+        resp["name"].as_str() == Some(call.name.as_str()) && (call.id.is_none() || resp["id"].is_null())
+    })
+}
+
+fn orphan_to_text(response: &Value) -> Value {
+    let resp = &response["functionResponse"];
+    let name = resp["name"].as_str().unwrap_or("unknown");
+    let result = match &resp["response"]["result"] {
+        Value::String(s) => s.clone(),
+        Value::Null => resp["response"].to_string(),
+        other => other.to_string(),
+    };
+    json!({ "text": format!("[Result of tool `{}`]\n{}", name, result) })
+}
+
+/// 重排 model turn 之后的 user turn：functionResponse 按调用顺序排在最前，其余 parts 保持原顺序
+fn regroup_turn(calls: &[Call], user_turn: &mut Value) {
+    let Some(parts) = user_turn["parts"].as_array_mut() else {
+        return;
+    };
+    let (responses, others): (Vec<Value>, Vec<Value>) =
+        std::mem::take(parts).into_iter().partition(|p| p.get("functionResponse").is_some());
+
+    let mut used = vec![false; responses.len()];
+    let mut grouped = Vec::with_capacity(calls.len() + others.len());
+    let mut missing = Vec::new();
+    for call in calls {
+        match find_response(call, &responses, &used) {
+            Some(i) => {
+                used[i] = true;
+                grouped.push(responses[i].clone());
+            }
+            None => {
+                missing.push(call.id.clone().unwrap_or_else(|| call.name.clone()));
+                let mut placeholder = json!({
+                    "functionResponse": {
+                        "name": call.name,
+                        "response": { "result": MISSING_RESULT_PLACEHOLDER }
+                    }
+                });
+                if let Some(id) = &call.id {
+                    placeholder["functionResponse"]["id"] = json!(id);
+                }
+                grouped.push(placeholder);
+            }
+        }
+    }
+    if !missing.is_empty() {
+        tracing::warn!("[Tool-Grouping] Inserted placeholder results for {} missing call(s): {:?}", missing.len(), missing);
+    }
+
+    let orphans: Vec<&Value> = responses.iter().zip(&used).filter(|(_, u)| !**u).map(|(r, _)| r).collect();
+    if !orphans.is_empty() {
+        tracing::warn!("[Tool-Grouping] {} tool result(s) do not match any preceding call, sending as text", orphans.len());
+    }
+    grouped.extend(others);
+    grouped.extend(orphans.into_iter().map(orphan_to_text));
+    *parts = grouped;
+}
+
+/// 对已合并 (严格交替) 的 contents 执行分组与排序
+pub fn group_function_responses(contents: &mut [Value]) {
+    for i in 0..contents.len() {
+        if contents[i]["role"] != "model" {
+            continue;
+        }
+        let calls = function_calls(&contents[i]);
+        match contents.get_mut(i + 1) {
+            Some(next) if next["role"] == "user" => regroup_turn(&calls, next),
+            _ => {}
+        }
+    }
+    // 第一个 turn 之前没有调用，其中的 functionResponse 均为孤立结果
+    if let Some(first) = contents.first_mut().filter(|c| c["role"] == "user") {
+        regroup_turn(&[], first);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, id: Option<&str>) -> Value {
+        let mut fc = json!({ "functionCall": { "name": name, "args": {} } });
+        if let Some(id) = id {
+            fc["functionCall"]["id"] = json!(id);
+        }
+        fc
+    }
+
+    fn response(name: &str, id: Option<&str>, result: &str) -> Value {
+        let mut fr = json!({ "functionResponse": { "name": name, "response": { "result": result } } });
+        if let Some(id) = id {
+            fr["functionResponse"]["id"] = json!(id);
+        }
+        fr
+    }
+
+    #[test]
+    fn test_responses_reordered_by_id_with_placeholders_and_orphans() {
+        let mut contents = vec![
+            json!({ "role": "user", "parts": [{ "text": "read three files" }] }),
+            json!({ "role": "model", "parts": [call("read", Some("a")), call("read", Some("b")), call("grep", Some("c"))] }),
+            json!({ "role": "user", "parts": [
+                { "text": "here you go" },
+                response("read", Some("b"), "B"),
+                response("shell", Some("zzz"), "stale"),
+                response("read", Some("a"), "A")
+            ] }),
+        ];
+        group_function_responses(&mut contents);
+
+        let parts = contents[2]["parts"].as_array().unwrap();
+        let ids: Vec<&str> = parts.iter().filter_map(|p| p["functionResponse"]["id"].as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+        assert_eq!(parts[2]["functionResponse"]["response"]["result"], MISSING_RESULT_PLACEHOLDER);
+        assert_eq!(parts[3]["text"], "here you go");
+        assert!(parts[4]["text"].as_str().unwrap().contains("stale"));
+        assert_eq!(parts.len(), 5);
+    }
+
+    #[test]
+    fn test_responses_without_ids_match_by_name_in_order() {
+        let mut contents = vec![
+            json!({ "role": "model", "parts": [call("read", None), call("grep", None), call("read", None)] }),
+            json!({ "role": "user", "parts": [response("grep", None, "G"), response("read", None, "R1"), response("read", None, "R2")] }),
+        ];
+        group_function_responses(&mut contents);
+        let results: Vec<&str> = contents[1]["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["functionResponse"]["response"]["result"].as_str().unwrap())
+            .collect();
+        assert_eq!(results, vec!["R1", "G", "R2"]);
+
+        // 没有调用的 turn 不受影响
+        let mut plain = vec![json!({ "role": "user", "parts": [{ "text": "hi" }] })];
+        group_function_responses(&mut plain);
+        assert_eq!(plain[0]["parts"], json!([{ "text": "hi" }]));
+    }
+}
@@ -2,6 +2,7 @@
 // 负责 v1internal 包装/解包
 
 pub mod models;
+pub mod validation;
 pub mod wrapper;
 
 // No public exports needed here if unused
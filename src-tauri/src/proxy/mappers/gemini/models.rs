@@ -1,16 +1,353 @@
 // Gemini v1internal 数据模型
+// 信封 (project / requestId / model ...) 与 generateContent 请求 / 响应的强类型表示。
+// 常用字段有明确类型以获得编译期检查；每一层都带 `extra` 兜底，
+// 未建模的字段 (cachedContent、videoMetadata 等) 在反序列化 / 序列化往返中原样保留。
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// v1internal 请求信封
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct V1InternalRequest {
+    /// 由发送方在取得账号后填充
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub project: String,
-    #[serde(rename = "requestId")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub request_id: String,
-    pub request: serde_json::Value,
+    pub request: GenerateContentRequest,
     pub model: String,
-    #[serde(rename = "userAgent")]
     pub user_agent: String,
-    #[serde(rename = "requestType")]
     pub request_type: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl V1InternalRequest {
+    pub fn new(model: &str, request_type: &str, request: GenerateContentRequest) -> Self {
+        Self {
+            request,
+            model: model.to_string(),
+            user_agent: "antigravity".to_string(),
+            request_type: request_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+/// generateContent 请求体
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    #[serde(default)]
+    pub contents: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl GenerateContentRequest {
+    /// 从原始 JSON 转换；结构不符合预期时整体保留在 `extra` 中，序列化结果与输入一致
+    /// 直接从借用的 JSON 反序列化，不预先复制整棵树 (请求体可能含大量 base64 附件)
+    pub fn from_value(value: Value) -> Self {
+        match Self::deserialize(&value) {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::debug!("[Gemini-Models] Keeping request untyped: {}", e);
+                match value {
+                    Value::Object(extra) => Self { extra, ..Default::default() },
+                    _ => Self::default(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Content {
+    pub fn user(parts: Vec<Part>) -> Self {
+        Self { role: Some("user".to_string()), parts, ..Default::default() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought_signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<Blob>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_response: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Part {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self { text: Some(text.into()), ..Default::default() }
+    }
+
+    pub fn inline_data(mime_type: &str, data: impl Into<String>) -> Self {
+        Self {
+            inline_data: Some(Blob { mime_type: Some(mime_type.to_string()), data: data.into(), ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_thought(&self) -> bool {
+        self.thought.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub data: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_config: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetySetting {
+    pub category: String,
+    pub threshold: String,
+}
+
+impl SafetySetting {
+    /// 关闭所有可配置类别的拦截
+    pub fn all_off() -> Vec<SafetySetting> {
+        [
+            "HARM_CATEGORY_HARASSMENT",
+            "HARM_CATEGORY_HATE_SPEECH",
+            "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+            "HARM_CATEGORY_DANGEROUS_CONTENT",
+            "HARM_CATEGORY_CIVIC_INTEGRITY",
+        ]
+        .iter()
+        .map(|category| SafetySetting { category: category.to_string(), threshold: "OFF".to_string() })
+        .collect()
+    }
+}
+
+/// generateContent 响应 (v1internal 响应中的 `response` 字段)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_feedback: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl GenerateContentResponse {
+    /// 解析 v1internal 响应 (带或不带 `response` 信封)
+    pub fn from_envelope(mut value: Value) -> Result<Self, serde_json::Error> {
+        if value.get("response").is_some_and(|r| r.is_object()) {
+            value = value["response"].take();
+        }
+        serde_json::from_value(value)
+    }
+
+    /// 首个候选的 parts
+    pub fn first_parts(&self) -> &[Part] {
+        self.candidates
+            .first()
+            .and_then(|c| c.content.as_ref())
+            .map(|c| c.parts.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn total_tokens(&self) -> Option<u64> {
+        self.usage_metadata.as_ref()?.total_token_count
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citation_metadata: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_token_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidates_token_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thoughts_token_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_content_token_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_token_count: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_round_trip_keeps_unknown_fields() {
+        let raw = json!({
+            "contents": [{
+                "role": "user",
+                "parts": [
+                    { "text": "describe", "videoMetadata": { "fps": 1 } },
+                    { "inlineData": { "mimeType": "image/png", "data": "AAAA" } }
+                ]
+            }],
+            "generationConfig": { "temperature": 0.5, "maxOutputTokens": 1024, "responseModalities": ["TEXT"] },
+            "cachedContent": "cachedContents/abc"
+        });
+        let request = GenerateContentRequest::from_value(raw.clone());
+        assert_eq!(request.contents[0].parts[1].inline_data.as_ref().unwrap().mime_type.as_deref(), Some("image/png"));
+        assert_eq!(request.generation_config.as_ref().unwrap().max_output_tokens, Some(1024));
+        assert_eq!(serde_json::to_value(&request).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_blob_keeps_unknown_fields_and_missing_mime_type() {
+        let raw = json!({
+            "contents": [{
+                "role": "user",
+                "parts": [
+                    { "inlineData": { "data": "AAAA", "displayName": "scan.pdf" } },
+                    { "fileData": { "mimeType": "video/mp4", "fileUri": "gs://bucket/clip.mp4" } }
+                ]
+            }]
+        });
+        let request = GenerateContentRequest::from_value(raw.clone());
+        let blob = request.contents[0].parts[0].inline_data.as_ref().unwrap();
+        assert_eq!(blob.mime_type, None);
+        assert_eq!(blob.extra.get("displayName"), Some(&json!("scan.pdf")));
+        // 缺失的 mimeType 不会被补成空字符串
+        assert_eq!(serde_json::to_value(&request).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_malformed_request_stays_untyped() {
+        let raw = json!({ "contents": "not-an-array", "foo": 1 });
+        let request = GenerateContentRequest::from_value(raw.clone());
+        assert!(request.contents.is_empty());
+        assert_eq!(serde_json::to_value(&request).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_envelope_serialization() {
+        let mut envelope = V1InternalRequest::new(
+            "gemini-3-pro-image",
+            "image_gen",
+            GenerateContentRequest {
+                contents: vec![Content::user(vec![Part::text("a cat")])],
+                safety_settings: Some(SafetySetting::all_off()),
+                ..Default::default()
+            },
+        );
+        let value = envelope.to_value();
+        assert!(value.get("project").is_none());
+        assert_eq!(value["userAgent"], "antigravity");
+        assert_eq!(value["request"]["contents"][0]["parts"][0]["text"], "a cat");
+        assert_eq!(value["request"]["safetySettings"][4]["threshold"], "OFF");
+
+        envelope.project = "p1".to_string();
+        assert_eq!(envelope.to_value()["project"], "p1");
+    }
+
+    #[test]
+    fn test_response_from_envelope() {
+        let wrapped = json!({
+            "response": {
+                "candidates": [{ "content": { "role": "model", "parts": [{ "text": "hi" }] }, "finishReason": "STOP" }],
+                "usageMetadata": { "totalTokenCount": 12 }
+            },
+            "traceId": "t"
+        });
+        let response = GenerateContentResponse::from_envelope(wrapped).unwrap();
+        assert_eq!(response.first_parts()[0].text.as_deref(), Some("hi"));
+        assert_eq!(response.candidates[0].finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(response.total_tokens(), Some(12));
+
+        let bare = GenerateContentResponse::from_envelope(json!({ "candidates": [] })).unwrap();
+        assert!(bare.first_parts().is_empty());
+    }
 }
@@ -0,0 +1,476 @@
+// Gemini 原生请求校验
+// 原生路由基本原样透传请求体，客户端 JSON 结构有误时只会拿到上游一个含糊的 400。
+// 这里在调用上游前对 contents / systemInstruction / generationConfig / tools / safetySettings
+// 做结构与类型检查，错误信息精确到字段路径和期望类型。未知字段一律放行，保持前向兼容。
+
+use serde_json::{json, Value};
+
+/// 单条校验错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// 字段路径，如 `contents[0].parts[1].text`
+    pub path: String,
+    pub description: String,
+}
+
+impl ValidationIssue {
+    fn new(path: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            description: description.into(),
+        }
+    }
+
+    fn type_mismatch(path: &str, expected: &str, found: &Value) -> Self {
+        Self::new(path, format!("expected {}, got {}", expected, json_type_name(found)))
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+/// Part 中携带数据的字段 (必须且只能出现一个)
+const PART_DATA_FIELDS: &[&str] = &[
+    "text",
+    "inlineData",
+    "fileData",
+    "functionCall",
+    "functionResponse",
+    "executableCode",
+    "codeExecutionResult",
+];
+
+const CONTENT_ROLES: &[&str] = &["user", "model"];
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// 请求校验器，收集全部错误而不是遇到第一个就返回
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn mismatch(&mut self, path: &str, expected: &str, found: &Value) {
+        self.issues.push(ValidationIssue::type_mismatch(path, expected, found));
+    }
+
+    fn object<'a>(&mut self, path: &str, value: &'a Value) -> Option<&'a serde_json::Map<String, Value>> {
+        let obj = value.as_object();
+        if obj.is_none() {
+            self.mismatch(path, "object", value);
+        }
+        obj
+    }
+
+    fn array<'a>(&mut self, path: &str, value: &'a Value) -> Option<&'a Vec<Value>> {
+        let arr = value.as_array();
+        if arr.is_none() {
+            self.mismatch(path, "array", value);
+        }
+        arr
+    }
+
+    /// 可选字段的类型检查 (字段不存在时跳过)
+    fn optional(&mut self, obj: &serde_json::Map<String, Value>, parent: &str, key: &str, expected: &str) {
+        let Some(value) = obj.get(key) else { return };
+        let ok = match expected {
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            _ => true,
+        };
+        if !ok {
+            self.mismatch(&join(parent, key), expected, value);
+        }
+    }
+
+    /// 必填字段的类型检查
+    fn required(&mut self, obj: &serde_json::Map<String, Value>, parent: &str, key: &str, expected: &str) {
+        if obj.contains_key(key) {
+            self.optional(obj, parent, key, expected);
+        } else {
+            self.issues.push(ValidationIssue::new(
+                join(parent, key),
+                format!("required field is missing (expected {})", expected),
+            ));
+        }
+    }
+
+    fn string_array(&mut self, path: &str, value: &Value) {
+        if let Some(items) = self.array(path, value) {
+            for (i, item) in items.iter().enumerate() {
+                if !item.is_string() {
+                    self.mismatch(&format!("{}[{}]", path, i), "string", item);
+                }
+            }
+        }
+    }
+
+    fn content(&mut self, path: &str, value: &Value, role_required: bool) {
+        let Some(obj) = self.object(path, value) else { return };
+
+        match obj.get("role") {
+            Some(Value::String(role)) if !CONTENT_ROLES.contains(&role.as_str()) => {
+                self.issues.push(ValidationIssue::new(
+                    join(path, "role"),
+                    format!("expected one of \"user\", \"model\", got \"{}\"", role),
+                ));
+            }
+            Some(Value::String(_)) => {}
+            Some(other) => self.mismatch(&join(path, "role"), "string", other),
+            None if role_required => {
+                // 多轮对话中缺少 role 时上游会按 user 处理，这里只对多轮场景提示
+                self.issues.push(ValidationIssue::new(
+                    join(path, "role"),
+                    "required field is missing in multi-turn contents (expected \"user\" or \"model\")",
+                ));
+            }
+            None => {}
+        }
+
+        let parts_path = join(path, "parts");
+        let Some(parts) = obj.get("parts") else {
+            self.issues.push(ValidationIssue::new(parts_path, "required field is missing (expected array)"));
+            return;
+        };
+        let Some(parts) = self.array(&parts_path, parts) else { return };
+        if parts.is_empty() {
+            self.issues.push(ValidationIssue::new(parts_path, "must contain at least one part"));
+            return;
+        }
+        for (i, part) in parts.iter().enumerate() {
+            self.part(&format!("{}[{}]", parts_path, i), part);
+        }
+    }
+
+    fn part(&mut self, path: &str, value: &Value) {
+        let Some(obj) = self.object(path, value) else { return };
+
+        let data_fields: Vec<&str> = PART_DATA_FIELDS
+            .iter()
+            .copied()
+            .filter(|k| obj.contains_key(*k))
+            .collect();
+        match data_fields.len() {
+            0 => self.issues.push(ValidationIssue::new(
+                path,
+                format!("part has no data, expected one of: {}", PART_DATA_FIELDS.join(", ")),
+            )),
+            1 => {}
+            _ => self.issues.push(ValidationIssue::new(
+                path,
+                format!("part must contain exactly one data field, got: {}", data_fields.join(", ")),
+            )),
+        }
+
+        self.optional(obj, path, "text", "string");
+        self.optional(obj, path, "thought", "boolean");
+        self.optional(obj, path, "thoughtSignature", "string");
+
+        if let Some(inline) = obj.get("inlineData") {
+            let inline_path = join(path, "inlineData");
+            if let Some(inline) = self.object(&inline_path, inline) {
+                self.required(inline, &inline_path, "mimeType", "string");
+                self.required(inline, &inline_path, "data", "string");
+            }
+        }
+        if let Some(file) = obj.get("fileData") {
+            let file_path = join(path, "fileData");
+            if let Some(file) = self.object(&file_path, file) {
+                self.required(file, &file_path, "fileUri", "string");
+                self.optional(file, &file_path, "mimeType", "string");
+            }
+        }
+        if let Some(call) = obj.get("functionCall") {
+            let call_path = join(path, "functionCall");
+            if let Some(call) = self.object(&call_path, call) {
+                self.required(call, &call_path, "name", "string");
+                self.optional(call, &call_path, "args", "object");
+                self.optional(call, &call_path, "id", "string");
+            }
+        }
+        if let Some(resp) = obj.get("functionResponse") {
+            let resp_path = join(path, "functionResponse");
+            if let Some(resp) = self.object(&resp_path, resp) {
+                self.required(resp, &resp_path, "name", "string");
+                self.required(resp, &resp_path, "response", "object");
+                self.optional(resp, &resp_path, "id", "string");
+            }
+        }
+        if let Some(code) = obj.get("executableCode") {
+            let code_path = join(path, "executableCode");
+            if let Some(code) = self.object(&code_path, code) {
+                self.required(code, &code_path, "code", "string");
+                self.optional(code, &code_path, "language", "string");
+            }
+        }
+        if let Some(result) = obj.get("codeExecutionResult") {
+            let result_path = join(path, "codeExecutionResult");
+            if let Some(result) = self.object(&result_path, result) {
+                self.optional(result, &result_path, "outcome", "string");
+                self.optional(result, &result_path, "output", "string");
+            }
+        }
+    }
+
+    fn generation_config(&mut self, path: &str, value: &Value) {
+        let Some(obj) = self.object(path, value) else { return };
+
+        for key in ["temperature", "topP", "presencePenalty", "frequencyPenalty"] {
+            self.optional(obj, path, key, "number");
+        }
+        for key in ["topK", "maxOutputTokens", "candidateCount", "seed"] {
+            self.optional(obj, path, key, "integer");
+        }
+        for key in ["responseMimeType", "mediaResolution"] {
+            self.optional(obj, path, key, "string");
+        }
+        for key in ["responseSchema", "responseJsonSchema", "imageConfig"] {
+            self.optional(obj, path, key, "object");
+        }
+        self.optional(obj, path, "responseLogprobs", "boolean");
+        self.optional(obj, path, "logprobs", "integer");
+
+        for key in ["stopSequences", "responseModalities"] {
+            if let Some(v) = obj.get(key) {
+                self.string_array(&join(path, key), v);
+            }
+        }
+
+        if let Some(t) = obj.get("temperature").and_then(|v| v.as_f64()) {
+            if !(0.0..=2.0).contains(&t) {
+                self.issues.push(ValidationIssue::new(
+                    join(path, "temperature"),
+                    format!("must be between 0 and 2, got {}", t),
+                ));
+            }
+        }
+        if let Some(p) = obj.get("topP").and_then(|v| v.as_f64()) {
+            if !(0.0..=1.0).contains(&p) {
+                self.issues.push(ValidationIssue::new(
+                    join(path, "topP"),
+                    format!("must be between 0 and 1, got {}", p),
+                ));
+            }
+        }
+
+        if let Some(thinking) = obj.get("thinkingConfig") {
+            let thinking_path = join(path, "thinkingConfig");
+            if let Some(thinking) = self.object(&thinking_path, thinking) {
+                self.optional(thinking, &thinking_path, "thinkingBudget", "integer");
+                self.optional(thinking, &thinking_path, "includeThoughts", "boolean");
+                self.optional(thinking, &thinking_path, "thinkingLevel", "string");
+            }
+        }
+    }
+
+    fn tools(&mut self, path: &str, value: &Value) {
+        let Some(tools) = self.array(path, value) else { return };
+        for (i, tool) in tools.iter().enumerate() {
+            let tool_path = format!("{}[{}]", path, i);
+            let Some(obj) = self.object(&tool_path, tool) else { continue };
+
+            for (key, v) in obj {
+                // googleSearch / codeExecution / urlContext 等内置工具均为对象
+                if key != "functionDeclarations" && !v.is_object() {
+                    self.mismatch(&join(&tool_path, key), "object", v);
+                }
+            }
+
+            let Some(decls) = obj.get("functionDeclarations") else { continue };
+            let decls_path = join(&tool_path, "functionDeclarations");
+            let Some(decls) = self.array(&decls_path, decls) else { continue };
+            for (j, decl) in decls.iter().enumerate() {
+                let decl_path = format!("{}[{}]", decls_path, j);
+                let Some(decl) = self.object(&decl_path, decl) else { continue };
+                self.required(decl, &decl_path, "name", "string");
+                self.optional(decl, &decl_path, "description", "string");
+                self.optional(decl, &decl_path, "parameters", "object");
+                self.optional(decl, &decl_path, "parametersJsonSchema", "object");
+                if let Some(name) = decl.get("name").and_then(|n| n.as_str()) {
+                    if name.is_empty() {
+                        self.issues.push(ValidationIssue::new(join(&decl_path, "name"), "must not be empty"));
+                    }
+                }
+            }
+        }
+    }
+
+    fn safety_settings(&mut self, path: &str, value: &Value) {
+        let Some(settings) = self.array(path, value) else { return };
+        for (i, setting) in settings.iter().enumerate() {
+            let setting_path = format!("{}[{}]", path, i);
+            if let Some(obj) = self.object(&setting_path, setting) {
+                self.required(obj, &setting_path, "category", "string");
+                self.required(obj, &setting_path, "threshold", "string");
+            }
+        }
+    }
+}
+
+/// 校验 Gemini generateContent 请求体，返回全部错误 (为空表示通过)
+pub fn validate_request(body: &Value) -> Vec<ValidationIssue> {
+    let mut v = Validator::default();
+    let Some(obj) = body.as_object() else {
+        v.mismatch("(request body)", "object", body);
+        return v.issues;
+    };
+
+    match obj.get("contents") {
+        None => v
+            .issues
+            .push(ValidationIssue::new("contents", "required field is missing (expected array)")),
+        Some(contents) => {
+            if let Some(items) = v.array("contents", contents) {
+                if items.is_empty() {
+                    v.issues.push(ValidationIssue::new("contents", "must contain at least one content"));
+                }
+                let multi_turn = items.len() > 1;
+                for (i, content) in items.iter().enumerate() {
+                    v.content(&format!("contents[{}]", i), content, multi_turn);
+                }
+            }
+        }
+    }
+
+    if let Some(system) = obj.get("systemInstruction") {
+        // systemInstruction 的 role 会被忽略，不做要求
+        v.content("systemInstruction", system, false);
+    }
+    if let Some(config) = obj.get("generationConfig") {
+        v.generation_config("generationConfig", config);
+    }
+    if let Some(tools) = obj.get("tools") {
+        v.tools("tools", tools);
+    }
+    if let Some(tool_config) = obj.get("toolConfig") {
+        v.object("toolConfig", tool_config);
+    }
+    if let Some(settings) = obj.get("safetySettings") {
+        v.safety_settings("safetySettings", settings);
+    }
+    v.optional(obj, "", "cachedContent", "string");
+
+    v.issues
+}
+
+/// Google API 风格的 400 错误体 (附带 google.rpc.BadRequest 字段明细)
+pub fn to_error_body(issues: &[ValidationIssue]) -> Value {
+    let mut message = match issues.first() {
+        Some(first) => format!("Invalid request: {}", first),
+        None => "Invalid request".to_string(),
+    };
+    if issues.len() > 1 {
+        message.push_str(&format!(" (and {} more)", issues.len() - 1));
+    }
+    let violations: Vec<Value> = issues
+        .iter()
+        .map(|i| json!({ "field": i.path, "description": i.description }))
+        .collect();
+    json!({
+        "error": {
+            "code": 400,
+            "message": message,
+            "status": "INVALID_ARGUMENT",
+            "details": [{
+                "@type": "type.googleapis.com/google.rpc.BadRequest",
+                "fieldViolations": violations
+            }]
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_request_passes() {
+        let body = json!({
+            "contents": [
+                { "role": "user", "parts": [{ "text": "hi" }, { "inlineData": { "mimeType": "image/png", "data": "aGk=" } }] },
+                { "role": "model", "parts": [{ "functionCall": { "name": "lookup", "args": { "q": "x" } } }] },
+                { "role": "user", "parts": [{ "functionResponse": { "name": "lookup", "response": { "result": 1 } } }] }
+            ],
+            "systemInstruction": { "parts": [{ "text": "be brief" }] },
+            "generationConfig": {
+                "temperature": 0.7,
+                "maxOutputTokens": 1024,
+                "stopSequences": ["END"],
+                "thinkingConfig": { "thinkingBudget": 1024, "includeThoughts": true },
+                "someFutureField": "ok"
+            },
+            "tools": [
+                { "functionDeclarations": [{ "name": "lookup", "parameters": { "type": "object" } }] },
+                { "googleSearch": {} }
+            ],
+            "safetySettings": [{ "category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE" }]
+        });
+        assert!(validate_request(&body).is_empty(), "{:?}", validate_request(&body));
+    }
+
+    #[test]
+    fn test_reports_path_and_expected_type() {
+        let body = json!({
+            "contents": [
+                { "role": "user", "parts": [{ "text": "hi" }, { "text": 42 }] },
+                { "role": "assistant", "parts": [] }
+            ],
+            "generationConfig": { "temperature": "0.5", "maxOutputTokens": 10.5, "topP": 1.5 },
+            "tools": [{ "functionDeclarations": [{ "description": "no name" }] }]
+        });
+        let issues = validate_request(&body);
+        let find = |path: &str| issues.iter().find(|i| i.path == path).map(|i| i.description.clone());
+
+        assert_eq!(find("contents[0].parts[1].text").unwrap(), "expected string, got integer");
+        assert!(find("contents[1].role").unwrap().contains("\"assistant\""));
+        assert_eq!(find("contents[1].parts").unwrap(), "must contain at least one part");
+        assert_eq!(find("generationConfig.temperature").unwrap(), "expected number, got string");
+        assert_eq!(find("generationConfig.maxOutputTokens").unwrap(), "expected integer, got number");
+        assert!(find("generationConfig.topP").unwrap().contains("between 0 and 1"));
+        assert!(find("tools[0].functionDeclarations[0].name").unwrap().contains("missing"));
+    }
+
+    #[test]
+    fn test_part_data_fields_and_error_body() {
+        let issues = validate_request(&json!({
+            "contents": [{ "parts": [{ "thought": true }, { "text": "a", "inlineData": { "mimeType": "image/png", "data": "" } }] }]
+        }));
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].description.starts_with("part has no data"));
+        assert!(issues[1].description.contains("text, inlineData"));
+
+        let body = to_error_body(&issues);
+        assert_eq!(body["error"]["status"], "INVALID_ARGUMENT");
+        assert!(body["error"]["message"].as_str().unwrap().ends_with("(and 1 more)"));
+        assert_eq!(body["error"]["details"][0]["fieldViolations"][1]["field"], "contents[0].parts[1]");
+
+        assert_eq!(validate_request(&json!({}))[0].path, "contents");
+        assert_eq!(validate_request(&json!([]))[0].description, "expected object, got array");
+    }
+}
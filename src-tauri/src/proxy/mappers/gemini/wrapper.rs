@@ -1,8 +1,15 @@
 // Gemini v1internal 包装/解包
+use super::models::{GenerateContentRequest, V1InternalRequest};
+use crate::proxy::model_capabilities::ModelCapabilities;
 use serde_json::{json, Value};
 
 /// 包装请求体为 v1internal 格式
 pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value {
+    wrap_request_with(body, project_id, mapped_model, None)
+}
+
+/// 同 `wrap_request`，`capabilities` 为目标模型的探测结果 (未探测时原样透传)
+pub fn wrap_request_with(body: &Value, project_id: &str, mapped_model: &str, capabilities: Option<&ModelCapabilities>) -> Value {
     // 优先使用传入的 mapped_model，其次尝试从 body 获取
     let original_model = body.get("model").and_then(|v| v.as_str()).unwrap_or(mapped_model);
     
@@ -67,25 +74,9 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
         crate::proxy::mappers::common_utils::inject_google_search_tool(&mut inner_request);
     }
 
-    // Inject imageConfig if present (for image generation models)
-    if let Some(image_config) = config.image_config {
-         if let Some(obj) = inner_request.as_object_mut() {
-             // 1. Remove tools (image generation does not support tools)
-             obj.remove("tools");
-             
-             // 2. Remove systemInstruction (image generation does not support system prompts)
-             obj.remove("systemInstruction");
-
-             // 3. Clean generationConfig (remove thinkingConfig, responseMimeType, responseModalities etc.)
-             let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
-             if let Some(gen_obj) = gen_config.as_object_mut() {
-                 gen_obj.remove("thinkingConfig");
-                 gen_obj.remove("responseMimeType"); 
-                 gen_obj.remove("responseModalities"); // Cherry Studio sends this, might conflict
-                 gen_obj.insert("imageConfig".to_string(), image_config);
-             }
-         }
-    } else {
+    // 图片模型：纯图片模式移除不支持的字段，图文混合输出写入 responseModalities
+    crate::proxy::mappers::common_utils::apply_output_modalities(&mut inner_request, &config);
+    if config.image_config.is_none() {
         // [NEW] 只在非图像生成模式下注入 Antigravity 身份 (原始简化版)
         let antigravity_identity = "You are Antigravity, a powerful agentic AI coding assistant designed by the Google Deepmind team working on Advanced Agentic Coding.\n\
         You are pair programming with a USER to solve their coding task. The task may require creating a new codebase, modifying or debugging an existing codebase, or simply answering a question.\n\
@@ -93,14 +84,8 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
         **Proactiveness**";
         
         // [HYBRID] 检查是否已有 systemInstruction
+        // (role 字段在下方按模型系列统一规范化)
         if let Some(system_instruction) = inner_request.get_mut("systemInstruction") {
-            // [NEW] 补全 role: user
-            if let Some(obj) = system_instruction.as_object_mut() {
-                if !obj.contains_key("role") {
-                     obj.insert("role".to_string(), json!("user"));
-                }
-            }
-
             if let Some(parts) = system_instruction.get_mut("parts") {
                 if let Some(parts_array) = parts.as_array_mut() {
                     // 检查第一个 part 是否已包含 Antigravity 身份
@@ -125,16 +110,22 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str) -> Value
         }
     }
 
-    let final_request = json!({
-        "project": project_id,
-        "requestId": format!("agent-{}", uuid::Uuid::new_v4()), // 修正为 agent- 前缀
-        "request": inner_request,
-        "model": config.final_model,
-        "userAgent": "antigravity",
-        "requestType": config.request_type
-    });
+    // 探测确认不支持 thinking / 工具 / systemInstruction 的目标模型 (联网请求会改用其他模型，探测结果不适用)
+    if let Some(caps) = capabilities.filter(|_| config.final_model == final_model_name) {
+        crate::proxy::model_capabilities::apply_to_request(&mut inner_request, caps);
+    }
 
-    final_request
+    // [FIX] systemInstruction 的 role 按目标模型系列规范化 (替代原先的无条件补全 role: user)
+    crate::proxy::mappers::common_utils::apply_system_instruction_role(&mut inner_request, &config.final_model);
+
+    let mut envelope = V1InternalRequest::new(
+        &config.final_model,
+        &config.request_type,
+        GenerateContentRequest::from_value(inner_request),
+    );
+    envelope.project = project_id.to_string();
+    envelope.request_id = format!("agent-{}", uuid::Uuid::new_v4()); // 修正为 agent- 前缀
+    envelope.to_value()
 }
 
 /// 解包响应（提取 response 字段）
@@ -195,6 +186,22 @@ mod tests {
         assert!(first_text.contains("You are Antigravity"));
     }
 
+    #[test]
+    fn test_gemini3_system_instruction_omits_role() {
+        let body = json!({
+            "model": "gemini-3-pro-high",
+            "systemInstruction": {
+                "role": "user",
+                "parts": [{"text": "User custom prompt"}]
+            }
+        });
+
+        let result = wrap_request(&body, "test-proj", "gemini-3-pro-high");
+        let sys = result.get("request").unwrap().get("systemInstruction").unwrap();
+        assert!(sys.get("role").is_none());
+        assert_eq!(sys["parts"].as_array().unwrap().len(), 2);
+    }
+
     #[test]
     fn test_user_instruction_preservation() {
         let body = json!({
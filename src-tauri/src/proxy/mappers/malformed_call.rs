@@ -0,0 +1,248 @@
+// MALFORMED_FUNCTION_CALL 处理
+// Gemini 生成的工具调用无法解析时会以 finishReason = MALFORMED_FUNCTION_CALL 结束，且没有任何可用内容，
+// 客户端只会收到一个空回复。这里在向客户端发送任何内容之前先窥探上游流：若在出现内容前就以该原因结束，
+// 由 handler 携带纠正提示 ("为工具 X 输出合法 JSON") 重试一次；仍然失败时返回结构化错误说明原因。
+// 已经输出内容之后才出现的 MALFORMED_FUNCTION_CALL 无法重试，按普通结束处理。
+// 同一窥探也用于在内容之前出现的 RECITATION (见 recitation 模块)。
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::pin::Pin;
+
+pub const FINISH_REASON: &str = "MALFORMED_FUNCTION_CALL";
+
+/// 上游返回的无效工具调用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedCall {
+    /// 从 finishMessage 中解析出的工具名
+    pub tool: Option<String>,
+    /// 上游原始 finishMessage
+    pub finish_message: Option<String>,
+}
+
+impl MalformedCall {
+    fn tool_label(&self) -> String {
+        self.tool.as_deref().map(|t| format!("`{}`", t)).unwrap_or_else(|| "a tool".to_string())
+    }
+
+    /// 重试时追加到 systemInstruction 的纠正提示
+    pub fn corrective_hint(&self) -> String {
+        format!(
+            "[System notice] Your previous response attempted to call {} but the call was malformed and could not be parsed. \
+             If you call a tool, emit exactly one well-formed function call whose arguments are a valid JSON object \
+             matching the tool's declared parameter schema. Do not wrap the call in code, print statements or markdown.",
+            self.tool_label()
+        )
+    }
+
+    /// 重试后仍失败时返回给客户端的说明
+    pub fn error_message(&self) -> String {
+        let mut message = format!(
+            "The upstream model produced a malformed function call for {} ({}) and a corrective retry did not fix it. \
+             Check that the tool's input schema is valid and not overly complex.",
+            self.tool_label(),
+            FINISH_REASON
+        );
+        if let Some(detail) = &self.finish_message {
+            message.push_str(&format!(" Upstream detail: {}", detail));
+        }
+        message
+    }
+
+    pub fn details(&self) -> Value {
+        json!({ "finish_reason": FINISH_REASON, "tool": self.tool, "finish_message": self.finish_message })
+    }
+}
+
+/// 从 finishMessage 中提取工具名
+/// 常见形态: `Malformed function call: print(default_api.read_file(path=...))` / `call:read_file{...}`
+pub fn extract_tool_name(finish_message: &str) -> Option<String> {
+    let rest = if let Some(i) = finish_message.find("default_api.") {
+        &finish_message[i + "default_api.".len()..]
+    } else {
+        let i = finish_message.rfind("call:")?;
+        finish_message[i + "call:".len()..].trim_start()
+    };
+    let name: String = rest
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+enum Peek {
+    /// 已出现可输出的内容 (文本 / 思考 / 工具调用 / 图片)
+    Content,
+    /// 在任何内容之前以其他原因结束
+    Finished,
+    Malformed(MalformedCall),
+    Recitation,
+}
+
+/// 在任何内容之前出现、可由 handler 重试的结束原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EarlyFinish {
+    Malformed(MalformedCall),
+    Recitation,
+}
+
+fn classify(event: &Value) -> Option<Peek> {
+    let response = event.get("response").unwrap_or(event);
+    let candidate = response.get("candidates")?.get(0)?;
+    let has_content = candidate["content"]["parts"].as_array().is_some_and(|parts| {
+        parts.iter().any(|p| {
+            p.get("functionCall").is_some()
+                || p.get("inlineData").is_some()
+                || p["text"].as_str().is_some_and(|t| !t.is_empty())
+        })
+    });
+    if has_content {
+        return Some(Peek::Content);
+    }
+    match candidate["finishReason"].as_str() {
+        Some(FINISH_REASON) => {
+            let finish_message = candidate["finishMessage"].as_str().map(str::to_string);
+            Some(Peek::Malformed(MalformedCall {
+                tool: finish_message.as_deref().and_then(extract_tool_name),
+                finish_message,
+            }))
+        }
+        Some(crate::proxy::mappers::recitation::FINISH_REASON) => Some(Peek::Recitation),
+        Some(_) => Some(Peek::Finished),
+        None => None,
+    }
+}
+
+/// 检测非流式响应 (有内容时返回 None)
+pub fn detect(response: &Value) -> Option<MalformedCall> {
+    match classify(response)? {
+        Peek::Malformed(call) => Some(call),
+        _ => None,
+    }
+}
+
+pub type PeekedStream<E> = Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>;
+
+/// 窥探上游 SSE 流直到出现内容或结束：
+/// 内容先于结束出现时原样返回完整流 (已读取的 chunk 会被重新放回)；在内容之前以 MALFORMED_FUNCTION_CALL 结束时返回 Err，
+/// `retry_recitation` 为 true 时在内容之前以 RECITATION 结束同样返回 Err (否则按普通结束原样返回)
+pub async fn peek_stream<S, E>(mut stream: S, retry_recitation: bool) -> Result<PeekedStream<E>, EarlyFinish>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+    E: Send + 'static,
+{
+    let mut buffered = Vec::new();
+    let mut pending = String::new();
+    'read: while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                buffered.push(Err(e));
+                break;
+            }
+        };
+        pending.push_str(&String::from_utf8_lossy(&chunk));
+        buffered.push(Ok(chunk));
+        while let Some(pos) = pending.find('\n') {
+            let line: String = pending.drain(..=pos).collect();
+            let Some(payload) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Value>(payload.trim()) else {
+                continue;
+            };
+            match classify(&event) {
+                Some(Peek::Malformed(call)) => return Err(EarlyFinish::Malformed(call)),
+                Some(Peek::Recitation) if retry_recitation => return Err(EarlyFinish::Recitation),
+                Some(Peek::Content | Peek::Finished | Peek::Recitation) => break 'read,
+                None => {}
+            }
+        }
+    }
+    Ok(Box::pin(futures::stream::iter(buffered).chain(stream)))
+}
+
+/// 将纠正提示追加到 v1internal 信封的 systemInstruction
+pub fn apply_hint(envelope: &mut Value, hint: &str) {
+    let Some(request) = envelope.get_mut("request").and_then(|r| r.as_object_mut()) else {
+        return;
+    };
+    let instruction = request
+        .entry("systemInstruction")
+        .or_insert_with(|| json!({ "role": "user", "parts": [] }));
+    if !instruction["parts"].is_array() {
+        instruction["parts"] = json!([]);
+    }
+    if let Some(parts) = instruction["parts"].as_array_mut() {
+        parts.push(json!({ "text": hint }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sse(events: &[Value]) -> Vec<Result<Bytes, String>> {
+        events
+            .iter()
+            .map(|e| Ok(Bytes::from(format!("data: {}\n\n", json!({ "response": e })))))
+            .collect()
+    }
+
+    fn malformed_event() -> Value {
+        json!({ "candidates": [{
+            "content": { "role": "model", "parts": [] },
+            "finishReason": "MALFORMED_FUNCTION_CALL",
+            "finishMessage": "Malformed function call: print(default_api.get_weather(city=Paris))"
+        }] })
+    }
+
+    #[test]
+    fn test_extract_tool_name() {
+        assert_eq!(extract_tool_name("Malformed function call: print(default_api.read_file(path='a'))").as_deref(), Some("read_file"));
+        assert_eq!(extract_tool_name("Malformed function call: call:mcp__fs__write{\"path\":1}").as_deref(), Some("mcp__fs__write"));
+        assert_eq!(extract_tool_name("Malformed function call"), None);
+    }
+
+    #[tokio::test]
+    async fn test_peek_detects_malformed_before_content() {
+        let stream = futures::stream::iter(sse(&[malformed_event()]));
+        let Err(EarlyFinish::Malformed(call)) = peek_stream(stream, false).await else {
+            panic!("expected malformed call");
+        };
+        assert_eq!(call.tool.as_deref(), Some("get_weather"));
+        assert!(call.corrective_hint().contains("`get_weather`"));
+    }
+
+    #[tokio::test]
+    async fn test_peek_passes_through_content_unchanged() {
+        let text = json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "hi" }] } }] });
+        let events = sse(&[text, malformed_event()]);
+        let expected: Vec<Bytes> = events.iter().map(|e| e.clone().unwrap()).collect();
+        let peeked = peek_stream(futures::stream::iter(events), true).await.expect("content first");
+        let chunks: Vec<Bytes> = peeked.map(|c| c.unwrap()).collect().await;
+        assert_eq!(chunks, expected);
+    }
+
+    #[tokio::test]
+    async fn test_peek_recitation_only_when_retryable() {
+        let recitation = json!({ "candidates": [{ "content": { "role": "model", "parts": [] }, "finishReason": "RECITATION" }] });
+        let stream = futures::stream::iter(sse(&[recitation.clone()]));
+        assert_eq!(peek_stream(stream, true).await.err(), Some(EarlyFinish::Recitation));
+        let stream = futures::stream::iter(sse(&[recitation]));
+        assert!(peek_stream(stream, false).await.is_ok());
+    }
+
+    #[test]
+    fn test_apply_hint_and_detect() {
+        let mut envelope = json!({ "request": { "contents": [] } });
+        apply_hint(&mut envelope, "fix it");
+        apply_hint(&mut envelope, "again");
+        assert_eq!(envelope["request"]["systemInstruction"]["parts"][1]["text"], "again");
+
+        assert!(detect(&json!({ "response": malformed_event() })).is_some());
+        let with_call = json!({ "candidates": [{ "content": { "parts": [{ "functionCall": { "name": "x", "args": {} } }] }, "finishReason": "MALFORMED_FUNCTION_CALL" }] });
+        assert!(detect(&with_call).is_none());
+    }
+}
@@ -4,6 +4,14 @@
 pub mod claude;
 pub mod common_utils;
 pub mod error_classifier;
+pub mod finish_reason;
+pub mod function_responses;
+pub mod malformed_call;
 pub mod gemini;
 pub mod openai;
+pub mod recitation;
+pub mod safety;
 pub mod signature_store;
+pub mod thinking_output;
+pub mod upstream_error;
+pub mod vendor_extension;
@@ -66,11 +66,18 @@ where
         created: chrono::Utc::now().timestamp() as u64,
         model: String::new(),
         choices: vec![],
+        prompt_filter_results: None,
+        usage: None,
     };
 
     let mut content = String::new();
+    let mut reasoning = String::new();
     let mut tool_calls: Vec<ToolCall> = Vec::new();
     let mut finish_reason: Option<String> = None;
+    let mut content_filter_results: Option<Value> = None;
+    let mut refusal: Option<String> = None;
+    let mut grounding_metadata: Option<Value> = None;
+    let mut images: Vec<Value> = Vec::new();
 
     for event in chunks {
         // 提取基本信息
@@ -92,6 +99,18 @@ where
                     if let Some(text) = delta.get("content").and_then(|v| v.as_str()) {
                         content.push_str(text);
                     }
+                    // 累积思考内容 (客户端配置关闭 reasoning_content 时流中不会出现)
+                    if let Some(text) = delta.get("reasoning_content").and_then(|v| v.as_str()) {
+                        reasoning.push_str(text);
+                    }
+                    // 累积图片 (structured_images)
+                    if let Some(items) = delta.get("images").and_then(|v| v.as_array()) {
+                        images.extend(items.iter().cloned());
+                    }
+                    // 累积 refusal
+                    if let Some(text) = delta.get("refusal").and_then(|v| v.as_str()) {
+                        refusal.get_or_insert_with(String::new).push_str(text);
+                    }
 
                     // 累积 tool_calls
                     if let Some(tc_arr) = delta.get("tool_calls").and_then(|v| v.as_array()) {
@@ -129,19 +148,41 @@ where
                 if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
                     finish_reason = Some(reason.to_string());
                 }
+                if let Some(results) = choice.get("content_filter_results").filter(|v| !v.is_null()) {
+                    content_filter_results = Some(results.clone());
+                }
+                if let Some(grounding) = choice.get("grounding_metadata").filter(|v| !v.is_null()) {
+                    grounding_metadata = Some(grounding.clone());
+                }
             }
         }
 
-        // OpenAIResponse 没有 usage 字段，跳过
+        // 末尾 chunk 携带 usage (include_usage)
+        if let Some(usage) = event.data.get("usage").filter(|v| !v.is_null()) {
+            response.usage = serde_json::from_value(usage.clone()).ok();
+        }
     }
 
     // 3. 构建最终的 choice
-    let message = if !tool_calls.is_empty() {
+    let mut message = if !tool_calls.is_empty() {
         OpenAIMessage {
             role: "assistant".to_string(),
             content: if content.is_empty() { None } else { Some(OpenAIContent::String(content)) },
             tool_calls: Some(tool_calls),
             reasoning_content: None,
+            refusal: None,
+            images: None,
+            tool_call_id: None,
+            name: None,
+        }
+    } else if refusal.is_some() {
+        OpenAIMessage {
+            role: "assistant".to_string(),
+            content: if content.is_empty() { None } else { Some(OpenAIContent::String(content)) },
+            tool_calls: None,
+            reasoning_content: None,
+            refusal,
+            images: None,
             tool_call_id: None,
             name: None,
         }
@@ -151,15 +192,26 @@ where
             content: Some(OpenAIContent::String(content)),
             tool_calls: None,
             reasoning_content: None,
+            refusal: None,
+            images: None,
             tool_call_id: None,
             name: None,
         }
     };
 
+    if !images.is_empty() {
+        message.images = Some(images);
+    }
+    if !reasoning.is_empty() {
+        message.reasoning_content = Some(reasoning);
+    }
+
     response.choices.push(Choice {
         index: 0,
         message,
         finish_reason,
+        content_filter_results,
+        grounding_metadata,
     });
 
     Ok(response)
@@ -196,5 +248,24 @@ mod tests {
         } else {
             panic!("Expected String content");
         }
+        assert!(response.choices[0].message.reasoning_content.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_collect_keeps_reasoning_content() {
+        let sse_data = vec![
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"reasoning_content\":\"think \"}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"reasoning_content\":\"more\"}}]}\n\n",
+            "data: {\"id\":\"chatcmpl-1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Answer\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        let byte_stream = stream::iter(
+            sse_data.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+
+        let response = collect_openai_stream_to_json(byte_stream).await.unwrap();
+        let message = &response.choices[0].message;
+        assert_eq!(message.reasoning_content.as_deref(), Some("think more"));
+        assert!(matches!(&message.content, Some(OpenAIContent::String(text)) if text == "Answer"));
     }
 }
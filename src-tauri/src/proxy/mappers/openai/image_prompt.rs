@@ -0,0 +1,116 @@
+// 图片生成扩展参数映射
+// Stable Diffusion 系前端常用的 negative_prompt / style_preset / seed 并非 OpenAI 标准字段，
+// Gemini 也没有对应的原生参数：负面提示词和风格预设折叠进提示词，seed 映射到 generationConfig.seed。
+
+use crate::proxy::mappers::gemini::models::GenerationConfig;
+use serde_json::Value;
+
+/// Stability AI 风格预设 -> 提示词描述
+const STYLE_PRESETS: &[(&str, &str)] = &[
+    ("3d-model", "3D model render, octane render, highly detailed volumetric lighting"),
+    ("analog-film", "analog film photo, faded film, desaturated, grainy, vignette"),
+    ("anime", "anime artwork, anime style, key visual, vibrant, studio anime"),
+    ("cinematic", "cinematic film still, shallow depth of field, vignette, high budget, moody, epic"),
+    ("comic-book", "comic book style, graphic illustration, bold outlines, vibrant colors"),
+    ("digital-art", "digital artwork, illustrative, painterly, matte painting, highly detailed"),
+    ("enhance", "breathtaking, award-winning, professional, highly detailed"),
+    ("fantasy-art", "ethereal fantasy concept art, magnificent, celestial, painterly, epic"),
+    ("isometric", "isometric style, vibrant, beautiful, crisp, detailed, ultra detailed"),
+    ("line-art", "line art drawing, professional, sleek, modern, minimalist, graphic, vector graphics"),
+    ("low-poly", "low-poly style, low-poly game art, polygon mesh, jagged, blocky"),
+    ("modeling-compound", "play-doh style, sculpture, clay art, centered composition"),
+    ("neon-punk", "neonpunk style, cyberpunk, vaporwave, neon, vibrant, stunningly beautiful"),
+    ("origami", "origami style, paper art, pleated paper, folded, centered composition"),
+    ("photographic", "cinematic photo, 35mm photograph, film, bokeh, professional, highly detailed"),
+    ("pixel-art", "pixel-art, low-res, blocky, pixel art style, 8-bit graphics"),
+    ("tile-texture", "seamless tileable texture, flat texture, top-down view"),
+];
+
+/// 风格预设描述；未知预设按 "<preset> style" 处理
+pub fn style_preset_description(preset: &str) -> String {
+    let key = preset.trim().to_lowercase().replace(['_', ' '], "-");
+    STYLE_PRESETS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, desc)| desc.to_string())
+        .unwrap_or_else(|| format!("{} style", preset.trim()))
+}
+
+/// SD 扩展字段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImagePromptExtensions {
+    pub negative_prompt: Option<String>,
+    pub style_preset: Option<String>,
+    pub seed: Option<i64>,
+}
+
+impl ImagePromptExtensions {
+    /// 从请求体读取
+    pub fn from_body(body: &Value) -> Self {
+        let text = |key: &str| {
+            body.get(key)
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+        Self {
+            negative_prompt: text("negative_prompt"),
+            style_preset: text("style_preset"),
+            // seed 可能以字符串形式出现；SD 惯例 -1 表示随机
+            seed: body
+                .get("seed")
+                .and_then(|v| v.as_i64().or_else(|| v.as_str()?.trim().parse().ok()))
+                .filter(|s| *s >= 0),
+        }
+    }
+
+    /// 将风格预设与负面提示词折叠进提示词
+    pub fn apply_to_prompt(&self, prompt: &mut String) {
+        if let Some(preset) = &self.style_preset {
+            prompt.push_str(&format!(", ({})", style_preset_description(preset)));
+        }
+        if let Some(negative) = &self.negative_prompt {
+            prompt.push_str(&format!(". Avoid the following in the image: {}", negative));
+        }
+    }
+
+    /// 写入 generationConfig
+    pub fn apply_to_generation_config(&self, generation_config: &mut GenerationConfig) {
+        if let Some(seed) = self.seed {
+            // Gemini seed 为 int32
+            generation_config.seed = Some(seed.min(i32::MAX as i64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extensions_fold_into_prompt_and_config() {
+        let ext = ImagePromptExtensions::from_body(&json!({
+            "negative_prompt": "blurry, watermark",
+            "style_preset": "Pixel Art",
+            "seed": "42"
+        }));
+        let mut prompt = "a castle".to_string();
+        ext.apply_to_prompt(&mut prompt);
+        assert!(prompt.starts_with("a castle, (pixel-art, low-res"));
+        assert!(prompt.ends_with("Avoid the following in the image: blurry, watermark"));
+
+        let mut config = GenerationConfig { candidate_count: Some(1), ..Default::default() };
+        ext.apply_to_generation_config(&mut config);
+        assert_eq!(config.seed, Some(42));
+    }
+
+    #[test]
+    fn test_unknown_preset_and_random_seed() {
+        let ext = ImagePromptExtensions::from_body(&json!({ "style_preset": "watercolor", "seed": -1 }));
+        assert_eq!(style_preset_description("watercolor"), "watercolor style");
+        assert_eq!(ext.seed, None);
+        assert_eq!(ImagePromptExtensions::from_body(&json!({})), ImagePromptExtensions::default());
+    }
+}
@@ -0,0 +1,251 @@
+// 流式 JSON 模式 (response_format = json_object / json_schema)
+// Gemini 在 responseMimeType=application/json 下偶尔仍会输出 markdown 代码块或在 JSON 之后追加说明文字。
+// JsonStreamGuard 逐字符跟踪 JSON 结构：丢弃顶层值之前的前缀 (```json、说明文字) 和顶层值结束后的所有内容，
+// 保证 JSON 模式下的客户端拿到可解析的输出；MAX_TOKENS 截断时可选地补全未闭合的结构。
+
+/// 对象内部的解析位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// 对象中等待键 (刚打开或逗号之后)
+    Key,
+    /// 键之后等待冒号
+    Colon,
+    /// 等待值 (对象冒号之后 / 数组中)
+    Value,
+    /// 值之后等待逗号或闭合
+    AfterValue,
+}
+
+fn is_literal_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-')
+}
+
+#[derive(Debug, Default)]
+pub struct JsonStreamGuard {
+    /// 已打开的容器 ('{' / '[')
+    stack: Vec<char>,
+    expect: Vec<Expect>,
+    started: bool,
+    finished: bool,
+    in_string: bool,
+    /// 暂缓输出的内容 (不完整的转义序列、逗号及其后的空白)，截断修复时可以安全丢弃
+    pending: String,
+    /// 未结束的裸字面量 (数字 / true / false / null)
+    literal: String,
+}
+
+impl JsonStreamGuard {
+    fn set_expect(&mut self, expect: Expect) {
+        if let Some(top) = self.expect.last_mut() {
+            *top = expect;
+        }
+    }
+
+    /// 一个值 (字符串 / 字面量 / 容器) 结束
+    fn end_value(&mut self) {
+        if self.stack.is_empty() {
+            self.finished = true;
+        } else {
+            self.set_expect(Expect::AfterValue);
+        }
+    }
+
+    /// 处理一段增量文本，返回可以立即下发的部分
+    pub fn push(&mut self, text: &str) -> String {
+        let mut out = String::new();
+        for c in text.chars() {
+            if self.finished {
+                break;
+            }
+            if !self.started {
+                if c == '{' || c == '[' {
+                    self.started = true;
+                } else {
+                    continue;
+                }
+            }
+
+            if self.in_string {
+                if !self.pending.is_empty() {
+                    // 转义序列进行中：\uXXXX 需要 6 个字符，其余 2 个
+                    self.pending.push(c);
+                    let done = if self.pending.starts_with("\\u") { self.pending.len() == 6 } else { true };
+                    if done {
+                        out.push_str(&std::mem::take(&mut self.pending));
+                    }
+                    continue;
+                }
+                match c {
+                    '\\' => self.pending.push(c),
+                    '"' => {
+                        out.push(c);
+                        self.in_string = false;
+                        if self.expect.last() == Some(&Expect::Key) {
+                            self.set_expect(Expect::Colon);
+                        } else {
+                            self.end_value();
+                        }
+                    }
+                    _ => out.push(c),
+                }
+                continue;
+            }
+
+            // 裸字面量在遇到分隔符时结束
+            if !self.literal.is_empty() && !is_literal_char(c) {
+                out.push_str(&std::mem::take(&mut self.literal));
+                self.end_value();
+                if self.finished {
+                    break;
+                }
+            }
+
+            if c.is_whitespace() {
+                if self.pending.is_empty() {
+                    out.push(c);
+                } else {
+                    self.pending.push(c);
+                }
+                continue;
+            }
+            if c != ',' && c != '}' && c != ']' {
+                out.push_str(&std::mem::take(&mut self.pending));
+            }
+
+            match c {
+                '{' | '[' => {
+                    out.push(c);
+                    self.stack.push(c);
+                    self.expect.push(if c == '{' { Expect::Key } else { Expect::Value });
+                }
+                '}' | ']' => {
+                    // 闭合前的逗号是非法的尾逗号，直接丢弃
+                    self.pending.clear();
+                    out.push(c);
+                    self.stack.pop();
+                    self.expect.pop();
+                    self.end_value();
+                }
+                ',' => {
+                    self.pending.push(c);
+                    let next = if self.stack.last() == Some(&'{') { Expect::Key } else { Expect::Value };
+                    self.set_expect(next);
+                }
+                ':' => {
+                    out.push(c);
+                    self.set_expect(Expect::Value);
+                }
+                '"' => {
+                    out.push(c);
+                    self.in_string = true;
+                }
+                _ => self.literal.push(c),
+            }
+        }
+        out
+    }
+
+    /// 流结束：返回需要追加的尾部。repair 为 true 时补全被截断的结构
+    pub fn finish(&mut self, repair: bool) -> String {
+        if self.finished || !self.started {
+            if !self.started {
+                tracing::warn!("[JSON-Mode] Upstream produced no JSON value");
+            }
+            return String::new();
+        }
+        if !repair {
+            // 不修复时仍下发缓冲中的内容，保持与上游一致
+            let mut out = std::mem::take(&mut self.pending);
+            out.push_str(&std::mem::take(&mut self.literal));
+            return out;
+        }
+
+        let mut out = String::new();
+        if self.in_string {
+            // 丢弃不完整的转义序列后闭合字符串
+            self.pending.clear();
+            out.push('"');
+            self.in_string = false;
+            if self.expect.last() == Some(&Expect::Key) {
+                self.set_expect(Expect::Colon);
+            } else {
+                self.set_expect(Expect::AfterValue);
+            }
+        } else if self.pending.starts_with(',') {
+            // 悬空的逗号
+            self.pending.clear();
+            self.set_expect(Expect::AfterValue);
+        }
+
+        if !self.literal.is_empty() {
+            let literal = std::mem::take(&mut self.literal);
+            let completed = ["true", "false", "null"]
+                .iter()
+                .find(|word| word.starts_with(literal.as_str()))
+                .map(|word| word.to_string())
+                .unwrap_or_else(|| {
+                    let mut number = literal.clone();
+                    if number.ends_with(['.', 'e', 'E', '+', '-']) {
+                        number.push('0');
+                    }
+                    number
+                });
+            out.push_str(&completed);
+            self.set_expect(Expect::AfterValue);
+        }
+
+        while let Some(open) = self.stack.pop() {
+            match self.expect.pop() {
+                Some(Expect::Colon) => out.push_str(":null"),
+                Some(Expect::Value) if open == '{' => out.push_str("null"),
+                _ => {}
+            }
+            out.push(if open == '{' { '}' } else { ']' });
+            // 外层容器中刚闭合的是一个值
+            self.set_expect(Expect::AfterValue);
+        }
+        self.finished = true;
+        tracing::warn!("[JSON-Mode] Repaired truncated JSON output with suffix {:?}", out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(chunks: &[&str], repair: bool) -> String {
+        let mut guard = JsonStreamGuard::default();
+        let mut out: String = chunks.iter().map(|c| guard.push(c)).collect();
+        out.push_str(&guard.finish(repair));
+        out
+    }
+
+    #[test]
+    fn test_strips_fences_and_trailing_commentary() {
+        let out = run(
+            &["```json\n{\"a\": [1, 2", "], \"b\": \"x}\\\"y\"}", "\n```\nHope this helps!"],
+            false,
+        );
+        assert_eq!(out, "{\"a\": [1, 2], \"b\": \"x}\\\"y\"}");
+        assert!(serde_json::from_str::<serde_json::Value>(&out).is_ok());
+    }
+
+    #[test]
+    fn test_repairs_truncated_output() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["{\"items\": [{\"name\": \"al"], r#"{"items": [{"name": "al"}]}"#),
+            (&["{\"a\": 1,"], r#"{"a": 1}"#),
+            (&["{\"a\": tr"], r#"{"a": true}"#),
+            (&["[1.", ""], "[1.0]"),
+            (&["{\"a\""], r#"{"a":null}"#),
+            (&["{\"a\": "], r#"{"a": null}"#),
+            (&["{\"s\": \"x\\u00"], r#"{"s": "x"}"#),
+        ];
+        for (chunks, expected) in cases {
+            let out = run(chunks, true);
+            assert_eq!(&out, expected);
+            assert!(serde_json::from_str::<serde_json::Value>(&out).is_ok(), "{}", out);
+        }
+    }
+}
@@ -6,6 +6,8 @@ pub mod request;
 pub mod response;
 pub mod streaming;
 pub mod collector;
+pub mod image_prompt;
+pub mod json_mode;
 
 pub use models::*;
 pub use request::*;
@@ -27,16 +27,49 @@ pub struct OpenAIRequest {
     pub tool_choice: Option<Value>,
     #[serde(rename = "parallel_tool_calls")]
     pub parallel_tool_calls: Option<bool>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    /// store=true 时在本地保存结果，可通过 GET /v1/chat/completions/{id} 检索
+    #[serde(default)]
+    pub store: Option<bool>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    /// 输出模态 (OpenRouter 风格，["text", "image"] 请求图文混合输出)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modalities: Option<Vec<String>>,
     // Codex proprietary fields
     pub instructions: Option<String>,
     pub input: Option<Value>,
 }
 
+impl OpenAIRequest {
+    /// 是否通过 modalities 请求图片输出
+    pub fn wants_image_output(&self) -> bool {
+        self.modalities
+            .as_ref()
+            .is_some_and(|m| m.iter().any(|x| x.eq_ignore_ascii_case("image")))
+    }
+}
+
+/// 流式选项 (include_usage 为 true 时在流中下发 usage chunk)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseFormat {
     pub r#type: String,
 }
 
+impl ResponseFormat {
+    /// json_object / json_schema 均映射为 responseMimeType = application/json
+    pub fn is_json(&self) -> bool {
+        self.r#type == "json_object" || self.r#type == "json_schema"
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum OpenAIContent {
@@ -82,6 +115,12 @@ pub struct OpenAIMessage {
     pub reasoning_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// 安全拦截时的拒绝说明 (OpenAI assistant message refusal 字段)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// 模型输出的图片 (OpenRouter 风格扩展字段，客户端开启 structured_images 时使用)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -108,6 +147,28 @@ pub struct OpenAIResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<Choice>,
+    /// 提示词被安全策略拦截时的详情 (Azure 风格)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_filter_results: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+}
+
+/// OpenAI usage 统计 (由 Gemini usageMetadata 映射，口径与流式 usage chunk 一致：输出含思考 token)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpenAIUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl OpenAIUsage {
+    pub fn from_usage_metadata(usage: &Value) -> Option<Self> {
+        let count = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+        let prompt_tokens = count("promptTokenCount")?;
+        let completion_tokens = count("candidatesTokenCount").unwrap_or(0) + count("thoughtsTokenCount").unwrap_or(0);
+        Some(Self { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -115,4 +176,10 @@ pub struct Choice {
     pub index: u32,
     pub message: OpenAIMessage,
     pub finish_reason: Option<String>,
+    /// finish_reason 为 content_filter 时的拦截类别详情 (Azure 风格)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_filter_results: Option<Value>,
+    /// 联网搜索的原始 groundingMetadata (扩展字段，客户端开启 grounding_metadata 时下发)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<Value>,
 }
@@ -2,18 +2,31 @@
 use super::models::*;
 use serde_json::{json, Value};
 use super::streaming::get_thought_signature;
-
-pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mapped_model: &str) -> Value {
+use crate::proxy::model_capabilities::ModelCapabilities;
+
+/// `capabilities` 为目标模型的探测结果 (未探测时按模型名判断)
+pub fn transform_openai_request(
+    request: &OpenAIRequest,
+    project_id: &str,
+    mapped_model: &str,
+    capabilities: Option<&ModelCapabilities>,
+) -> Value {
     // 将 OpenAI 工具转为 Value 数组以便探测
     let tools_val = request.tools.as_ref().map(|list| {
         list.iter().map(|v| v.clone()).collect::<Vec<_>>()
     });
 
     // Resolve grounding config
-    let config = crate::proxy::mappers::common_utils::resolve_request_config(&request.model, mapped_model, &tools_val);
+    let mut config = crate::proxy::mappers::common_utils::resolve_request_config(&request.model, mapped_model, &tools_val);
+    // modalities 包含 image 时请求图文混合输出 (与模型后缀 -text-image 等价)
+    if request.wants_image_output() {
+        config.response_modalities = Some(crate::proxy::mappers::common_utils::mixed_output_modalities());
+    }
 
     tracing::debug!("[Debug] OpenAI Request: original='{}', mapped='{}', type='{}', has_image_config={}", 
         request.model, mapped_model, config.request_type, config.image_config.is_some());
+    // 探测结果只适用于映射后的目标模型 (联网请求会改用其他模型)
+    let capabilities = capabilities.filter(|_| config.final_model == mapped_model).copied();
     
     // 1. 提取所有 System Message 并注入补丁
     let system_instructions: Vec<String> = request.messages.iter()
@@ -23,7 +36,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                 OpenAIContent::String(s) => s.clone(),
                 OpenAIContent::Array(blocks) => {
                     blocks.iter().filter_map(|b| {
-                        if let OpenAIContentBlock::Text { text } = b {
+                        if let OpenAIContentBlock::Text { text, .. } = b {
                             Some(text.clone())
                         } else {
                             None
@@ -37,6 +50,8 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
 
 
     // Pre-scan to map tool_call_id to function name (for Codex)
+    // 映射同时按 tool_call_id 缓存，客户端截断历史后仍可还原 functionResponse 的名称
+    let signature_cache = crate::proxy::SignatureCache::global();
     let mut tool_id_to_name = std::collections::HashMap::new();
     for msg in &request.messages {
         if let Some(tool_calls) = &msg.tool_calls {
@@ -44,6 +59,16 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                 let name = &call.function.name;
                 let final_name = if name == "local_shell_call" { "shell" } else { name };
                 tool_id_to_name.insert(call.id.clone(), final_name.to_string());
+                signature_cache.cache_tool_name(&call.id, final_name);
+            }
+        }
+    }
+    for msg in &request.messages {
+        if let Some(id) = &msg.tool_call_id {
+            if !tool_id_to_name.contains_key(id) {
+                if let Some(name) = signature_cache.get_tool_name(id) {
+                    tool_id_to_name.insert(id.clone(), name);
+                }
             }
         }
     }
@@ -67,9 +92,11 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
             };
 
             let mut parts = Vec::new();
-            
+            // [FIX] tool 消息的内容只放入 functionResponse，避免重复发送一份纯文本
+            let is_tool_response = msg.role == "tool" || msg.role == "function";
+
             // Handle content (multimodal or text)
-            if let Some(content) = &msg.content {
+            if let Some(content) = msg.content.as_ref().filter(|_| !is_tool_response) {
                 match content {
                     OpenAIContent::String(s) => {
                         if !s.is_empty() {
@@ -79,7 +106,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                     OpenAIContent::Array(blocks) => {
                         for block in blocks {
                             match block {
-                                OpenAIContentBlock::Text { text } => {
+                                OpenAIContentBlock::Text { text, .. } => {
                                     parts.push(json!({"text": text}));
                                 }
                                 OpenAIContentBlock::ImageUrl { image_url } => {
@@ -163,7 +190,8 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
                     let mut func_call_part = json!({
                         "functionCall": {
                             "name": if tc.function.name == "local_shell_call" { "shell" } else { &tc.function.name },
-                            "args": args
+                            "args": args,
+                            "id": tc.id
                         }
                     });
 
@@ -177,7 +205,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
             }
 
             // Handle tool response
-            if msg.role == "tool" || msg.role == "function" {
+            if is_tool_response {
                 let name = msg.name.as_deref().unwrap_or("unknown");
                 let final_name = if name == "local_shell_call" { "shell" } 
                                 else if let Some(id) = &msg.tool_call_id { tool_id_to_name.get(id).map(|s| s.as_str()).unwrap_or(name) }
@@ -185,16 +213,20 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
 
                 let content_val = match &msg.content {
                     Some(OpenAIContent::String(s)) => s.clone(),
-                    Some(OpenAIContent::Array(blocks)) => blocks.iter().filter_map(|b| if let OpenAIContentBlock::Text { text } = b { Some(text.clone()) } else { None }).collect::<Vec<_>>().join("\n"),
+                    Some(OpenAIContent::Array(blocks)) => blocks.iter().filter_map(|b| if let OpenAIContentBlock::Text { text, .. } = b { Some(text.clone()) } else { None }).collect::<Vec<_>>().join("\n"),
                     None => "".to_string()
                 };
 
-                parts.push(json!({
+                let mut response_part = json!({
                     "functionResponse": {
                        "name": final_name,
                        "response": { "result": content_val }
                     }
-                }));
+                });
+                if let Some(id) = &msg.tool_call_id {
+                    response_part["functionResponse"]["id"] = json!(id);
+                }
+                parts.push(response_part);
             }
 
             json!({ "role": role, "parts": parts })
@@ -215,12 +247,18 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         }
         merged_contents.push(msg);
     }
+    // [FIX] 并行工具调用的结果按调用 id 分组排序，缺失的补占位结果
+    crate::proxy::mappers::function_responses::group_function_responses(&mut merged_contents);
     let contents = merged_contents;
 
     // 3. 构建请求体
     // [FIX PR #368] 检测 Gemini 3 Pro thinking 模型，注入 thinkingBudget 配置
-    let is_gemini_3_thinking = mapped_model.contains("gemini-3") && 
-        (mapped_model.ends_with("-high") || mapped_model.ends_with("-low") || mapped_model.contains("-pro"));
+    // 已探测的目标模型以探测结果为准
+    let is_gemini_3_thinking = capabilities.map_or_else(
+        || mapped_model.contains("gemini-3") &&
+            (mapped_model.ends_with("-high") || mapped_model.ends_with("-low") || mapped_model.contains("-pro")),
+        |caps| caps.thinking,
+    );
 
     let mut gen_config = json!({
         "maxOutputTokens": request.max_tokens.unwrap_or(64000),
@@ -249,7 +287,7 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     }
 
     if let Some(fmt) = &request.response_format {
-        if fmt.r#type == "json_object" {
+        if fmt.is_json() {
             gen_config["responseMimeType"] = json!("application/json");
         }
     }
@@ -361,20 +399,16 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         crate::proxy::mappers::common_utils::inject_google_search_tool(&mut inner_request);
     }
 
-    if let Some(image_config) = config.image_config {
-         if let Some(obj) = inner_request.as_object_mut() {
-             obj.remove("tools");
-             obj.remove("systemInstruction");
-             let gen_config = obj.entry("generationConfig").or_insert_with(|| json!({}));
-             if let Some(gen_obj) = gen_config.as_object_mut() {
-                 gen_obj.remove("thinkingConfig");
-                 gen_obj.remove("responseMimeType"); 
-                 gen_obj.remove("responseModalities");
-                 gen_obj.insert("imageConfig".to_string(), image_config);
-             }
-         }
+    crate::proxy::mappers::common_utils::apply_output_modalities(&mut inner_request, &config);
+
+    // 探测确认不支持工具 / systemInstruction 的目标模型
+    if let Some(caps) = &capabilities {
+        crate::proxy::model_capabilities::apply_to_request(&mut inner_request, caps);
     }
 
+    // [FIX] systemInstruction 的 role 按目标模型系列规范化
+    crate::proxy::mappers::common_utils::apply_system_instruction_role(&mut inner_request, &config.final_model);
+
     json!({
         "project": project_id,
         "requestId": format!("openai-{}", uuid::Uuid::new_v4()),
@@ -428,6 +462,8 @@ mod tests {
                 ])),
                 reasoning_content: None,
                 tool_calls: None,
+                refusal: None,
+                images: None,
                 tool_call_id: None,
                 name: None,
             }],
@@ -441,15 +477,102 @@ mod tests {
             tools: None,
             tool_choice: None,
             parallel_tool_calls: None,
+            modalities: None,
+            stream_options: None,
+            store: None,
+            metadata: None,
             instructions: None,
             input: None,
             prompt: None,
         };
 
-        let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash");
+        let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash", None);
         let parts = &result["request"]["contents"][0]["parts"];
         assert_eq!(parts.as_array().unwrap().len(), 2);
         assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
         assert_eq!(parts[1]["inlineData"]["mimeType"].as_str().unwrap(), "image/png");
     }
+
+    #[test]
+    fn test_parallel_tool_results_grouped_in_call_order() {
+        let req: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "user", "content": "weather in two cities" },
+                { "role": "assistant", "content": null, "tool_calls": [
+                    { "id": "call_1", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Paris\"}" } },
+                    { "id": "call_2", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Rome\"}" } }
+                ] },
+                { "role": "tool", "tool_call_id": "call_2", "content": "sunny" },
+                { "role": "tool", "tool_call_id": "call_1", "content": "rainy" }
+            ]
+        }))
+        .unwrap();
+
+        let result = transform_openai_request(&req, "test-v", "gemini-2.5-flash", None);
+        let contents = result["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        let parts = contents[2]["parts"].as_array().unwrap();
+        let ids: Vec<&str> = parts.iter().map(|p| p["functionResponse"]["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["call_1", "call_2"]);
+        assert_eq!(parts[0]["functionResponse"]["response"]["result"], "rainy");
+    }
+
+    #[test]
+    fn test_json_response_format_requests_json_mime_type() {
+        for format in ["json_object", "json_schema"] {
+            let req: OpenAIRequest = serde_json::from_value(json!({
+                "model": "gemini-2.5-flash",
+                "response_format": { "type": format },
+                "messages": [{ "role": "user", "content": "city as json" }]
+            }))
+            .unwrap();
+            let body = transform_openai_request(&req, "p", "gemini-2.5-flash", None);
+            assert_eq!(body["request"]["generationConfig"]["responseMimeType"], "application/json");
+        }
+    }
+
+    #[test]
+    fn test_modalities_request_mixed_output() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gemini-3-pro-image",
+            "modalities": ["text", "image"],
+            "messages": [
+                { "role": "system", "content": "Explain with diagrams." },
+                { "role": "user", "content": "How does TCP work?" }
+            ]
+        }))
+        .unwrap();
+        assert!(req.wants_image_output());
+        let body = transform_openai_request(&req, "p", "gemini-3-pro-image", None);
+        let gen_config = &body["request"]["generationConfig"];
+        assert_eq!(gen_config["responseModalities"], json!(["TEXT", "IMAGE"]));
+        assert!(gen_config.get("imageConfig").is_some());
+        // 混合输出保留系统提示
+        assert!(body["request"]["systemInstruction"].to_string().contains("Explain with diagrams."));
+    }
+
+    #[test]
+    fn test_probed_capabilities_override_name_heuristics() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "my-model",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "hi" }
+            ],
+            "tools": [{ "type": "function", "function": { "name": "lookup", "parameters": { "type": "object" } } }]
+        }))
+        .unwrap();
+        // 按名称判断 gemini-3-pro-high 支持 thinking，但探测结果表明不支持
+        let caps = ModelCapabilities { thinking: false, tools: false, system_instruction: true };
+        let body = transform_openai_request(&req, "p", "gemini-3-pro-high", Some(&caps));
+        assert!(body["request"]["generationConfig"].get("thinkingConfig").is_none());
+        assert!(body["request"].get("tools").is_none());
+
+        // 名称判断不支持 thinking 的自定义模型，探测结果表明支持
+        let caps = ModelCapabilities { thinking: true, tools: true, system_instruction: true };
+        let body = transform_openai_request(&req, "p", "custom-target", Some(&caps));
+        assert!(body["request"]["generationConfig"].get("thinkingConfig").is_some());
+        assert!(body["request"].get("tools").is_some());
+    }
 }
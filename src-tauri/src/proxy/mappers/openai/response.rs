@@ -1,8 +1,27 @@
 // OpenAI 协议响应转换模块
 use super::models::*;
-use serde_json::Value;
+use crate::proxy::common::output_locale::{grounding_markdown_from_json, Locale};
+use crate::proxy::config::ClientCompatProfile;
+use crate::proxy::mappers::thinking_output::{Routed, ThoughtRouter};
+use serde_json::{json, Value};
 
-pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
+/// Gemini inlineData 图片：开启 structured_images 时放入 images (OpenRouter 风格)，否则以 Markdown 图片追加到正文
+pub(super) fn push_inline_image(img: &Value, structured: bool, content: &mut String, images: &mut Vec<Value>) {
+    let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
+    let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
+    if data.is_empty() {
+        return;
+    }
+    let url = format!("data:{};base64,{}", mime_type, data);
+    if structured {
+        images.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+    } else {
+        content.push_str(&format!("![image]({})", url));
+    }
+}
+
+/// `profile` 为调用方的兼容性配置，`locale` 决定注入的联网搜索文本所用语言
+pub fn transform_openai_response(gemini_response: &Value, profile: &ClientCompatProfile, locale: Locale) -> OpenAIResponse {
     // 解包 response 字段
     let raw = gemini_response.get("response").unwrap_or(gemini_response);
 
@@ -14,6 +33,8 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             let mut content_out = String::new();
             let mut thought_out = String::new();
             let mut tool_calls = Vec::new();
+            let mut images = Vec::new();
+            let mut router = ThoughtRouter::new(profile.thinking_mode());
 
             // 提取 content 和 tool_calls
             if let Some(parts) = candidate
@@ -38,17 +59,17 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
 
                     // 文本部分
                     if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                        if is_thought_part {
-                            // thought: true 时，text 是思考内容
-                            thought_out.push_str(text);
-                        } else {
-                            // 正常内容
-                            content_out.push_str(text);
+                        // thought: true 时，text 是思考内容，按 thinking_output 决定去向
+                        match router.route(text, is_thought_part) {
+                            Routed::Text(out) => content_out.push_str(&out),
+                            Routed::Thinking(out) => thought_out.push_str(&out),
+                            Routed::Dropped => {}
                         }
                     }
 
                     // 工具调用部分
                     if let Some(fc) = part.get("functionCall") {
+                        content_out.push_str(router.close().unwrap_or_default());
                         let name = fc.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
                         let args = fc
                             .get("args")
@@ -72,68 +93,32 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
 
                     // 图片处理 (响应中直接返回图片的情况)
                     if let Some(img) = part.get("inlineData") {
-                        let mime_type = img
-                            .get("mimeType")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("image/png");
-                        let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                        if !data.is_empty() {
-                            content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
-                        }
+                        content_out.push_str(router.close().unwrap_or_default());
+                        push_inline_image(img, profile.structured_images, &mut content_out, &mut images);
                     }
                 }
             }
 
-            // 提取并处理该候选结果的联网搜索引文 (Grounding Metadata)
-            if let Some(grounding) = candidate.get("groundingMetadata") {
-                let mut grounding_text = String::new();
-
-                // 1. 处理搜索词
-                if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
-                    let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
-                    if !query_list.is_empty() {
-                        grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                        grounding_text.push_str(&query_list.join(", "));
-                    }
-                }
+            content_out.push_str(router.close().unwrap_or_default());
 
-                // 2. 处理来源链接 (Chunks)
-                if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
-                    let mut links = Vec::new();
-                    for (i, chunk) in chunks.iter().enumerate() {
-                        if let Some(web) = chunk.get("web") {
-                            let title = web
-                                .get("title")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("网页来源");
-                            let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
-                            links.push(format!("[{}] [{}]({})", i + 1, title, uri));
-                        }
-                    }
-
-                    if !links.is_empty() {
-                        grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                        grounding_text.push_str(&links.join("\n"));
-                    }
-                }
-
-                if !grounding_text.is_empty() {
-                    content_out.push_str(&grounding_text);
-                }
+            // 提取并处理该候选结果的联网搜索引文 (Grounding Metadata)
+            let grounding = candidate.get("groundingMetadata");
+            if let Some(grounding) = grounding.filter(|_| profile.grounding_text) {
+                content_out.push_str(&grounding_markdown_from_json(locale, grounding));
             }
+            let grounding_metadata = grounding.filter(|_| profile.grounding_metadata).cloned();
 
             // 提取该候选结果的 finish_reason
             let finish_reason = candidate
                 .get("finishReason")
                 .and_then(|f| f.as_str())
-                .map(|f| match f {
-                    "STOP" => "stop",
-                    "MAX_TOKENS" => "length",
-                    "SAFETY" => "content_filter",
-                    "RECITATION" => "content_filter",
-                    _ => "stop",
-                })
+                .map(crate::proxy::mappers::finish_reason::to_openai_finish_reason)
                 .unwrap_or("stop");
+            // [NEW] 安全拦截详情
+            let candidate_block = crate::proxy::mappers::safety::detect_candidate_block(candidate);
+            let content_filter_results = candidate_block.as_ref().map(|b| b.to_openai_filter_results());
+            // [NEW] 被拦截时通过 refusal 字段说明原因 (OpenAI SDK 会单独暴露 message.refusal)
+            let refusal = candidate_block.as_ref().map(|b| b.describe());
 
             choices.push(Choice {
                 index: idx as u32,
@@ -154,10 +139,37 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                     } else {
                         Some(tool_calls)
                     },
+                    refusal,
+                    images: if images.is_empty() { None } else { Some(images) },
                     tool_call_id: None,
                     name: None,
                 },
                 finish_reason: Some(finish_reason.to_string()),
+                content_filter_results,
+                grounding_metadata,
+            });
+        }
+    }
+
+    // [NEW] 提示词被拦截时没有候选结果：返回空内容 + content_filter，避免客户端收到无原因的空响应
+    let prompt_block = crate::proxy::mappers::safety::detect_prompt_block(raw);
+    if let Some(block) = &prompt_block {
+        if choices.is_empty() {
+            choices.push(Choice {
+                index: 0,
+                message: OpenAIMessage {
+                    role: "assistant".to_string(),
+                    content: None,
+                    reasoning_content: None,
+                    tool_calls: None,
+                    refusal: Some(block.describe()),
+                    images: None,
+                    tool_call_id: None,
+                    name: None,
+                },
+                finish_reason: Some("content_filter".to_string()),
+                content_filter_results: Some(block.to_openai_filter_results()),
+                grounding_metadata: None,
             });
         }
     }
@@ -176,6 +188,10 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             .unwrap_or("unknown")
             .to_string(),
         choices,
+        prompt_filter_results: prompt_block.map(|b| {
+            serde_json::json!([{ "prompt_index": 0, "content_filter_results": b.to_openai_filter_results() }])
+        }),
+        usage: raw.get("usageMetadata").and_then(OpenAIUsage::from_usage_metadata),
     }
 }
 
@@ -184,6 +200,51 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_safety_blocks_map_to_content_filter_with_details() {
+        let blocked = json!({
+            "candidates": [{
+                "content": { "parts": [] },
+                "finishReason": "SAFETY",
+                "safetyRatings": [{ "category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH", "blocked": true }]
+            }]
+        });
+        let result = transform_openai_response(&blocked, &ClientCompatProfile::default(), Locale::default());
+        assert_eq!(result.choices[0].finish_reason.as_deref(), Some("content_filter"));
+        let filter = result.choices[0].content_filter_results.as_ref().unwrap();
+        assert_eq!(filter["harassment"]["filtered"], true);
+        assert!(result.choices[0].message.refusal.as_deref().unwrap().contains("SAFETY"));
+
+        // 提示词被拦截：没有 candidates
+        let prompt_blocked = json!({ "promptFeedback": { "blockReason": "PROHIBITED_CONTENT" } });
+        let result = transform_openai_response(&prompt_blocked, &ClientCompatProfile::default(), Locale::default());
+        assert_eq!(result.choices.len(), 1);
+        assert_eq!(result.choices[0].finish_reason.as_deref(), Some("content_filter"));
+        assert!(result.choices[0].message.refusal.is_some());
+        let prompt_filter = result.prompt_filter_results.unwrap();
+        assert!(prompt_filter[0]["content_filter_results"]["reason"]["detail"]
+            .as_str()
+            .unwrap()
+            .contains("PROHIBITED_CONTENT"));
+    }
+
+    #[test]
+    fn test_non_category_finish_reasons_are_mapped() {
+        let recitation = json!({
+            "candidates": [{ "content": { "parts": [{ "text": "partial" }] }, "finishReason": "RECITATION" }]
+        });
+        let result = transform_openai_response(&recitation, &ClientCompatProfile::default(), Locale::default());
+        assert_eq!(result.choices[0].finish_reason.as_deref(), Some("content_filter"));
+        assert!(result.choices[0].message.refusal.as_deref().unwrap().contains("RECITATION"));
+
+        let malformed = json!({
+            "candidates": [{ "content": { "parts": [] }, "finishReason": "MALFORMED_FUNCTION_CALL" }]
+        });
+        let result = transform_openai_response(&malformed, &ClientCompatProfile::default(), Locale::default());
+        assert_eq!(result.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert!(result.choices[0].message.refusal.is_none());
+    }
+
     #[test]
     fn test_transform_openai_response() {
         let gemini_resp = json!({
@@ -197,7 +258,7 @@ mod tests {
             "responseId": "resp_123"
         });
 
-        let result = transform_openai_response(&gemini_resp);
+        let result = transform_openai_response(&gemini_resp, &ClientCompatProfile::default(), Locale::default());
         assert_eq!(result.object, "chat.completion");
         let content = match result.choices[0].message.content.as_ref().unwrap() {
             OpenAIContent::String(s) => s,
@@ -206,4 +267,89 @@ mod tests {
         assert_eq!(content, "Hello!");
         assert_eq!(result.choices[0].finish_reason, Some("stop".to_string()));
     }
+
+    #[test]
+    fn test_grounding_metadata_passthrough() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "Answer" }] },
+                "finishReason": "STOP",
+                "groundingMetadata": {
+                    "webSearchQueries": ["axum"],
+                    "groundingChunks": [{ "web": { "title": "Axum", "uri": "https://docs.rs/axum" } }]
+                }
+            }]
+        });
+        let text_of = |r: &OpenAIResponse| match r.choices[0].message.content.as_ref().unwrap() {
+            OpenAIContent::String(s) => s.clone(),
+            _ => panic!("Expected string content"),
+        };
+
+        let result = transform_openai_response(&gemini_resp, &ClientCompatProfile::default(), Locale::En);
+        assert!(text_of(&result).contains("[1] [Axum](https://docs.rs/axum)"));
+        assert!(result.choices[0].grounding_metadata.is_none());
+
+        let profile = ClientCompatProfile { grounding_text: false, grounding_metadata: true, ..Default::default() };
+        let result = transform_openai_response(&gemini_resp, &profile, Locale::En);
+        assert_eq!(text_of(&result), "Answer");
+        let grounding = result.choices[0].grounding_metadata.as_ref().unwrap();
+        assert_eq!(grounding["groundingChunks"][0]["web"]["uri"], "https://docs.rs/axum");
+    }
+
+    #[test]
+    fn test_mixed_text_image_parts() {
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": { "parts": [
+                    { "text": "Here is the diagram:" },
+                    { "inlineData": { "mimeType": "image/png", "data": "iVBORw0KGgo" } },
+                    { "text": " Done." }
+                ] },
+                "finishReason": "STOP"
+            }]
+        });
+
+        // 默认：图片以 Markdown 内嵌到正文
+        let result = transform_openai_response(&gemini_resp, &ClientCompatProfile::default(), Locale::En);
+        let message = &result.choices[0].message;
+        assert!(matches!(message.content.as_ref().unwrap(), OpenAIContent::String(s) if s.contains("![image](data:image/png;base64,iVBORw0KGgo)")));
+        assert!(message.images.is_none());
+
+        // structured_images：文字保留在正文，图片放入 images
+        let profile = ClientCompatProfile { structured_images: true, ..Default::default() };
+        let result = transform_openai_response(&gemini_resp, &profile, Locale::En);
+        let message = &result.choices[0].message;
+        assert!(matches!(message.content.as_ref().unwrap(), OpenAIContent::String(s) if s == "Here is the diagram: Done."));
+        let images = message.images.as_ref().unwrap();
+        assert_eq!(images[0]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo");
+    }
+
+    #[test]
+    fn test_thinking_output_modes() {
+        use crate::proxy::config::ThinkingOutput;
+        let gemini_resp = json!({
+            "candidates": [{
+                "content": { "parts": [
+                    { "text": "pondering", "thought": true },
+                    { "text": "Answer" }
+                ] },
+                "finishReason": "STOP"
+            }]
+        });
+        let run = |profile: ClientCompatProfile| {
+            let message = transform_openai_response(&gemini_resp, &profile, Locale::En).choices[0].message.clone();
+            let content = match message.content {
+                Some(OpenAIContent::String(s)) => s,
+                _ => panic!("Expected string content"),
+            };
+            (content, message.reasoning_content)
+        };
+
+        assert_eq!(run(ClientCompatProfile::default()), ("Answer".to_string(), Some("pondering".to_string())));
+        // 未设置 thinking_output 时沿用 reasoning_content 开关
+        let profile = ClientCompatProfile { reasoning_content: false, ..Default::default() };
+        assert_eq!(run(profile), ("Answer".to_string(), None));
+        let profile = ClientCompatProfile { thinking_output: Some(ThinkingOutput::Inline), ..Default::default() };
+        assert_eq!(run(profile), ("<think>\npondering\n</think>\n\nAnswer".to_string(), None));
+    }
 }
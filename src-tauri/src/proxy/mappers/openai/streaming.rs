@@ -8,6 +8,8 @@ use chrono::Utc;
 use uuid::Uuid;
 use tracing::debug;
 use rand::Rng;
+use crate::proxy::common::usage_heartbeat::{UsageHeartbeat, UsageProgress};
+use crate::proxy::mappers::thinking_output::{Routed, ThinkingOutput, ThoughtRouter};
 
 // === 全局 ThoughtSignature 存储 ===
 // 用于在流式响应和后续请求之间传递签名，避免嵌入到用户可见的文本中
@@ -53,8 +55,21 @@ pub fn get_thought_signature() -> Option<String> {
 pub fn create_openai_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    json_mode: Option<crate::proxy::config::JsonModeConfig>,
+    profile: crate::proxy::config::ClientCompatProfile,
+    locale: crate::proxy::common::output_locale::Locale,
+    include_usage: bool,
+    usage_heartbeat: Option<UsageHeartbeat>,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    // [NEW] 用量统计：include_usage 时在流末尾下发最终用量，启用心跳时定期下发阶段性用量
+    let heartbeat_enabled = include_usage && usage_heartbeat.is_some();
+    let mut usage = usage_heartbeat.unwrap_or_else(|| UsageHeartbeat::new(std::time::Duration::MAX));
+    // [NEW] JSON 模式：每个候选一个增量校验器
+    let mut json_guards: std::collections::HashMap<usize, super::json_mode::JsonStreamGuard> = std::collections::HashMap::new();
+    // [NEW] 思考内容输出方式：每个候选一个路由器 (Inline 模式的 <think> 标签跨 chunk)
+    let thinking_mode = profile.thinking_mode();
+    let mut thought_routers: std::collections::HashMap<usize, ThoughtRouter> = std::collections::HashMap::new();
     
     // 在流开始时生成固定的 ID 和 timestamp，所有 chunk 共用
     let stream_id = format!("chatcmpl-{}", Uuid::new_v4());
@@ -91,6 +106,27 @@ pub fn create_openai_sse_stream(
                                     } else {
                                         json
                                     };
+                                    usage.observe(&actual_data);
+
+                                    // [NEW] 提示词被安全策略拦截：没有 candidates，直接以 content_filter 结束
+                                    if let Some(block) = crate::proxy::mappers::safety::detect_prompt_block(&actual_data) {
+                                        tracing::warn!("[OpenAI-SSE] {}", block.describe());
+                                        let blocked_chunk = json!({
+                                            "id": &stream_id,
+                                            "object": "chat.completion.chunk",
+                                            "created": created_ts,
+                                            "model": model,
+                                            "choices": [
+                                                {
+                                                    "index": 0,
+                                                    "delta": { "refusal": block.describe() },
+                                                    "finish_reason": "content_filter",
+                                                    "content_filter_results": block.to_openai_filter_results()
+                                                }
+                                            ]
+                                        });
+                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&blocked_chunk).unwrap_or_default())));
+                                    }
 
                                     // Extract candidates
                                     if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
@@ -99,7 +135,9 @@ pub fn create_openai_sse_stream(
 
                                             let mut content_out = String::new();
                                             let mut thought_out = String::new();
+                                            let mut images_out: Vec<Value> = Vec::new();
                                             
+                                            let router = thought_routers.entry(idx).or_insert_with(|| ThoughtRouter::new(thinking_mode));
                                             if let Some(parts_list) = parts {
                                                 for part in parts_list {
                                                     let is_thought_part = part.get("thought")
@@ -107,10 +145,10 @@ pub fn create_openai_sse_stream(
                                                         .unwrap_or(false);
                                                     
                                                     if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                        if is_thought_part {
-                                                            thought_out.push_str(text);
-                                                        } else {
-                                                            content_out.push_str(text);
+                                                        match router.route(text, is_thought_part) {
+                                                            Routed::Text(out) => content_out.push_str(&out),
+                                                            Routed::Thinking(out) => thought_out.push_str(&out),
+                                                            Routed::Dropped => {}
                                                         }
                                                     }
                                                     // 捕获 thoughtSignature (Gemini 3 工具调用必需)
@@ -119,52 +157,35 @@ pub fn create_openai_sse_stream(
                                                     }
 
                                                     if let Some(img) = part.get("inlineData") {
-                                                        let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
-                                                        let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                                        if !data.is_empty() {
-                                                            content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
-                                                        }
+                                                        content_out.push_str(router.close().unwrap_or_default());
+                                                        super::response::push_inline_image(img, profile.structured_images, &mut content_out, &mut images_out);
                                                     }
                                                 }
                                             }
-
+                                            if candidate.get("finishReason").is_some() {
+                                                content_out.push_str(router.close().unwrap_or_default());
+                                            }
 
                                             // 处理联网搜索引文 (Grounding Metadata) - 流式
-                                            if let Some(grounding) = candidate.get("groundingMetadata") {
-                                                let mut grounding_text = String::new();
-                                                
-                                                // 1. 处理搜索词
-                                                if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
-                                                    let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
-                                                    if !query_list.is_empty() {
-                                                        grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                                                        grounding_text.push_str(&query_list.join(", "));
-                                                    }
-                                                }
-
-                                                // 2. 处理来源链接 (Chunks)
-                                                if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
-                                                    let mut links = Vec::new();
-                                                    for (i, chunk) in chunks.iter().enumerate() {
-                                                        if let Some(web) = chunk.get("web") {
-                                                            let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
-                                                            let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
-                                                            links.push(format!("[{}] [{}]({})", i + 1, title, uri));
-                                                        }
-                                                    }
-                                                    if !links.is_empty() {
-                                                        grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                                                        grounding_text.push_str(&links.join("\n"));
-                                                    }
-                                                }
-                                                
-                                                if !grounding_text.is_empty() {
-                                                    content_out.push_str(&grounding_text);
+                                            let grounding = candidate.get("groundingMetadata");
+                                            if let Some(grounding) = grounding.filter(|_| profile.grounding_text) {
+                                                content_out.push_str(&crate::proxy::common::output_locale::grounding_markdown_from_json(locale, grounding));
+                                            }
+                                            // [NEW] 原始元数据以 grounding_metadata 扩展字段下发
+                                            let grounding_metadata = grounding.filter(|_| profile.grounding_metadata);
+
+                                            // [NEW] JSON 模式：只下发顶层 JSON 值本身，截断时按配置补全
+                                            if let Some(json_config) = &json_mode {
+                                                let guard = json_guards.entry(idx).or_default();
+                                                content_out = guard.push(&content_out);
+                                                if let Some(reason) = candidate.get("finishReason").and_then(|f| f.as_str()) {
+                                                    let repair = json_config.repair_truncated && reason == "MAX_TOKENS";
+                                                    content_out.push_str(&guard.finish(repair));
                                                 }
                                             }
 
                                             // 只有当 content 和 thought 都为空时才跳过
-                                            if content_out.is_empty() && thought_out.is_empty() {
+                                            if content_out.is_empty() && thought_out.is_empty() && images_out.is_empty() && grounding_metadata.is_none() {
                                                 // Skip empty chunks if no text/grounding/thought was found
                                                 if candidate.get("finishReason").is_none() {
                                                     continue;
@@ -174,13 +195,7 @@ pub fn create_openai_sse_stream(
                                             // Extract finish reason
                                             let finish_reason = candidate.get("finishReason")
                                                 .and_then(|f| f.as_str())
-                                                .map(|f| match f {
-                                                    "STOP" => "stop",
-                                                    "MAX_TOKENS" => "length",
-                                                    "SAFETY" => "content_filter",
-                                                    "RECITATION" => "content_filter",
-                                                    _ => f,
-                                                });
+                                                .map(crate::proxy::mappers::finish_reason::to_openai_finish_reason);
 
                                             // Construct OpenAI SSE chunk
                                             // 如果有思考内容，先发送 reasoning_content chunk
@@ -207,8 +222,8 @@ pub fn create_openai_sse_stream(
                                             }
 
                                             // 发送正常 content chunk
-                                            if !content_out.is_empty() || finish_reason.is_some() {
-                                                let openai_chunk = json!({
+                                            if !content_out.is_empty() || !images_out.is_empty() || finish_reason.is_some() || grounding_metadata.is_some() {
+                                                let mut openai_chunk = json!({
                                                     "id": &stream_id,
                                                     "object": "chat.completion.chunk",
                                                     "created": created_ts,
@@ -223,6 +238,18 @@ pub fn create_openai_sse_stream(
                                                         }
                                                     ]
                                                 });
+                                                // [NEW] 安全拦截详情
+                                                if let Some(block) = crate::proxy::mappers::safety::detect_candidate_block(candidate) {
+                                                    tracing::warn!("[OpenAI-SSE] {}", block.describe());
+                                                    openai_chunk["choices"][0]["content_filter_results"] = block.to_openai_filter_results();
+                                                    openai_chunk["choices"][0]["delta"]["refusal"] = json!(block.describe());
+                                                }
+                                                if let Some(grounding) = grounding_metadata {
+                                                    openai_chunk["choices"][0]["grounding_metadata"] = grounding.clone();
+                                                }
+                                                if !images_out.is_empty() {
+                                                    openai_chunk["choices"][0]["delta"]["images"] = json!(images_out);
+                                                }
 
                                                 let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
                                                 yield Ok::<Bytes, String>(Bytes::from(sse_out));
@@ -233,6 +260,12 @@ pub fn create_openai_sse_stream(
                             }
                         }
                     }
+
+                    if heartbeat_enabled {
+                        if let Some(progress) = usage.poll() {
+                            yield Ok::<Bytes, String>(usage_chunk(&stream_id, created_ts, &model, progress));
+                        }
+                    }
                 }
                 Err(e) => {
                     use crate::proxy::mappers::error_classifier::classify_stream_error;
@@ -268,6 +301,9 @@ pub fn create_openai_sse_stream(
                 }
             }
         }
+        if include_usage {
+            yield Ok::<Bytes, String>(usage_chunk(&stream_id, created_ts, &model, usage.progress()));
+        }
         // End of stream signal for OpenAI
         yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
     };
@@ -275,11 +311,31 @@ pub fn create_openai_sse_stream(
     Box::pin(stream)
 }
 
+/// 仅携带用量的 chunk (stream_options.include_usage)
+fn usage_chunk(stream_id: &str, created_ts: i64, model: &str, progress: UsageProgress) -> Bytes {
+    let chunk = json!({
+        "id": stream_id,
+        "object": "chat.completion.chunk",
+        "created": created_ts,
+        "model": model,
+        "choices": [],
+        "usage": {
+            "prompt_tokens": progress.prompt_tokens,
+            "completion_tokens": progress.output_tokens,
+            "total_tokens": progress.prompt_tokens + progress.output_tokens
+        }
+    });
+    Bytes::from(format!("data: {}\n\n", serde_json::to_string(&chunk).unwrap_or_default()))
+}
+
+/// `thinking_mode` 为思考内容输出方式 (Completions 没有思考字段，仅 Inline 时输出到正文)
 pub fn create_legacy_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
+    thinking_mode: ThinkingOutput,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    let mut router = ThoughtRouter::new(thinking_mode);
     
     // Generate constant alphanumeric ID (mimics OpenAI base62 format)
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -316,14 +372,9 @@ pub fn create_legacy_sse_stream(
                                         if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
                                             for part in parts {
                                                 if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    content_out.push_str(text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    // // content_out.push_str(thought_text);
+                                                    let thought = part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false);
+                                                    router.push_text_only(text, thought, &mut content_out);
                                                 }
-                                                */
-                                                // 捕获 thoughtSignature
                                                 // 捕获 thoughtSignature 到全局存储
                                                 if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
                                                     store_thought_signature(sig);
@@ -337,12 +388,10 @@ pub fn create_legacy_sse_stream(
                                         .and_then(|c| c.get(0))
                                         .and_then(|c| c.get("finishReason"))
                                         .and_then(|f| f.as_str())
-                                        .map(|f| match f {
-                                            "STOP" => "stop",
-                                            "MAX_TOKENS" => "length",
-                                            "SAFETY" => "content_filter",
-                                            _ => f,
-                                        });
+                                        .map(crate::proxy::mappers::finish_reason::to_openai_finish_reason);
+                                    if finish_reason.is_some() {
+                                        content_out.push_str(router.close().unwrap_or_default());
+                                    }
 
                                     // Construct LEGACY completion chunk - STRICT VERSION
                                     let legacy_chunk = json!({
@@ -412,11 +461,14 @@ pub fn create_legacy_sse_stream(
     Box::pin(stream)
 }
 
+/// `thinking_mode` 为思考内容输出方式 (Responses 输出只映射正文，仅 Inline 时输出思考内容)
 pub fn create_codex_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     _model: String,
+    thinking_mode: ThinkingOutput,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
+    let mut router = ThoughtRouter::new(thinking_mode);
     
     // Generate alphanumeric ID
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -464,11 +516,7 @@ pub fn create_codex_sse_stream(
                                 if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
                                     if let Some(candidate) = candidates.get(0) {
                                         if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
-                                            last_finish_reason = match reason {
-                                                "STOP" => "stop".to_string(),
-                                                "MAX_TOKENS" => "length".to_string(),
-                                                _ => "stop".to_string(),
-                                            };
+                                            last_finish_reason = crate::proxy::mappers::finish_reason::to_openai_finish_reason(reason).to_string();
                                         }
                                     }
                                 }
@@ -479,17 +527,11 @@ pub fn create_codex_sse_stream(
                                     if let Some(candidate) = candidates.get(0) {
                                         if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
                                             for part in parts {
+                                                // 弯引号等由 text_sanitize 统一处理
                                                 if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    // Sanitize smart quotes to standard quotes for JSON compatibility
-                                                    let clean_text = text.replace('“', "\"").replace('”', "\"");
-                                                    delta_text.push_str(&clean_text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    let clean_thought = thought_text.replace('"', "\"").replace('"', "\"");
-                                                    // delta_text.push_str(&clean_thought);
+                                                    let thought = part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false);
+                                                    router.push_text_only(text, thought, &mut delta_text);
                                                 }
-                                                */
                                                 // 捕获 thoughtSignature (Gemini 3 工具调用必需)
                                                 // 存储到全局状态，不再嵌入到用户可见的文本中
                                                 if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
@@ -498,6 +540,7 @@ pub fn create_codex_sse_stream(
                                                 }
                                                 // Handle function call in chunk with deduplication
                                                 if let Some(func_call) = part.get("functionCall") {
+                                                    delta_text.push_str(router.close().unwrap_or_default());
                                                     let call_key = serde_json::to_string(func_call).unwrap_or_default();
                                                     if !emitted_tool_calls.contains(&call_key) {
                                                         emitted_tool_calls.insert(call_key);
@@ -652,6 +695,9 @@ pub fn create_codex_sse_stream(
                                                 }
                                             }
                                         }
+                                        if candidate.get("finishReason").is_some() {
+                                            delta_text.push_str(router.close().unwrap_or_default());
+                                        }
                                     }
                                 }
 
@@ -901,3 +947,63 @@ pub fn create_codex_sse_stream(
 
     Box::pin(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::{ClientCompatProfile, JsonModeConfig};
+
+    fn text_chunk(text: &str, finish_reason: Option<&str>) -> Value {
+        let mut candidate = json!({ "content": { "role": "model", "parts": [{ "text": text }] } });
+        if let Some(reason) = finish_reason {
+            candidate["finishReason"] = json!(reason);
+        }
+        json!({ "response": { "candidates": [candidate] } })
+    }
+
+    /// 将 Gemini 事件转换为 OpenAI SSE，并拼接所有 delta.content
+    async fn streamed_content(events: Vec<Value>, json_mode: Option<JsonModeConfig>) -> String {
+        let upstream = futures::stream::iter(
+            events
+                .into_iter()
+                .map(|e| Ok::<Bytes, reqwest::Error>(Bytes::from(format!("data: {}\n\n", e)))),
+        );
+        let stream = create_openai_sse_stream(
+            Box::pin(upstream),
+            "gemini-2.5-flash".to_string(),
+            json_mode,
+            ClientCompatProfile::default(),
+            Default::default(),
+            false,
+            None,
+        );
+        let output: Vec<Result<Bytes, String>> = stream.collect().await;
+        output
+            .into_iter()
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .flat_map(|chunk| chunk.lines().map(str::to_string).collect::<Vec<_>>())
+            .filter_map(|line| serde_json::from_str::<Value>(line.strip_prefix("data: ")?).ok())
+            .filter_map(|event| event["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_json_mode_strips_fences_and_repairs_truncation() {
+        let json_mode = JsonModeConfig { enabled: true, repair_truncated: true };
+        let fenced = vec![
+            text_chunk("```json\n{\"city\": ", None),
+            text_chunk("\"Paris\"}\n```", None),
+            text_chunk("\nLet me know!", Some("STOP")),
+        ];
+        let content = streamed_content(fenced, Some(json_mode.clone())).await;
+        assert_eq!(serde_json::from_str::<Value>(&content).unwrap(), json!({ "city": "Paris" }));
+
+        let truncated = || vec![text_chunk("{\"items\": [\"a\", \"b", None), text_chunk("", Some("MAX_TOKENS"))];
+        let content = streamed_content(truncated(), Some(json_mode)).await;
+        assert_eq!(serde_json::from_str::<Value>(&content).unwrap(), json!({ "items": ["a", "b"] }));
+
+        // 未开启补全时保留截断的输出
+        let content = streamed_content(truncated(), Some(JsonModeConfig { enabled: true, repair_truncated: false })).await;
+        assert!(serde_json::from_str::<Value>(&content).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+// RECITATION 处理
+// Gemini 判断输出与训练数据中的已有内容 (代码 / 歌词 / 文章等) 过于接近时，会以 finishReason = RECITATION 提前结束，
+// 客户端拿到的是被截断的回复。这里在响应发送给客户端之前检测该原因 (非流式响应 / 流式响应在出现内容之前)，
+// 由 handler 略微提高温度并追加 "不要逐字复述" 的提示重试一次；重试仍被截断时按安全拦截处理，
+// 以 stop_reason = refusal + stop_details (type = recitation) 告知客户端。
+
+use crate::proxy::config::RecitationRetryConfig;
+use serde_json::{json, Value};
+
+pub const FINISH_REASON: &str = "RECITATION";
+
+/// Gemini 允许的最大温度
+const MAX_TEMPERATURE: f64 = 2.0;
+/// 请求未设置温度时上游使用的默认值
+const DEFAULT_TEMPERATURE: f64 = 1.0;
+
+const DEFAULT_INSTRUCTION: &str = "[System notice] Your previous response was stopped because it reproduced existing text too closely. \
+     Answer in your own words: paraphrase and summarize instead of quoting long passages verbatim, and write original code \
+     rather than reproducing well-known source files line by line.";
+
+/// 非流式响应的第一个候选结果是否以 RECITATION 结束 (无论之前是否已有内容)
+pub fn detect(response: &Value) -> bool {
+    let response = response.get("response").unwrap_or(response);
+    response
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("finishReason"))
+        .and_then(|r| r.as_str())
+        == Some(FINISH_REASON)
+}
+
+/// 重试时应用的变化：提高温度并追加提示 (作用于 v1internal 信封)
+pub fn apply_variation(envelope: &mut Value, config: &RecitationRetryConfig) {
+    if let Some(request) = envelope.get_mut("request").and_then(|r| r.as_object_mut()) {
+        let generation_config = request.entry("generationConfig").or_insert_with(|| json!({}));
+        if generation_config.is_object() {
+            let current = generation_config["temperature"].as_f64().unwrap_or(DEFAULT_TEMPERATURE);
+            let bumped = (current + config.temperature_bump.max(0.0)).min(MAX_TEMPERATURE);
+            generation_config["temperature"] = json!(bumped);
+        }
+    }
+    let instruction = if config.instruction.trim().is_empty() {
+        DEFAULT_INSTRUCTION
+    } else {
+        config.instruction.as_str()
+    };
+    crate::proxy::mappers::malformed_call::apply_hint(envelope, instruction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_recitation() {
+        let truncated = json!({ "response": { "candidates": [{
+            "content": { "parts": [{ "text": "Once upon a" }] },
+            "finishReason": "RECITATION"
+        }] } });
+        assert!(detect(&truncated));
+        assert!(!detect(&json!({ "candidates": [{ "finishReason": "STOP" }] })));
+    }
+
+    #[test]
+    fn test_apply_variation_bumps_temperature_and_adds_hint() {
+        let config = RecitationRetryConfig::default();
+        let mut envelope = json!({ "request": { "contents": [], "generationConfig": { "temperature": 0.5 } } });
+        apply_variation(&mut envelope, &config);
+        assert!((envelope["request"]["generationConfig"]["temperature"].as_f64().unwrap() - 0.7).abs() < 1e-9);
+        assert_eq!(envelope["request"]["systemInstruction"]["parts"][0]["text"], DEFAULT_INSTRUCTION);
+
+        let mut envelope = json!({ "request": { "contents": [], "generationConfig": { "temperature": 1.95 } } });
+        apply_variation(&mut envelope, &RecitationRetryConfig { instruction: "paraphrase".to_string(), ..config });
+        assert_eq!(envelope["request"]["generationConfig"]["temperature"], 2.0);
+        assert_eq!(envelope["request"]["systemInstruction"]["parts"][0]["text"], "paraphrase");
+    }
+}
@@ -0,0 +1,250 @@
+// 安全拦截解析模块
+// Gemini 在提示词被拦截 (promptFeedback.blockReason) 或候选结果被拦截 (finishReason SAFETY 等) 时
+// 只返回空内容，这里统一提取拦截原因与类别，供 Claude / OpenAI 映射为带详情的结束原因
+
+use serde_json::{json, Value};
+
+/// 表示内容被安全策略拦截的 finishReason
+const SAFETY_FINISH_REASONS: &[&str] = &[
+    "SAFETY",
+    "RECITATION",
+    "BLOCKLIST",
+    "PROHIBITED_CONTENT",
+    "SPII",
+    "IMAGE_SAFETY",
+];
+
+/// 拦截发生的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStage {
+    /// 提示词被拦截 (没有任何候选结果)
+    Prompt,
+    /// 生成的内容被拦截
+    Response,
+}
+
+/// 单个安全类别的评级
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockedCategory {
+    /// e.g. "HARM_CATEGORY_DANGEROUS_CONTENT"
+    pub category: String,
+    /// e.g. "HIGH" / "MEDIUM"
+    pub probability: Option<String>,
+    pub blocked: bool,
+}
+
+/// 安全拦截详情
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafetyBlock {
+    pub stage: BlockStage,
+    /// 上游给出的原因 (blockReason 或 finishReason)
+    pub reason: String,
+    pub categories: Vec<BlockedCategory>,
+    /// 上游附带的说明 (blockReasonMessage / finishMessage)
+    pub message: Option<String>,
+}
+
+pub fn is_safety_finish_reason(reason: &str) -> bool {
+    SAFETY_FINISH_REASONS.contains(&reason)
+}
+
+/// 从 (已解包的) Gemini 响应中检测安全拦截 (提示词拦截优先，其次第一个候选结果)
+pub fn detect_safety_block(raw: &Value) -> Option<SafetyBlock> {
+    detect_prompt_block(raw).or_else(|| detect_candidate_block(raw.get("candidates")?.get(0)?))
+}
+
+/// 检测提示词拦截 (promptFeedback.blockReason)
+pub fn detect_prompt_block(raw: &Value) -> Option<SafetyBlock> {
+    let feedback = raw.get("promptFeedback")?;
+    let reason = feedback.get("blockReason").and_then(|r| r.as_str())?;
+    Some(SafetyBlock {
+        stage: BlockStage::Prompt,
+        reason: reason.to_string(),
+        categories: parse_ratings(feedback.get("safetyRatings")),
+        message: feedback
+            .get("blockReasonMessage")
+            .and_then(|m| m.as_str())
+            .map(|s| s.to_string()),
+    })
+}
+
+/// 检测单个候选结果的拦截 (finishReason 为 SAFETY 等)
+pub fn detect_candidate_block(candidate: &Value) -> Option<SafetyBlock> {
+    let reason = candidate.get("finishReason").and_then(|r| r.as_str())?;
+    if !is_safety_finish_reason(reason) {
+        return None;
+    }
+    Some(SafetyBlock {
+        stage: BlockStage::Response,
+        reason: reason.to_string(),
+        categories: parse_ratings(candidate.get("safetyRatings")),
+        message: candidate
+            .get("finishMessage")
+            .and_then(|m| m.as_str())
+            .map(|s| s.to_string()),
+    })
+}
+
+/// 解析 safetyRatings，只保留被拦截或概率较高的类别 (都没有时保留全部)
+fn parse_ratings(ratings: Option<&Value>) -> Vec<BlockedCategory> {
+    let all: Vec<BlockedCategory> = ratings
+        .and_then(|r| r.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|r| {
+                    Some(BlockedCategory {
+                        category: r.get("category")?.as_str()?.to_string(),
+                        probability: r
+                            .get("probability")
+                            .and_then(|p| p.as_str())
+                            .map(|s| s.to_string()),
+                        blocked: r.get("blocked").and_then(|b| b.as_bool()).unwrap_or(false),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let relevant: Vec<BlockedCategory> = all
+        .iter()
+        .filter(|c| c.blocked || matches!(c.probability.as_deref(), Some("HIGH") | Some("MEDIUM")))
+        .cloned()
+        .collect();
+    if relevant.is_empty() {
+        all
+    } else {
+        relevant
+    }
+}
+
+impl SafetyBlock {
+    /// 是否因复述已有内容被截断 (RECITATION，不是内容安全类别的拦截)
+    pub fn is_recitation(&self) -> bool {
+        self.reason == crate::proxy::mappers::recitation::FINISH_REASON
+    }
+
+    /// 人类可读的说明
+    pub fn describe(&self) -> String {
+        if self.is_recitation() {
+            let mut text = "Response stopped by upstream recitation check (reason: RECITATION): the output reproduced existing text too closely and was truncated. Ask for a paraphrase or summary instead of verbatim content".to_string();
+            if let Some(msg) = &self.message {
+                text.push_str(&format!(": {}", msg));
+            }
+            return text;
+        }
+        let subject = match self.stage {
+            BlockStage::Prompt => "Prompt",
+            BlockStage::Response => "Response",
+        };
+        let mut text = format!("{} blocked by upstream safety filter (reason: {}", subject, self.reason);
+        if !self.categories.is_empty() {
+            let cats: Vec<String> = self
+                .categories
+                .iter()
+                .map(|c| match &c.probability {
+                    Some(p) => format!("{}={}", c.category, p),
+                    None => c.category.clone(),
+                })
+                .collect();
+            text.push_str(&format!("; categories: {}", cats.join(", ")));
+        }
+        text.push(')');
+        if let Some(msg) = &self.message {
+            text.push_str(&format!(": {}", msg));
+        }
+        text
+    }
+
+    /// 结构化详情 (Claude `stop_details`)
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": if self.is_recitation() { "recitation" } else { "safety" },
+            "stage": match self.stage {
+                BlockStage::Prompt => "prompt",
+                BlockStage::Response => "response",
+            },
+            "reason": self.reason,
+            "message": self.describe(),
+            "categories": self.categories.iter().map(|c| json!({
+                "category": c.category,
+                "probability": c.probability,
+                "blocked": c.blocked,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// OpenAI (Azure 风格) `content_filter_results`
+    ///
+    /// key 为去掉 HARM_CATEGORY_ 前缀的小写类别名，e.g. `dangerous_content`
+    pub fn to_openai_filter_results(&self) -> Value {
+        let mut results = serde_json::Map::new();
+        for c in &self.categories {
+            let key = c
+                .category
+                .trim_start_matches("HARM_CATEGORY_")
+                .to_lowercase();
+            results.insert(
+                key,
+                json!({
+                    "filtered": c.blocked || matches!(c.probability.as_deref(), Some("HIGH") | Some("MEDIUM")),
+                    "severity": c.probability.as_deref().unwrap_or("unknown").to_lowercase(),
+                }),
+            );
+        }
+        // 非类别型拦截 (RECITATION / BLOCKLIST 等) 也给出原因
+        results.insert(
+            "reason".to_string(),
+            json!({ "filtered": true, "detail": self.describe() }),
+        );
+        Value::Object(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_prompt_block() {
+        let raw = json!({
+            "promptFeedback": {
+                "blockReason": "SAFETY",
+                "safetyRatings": [
+                    { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true },
+                    { "category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE" }
+                ]
+            }
+        });
+        let block = detect_safety_block(&raw).unwrap();
+        assert_eq!(block.stage, BlockStage::Prompt);
+        assert_eq!(block.categories.len(), 1);
+        assert!(block.describe().contains("HARM_CATEGORY_DANGEROUS_CONTENT=HIGH"));
+
+        let filter = block.to_openai_filter_results();
+        assert_eq!(filter["dangerous_content"]["filtered"], true);
+        assert_eq!(filter["dangerous_content"]["severity"], "high");
+    }
+
+    #[test]
+    fn test_detect_candidate_block_and_ignore_normal_finish() {
+        let raw = json!({
+            "candidates": [{
+                "finishReason": "PROHIBITED_CONTENT",
+                "finishMessage": "Output blocked.",
+                "safetyRatings": [{ "category": "HARM_CATEGORY_HATE_SPEECH", "probability": "MEDIUM" }]
+            }]
+        });
+        let block = detect_safety_block(&raw).unwrap();
+        assert_eq!(block.stage, BlockStage::Response);
+        assert_eq!(block.to_json()["stage"], "response");
+        assert!(block.describe().ends_with("Output blocked."));
+
+        let recitation = json!({ "candidates": [{ "finishReason": "RECITATION" }] });
+        let block = detect_safety_block(&recitation).unwrap();
+        assert_eq!(block.to_json()["type"], "recitation");
+        assert!(block.describe().contains("paraphrase"));
+
+        let normal = json!({ "candidates": [{ "finishReason": "STOP" }] });
+        assert!(detect_safety_block(&normal).is_none());
+    }
+}
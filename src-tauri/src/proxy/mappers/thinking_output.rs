@@ -0,0 +1,94 @@
+// 思考内容输出路由
+// 各协议的流式 / 非流式转换统一通过 ThoughtRouter 决定 thought part 的去向
+// (丢弃 / 独立思考字段 / 以 <think> 标签合并到正文)，取代各适配器中分散的处理。
+// Inline 模式下 <think> 标签可能跨多个 chunk，流式响应需为每个候选持有一个 router。
+
+pub use crate::proxy::config::ThinkingOutput;
+
+pub const THINK_OPEN: &str = "<think>\n";
+pub const THINK_CLOSE: &str = "\n</think>\n\n";
+
+/// 单个 text part 的去向
+#[derive(Debug, PartialEq, Eq)]
+pub enum Routed {
+    /// 追加到正文 (可能带有开闭标签)
+    Text(String),
+    /// 追加到独立的思考字段
+    Thinking(String),
+    Dropped,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ThoughtRouter {
+    mode: ThinkingOutput,
+    inline_open: bool,
+}
+
+impl ThoughtRouter {
+    pub fn new(mode: ThinkingOutput) -> Self {
+        Self { mode, inline_open: false }
+    }
+
+    pub fn route(&mut self, text: &str, thought: bool) -> Routed {
+        if !thought {
+            let mut out = self.close().unwrap_or_default().to_string();
+            out.push_str(text);
+            return Routed::Text(out);
+        }
+        match self.mode {
+            ThinkingOutput::Drop => Routed::Dropped,
+            ThinkingOutput::Reasoning => Routed::Thinking(text.to_string()),
+            ThinkingOutput::Inline => {
+                let mut out = String::new();
+                if !self.inline_open {
+                    self.inline_open = true;
+                    out.push_str(THINK_OPEN);
+                }
+                out.push_str(text);
+                Routed::Text(out)
+            }
+        }
+    }
+
+    /// 思考之后出现非文本内容 (工具调用 / 图片) 或流结束时，闭合未闭合的 <think>
+    pub fn close(&mut self) -> Option<&'static str> {
+        std::mem::take(&mut self.inline_open).then_some(THINK_CLOSE)
+    }
+
+    /// 只有正文字段的协议 (Legacy Completions / Codex Responses)：思考内容仅在 Inline 模式下输出
+    pub fn push_text_only(&mut self, text: &str, thought: bool, content: &mut String) {
+        if let Routed::Text(out) = self.route(text, thought) {
+            content.push_str(&out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_by_mode() {
+        let mut router = ThoughtRouter::new(ThinkingOutput::Reasoning);
+        assert_eq!(router.route("plan", true), Routed::Thinking("plan".to_string()));
+        assert_eq!(router.route("answer", false), Routed::Text("answer".to_string()));
+
+        let mut router = ThoughtRouter::new(ThinkingOutput::Drop);
+        assert_eq!(router.route("plan", true), Routed::Dropped);
+        assert_eq!(router.close(), None);
+    }
+
+    #[test]
+    fn test_inline_tags_span_parts() {
+        let mut router = ThoughtRouter::new(ThinkingOutput::Inline);
+        let mut content = String::new();
+        router.push_text_only("a", true, &mut content);
+        router.push_text_only("b", true, &mut content);
+        router.push_text_only("answer", false, &mut content);
+        assert_eq!(content, "<think>\nab\n</think>\n\nanswer");
+        assert_eq!(router.close(), None);
+
+        router.push_text_only("again", true, &mut content);
+        assert_eq!(router.close(), Some(THINK_CLOSE));
+    }
+}
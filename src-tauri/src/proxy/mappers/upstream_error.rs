@@ -0,0 +1,293 @@
+// 上游错误翻译 (Upstream Error Translation)
+// Google 返回的结构化错误 (error.code / status / details[ErrorInfo, RetryInfo]) 会提及 v1internal 等内部概念，
+// 直接透传给客户端难以理解。这里按翻译表转换为可操作的提示，原始错误体保留在 debug 字段中便于排查。
+
+use serde_json::{json, Value};
+
+/// 翻译规则：所有给定条件均满足时命中 (needles 任一出现即可，不区分大小写)
+struct Rule {
+    status: Option<u16>,
+    needles: &'static [&'static str],
+    /// 额外判定 (状态码, 原始错误体)
+    check: Option<fn(u16, &str) -> bool>,
+    error_type: &'static str,
+    /// `{detail}` 替换为上游原始的 error.message
+    message: &'static str,
+}
+
+/// 按顺序匹配，先具体后笼统
+const RULES: &[Rule] = &[
+    Rule {
+        status: None,
+        needles: &["thinking.signature", "invalid `signature`", "corrupted thought signature", "thought_signature"],
+        check: None,
+        error_type: "invalid_request_error",
+        message: "Thinking signature rejected by upstream",
+    },
+    Rule {
+        status: None,
+        needles: &["quota_exhausted"],
+        check: None,
+        error_type: "rate_limit_error",
+        message: "Account quota exhausted for this model — wait for the quota reset or add more accounts",
+    },
+    Rule {
+        status: Some(429),
+        needles: &[],
+        check: None,
+        error_type: "rate_limit_error",
+        message: "Upstream rate limit reached for this account — retry shortly",
+    },
+    Rule {
+        status: None,
+        needles: &["prompt is too long", "exceeds the maximum number of tokens", "input token count"],
+        check: None,
+        error_type: "invalid_request_error",
+        message: "Prompt exceeds the model context window — shorten the conversation",
+    },
+    Rule {
+        status: None,
+        needles: &["user location is not supported"],
+        check: None,
+        error_type: "permission_error",
+        message: "Account region is not supported by the upstream — use an account from a supported region",
+    },
+    Rule {
+        status: Some(404),
+        needles: &[],
+        check: Some(is_model_unavailable),
+        error_type: "not_found_error",
+        message: "Model not available on this account tier",
+    },
+    Rule {
+        status: Some(404),
+        needles: &[],
+        check: None,
+        error_type: "not_found_error",
+        message: "Upstream resource not found: {detail}",
+    },
+    Rule {
+        status: Some(403),
+        needles: &[],
+        check: None,
+        error_type: "permission_error",
+        message: "Account lacks permission for this model — check the account tier or re-verify the account",
+    },
+    Rule {
+        status: Some(401),
+        needles: &[],
+        check: None,
+        error_type: "authentication_error",
+        message: "Account credentials expired or were revoked — re-authorize the account",
+    },
+    Rule {
+        status: Some(400),
+        needles: &[],
+        check: None,
+        error_type: "invalid_request_error",
+        message: "Upstream rejected the request: {detail}",
+    },
+    Rule {
+        status: Some(503),
+        needles: &[],
+        check: None,
+        error_type: "overloaded_error",
+        message: "Upstream model is temporarily overloaded — retry shortly",
+    },
+    Rule {
+        status: Some(529),
+        needles: &[],
+        check: None,
+        error_type: "overloaded_error",
+        message: "Upstream model is temporarily overloaded — retry shortly",
+    },
+    Rule {
+        status: Some(500),
+        needles: &[],
+        check: None,
+        error_type: "api_error",
+        message: "Upstream internal error — retry shortly",
+    },
+];
+
+/// 上游明确指出账号无权使用该模型 (而非项目 / 端点不存在)
+fn is_model_unavailable(status: u16, body: &str) -> bool {
+    crate::proxy::model_access::is_model_access_error(status, body, "")
+}
+
+/// 翻译后的上游错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslatedError {
+    pub status: u16,
+    /// Anthropic / OpenAI 风格的错误类型
+    pub error_type: &'static str,
+    pub message: String,
+    /// Google 的 error.status (INVALID_ARGUMENT / RESOURCE_EXHAUSTED ...)
+    pub google_status: Option<String>,
+    /// 原始错误体 (JSON 时保留结构)
+    pub raw: Value,
+}
+
+impl TranslatedError {
+    /// 已安排重试时在提示后附加说明 (仅在确实重试时调用)
+    pub fn note_retry(&mut self, note: &str) {
+        self.message.push_str(&format!(" — {}", note));
+    }
+
+    /// 排查用的原始信息
+    pub fn debug(&self) -> Value {
+        json!({
+            "upstream_status": self.status,
+            "google_status": self.google_status,
+            "raw_error": self.raw,
+        })
+    }
+
+    pub fn to_claude(&self) -> Value {
+        json!({
+            "type": "error",
+            "error": {
+                "type": self.error_type,
+                "message": self.message,
+                "debug": self.debug(),
+            }
+        })
+    }
+
+    pub fn to_openai(&self) -> Value {
+        json!({
+            "error": {
+                "message": self.message,
+                "type": self.error_type,
+                "code": self.google_status.as_deref().unwrap_or(self.error_type),
+                "debug": self.debug(),
+            }
+        })
+    }
+
+    /// Gemini 原生协议沿用 Google 的错误结构，仅替换 message
+    pub fn to_gemini(&self) -> Value {
+        let mut body = match &self.raw {
+            Value::Object(_) if self.raw.get("error").is_some_and(|e| e.is_object()) => self.raw.clone(),
+            _ => json!({ "error": { "code": self.status, "status": self.google_status } }),
+        };
+        body["error"]["message"] = json!(self.message);
+        body["error"]["debug"] = self.debug();
+        body
+    }
+}
+
+/// 翻译上游错误
+pub fn translate(status: u16, body: &str) -> TranslatedError {
+    let parsed: Option<Value> = serde_json::from_str(body).ok();
+    let error = parsed.as_ref().and_then(|v| v.get("error"));
+    let detail = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .unwrap_or(body)
+        .trim()
+        .to_string();
+    let google_status = error
+        .and_then(|e| e.get("status"))
+        .and_then(|s| s.as_str())
+        .map(|s| s.to_string());
+
+    // ErrorInfo.reason 也参与匹配 (如 QUOTA_EXHAUSTED / RATE_LIMIT_EXCEEDED)
+    let reasons: Vec<&str> = error
+        .and_then(|e| e.get("details"))
+        .and_then(|d| d.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.get("reason").and_then(|r| r.as_str()))
+        .collect();
+    let haystack = format!("{} {}", detail, reasons.join(" ")).to_lowercase();
+
+    let rule = RULES.iter().find(|r| {
+        r.status.is_none_or(|s| s == status)
+            && (r.needles.is_empty() || r.needles.iter().any(|n| haystack.contains(n)))
+            && r.check.is_none_or(|check| check(status, body))
+    });
+    let (error_type, mut message) = match rule {
+        Some(rule) => (rule.error_type, rule.message.replace("{detail}", &detail)),
+        None => ("api_error", detail.clone()),
+    };
+    if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(body) {
+        message.push_str(&format!(" (retry in {:.1}s)", delay_ms as f64 / 1000.0));
+    }
+
+    TranslatedError {
+        status,
+        error_type,
+        message,
+        google_status,
+        raw: parsed.unwrap_or_else(|| Value::String(body.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn google_error(code: u16, status: &str, message: &str, details: Value) -> String {
+        json!({ "error": { "code": code, "message": message, "status": status, "details": details } }).to_string()
+    }
+
+    #[test]
+    fn test_translate_table() {
+        let no_model = translate(
+            404,
+            &google_error(404, "NOT_FOUND", "Publisher Model `gemini-3-pro` was not found or your project does not have access to it.", json!([])),
+        );
+        assert_eq!(no_model.message, "Model not available on this account tier");
+        assert_eq!(no_model.error_type, "not_found_error");
+        assert_eq!(no_model.google_status.as_deref(), Some("NOT_FOUND"));
+
+        // 泛化的 404 (项目 / 端点不存在) 不归因于账号等级
+        let not_found = translate(404, &google_error(404, "NOT_FOUND", "Requested entity was not found.", json!([])));
+        assert_eq!(not_found.message, "Upstream resource not found: Requested entity was not found.");
+        assert_eq!(not_found.error_type, "not_found_error");
+
+        let signature = translate(
+            400,
+            &google_error(400, "INVALID_ARGUMENT", "messages.1.content.0.thinking.signature: Field required", json!([])),
+        );
+        assert_eq!(signature.message, "Thinking signature rejected by upstream");
+        let mut retried = signature.clone();
+        retried.note_retry("retry will use non-thinking mode");
+        assert_eq!(retried.message, "Thinking signature rejected by upstream — retry will use non-thinking mode");
+
+        let bad_request = translate(400, &google_error(400, "INVALID_ARGUMENT", "Invalid value at 'top_k'", json!([])));
+        assert_eq!(bad_request.message, "Upstream rejected the request: Invalid value at 'top_k'");
+    }
+
+    #[test]
+    fn test_quota_reason_and_retry_info() {
+        let body = google_error(
+            429,
+            "RESOURCE_EXHAUSTED",
+            "Resource has been exhausted (e.g. check quota).",
+            json!([
+                { "@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "QUOTA_EXHAUSTED" },
+                { "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "2.5s" }
+            ]),
+        );
+        let translated = translate(429, &body);
+        assert_eq!(translated.error_type, "rate_limit_error");
+        assert!(translated.message.starts_with("Account quota exhausted"));
+        assert!(translated.message.ends_with("(retry in 2.5s)"));
+        // 原始错误体保留在 debug 字段
+        assert_eq!(translated.to_claude()["error"]["debug"]["raw_error"]["error"]["status"], "RESOURCE_EXHAUSTED");
+    }
+
+    #[test]
+    fn test_non_json_body_and_gemini_shape() {
+        let translated = translate(502, "Bad Gateway");
+        assert_eq!(translated.message, "Bad Gateway");
+        assert_eq!(translated.raw, json!("Bad Gateway"));
+
+        let gemini = translate(403, &google_error(403, "PERMISSION_DENIED", "v1internal denied", json!([]))).to_gemini();
+        assert_eq!(gemini["error"]["status"], "PERMISSION_DENIED");
+        assert!(gemini["error"]["message"].as_str().unwrap().starts_with("Account lacks permission"));
+        assert_eq!(gemini["error"]["debug"]["raw_error"]["error"]["message"], "v1internal denied");
+    }
+}
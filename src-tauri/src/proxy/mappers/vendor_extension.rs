@@ -0,0 +1,117 @@
+// Gemini 扩展字段透传 (Vendor Extension Passthrough)
+// 映射器尚未建模的新 Gemini 能力 (如新的 generationConfig 字段、新的内置工具)，可由客户端通过扩展字段直接传入：
+//   - OpenAI: `extra_body.google` 或顶层 `google` (OpenAI SDK 会把 extra_body 展开到顶层)
+//   - Claude: `metadata.google`
+// 内容按 Gemini 请求结构深度合并到最终 `request` 中：对象递归合并，null 删除字段，`tools` 数组追加，其余数组整体替换。
+
+use serde_json::Value;
+
+pub const EXTENSION_KEY: &str = "google";
+
+/// 不允许通过扩展字段覆盖的请求字段 (由映射器负责生成)
+const PROTECTED_FIELDS: &[&str] = &["contents"];
+
+/// 读取 OpenAI 请求中的扩展字段
+pub fn from_openai(body: &Value) -> Option<Value> {
+    body.get("extra_body")
+        .and_then(|v| v.get(EXTENSION_KEY))
+        .or_else(|| body.get(EXTENSION_KEY))
+        .filter(|v| v.is_object())
+        .cloned()
+}
+
+/// 读取 Claude 请求中的扩展字段
+pub fn from_claude(body: &Value) -> Option<Value> {
+    body.get("metadata")
+        .and_then(|v| v.get(EXTENSION_KEY))
+        .filter(|v| v.is_object())
+        .cloned()
+}
+
+fn deep_merge(target: &mut Value, patch: &Value, key: Option<&str>) {
+    match (target, patch) {
+        (Value::Object(target_obj), Value::Object(patch_obj)) => {
+            for (k, v) in patch_obj {
+                if v.is_null() {
+                    target_obj.remove(k);
+                } else if let Some(existing) = target_obj.get_mut(k) {
+                    deep_merge(existing, v, Some(k));
+                } else {
+                    target_obj.insert(k.clone(), v.clone());
+                }
+            }
+        }
+        (Value::Array(target_arr), Value::Array(patch_arr)) if key == Some("tools") => {
+            target_arr.extend(patch_arr.iter().cloned());
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// 将扩展字段合并到 v1internal 信封的 `request` 中
+pub fn apply(envelope: &mut Value, extension: Option<&Value>) {
+    let Some(Value::Object(extension)) = extension else {
+        return;
+    };
+    let Some(request) = envelope.get_mut("request") else {
+        return;
+    };
+    for (key, value) in extension {
+        if PROTECTED_FIELDS.contains(&key.as_str()) {
+            tracing::warn!("[Vendor-Extension] Ignoring protected field `{}`", key);
+            continue;
+        }
+        let mut patch = serde_json::Map::new();
+        patch.insert(key.clone(), value.clone());
+        deep_merge(request, &Value::Object(patch), None);
+    }
+    tracing::debug!("[Vendor-Extension] Merged fields: {:?}", extension.keys().collect::<Vec<_>>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extension_sources() {
+        let nested = json!({ "extra_body": { "google": { "generationConfig": { "seed": 1 } } } });
+        assert_eq!(from_openai(&nested).unwrap()["generationConfig"]["seed"], 1);
+        let flattened = json!({ "google": { "generationConfig": { "seed": 2 } } });
+        assert_eq!(from_openai(&flattened).unwrap()["generationConfig"]["seed"], 2);
+        let claude = json!({ "metadata": { "user_id": "u", "google": { "labels": { "team": "x" } } } });
+        assert_eq!(from_claude(&claude).unwrap()["labels"]["team"], "x");
+        assert!(from_claude(&json!({ "metadata": { "user_id": "u" } })).is_none());
+        assert!(from_openai(&json!({ "google": "not an object" })).is_none());
+    }
+
+    #[test]
+    fn test_deep_merge_into_request() {
+        let mut envelope = json!({
+            "model": "gemini-2.5-flash",
+            "request": {
+                "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+                "generationConfig": { "temperature": 1.0, "topP": 1.0, "thinkingConfig": { "includeThoughts": true } },
+                "tools": [{ "functionDeclarations": [] }]
+            }
+        });
+        let extension = json!({
+            "generationConfig": { "mediaResolution": "MEDIA_RESOLUTION_LOW", "topP": null, "thinkingConfig": { "thinkingBudget": 0 } },
+            "tools": [{ "urlContext": {} }],
+            "contents": [],
+            "labels": { "team": "x" }
+        });
+        apply(&mut envelope, Some(&extension));
+
+        let request = &envelope["request"];
+        assert_eq!(request["generationConfig"]["mediaResolution"], "MEDIA_RESOLUTION_LOW");
+        assert_eq!(request["generationConfig"]["temperature"], 1.0);
+        assert!(request["generationConfig"].get("topP").is_none());
+        assert_eq!(request["generationConfig"]["thinkingConfig"], json!({ "includeThoughts": true, "thinkingBudget": 0 }));
+        assert_eq!(request["tools"].as_array().unwrap().len(), 2);
+        assert_eq!(request["tools"][1], json!({ "urlContext": {} }));
+        assert_eq!(request["contents"].as_array().unwrap().len(), 1);
+        assert_eq!(request["labels"]["team"], "x");
+        assert_eq!(envelope["model"], "gemini-2.5-flash");
+    }
+}
@@ -0,0 +1,31 @@
+// 账号统计中间件
+// 按响应头 X-Account-Email 将请求结果与响应延迟计入账号统计 (需位于 routing_info_middleware 之内，
+// 以便在路由信息头被移除之前读取)。流式响应的延迟为首个响应头到达的时间。
+
+use crate::proxy::server::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+
+pub async fn account_stats_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let response = next.run(request).await;
+    if let Some(email) = response
+        .headers()
+        .get("X-Account-Email")
+        .and_then(|v| v.to_str().ok())
+    {
+        let success = response.status().is_success();
+        state
+            .token_manager
+            .record_request_stats(email, success, start.elapsed().as_millis() as u64);
+    }
+    response
+}
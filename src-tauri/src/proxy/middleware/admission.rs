@@ -0,0 +1,86 @@
+// 准入控制中间件
+// 按 X-Priority 为转发请求排队 (见 proxy::admission)。许可随响应体一起释放：
+// 流式响应在整条流发送完毕 (或客户端断开) 之前持续占用并发名额。
+
+use crate::proxy::admission::{Priority, Rejection};
+use crate::proxy::server::AppState;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use serde_json::json;
+
+/// 只有实际转发到上游的请求参与排队
+fn is_admitted_path(method: &Method, path: &str) -> bool {
+    method == Method::POST && !path.starts_with("/internal") && !path.ends_with("/count_tokens")
+}
+
+fn rejection_response(rejection: Rejection, retry_after: u64) -> Response {
+    let response = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "overloaded_error",
+                "message": format!("{}. Please retry after {} seconds.", rejection.message(), retry_after),
+            }
+        })),
+    )
+        .into_response();
+    crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after)
+}
+
+pub async fn admission_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = state.experimental.read().await.admission.clone();
+    if !config.enabled || !is_admitted_path(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let priority = Priority::from_headers(request.headers());
+    let permit = match state.admission.acquire(priority, &config).await {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            tracing::warn!(
+                "[Admission] Rejected {} request to {}: {:?}",
+                priority.as_str(),
+                request.uri().path(),
+                rejection
+            );
+            return rejection_response(rejection, crate::proxy::common::ratelimit_headers::DEFAULT_RETRY_AFTER_SECS);
+        }
+    };
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let mut upstream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        let _permit = permit;
+        while let Some(chunk) = upstream.next().await {
+            yield chunk;
+        }
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admitted_paths() {
+        assert!(is_admitted_path(&Method::POST, "/v1/messages"));
+        assert!(is_admitted_path(&Method::POST, "/v1beta/models/gemini-2.5-flash:generateContent"));
+        assert!(!is_admitted_path(&Method::POST, "/v1/messages/count_tokens"));
+        assert!(!is_admitted_path(&Method::GET, "/v1/models"));
+        assert!(!is_admitted_path(&Method::POST, "/internal/warmup"));
+    }
+}
@@ -9,8 +9,24 @@ use axum::{
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::proxy::token_manager::with_pinned_account;
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+/// 管理端点：可读取其他客户端的请求内容 / 账号信息或影响其他客户端，
+/// 无论鉴权模式如何都要求 X-Admin-Key (未配置 admin_key 时一律拒绝)
+const ADMIN_PATHS: &[&str] = &[
+    "/internal/logging",
+    "/internal/inflight",
+    "/internal/scheduler",
+    "/internal/content-filter",
+];
+
+fn is_admin_path(path: &str) -> bool {
+    ADMIN_PATHS
+        .iter()
+        .any(|p| path.strip_prefix(p).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+}
+
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
@@ -32,15 +48,17 @@ pub async fn auth_middleware(
         return Ok(next.run(request).await);
     }
 
-    let security = security.read().await.clone();
+    // [NEW] 协议独立端口 / 路径前缀可覆盖鉴权配置
+    let scope = request.extensions().get::<crate::proxy::security::ListenerScope>().copied();
+    let security = security.read().await.resolve(scope, &path);
     let effective_mode = security.effective_auth_mode();
 
     if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
+        return run_authorized(&security, request, next).await;
     }
 
     if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
+        return run_authorized(&security, request, next).await;
     }
     
     // 从 header 中提取 API key
@@ -65,19 +83,121 @@ pub async fn auth_middleware(
     let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
 
     if authorized {
-        Ok(next.run(request).await)
+        run_authorized(&security, request, next).await
     } else {
         Err(StatusCode::UNAUTHORIZED)
     }
 }
 
+/// 通过 API Key 鉴权后的处理：管理端点额外校验 X-Admin-Key
+async fn run_authorized(
+    security: &ProxySecurityConfig,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if is_admin_path(request.uri().path()) {
+        let admin_key = request.headers().get("x-admin-key").and_then(|h| h.to_str().ok());
+        if !admin_key_matches(&security.admin_key, admin_key) {
+            tracing::warn!("Admin endpoint {} rejected: missing or invalid X-Admin-Key", request.uri().path());
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    run_with_pinned_account(security, request, next).await
+}
+
+/// [NEW] X-Account: 把请求固定到指定账号 (email 或 account_id)，绕过调度器
+/// 仅当 X-Admin-Key 与配置的 admin_key 一致时生效，否则拒绝请求 (403)，避免普通 API Key 持有者挑选账号
+async fn run_with_pinned_account(
+    security: &ProxySecurityConfig,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let pinned = request
+        .headers()
+        .get("x-account")
+        .and_then(|h| h.to_str().ok())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let Some(account) = pinned else {
+        return Ok(next.run(request).await);
+    };
+
+    let admin_key = request.headers().get("x-admin-key").and_then(|h| h.to_str().ok());
+    if !admin_key_matches(&security.admin_key, admin_key) {
+        tracing::warn!("X-Account header rejected: missing or invalid X-Admin-Key");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    tracing::info!("[X-Account] Pinning request to account {}", account);
+    Ok(with_pinned_account(Some(account), next.run(request)).await)
+}
+
+fn admin_key_matches(configured: &str, provided: Option<&str>) -> bool {
+    !configured.is_empty() && provided == Some(configured)
+}
+
 #[cfg(test)]
 mod tests {
-    // 移除未使用的 use super::*;
+    use super::*;
 
     #[test]
     fn test_auth_placeholder() {
         // Placeholder test
         assert!(true);
     }
+
+    #[test]
+    fn test_admin_key_required_for_pinning() {
+        assert!(admin_key_matches("sk-admin", Some("sk-admin")));
+        assert!(!admin_key_matches("sk-admin", Some("sk-other")));
+        assert!(!admin_key_matches("sk-admin", None));
+        // 未配置管理密钥时禁用
+        assert!(!admin_key_matches("", Some("")));
+        assert!(!admin_key_matches("", None));
+    }
+
+    async fn admin_status(security: ProxySecurityConfig, path: &str, admin_key: Option<&str>) -> StatusCode {
+        let app = axum::Router::new()
+            .fallback(|| async { "ok" })
+            .layer(axum::middleware::from_fn_with_state(Arc::new(RwLock::new(security)), auth_middleware));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        let mut request = reqwest::Client::new().get(format!("http://{}{}", addr, path));
+        if let Some(key) = admin_key {
+            request = request.header("x-admin-key", key);
+        }
+        StatusCode::from_u16(request.send().await.unwrap().status().as_u16()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints_require_admin_key() {
+        // 鉴权关闭 (Auto 且未开放局域网) 时管理端点同样要求管理密钥
+        let security = ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Auto,
+            api_key: "sk-client".to_string(),
+            admin_key: "sk-admin".to_string(),
+            allow_lan_access: false,
+            listeners: Vec::new(),
+        };
+        for path in ADMIN_PATHS {
+            assert_eq!(admin_status(security.clone(), path, None).await, StatusCode::FORBIDDEN, "{}", path);
+            assert_eq!(admin_status(security.clone(), path, Some("sk-client")).await, StatusCode::FORBIDDEN, "{}", path);
+            assert_eq!(admin_status(security.clone(), path, Some("sk-admin")).await, StatusCode::OK, "{}", path);
+        }
+        // 子路径同样受保护 (DELETE /internal/inflight/:trace_id)
+        assert_eq!(admin_status(security.clone(), "/internal/inflight/trace-1", None).await, StatusCode::FORBIDDEN);
+        assert!(!is_admin_path("/internal/inflightx"));
+        assert_eq!(admin_status(security.clone(), "/internal/scheduler/dry-run", None).await, StatusCode::FORBIDDEN);
+        assert_eq!(admin_status(security.clone(), "/internal/content-filter/audit", None).await, StatusCode::FORBIDDEN);
+        assert_eq!(admin_status(security.clone(), "/v1/models", None).await, StatusCode::OK);
+
+        // 未配置管理密钥时管理端点一律拒绝
+        let security = ProxySecurityConfig { admin_key: String::new(), ..security };
+        assert_eq!(admin_status(security, ADMIN_PATHS[0], Some("")).await, StatusCode::FORBIDDEN);
+    }
 }
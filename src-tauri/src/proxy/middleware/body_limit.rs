@@ -0,0 +1,44 @@
+// 请求体大小限制中间件
+// 按 Content-Length 提前拒绝超过 upload_limits.max_request_body_mb 的请求，返回 413 与调整建议，
+// 避免监控 / 内容过滤中间件先把整个请求体读入内存。未声明长度的请求由 DefaultBodyLimit 兜底。
+
+use crate::proxy::server::AppState;
+use crate::proxy::upload;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+pub async fn body_limit_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limits = &state.upload_limits;
+    if let Some(len) = upload::content_length(request.headers()) {
+        if len > limits.max_request_body_bytes() as u64 {
+            tracing::warn!(
+                "[Upload] Rejected {} {}: body of {} bytes exceeds {} MB",
+                request.method(),
+                request.uri().path(),
+                len,
+                limits.max_request_body_mb
+            );
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(json!({
+                    "error": {
+                        "type": "request_too_large",
+                        "message": upload::body_too_large_message(Some(len), limits),
+                    }
+                })),
+            )
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
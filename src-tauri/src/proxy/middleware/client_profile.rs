@@ -0,0 +1,22 @@
+// 客户端识别中间件
+// 识别调用方并写回请求头 X-Client-Profile 供 handler 查找兼容性配置，
+// 同时通过同名响应头回传 (监控中间件据此记录到请求日志)。
+
+use crate::proxy::client_profile::{self, HEADER_CLIENT_PROFILE};
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+
+pub async fn client_profile_middleware(mut request: Request, next: Next) -> Response {
+    let kind = client_profile::detect(request.headers(), request.uri().path());
+    let value = HeaderValue::from_static(kind.as_str());
+    tracing::debug!("[Client-Profile] Detected client: {}", kind.as_str());
+    request.headers_mut().insert(HEADER_CLIENT_PROFILE, value.clone());
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(HEADER_CLIENT_PROFILE, value);
+    response
+}
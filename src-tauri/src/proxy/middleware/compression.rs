@@ -0,0 +1,197 @@
+// 响应压缩中间件
+// 客户端声明 Accept-Encoding 时对 JSON / SSE / 文本响应进行 br 或 gzip 压缩，适用于代理部署在远程 VPS、
+// 客户端在笔记本上流式接收的场景。每个上游数据块写入后立即 flush (SYNC_FLUSH)，SSE 事件不会被压缩缓冲区攒住。
+// 必须位于监控中间件之外，监控记录的始终是未压缩的响应体。
+
+use crate::proxy::server::AppState;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        })
+    }
+}
+
+/// 按 Accept-Encoding 选择编码 (优先 br，忽略 q=0 的编码)
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let accepted = |name: &str| {
+        accept_encoding.split(',').any(|item| {
+            let mut fields = item.split(';').map(str::trim);
+            let coding = fields.next().unwrap_or_default();
+            let rejected = fields.any(|f| {
+                f.strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+            coding.eq_ignore_ascii_case(name) && !rejected
+        })
+    };
+    if accepted("br") {
+        Some(Encoding::Brotli)
+    } else if accepted("gzip") {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime == "application/json" || mime == "text/event-stream" || mime.starts_with("text/")
+}
+
+/// 流式压缩器：每个数据块写入后 flush，输出可立即发送的压缩字节
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            // 质量 5 / 窗口 22：流式场景下兼顾压缩率与 CPU
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22))),
+            Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default())),
+        }
+    }
+
+    fn compress_chunk(&mut self, data: &[u8]) -> std::io::Result<Bytes> {
+        let output = match self {
+            Encoder::Brotli(w) => {
+                w.write_all(data)?;
+                w.flush()?;
+                w.get_mut()
+            }
+            Encoder::Gzip(w) => {
+                w.write_all(data)?;
+                w.flush()?;
+                w.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    fn finish(self) -> std::io::Result<Bytes> {
+        let output = match self {
+            Encoder::Brotli(w) => w.into_inner(),
+            Encoder::Gzip(w) => w.finish()?,
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+/// 压缩响应体 (流式)
+pub fn compress_body(body: Body, encoding: Encoding) -> Body {
+    let mut upstream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        let mut encoder = Encoder::new(encoding);
+        while let Some(chunk) = upstream.next().await {
+            match chunk {
+                Ok(data) => match encoder.compress_chunk(&data) {
+                    Ok(out) if !out.is_empty() => yield Ok::<Bytes, std::io::Error>(out),
+                    Ok(_) => {}
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                },
+                Err(e) => {
+                    yield Err(std::io::Error::other(e));
+                    return;
+                }
+            }
+        }
+        yield encoder.finish();
+    };
+    Body::from_stream(stream)
+}
+
+pub async fn compression_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = state.experimental.read().await.response_compression.clone();
+    let encoding = config
+        .enabled
+        .then(|| request.headers().get(header::ACCEPT_ENCODING))
+        .flatten()
+        .and_then(|v| v.to_str().ok())
+        .and_then(negotiate);
+
+    let response = next.run(request).await;
+    let Some(encoding) = encoding else {
+        return response;
+    };
+
+    let headers = response.headers();
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let is_sse = content_type.starts_with("text/event-stream");
+    let too_small = crate::proxy::upload::content_length(headers).is_some_and(|len| len < config.min_size_bytes as u64);
+    if headers.contains_key(header::CONTENT_ENCODING) || !is_compressible(content_type) || (!is_sse && too_small) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_ENCODING, encoding.header_value());
+    parts.headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, compress_body(body, encoding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("GZIP"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn test_each_chunk_is_decodable_before_finish() {
+        // SSE：每个事件写入后即可解出，无需等待流结束
+        let mut encoder = Encoder::new(Encoding::Gzip);
+        let mut compressed = encoder.compress_chunk(b"data: first\n\n").unwrap().to_vec();
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+        decoder.write_all(&compressed).unwrap();
+        decoder.flush().unwrap();
+        assert_eq!(decoder.get_ref().as_slice(), b"data: first\n\n");
+
+        compressed.extend_from_slice(&encoder.compress_chunk(b"data: second\n\n").unwrap());
+        compressed.extend_from_slice(&encoder.finish().unwrap());
+        let mut full = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice()).read_to_string(&mut full).unwrap();
+        assert_eq!(full, "data: first\n\ndata: second\n\n");
+
+        let mut encoder = Encoder::new(Encoding::Brotli);
+        let mut compressed = encoder.compress_chunk(b"{\"ok\":true}").unwrap().to_vec();
+        compressed.extend_from_slice(&encoder.finish().unwrap());
+        let mut full = String::new();
+        brotli::Decompressor::new(compressed.as_slice(), 4096).read_to_string(&mut full).unwrap();
+        assert_eq!(full, "{\"ok\":true}");
+    }
+}
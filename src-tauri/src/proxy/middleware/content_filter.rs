@@ -0,0 +1,63 @@
+// 内容过滤中间件
+// 在请求进入协议 handler 之前按用户规则扫描请求体：命中 Block 规则返回协议对应的 403 策略错误，
+// 命中 Redact 规则则用脱敏后的请求体替换原请求体。
+
+use crate::proxy::content_filter::FilterProtocol;
+use crate::proxy::server::AppState;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::Value;
+
+pub async fn content_filter_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // multipart 上传 (图片编辑 / 音频) 不含 JSON 文本，直接放行以保持流式读取
+    if request.method() != Method::POST
+        || !state.content_filter.is_enabled().await
+        || !crate::proxy::upload::can_buffer_body(request.headers(), &state.upload_limits)
+    {
+        return next.run(request).await;
+    }
+    let path = request.uri().path().to_string();
+    let Some(protocol) = FilterProtocol::from_path(&path) else {
+        return next.run(request).await;
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, state.upload_limits.max_request_body_bytes()).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                crate::proxy::upload::body_too_large_message(None, &state.upload_limits),
+            )
+                .into_response();
+        }
+    };
+
+    // 非 JSON 请求体原样放行
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return next.run(Request::from_parts(parts, Body::from(bytes))).await;
+    };
+
+    let outcome = state.content_filter.apply(&path, &mut json).await;
+    if let Some(rule) = outcome.blocked_by() {
+        return (StatusCode::FORBIDDEN, Json(protocol.policy_error_body(rule))).into_response();
+    }
+
+    let body = if outcome.redacted() {
+        parts.headers.remove(header::CONTENT_LENGTH);
+        Body::from(serde_json::to_vec(&json).unwrap_or_else(|_| bytes.to_vec()))
+    } else {
+        Body::from(bytes)
+    };
+    next.run(Request::from_parts(parts, body)).await
+}
@@ -0,0 +1,85 @@
+// 进行中流式请求登记中间件
+// 把 text/event-stream 响应登记到 InflightRegistry (见 proxy::inflight)，统计已发送字节数，
+// 并在被强制终止时补发 SSE error 事件后结束流。需位于 routing_info_middleware 之内，
+// 以便在路由信息头被移除之前读取账号与模型。
+
+use crate::proxy::common::trace_id::HEADER_REQUEST_ID;
+use crate::proxy::inflight::{termination_event, InflightInfo};
+use crate::proxy::server::AppState;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::StreamExt;
+
+fn header_string(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+pub async fn inflight_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let trace_id = request
+        .headers()
+        .get(HEADER_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let path = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+    let is_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("text/event-stream"));
+    if !is_stream || trace_id.is_empty() {
+        return response;
+    }
+
+    let info = InflightInfo {
+        trace_id: trace_id.clone(),
+        path,
+        client: header_string(&response, crate::proxy::client_profile::HEADER_CLIENT_PROFILE),
+        model: header_string(&response, "X-Mapped-Model"),
+        account: header_string(&response, "X-Account-Email"),
+    };
+    let (guard, killed) = state.inflight.register(info);
+
+    let (parts, body) = response.into_parts();
+    let mut upstream = body.into_data_stream();
+    let stream = async_stream::stream! {
+        // 登记失败 (发送端被丢弃) 时视为永不终止
+        let killed = async move {
+            if killed.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        tokio::pin!(killed);
+        loop {
+            tokio::select! {
+                chunk = upstream.next() => {
+                    let Some(chunk) = chunk else { break };
+                    if let Ok(bytes) = &chunk {
+                        guard.add_bytes(bytes.len());
+                    }
+                    yield chunk;
+                }
+                _ = &mut killed => {
+                    yield Ok(Bytes::from(termination_event(&trace_id)));
+                    break;
+                }
+            }
+        }
+    };
+    Response::from_parts(parts, Body::from_stream(stream))
+}
@@ -1,9 +1,33 @@
 // Middleware 模块 - Axum 中间件
 
+pub mod account_stats;
+pub mod admission;
 pub mod auth;
+pub mod body_limit;
+pub mod client_profile;
+pub mod compression;
+pub mod content_filter;
 pub mod cors;
+pub mod inflight;
 pub mod logging;
 pub mod monitor;
+pub mod offline;
+pub mod panic_guard;
+pub mod rate_limit_headers;
+pub mod routing_info;
+pub mod trace_id;
 
+pub use account_stats::account_stats_middleware;
+pub use admission::admission_middleware;
 pub use auth::auth_middleware;
+pub use body_limit::body_limit_middleware;
+pub use client_profile::client_profile_middleware;
+pub use compression::compression_middleware;
+pub use content_filter::content_filter_middleware;
 pub use cors::cors_layer;
+pub use inflight::inflight_middleware;
+pub use offline::offline_middleware;
+pub use panic_guard::panic_guard_middleware;
+pub use rate_limit_headers::rate_limit_headers_middleware;
+pub use routing_info::routing_info_middleware;
+pub use trace_id::trace_id_middleware;
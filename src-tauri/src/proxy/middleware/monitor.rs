@@ -6,13 +6,25 @@ use axum::{
 };
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{ProxyRequestLog, ProxyRequestProgress};
+use crate::proxy::common::usage_heartbeat::parse_heartbeat_line;
+use crate::proxy::common::timing::RequestTiming;
 use serde_json::Value;
 use futures::StreamExt;
 
-const MAX_REQUEST_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB
 const MAX_RESPONSE_LOG_SIZE: usize = 100 * 1024 * 1024; // 100MB for image responses
 
+/// 思考 token (Claude 扩展字段 / Gemini thoughtsTokenCount / OpenAI reasoning_tokens)
+fn thinking_tokens(usage: &Value) -> Option<u32> {
+    usage.get("thinking_tokens")
+        .or(usage.get("thoughtsTokenCount"))
+        .or(usage.pointer("/completion_tokens_details/reasoning_tokens"))
+        .or(usage.pointer("/output_tokens_details/reasoning_tokens"))
+        .and_then(|v| v.as_u64())
+        .filter(|v| *v > 0)
+        .map(|v| v as u32)
+}
+
 pub async fn monitor_middleware(
     State(state): State<AppState>,
     request: Request,
@@ -40,9 +52,13 @@ pub async fn monitor_middleware(
     };
 
     let request_body_str;
-    let request = if method == "POST" {
+    // multipart 上传与超限请求不缓存请求体，交给 handler 流式读取
+    let request = if method == "POST" && !crate::proxy::upload::can_buffer_body(request.headers(), &state.upload_limits) {
+        request_body_str = Some("[Multipart / Oversized Request Data]".to_string());
+        request
+    } else if method == "POST" {
         let (parts, body) = request.into_parts();
-        match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
+        match axum::body::to_bytes(body, state.upload_limits.max_request_body_bytes()).await {
             Ok(bytes) => {
                 if model.is_none() {
                     model = serde_json::from_slice::<Value>(&bytes).ok().and_then(|v|
@@ -90,6 +106,30 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
+    // 客户端识别结果 (由 client_profile_middleware 写入)
+    let client = response
+        .headers()
+        .get(crate::proxy::client_profile::HEADER_CLIENT_PROFILE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // A/B 实验分组 (由 handler 通过 RoutingInfo 写入)
+    let experiment = response
+        .headers()
+        .get(crate::proxy::common::routing_info::HEADER_EXPERIMENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // 会话指纹 (由 handler 通过 RoutingInfo 写入)
+    let session_id = response
+        .headers()
+        .get(crate::proxy::common::routing_info::HEADER_SESSION_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // 转换 / 上游首字节耗时 (由 handler 通过 RoutingInfo 写入)，流式耗时在下方转发结束时补充
+    let timing = RequestTiming::from_headers(response.headers());
+
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
@@ -106,6 +146,11 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        thinking_tokens: None,
+        client,
+        experiment,
+        session_id,
+        timing: (!timing.is_empty()).then_some(timing),
     };
 
     if content_type.contains("text/event-stream") {
@@ -114,10 +159,24 @@ pub async fn monitor_middleware(
         let mut stream = body.into_data_stream();
         let (tx, rx) = tokio::sync::mpsc::channel(64);
         
+        let stream_start = Instant::now();
         tokio::spawn(async move {
             let mut last_few_bytes = Vec::new();
             while let Some(chunk_res) = stream.next().await {
                 if let Ok(chunk) = chunk_res {
+                    // 用量心跳 (见 common::usage_heartbeat) 转发为实时进度事件
+                    if chunk.windows(8).any(|w| w == b"\"usage\":") {
+                        for line in String::from_utf8_lossy(&chunk).lines() {
+                            if let Some(output_tokens) = parse_heartbeat_line(line) {
+                                monitor.emit_progress(&ProxyRequestProgress {
+                                    id: log.id.clone(),
+                                    url: log.url.clone(),
+                                    output_tokens,
+                                    elapsed_ms: start.elapsed().as_millis() as u64,
+                                });
+                            }
+                        }
+                    }
                     if chunk.len() > 8192 {
                         last_few_bytes = chunk.slice(chunk.len()-8192..).to_vec();
                     } else {
@@ -132,37 +191,39 @@ pub async fn monitor_middleware(
                 }
             }
             
-            if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
-                for line in full_tail.lines().rev() {
-                    if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
-                        let json_str = line.trim_start_matches("data: ").trim();
-                        if let Ok(json) = serde_json::from_str::<Value>(json_str) {
-                            // 支持 OpenAI "usage" 或 Gemini "usageMetadata"
-                            if let Some(usage) = json.get("usage").or(json.get("usageMetadata")) {
-                                log.input_tokens = usage.get("prompt_tokens")
-                                    .or(usage.get("input_tokens"))
-                                    .or(usage.get("promptTokenCount"))
-                                    .and_then(|v| v.as_u64())
-                                    .map(|v| v as u32);
-                                log.output_tokens = usage.get("completion_tokens")
-                                    .or(usage.get("output_tokens"))
-                                    .or(usage.get("candidatesTokenCount"))
+            // 尾部缓冲可能从多字节字符中间开始，按 lossy 解码避免整段丢弃
+            let full_tail = String::from_utf8_lossy(&last_few_bytes);
+            for line in full_tail.lines().rev() {
+                if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
+                    let json_str = line.trim_start_matches("data: ").trim();
+                    if let Ok(json) = serde_json::from_str::<Value>(json_str) {
+                        // 支持 OpenAI "usage" 或 Gemini "usageMetadata"
+                        if let Some(usage) = json.get("usage").or(json.get("usageMetadata")) {
+                            log.input_tokens = usage.get("prompt_tokens")
+                                .or(usage.get("input_tokens"))
+                                .or(usage.get("promptTokenCount"))
+                                .and_then(|v| v.as_u64())
+                                .map(|v| v as u32);
+                            log.output_tokens = usage.get("completion_tokens")
+                                .or(usage.get("output_tokens"))
+                                .or(usage.get("candidatesTokenCount"))
+                                .and_then(|v| v.as_u64())
+                                .map(|v| v as u32);
+                            log.thinking_tokens = thinking_tokens(usage);
+                            
+                            if log.input_tokens.is_none() && log.output_tokens.is_none() {
+                                log.output_tokens = usage.get("total_tokens")
+                                    .or(usage.get("totalTokenCount"))
                                     .and_then(|v| v.as_u64())
                                     .map(|v| v as u32);
-                                
-                                if log.input_tokens.is_none() && log.output_tokens.is_none() {
-                                    log.output_tokens = usage.get("total_tokens")
-                                        .or(usage.get("totalTokenCount"))
-                                        .and_then(|v| v.as_u64())
-                                        .map(|v| v as u32);
-                                }
-                                break;
                             }
+                            break;
                         }
                     }
                 }
             }
             
+            log.timing.get_or_insert_with(RequestTiming::default).stream_ms = Some(stream_start.elapsed().as_millis() as u64);
             if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
@@ -188,6 +249,7 @@ pub async fn monitor_middleware(
                                 .or(usage.get("candidatesTokenCount"))
                                 .and_then(|v| v.as_u64())
                                 .map(|v| v as u32);
+                            log.thinking_tokens = thinking_tokens(usage);
                                 
                             if log.input_tokens.is_none() && log.output_tokens.is_none() {
                                 log.output_tokens = usage.get("total_tokens")
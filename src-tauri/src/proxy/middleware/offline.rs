@@ -0,0 +1,106 @@
+// 离线快速失败中间件
+// 离线检测 (见 proxy::connectivity) 判定本机无法连通 Google 上游时，发往该上游的请求直接返回 503 offline_error
+// 并附带 Retry-After，而不是等待上游连接超时。离线期间的请求会触发一次限频的即时复查。
+// z.ai 透传 / MCP 等其他上游不经过 v1internal，不受离线状态影响。
+
+use crate::proxy::server::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::time::Duration;
+
+/// 只有需要访问 Google 上游的请求快速失败 (内部端点 / 本地 count_tokens / 埋点照常处理)；
+/// 启用 z.ai 分发时 Anthropic 消息请求可能发往 z.ai，同样放行
+fn is_upstream_path(method: &Method, path: &str, zai_dispatch: bool) -> bool {
+    if method != Method::POST
+        || path.starts_with("/internal")
+        || path.starts_with("/mcp/")
+        || path.contains("/event_logging")
+        || path.ends_with("/count_tokens")
+        || path.ends_with(":countTokens")
+    {
+        return false;
+    }
+    !(zai_dispatch && path.ends_with("/v1/messages"))
+}
+
+fn offline_response(retry_after: u64) -> Response {
+    let response = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "offline_error",
+                "message": format!(
+                    "Proxy is offline: the upstream cannot be reached from this machine. Check the network connection and retry after {} seconds.",
+                    retry_after
+                ),
+            }
+        })),
+    )
+        .into_response();
+    crate::proxy::common::ratelimit_headers::with_retry_hint(response, retry_after)
+}
+
+pub async fn offline_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.connectivity.is_offline() {
+        return next.run(request).await;
+    }
+    let zai_dispatch = {
+        let zai = state.zai.read().await;
+        zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off)
+    };
+    if !is_upstream_path(request.method(), request.uri().path(), zai_dispatch) {
+        return next.run(request).await;
+    }
+
+    let config = state.experimental.read().await.offline_detection.clone();
+    if !config.enabled {
+        return next.run(request).await;
+    }
+    state
+        .connectivity
+        .recheck(&state.upstream, Duration::from_millis(config.probe_timeout_ms.max(100)))
+        .await;
+    if !state.connectivity.is_offline() {
+        return next.run(request).await;
+    }
+
+    tracing::warn!("[Connectivity] Offline, rejecting request to {}", request.uri().path());
+    offline_response(config.retry_after_secs.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_paths() {
+        assert!(is_upstream_path(&Method::POST, "/v1/messages", false));
+        assert!(is_upstream_path(&Method::POST, "/v1/chat/completions", false));
+        assert!(is_upstream_path(&Method::POST, "/v1beta/models/gemini-2.5-flash:generateContent", false));
+        assert!(!is_upstream_path(&Method::POST, "/v1/messages/count_tokens", false));
+        assert!(!is_upstream_path(&Method::POST, "/internal/warmup", false));
+        assert!(!is_upstream_path(&Method::POST, "/mcp/web_reader/mcp", false));
+        assert!(!is_upstream_path(&Method::GET, "/v1/models", false));
+        // z.ai 分发开启时 Anthropic 请求可能不走 Google 上游
+        assert!(!is_upstream_path(&Method::POST, "/v1/messages", true));
+        assert!(is_upstream_path(&Method::POST, "/v1/chat/completions", true));
+    }
+
+    #[test]
+    fn test_offline_response_carries_retry_after() {
+        let response = offline_response(10);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("retry-after").unwrap(), "10");
+    }
+}
@@ -0,0 +1,185 @@
+// Panic 隔离中间件
+// handler 或协议转换 (mapper) 中的 panic 原本只会让所在的连接任务静默退出：客户端看到连接被重置，
+// 流式响应则在中途被截断，日志中也没有对应的请求记录。这里在 handler 外捕获 panic：
+// - handler 执行期间的 panic 转换为结构化的 500 响应 (携带 trace_id)
+// - 流式响应体在轮询时的 panic 补发 SSE error 事件 (同样携带 trace_id) 后结束流
+// 两者都会计入 panic 计数 (见 /status)，服务器监听循环不受影响。
+// 需位于监控中间件之内，使 panic 产生的 500 同样留有请求记录。
+
+use crate::proxy::common::trace_id::HEADER_REQUEST_ID;
+use axum::{
+    body::{Body, HttpBody as _},
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use futures::{FutureExt, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static HANDLER_PANICS: AtomicU64 = AtomicU64::new(0);
+static STREAM_PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// 进程启动以来捕获的 panic 次数
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct PanicCounts {
+    /// handler 执行期间
+    pub handler: u64,
+    /// 流式响应体输出期间
+    pub stream: u64,
+}
+
+pub fn panic_counts() -> PanicCounts {
+    PanicCounts {
+        handler: HANDLER_PANICS.load(Ordering::Relaxed),
+        stream: STREAM_PANICS.load(Ordering::Relaxed),
+    }
+}
+
+/// 提取 panic 信息 (panic!("...") 的载荷为 &str 或 String)
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+fn panic_response(trace_id: &str) -> Response {
+    let mut response = (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": format!("Internal proxy error while handling request {}. Please retry; if it persists, report this trace_id.", trace_id),
+                "trace_id": trace_id,
+            }
+        })),
+    )
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(trace_id) {
+        response.headers_mut().insert(HEADER_REQUEST_ID, value);
+    }
+    response
+}
+
+/// 流式响应中途 panic 时补发的 SSE error 事件
+fn panic_event(trace_id: &str) -> String {
+    let payload = json!({
+        "type": "error",
+        "error": {
+            "type": "api_error",
+            "message": format!("Internal proxy error while streaming request {}. The response is incomplete.", trace_id),
+            "trace_id": trace_id,
+        }
+    });
+    format!("event: error\ndata: {}\n\n", payload)
+}
+
+/// 执行 handler，panic 时返回 500
+async fn guard_handler(future: impl Future<Output = Response>, trace_id: &str) -> Response {
+    match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(response) => response,
+        Err(payload) => {
+            HANDLER_PANICS.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("[Panic-Guard] handler panicked (trace_id={}): {}", trace_id, panic_message(&*payload));
+            panic_response(trace_id)
+        }
+    }
+}
+
+/// 包装流式响应体，轮询 panic 时 (SSE 响应补发 error 事件后) 结束流
+fn guard_body(body: Body, trace_id: String, is_sse: bool) -> Body {
+    let stream = AssertUnwindSafe(body.into_data_stream()).catch_unwind();
+    let stream = async_stream::stream! {
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(chunk) => yield chunk,
+                Err(payload) => {
+                    STREAM_PANICS.fetch_add(1, Ordering::Relaxed);
+                    tracing::error!("[Panic-Guard] response stream panicked (trace_id={}): {}", trace_id, panic_message(&*payload));
+                    if is_sse {
+                        yield Ok(Bytes::from(panic_event(&trace_id)));
+                    }
+                    break;
+                }
+            }
+        }
+    };
+    Body::from_stream(stream)
+}
+
+pub async fn panic_guard_middleware(request: Request, next: Next) -> Response {
+    let trace_id = request
+        .headers()
+        .get(HEADER_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let response = guard_handler(next.run(request), &trace_id).await;
+    // 长度已知的响应体已完整生成，只有流式响应体需要在轮询时隔离
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+    let is_sse = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("text/event-stream"));
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, guard_body(body, trace_id, is_sse))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handler_panic_becomes_structured_500() {
+        let before = panic_counts().handler;
+        let response = guard_handler(
+            async {
+                if true {
+                    panic!("mapper exploded");
+                }
+                StatusCode::OK.into_response()
+            },
+            "trace-1",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[HEADER_REQUEST_ID], "trace-1");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["trace_id"], "trace-1");
+        assert!(panic_counts().handler > before);
+    }
+
+    #[tokio::test]
+    async fn test_stream_panic_emits_error_event_and_ends() {
+        let before = panic_counts().stream;
+        let upstream = futures::stream::iter(0..3).map(|i| {
+            if i == 1 {
+                panic!("chunk mapper exploded");
+            }
+            Ok::<_, std::io::Error>(Bytes::from(format!("data: {}\n\n", i)))
+        });
+        let body = guard_body(Body::from_stream(upstream), "trace-2".to_string(), true);
+        let text = String::from_utf8(axum::body::to_bytes(body, usize::MAX).await.unwrap().to_vec()).unwrap();
+        assert!(text.starts_with("data: 0\n\n"));
+        assert!(text.contains("event: error"));
+        assert!(text.contains("trace-2"));
+        assert!(!text.contains("data: 2"));
+        assert!(panic_counts().stream > before);
+    }
+}
@@ -0,0 +1,24 @@
+// 限流状态响应头中间件
+// 每个响应都附带根据账号池状态计算的 anthropic-ratelimit-* / x-ratelimit-* 头
+
+use crate::proxy::server::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+pub async fn rate_limit_headers_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if !state.experimental.read().await.rate_limit_headers {
+        return response;
+    }
+    let pool = state.token_manager.rate_limit_state();
+    let status = response.status();
+    crate::proxy::common::ratelimit_headers::apply(response.headers_mut(), status, &pool, chrono::Utc::now());
+    response
+}
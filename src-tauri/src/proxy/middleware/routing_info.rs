@@ -0,0 +1,24 @@
+// 路由信息中间件
+// 关闭 expose_routing_info 时，在监控记录完成后移除 X-Account-Email 等路由信息头；
+// 耗时头只用于写入请求日志，始终移除
+
+use crate::proxy::server::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::Ordering;
+
+pub async fn routing_info_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    crate::proxy::common::timing::strip_headers(response.headers_mut());
+    if !state.expose_routing_info.load(Ordering::Relaxed) {
+        crate::proxy::common::routing_info::strip_headers(response.headers_mut());
+    }
+    response
+}
@@ -0,0 +1,29 @@
+// 追踪 ID 中间件
+// 解析或生成追踪 ID，写回请求头供 handler 读取，在该 ID 的 span 内处理请求 (日志自动带上 trace_id)，
+// 并通过响应头 X-Request-Id (以及客户端使用的 X-Trace-Id) 回传。
+
+use crate::proxy::common::trace_id::{self, HEADER_REQUEST_ID, HEADER_TRACE_ID};
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+pub async fn trace_id_middleware(mut request: Request, next: Next) -> Response {
+    let echo_trace_header = request.headers().contains_key(HEADER_TRACE_ID);
+    let id = trace_id::resolve(request.headers());
+    let Ok(value) = HeaderValue::from_str(&id) else {
+        return next.run(request).await;
+    };
+    request.headers_mut().insert(HEADER_REQUEST_ID, value.clone());
+
+    let span = tracing::info_span!("request", trace_id = %id);
+    let mut response = next.run(request).instrument(span).await;
+    response.headers_mut().insert(HEADER_REQUEST_ID, value.clone());
+    if echo_trace_header {
+        response.headers_mut().insert(HEADER_TRACE_ID, value);
+    }
+    response
+}
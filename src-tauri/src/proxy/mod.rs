@@ -17,8 +17,37 @@ pub mod providers;         // Extra upstream providers (z.ai, etc.)
 pub mod zai_vision_mcp;    // Built-in Vision MCP server state
 pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
+pub mod ui_events;         // 监控界面事件合并推送
 pub mod rate_limit;        // 限流跟踪
+pub mod model_access;      // 账号级模型访问控制
+pub mod usage_limits;      // 每日用量上限
+pub mod quota_groups;      // 配额分组规则与分组账号绑定
+pub mod admission;         // 准入控制 (并发上限与优先级排队)
+pub mod inflight;          // 进行中的流式请求 (列表与强制终止)
+pub mod loop_guard;        // Agent 失控循环检测
+pub mod background_batch;  // 后台小请求合并
+pub mod image_jobs;        // 图片生成异步任务
+pub mod image_cache;       // 图片生成结果缓存 (磁盘 LRU)
+pub mod stored_responses;  // store=true 的补全结果存储与检索
+pub mod image_precheck;    // 图片提示词安全预检
+pub mod content_filter;    // 请求内容过滤 (WAF)
+pub mod client_profile;    // 客户端识别与兼容性配置
+pub mod upload;            // 请求体大小限制与 multipart 上传分流
+pub mod local_socket;      // 本地套接字 / 命名管道监听
+pub mod idle_warmup;       // 空闲 / 休眠唤醒后的账号与连接预热
+pub mod connectivity;      // 离线检测与快速失败
+pub mod shadow;            // 影子流量 (模型对比)
+pub mod experiments;       // A/B 路由实验
+pub mod subagent_routing;  // Claude Code 子代理模型路由
+pub mod transcript;        // 会话对话记录导出
+pub mod conformance;       // 官方 SDK 协议一致性自检
+pub mod account_stats;     // 账号使用统计
+pub mod runtime_state;     // 用量计数与限流锁定的崩溃恢复快照
+pub mod model_capabilities; // 未知目标模型的能力探测 (按账号缓存)
+pub mod feature_flags;     // 实验性功能开关注册表
+pub mod load_test;         // 压测工具 (按目标 RPS 回放录制请求)
 pub mod sticky_config;     // 粘性调度配置
+pub mod session_bindings;  // 粘性会话绑定表 (TTL / LRU)
 pub mod session_manager;   // 会话指纹管理
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
@@ -0,0 +1,219 @@
+// 账号级模型访问控制
+// 部分账号没有 gemini-3 等模型的权限，只有在实际请求时才会收到 404/403。
+// 这里记录从错误中学到的 "拒绝列表"，配合账号配额数据中的模型列表 ("允许列表")，
+// 让调度器只把某个模型的请求分配给能够服务它的账号。
+
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// 拒绝记录的有效期：过期后重新探测 (账号权限可能已开通)
+const DENIAL_TTL: Duration = Duration::from_secs(6 * 3600);
+
+/// 模型访问跟踪器 (key 为账号 email)
+pub struct ModelAccessTracker {
+    denied: DashMap<String, HashMap<String, Instant>>,
+    ttl: Duration,
+}
+
+impl Default for ModelAccessTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelAccessTracker {
+    pub fn new() -> Self {
+        Self::with_ttl(DENIAL_TTL)
+    }
+
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            denied: DashMap::new(),
+            ttl,
+        }
+    }
+
+    /// 记录账号无法访问某个模型
+    pub fn record_denied(&self, email: &str, model: &str) {
+        let model = normalize_model(model);
+        tracing::warn!(
+            "[ModelAccess] Account {} cannot serve model {}, excluding it for {}s",
+            email,
+            model,
+            self.ttl.as_secs()
+        );
+        self.denied
+            .entry(email.to_string())
+            .or_default()
+            .insert(model, Instant::now());
+    }
+
+    /// 检查账号是否已被学习为无法访问该模型
+    pub fn is_denied(&self, email: &str, model: &str) -> bool {
+        let model = normalize_model(model);
+        let Some(mut entry) = self.denied.get_mut(email) else {
+            return false;
+        };
+        match entry.get(&model) {
+            Some(at) if at.elapsed() < self.ttl => true,
+            Some(_) => {
+                entry.remove(&model);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 清除账号的拒绝记录 (例如账号重新授权后)
+    pub fn clear(&self, email: &str) -> bool {
+        self.denied.remove(email).is_some()
+    }
+
+    /// 获取账号当前有效的拒绝模型列表
+    pub fn denied_models(&self, email: &str) -> Vec<String> {
+        let mut models: Vec<String> = self
+            .denied
+            .get(email)
+            .map(|entry| {
+                entry
+                    .iter()
+                    .filter(|(_, at)| at.elapsed() < self.ttl)
+                    .map(|(m, _)| m.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        models.sort();
+        models
+    }
+}
+
+/// 明确指向模型不可用 / 无权访问的 ErrorInfo.reason
+const MODEL_ACCESS_REASONS: &[&str] = &["MODEL_NOT_FOUND", "MODEL_NOT_SUPPORTED", "MODEL_ACCESS_DENIED"];
+
+/// 判断上游错误是否表示 "该账号无权访问此模型"
+///
+/// 只认 Google 结构化错误：404 须为 NOT_FOUND、403 须为 PERMISSION_DENIED，
+/// 且 ErrorInfo.reason 属于模型类原因，或 error.message 明确提到模型。
+/// 泛化的 "Requested entity was not found" (可能是项目 / 端点问题) 与账号级封禁都不算，
+/// 避免误把账号对该模型锁定 6 小时。
+pub fn is_model_access_error(status: u16, error_text: &str, model: &str) -> bool {
+    let expected_status = match status {
+        404 => "NOT_FOUND",
+        403 => "PERMISSION_DENIED",
+        _ => return false,
+    };
+    let Ok(body) = serde_json::from_str::<serde_json::Value>(error_text) else {
+        return false;
+    };
+    let Some(error) = body.get("error") else {
+        return false;
+    };
+    if error.get("status").and_then(|s| s.as_str()) != Some(expected_status) {
+        return false;
+    }
+
+    let has_model_reason = error
+        .get("details")
+        .and_then(|d| d.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.get("reason").and_then(|r| r.as_str()))
+        .any(|reason| MODEL_ACCESS_REASONS.contains(&reason));
+    let message = error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let model = normalize_model(model);
+    has_model_reason || message.contains("model") || (!model.is_empty() && message.contains(&model))
+}
+
+/// 从账号文件的 quota.models 中提取允许的模型列表
+///
+/// 无配额数据时返回 None (表示未知，不做限制)
+pub fn allowed_models_from_quota(quota: Option<&serde_json::Value>) -> Option<HashSet<String>> {
+    let models = quota?.get("models")?.as_array()?;
+    let set: HashSet<String> = models
+        .iter()
+        .filter_map(|m| m.get("name").and_then(|n| n.as_str()))
+        .map(normalize_model)
+        .collect();
+    if set.is_empty() {
+        None
+    } else {
+        Some(set)
+    }
+}
+
+pub fn normalize_model(model: &str) -> String {
+    model.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_and_expire_denial() {
+        let tracker = ModelAccessTracker::with_ttl(Duration::from_millis(50));
+        tracker.record_denied("a@example.com", "Gemini-3-Pro-High");
+        assert!(tracker.is_denied("a@example.com", "gemini-3-pro-high"));
+        assert!(!tracker.is_denied("a@example.com", "gemini-2.5-flash"));
+        assert!(!tracker.is_denied("b@example.com", "gemini-3-pro-high"));
+        assert_eq!(tracker.denied_models("a@example.com"), vec!["gemini-3-pro-high"]);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!tracker.is_denied("a@example.com", "gemini-3-pro-high"));
+    }
+
+    #[test]
+    fn test_is_model_access_error() {
+        assert!(is_model_access_error(
+            404,
+            r#"{"error":{"code":404,"message":"Publisher Model `gemini-3-pro-high` was not found.","status":"NOT_FOUND"}}"#,
+            "gemini-3-pro-high"
+        ));
+        assert!(is_model_access_error(
+            404,
+            r#"{"error":{"code":404,"message":"Requested entity was not found.","status":"NOT_FOUND","details":[{"@type":"type.googleapis.com/google.rpc.ErrorInfo","reason":"MODEL_NOT_FOUND"}]}}"#,
+            "gemini-3-pro-high"
+        ));
+        // 泛化的 404 (项目 / 端点不存在) 与非结构化文本不锁定模型
+        assert!(!is_model_access_error(
+            404,
+            r#"{"error":{"code":404,"message":"Requested entity was not found.","status":"NOT_FOUND"}}"#,
+            "gemini-3-pro-high"
+        ));
+        assert!(!is_model_access_error(404, "404 page not found", "gemini-3-pro-high"));
+        assert!(is_model_access_error(
+            403,
+            r#"{"error":{"code":403,"message":"Permission denied on model gemini-3-pro-high","status":"PERMISSION_DENIED"}}"#,
+            "gemini-3-pro-high"
+        ));
+        // 账号级 403 (验证要求) 不是模型权限问题
+        assert!(!is_model_access_error(
+            403,
+            r#"{"error":{"code":403,"message":"Verify your account to continue.","status":"PERMISSION_DENIED"}}"#,
+            "gemini-3-pro-high"
+        ));
+        assert!(!is_model_access_error(429, "RESOURCE_EXHAUSTED", "gemini-3-pro-high"));
+    }
+
+    #[test]
+    fn test_allowed_models_from_quota() {
+        let quota = json!({
+            "models": [
+                { "name": "gemini-2.5-flash", "percentage": 100, "reset_time": "" },
+                { "name": "claude-sonnet-4-5", "percentage": 80, "reset_time": "" }
+            ]
+        });
+        let allowed = allowed_models_from_quota(Some(&quota)).unwrap();
+        assert!(allowed.contains("gemini-2.5-flash"));
+        assert!(!allowed.contains("gemini-3-pro-high"));
+
+        assert!(allowed_models_from_quota(None).is_none());
+        assert!(allowed_models_from_quota(Some(&json!({ "models": [] }))).is_none());
+    }
+}
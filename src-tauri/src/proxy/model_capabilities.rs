@@ -0,0 +1,318 @@
+// 模型能力探测 (Model Capability Probing)
+// 映射器原本按模型名判断目标模型是否支持 thinking (contains("-thinking") / starts_with("claude-"))，
+// 自定义映射或新上线的模型经常被误判：要么 thinking 被强制关闭，要么请求因不支持的字段返回 400。
+// 开启后，某个账号首次请求内置映射之外的目标模型时，在后台发起几个极小的探测请求 (先发一个基线请求确认模型可用，
+// 再分别携带 thinkingConfig / 工具声明 / systemInstruction)，2xx 视为支持、400 视为不支持，
+// 结果按 (账号, 模型) 缓存并持久化。探测不阻塞触发它的请求 (该请求仍按名称判断)，完成后的请求由各协议映射器使用探测结果。
+// 限流 / 5xx / 超时等不确定的结果不缓存 (稍后重新探测)，映射器回退到名称判断。
+
+use crate::proxy::config::CapabilityProbeConfig;
+use crate::proxy::upstream::client::UpstreamClient;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+const STORE_FILE: &str = "model_capabilities.json";
+/// 探测结果不确定后，同一 (账号, 模型) 再次探测前的等待时间 (避免每个请求都重复探测)
+const INCONCLUSIVE_RETRY: Duration = Duration::from_secs(600);
+
+/// 目标模型支持的请求特性
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ModelCapabilities {
+    pub thinking: bool,
+    pub tools: bool,
+    pub system_instruction: bool,
+}
+
+impl ModelCapabilities {
+    /// 未探测时按模型名推断
+    pub fn guess(model: &str) -> Self {
+        Self {
+            thinking: model.contains("-thinking") || model.starts_with("claude-"),
+            tools: true,
+            system_instruction: true,
+        }
+    }
+}
+
+/// 按探测结果调整已转换的请求：不支持的 thinkingConfig / 工具声明被移除，systemInstruction 并入首条用户消息
+/// (thinking 还影响历史消息的转换，Claude 映射器在构建 contents 之前另行处理)
+pub fn apply_to_request(inner_request: &mut Value, capabilities: &ModelCapabilities) {
+    let Some(obj) = inner_request.as_object_mut() else {
+        return;
+    };
+    if !capabilities.thinking {
+        if let Some(gen_config) = obj.get_mut("generationConfig").and_then(|g| g.as_object_mut()) {
+            if gen_config.remove("thinkingConfig").is_some() {
+                tracing::warn!("[Capability] Target model does not support thinking, thinkingConfig removed");
+            }
+        }
+    }
+    if !capabilities.tools && obj.remove("tools").is_some() {
+        obj.remove("toolConfig");
+        tracing::warn!("[Capability] Target model does not accept tools, tool declarations removed");
+    }
+    if !capabilities.system_instruction {
+        let Some(system) = obj.remove("systemInstruction") else {
+            return;
+        };
+        let parts = system.get("parts").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+        if let Some(first_user) = obj
+            .get_mut("contents")
+            .and_then(|c| c.as_array_mut())
+            .and_then(|contents| contents.iter_mut().find(|c| c["role"] == "user"))
+            .and_then(|c| c.get_mut("parts"))
+            .and_then(|p| p.as_array_mut())
+        {
+            first_user.splice(0..0, parts);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ProbeKind {
+    Baseline,
+    Thinking,
+    Tools,
+    SystemInstruction,
+}
+
+fn probe_body(kind: ProbeKind, project_id: &str, model: &str) -> Value {
+    let mut request = json!({
+        "contents": [{ "role": "user", "parts": [{ "text": "Reply with OK." }] }],
+        "generationConfig": { "maxOutputTokens": 1 }
+    });
+    match kind {
+        ProbeKind::Baseline => {}
+        ProbeKind::Thinking => {
+            request["generationConfig"] = json!({
+                "maxOutputTokens": 256,
+                "thinkingConfig": { "includeThoughts": true, "thinkingBudget": 128 }
+            });
+        }
+        ProbeKind::Tools => {
+            request["tools"] = json!([{ "functionDeclarations": [{ "name": "capability_probe", "description": "Capability probe" }] }]);
+        }
+        ProbeKind::SystemInstruction => {
+            request["systemInstruction"] = json!({ "role": "user", "parts": [{ "text": "You are a capability probe." }] });
+        }
+    }
+    json!({
+        "project": project_id,
+        "requestId": format!("probe-{}", uuid::Uuid::new_v4()),
+        "request": request,
+        "model": model,
+        "userAgent": "antigravity",
+        "requestType": "agent",
+    })
+}
+
+/// 2xx 支持、400 不支持，其余 (限流 / 5xx / 网络错误) 不确定
+fn verdict(status: Option<u16>) -> Option<bool> {
+    match status? {
+        200..=299 => Some(true),
+        400 => Some(false),
+        _ => None,
+    }
+}
+
+async fn probe_one(upstream: &UpstreamClient, access_token: &str, project_id: &str, model: &str, kind: ProbeKind, timeout: Duration) -> Option<bool> {
+    let body = probe_body(kind, project_id, model);
+    let status = upstream
+        .call_v1_internal_with_timeout("generateContent", access_token, body, None, Some(timeout))
+        .await
+        .ok()
+        .map(|r| r.status().as_u16());
+    tracing::debug!("[Capability] Probe {:?} for {} -> {:?}", kind, model, status);
+    verdict(status)
+}
+
+/// 探测单个模型，任一结果不确定时返回 None
+pub async fn probe(upstream: &UpstreamClient, access_token: &str, project_id: &str, model: &str, timeout: Duration) -> Option<ModelCapabilities> {
+    // 基线请求失败说明模型本身不可用 (或账号无权限)，此时的 400 不能说明特性不受支持
+    if probe_one(upstream, access_token, project_id, model, ProbeKind::Baseline, timeout).await != Some(true) {
+        return None;
+    }
+    let (thinking, tools, system_instruction) = futures::join!(
+        probe_one(upstream, access_token, project_id, model, ProbeKind::Thinking, timeout),
+        probe_one(upstream, access_token, project_id, model, ProbeKind::Tools, timeout),
+        probe_one(upstream, access_token, project_id, model, ProbeKind::SystemInstruction, timeout),
+    );
+    Some(ModelCapabilities {
+        thinking: thinking?,
+        tools: tools?,
+        system_instruction: system_instruction?,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedCapabilities {
+    capabilities: ModelCapabilities,
+    /// 探测时间 (unix 秒)
+    probed_at: i64,
+}
+
+/// 按 (账号, 模型) 缓存的探测结果
+pub struct ModelCapabilityStore {
+    path: PathBuf,
+    entries: RwLock<HashMap<String, CachedCapabilities>>,
+    /// 正在探测或最近探测结果不确定的 (账号, 模型) -> 允许再次探测的时间；
+    /// 并发的首个请求只探测一次，其余请求直接使用名称判断
+    pending: Mutex<HashMap<String, Instant>>,
+    /// 串行化持久化写入，保证最后落盘的是最新快照
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+fn entry_key(account: &str, model: &str) -> String {
+    format!("{}|{}", account, model)
+}
+
+impl ModelCapabilityStore {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(STORE_FILE);
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: RwLock::new(entries),
+            pending: Mutex::new(HashMap::new()),
+            persist_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// 未过期的缓存结果
+    pub fn get(&self, account: &str, model: &str, ttl_hours: u64) -> Option<ModelCapabilities> {
+        let entries = self.entries.read().unwrap();
+        let cached = entries.get(&entry_key(account, model))?;
+        let age = chrono::Utc::now().timestamp() - cached.probed_at;
+        (age < (ttl_hours * 3600) as i64).then_some(cached.capabilities)
+    }
+
+    /// 任一账号的探测结果 (模型详情接口展示用)
+    pub fn any_account(&self, model: &str) -> Option<ModelCapabilities> {
+        let suffix = format!("|{}", model);
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.ends_with(&suffix))
+            .max_by_key(|(_, cached)| cached.probed_at)
+            .map(|(_, cached)| cached.capabilities)
+    }
+
+    /// 写入探测结果并在阻塞线程池中落盘 (write_atomic 含 fsync，不占用异步工作线程)
+    async fn insert(&self, account: &str, model: &str, capabilities: ModelCapabilities) {
+        let _guard = self.persist_lock.lock().await;
+        let snapshot = {
+            let mut entries = self.entries.write().unwrap();
+            entries.insert(
+                entry_key(account, model),
+                CachedCapabilities { capabilities, probed_at: chrono::Utc::now().timestamp() },
+            );
+            serde_json::to_vec_pretty(&*entries)
+        };
+        let Ok(data) = snapshot else {
+            return;
+        };
+        let path = self.path.clone();
+        let result = tokio::task::spawn_blocking(move || crate::proxy::runtime_state::write_atomic(&path, &data))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        if let Err(e) = result {
+            tracing::warn!("[Capability] Failed to persist probe results: {}", e);
+        }
+    }
+
+    /// 当前请求的目标模型能力：内置模型、关闭探测或尚无探测结果时返回 None (映射器按模型名判断)；
+    /// 未缓存时用当前账号在后台探测一次，不阻塞当前请求
+    pub fn resolve(
+        self: &Arc<Self>,
+        config: &CapabilityProbeConfig,
+        upstream: &Arc<UpstreamClient>,
+        access_token: &str,
+        project_id: &str,
+        account: &str,
+        model: &str,
+    ) -> Option<ModelCapabilities> {
+        if !config.enabled || crate::proxy::common::model_mapping::is_builtin_target(model) {
+            return None;
+        }
+        if let Some(capabilities) = self.get(account, model, config.ttl_hours) {
+            return Some(capabilities);
+        }
+        let key = entry_key(account, model);
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.get(&key).is_some_and(|until| *until > Instant::now()) {
+                return None;
+            }
+            // 基线 + 并发探测最多耗时两个超时周期
+            pending.insert(key.clone(), Instant::now() + timeout * 2);
+        }
+        let store = self.clone();
+        let upstream = upstream.clone();
+        let (access_token, project_id, account, model) =
+            (access_token.to_string(), project_id.to_string(), account.to_string(), model.to_string());
+        tokio::spawn(async move {
+            match probe(&upstream, &access_token, &project_id, &model, timeout).await {
+                Some(capabilities) => {
+                    tracing::info!("[Capability] Probed {} on {}: {:?}", model, account, capabilities);
+                    store.insert(&account, &model, capabilities).await;
+                    store.pending.lock().unwrap().remove(&key);
+                }
+                None => {
+                    tracing::info!("[Capability] Probe for {} on {} was inconclusive, using name heuristics", model, account);
+                    store.pending.lock().unwrap().insert(key, Instant::now() + INCONCLUSIVE_RETRY);
+                }
+            }
+        });
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verdict_and_store_roundtrip() {
+        assert_eq!(verdict(Some(200)), Some(true));
+        assert_eq!(verdict(Some(400)), Some(false));
+        assert_eq!(verdict(Some(429)), None);
+        assert_eq!(verdict(None), None);
+
+        let dir = std::env::temp_dir().join(format!("model_caps_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let caps = ModelCapabilities { thinking: true, tools: false, system_instruction: true };
+        ModelCapabilityStore::load(&dir).insert("a@example.com", "gemini-next", caps).await;
+
+        let store = ModelCapabilityStore::load(&dir);
+        assert_eq!(store.get("a@example.com", "gemini-next", 1), Some(caps));
+        assert_eq!(store.get("b@example.com", "gemini-next", 1), None);
+        assert_eq!(store.get("a@example.com", "gemini-next", 0), None);
+        assert_eq!(store.any_account("gemini-next"), Some(caps));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_apply_to_request_moves_system_and_drops_tools() {
+        let mut request = json!({
+            "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+            "systemInstruction": { "role": "user", "parts": [{ "text": "be brief" }] },
+            "tools": [{ "functionDeclarations": [] }],
+            "toolConfig": {},
+            "generationConfig": { "maxOutputTokens": 8, "thinkingConfig": { "includeThoughts": true } }
+        });
+        apply_to_request(&mut request, &ModelCapabilities { thinking: false, tools: false, system_instruction: false });
+        assert!(request.get("tools").is_none() && request.get("toolConfig").is_none());
+        assert_eq!(request["generationConfig"], json!({ "maxOutputTokens": 8 }));
+        assert!(request.get("systemInstruction").is_none());
+        assert_eq!(request["contents"][0]["parts"], json!([{ "text": "be brief" }, { "text": "hi" }]));
+    }
+}
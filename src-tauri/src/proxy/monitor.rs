@@ -3,6 +3,8 @@ use std::collections::VecDeque;
 use tokio::sync::RwLock;
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::proxy::ui_events::UiEventBatcher;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
@@ -20,6 +22,25 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    #[serde(default)]
+    pub thinking_tokens: Option<u32>, // 思考消耗的 token (不计入 output_tokens)
+    #[serde(default)]
+    pub client: Option<String>, // 识别出的调用方 (claude_code / cline / ...)
+    #[serde(default)]
+    pub experiment: Option<String>, // A/B 实验分组标签 (实验名:分组)
+    #[serde(default)]
+    pub session_id: Option<String>, // 会话指纹 (用于导出对话记录)
+    #[serde(default)]
+    pub timing: Option<crate::proxy::common::timing::RequestTiming>, // 耗时分解 (转换 / 上游首字节 / 流式传输)
+}
+
+/// 流式请求的实时进度 (由用量心跳驱动，id 与请求完成后的日志一致)
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyRequestProgress {
+    pub id: String,
+    pub url: String,
+    pub output_tokens: u32,
+    pub elapsed_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -35,6 +56,8 @@ pub struct ProxyMonitor {
     pub max_logs: usize,
     pub enabled: AtomicBool,
     app_handle: Option<tauri::AppHandle>,
+    /// 推送给监控界面的事件 (合并批量发送，见 ui_events)
+    ui_events: Option<Arc<UiEventBatcher>>,
 }
 
 impl ProxyMonitor {
@@ -58,12 +81,31 @@ impl ProxyMonitor {
             }
         });
 
+        let ui_events = app_handle.clone().map(|app| {
+            UiEventBatcher::new(Arc::new(move |event: &'static str, payload: serde_json::Value| {
+                let _ = app.emit(event, payload);
+            }))
+        });
+
         Self {
             logs: RwLock::new(VecDeque::with_capacity(max_logs)),
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
             app_handle,
+            ui_events,
+        }
+    }
+
+    /// Tauri 句柄 (供其他模块推送事件)
+    pub fn app_handle(&self) -> Option<tauri::AppHandle> {
+        self.app_handle.clone()
+    }
+
+    /// 应用监控界面事件推送配置 (服务启动及实验性配置热更新时调用)
+    pub fn configure_ui_events(&self, config: crate::proxy::config::UiEventConfig) {
+        if let Some(events) = &self.ui_events {
+            events.configure(config);
         }
     }
 
@@ -80,8 +122,8 @@ impl ProxyMonitor {
             return;
         }
         tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
-        // Update stats
-        {
+        // Update stats (影子请求只记录日志，不计入统计)
+        if log.method != crate::proxy::shadow::SHADOW_METHOD {
             let mut stats = self.stats.write().await;
             stats.total_requests += 1;
             if log.status >= 200 && log.status < 400 {
@@ -108,9 +150,19 @@ impl ProxyMonitor {
             }
         });
 
-        // Emit event
-        if let Some(app) = &self.app_handle {
-             let _ = app.emit("proxy://request", &log);
+        // Emit event (合并批量推送)
+        if let Some(events) = &self.ui_events {
+            events.push_log(&log);
+        }
+    }
+
+    /// 推送流式请求的实时进度
+    pub fn emit_progress(&self, progress: &ProxyRequestProgress) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some(events) = &self.ui_events {
+            events.push_progress(progress);
         }
     }
 
@@ -78,7 +78,8 @@ fn copy_passthrough_headers(incoming: &HeaderMap) -> HeaderMap {
                 out.insert(k.clone(), v.clone());
             }
             // Some clients use these for streaming; safe to pass through.
-            "accept-encoding" | "cache-control" => {
+            // accept-encoding 不转发：由 reqwest 协商并透明解压 (转发后 reqwest 不再解压，客户端会收到无标记的压缩字节)
+            "cache-control" => {
                 out.insert(k.clone(), v.clone());
             }
             _ => {}
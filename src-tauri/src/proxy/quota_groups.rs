@@ -0,0 +1,219 @@
+// 配额分组 (Quota Group)
+// 内置请求类型 agent / web_search / image_gen 由 resolve_request_config 决定，同时作为上游 requestType 发送，不能随意改动。
+// 这里在其之上按用户规则 (模型 / 请求类型 / 工具 / 客户端) 为调度器计算分组：
+// 分组可绑定专用账号 (只在这些账号中调度)，也可独占账号 (其他分组不再使用)，
+// 例如把 embedding 流量隔离到指定账号。未启用或没有规则命中时，分组即内置请求类型。
+
+use crate::proxy::common::model_mapping::wildcard_match;
+use crate::proxy::config::{QuotaGroupConfig, QuotaGroupRule};
+use axum::http::HeaderMap;
+use serde_json::Value;
+use std::sync::RwLock;
+
+/// 参与分组匹配的请求特征
+pub struct QuotaGroupRequest<'a> {
+    /// 内置请求类型 (agent / web_search / image_gen)
+    pub request_type: &'a str,
+    /// 原始模型名与映射后的模型名
+    pub models: &'a [&'a str],
+    pub tools: Option<&'a [Value]>,
+    /// 客户端标识 (见 client_profile::ClientKind)
+    pub client: Option<&'a str>,
+}
+
+/// 读取客户端识别中间件写回的客户端标识
+pub fn client_from_headers(headers: &HeaderMap) -> &'static str {
+    crate::proxy::client_profile::detect(headers, "").as_str()
+}
+
+/// 工具定义中的函数名 (兼容 Anthropic / OpenAI / Gemini 三种格式)
+fn tool_names(tools: &[Value]) -> Vec<&str> {
+    let mut names = Vec::new();
+    for tool in tools {
+        if let Some(name) = tool["name"].as_str().or_else(|| tool["function"]["name"].as_str()) {
+            names.push(name);
+        }
+        let declarations = tool
+            .get("functionDeclarations")
+            .or_else(|| tool.get("function_declarations"))
+            .and_then(|d| d.as_array());
+        names.extend(declarations.into_iter().flatten().filter_map(|d| d["name"].as_str()));
+    }
+    names
+}
+
+fn rule_matches(rule: &QuotaGroupRule, request: &QuotaGroupRequest) -> bool {
+    if !rule.models.is_empty()
+        && !rule.models.iter().any(|p| request.models.iter().any(|m| wildcard_match(p, m)))
+    {
+        return false;
+    }
+    if !rule.request_types.is_empty() && !rule.request_types.iter().any(|t| t == request.request_type) {
+        return false;
+    }
+    let tools = request.tools.unwrap_or_default();
+    if rule.has_tools.is_some_and(|has| has == tools.is_empty()) {
+        return false;
+    }
+    if !rule.tools.is_empty() {
+        let names = tool_names(tools);
+        if !rule.tools.iter().any(|p| names.iter().any(|n| wildcard_match(p, n))) {
+            return false;
+        }
+    }
+    if !rule.clients.is_empty() && !request.client.is_some_and(|c| rule.clients.iter().any(|r| r == c)) {
+        return false;
+    }
+    true
+}
+
+/// 分组可使用的账号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountFilter {
+    Any,
+    /// 分组绑定的账号 (email 或 account_id)
+    Only(Vec<String>),
+    /// 被其他分组独占的账号
+    Exclude(Vec<String>),
+}
+
+impl AccountFilter {
+    fn contains(list: &[String], account_id: &str, email: &str) -> bool {
+        list.iter().any(|a| a == account_id || a.eq_ignore_ascii_case(email))
+    }
+
+    pub fn allows(&self, account_id: &str, email: &str) -> bool {
+        match self {
+            AccountFilter::Any => true,
+            AccountFilter::Only(list) => Self::contains(list, account_id, email),
+            AccountFilter::Exclude(list) => !Self::contains(list, account_id, email),
+        }
+    }
+}
+
+/// 配额分组规则 (可热更新)
+#[derive(Default)]
+pub struct QuotaGroups {
+    config: RwLock<QuotaGroupConfig>,
+}
+
+impl QuotaGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update_config(&self, config: QuotaGroupConfig) {
+        if let Ok(mut current) = self.config.write() {
+            *current = config;
+        }
+    }
+
+    /// 计算请求所属的分组：第一条命中的规则生效，否则为内置请求类型
+    pub fn resolve(&self, request: &QuotaGroupRequest) -> String {
+        let Ok(config) = self.config.read() else {
+            return request.request_type.to_string();
+        };
+        if !config.enabled {
+            return request.request_type.to_string();
+        }
+        config
+            .rules
+            .iter()
+            .filter(|rule| !rule.group.trim().is_empty())
+            .find(|rule| rule_matches(rule, request))
+            .map(|rule| rule.group.trim().to_string())
+            .unwrap_or_else(|| request.request_type.to_string())
+    }
+
+    /// 分组可使用的账号：有绑定账号的分组只用绑定账号，否则排除被其他分组独占的账号
+    pub fn account_filter(&self, group: &str) -> AccountFilter {
+        let Ok(config) = self.config.read() else {
+            return AccountFilter::Any;
+        };
+        if !config.enabled {
+            return AccountFilter::Any;
+        }
+        if let Some(bound) = config.groups.iter().find(|g| g.group == group && !g.accounts.is_empty()) {
+            return AccountFilter::Only(bound.accounts.clone());
+        }
+        let reserved: Vec<String> = config
+            .groups
+            .iter()
+            .filter(|g| g.exclusive && g.group != group)
+            .flat_map(|g| g.accounts.iter().cloned())
+            .collect();
+        if reserved.is_empty() {
+            AccountFilter::Any
+        } else {
+            AccountFilter::Exclude(reserved)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::QuotaGroupAccounts;
+    use serde_json::json;
+
+    fn groups() -> QuotaGroups {
+        let groups = QuotaGroups::new();
+        groups.update_config(QuotaGroupConfig {
+            enabled: true,
+            rules: vec![
+                QuotaGroupRule { group: "embedding".into(), models: vec!["text-embedding-*".into()], ..Default::default() },
+                QuotaGroupRule { group: "mcp".into(), tools: vec!["mcp__*".into()], ..Default::default() },
+                QuotaGroupRule {
+                    group: "cline-chat".into(),
+                    clients: vec!["cline".into()],
+                    has_tools: Some(false),
+                    ..Default::default()
+                },
+            ],
+            groups: vec![
+                QuotaGroupAccounts { group: "embedding".into(), accounts: vec!["embed@example.com".into()], exclusive: true },
+                QuotaGroupAccounts { group: "mcp".into(), accounts: vec![], exclusive: false },
+            ],
+        });
+        groups
+    }
+
+    #[test]
+    fn test_rules_match_in_order() {
+        let groups = groups();
+        let request = |models: &'static [&'static str], tools: Option<&'static [Value]>, client| QuotaGroupRequest {
+            request_type: "agent",
+            models,
+            tools,
+            client,
+        };
+        assert_eq!(groups.resolve(&request(&["text-embedding-004"], None, None)), "embedding");
+        assert_eq!(groups.resolve(&request(&["gemini-2.5-flash"], None, Some("cline"))), "cline-chat");
+        assert_eq!(groups.resolve(&request(&["gemini-2.5-flash"], None, Some("claude_code"))), "agent");
+
+        let tools = vec![
+            json!({ "name": "Read" }),
+            json!({ "type": "function", "function": { "name": "mcp__github__search" } }),
+        ];
+        let request = QuotaGroupRequest { request_type: "agent", models: &["claude-sonnet-4-5"], tools: Some(&tools), client: Some("cline") };
+        assert_eq!(groups.resolve(&request), "mcp");
+
+        let gemini_tools = vec![json!({ "functionDeclarations": [{ "name": "lookup" }] })];
+        let request = QuotaGroupRequest { request_type: "agent", models: &["gemini-2.5-flash"], tools: Some(&gemini_tools), client: Some("cline") };
+        assert_eq!(groups.resolve(&request), "agent");
+    }
+
+    #[test]
+    fn test_account_filters() {
+        let groups = groups();
+        assert_eq!(groups.account_filter("embedding"), AccountFilter::Only(vec!["embed@example.com".into()]));
+        let other = groups.account_filter("agent");
+        assert!(!other.allows("acc-9", "Embed@example.com"));
+        assert!(other.allows("acc-1", "user1@example.com"));
+
+        groups.update_config(QuotaGroupConfig::default());
+        assert_eq!(groups.account_filter("embedding"), AccountFilter::Any);
+        let request = QuotaGroupRequest { request_type: "web_search", models: &["text-embedding-004"], tools: None, client: None };
+        assert_eq!(groups.resolve(&request), "web_search");
+    }
+}
@@ -1,9 +1,13 @@
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::time::{SystemTime, Duration};
 use regex::Regex;
 
 /// 限流原因类型
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RateLimitReason {
     /// 配额耗尽 (QUOTA_EXHAUSTED)
     QuotaExhausted,
@@ -43,13 +47,29 @@ pub struct RateLimitTracker {
     limits: DashMap<String, RateLimitInfo>,
     /// 连续失败计数（用于智能指数退避）
     failure_counts: DashMap<String, u32>,
+    /// 设置锁定后调用 (通知快照任务持久化，见 runtime_state；回调不得阻塞)
+    lockout_hook: OnceLock<LockoutHook>,
 }
 
+pub type LockoutHook = Box<dyn Fn() + Send + Sync>;
+
 impl RateLimitTracker {
     pub fn new() -> Self {
         Self {
             limits: DashMap::new(),
             failure_counts: DashMap::new(),
+            lockout_hook: OnceLock::new(),
+        }
+    }
+
+    /// 注册锁定回调 (仅首次注册生效)
+    pub fn set_lockout_hook(&self, hook: LockoutHook) {
+        let _ = self.lockout_hook.set(hook);
+    }
+
+    fn notify_lockout(&self) {
+        if let Some(hook) = self.lockout_hook.get() {
+            hook();
         }
     }
     
@@ -99,6 +119,7 @@ impl RateLimitTracker {
         };
         
         self.limits.insert(account_id.to_string(), info);
+        self.notify_lockout();
         
         if let Some(m) = &model {
             tracing::info!(
@@ -256,6 +277,7 @@ impl RateLimitTracker {
         
         // 存储
         self.limits.insert(account_id.to_string(), info.clone());
+        self.notify_lockout();
         
         tracing::warn!(
             "账号 {} [{}] 限流类型: {:?}, 重置延时: {}秒",
@@ -486,6 +508,36 @@ impl RateLimitTracker {
         count
     }
     
+    /// 未过期的限流记录 (用于持久化)
+    pub fn active_limits(&self) -> Vec<(String, RateLimitInfo)> {
+        let now = SystemTime::now();
+        self.limits
+            .iter()
+            .filter(|e| e.reset_time > now)
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect()
+    }
+
+    /// 连续失败计数 (用于持久化)
+    pub fn failure_counts(&self) -> HashMap<String, u32> {
+        self.failure_counts
+            .iter()
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect()
+    }
+
+    /// 从快照恢复限流记录 (不触发锁定回调)
+    pub fn restore(&self, account_id: &str, info: RateLimitInfo) {
+        self.limits.insert(account_id.to_string(), info);
+    }
+
+    /// 从快照恢复连续失败计数
+    pub fn restore_failure_counts(&self, counts: HashMap<String, u32>) {
+        for (account_id, count) in counts {
+            self.failure_counts.insert(account_id, count);
+        }
+    }
+
     /// 清除指定账号的限流记录
     #[allow(dead_code)]
     pub fn clear(&self, account_id: &str) -> bool {
@@ -0,0 +1,325 @@
+// 运行时状态快照 (Crash-Safe Runtime State)
+// 每日用量计数与限流锁定只存在于内存中，桌面应用崩溃或被强制退出后全部丢失：
+// 已锁定的账号会被立即重新调度并再次触发 429，仪表盘的用量也从零开始。
+// 这里把两者写入数据目录下的 runtime_state.json (sidecar 文件)：
+// - 定期快照 (内容未变化时跳过写入)
+// - 设置锁定时通知快照任务，短时间内的多次锁定合并为一次写入，在阻塞线程池中执行 (不阻塞异步 worker)
+// - 启动时恢复，已过期的锁定与用量周期直接丢弃
+// 所有写入先写临时文件并 fsync 后再 rename，进程中途退出不会留下半个文件。
+
+use crate::proxy::rate_limit::{RateLimitInfo, RateLimitReason, RateLimitTracker};
+use crate::proxy::usage_limits::{DailyUsage, UsageLimiter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const STATE_FILE: &str = "runtime_state.json";
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+/// 收到锁定通知后的合并窗口
+const LOCKOUT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// 持久化的限流锁定
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedLockout {
+    /// 锁定解除时间 (unix 毫秒)
+    pub reset_at_ms: i64,
+    /// 检测到限流的时间 (unix 毫秒)
+    pub detected_at_ms: i64,
+    pub reason: RateLimitReason,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// runtime_state.json 的内容
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeSnapshot {
+    /// 每日用量 (email -> 当前周期用量)
+    #[serde(default)]
+    pub usage: HashMap<String, DailyUsage>,
+    /// 限流锁定 (account_id -> 锁定信息)
+    #[serde(default)]
+    pub lockouts: HashMap<String, PersistedLockout>,
+    /// 连续失败计数 (决定下一次配额耗尽的退避时长)
+    #[serde(default)]
+    pub failure_counts: HashMap<String, u32>,
+}
+
+impl RuntimeSnapshot {
+    /// 采集当前内存状态 (仅包含未过期的条目)
+    pub fn capture(tracker: &RateLimitTracker, limiter: &UsageLimiter) -> Self {
+        let lockouts = tracker
+            .active_limits()
+            .into_iter()
+            .map(|(account_id, info)| {
+                let lockout = PersistedLockout {
+                    reset_at_ms: to_unix_ms(info.reset_time),
+                    detected_at_ms: to_unix_ms(info.detected_at),
+                    reason: info.reason,
+                    model: info.model,
+                };
+                (account_id, lockout)
+            })
+            .collect();
+        Self {
+            usage: limiter.export(),
+            lockouts,
+            failure_counts: tracker.failure_counts(),
+        }
+    }
+
+    /// 恢复到内存，返回 (恢复的用量条数, 恢复的锁定条数)
+    pub fn restore(self, tracker: &RateLimitTracker, limiter: &UsageLimiter) -> (usize, usize) {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut lockouts = 0;
+        for (account_id, lockout) in self.lockouts {
+            if lockout.reset_at_ms <= now_ms {
+                continue;
+            }
+            let reset_time = from_unix_ms(lockout.reset_at_ms);
+            tracker.restore(
+                &account_id,
+                RateLimitInfo {
+                    reset_time,
+                    retry_after_sec: ((lockout.reset_at_ms - now_ms) / 1000) as u64,
+                    detected_at: from_unix_ms(lockout.detected_at_ms),
+                    reason: lockout.reason,
+                    model: lockout.model,
+                },
+            );
+            lockouts += 1;
+        }
+        tracker.restore_failure_counts(self.failure_counts);
+        (limiter.restore(self.usage), lockouts)
+    }
+}
+
+fn to_unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+fn from_unix_ms(ms: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms.max(0) as u64)
+}
+
+/// 快照文件存储
+pub struct RuntimeStateStore {
+    path: PathBuf,
+    /// 最近一次写入的内容 (同时串行化并发写入)
+    last_saved: Mutex<Option<RuntimeSnapshot>>,
+}
+
+impl RuntimeStateStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join(STATE_FILE),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    /// 读取快照 (文件不存在或损坏时返回空快照)
+    pub fn load(&self) -> RuntimeSnapshot {
+        let Ok(content) = std::fs::read_to_string(&self.path) else {
+            return RuntimeSnapshot::default();
+        };
+        match serde_json::from_str(&content) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::warn!("[Runtime-State] 快照文件损坏，已忽略: {} ({})", self.path.display(), e);
+                RuntimeSnapshot::default()
+            }
+        }
+    }
+
+    /// 写入快照，内容与上次写入相同时跳过
+    pub fn save(&self, snapshot: RuntimeSnapshot) -> Result<(), String> {
+        let mut last_saved = self.last_saved.lock().unwrap();
+        if last_saved.as_ref() == Some(&snapshot) {
+            return Ok(());
+        }
+        let content = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+        write_atomic(&self.path, content.as_bytes())?;
+        *last_saved = Some(snapshot);
+        Ok(())
+    }
+}
+
+/// 运行时状态持久化：绑定限流跟踪器与用量跟踪器
+pub struct RuntimeStatePersister {
+    store: RuntimeStateStore,
+    tracker: Weak<RateLimitTracker>,
+    limiter: Weak<UsageLimiter>,
+    /// 锁定变化信号 (由快照任务消费)
+    lockout_changed: Arc<tokio::sync::Notify>,
+}
+
+impl RuntimeStatePersister {
+    /// 从数据目录恢复状态，并注册锁定时的写入通知
+    pub fn attach(data_dir: &Path, tracker: &Arc<RateLimitTracker>, limiter: &Arc<UsageLimiter>) -> Arc<Self> {
+        let persister = Arc::new(Self {
+            store: RuntimeStateStore::new(data_dir),
+            tracker: Arc::downgrade(tracker),
+            limiter: Arc::downgrade(limiter),
+            lockout_changed: Arc::new(tokio::sync::Notify::new()),
+        });
+
+        let (usage, lockouts) = persister.store.load().restore(tracker, limiter);
+        if usage > 0 || lockouts > 0 {
+            tracing::info!("[Runtime-State] 已恢复 {} 个账号的用量计数与 {} 个限流锁定", usage, lockouts);
+        }
+
+        let lockout_changed = persister.lockout_changed.clone();
+        tracker.set_lockout_hook(Box::new(move || lockout_changed.notify_one()));
+        persister
+    }
+
+    /// 立即写入当前状态 (同步，仅在阻塞线程或服务停止时调用)
+    pub fn persist(&self) {
+        let (Some(tracker), Some(limiter)) = (self.tracker.upgrade(), self.limiter.upgrade()) else {
+            return;
+        };
+        if let Err(e) = self.store.save(RuntimeSnapshot::capture(&tracker, &limiter)) {
+            tracing::warn!("[Runtime-State] {}", e);
+        }
+    }
+
+    /// 启动快照任务 (定期写入 + 锁定时合并写入)；持久化器被释放后任务自动退出
+    pub fn start_snapshots(self: &Arc<Self>) {
+        let weak: Weak<Self> = Arc::downgrade(self);
+        let lockout_changed = self.lockout_changed.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = lockout_changed.notified() => tokio::time::sleep(LOCKOUT_DEBOUNCE).await,
+                }
+                let Some(persister) = weak.upgrade() else { break };
+                if let Err(e) = tokio::task::spawn_blocking(move || persister.persist()).await {
+                    tracing::warn!("[Runtime-State] 快照任务异常: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// 原子写入：先写同目录临时文件并 fsync，再 rename 覆盖目标文件
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension(format!(
+        "{}.tmp",
+        path.extension().and_then(|e| e.to_str()).unwrap_or_default()
+    ));
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        format!("Failed to write {}: {}", path.display(), e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("runtime-state-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_lockout_triggers_snapshot_and_is_restored() {
+        let dir = temp_dir();
+        let future = chrono::Utc::now().timestamp() + 3600;
+        {
+            let tracker = Arc::new(RateLimitTracker::new());
+            let limiter = Arc::new(UsageLimiter::new());
+            let persister = RuntimeStatePersister::attach(&dir, &tracker, &limiter);
+            persister.start_snapshots();
+            limiter.record("a@example.com", 1200, || future);
+            tracker.set_lockout_until(
+                "acc-1",
+                SystemTime::now() + Duration::from_secs(600),
+                RateLimitReason::QuotaExhausted,
+                Some("claude-sonnet-4-5".to_string()),
+            );
+            // 锁定不同步写盘，由快照任务在合并窗口后写入 (远早于定期快照)
+            assert!(!dir.join(STATE_FILE).exists());
+            let written = async {
+                while !std::fs::read_to_string(dir.join(STATE_FILE)).is_ok_and(|c| c.contains("acc-1")) {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(3), written)
+                .await
+                .expect("lockout snapshot should be written");
+            // 模拟崩溃：没有任何收尾写入
+        }
+
+        let tracker = Arc::new(RateLimitTracker::new());
+        let limiter = Arc::new(UsageLimiter::new());
+        let _persister = RuntimeStatePersister::attach(&dir, &tracker, &limiter);
+        assert!(tracker.is_rate_limited("acc-1"));
+        assert_eq!(tracker.get("acc-1").unwrap().model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(tracker.get("acc-1").unwrap().reason, RateLimitReason::QuotaExhausted);
+        let usage = limiter.usage("a@example.com").unwrap();
+        assert_eq!((usage.tokens, usage.resets_at), (1200, future));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_restore_drops_expired_entries() {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let lockout = |reset_at_ms| PersistedLockout {
+            reset_at_ms,
+            detected_at_ms: now_ms - 1000,
+            reason: RateLimitReason::RateLimitExceeded,
+            model: None,
+        };
+        let snapshot = RuntimeSnapshot {
+            usage: HashMap::from([
+                ("old@example.com".to_string(), DailyUsage { tokens: 5, resets_at: now_ms / 1000 - 10 }),
+                ("new@example.com".to_string(), DailyUsage { tokens: 7, resets_at: now_ms / 1000 + 60 }),
+            ]),
+            lockouts: HashMap::from([
+                ("expired".to_string(), lockout(now_ms - 1)),
+                ("active".to_string(), lockout(now_ms + 60_000)),
+            ]),
+            failure_counts: HashMap::from([("active".to_string(), 2)]),
+        };
+
+        let tracker = RateLimitTracker::new();
+        let limiter = UsageLimiter::new();
+        assert_eq!(snapshot.restore(&tracker, &limiter), (1, 1));
+        assert!(tracker.is_rate_limited("active"));
+        assert!(tracker.get("expired").is_none());
+        assert!(limiter.usage("old@example.com").is_none());
+        assert_eq!(tracker.failure_counts()["active"], 2);
+    }
+
+    #[test]
+    fn test_corrupt_file_and_atomic_write() {
+        let dir = temp_dir();
+        let store = RuntimeStateStore::new(&dir);
+        std::fs::write(dir.join(STATE_FILE), "{\"usage\": {").unwrap();
+        assert_eq!(store.load(), RuntimeSnapshot::default());
+
+        let snapshot = RuntimeSnapshot {
+            failure_counts: HashMap::from([("acc".to_string(), 1)]),
+            ..Default::default()
+        };
+        store.save(snapshot.clone()).unwrap();
+        assert_eq!(store.load(), snapshot);
+        // 不留下临时文件
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(files, vec![std::ffi::OsString::from(STATE_FILE)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,18 +1,48 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use crate::proxy::config::{ProtocolListenerConfig, ProxyAuthMode, ProxyConfig, ProxyProtocol};
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
+    /// 允许使用 X-Account 固定账号的管理密钥 (为空表示禁用)
+    pub admin_key: String,
     pub allow_lan_access: bool,
+    /// 协议独立端口 / 路径前缀的鉴权覆盖
+    pub listeners: Vec<ProtocolListenerConfig>,
 }
 
+/// 请求来源的协议独立端口 (由该端口的路由以 Extension 注入)
+#[derive(Debug, Clone, Copy)]
+pub struct ListenerScope(pub ProxyProtocol);
+
 impl ProxySecurityConfig {
     pub fn from_proxy_config(config: &ProxyConfig) -> Self {
         Self {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
+            admin_key: config.admin_key.clone(),
             allow_lan_access: config.allow_lan_access,
+            listeners: config.protocol_listeners.clone(),
+        }
+    }
+
+    /// 选择本次请求生效的鉴权配置：协议独立端口优先，其次按路径前缀匹配，否则使用全局配置
+    pub fn resolve(&self, scope: Option<ListenerScope>, path: &str) -> ProxySecurityConfig {
+        let listener = self.listeners.iter().filter(|l| l.enabled).find(|l| match scope {
+            Some(ListenerScope(protocol)) => l.protocol == protocol && l.port.is_some_and(|p| p != 0),
+            None => l.normalized_prefix().is_some_and(|prefix| {
+                path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            }),
+        });
+        match listener {
+            Some(l) => ProxySecurityConfig {
+                auth_mode: l.auth_mode.clone().unwrap_or_else(|| self.auth_mode.clone()),
+                api_key: l.api_key.clone().filter(|k| !k.is_empty()).unwrap_or_else(|| self.api_key.clone()),
+                admin_key: self.admin_key.clone(),
+                allow_lan_access: self.allow_lan_access,
+                listeners: Vec::new(),
+            },
+            None => self.clone(),
         }
     }
 
@@ -39,7 +69,9 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            admin_key: String::new(),
             allow_lan_access: false,
+            listeners: Vec::new(),
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
@@ -49,12 +81,45 @@ mod tests {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
+            admin_key: String::new(),
             allow_lan_access: true,
+            listeners: Vec::new(),
         };
         assert!(matches!(
             s.effective_auth_mode(),
             ProxyAuthMode::AllExceptHealth
         ));
     }
-}
 
+    #[test]
+    fn listener_overrides_resolve_by_port_scope_and_path_prefix() {
+        let listener = |protocol, port, prefix: &str, key: &str| ProtocolListenerConfig {
+            protocol,
+            enabled: true,
+            port,
+            path_prefix: prefix.to_string(),
+            auth_mode: Some(ProxyAuthMode::Strict),
+            api_key: Some(key.to_string()),
+        };
+        let s = ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Off,
+            api_key: "sk-global".to_string(),
+            admin_key: String::new(),
+            allow_lan_access: false,
+            listeners: vec![
+                listener(ProxyProtocol::Anthropic, Some(8046), "", "sk-claude"),
+                listener(ProxyProtocol::Openai, None, "/openai/", "sk-openai"),
+            ],
+        };
+
+        let claude_port = s.resolve(Some(ListenerScope(ProxyProtocol::Anthropic)), "/v1/messages");
+        assert_eq!(claude_port.api_key, "sk-claude");
+        assert!(matches!(claude_port.auth_mode, ProxyAuthMode::Strict));
+
+        assert_eq!(s.resolve(None, "/openai/v1/chat/completions").api_key, "sk-openai");
+        // 前缀需按路径段匹配
+        assert_eq!(s.resolve(None, "/openaix/v1/chat/completions").api_key, "sk-global");
+        let main = s.resolve(None, "/v1/messages");
+        assert!(matches!(main.auth_mode, ProxyAuthMode::Off));
+    }
+}
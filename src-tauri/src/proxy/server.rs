@@ -11,13 +11,14 @@ use tokio::sync::oneshot;
 use tower_http::trace::TraceLayer;
 use tracing::{debug, error};
 use tokio::sync::RwLock;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Axum 应用状态
 #[derive(Clone)]
 pub struct AppState {
     pub token_manager: Arc<TokenManager>,
     pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub model_aliases: Arc<RwLock<Vec<crate::proxy::config::ModelAlias>>>, // 模型别名 (热更新)
     #[allow(dead_code)]
     pub request_timeout: u64, // API 请求超时(秒)
     #[allow(dead_code)]
@@ -30,15 +31,46 @@ pub struct AppState {
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub expose_routing_info: Arc<AtomicBool>, // 是否向客户端暴露路由信息
+    pub background_batcher: Arc<crate::proxy::background_batch::BackgroundBatcher>, // 后台小请求合并
+    pub image_jobs: Arc<crate::proxy::image_jobs::ImageJobStore>, // 图片生成异步任务
+    pub content_filter: Arc<crate::proxy::content_filter::ContentFilter>, // 请求内容过滤
+    pub client_profiles: Arc<RwLock<crate::proxy::config::ClientProfilesConfig>>, // 客户端兼容性配置
+    pub upload_limits: Arc<crate::proxy::config::UploadLimitsConfig>, // 请求体与上传大小限制 (启动时确定)
+    pub admission: Arc<crate::proxy::admission::AdmissionController>, // 准入控制 (优先级排队)
+    pub inflight: Arc<crate::proxy::inflight::InflightRegistry>, // 进行中的流式请求
+    pub stored_responses: Arc<crate::proxy::stored_responses::ResponseStore>, // store=true 的补全结果
+    pub connectivity: Arc<crate::proxy::connectivity::Connectivity>, // 离线检测状态
+    pub image_cache: Arc<crate::proxy::image_cache::ImageCache>, // 图片生成结果缓存
+    pub model_capabilities: Arc<crate::proxy::model_capabilities::ModelCapabilityStore>, // 目标模型能力探测结果
+}
+
+impl AppState {
+    /// 解析客户端请求的模型名 (模型别名 -> 自定义映射 -> 系统默认映射)
+    pub async fn resolve_model(&self, model: &str) -> String {
+        crate::proxy::common::model_mapping::resolve_model_route_with_aliases(
+            model,
+            &self.model_aliases.read().await,
+            &*self.custom_mapping.read().await,
+        )
+    }
 }
 
 /// Axum 服务器实例
 pub struct AxumServer {
     shutdown_tx: Option<oneshot::Sender<()>>,
     custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    model_aliases: Arc<RwLock<Vec<crate::proxy::config::ModelAlias>>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    expose_routing_info: Arc<AtomicBool>,
+    content_filter: Arc<crate::proxy::content_filter::ContentFilter>,
+    client_profiles: Arc<RwLock<crate::proxy::config::ClientProfilesConfig>>,
+    experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
+    pub inflight: Arc<crate::proxy::inflight::InflightRegistry>,
+    pub connectivity: Arc<crate::proxy::connectivity::Connectivity>,
 }
 
 impl AxumServer {
@@ -47,7 +79,11 @@ impl AxumServer {
             let mut m = self.custom_mapping.write().await;
             *m = config.custom_mapping.clone();
         }
-        tracing::debug!("模型映射 (Custom) 已全量热更新");
+        {
+            let mut aliases = self.model_aliases.write().await;
+            *aliases = config.model_aliases.clone();
+        }
+        tracing::debug!("模型映射 (Custom) 与模型别名已全量热更新");
     }
 
     /// 更新代理配置
@@ -68,54 +104,323 @@ impl AxumServer {
         *zai = config.zai.clone();
         tracing::info!("z.ai 配置已热更新");
     }
+
+    pub fn update_routing_info(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.expose_routing_info
+            .store(config.expose_routing_info, Ordering::Relaxed);
+        tracing::info!("路由信息暴露配置已热更新: {}", config.expose_routing_info);
+    }
+
+    /// 更新内容过滤规则
+    pub async fn update_content_filter(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.content_filter.update_config(&config.content_filter).await;
+    }
+
+    /// 更新客户端兼容性配置
+    pub async fn update_client_profiles(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut profiles = self.client_profiles.write().await;
+        *profiles = config.client_profiles.clone();
+        tracing::info!("客户端兼容性配置已热更新");
+    }
+
+    /// 更新实验性功能配置 (功能开关的当前值即从此处读取)
+    pub async fn update_experimental(&self, config: &crate::proxy::config::ExperimentalConfig) {
+        let mut experimental = self.experimental.write().await;
+        *experimental = config.clone();
+        // 监控界面事件推送的节流 / 批量设置保存在监控模块中，需单独同步
+        self.monitor.configure_ui_events(config.ui_events.clone());
+        tracing::info!("实验性功能配置已热更新");
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
         port: u16,
         token_manager: Arc<TokenManager>,
         custom_mapping: std::collections::HashMap<String, String>,
+        model_aliases: Vec<crate::proxy::config::ModelAlias>,
         _request_timeout: u64,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
         experimental_config: crate::proxy::config::ExperimentalConfig,
-
+        fixture_config: crate::proxy::config::FixtureConfig,
+        chaos_config: crate::proxy::config::ChaosConfig,
+        expose_routing_info: bool,
+        content_filter_config: crate::proxy::config::ContentFilterConfig,
+        client_profiles_config: crate::proxy::config::ClientProfilesConfig,
+        upload_limits: crate::proxy::config::UploadLimitsConfig,
+        local_socket: crate::proxy::config::LocalSocketConfig,
+        protocol_listeners: Vec<crate::proxy::config::ProtocolListenerConfig>,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
+        let model_aliases_state = Arc::new(RwLock::new(model_aliases));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
 	        let zai_state = Arc::new(RwLock::new(zai_config));
 	        let provider_rr = Arc::new(AtomicUsize::new(0));
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
+	        monitor.configure_ui_events(experimental_config.ui_events.clone());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let expose_routing_info = Arc::new(AtomicBool::new(expose_routing_info));
+	        let content_filter = Arc::new(crate::proxy::content_filter::ContentFilter::new(&content_filter_config));
+	        let client_profiles = Arc::new(RwLock::new(client_profiles_config));
+	        let inflight = Arc::new(crate::proxy::inflight::InflightRegistry::new());
+	        let connectivity = Arc::new(crate::proxy::connectivity::Connectivity::new(monitor.app_handle()));
 
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
 	            custom_mapping: custom_mapping_state.clone(),
+	            model_aliases: model_aliases_state.clone(),
 	            request_timeout: 300, // 5分钟超时
             thought_signature_map: Arc::new(tokio::sync::Mutex::new(
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: Arc::new(
+                crate::proxy::upstream::client::UpstreamClient::new(Some(upstream_proxy.clone()))
+                    .with_fixtures(crate::proxy::upstream::fixtures::FixtureStore::from_config(
+                        &fixture_config,
+                        crate::modules::account::get_data_dir()
+                            .unwrap_or_else(|_| std::env::temp_dir())
+                            .join("fixtures"),
+                    ))
+                    .with_chaos(crate::proxy::upstream::chaos::ChaosInjector::from_config(
+                        &chaos_config,
+                    )),
+            ),
             zai: zai_state.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state,
+            expose_routing_info: expose_routing_info.clone(),
+            background_batcher: Arc::new(crate::proxy::background_batch::BackgroundBatcher::new()),
+            image_jobs: Arc::new(crate::proxy::image_jobs::ImageJobStore::new(monitor.app_handle())),
+            content_filter: content_filter.clone(),
+            client_profiles: client_profiles.clone(),
+            upload_limits: Arc::new(upload_limits.clone()),
+            admission: Arc::new(crate::proxy::admission::AdmissionController::new()),
+            inflight: inflight.clone(),
+            stored_responses: Arc::new(crate::proxy::stored_responses::ResponseStore::open(
+                &crate::modules::account::get_data_dir().unwrap_or_else(|_| std::env::temp_dir()),
+            )),
+            connectivity: connectivity.clone(),
+            image_cache: Arc::new(crate::proxy::image_cache::ImageCache::new(
+                &crate::modules::account::get_data_dir().unwrap_or_else(|_| std::env::temp_dir()),
+            )),
+            model_capabilities: Arc::new(crate::proxy::model_capabilities::ModelCapabilityStore::load(
+                &crate::modules::account::get_data_dir().unwrap_or_else(|_| std::env::temp_dir()),
+            )),
+        };
+
+
+        let app = main_router(&protocol_listeners, state.clone(), security_state.clone(), &upload_limits);
+
+        // 先绑定所有端口 (主端口在前)，全部成功后再启动监听任务，任一失败都不会遗留已启动的任务
+        let addr = format!("{}:{}", host, port);
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
+
+        // [NEW] 协议独立端口：仅提供该协议的路由，鉴权可单独配置
+        let mut protocol_bindings = Vec::new();
+        for listener in protocol_listeners.iter().filter(|l| l.enabled) {
+            let Some(protocol_port) = listener.port.filter(|p| *p != 0) else {
+                continue;
+            };
+            let router = protocol_port_router(listener.protocol, state.clone(), security_state.clone(), &upload_limits);
+            let protocol_addr = format!("{}:{}", host, protocol_port);
+            let protocol_listener = tokio::net::TcpListener::bind(&protocol_addr)
+                .await
+                .map_err(|e| format!("{:?} 协议端口 {} 绑定失败: {}", listener.protocol, protocol_addr, e))?;
+            protocol_bindings.push((listener.protocol, protocol_addr, protocol_listener, router));
+        }
+
+        // [NEW] 可选的本地套接字 / 命名管道监听 (与 TCP 共用同一套路由)；最后一个可能失败的步骤
+        let local_listener = if local_socket.enabled {
+            Some(crate::proxy::local_socket::spawn(&local_socket, app.clone())?)
+        } else {
+            None
+        };
+
+        tracing::info!("反代服务器启动在 http://{}", addr);
+        let protocol_tasks: Vec<_> = protocol_bindings
+            .into_iter()
+            .map(|(protocol, protocol_addr, protocol_listener, router)| {
+                tracing::info!("{:?} 协议单独监听在 http://{}", protocol, protocol_addr);
+                tokio::spawn(async move {
+                    loop {
+                        match protocol_listener.accept().await {
+                            Ok((stream, _)) => serve_connection(stream, router.clone()),
+                            Err(e) => error!("接收连接失败: {:?}", e),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // [NEW] 空闲 / 休眠唤醒后预热账号与上游连接
+        let idle_warmup_task = crate::proxy::idle_warmup::spawn(
+            state.token_manager.clone(),
+            state.upstream.clone(),
+            state.experimental.clone(),
+        );
+
+        // [NEW] 离线检测：上游不可达时快速失败
+        let connectivity_task = crate::proxy::connectivity::spawn(
+            connectivity.clone(),
+            state.upstream.clone(),
+            state.experimental.clone(),
+        );
+
+        // 创建关闭通道
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        let server_instance = Self {
+            shutdown_tx: Some(shutdown_tx),
+            custom_mapping: custom_mapping_state.clone(),
+            model_aliases: model_aliases_state,
+            proxy_state,
+            security_state,
+            zai_state,
+            expose_routing_info,
+            content_filter,
+            client_profiles,
+            experimental: state.experimental.clone(),
+            monitor: monitor.clone(),
+            inflight,
+            connectivity,
         };
 
+        // 在新任务中启动服务器
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    res = listener.accept() => {
+                        match res {
+                            Ok((stream, _)) => serve_connection(stream, app.clone()),
+                            Err(e) => {
+                                error!("接收连接失败: {:?}", e);
+                            }
+                        }
+                    }
+                    _ = &mut shutdown_rx => {
+                        tracing::info!("反代服务器停止监听");
+                        break;
+                    }
+                }
+            }
+            for task in protocol_tasks {
+                task.abort();
+            }
+            idle_warmup_task.abort();
+            connectivity_task.abort();
+            if let Some((local_handle, path)) = local_listener {
+                local_handle.abort();
+                crate::proxy::local_socket::cleanup(&path);
+            }
+        });
+
+        Ok((server_instance, handle))
+    }
+
+    /// 停止服务器
+    pub fn stop(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// 主端口的完整路由 (含路径前缀挂载与协议自动识别兜底)，已挂载中间件栈
+pub(crate) fn main_router(
+    protocol_listeners: &[crate::proxy::config::ProtocolListenerConfig],
+    state: AppState,
+    security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    upload_limits: &crate::proxy::config::UploadLimitsConfig,
+) -> Router {
+    // 构建路由 - 使用新架构的 handlers！
+    use crate::proxy::handlers;
+    use crate::proxy::config::ProxyProtocol;
+    // 构建路由 (各协议的路由分组见 protocol_routes)
+    let mut routes = Router::new()
+        .merge(protocol_routes(ProxyProtocol::Openai))
+        .merge(protocol_routes(ProxyProtocol::Anthropic))
+        .merge(protocol_routes(ProxyProtocol::Gemini))
+        .merge(model_routes(get(handlers::openai::handle_list_models)))
+        // z.ai MCP (optional reverse-proxy)
+        .route(
+            "/mcp/web_search_prime/mcp",
+            any(handlers::mcp::handle_web_search_prime),
+        )
+        .route(
+            "/mcp/web_reader/mcp",
+            any(handlers::mcp::handle_web_reader),
+        )
+        .route(
+            "/mcp/zai-mcp-server/mcp",
+            any(handlers::mcp::handle_zai_mcp_server),
+        )
+        .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
+        .route(
+            "/internal/scheduler/dry-run",
+            get(handlers::scheduler::handle_scheduler_dry_run),
+        ) // 调度决策预演
+        .route(
+            "/internal/content-filter/audit",
+            get(handlers::content_filter::handle_content_filter_audit),
+        ) // 内容过滤审计
+        .route(
+            "/internal/logging",
+            get(handlers::log_settings::handle_get_log_settings)
+                .post(handlers::log_settings::handle_update_log_settings),
+        ) // 运行时日志级别调整
+        .route("/internal/inflight", get(handlers::inflight::handle_list_inflight)) // 进行中的流
+        .route(
+            "/internal/inflight/:trace_id",
+            axum::routing::delete(handlers::inflight::handle_kill_inflight),
+        ) // 强制终止指定流
+        .route("/healthz", get(health_check_handler))
+        .route("/status", get(handlers::status::handle_status)); // 账号池状态与配额预测
+
+    // [NEW] 协议路径前缀：在主端口上以 /<prefix>/v1/... 单独挂载某个协议 (鉴权按前缀覆盖，见 ProxySecurityConfig::resolve)
+    for listener in protocol_listeners.iter().filter(|l| l.enabled) {
+        if let Some(prefix) = listener.normalized_prefix() {
+            tracing::info!("{:?} 协议挂载在路径前缀 {}", listener.protocol, prefix);
+            routes = routes.nest(&prefix, dedicated_routes(listener.protocol));
+        }
+    }
+    // [NEW] 兼容路由：未匹配的 POST /v1/* 按请求体结构推断协议后分发
+    let routes = routes.fallback(handlers::auto_protocol::handle_auto_protocol);
+    apply_layers(routes, state, security_state, upload_limits)
+}
 
-        // 构建路由 - 使用新架构的 handlers！
-        use crate::proxy::handlers;
-        // 构建路由
-        let app = Router::new()
-            // OpenAI Protocol
-            .route("/v1/models", get(handlers::openai::handle_list_models))
+/// 协议独立端口的路由：仅提供该协议的路由，鉴权可单独配置
+pub(crate) fn protocol_port_router(
+    protocol: crate::proxy::config::ProxyProtocol,
+    state: AppState,
+    security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    upload_limits: &crate::proxy::config::UploadLimitsConfig,
+) -> Router {
+    apply_layers(
+        dedicated_routes(protocol).route("/healthz", get(health_check_handler)),
+        state,
+        security_state,
+        upload_limits,
+    )
+    // 标记请求来自该协议端口，供鉴权中间件选择独立配置 (需位于鉴权层之外)
+    .layer(axum::Extension(crate::proxy::security::ListenerScope(protocol)))
+}
+
+/// 单个协议的路由分组 (不含模型列表，见 model_routes)
+pub(crate) fn protocol_routes(protocol: crate::proxy::config::ProxyProtocol) -> Router<AppState> {
+    use crate::proxy::config::ProxyProtocol;
+    use crate::proxy::handlers;
+
+    match protocol {
+        ProxyProtocol::Openai => Router::new()
             .route(
                 "/v1/chat/completions",
                 post(handlers::openai::handle_chat_completions),
@@ -124,7 +429,15 @@ impl AxumServer {
                 "/v1/completions",
                 post(handlers::openai::handle_completions),
             )
+            .route(
+                "/v1/chat/completions/:id",
+                get(handlers::openai::handle_get_chat_completion).delete(handlers::openai::handle_delete_chat_completion),
+            ) // store=true 的补全结果
             .route("/v1/responses", post(handlers::openai::handle_completions)) // 兼容 Codex CLI
+            .route(
+                "/v1/responses/:id",
+                get(handlers::openai::handle_get_response).delete(handlers::openai::handle_delete_response),
+            )
             .route(
                 "/v1/images/generations",
                 post(handlers::openai::handle_images_generations),
@@ -133,11 +446,15 @@ impl AxumServer {
                 "/v1/images/edits",
                 post(handlers::openai::handle_images_edits),
             ) // 图像编辑 API
+            .route(
+                "/v1/images/jobs/:job_id",
+                get(handlers::openai::handle_image_job_status),
+            ) // 图像异步任务进度
             .route(
                 "/v1/audio/transcriptions",
                 post(handlers::audio::handle_audio_transcription),
-            ) // 音频转录 API (PR #311)
-            // Claude Protocol
+            ), // 音频转录 API (PR #311)
+        ProxyProtocol::Anthropic => Router::new()
             .route("/v1/messages", post(handlers::claude::handle_messages))
             .route(
                 "/v1/messages/count_tokens",
@@ -147,21 +464,23 @@ impl AxumServer {
                 "/v1/models/claude",
                 get(handlers::claude::handle_list_models),
             )
-            // z.ai MCP (optional reverse-proxy)
+            .route("/v1/api/event_logging/batch", post(silent_ok_handler))
+            .route("/v1/api/event_logging", post(silent_ok_handler))
+            // 企业版 SDK 初始化时探测的组织 / 用量端点
             .route(
-                "/mcp/web_search_prime/mcp",
-                any(handlers::mcp::handle_web_search_prime),
+                "/v1/organizations/me",
+                get(handlers::organization::handle_get_organization),
             )
-	            .route(
-	                "/mcp/web_reader/mcp",
-	                any(handlers::mcp::handle_web_reader),
-	            )
-	            .route(
-	                "/mcp/zai-mcp-server/mcp",
-	                any(handlers::mcp::handle_zai_mcp_server),
-	            )
-	            // Gemini Protocol (Native)
-	            .route("/v1beta/models", get(handlers::gemini::handle_list_models))
+            .route(
+                "/v1/organizations/usage_report/messages",
+                get(handlers::organization::handle_usage_report),
+            )
+            .route(
+                "/v1/organizations/cost_report",
+                get(handlers::organization::handle_cost_report),
+            ),
+        ProxyProtocol::Gemini => Router::new()
+            .route("/v1beta/models", get(handlers::gemini::handle_list_models))
             // Handle both GET (get info) and POST (generateContent with colon) at the same route
             .route(
                 "/v1beta/models/:model",
@@ -170,87 +489,96 @@ impl AxumServer {
             .route(
                 "/v1beta/models/:model/countTokens",
                 post(handlers::gemini::handle_count_tokens),
-            ) // Specific route priority
-            .route("/v1/models/detect", post(handlers::common::handle_detect_model))
-            .route("/internal/warmup", post(handlers::warmup::handle_warmup)) // 内部预热端点
-            .route("/v1/api/event_logging/batch", post(silent_ok_handler))
-            .route("/v1/api/event_logging", post(silent_ok_handler))
-            .route("/healthz", get(health_check_handler))
-            .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
-            .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
-            .layer(TraceLayer::new_for_http())
-            .layer(axum::middleware::from_fn_with_state(
-                security_state.clone(),
-                crate::proxy::middleware::auth_middleware,
-            ))
-            .layer(crate::proxy::middleware::cors_layer())
-            .with_state(state);
-
-        // 绑定地址
-        let addr = format!("{}:{}", host, port);
-        let listener = tokio::net::TcpListener::bind(&addr)
-            .await
-            .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
+            ), // Specific route priority
+    }
+}
 
-        tracing::info!("反代服务器启动在 http://{}", addr);
+/// /v1/models 系列路由 (列表格式随挂载的协议而定)
+fn model_routes(list: axum::routing::MethodRouter<AppState>) -> Router<AppState> {
+    use crate::proxy::handlers;
 
-        // 创建关闭通道
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+    Router::new()
+        .route("/v1/models", list)
+        .route("/v1/models/detect", post(handlers::common::handle_detect_model))
+        .route("/v1/models/:model", get(handlers::common::handle_get_model)) // 单个模型详情 (OpenAI / Anthropic 格式)
+}
 
-        let server_instance = Self {
-            shutdown_tx: Some(shutdown_tx),
-            custom_mapping: custom_mapping_state.clone(),
-            proxy_state,
-            security_state,
-            zai_state,
-        };
+/// 独立端口 / 路径前缀下的协议路由：/v1/models 返回该协议自己的列表格式
+pub(crate) fn dedicated_routes(protocol: crate::proxy::config::ProxyProtocol) -> Router<AppState> {
+    use crate::proxy::config::ProxyProtocol;
+    use crate::proxy::handlers;
 
-        // 在新任务中启动服务器
-        let handle = tokio::spawn(async move {
-            use hyper::server::conn::http1;
-            use hyper_util::rt::TokioIo;
-            use hyper_util::service::TowerToHyperService;
+    let routes = protocol_routes(protocol);
+    match protocol {
+        ProxyProtocol::Openai => routes.merge(model_routes(get(handlers::openai::handle_list_models))),
+        ProxyProtocol::Anthropic => routes.merge(model_routes(get(handlers::claude::handle_list_models))),
+        ProxyProtocol::Gemini => routes,
+    }
+}
 
-            loop {
-                tokio::select! {
-                    res = listener.accept() => {
-                        match res {
-                            Ok((stream, _)) => {
-                                let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
-
-                                tokio::task::spawn(async move {
-                                    if let Err(err) = http1::Builder::new()
-                                        .serve_connection(io, service)
-                                        .with_upgrades() // 支持 WebSocket (如果以后需要)
-                                        .await
-                                    {
-                                        debug!("连接处理结束或出错: {:?}", err);
-                                    }
-                                });
-                            }
-                            Err(e) => {
-                                error!("接收连接失败: {:?}", e);
-                            }
-                        }
-                    }
-                    _ = &mut shutdown_rx => {
-                        tracing::info!("反代服务器停止监听");
-                        break;
-                    }
-                }
-            }
-        });
+/// 为路由挂载中间件栈 (主端口与协议独立端口共用)
+fn apply_layers(
+    routes: Router<AppState>,
+    state: AppState,
+    security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    upload_limits: &crate::proxy::config::UploadLimitsConfig,
+) -> Router {
+    routes
+        .layer(DefaultBodyLimit::max(upload_limits.max_request_body_bytes()))
+        // panic 隔离紧贴 handler：panic 转换为 500 / SSE error 事件，外层中间件与监控照常记录
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::panic_guard_middleware))
+        // 客户端识别在监控记录之内执行，识别结果经响应头写入请求日志
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::client_profile_middleware))
+        // 内容过滤在监控记录之内、handler 之前执行
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::content_filter_middleware))
+        // 按 Content-Length 提前拒绝超限请求，避免下游中间件缓存整个请求体
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::body_limit_middleware))
+        // 准入控制在监控记录之内：排队时间计入请求耗时，被拒绝的请求同样留有记录
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::admission_middleware))
+        // 离线快速失败同样留有监控记录，且先于准入排队执行 (离线请求不占用并发名额)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::offline_middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+        // 流式请求登记同样依赖路由信息头 (账号 / 模型)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::inflight_middleware))
+        // 账号统计不依赖监控开关，同样需在路由信息头被移除之前执行
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::account_stats_middleware))
+        // 在监控记录之后按配置移除路由信息头
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::routing_info_middleware))
+        // 限流状态头基于账号池计算，覆盖所有已通过鉴权的响应
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::rate_limit_headers_middleware))
+        // 响应压缩位于监控之外，监控记录的始终是未压缩的响应体
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::compression_middleware))
+        // 追踪 ID 在最外层解析，监控与 handler 日志均处于该 span 内
+        .layer(axum::middleware::from_fn(crate::proxy::middleware::trace_id_middleware))
+        .layer(TraceLayer::new_for_http())
+        .layer(axum::middleware::from_fn_with_state(
+            security_state,
+            crate::proxy::middleware::auth_middleware,
+        ))
+        .layer(crate::proxy::middleware::cors_layer())
+        .with_state(state)
+}
 
-        Ok((server_instance, handle))
-    }
+/// 在独立任务中处理单个连接 (TCP / 本地套接字共用)
+pub(crate) fn serve_connection<I>(io: I, app: Router)
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use hyper_util::service::TowerToHyperService;
 
-    /// 停止服务器
-    pub fn stop(mut self) {
-        if let Some(tx) = self.shutdown_tx.take() {
-            let _ = tx.send(());
+    let io = TokioIo::new(io);
+    let service = TowerToHyperService::new(app);
+    tokio::task::spawn(async move {
+        if let Err(err) = http1::Builder::new()
+            .serve_connection(io, service)
+            .with_upgrades() // 支持 WebSocket (如果以后需要)
+            .await
+        {
+            debug!("连接处理结束或出错: {:?}", err);
         }
-    }
+    });
 }
 
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
@@ -0,0 +1,265 @@
+// 粘性会话绑定表 (SessionID -> AccountID)
+// 绑定在空闲超过 TTL 后自动失效，条目数超过上限时淘汰最久未使用的绑定 (LRU)，
+// 同时统计绑定的建立 / 换绑 / 过期 / 淘汰次数，用于观察会话在账号间的流转。
+
+use dashmap::DashMap;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+#[derive(Debug, Clone)]
+struct Binding {
+    account_id: String,
+    /// 建立时间 (unix 秒)
+    bound_at: i64,
+    /// 最近一次使用时间 (unix 毫秒，用于 TTL 与 LRU)
+    last_used_ms: i64,
+    hits: u64,
+}
+
+/// 单个绑定的快照
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionBindingInfo {
+    pub session_id: String,
+    pub account_id: String,
+    pub bound_at: i64,
+    pub last_used_at: i64,
+    pub hits: u64,
+}
+
+/// 绑定流转统计 (进程启动以来累计)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionBindingStats {
+    pub active: usize,
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+    /// 新建绑定
+    pub created: u64,
+    /// 会话换绑到其他账号
+    pub rebound: u64,
+    /// 复用已有绑定
+    pub reused: u64,
+    /// 空闲超时失效
+    pub expired: u64,
+    /// 超出上限被淘汰
+    pub evicted: u64,
+    /// 主动解绑 (限流 / 预轮换 / 账号移除 / 手动清除)
+    pub removed: u64,
+}
+
+#[derive(Default)]
+pub struct SessionBindings {
+    map: DashMap<String, Binding>,
+    ttl_secs: AtomicU64,
+    max_entries: AtomicUsize,
+    created: AtomicU64,
+    rebound: AtomicU64,
+    reused: AtomicU64,
+    expired: AtomicU64,
+    evicted: AtomicU64,
+    removed: AtomicU64,
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+impl SessionBindings {
+    pub fn new(ttl_secs: u64, max_entries: usize) -> Self {
+        let bindings = Self::default();
+        bindings.configure(ttl_secs, max_entries);
+        bindings
+    }
+
+    /// 更新 TTL (0 表示不过期) 与最大条目数 (0 表示不限)
+    pub fn configure(&self, ttl_secs: u64, max_entries: usize) {
+        self.ttl_secs.store(ttl_secs, Ordering::Relaxed);
+        self.max_entries.store(max_entries, Ordering::Relaxed);
+        self.purge_expired();
+        self.evict_over_limit();
+    }
+
+    fn is_expired(&self, binding: &Binding, now_ms: i64) -> bool {
+        let ttl = self.ttl_secs.load(Ordering::Relaxed);
+        ttl > 0 && now_ms - binding.last_used_ms > (ttl as i64).saturating_mul(1000)
+    }
+
+    /// 查询会话绑定的账号并刷新其使用时间；已过期的绑定会被移除
+    pub fn get(&self, session_id: &str) -> Option<String> {
+        let now = now_ms();
+        let mut entry = self.map.get_mut(session_id)?;
+        if self.is_expired(&entry, now) {
+            drop(entry);
+            self.map.remove(session_id);
+            self.expired.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        entry.last_used_ms = now;
+        entry.hits += 1;
+        self.reused.fetch_add(1, Ordering::Relaxed);
+        Some(entry.account_id.clone())
+    }
+
+    /// 只读查询 (不刷新使用时间，供调度预演使用)
+    pub fn peek(&self, session_id: &str) -> Option<String> {
+        let entry = self.map.get(session_id)?;
+        (!self.is_expired(&entry, now_ms())).then(|| entry.account_id.clone())
+    }
+
+    /// 建立或更新绑定
+    pub fn bind(&self, session_id: &str, account_id: &str) {
+        let now = now_ms();
+        let previous = self.map.insert(
+            session_id.to_string(),
+            Binding {
+                account_id: account_id.to_string(),
+                bound_at: now / 1000,
+                last_used_ms: now,
+                hits: 0,
+            },
+        );
+        match previous {
+            Some(prev) if prev.account_id != account_id => self.rebound.fetch_add(1, Ordering::Relaxed),
+            Some(_) => 0,
+            None => self.created.fetch_add(1, Ordering::Relaxed),
+        };
+        self.evict_over_limit();
+    }
+
+    /// 主动解绑
+    pub fn remove(&self, session_id: &str) {
+        if self.map.remove(session_id).is_some() {
+            self.removed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&self) {
+        let count = self.map.len() as u64;
+        self.map.clear();
+        self.removed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// 移除全部过期绑定，返回移除数量
+    pub fn purge_expired(&self) -> usize {
+        let now = now_ms();
+        let before = self.map.len();
+        self.map.retain(|_, binding| !self.is_expired(binding, now));
+        let purged = before.saturating_sub(self.map.len());
+        self.expired.fetch_add(purged as u64, Ordering::Relaxed);
+        purged
+    }
+
+    /// 超出上限时按最近使用时间淘汰最旧的绑定
+    fn evict_over_limit(&self) {
+        let max = self.max_entries.load(Ordering::Relaxed);
+        if max == 0 || self.map.len() <= max {
+            return;
+        }
+        // 先清理过期条目，仍超限再做 LRU 淘汰
+        self.purge_expired();
+        let excess = self.map.len().saturating_sub(max);
+        if excess == 0 {
+            return;
+        }
+        let mut by_age: Vec<(i64, String)> = self
+            .map
+            .iter()
+            .map(|e| (e.value().last_used_ms, e.key().clone()))
+            .collect();
+        by_age.sort();
+        for (_, session_id) in by_age.into_iter().take(excess) {
+            if self.map.remove(&session_id).is_some() {
+                self.evicted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        tracing::debug!("[Sticky] Evicted {} least recently used session binding(s) (max {})", excess, max);
+    }
+
+    /// 当前绑定快照 (按最近使用时间倒序)
+    pub fn list(&self) -> Vec<SessionBindingInfo> {
+        let now = now_ms();
+        let mut list: Vec<SessionBindingInfo> = self
+            .map
+            .iter()
+            .filter(|e| !self.is_expired(e.value(), now))
+            .map(|e| SessionBindingInfo {
+                session_id: e.key().clone(),
+                account_id: e.value().account_id.clone(),
+                bound_at: e.value().bound_at,
+                last_used_at: e.value().last_used_ms / 1000,
+                hits: e.value().hits,
+            })
+            .collect();
+        list.sort_by_key(|b| std::cmp::Reverse(b.last_used_at));
+        list
+    }
+
+    pub fn stats(&self) -> SessionBindingStats {
+        SessionBindingStats {
+            active: self.map.len(),
+            max_entries: self.max_entries.load(Ordering::Relaxed),
+            ttl_secs: self.ttl_secs.load(Ordering::Relaxed),
+            created: self.created.load(Ordering::Relaxed),
+            rebound: self.rebound.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+            removed: self.removed.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(test)]
+    fn backdate(&self, session_id: &str, secs: i64) {
+        if let Some(mut entry) = self.map.get_mut(session_id) {
+            entry.last_used_ms -= secs * 1000;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_expires_idle_bindings() {
+        let bindings = SessionBindings::new(60, 0);
+        bindings.bind("s1", "acc-1");
+        assert_eq!(bindings.get("s1").as_deref(), Some("acc-1"));
+
+        bindings.backdate("s1", 61);
+        assert_eq!(bindings.peek("s1"), None);
+        assert_eq!(bindings.get("s1"), None);
+        assert_eq!(bindings.stats().active, 0);
+
+        let stats = bindings.stats();
+        assert_eq!((stats.created, stats.reused, stats.expired), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_lru_eviction_and_churn_metrics() {
+        let bindings = SessionBindings::new(0, 2);
+        bindings.bind("s1", "acc-1");
+        bindings.bind("s2", "acc-2");
+        bindings.backdate("s1", 10);
+        bindings.backdate("s2", 20);
+        // s1 最近使用过，淘汰 s2
+        bindings.get("s1");
+        bindings.bind("s3", "acc-1");
+        assert_eq!(bindings.stats().active, 2);
+        assert!(bindings.peek("s2").is_none());
+        assert!(bindings.peek("s1").is_some());
+
+        bindings.bind("s1", "acc-3");
+        bindings.remove("s3");
+        let stats = bindings.stats();
+        assert_eq!(stats.created, 3);
+        assert_eq!(stats.rebound, 1);
+        assert_eq!(stats.evicted, 1);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(bindings.list()[0].account_id, "acc-3");
+
+        // 缩小上限立即生效
+        bindings.bind("s4", "acc-2");
+        bindings.configure(0, 1);
+        assert_eq!(bindings.stats().active, 1);
+    }
+}
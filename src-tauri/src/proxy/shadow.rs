@@ -0,0 +1,197 @@
+// 影子流量 (Shadow Traffic)
+// 按比例把已转换好的上游请求异步镜像到另一个模型 (由账号池另选账号)，响应不返回给客户端，
+// 只记录状态 / 耗时 / 用量 (可选记录响应文本) 到请求日志，用于在切换模型映射前用真实流量做对比。
+
+use crate::proxy::config::ShadowTrafficConfig;
+use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::server::AppState;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// 影子请求在请求日志中的 method 标记 (不计入代理统计)
+pub const SHADOW_METHOD: &str = "SHADOW";
+
+/// 是否镜像本次请求 (`roll` 为 [0, 1) 的随机数)
+pub fn should_mirror(config: &ShadowTrafficConfig, primary_model: &str, request_type: &str, roll: f64) -> bool {
+    config.enabled
+        && !config.model.trim().is_empty()
+        && config.model != primary_model
+        && request_type != "image_gen"
+        && roll * 100.0 < config.percentage
+}
+
+/// 基于主请求的 v1internal 信封构建影子请求 (替换模型、项目与 requestId)
+pub fn build_shadow_body(body: &Value, model: &str, project_id: &str) -> Value {
+    let mut shadow = body.clone();
+    if let Some(obj) = shadow.as_object_mut() {
+        obj.insert("model".to_string(), Value::String(model.to_string()));
+        obj.insert("project".to_string(), Value::String(project_id.to_string()));
+        obj.insert("requestId".to_string(), Value::String(format!("shadow-{}", uuid::Uuid::new_v4())));
+    }
+    shadow
+}
+
+/// 提取响应中的可见文本 (忽略思维链)
+fn extract_text(value: &Value) -> String {
+    let raw = value.get("response").unwrap_or(value);
+    raw["candidates"][0]["content"]["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|p| !p["thought"].as_bool().unwrap_or(false))
+                .filter_map(|p| p["text"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn usage_tokens(value: &Value) -> (Option<u32>, Option<u32>) {
+    let raw = value.get("response").unwrap_or(value);
+    let usage = &raw["usageMetadata"];
+    (
+        usage["promptTokenCount"].as_u64().map(|v| v as u32),
+        usage["candidatesTokenCount"].as_u64().map(|v| v as u32),
+    )
+}
+
+/// 按配置决定是否镜像；镜像请求在后台任务中执行，不阻塞主请求
+pub async fn maybe_mirror(state: &AppState, body: &Value, client_model: &str, primary_model: &str, path: &str) {
+    let config = state.experimental.read().await.shadow_traffic.clone();
+    let request_type = body.get("requestType").and_then(|v| v.as_str()).unwrap_or("agent").to_string();
+    if !should_mirror(&config, primary_model, &request_type, rand::random::<f64>()) {
+        return;
+    }
+
+    let token_manager = state.token_manager.clone();
+    let upstream = state.upstream.clone();
+    let monitor = state.monitor.clone();
+    let body = body.clone();
+    let client_model = client_model.to_string();
+    let primary_model = primary_model.to_string();
+    let path = path.to_string();
+
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let mut log = ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            method: SHADOW_METHOD.to_string(),
+            url: path,
+            status: 0,
+            duration: 0,
+            model: Some(client_model),
+            mapped_model: Some(config.model.clone()),
+            account_email: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: None,
+            output_tokens: None,
+            thinking_tokens: None,
+            client: None,
+            experiment: None,
+            session_id: None,
+            timing: None,
+        };
+
+        let run = async {
+            let (access_token, project_id, email) = token_manager
+                .get_token(&request_type, &request_type, false, None, Some(&config.model))
+                .await?;
+            log.account_email = Some(email.clone());
+            let shadow_body = build_shadow_body(&body, &config.model, &project_id);
+            let response = upstream
+                .call_v1_internal("generateContent", &access_token, shadow_body, None)
+                .await?;
+            log.status = response.status().as_u16();
+            let text = response.text().await.map_err(|e| format!("Read error: {}", e))?;
+            if !(200..300).contains(&log.status) {
+                return Err(format!("HTTP {}: {}", log.status, text));
+            }
+            let value: Value = serde_json::from_str(&text).map_err(|e| format!("Parse error: {}", e))?;
+            if let Some(tokens) = crate::proxy::usage_limits::extract_total_tokens(&value) {
+                token_manager.record_usage(&email, tokens);
+            }
+            Ok(value)
+        };
+
+        match tokio::time::timeout(Duration::from_millis(config.timeout_ms), run).await {
+            Ok(Ok(value)) => {
+                (log.input_tokens, log.output_tokens) = usage_tokens(&value);
+                let text = extract_text(&value);
+                tracing::info!(
+                    "[Shadow] {} -> {} completed in {}ms ({} chars)",
+                    primary_model,
+                    config.model,
+                    started.elapsed().as_millis(),
+                    text.chars().count()
+                );
+                if config.log_responses {
+                    log.response_body = Some(text);
+                }
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("[Shadow] {} -> {} failed: {}", primary_model, config.model, e);
+                if log.status == 0 {
+                    log.status = 502;
+                }
+                log.error = Some(e);
+            }
+            Err(_) => {
+                tracing::warn!("[Shadow] {} -> {} timed out after {}ms", primary_model, config.model, config.timeout_ms);
+                log.status = 504;
+                log.error = Some(format!("Shadow request timed out after {}ms", config.timeout_ms));
+            }
+        }
+        log.duration = started.elapsed().as_millis() as u64;
+        monitor.log_request(log).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_should_mirror() {
+        let config = ShadowTrafficConfig {
+            enabled: true,
+            percentage: 25.0,
+            model: "gemini-2.5-flash".to_string(),
+            ..Default::default()
+        };
+        assert!(should_mirror(&config, "gemini-2.5-pro", "agent", 0.1));
+        assert!(!should_mirror(&config, "gemini-2.5-pro", "agent", 0.5));
+        // 与主模型相同 / 图片请求 / 未启用时不镜像
+        assert!(!should_mirror(&config, "gemini-2.5-flash", "agent", 0.0));
+        assert!(!should_mirror(&config, "gemini-2.5-pro", "image_gen", 0.0));
+        let disabled = ShadowTrafficConfig { enabled: false, ..config.clone() };
+        assert!(!should_mirror(&disabled, "gemini-2.5-pro", "agent", 0.0));
+        let no_model = ShadowTrafficConfig { model: String::new(), ..config };
+        assert!(!should_mirror(&no_model, "gemini-2.5-pro", "agent", 0.0));
+    }
+
+    #[test]
+    fn test_build_shadow_body_and_extract_text() {
+        let body = json!({
+            "project": "primary-project",
+            "requestId": "openai-abc",
+            "model": "gemini-2.5-pro",
+            "request": { "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }] }
+        });
+        let shadow = build_shadow_body(&body, "gemini-2.5-flash", "shadow-project");
+        assert_eq!(shadow["model"], "gemini-2.5-flash");
+        assert_eq!(shadow["project"], "shadow-project");
+        assert!(shadow["requestId"].as_str().unwrap().starts_with("shadow-"));
+        assert_eq!(shadow["request"], body["request"]);
+
+        let response = json!({ "response": {
+            "candidates": [{ "content": { "parts": [{ "text": "plan", "thought": true }, { "text": "Hello" }] } }],
+            "usageMetadata": { "promptTokenCount": 3, "candidatesTokenCount": 1 }
+        }});
+        assert_eq!(extract_text(&response), "Hello");
+        assert_eq!(usage_tokens(&response), (Some(3), Some(1)));
+    }
+}
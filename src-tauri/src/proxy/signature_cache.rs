@@ -10,6 +10,7 @@ const MIN_SIGNATURE_LENGTH: usize = 50;
 const TOOL_CACHE_LIMIT: usize = 500;      // Layer 1: Tool-specific signatures
 const FAMILY_CACHE_LIMIT: usize = 200;    // Layer 2: Model family mappings
 const SESSION_CACHE_LIMIT: usize = 1000;  // Layer 3: Session-based signatures (largest)
+const TOOL_NAME_CACHE_LIMIT: usize = 5000; // Layer 4: tool_id -> name mappings
 
 /// Cache entry with timestamp for TTL
 #[derive(Clone, Debug)]
@@ -51,6 +52,13 @@ pub struct SignatureCache {
     /// Value: The most recent valid thought signature for this session
     /// This prevents signature pollution between different conversations
     session_signatures: Mutex<HashMap<String, CacheEntry<String>>>,
+
+    /// Layer 4: Tool Use ID -> Function Name
+    /// Lets functionResponse parts keep their real name when a client truncates
+    /// the history and the original tool_use block is no longer present.
+    /// Keyed by the tool call id itself: it is unique per call and, unlike the
+    /// message-derived session fingerprint, does not change when history is truncated.
+    tool_names: Mutex<HashMap<String, CacheEntry<String>>>,
 }
 
 impl SignatureCache {
@@ -59,6 +67,7 @@ impl SignatureCache {
             tool_signatures: Mutex::new(HashMap::new()),
             thinking_families: Mutex::new(HashMap::new()),
             session_signatures: Mutex::new(HashMap::new()),
+            tool_names: Mutex::new(HashMap::new()),
         }
     }
 
@@ -207,6 +216,41 @@ impl SignatureCache {
         None
     }
 
+    // ===== Layer 4: tool_id -> name Mapping =====
+
+    /// Remember the function name behind a tool call id.
+    pub fn cache_tool_name(&self, tool_use_id: &str, name: &str) {
+        if tool_use_id.is_empty() || name.is_empty() || tool_use_id == name {
+            return;
+        }
+
+        if let Ok(mut cache) = self.tool_names.lock() {
+            cache.insert(tool_use_id.to_string(), CacheEntry::new(name.to_string()));
+
+            if cache.len() > TOOL_NAME_CACHE_LIMIT {
+                let before = cache.len();
+                cache.retain(|_, v| !v.is_expired());
+                let after = cache.len();
+                if before != after {
+                    tracing::debug!("[SignatureCache] Tool name cache cleanup: {} -> {} entries", before, after);
+                }
+            }
+        }
+    }
+
+    /// Look up the function name recorded for a tool call id.
+    pub fn get_tool_name(&self, tool_use_id: &str) -> Option<String> {
+        if let Ok(cache) = self.tool_names.lock() {
+            if let Some(entry) = cache.get(tool_use_id) {
+                if !entry.is_expired() {
+                    tracing::debug!("[SignatureCache] Recovered tool name '{}' for id: {}", entry.data, tool_use_id);
+                    return Some(entry.data.clone());
+                }
+            }
+        }
+        None
+    }
+
     /// Clear all caches (for testing or manual reset)
     #[allow(dead_code)] // Used in tests
     pub fn clear(&self) {
@@ -219,6 +263,9 @@ impl SignatureCache {
         if let Ok(mut cache) = self.session_signatures.lock() {
             cache.clear();
         }
+        if let Ok(mut cache) = self.tool_names.lock() {
+            cache.clear();
+        }
     }
 }
 
@@ -283,6 +330,19 @@ mod tests {
         assert!(cache.get_session_signature("sid-other").is_none());
     }
 
+    #[test]
+    fn test_tool_names() {
+        let cache = SignatureCache::new();
+
+        cache.cache_tool_name("toolu_1", "read_file");
+        assert_eq!(cache.get_tool_name("toolu_1"), Some("read_file".to_string()));
+        assert!(cache.get_tool_name("toolu_2").is_none());
+
+        // id == name carries no information and is not stored
+        cache.cache_tool_name("toolu_3", "toolu_3");
+        assert!(cache.get_tool_name("toolu_3").is_none());
+    }
+
     #[test]
     fn test_clear_all_caches() {
         let cache = SignatureCache::new();
@@ -291,6 +351,7 @@ mod tests {
         cache.cache_tool_signature("tool_1", sig.clone());
         cache.cache_thinking_family(sig.clone(), "model".to_string());
         cache.cache_session_signature("sid-1", sig.clone());
+        cache.cache_tool_name("tool_1", "bash");
         
         assert!(cache.get_tool_signature("tool_1").is_some());
         assert!(cache.get_signature_family(&sig).is_some());
@@ -301,5 +362,6 @@ mod tests {
         assert!(cache.get_tool_signature("tool_1").is_none());
         assert!(cache.get_signature_family(&sig).is_none());
         assert!(cache.get_session_signature("sid-1").is_none());
+        assert!(cache.get_tool_name("tool_1").is_none());
     }
 }
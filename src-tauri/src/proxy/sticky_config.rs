@@ -24,6 +24,23 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 会话预轮换：绑定账号预计在该小时数内耗尽配额时，提前为会话换绑其他账号 (0 表示关闭)
+    #[serde(default)]
+    pub forecast_rotate_hours: f64,
+    /// 会话绑定空闲超过该秒数后失效 (0 表示不过期)
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// 会话绑定的最大条目数，超出时淘汰最久未使用的绑定 (0 表示不限)
+    #[serde(default = "default_max_session_bindings")]
+    pub max_session_bindings: usize,
+}
+
+fn default_session_ttl_secs() -> u64 {
+    6 * 3600
+}
+
+fn default_max_session_bindings() -> usize {
+    10_000
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +48,9 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            forecast_rotate_hours: 0.0,
+            session_ttl_secs: default_session_ttl_secs(),
+            max_session_bindings: default_max_session_bindings(),
         }
     }
 }
@@ -0,0 +1,317 @@
+// 存储的补全结果 (OpenAI store=true)
+// 客户端设置 store=true 时把 Chat Completion / Responses API 的最终结果保存到本地 SQLite，
+// 供 GET /v1/chat/completions/{id} 与 GET /v1/responses/{id} 检索 (stored completions 工作流 / 评估工具)。
+// 流式响应在转发的同时旁路收集，流结束后再还原为完整对象保存。
+
+use crate::proxy::mappers::openai::{OpenAIContent, OpenAIResponse};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+const DB_FILE: &str = "stored_responses.db";
+/// 保留天数 (与请求日志一致)
+const RETENTION_DAYS: i64 = 30;
+/// 流式旁路收集的上限，超出后放弃保存
+const MAX_CAPTURE_BYTES: usize = 32 * 1024 * 1024;
+
+/// 存储对象的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredKind {
+    ChatCompletion,
+    Response,
+}
+
+impl StoredKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StoredKind::ChatCompletion => "chat.completion",
+            StoredKind::Response => "response",
+        }
+    }
+}
+
+pub struct ResponseStore {
+    path: PathBuf,
+}
+
+impl ResponseStore {
+    pub fn open(data_dir: &Path) -> Self {
+        let store = Self { path: data_dir.join(DB_FILE) };
+        if let Err(e) = store.init() {
+            tracing::error!("[StoredResponses] Failed to initialize {}: {}", store.path.display(), e);
+        }
+        store
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        Connection::open(&self.path).map_err(|e| e.to_string())
+    }
+
+    fn init(&self) -> Result<(), String> {
+        let conn = self.connect()?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS stored_responses (
+                kind TEXT NOT NULL,
+                id TEXT NOT NULL,
+                created INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                PRIMARY KEY (kind, id)
+            )",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let cutoff = chrono::Utc::now().timestamp() - RETENTION_DAYS * 24 * 3600;
+        conn.execute("DELETE FROM stored_responses WHERE created < ?1", params![cutoff])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// 保存对象 (以 body.id 为键，metadata 按 OpenAI 约定随对象返回)
+    pub fn save(&self, kind: StoredKind, body: &Value, metadata: Option<&Value>) -> Result<(), String> {
+        let id = body.get("id").and_then(|v| v.as_str()).ok_or("missing id")?;
+        let mut body = body.clone();
+        if let Some(metadata) = metadata {
+            body["metadata"] = metadata.clone();
+        }
+        self.connect()?
+            .execute(
+                "INSERT OR REPLACE INTO stored_responses (kind, id, created, body) VALUES (?1, ?2, ?3, ?4)",
+                params![kind.as_str(), id, chrono::Utc::now().timestamp(), body.to_string()],
+            )
+            .map_err(|e| e.to_string())?;
+        tracing::debug!("[StoredResponses] Stored {} {}", kind.as_str(), id);
+        Ok(())
+    }
+
+    /// 在阻塞线程池中保存，失败仅记录日志
+    pub fn save_in_background(self: &Arc<Self>, kind: StoredKind, body: Value, metadata: Option<Value>) {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = store.save(kind, &body, metadata.as_ref()) {
+                tracing::warn!("[StoredResponses] Failed to store {}: {}", kind.as_str(), e);
+            }
+        });
+    }
+
+    pub fn get(&self, kind: StoredKind, id: &str) -> Option<Value> {
+        let body: Option<String> = self
+            .connect()
+            .ok()?
+            .query_row(
+                "SELECT body FROM stored_responses WHERE kind = ?1 AND id = ?2",
+                params![kind.as_str(), id],
+                |row| row.get(0),
+            )
+            .optional()
+            .ok()?;
+        body.and_then(|b| serde_json::from_str(&b).ok())
+    }
+
+    pub fn delete(&self, kind: StoredKind, id: &str) -> bool {
+        self.connect()
+            .and_then(|conn| {
+                conn.execute(
+                    "DELETE FROM stored_responses WHERE kind = ?1 AND id = ?2",
+                    params![kind.as_str(), id],
+                )
+                .map_err(|e| e.to_string())
+            })
+            .is_ok_and(|n| n > 0)
+    }
+}
+
+/// 旁路收集 SSE 流，流正常结束后把全部字节交给 `on_complete`
+pub fn capture_stream<F>(
+    mut stream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>,
+    on_complete: F,
+) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>
+where
+    F: FnOnce(Vec<Bytes>) + Send + 'static,
+{
+    Box::pin(async_stream::stream! {
+        let mut captured = Vec::new();
+        let mut size = 0usize;
+        let mut overflow = false;
+        while let Some(item) = stream.next().await {
+            if let Ok(bytes) = &item {
+                size += bytes.len();
+                overflow |= size > MAX_CAPTURE_BYTES;
+                if !overflow {
+                    captured.push(bytes.clone());
+                }
+            }
+            yield item;
+        }
+        if overflow {
+            tracing::warn!("[StoredResponses] Stream exceeded {} bytes, not stored", MAX_CAPTURE_BYTES);
+        } else {
+            on_complete(captured);
+        }
+    })
+}
+
+/// store=true 的 Chat Completions 流：结束后还原完整的 chat.completion 并保存
+pub fn save_chat_stream(store: Arc<ResponseStore>, metadata: Option<Value>) -> impl FnOnce(Vec<Bytes>) + Send + 'static {
+    move |chunks| {
+        tokio::spawn(async move {
+            use crate::proxy::mappers::openai::collect_openai_stream_to_json;
+            let stream = futures::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>));
+            match collect_openai_stream_to_json(stream)
+                .await
+                .and_then(|response| serde_json::to_value(response).map_err(|e| e.to_string()))
+            {
+                Ok(body) => store.save_in_background(StoredKind::ChatCompletion, body, metadata),
+                Err(e) => tracing::warn!("[StoredResponses] Failed to rebuild chat completion: {}", e),
+            }
+        });
+    }
+}
+
+/// store=true 的 Responses API 流：结束后还原完整的 response 对象并保存
+pub fn save_response_stream(store: Arc<ResponseStore>, metadata: Option<Value>) -> impl FnOnce(Vec<Bytes>) + Send + 'static {
+    move |chunks| match assemble_response(&chunks) {
+        Some(body) => store.save_in_background(StoredKind::Response, body, metadata),
+        None => tracing::warn!("[StoredResponses] Stream ended without response.completed, not stored"),
+    }
+}
+
+/// 非流式 Responses API 请求：由 Chat 结果构造 response 对象 (id 与返回给客户端的一致)
+pub fn response_from_chat(chat: &OpenAIResponse) -> Value {
+    let mut output = Vec::new();
+    if let Some(choice) = chat.choices.first() {
+        let text = match &choice.message.content {
+            Some(OpenAIContent::String(s)) => s.clone(),
+            _ => String::new(),
+        };
+        output.push(json!({
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": text }]
+        }));
+        for call in choice.message.tool_calls.iter().flatten() {
+            output.push(json!({
+                "type": "function_call",
+                "name": call.function.name,
+                "arguments": call.function.arguments,
+                "call_id": call.id
+            }));
+        }
+    }
+
+    let mut response = json!({
+        "id": chat.id,
+        "object": "response",
+        "created_at": chat.created,
+        "status": "completed",
+        "model": chat.model,
+        "output": output
+    });
+    if let Some(usage) = &chat.usage {
+        response["usage"] = json!({
+            "input_tokens": usage.prompt_tokens,
+            "output_tokens": usage.completion_tokens,
+            "total_tokens": usage.total_tokens
+        });
+    }
+    response
+}
+
+/// 由 Responses API 的 SSE 事件还原完整的 response 对象
+/// (response.completed 提供元数据，output 由各 response.output_item.done 组成)
+pub fn assemble_response(chunks: &[Bytes]) -> Option<Value> {
+    let text: String = chunks.iter().map(|c| String::from_utf8_lossy(c)).collect();
+    let mut output = Vec::new();
+    let mut completed = None;
+    for line in text.lines() {
+        let Some(data) = line.strip_prefix("data: ") else { continue };
+        let Ok(event) = serde_json::from_str::<Value>(data.trim()) else { continue };
+        match event.get("type").and_then(|t| t.as_str()) {
+            Some("response.output_item.done") => {
+                if let Some(item) = event.get("item") {
+                    output.push(item.clone());
+                }
+            }
+            Some("response.completed") => completed = event.get("response").cloned(),
+            _ => {}
+        }
+    }
+    let mut response = completed?;
+    if response.get("output").is_none_or(|o| o.as_array().is_none_or(|a| a.is_empty())) {
+        response["output"] = Value::Array(output);
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_save_get_delete() {
+        let dir = std::env::temp_dir().join(format!("stored_responses_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = ResponseStore::open(&dir);
+
+        let completion = json!({"id": "chatcmpl-1", "object": "chat.completion", "choices": []});
+        store.save(StoredKind::ChatCompletion, &completion, Some(&json!({"run": "eval-7"}))).unwrap();
+
+        let stored = store.get(StoredKind::ChatCompletion, "chatcmpl-1").unwrap();
+        assert_eq!(stored["metadata"]["run"], "eval-7");
+        // 不同类型互不可见
+        assert!(store.get(StoredKind::Response, "chatcmpl-1").is_none());
+
+        assert!(store.delete(StoredKind::ChatCompletion, "chatcmpl-1"));
+        assert!(store.get(StoredKind::ChatCompletion, "chatcmpl-1").is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_assemble_response_from_events() {
+        let events = [
+            json!({"type": "response.created", "response": {"id": "resp-1", "status": "in_progress"}}),
+            json!({"type": "response.output_item.done", "item": {"type": "message", "content": [{"type": "output_text", "text": "Hi"}]}}),
+            json!({"type": "response.completed", "response": {"id": "resp-1", "object": "response", "status": "completed"}}),
+        ];
+        let chunks: Vec<Bytes> = events.iter().map(|e| Bytes::from(format!("data: {}\n\n", e))).collect();
+
+        let response = assemble_response(&chunks).unwrap();
+        assert_eq!(response["id"], "resp-1");
+        assert_eq!(response["status"], "completed");
+        assert_eq!(response["output"][0]["content"][0]["text"], "Hi");
+        assert!(assemble_response(&chunks[..2]).is_none());
+    }
+
+    #[test]
+    fn test_response_from_chat() {
+        let chat: OpenAIResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-9",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gemini-2.5-flash",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Hi",
+                    "tool_calls": [{ "id": "call_1", "type": "function", "function": { "name": "ls", "arguments": "{}" } }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 }
+        }))
+        .unwrap();
+
+        let response = response_from_chat(&chat);
+        assert_eq!(response["id"], "chatcmpl-9");
+        assert_eq!(response["object"], "response");
+        assert_eq!(response["output"][0]["content"][0]["text"], "Hi");
+        assert_eq!(response["output"][1]["call_id"], "call_1");
+        assert_eq!(response["usage"]["total_tokens"], 5);
+    }
+}
@@ -0,0 +1,116 @@
+// Claude Code 子代理路由 (Subagent Model Routing)
+// Claude Code 以独立的系统提示词启动子代理 (如 "You are a code reviewer")。
+// 按配置的关键词识别子代理类别，把这类请求路由到更便宜 / 更快的模型，主对话的模型映射不受影响。
+// 与后台任务降级类似，但规则由用户配置；命中的类别随 RoutingInfo 写入 X-Subagent 响应头。
+
+use crate::proxy::common::routing_info::RoutingInfo;
+use crate::proxy::config::SubagentRoute;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, SystemPrompt};
+use crate::proxy::server::AppState;
+
+fn system_text(request: &ClaudeRequest) -> String {
+    match &request.system {
+        Some(SystemPrompt::String(s)) => s.clone(),
+        Some(SystemPrompt::Array(blocks)) => blocks.iter().map(|b| b.text.as_str()).collect::<Vec<_>>().join("\n"),
+        None => String::new(),
+    }
+}
+
+fn matches_model(pattern: &str, model: &str) -> bool {
+    pattern == model || (pattern.contains('*') && crate::proxy::common::model_mapping::wildcard_match(pattern, model))
+}
+
+/// 返回第一条命中的子代理规则
+pub fn classify<'a>(routes: &'a [SubagentRoute], request: &ClaudeRequest) -> Option<&'a SubagentRoute> {
+    if routes.is_empty() {
+        return None;
+    }
+    let system = system_text(request).to_lowercase();
+    if system.is_empty() {
+        return None;
+    }
+    routes.iter().find(|r| {
+        r.enabled
+            && !r.target_model.trim().is_empty()
+            && matches_model(&r.model_pattern, &request.model)
+            && r
+                .system_patterns
+                .iter()
+                .any(|p| !p.trim().is_empty() && system.contains(&p.to_lowercase()))
+    })
+}
+
+/// 识别子代理并返回生效的映射模型 (未命中时沿用原映射)
+pub async fn route(state: &AppState, request: &ClaudeRequest, mapped_model: String, routing: &mut RoutingInfo) -> String {
+    let target = {
+        let experimental = state.experimental.read().await;
+        match classify(&experimental.subagent_routes, request) {
+            Some(rule) => {
+                routing.subagent = Some(rule.name.clone());
+                rule.target_model.clone()
+            }
+            None => return mapped_model,
+        }
+    };
+    let resolved = state.resolve_model(&target).await;
+    tracing::debug!(
+        "[Subagent] {} -> {} ({})",
+        request.model,
+        resolved,
+        routing.subagent.as_deref().unwrap_or_default()
+    );
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(name: &str, pattern: &str, target: &str) -> SubagentRoute {
+        SubagentRoute {
+            name: name.to_string(),
+            enabled: true,
+            system_patterns: vec![pattern.to_string()],
+            model_pattern: "*".to_string(),
+            target_model: target.to_string(),
+        }
+    }
+
+    fn request(system: serde_json::Value) -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": "claude-opus-4-5",
+            "system": system,
+            "messages": [{"role": "user", "content": "review this diff"}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_classify_by_system_prompt() {
+        let routes = vec![
+            rule("reviewer", "you are a code reviewer", "gemini-2.5-flash"),
+            rule("search", "file search specialist", "gemini-2.5-flash-lite"),
+        ];
+
+        let reviewer = request(json!([{"type": "text", "text": "You are a Code Reviewer. Be strict."}]));
+        assert_eq!(classify(&routes, &reviewer).map(|r| r.name.as_str()), Some("reviewer"));
+
+        let search = request(json!("You are a file search specialist for Claude Code."));
+        assert_eq!(classify(&routes, &search).map(|r| r.name.as_str()), Some("search"));
+
+        let main = request(json!("You are Claude Code, Anthropic's official CLI for Claude."));
+        assert!(classify(&routes, &main).is_none());
+    }
+
+    #[test]
+    fn test_disabled_and_model_filtered_rules_are_skipped() {
+        let mut disabled = rule("reviewer", "code reviewer", "gemini-2.5-flash");
+        disabled.enabled = false;
+        let mut sonnet_only = rule("reviewer-sonnet", "code reviewer", "gemini-2.5-flash");
+        sonnet_only.model_pattern = "claude-sonnet-*".to_string();
+
+        let req = request(json!("You are a code reviewer."));
+        assert!(classify(&[disabled, sonnet_only], &req).is_none());
+    }
+}
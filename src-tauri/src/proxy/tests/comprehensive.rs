@@ -36,6 +36,8 @@ mod tests {
             }),
             metadata: None,
             output_config: None,
+            output_format: None,
+            betas: Default::default(),
         };
 
         // 2. 执行转换
@@ -0,0 +1,280 @@
+// Mock v1internal 上游
+// 用于端到端测试：模拟 cloudcode-pa 的 SSE 流式响应、429 (RetryInfo) 与 thoughtSignature 下发
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{StatusCode, Uri},
+    response::Response,
+    Router,
+};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// 预设的上游响应
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// SSE 流：每个元素为一个 v1internal 事件 (会被包装为 `data: {"response": ...}`)
+    Sse(Vec<Value>),
+    /// 非流式 JSON 响应 (generateContent)
+    Json(Value),
+    /// 错误响应
+    Error { status: u16, body: Value },
+    /// 发送部分 SSE 事件后中断连接 (模拟生成中途断流)
+    BrokenSse(Vec<Value>),
+}
+
+impl MockReply {
+    /// 纯文本流式响应 (按 chunk 拆分)
+    pub fn text_stream(chunks: &[&str]) -> Self {
+        let mut events: Vec<Value> = chunks
+            .iter()
+            .map(|t| {
+                json!({
+                    "candidates": [{ "content": { "role": "model", "parts": [{ "text": t }] } }],
+                    "modelVersion": "gemini-mock",
+                    "responseId": "resp_mock"
+                })
+            })
+            .collect();
+        events.push(Self::finish_event("STOP"));
+        MockReply::Sse(events)
+    }
+
+    /// 带 thinking + thoughtSignature 的流式响应
+    pub fn thinking_stream(thought: &str, signature: &str, answer: &str) -> Self {
+        MockReply::Sse(vec![
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [
+                    { "text": thought, "thought": true, "thoughtSignature": signature }
+                ] } }],
+                "modelVersion": "gemini-mock",
+                "responseId": "resp_mock"
+            }),
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [{ "text": answer }] } }],
+                "modelVersion": "gemini-mock"
+            }),
+            Self::finish_event("STOP"),
+        ])
+    }
+
+    /// 单个 functionCall 的流式响应
+    pub fn function_call_stream(name: &str, args: Value, signature: Option<&str>) -> Self {
+        Self::function_call_stream_with_id(name, args, signature, "call_mock_1")
+    }
+
+    /// 同 function_call_stream，但指定调用 id (全局缓存按 id 索引，需避免测试间冲突)
+    pub fn function_call_stream_with_id(name: &str, args: Value, signature: Option<&str>, id: &str) -> Self {
+        let mut part = json!({ "functionCall": { "name": name, "args": args, "id": id } });
+        if let Some(sig) = signature {
+            part["thoughtSignature"] = json!(sig);
+        }
+        MockReply::Sse(vec![
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [part] } }],
+                "modelVersion": "gemini-mock",
+                "responseId": "resp_mock"
+            }),
+            Self::finish_event("STOP"),
+        ])
+    }
+
+    /// 429 RESOURCE_EXHAUSTED，携带 RetryInfo 与 quotaResetDelay
+    pub fn rate_limited(retry_delay: &str) -> Self {
+        MockReply::Error {
+            status: 429,
+            body: json!({
+                "error": {
+                    "code": 429,
+                    "message": "Resource has been exhausted (e.g. check quota).",
+                    "status": "RESOURCE_EXHAUSTED",
+                    "details": [
+                        {
+                            "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                            "retryDelay": retry_delay
+                        },
+                        {
+                            "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+                            "reason": "RATE_LIMIT_EXCEEDED",
+                            "metadata": { "quotaResetDelay": retry_delay }
+                        }
+                    ]
+                }
+            }),
+        }
+    }
+
+    fn finish_event(reason: &str) -> Value {
+        json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "" }] },
+                "finishReason": reason
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 12,
+                "candidatesTokenCount": 8,
+                "totalTokenCount": 20
+            },
+            "modelVersion": "gemini-mock"
+        })
+    }
+}
+
+/// Mock 收到的请求记录
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// v1internal 方法名 (e.g. "streamGenerateContent")
+    pub method: String,
+    pub query: Option<String>,
+    pub authorization: Option<String>,
+    pub body: Value,
+}
+
+#[derive(Default)]
+struct MockInner {
+    replies: VecDeque<MockReply>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// Mock v1internal 服务器
+#[derive(Clone)]
+pub struct MockUpstream {
+    inner: Arc<Mutex<MockInner>>,
+    pub base_url: String,
+}
+
+impl MockUpstream {
+    /// 启动 mock 服务器 (绑定随机端口)
+    pub async fn start() -> Self {
+        let inner = Arc::new(Mutex::new(MockInner::default()));
+        let app = Router::new()
+            .fallback(mock_handler)
+            .with_state(inner.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock upstream");
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Self {
+            inner,
+            base_url: format!("http://{}/v1internal", addr),
+        }
+    }
+
+    /// 追加一个预设响应 (按 FIFO 顺序消费)
+    pub fn push(&self, reply: MockReply) {
+        self.inner.lock().unwrap().replies.push_back(reply);
+    }
+
+    /// 获取已收到的请求
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.inner.lock().unwrap().requests.clone()
+    }
+}
+
+async fn mock_handler(
+    State(inner): State<Arc<Mutex<MockInner>>>,
+    uri: Uri,
+    headers: axum::http::HeaderMap,
+    body: Bytes,
+) -> Response {
+    let method = uri
+        .path()
+        .rsplit(':')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let reply = {
+        let mut guard = inner.lock().unwrap();
+        guard.requests.push(RecordedRequest {
+            method,
+            query: uri.query().map(|q| q.to_string()),
+            authorization: headers
+                .get("authorization")
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string()),
+            body,
+        });
+        guard.replies.pop_front()
+    };
+
+    match reply {
+        Some(MockReply::Sse(events)) => {
+            let mut payload = String::new();
+            for event in events {
+                payload.push_str(&format!("data: {}\n\n", json!({ "response": event })));
+            }
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/event-stream")
+                .body(Body::from(payload))
+                .unwrap()
+        }
+        Some(MockReply::BrokenSse(events)) => {
+            // 先让已发送的事件到达客户端，再中断连接
+            let stream = async_stream::stream! {
+                for event in events {
+                    yield Ok::<Bytes, std::io::Error>(Bytes::from(format!("data: {}\n\n", json!({ "response": event }))));
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                yield Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "upstream reset"));
+            };
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "text/event-stream")
+                .body(Body::from_stream(stream))
+                .unwrap()
+        }
+        Some(MockReply::Json(value)) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "response": value }).to_string()))
+            .unwrap(),
+        Some(MockReply::Error { status, body }) => Response::builder()
+            .status(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("mock upstream: no scripted reply left"))
+            .unwrap(),
+    }
+}
+
+/// 在临时目录中写入测试账号 (token 未过期且带 project_id，避免触发 OAuth 刷新)
+pub fn write_test_accounts(count: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ag-e2e-{}", uuid::Uuid::new_v4()));
+    let accounts_dir = dir.join("accounts");
+    std::fs::create_dir_all(&accounts_dir).unwrap();
+
+    let expiry = chrono::Utc::now().timestamp() + 3600;
+    for i in 0..count {
+        let account = json!({
+            "id": format!("acc-{}", i),
+            "email": format!("user{}@example.com", i),
+            "token": {
+                "access_token": format!("token-{}", i),
+                "refresh_token": format!("refresh-{}", i),
+                "expires_in": 3600,
+                "expiry_timestamp": expiry,
+                "project_id": format!("project-{}", i)
+            }
+        });
+        std::fs::write(
+            accounts_dir.join(format!("acc-{}.json", i)),
+            serde_json::to_string_pretty(&account).unwrap(),
+        )
+        .unwrap();
+    }
+    dir
+}
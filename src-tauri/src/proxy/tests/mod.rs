@@ -1 +1,4 @@
 pub mod comprehensive;
+pub mod mock_upstream;
+pub mod e2e;
+pub mod round_trip;
@@ -0,0 +1,424 @@
+// 协议往返契约测试
+// 代表性的 Claude / OpenAI 请求转换为 Gemini 后，再将模型轮次作为上游响应转换回来，
+// 断言角色、工具、停止原因与 usage 等语义内容保持不变，防止映射层不断增加特例时发生回归。
+// 固定样例之外，再用 proptest 随机生成对话 / 工具参数 / 结束原因 / 用量，检验同样的性质对任意输入成立。
+#[cfg(test)]
+mod tests {
+    use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, GeminiResponse};
+    use crate::proxy::mappers::claude::request::transform_claude_request_in;
+    use crate::proxy::mappers::claude::response::transform_response;
+    use crate::proxy::mappers::openai::models::{OpenAIContent, OpenAIRequest};
+    use crate::proxy::mappers::openai::request::transform_openai_request;
+    use crate::proxy::mappers::openai::response::transform_openai_response;
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    fn weather_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"]
+        })
+    }
+
+    /// 将 Gemini 请求中的某个 model 轮次包装为上游响应
+    fn echo_model_turn(contents: &Value, finish_reason: &str, usage: Value) -> Value {
+        let parts = contents
+            .as_array()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|c| c["role"] == "model")
+            .map(|c| c["parts"].clone())
+            .expect("no model turn");
+        json!({
+            "candidates": [{ "content": { "role": "model", "parts": parts }, "finishReason": finish_reason }],
+            "usageMetadata": usage,
+            "modelVersion": "gemini-test",
+            "responseId": "resp-round-trip"
+        })
+    }
+
+    fn visible_parts(content: &Value) -> Vec<&Value> {
+        content["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|p| !p["thought"].as_bool().unwrap_or(false))
+            .collect()
+    }
+
+    // ==================================================================================
+    // Claude <-> Gemini
+    // ==================================================================================
+
+    fn claude_tool_request() -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": "You are a weather bot.",
+            "tools": [{ "name": "get_weather", "description": "Look up the weather", "input_schema": weather_schema() }],
+            "messages": [
+                { "role": "user", "content": "Weather in Paris?" },
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "Let me check." },
+                    { "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": { "city": "Paris" } }
+                ]},
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_01", "content": "Sunny, 21C" }
+                ]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_claude_request_maps_roles_tools_and_system() {
+        let body = transform_claude_request_in(&claude_tool_request(), "test-project").unwrap();
+        let request = &body["request"];
+
+        let system_text = request["systemInstruction"]["parts"].to_string();
+        assert!(system_text.contains("You are a weather bot."));
+
+        let declarations = &request["tools"][0]["functionDeclarations"];
+        assert_eq!(declarations[0]["name"], "get_weather");
+        assert!(declarations[0]["parameters"]["properties"]["city"].is_object());
+
+        let contents = request["contents"].as_array().unwrap();
+        let roles: Vec<&str> = contents.iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+
+        let model_parts = visible_parts(&contents[1]);
+        assert_eq!(model_parts[0]["text"], "Let me check.");
+        assert_eq!(model_parts[1]["functionCall"]["name"], "get_weather");
+        assert_eq!(model_parts[1]["functionCall"]["args"]["city"], "Paris");
+        assert_eq!(model_parts[1]["functionCall"]["id"], "toolu_01");
+
+        let response = &contents[2]["parts"][0]["functionResponse"];
+        assert_eq!(response["name"], "get_weather");
+        assert_eq!(response["id"], "toolu_01");
+        assert!(response["response"].to_string().contains("Sunny, 21C"));
+    }
+
+    #[test]
+    fn test_claude_assistant_turn_round_trips_through_gemini() {
+        let body = transform_claude_request_in(&claude_tool_request(), "test-project").unwrap();
+        let upstream = echo_model_turn(
+            &body["request"]["contents"],
+            "STOP",
+            json!({ "promptTokenCount": 120, "candidatesTokenCount": 30, "totalTokenCount": 150 }),
+        );
+        let gemini: GeminiResponse = serde_json::from_value(upstream).unwrap();
+        let claude = transform_response(&gemini, Default::default(), Default::default()).unwrap();
+
+        assert_eq!(claude.role, "assistant");
+        assert_eq!(claude.stop_reason, "tool_use");
+        assert_eq!(claude.usage.input_tokens, 120);
+        assert_eq!(claude.usage.output_tokens, 30);
+
+        let visible: Vec<&ContentBlock> = claude
+            .content
+            .iter()
+            .filter(|b| !matches!(b, ContentBlock::Thinking { .. }))
+            .collect();
+        assert_eq!(visible.len(), 2);
+        match visible[0] {
+            ContentBlock::Text { text, .. } => assert_eq!(text, "Let me check."),
+            other => panic!("expected text, got {:?}", other),
+        }
+        match visible[1] {
+            ContentBlock::ToolUse { id, name, input, .. } => {
+                assert_eq!(id, "toolu_01");
+                assert_eq!(name, "get_weather");
+                assert_eq!(input["city"], "Paris");
+            }
+            other => panic!("expected tool_use, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_claude_stop_reasons_and_usage_contract() {
+        let cases = [
+            ("STOP", json!([{ "text": "done" }]), "end_turn"),
+            ("MAX_TOKENS", json!([{ "text": "trunc" }]), "max_tokens"),
+            ("STOP", json!([{ "functionCall": { "name": "get_weather", "args": {}, "id": "t1" } }]), "tool_use"),
+        ];
+        for (finish_reason, parts, expected) in cases {
+            let gemini: GeminiResponse = serde_json::from_value(json!({
+                "candidates": [{ "content": { "role": "model", "parts": parts }, "finishReason": finish_reason }],
+                "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 5, "totalTokenCount": 15 }
+            }))
+            .unwrap();
+            let claude = transform_response(&gemini, Default::default(), Default::default()).unwrap();
+            assert_eq!(claude.stop_reason, expected, "finishReason {}", finish_reason);
+            assert_eq!((claude.usage.input_tokens, claude.usage.output_tokens), (10, 5));
+        }
+    }
+
+    // ==================================================================================
+    // OpenAI <-> Gemini
+    // ==================================================================================
+
+    fn openai_tool_request() -> OpenAIRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "You are a weather bot." },
+                { "role": "user", "content": "Weather in Paris?" },
+                { "role": "assistant", "content": "Let me check.", "tool_calls": [{
+                    "id": "call_01",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+                }]},
+                { "role": "tool", "tool_call_id": "call_01", "name": "get_weather", "content": "Sunny, 21C" }
+            ],
+            "tools": [{ "type": "function", "function": {
+                "name": "get_weather", "description": "Look up the weather", "parameters": weather_schema()
+            }}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_openai_request_maps_roles_tools_and_system() {
+        let body = transform_openai_request(&openai_tool_request(), "test-project", "gemini-2.5-flash", None);
+        let request = &body["request"];
+
+        assert!(request["systemInstruction"]["parts"].to_string().contains("You are a weather bot."));
+        let declarations = &request["tools"][0]["functionDeclarations"];
+        assert_eq!(declarations[0]["name"], "get_weather");
+
+        let contents = request["contents"].as_array().unwrap();
+        let roles: Vec<&str> = contents.iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+
+        let model_parts = visible_parts(&contents[1]);
+        assert_eq!(model_parts[0]["text"], "Let me check.");
+        assert_eq!(model_parts[1]["functionCall"]["name"], "get_weather");
+        assert_eq!(model_parts[1]["functionCall"]["args"]["city"], "Paris");
+
+        // 工具结果只以 functionResponse 下发，不重复附带纯文本
+        assert_eq!(contents[2]["parts"].as_array().unwrap().len(), 1);
+        let response = &contents[2]["parts"][0]["functionResponse"];
+        assert_eq!(response["name"], "get_weather");
+        assert!(response["response"].to_string().contains("Sunny, 21C"));
+    }
+
+    #[test]
+    fn test_openai_assistant_turn_round_trips_through_gemini() {
+        let body = transform_openai_request(&openai_tool_request(), "test-project", "gemini-2.5-flash", None);
+        let upstream = echo_model_turn(
+            &body["request"]["contents"],
+            "STOP",
+            json!({ "promptTokenCount": 120, "candidatesTokenCount": 30, "totalTokenCount": 150 }),
+        );
+        let response = transform_openai_response(&upstream, &Default::default(), Default::default());
+
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.role, "assistant");
+        assert_eq!(choice.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(choice.message.content, Some(OpenAIContent::String("Let me check.".to_string())));
+
+        let calls = choice.message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "get_weather");
+        let args: Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+        assert_eq!(args, json!({ "city": "Paris" }));
+
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (120, 30, 150));
+    }
+
+    #[test]
+    fn test_openai_finish_reasons_and_usage_contract() {
+        // 流式输出不下发工具调用，工具调用结束时与流式一致仍为 stop
+        let cases = [
+            ("STOP", json!([{ "text": "done" }]), "stop"),
+            ("MAX_TOKENS", json!([{ "text": "trunc" }]), "length"),
+            ("STOP", json!([{ "functionCall": { "name": "get_weather", "args": {} } }]), "stop"),
+            ("SAFETY", json!([]), "content_filter"),
+        ];
+        for (finish_reason, parts, expected) in cases {
+            let response = transform_openai_response(&json!({
+                "response": {
+                    "candidates": [{ "content": { "role": "model", "parts": parts }, "finishReason": finish_reason }],
+                    "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 5 }
+                }
+            }), &Default::default(), Default::default());
+            assert_eq!(response.choices[0].finish_reason.as_deref(), Some(expected), "finishReason {}", finish_reason);
+            let usage = response.usage.unwrap();
+            assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (10, 5, 15));
+        }
+    }
+
+    // ==================================================================================
+    // 属性测试 (proptest)
+    // ==================================================================================
+
+    /// 非空、首尾无空白的纯文本 (避免映射层对空白 / "[undefined]" 的清理影响断言)
+    fn plain_text() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9][a-zA-Z0-9 ,.?]{0,30}[a-zA-Z0-9]"
+    }
+
+    /// 多轮 user / assistant 纯文本对话，始终以 user 结尾
+    fn conversation() -> impl Strategy<Value = Vec<String>> {
+        (0usize..4).prop_flat_map(|pairs| prop::collection::vec(plain_text(), pairs * 2 + 1))
+    }
+
+    /// 工具名与参数 (前缀避开联网 / shell 等被映射层特殊处理的内置工具名)
+    fn tool_call() -> impl Strategy<Value = (String, serde_json::Map<String, Value>)> {
+        (
+            "[a-z][a-z_]{0,15}".prop_map(|s| format!("tool_{}", s)),
+            prop::collection::btree_map("[a-z]{1,8}", plain_text(), 0..4)
+                .prop_map(|m| m.into_iter().map(|(k, v)| (k, Value::String(v))).collect()),
+        )
+    }
+
+    fn text_of(content: &Value) -> String {
+        visible_parts(content).iter().filter_map(|p| p["text"].as_str()).collect()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_claude_conversation_keeps_roles_and_text(turns in conversation()) {
+            let messages: Vec<Value> = turns
+                .iter()
+                .enumerate()
+                .map(|(i, text)| json!({ "role": if i % 2 == 0 { "user" } else { "assistant" }, "content": text }))
+                .collect();
+            let req: ClaudeRequest = serde_json::from_value(json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "messages": messages
+            }))
+            .unwrap();
+            let body = transform_claude_request_in(&req, "test-project").unwrap();
+            let contents = body["request"]["contents"].as_array().unwrap();
+
+            prop_assert_eq!(contents.len(), turns.len());
+            for (i, (content, text)) in contents.iter().zip(&turns).enumerate() {
+                prop_assert_eq!(content["role"].as_str(), Some(if i % 2 == 0 { "user" } else { "model" }));
+                prop_assert_eq!(&text_of(content), text);
+            }
+        }
+
+        #[test]
+        fn prop_openai_conversation_keeps_roles_and_text(system in plain_text(), turns in conversation()) {
+            let mut messages = vec![json!({ "role": "system", "content": system })];
+            messages.extend(
+                turns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, text)| json!({ "role": if i % 2 == 0 { "user" } else { "assistant" }, "content": text })),
+            );
+            let req: OpenAIRequest = serde_json::from_value(json!({ "model": "gpt-4o", "messages": messages })).unwrap();
+            let body = transform_openai_request(&req, "test-project", "gemini-2.5-flash", None);
+            let request = &body["request"];
+            let contents = request["contents"].as_array().unwrap();
+
+            prop_assert!(request["systemInstruction"]["parts"].to_string().contains(system.as_str()));
+            prop_assert_eq!(contents.len(), turns.len());
+            for (i, (content, text)) in contents.iter().zip(&turns).enumerate() {
+                prop_assert_eq!(content["role"].as_str(), Some(if i % 2 == 0 { "user" } else { "model" }));
+                prop_assert_eq!(&text_of(content), text);
+            }
+        }
+
+        #[test]
+        fn prop_claude_tool_use_round_trips((name, args) in tool_call(), id in "[a-zA-Z0-9]{8}") {
+            let id = format!("toolu_{}", id);
+            let req: ClaudeRequest = serde_json::from_value(json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "tools": [{ "name": name, "description": "generated", "input_schema": { "type": "object" } }],
+                "messages": [
+                    { "role": "user", "content": "go" },
+                    { "role": "assistant", "content": [{ "type": "tool_use", "id": id, "name": name, "input": args }] },
+                    { "role": "user", "content": [{ "type": "tool_result", "tool_use_id": id, "content": "ok" }] }
+                ]
+            }))
+            .unwrap();
+            let body = transform_claude_request_in(&req, "test-project").unwrap();
+            let upstream = echo_model_turn(&body["request"]["contents"], "STOP", json!({ "promptTokenCount": 1 }));
+            let gemini: GeminiResponse = serde_json::from_value(upstream).unwrap();
+            let claude = transform_response(&gemini, Default::default(), Default::default()).unwrap();
+
+            prop_assert_eq!(claude.stop_reason.as_str(), "tool_use");
+            let tool_uses: Vec<(&String, &String, &Value)> = claude
+                .content
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolUse { id, name, input, .. } => Some((id, name, input)),
+                    _ => None,
+                })
+                .collect();
+            prop_assert_eq!(tool_uses.len(), 1);
+            prop_assert_eq!(tool_uses[0].0, &id);
+            prop_assert_eq!(tool_uses[0].1, &name);
+            prop_assert_eq!(tool_uses[0].2, &Value::Object(args));
+        }
+
+        #[test]
+        fn prop_openai_tool_call_round_trips((name, args) in tool_call()) {
+            let arguments = serde_json::to_string(&args).unwrap();
+            let req: OpenAIRequest = serde_json::from_value(json!({
+                "model": "gpt-4o",
+                "messages": [
+                    { "role": "user", "content": "go" },
+                    { "role": "assistant", "content": null, "tool_calls": [{
+                        "id": "call_1", "type": "function", "function": { "name": name, "arguments": arguments }
+                    }] },
+                    { "role": "tool", "tool_call_id": "call_1", "content": "ok" }
+                ],
+                "tools": [{ "type": "function", "function": { "name": name, "parameters": { "type": "object" } } }]
+            }))
+            .unwrap();
+            let body = transform_openai_request(&req, "test-project", "gemini-2.5-flash", None);
+            let upstream = echo_model_turn(&body["request"]["contents"], "STOP", json!({ "promptTokenCount": 1 }));
+            let response = transform_openai_response(&upstream, &Default::default(), Default::default());
+
+            let calls = response.choices[0].message.tool_calls.as_ref().unwrap();
+            prop_assert_eq!(calls.len(), 1);
+            prop_assert_eq!(&calls[0].function.name, &name);
+            let round_tripped: Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+            prop_assert_eq!(round_tripped, Value::Object(args));
+        }
+
+        #[test]
+        fn prop_finish_reasons_map_to_known_values(reason in "[A-Z_]{0,24}", used_tool: bool) {
+            use crate::proxy::mappers::finish_reason::{to_claude_stop_reason, to_openai_finish_reason};
+            prop_assert!(["stop", "length", "content_filter"].contains(&to_openai_finish_reason(&reason)));
+            let stop = to_claude_stop_reason(Some(&reason), used_tool, false);
+            prop_assert!(["end_turn", "max_tokens", "tool_use", "refusal"].contains(&stop));
+            // 非安全拦截时，使用了工具的轮次总是以 tool_use 结束
+            if used_tool && stop != "refusal" {
+                prop_assert_eq!(stop, "tool_use");
+            }
+        }
+
+        #[test]
+        fn prop_usage_is_preserved(prompt in 0u32..30_000, candidates in 0u32..100_000, thoughts in prop::option::of(0u32..100_000)) {
+            let mut usage = json!({ "promptTokenCount": prompt, "candidatesTokenCount": candidates });
+            if let Some(thoughts) = thoughts {
+                usage["thoughtsTokenCount"] = json!(thoughts);
+            }
+            let candidate = json!([{ "content": { "role": "model", "parts": [{ "text": "done" }] }, "finishReason": "STOP" }]);
+
+            let response = transform_openai_response(
+                &json!({ "candidates": candidate, "usageMetadata": usage }),
+                &Default::default(),
+                Default::default(),
+            );
+            let openai = response.usage.unwrap();
+            let completion = candidates + thoughts.unwrap_or(0);
+            prop_assert_eq!((openai.prompt_tokens, openai.completion_tokens, openai.total_tokens), (prompt, completion, prompt + completion));
+
+            // Claude 对超过 30k 的输入做缩放，阈值以内原样上报
+            let gemini: GeminiResponse = serde_json::from_value(json!({ "candidates": candidate, "usageMetadata": usage })).unwrap();
+            let claude = transform_response(&gemini, Default::default(), Default::default()).unwrap();
+            prop_assert_eq!((claude.usage.input_tokens, claude.usage.output_tokens), (prompt, candidates));
+        }
+    }
+}
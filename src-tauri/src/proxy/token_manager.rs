@@ -1,12 +1,76 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::proxy::account_stats::AccountStatsStore;
+use crate::proxy::runtime_state::{write_atomic, RuntimeStatePersister};
+use crate::modules::quota_forecast::{AccountForecast, QuotaHistory};
+use crate::proxy::model_access::ModelAccessTracker;
 use crate::proxy::rate_limit::RateLimitTracker;
+use crate::proxy::quota_groups::{AccountFilter, QuotaGroupRequest, QuotaGroups};
+use crate::proxy::usage_limits::{CapStatus, UsageLimiter};
+use crate::proxy::session_bindings::SessionBindings;
 use crate::proxy::sticky_config::StickySessionConfig;
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    /// 本次请求固定使用的账号 (account_id 或 email)，由鉴权中间件根据 X-Account 头设置
+    static PINNED_ACCOUNT: String;
+    /// 派生任务所属请求的取消令牌，请求被丢弃 (客户端断开) 时触发
+    static REQUEST_CANCEL: CancellationToken;
+}
+
+/// 当前请求固定的账号 (未固定时为 None)
+pub fn pinned_account() -> Option<String> {
+    PINNED_ACCOUNT.try_with(|account| account.clone()).ok()
+}
+
+/// 在固定账号的上下文中执行 `fut`，其中的 `get_token` 调用绕过调度直接使用该账号
+/// 派生任务 (tokio::spawn) 不继承上下文，需要先取出 `pinned_account()` 再以此包装
+pub async fn with_pinned_account<F: std::future::Future>(account: Option<String>, fut: F) -> F::Output {
+    match account {
+        Some(account) => PINNED_ACCOUNT.scope(account, fut).await,
+        None => fut.await,
+    }
+}
+
+/// 在请求取消令牌的上下文中执行 `fut`
+/// 请求内直接 await 的调用会随请求 future 一起被丢弃，无需令牌；
+/// 只有脱离请求 future 的派生任务 (如并发图片生成) 需要以此包装，并由请求持有令牌的 DropGuard
+pub async fn with_cancellation<F: std::future::Future>(token: CancellationToken, fut: F) -> F::Output {
+    REQUEST_CANCEL.scope(token, fut).await
+}
+
+/// 执行与请求绑定的网络操作 (token 刷新 / 实时配额刷新)：所属请求已取消时立即放弃并返回 None
+async fn cancellable<F: std::future::Future>(fut: F) -> Option<F::Output> {
+    match REQUEST_CANCEL.try_with(|token| token.clone()).ok() {
+        Some(token) => tokio::select! {
+            biased;
+            _ = token.cancelled() => None,
+            output = fut => Some(output),
+        },
+        None => Some(fut.await),
+    }
+}
+
+/// 账号池大面积过期判定：至少这么多账号、且不低于该比例的账号即将过期 (如笔记本休眠唤醒后)
+const BURST_REFRESH_MIN_EXPIRED: usize = 3;
+const BURST_REFRESH_MIN_RATIO: f64 = 0.5;
+/// 批量刷新的并发上限与整体超时 (超时后未完成的账号回退到逐个刷新)
+const BURST_REFRESH_CONCURRENCY: usize = 8;
+const BURST_REFRESH_TIMEOUT_SECS: u64 = 20;
+/// 批量刷新未能恢复账号池 (超时 / 离线) 后的冷却时间，期间请求不再触发批量刷新，避免每个请求都等满超时
+const BURST_REFRESH_COOLDOWN_SECS: i64 = 60;
+/// token 在过期前该秒数内即视为需要刷新 (与取 Token 时的提前刷新窗口一致)
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 300;
+
+/// 过期账号数是否达到批量刷新的阈值
+fn is_widespread_expiry(expiring: usize, total: usize) -> bool {
+    expiring >= BURST_REFRESH_MIN_EXPIRED && expiring as f64 >= total as f64 * BURST_REFRESH_MIN_RATIO
+}
 
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -20,6 +84,8 @@ pub struct ProxyToken {
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
     pub remaining_quota: Option<i32>, // [FIX #563] Remaining quota for priority sorting
+    pub allowed_models: Option<HashSet<String>>, // 配额数据中列出的可用模型 (None 表示未知)
+    pub quota_reset_time: Option<String>, // 配额数据中最早的刷新时间 (ISO 8601，用于用量计数清零，避免在请求路径上读账号文件)
 }
 
 
@@ -30,20 +96,46 @@ pub struct TokenManager {
     data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
-    session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    session_accounts: Arc<SessionBindings>, // 新增：会话与账号映射 (SessionID -> AccountID，带 TTL 与 LRU 淘汰)
+    model_access: Arc<ModelAccessTracker>, // 新增：从 404/403 学习到的账号模型拒绝列表
+    usage_limiter: Arc<UsageLimiter>, // 新增：每日 Token 用量上限
+    quota_groups: Arc<QuotaGroups>, // 新增：配额分组规则与分组账号绑定
+    account_stats: Arc<AccountStatsStore>, // 新增：账号使用统计 (持久化到 account_stats.json)
+    runtime_state: Arc<RuntimeStatePersister>, // 新增：用量计数与限流锁定快照 (持久化到 runtime_state.json)
+    quota_history: Arc<std::sync::RwLock<QuotaHistory>>, // 新增：配额采样历史 (用于消耗预测)
+    last_activity: Arc<AtomicI64>, // 新增：最近一次取 Token 的时间戳 (用于空闲预热)
+    burst_refresh_lock: Arc<tokio::sync::Mutex<()>>, // 新增：批量刷新互斥 (并发请求共用同一轮刷新)
+    burst_refresh_failed_at: Arc<AtomicI64>, // 新增：最近一次批量刷新失败的时间戳 (0 表示无)
 }
 
 impl TokenManager {
     /// 创建新的 TokenManager
     pub fn new(data_dir: PathBuf) -> Self {
+        let stats_dir = data_dir.clone();
+        let rate_limit_tracker = Arc::new(RateLimitTracker::new());
+        let usage_limiter = Arc::new(UsageLimiter::new());
+        // 恢复上次运行 (包括崩溃) 遗留的用量计数与限流锁定
+        let runtime_state = RuntimeStatePersister::attach(&data_dir, &rate_limit_tracker, &usage_limiter);
         Self {
             tokens: Arc::new(DashMap::new()),
             current_index: Arc::new(AtomicUsize::new(0)),
             last_used_account: Arc::new(tokio::sync::Mutex::new(None)),
             data_dir,
-            rate_limit_tracker: Arc::new(RateLimitTracker::new()),
+            rate_limit_tracker,
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
-            session_accounts: Arc::new(DashMap::new()),
+            session_accounts: Arc::new({
+                let defaults = StickySessionConfig::default();
+                SessionBindings::new(defaults.session_ttl_secs, defaults.max_session_bindings)
+            }),
+            model_access: Arc::new(ModelAccessTracker::new()),
+            usage_limiter,
+            quota_groups: Arc::new(QuotaGroups::new()),
+            account_stats: Arc::new(AccountStatsStore::load(&stats_dir)),
+            runtime_state,
+            quota_history: Arc::new(std::sync::RwLock::new(QuotaHistory::new())),
+            last_activity: Arc::new(AtomicI64::new(chrono::Utc::now().timestamp())),
+            burst_refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+            burst_refresh_failed_at: Arc::new(AtomicI64::new(0)),
         }
     }
     
@@ -57,6 +149,7 @@ impl TokenManager {
 
         // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
         self.tokens.clear();
+        self.reload_quota_history();
         self.current_index.store(0, Ordering::SeqCst);
         {
             let mut last_used = self.last_used_account.lock().await;
@@ -104,6 +197,9 @@ impl TokenManager {
 
         match self.load_single_account(&path).await {
             Ok(Some(token)) => {
+                // 配额数据已同步 (模型清单为最新)，重新探测之前学习到的拒绝模型
+                self.model_access.clear(&token.email);
+                self.reload_quota_history();
                 self.tokens.insert(account_id.to_string(), token);
                 Ok(())
             }
@@ -204,6 +300,10 @@ impl TokenManager {
         let remaining_quota = account.get("quota")
             .map(|q| self.calculate_quota_stats(q).1) // (total, remaining) -> remaining
             .filter(|&r| r > 0);
+
+        // 配额数据中的模型列表即该账号可访问的模型
+        let allowed_models = crate::proxy::model_access::allowed_models_from_quota(account.get("quota"));
+        let quota_reset_time = earliest_quota_reset(account.get("quota"));
         
         Ok(Some(ProxyToken {
             account_id,
@@ -216,6 +316,8 @@ impl TokenManager {
             project_id,
             subscription_tier,
             remaining_quota,
+            allowed_models,
+            quota_reset_time,
         }))
     }
 
@@ -325,7 +427,7 @@ impl TokenManager {
             format!("quota_protection: {}/{} (阈值: {})", remaining, total, threshold)
         );
         
-        std::fs::write(account_path, serde_json::to_string_pretty(&content).unwrap())
+        write_atomic(account_path, serde_json::to_string_pretty(&content).unwrap().as_bytes())
             .map_err(|e| format!("写入文件失败: {}", e))?;
         
         tracing::info!("账号 {} 已被配额保护自动禁用", account_id);
@@ -391,61 +493,68 @@ impl TokenManager {
 
     
     /// 获取当前可用的 Token（支持粘性会话与智能调度）
-    /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
+    /// 参数 `request_type` 为内置请求类型 (agent / web_search / image_gen)，决定调度策略 (image_gen 不参与 60s 锁定)
+    /// 参数 `quota_group` 为配额分组 (默认与请求类型同名，用户规则可划入自定义分组)，决定可用账号范围
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
-    pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    /// 参数 `target_model` 为本次请求的上游模型，仅调度给能够服务该模型的账号
+    pub async fn get_token(&self, request_type: &str, quota_group: &str, force_rotate: bool, session_id: Option<&str>, target_model: Option<&str>) -> Result<(String, String, String), String> {
+        self.last_activity.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        // [NEW] 账号池大面积过期时先并发刷新，避免随后的请求逐个串行刷新
+        self.burst_refresh_if_needed().await;
+        // [NEW] X-Account 固定账号：绕过调度器 (限流 / 用量上限 / 粘性会话均不参与)
+        if let Some(account) = pinned_account() {
+            return self.get_token_for_account(&account).await;
+        }
         // 【优化 Issue #284】添加 5 秒超时，防止死锁
         let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id)).await {
+        match tokio::time::timeout(timeout_duration, self.get_token_internal(request_type, quota_group, force_rotate, session_id, target_model)).await {
             Ok(result) => result,
             Err(_) => Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
         }
     }
 
     /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
+    async fn get_token_internal(&self, request_type: &str, quota_group: &str, force_rotate: bool, session_id: Option<&str>, target_model: Option<&str>) -> Result<(String, String, String), String> {
         let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+        if tokens_snapshot.is_empty() {
             return Err("Token pool is empty".to_string());
         }
 
+        // 【新增】配额分组账号绑定 (硬约束，先于其他过滤)
+        tokens_snapshot = self.filter_quota_group_accounts(tokens_snapshot, quota_group)?;
+
+        // 【新增】按模型访问能力过滤账号 (允许列表 + 从 404/403 学习到的拒绝列表)
+        if let Some(model) = target_model {
+            tokens_snapshot = self.filter_capable_accounts(tokens_snapshot, model);
+        }
+
+        // 【新增】每日用量上限：硬上限严格排除，软上限尽量避开
+        tokens_snapshot = self.apply_usage_caps(tokens_snapshot).await?;
+        let total = tokens_snapshot.len();
+
         // ===== 【优化】根据订阅等级和剩余配额排序 =====
-        // [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
-        // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
-        //       高配額账号优先使用，避免低配额账号被用光
-        tokens_snapshot.sort_by(|a, b| {
-            let tier_priority = |tier: &Option<String>| match tier.as_deref() {
-                Some("ULTRA") => 0,
-                Some("PRO") => 1,
-                Some("FREE") => 2,
-                _ => 3,
-            };
-            
-            // First: compare by subscription tier
-            let tier_cmp = tier_priority(&a.subscription_tier)
-                .cmp(&tier_priority(&b.subscription_tier));
-            
-            if tier_cmp != std::cmp::Ordering::Equal {
-                return tier_cmp;
-            }
-            
-            // [FIX #563] Second: compare by remaining quota (higher is better)
-            // Accounts with unknown/zero quota go last within their tier
-            let quota_a = a.remaining_quota.unwrap_or(0);
-            let quota_b = b.remaining_quota.unwrap_or(0);
-            quota_b.cmp(&quota_a)  // Descending: higher quota first
-        });
+        sort_by_priority(&mut tokens_snapshot);
 
 
         // 0. 读取当前调度配置
         let scheduling = self.sticky_config.read().await.clone();
         use crate::proxy::sticky_config::SchedulingMode;
 
+        // 【新增】会话预轮换：避免把会话绑定到预计对话途中耗尽配额的账号
+        // 仅在存在其他可用账号时生效，否则照常使用
+        let mut exhausting = if session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
+            self.accounts_exhausting_within(scheduling.forecast_rotate_hours)
+        } else {
+            HashSet::new()
+        };
+        if tokens_snapshot.iter().all(|t| exhausting.contains(&t.account_id)) {
+            exhausting.clear();
+        }
+
         // 【优化 Issue #284】将锁操作移到循环外，避免重复获取锁
         // 预先获取 last_used_account 的快照，避免在循环中多次加锁
-        let last_used_account_id = if quota_group != "image_gen" {
+        let last_used_account_id = if request_type != "image_gen" {
             let last_used = self.last_used_account.lock().await;
             last_used.clone()
         } else {
@@ -467,7 +576,7 @@ impl TokenManager {
                 let sid = session_id.unwrap();
                 
                 // 1. 检查会话是否已绑定账号
-                if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
+                if let Some(bound_id) = self.session_accounts.get(sid) {
                     // 【修复】先通过 account_id 找到对应的账号，获取其 email
                     // 因为限流记录是以 email 为 key 存储的
                     if let Some(bound_token) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
@@ -481,6 +590,12 @@ impl TokenManager {
                                 sid, bound_token.email, reset_sec
                             );
                             self.session_accounts.remove(sid);
+                        } else if exhausting.contains(&bound_id) {
+                            tracing::info!(
+                                "Session {} bound account {} is forecast to exhaust quota within {}h. Pre-rotating to another account.",
+                                sid, bound_token.email, scheduling.forecast_rotate_hours
+                            );
+                            self.session_accounts.remove(sid);
                         } else if !attempted.contains(&bound_id) {
                             // 3. 账号可用且未被标记为尝试失败，优先复用
                             tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
@@ -495,10 +610,10 @@ impl TokenManager {
             }
 
             // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
-            if target_token.is_none() && !rotate && quota_group != "image_gen" {
+            if target_token.is_none() && !rotate && request_type != "image_gen" {
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
-                    if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
+                    if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) && !exhausting.contains(account_id) {
                         if let Some(found) = tokens_snapshot.iter().find(|t| &t.account_id == account_id) {
                             // 【修复】检查限流状态，避免复用已被锁定的账号
                             if !self.is_rate_limited(&found.email) {
@@ -514,10 +629,16 @@ impl TokenManager {
                 // 若无锁定，则轮询选择新账号
                 if target_token.is_none() {
                     let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
-                    for offset in 0..total {
+                    // 第一轮避开预计耗尽的账号，没有其他可用账号时第二轮不再避开
+                    for offset in 0..total * 2 {
                         let idx = (start_idx + offset) % total;
                         let candidate = &tokens_snapshot[idx];
-                        if attempted.contains(&candidate.account_id) {
+                        if offset >= total && exhausting.is_empty() {
+                            break;
+                        }
+                        if attempted.contains(&candidate.account_id)
+                            || (offset < total && exhausting.contains(&candidate.account_id))
+                        {
                             continue;
                         }
 
@@ -533,7 +654,7 @@ impl TokenManager {
                         // 如果是会话首次分配且需要粘性，在此建立绑定
                         if let Some(sid) = session_id {
                             if scheduling.mode != SchedulingMode::PerformanceFirst {
-                                self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
+                                self.session_accounts.bind(sid, &candidate.account_id);
                                 tracing::debug!("Sticky Session: Bound new account {} to session {}", candidate.email, sid);
                             }
                         }
@@ -634,8 +755,12 @@ impl TokenManager {
             if now >= token.timestamp - 300 {
                 tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
 
-                // 调用 OAuth 刷新 token
-                match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
+                // 调用 OAuth 刷新 token (所属请求已取消时放弃刷新)
+                let Some(refreshed) = cancellable(crate::modules::oauth::refresh_access_token(&token.refresh_token)).await else {
+                    tracing::debug!("请求已取消，放弃刷新账号 {} 的 token", token.email);
+                    return Err("Request cancelled".to_string());
+                };
+                match refreshed {
                     Ok(token_response) => {
                         tracing::debug!("Token 刷新成功！");
 
@@ -673,7 +798,7 @@ impl TokenManager {
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if request_type != "image_gen" {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -702,7 +827,7 @@ impl TokenManager {
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if request_type != "image_gen" {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -714,7 +839,7 @@ impl TokenManager {
 
             // 【优化】在成功返回前，统一更新 last_used_account（如果需要）
             if let Some((new_account_id, new_time)) = need_update_last_used {
-                if quota_group != "image_gen" {
+                if request_type != "image_gen" {
                     let mut last_used = self.last_used_account.lock().await;
                     if new_account_id.is_empty() {
                         // 空字符串表示需要清除锁定
@@ -731,6 +856,106 @@ impl TokenManager {
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
     }
 
+    /// 按配额分组的账号绑定过滤账号池
+    fn filter_quota_group_accounts(&self, tokens: Vec<ProxyToken>, quota_group: &str) -> Result<Vec<ProxyToken>, String> {
+        let filter = self.quota_groups.account_filter(quota_group);
+        if filter == AccountFilter::Any {
+            return Ok(tokens);
+        }
+        let kept: Vec<ProxyToken> = tokens.into_iter().filter(|t| filter.allows(&t.account_id, &t.email)).collect();
+        if kept.is_empty() {
+            return Err(match filter {
+                AccountFilter::Only(_) => format!("No accounts assigned to quota group '{}' are in the pool", quota_group),
+                _ => format!("All accounts are reserved by exclusive quota groups (group '{}')", quota_group),
+            });
+        }
+        Ok(kept)
+    }
+
+    /// 过滤出能够服务指定模型的账号
+    ///
+    /// - 已从 404/403 学习到无权访问的账号直接排除
+    /// - 账号配额数据列出了模型清单、且该模型出现在任一账号的清单中时，按清单判断
+    ///   (模型未出现在任何清单中说明配额数据不覆盖它，此时不做限制)
+    /// - 若过滤后没有任何账号，回退到完整列表，交由上游给出真实错误
+    fn filter_capable_accounts(&self, tokens: Vec<ProxyToken>, model: &str) -> Vec<ProxyToken> {
+        let normalized = crate::proxy::model_access::normalize_model(model);
+        let listed_anywhere = tokens
+            .iter()
+            .any(|t| t.allowed_models.as_ref().is_some_and(|m| m.contains(&normalized)));
+
+        let capable: Vec<ProxyToken> = tokens
+            .iter()
+            .filter(|t| {
+                if self.model_access.is_denied(&t.email, &normalized) {
+                    return false;
+                }
+                match &t.allowed_models {
+                    Some(allowed) if listed_anywhere => allowed.contains(&normalized),
+                    _ => true,
+                }
+            })
+            .cloned()
+            .collect();
+
+        if capable.is_empty() {
+            tracing::warn!(
+                "[ModelAccess] No account is known to serve model {}, falling back to all {} accounts",
+                model,
+                tokens.len()
+            );
+            return tokens;
+        }
+        if capable.len() < tokens.len() {
+            tracing::debug!(
+                "[ModelAccess] {} of {} accounts can serve model {}",
+                capable.len(),
+                tokens.len(),
+                model
+            );
+        }
+        capable
+    }
+
+    /// 按每日用量上限过滤账号
+    ///
+    /// - 超过硬上限的账号直接排除，全部超过时返回明确的错误
+    /// - 超过软上限的账号仅在没有其他账号可用时才会被使用
+    async fn apply_usage_caps(&self, tokens: Vec<ProxyToken>) -> Result<Vec<ProxyToken>, String> {
+        if !self.usage_limiter.is_enabled().await {
+            return Ok(tokens);
+        }
+
+        let mut under_soft = Vec::new();
+        let mut over_soft = Vec::new();
+        let mut min_reset: Option<i64> = None;
+        for token in tokens {
+            match self.usage_limiter.status(&token.email, token.subscription_tier.as_deref()).await {
+                CapStatus::Ok => under_soft.push(token),
+                CapStatus::OverSoft => over_soft.push(token),
+                CapStatus::OverHard { resets_in } => {
+                    tracing::debug!("[UsageLimit] {} reached its daily hard cap, skipping", token.email);
+                    min_reset = Some(min_reset.map_or(resets_in, |m| m.min(resets_in)));
+                }
+            }
+        }
+
+        if !under_soft.is_empty() {
+            return Ok(under_soft);
+        }
+        if !over_soft.is_empty() {
+            tracing::warn!(
+                "[UsageLimit] All available accounts are past their daily soft cap, using them anyway ({} accounts)",
+                over_soft.len()
+            );
+            return Ok(over_soft);
+        }
+        Err(format!(
+            "All accounts have reached their daily hard token cap. Counters reset in {}s.",
+            min_reset.unwrap_or(0)
+        ))
+    }
+
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
         let path = if let Some(entry) = self.tokens.get(account_id) {
             entry.account_path.clone()
@@ -750,7 +975,8 @@ impl TokenManager {
         content["disabled_at"] = serde_json::Value::Number(now.into());
         content["disabled_reason"] = serde_json::Value::String(truncate_reason(reason, 800));
 
-        std::fs::write(&path, serde_json::to_string_pretty(&content).unwrap())
+        // 禁用是关键状态变化：原子写入并落盘后再返回，崩溃也不会丢失或写坏账号文件
+        write_atomic(&path, serde_json::to_string_pretty(&content).unwrap().as_bytes())
             .map_err(|e| format!("写入文件失败: {}", e))?;
 
         tracing::warn!("Account disabled: {} ({:?})", account_id, path);
@@ -805,6 +1031,40 @@ impl TokenManager {
         self.tokens.len()
     }
 
+    /// 账号池限流概况 (账号级与按 email 记录的锁定均计入)
+    pub fn rate_limit_state(&self) -> crate::proxy::common::ratelimit_headers::PoolRateLimitState {
+        let mut state = crate::proxy::common::ratelimit_headers::PoolRateLimitState {
+            total: self.tokens.len(),
+            ..Default::default()
+        };
+        for entry in self.tokens.iter() {
+            let token = entry.value();
+            let wait = [token.email.as_str(), token.account_id.as_str()]
+                .iter()
+                .filter_map(|key| self.rate_limit_tracker.get_reset_seconds(key))
+                .filter(|secs| *secs > 0)
+                .max();
+            match wait {
+                Some(secs) => {
+                    state.soonest_reset_secs = Some(state.soonest_reset_secs.map_or(secs, |s| s.min(secs)));
+                }
+                None => state.available += 1,
+            }
+        }
+        state
+    }
+
+    /// 账号池中的账号 (account_id, email, subscription_tier)，按 email 排序
+    pub fn list_accounts(&self) -> Vec<(String, String, Option<String>)> {
+        let mut accounts: Vec<_> = self
+            .tokens
+            .iter()
+            .map(|e| (e.key().clone(), e.value().email.clone(), e.value().subscription_tier.clone()))
+            .collect();
+        accounts.sort_by(|a, b| a.1.cmp(&b.1));
+        accounts
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
     pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
@@ -873,6 +1133,184 @@ impl TokenManager {
         }
     }
     
+    /// 通过 account_id 或 email 获取指定账号的 Token (X-Account 固定账号)
+    pub async fn get_token_for_account(&self, account: &str) -> Result<(String, String, String), String> {
+        let email = self
+            .tokens
+            .get(account)
+            .map(|entry| entry.email.clone())
+            .or_else(|| {
+                self.tokens
+                    .iter()
+                    .find(|entry| entry.email.eq_ignore_ascii_case(account))
+                    .map(|entry| entry.email.clone())
+            })
+            .ok_or_else(|| format!("Pinned account not found in pool: {}", account))?;
+        tracing::debug!("[X-Account] Request pinned to {}", email);
+        self.get_token_by_email(&email).await
+    }
+
+    // ===== 空闲预热 =====
+
+    /// 在 `margin_secs` 内即将过期的账号
+    fn expiring_tokens(&self, margin_secs: i64) -> Vec<ProxyToken> {
+        let now = chrono::Utc::now().timestamp();
+        self.tokens
+            .iter()
+            .filter(|e| now >= e.value().timestamp - margin_secs)
+            .map(|e| e.value().clone())
+            .collect()
+    }
+
+    /// 刷新单个账号的 token 并同步内存与磁盘，返回新的 access_token
+    /// refresh_token 失效 (invalid_grant) 时禁用账号并移出账号池
+    async fn refresh_token_entry(&self, token: &ProxyToken) -> Result<String, String> {
+        let Some(refreshed) = cancellable(crate::modules::oauth::refresh_access_token(&token.refresh_token)).await else {
+            tracing::debug!("请求已取消，放弃刷新账号 {} 的 token", token.email);
+            return Err("Request cancelled".to_string());
+        };
+        match refreshed {
+            Ok(token_response) => {
+                let now = chrono::Utc::now().timestamp();
+                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                    entry.access_token = token_response.access_token.clone();
+                    entry.expires_in = token_response.expires_in;
+                    entry.timestamp = now + token_response.expires_in;
+                }
+                if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
+                    tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
+                }
+                Ok(token_response.access_token)
+            }
+            Err(e) => {
+                if e.contains("invalid_grant") {
+                    let _ = self
+                        .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
+                        .await;
+                    self.tokens.remove(&token.account_id);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// 账号池大面积过期时以有限并发批量刷新所有即将过期的 token
+    /// 并发到达的请求等待同一轮刷新完成后重新检查，不会重复刷新；
+    /// 刷新后仍大面积过期 (超时 / 离线) 时进入冷却，冷却期内回退到逐个刷新
+    async fn burst_refresh_if_needed(&self) {
+        if self.burst_refresh_cooling_down()
+            || !is_widespread_expiry(self.expiring_tokens(TOKEN_REFRESH_MARGIN_SECS).len(), self.tokens.len())
+        {
+            return;
+        }
+        let _guard = self.burst_refresh_lock.lock().await;
+        // 等锁期间上一轮可能已失败并进入冷却
+        if self.burst_refresh_cooling_down() {
+            return;
+        }
+        let expiring = self.expiring_tokens(TOKEN_REFRESH_MARGIN_SECS);
+        if !is_widespread_expiry(expiring.len(), self.tokens.len()) {
+            return;
+        }
+
+        use futures::StreamExt;
+        let total = expiring.len();
+        let started = std::time::Instant::now();
+        tracing::info!("[Burst-Refresh] {} / {} 个账号 token 已过期，开始并发刷新", total, self.tokens.len());
+        let refresh_all = futures::stream::iter(expiring)
+            .map(|token| async move {
+                let result = self.refresh_token_entry(&token).await;
+                if let Err(e) = &result {
+                    tracing::warn!("[Burst-Refresh] Token 刷新失败 ({}): {}", token.email, e);
+                }
+                result.is_ok()
+            })
+            .buffer_unordered(BURST_REFRESH_CONCURRENCY)
+            .collect::<Vec<bool>>();
+        match tokio::time::timeout(std::time::Duration::from_secs(BURST_REFRESH_TIMEOUT_SECS), refresh_all).await {
+            Ok(results) => {
+                let refreshed = results.iter().filter(|ok| **ok).count();
+                tracing::info!(
+                    "[Burst-Refresh] 完成: 成功 {}, 失败 {}, 耗时 {}ms",
+                    refreshed,
+                    total - refreshed,
+                    started.elapsed().as_millis()
+                );
+            }
+            Err(_) => tracing::warn!(
+                "[Burst-Refresh] 超时 ({}s)，剩余账号将在取用时逐个刷新",
+                BURST_REFRESH_TIMEOUT_SECS
+            ),
+        }
+
+        if is_widespread_expiry(self.expiring_tokens(TOKEN_REFRESH_MARGIN_SECS).len(), self.tokens.len()) {
+            tracing::warn!(
+                "[Burst-Refresh] 刷新后账号池仍大面积过期，{}s 内不再批量刷新",
+                BURST_REFRESH_COOLDOWN_SECS
+            );
+            self.burst_refresh_failed_at
+                .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        } else {
+            self.burst_refresh_failed_at.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// 最近一次批量刷新失败后是否仍处于冷却期
+    fn burst_refresh_cooling_down(&self) -> bool {
+        let failed_at = self.burst_refresh_failed_at.load(Ordering::Relaxed);
+        failed_at > 0 && chrono::Utc::now().timestamp() - failed_at < BURST_REFRESH_COOLDOWN_SECS
+    }
+
+    /// 距离最近一次取 Token 的秒数
+    pub fn idle_secs(&self) -> i64 {
+        chrono::Utc::now().timestamp() - self.last_activity.load(Ordering::Relaxed)
+    }
+
+    /// 刷新将在 `refresh_within_secs` 内过期的 token，并补全缺失的 project_id
+    /// 返回 (成功预热的账号数, 失败数)
+    pub async fn warm_up_idle_accounts(&self, refresh_within_secs: i64) -> (usize, usize) {
+        let snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        let (mut warmed, mut failed) = (0, 0);
+
+        for token in snapshot {
+            let now = chrono::Utc::now().timestamp();
+            let mut access_token = token.access_token.clone();
+            let needs_refresh = now >= token.timestamp - refresh_within_secs;
+            if !needs_refresh && token.project_id.is_some() {
+                continue;
+            }
+
+            if needs_refresh {
+                match self.refresh_token_entry(&token).await {
+                    Ok(refreshed) => access_token = refreshed,
+                    Err(e) => {
+                        tracing::warn!("[Idle-Warmup] Token 刷新失败 ({}): {}", token.email, e);
+                        failed += 1;
+                        continue;
+                    }
+                }
+            }
+
+            if token.project_id.is_none() {
+                match crate::proxy::project_resolver::fetch_project_id(&access_token).await {
+                    Ok(pid) => {
+                        if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
+                            entry.project_id = Some(pid.clone());
+                        }
+                        let _ = self.save_project_id(&token.account_id, &pid).await;
+                    }
+                    Err(e) => {
+                        tracing::warn!("[Idle-Warmup] 获取 project_id 失败 ({}): {}", token.email, e);
+                        failed += 1;
+                        continue;
+                    }
+                }
+            }
+            warmed += 1;
+        }
+        (warmed, failed)
+    }
+
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
@@ -923,47 +1361,173 @@ impl TokenManager {
         self.rate_limit_tracker.mark_success(account_id);
     }
     
+    // ===== 配额分组 =====
+
+    /// 更新配额分组规则
+    pub fn update_quota_groups(&self, config: crate::proxy::config::QuotaGroupConfig) {
+        self.quota_groups.update_config(config);
+        tracing::debug!("Quota group config updated");
+    }
+
+    /// 计算请求的配额分组 (未命中规则时为内置请求类型)
+    pub fn resolve_quota_group(
+        &self,
+        request_type: &str,
+        models: &[&str],
+        tools: &Option<Vec<serde_json::Value>>,
+        headers: &axum::http::HeaderMap,
+    ) -> String {
+        let group = self.quota_groups.resolve(&QuotaGroupRequest {
+            request_type,
+            models,
+            tools: tools.as_deref(),
+            client: Some(crate::proxy::quota_groups::client_from_headers(headers)),
+        });
+        if group != request_type {
+            tracing::debug!("[QuotaGroup] {} request assigned to group '{}'", request_type, group);
+        }
+        group
+    }
+
+    // ===== 每日用量上限 =====
+
+    /// 更新每日用量上限配置
+    pub async fn update_usage_limits(&self, config: crate::proxy::config::UsageLimitConfig) {
+        self.usage_limiter.update_config(config).await;
+        tracing::debug!("Daily usage limit config updated");
+    }
+
+    /// 计算账号用量计数的清零时间：优先使用账号的配额重置时间 (加载账号时缓存)，否则为下一个 UTC 零点
+    /// 流结束的 Drop 中也会调用，只读内存中的账号信息
+    fn next_usage_reset(tokens: &DashMap<String, ProxyToken>, email: &str) -> i64 {
+        let now = chrono::Utc::now().timestamp();
+        tokens
+            .iter()
+            .find(|entry| entry.value().email == email)
+            .and_then(|entry| entry.value().quota_reset_time.clone())
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.timestamp())
+            .filter(|ts| *ts > now)
+            .unwrap_or_else(crate::proxy::usage_limits::next_utc_midnight)
+    }
+
+    /// 记录账号用量 (非流式响应)
+    pub fn record_usage(&self, email: &str, tokens: u64) {
+        self.usage_limiter
+            .record(email, tokens, || Self::next_usage_reset(&self.tokens, email));
+        self.account_stats.record_tokens(&self.stats_key(email), tokens);
+    }
+
+    /// 旁路统计上游 SSE 流的用量，流结束时计入账号
+    pub fn track_usage_stream<S, E>(
+        &self,
+        email: &str,
+        stream: S,
+    ) -> impl futures::Stream<Item = Result<bytes::Bytes, E>>
+    where
+        S: futures::Stream<Item = Result<bytes::Bytes, E>>,
+    {
+        let tokens = self.tokens.clone();
+        let owner = email.to_string();
+        let limiter = self.usage_limiter.clone();
+        let stats = self.account_stats.clone();
+        let stats_key = self.stats_key(email);
+        crate::proxy::usage_limits::tap_usage(stream, move |used| {
+            limiter.record(&owner, used, || Self::next_usage_reset(&tokens, &owner));
+            stats.record_tokens(&stats_key, used);
+        })
+    }
+
+    // ===== 账号使用统计 =====
+
+    /// 统计以账号 ID 为键 (与 UI 账号列表一致)，未知账号退回使用 email
+    fn stats_key(&self, email: &str) -> String {
+        self.tokens
+            .iter()
+            .find(|entry| entry.value().email == email)
+            .map(|entry| entry.key().clone())
+            .unwrap_or_else(|| email.to_string())
+    }
+
+    /// 记录一次请求的结果与响应延迟
+    pub fn record_request_stats(&self, email: &str, success: bool, latency_ms: u64) {
+        self.account_stats
+            .record_request(&self.stats_key(email), success, latency_ms);
+    }
+
+    /// 所有账号的累计统计 (account_id -> stats)
+    pub fn account_stats(&self) -> std::collections::HashMap<String, crate::proxy::account_stats::AccountStats> {
+        self.account_stats.snapshot()
+    }
+
+    /// 启动统计与运行时状态的定期持久化
+    pub fn start_stats_persistence(&self) {
+        self.account_stats.start_persistence();
+        self.runtime_state.start_snapshots();
+    }
+
+    /// 立即写入统计与运行时状态 (服务停止时调用)
+    pub fn persist_state(&self) {
+        if let Err(e) = self.account_stats.flush() {
+            tracing::warn!("[Account-Stats] {}", e);
+        }
+        self.runtime_state.persist();
+    }
+
+    // ===== 配额消耗预测 =====
+
+    fn reload_quota_history(&self) {
+        let history = crate::modules::quota_forecast::load_history(&self.data_dir);
+        if let Ok(mut guard) = self.quota_history.write() {
+            *guard = history;
+        }
+    }
+
+    /// 所有账号的配额消耗预测 (account_id -> forecast)
+    pub fn quota_forecasts(&self) -> HashMap<String, AccountForecast> {
+        let now = chrono::Utc::now().timestamp();
+        self.quota_history
+            .read()
+            .map(|history| crate::modules::quota_forecast::forecast_all(&history, now))
+            .unwrap_or_default()
+    }
+
+    /// 预计在 hours 小时内 (且在配额重置前) 耗尽的账号
+    fn accounts_exhausting_within(&self, hours: f64) -> HashSet<String> {
+        if hours <= 0.0 {
+            return HashSet::new();
+        }
+        self.quota_forecasts()
+            .into_iter()
+            .filter(|(_, forecast)| forecast.exhausts_within(hours))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    // ===== 模型访问控制 =====
+
+    /// 根据上游错误学习账号的模型访问能力
+    ///
+    /// 若错误表明该账号无权访问此模型 (模型级 404 / 403)，记录后调度器将不再为该模型选择此账号。
+    /// 返回 true 表示已记录，调用方应轮换账号重试
+    pub fn record_model_access_error(&self, email: &str, model: &str, status: u16, error_text: &str) -> bool {
+        if !crate::proxy::model_access::is_model_access_error(status, error_text, model) {
+            return false;
+        }
+        self.model_access.record_denied(email, model);
+        true
+    }
+
+    /// 获取账号已学习到的不可用模型列表 (/status 展示)
+    pub fn get_denied_models(&self, email: &str) -> Vec<String> {
+        self.model_access.denied_models(email)
+    }
+    
     /// 从账号文件获取配额刷新时间
     /// 
     /// 返回该账号最近的配额刷新时间字符串（ISO 8601 格式）
     pub fn get_quota_reset_time(&self, email: &str) -> Option<String> {
-        // 尝试从账号文件读取配额信息
-        let accounts_dir = self.data_dir.join("accounts");
-        
-        // 遍历账号文件查找对应的 email
-        if let Ok(entries) = std::fs::read_dir(&accounts_dir) {
-            for entry in entries.flatten() {
-                if let Ok(content) = std::fs::read_to_string(entry.path()) {
-                    if let Ok(account) = serde_json::from_str::<serde_json::Value>(&content) {
-                        // 检查 email 是否匹配
-                        if account.get("email").and_then(|e| e.as_str()) == Some(email) {
-                            // 获取 quota.models 中最早的 reset_time
-                            if let Some(models) = account
-                                .get("quota")
-                                .and_then(|q| q.get("models"))
-                                .and_then(|m| m.as_array()) 
-                            {
-                                // 找到最早的 reset_time（最保守的锁定策略）
-                                let mut earliest_reset: Option<&str> = None;
-                                for model in models {
-                                    if let Some(reset_time) = model.get("reset_time").and_then(|r| r.as_str()) {
-                                        if !reset_time.is_empty() {
-                                            if earliest_reset.is_none() || reset_time < earliest_reset.unwrap() {
-                                                earliest_reset = Some(reset_time);
-                                            }
-                                        }
-                                    }
-                                }
-                                if let Some(reset) = earliest_reset {
-                                    return Some(reset.to_string());
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        None
+        read_quota_reset_time(&self.data_dir, email)
     }
     
     /// 使用配额刷新时间精确锁定账号
@@ -1017,9 +1581,13 @@ impl TokenManager {
             }
         };
         
-        // 2. 调用配额刷新 API
+        // 2. 调用配额刷新 API (所属请求已取消时放弃，由调用方回退到本地策略)
         tracing::info!("账号 {} 正在实时刷新配额...", email);
-        match crate::modules::quota::fetch_quota(&access_token, email).await {
+        let Some(fetched) = cancellable(crate::modules::quota::fetch_quota(&access_token, email)).await else {
+            tracing::info!("请求已取消，放弃账号 {} 的实时配额刷新", email);
+            return false;
+        };
+        match fetched {
             Ok((quota_data, _project_id)) => {
                 // 3. 从最新配额中提取 reset_time
                 let earliest_reset = quota_data.models.iter()
@@ -1136,6 +1704,8 @@ impl TokenManager {
 
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
+        self.session_accounts
+            .configure(new_config.session_ttl_secs, new_config.max_session_bindings);
         let mut config = self.sticky_config.write().await;
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
@@ -1151,13 +1721,637 @@ impl TokenManager {
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
     }
+
+    /// 当前会话绑定，按账号分组 (account_id, email, bindings)
+    pub fn session_bindings_by_account(
+        &self,
+    ) -> Vec<(String, String, Vec<crate::proxy::session_bindings::SessionBindingInfo>)> {
+        let mut grouped: HashMap<String, Vec<_>> = HashMap::new();
+        for binding in self.session_accounts.list() {
+            grouped.entry(binding.account_id.clone()).or_default().push(binding);
+        }
+        let mut result: Vec<_> = grouped
+            .into_iter()
+            .map(|(account_id, bindings)| {
+                let email = self
+                    .tokens
+                    .get(&account_id)
+                    .map(|t| t.email.clone())
+                    .unwrap_or_default();
+                (account_id, email, bindings)
+            })
+            .collect();
+        result.sort_by(|a, b| b.2.len().cmp(&a.2.len()).then_with(|| a.1.cmp(&b.1)));
+        result
+    }
+
+    /// 会话绑定流转统计
+    pub fn session_binding_stats(&self) -> crate::proxy::session_bindings::SessionBindingStats {
+        self.session_accounts.stats()
+    }
+
+    // ===== 调度决策预演 (Dry-run) =====
+
+    /// 预演 get_token 的首次选择 (不轮换、不刷新 Token、不修改绑定与轮询指针)
+    ///
+    /// 逐步记录过滤与选择的原因：模型访问能力、用量上限、等级排序、限流锁定、
+    /// 粘性会话绑定、60s 全局锁定与轮询位置
+    pub async fn explain_selection(
+        &self,
+        request_type: &str,
+        quota_group: &str,
+        session_id: Option<&str>,
+        target_model: Option<&str>,
+    ) -> SchedulerDecision {
+        use crate::proxy::sticky_config::SchedulingMode;
+
+        let scheduling = self.sticky_config.read().await.clone();
+        let mut decision = SchedulerDecision {
+            quota_group: quota_group.to_string(),
+            model: target_model.map(str::to_string),
+            session_id: session_id.map(str::to_string),
+            scheduling_mode: scheduling.mode,
+            selected: None,
+            reason: "none",
+            steps: Vec::new(),
+            candidates: Vec::new(),
+        };
+
+        let all: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        if all.is_empty() {
+            decision.steps.push("Token pool is empty".to_string());
+            return decision;
+        }
+        let pool_ids = |tokens: &[ProxyToken]| -> HashSet<String> { tokens.iter().map(|t| t.account_id.clone()).collect() };
+
+        // 0. 配额分组账号绑定
+        let mut pool = all.clone();
+        let group_filter = self.quota_groups.account_filter(quota_group);
+        if group_filter != AccountFilter::Any {
+            pool.retain(|t| group_filter.allows(&t.account_id, &t.email));
+            for t in all.iter().filter(|t| !group_filter.allows(&t.account_id, &t.email)) {
+                decision.candidates.push(SchedulerCandidate::excluded(
+                    t,
+                    "quota_group",
+                    format!("Not available to quota group '{}'", quota_group),
+                ));
+            }
+            decision.steps.push(format!("Quota group '{}': {} of {} accounts available", quota_group, pool.len(), all.len()));
+        }
+
+        if pool.is_empty() {
+            return decision;
+        }
+
+        // 1. 模型访问能力
+        if let Some(model) = target_model {
+            let before = pool.clone();
+            pool = self.filter_capable_accounts(pool, model);
+            let kept = pool_ids(&pool);
+            let normalized = crate::proxy::model_access::normalize_model(model);
+            for t in before.iter().filter(|t| !kept.contains(&t.account_id)) {
+                let (status, detail) = if self.model_access.is_denied(&t.email, &normalized) {
+                    ("model_denied", format!("Upstream previously rejected {} for this account (404/403)", model))
+                } else {
+                    ("model_not_in_allowlist", format!("{} is not listed in this account's quota models", model))
+                };
+                decision.candidates.push(SchedulerCandidate::excluded(t, status, detail));
+            }
+            decision.steps.push(format!("Model filter: {} of {} accounts can serve {}", pool.len(), before.len(), model));
+        }
+
+        // 2. 每日用量上限
+        if self.usage_limiter.is_enabled().await {
+            let before = pool.clone();
+            match self.apply_usage_caps(pool).await {
+                Ok(kept_tokens) => {
+                    let kept = pool_ids(&kept_tokens);
+                    for t in before.iter().filter(|t| !kept.contains(&t.account_id)) {
+                        let status = match self.usage_limiter.status(&t.email, t.subscription_tier.as_deref()).await {
+                            CapStatus::OverHard { .. } => "over_hard_cap",
+                            _ => "over_soft_cap",
+                        };
+                        decision.candidates.push(SchedulerCandidate::excluded(t, status, "Daily token usage cap".to_string()));
+                    }
+                    decision.steps.push(format!("Usage caps: {} of {} accounts remain", kept_tokens.len(), before.len()));
+                    pool = kept_tokens;
+                }
+                Err(e) => {
+                    for t in &before {
+                        decision.candidates.push(SchedulerCandidate::excluded(t, "over_hard_cap", "Daily token usage cap".to_string()));
+                    }
+                    decision.steps.push(e);
+                    return decision;
+                }
+            }
+        }
+
+        // 3. 等级 / 配额排序与限流状态
+        sort_by_priority(&mut pool);
+        let lockout = |t: &ProxyToken| -> Option<String> {
+            [t.email.as_str(), t.account_id.as_str()]
+                .iter()
+                .find_map(|key| {
+                    let wait = self.rate_limit_tracker.get_reset_seconds(key).filter(|s| *s > 0)?;
+                    let reason = self.rate_limit_tracker.get(key).map(|i| i.reason);
+                    Some(format!("Locked out for {}s ({:?})", wait, reason.unwrap_or(crate::proxy::rate_limit::RateLimitReason::Unknown)))
+                })
+        };
+        for (rank, t) in pool.iter().enumerate() {
+            let mut candidate = SchedulerCandidate::eligible(t, rank);
+            if let Some(detail) = lockout(t) {
+                candidate.status = "rate_limited";
+                candidate.detail = Some(detail);
+            }
+            decision.candidates.push(candidate);
+        }
+        decision.steps.push(format!(
+            "Priority order (tier, then remaining quota): {}",
+            pool.iter().map(|t| t.email.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+        let total = pool.len();
+
+        // 4. 粘性会话
+        let mut selected: Option<(&ProxyToken, &'static str)> = None;
+        match (session_id, scheduling.mode) {
+            (Some(_), SchedulingMode::PerformanceFirst) => {
+                decision.steps.push("Sticky session ignored in PerformanceFirst mode".to_string());
+            }
+            (Some(sid), _) => match self.session_accounts.peek(sid) {
+                Some(bound_id) => match pool.iter().find(|t| t.account_id == bound_id) {
+                    Some(bound) if self.rate_limit_tracker.get_remaining_wait(&bound.email) > 0 => {
+                        decision.steps.push(format!(
+                            "Session is bound to {} but it is rate-limited; the binding would be dropped",
+                            bound.email
+                        ));
+                    }
+                    Some(bound) => {
+                        decision.steps.push(format!("Session is bound to {}; reusing it", bound.email));
+                        selected = Some((bound, "sticky_session"));
+                    }
+                    None => {
+                        decision.steps.push(format!(
+                            "Session is bound to account {} which is no longer eligible; the binding would be dropped",
+                            bound_id
+                        ));
+                    }
+                },
+                None => decision.steps.push("Session has no bound account yet; the selected account would be bound".to_string()),
+            },
+            (None, _) => {}
+        }
+
+        // 5. 60s 全局锁定 (image_gen 不参与)
+        if selected.is_none() && request_type != "image_gen" {
+            let last_used = self.last_used_account.lock().await.clone();
+            if let Some((account_id, last_time)) = last_used {
+                let elapsed = last_time.elapsed().as_secs();
+                match pool.iter().find(|t| t.account_id == account_id) {
+                    Some(found) if elapsed < 60 && !self.is_rate_limited(&found.email) => {
+                        decision.steps.push(format!("60s lock: {} was used {}s ago; reusing it", found.email, elapsed));
+                        selected = Some((found, "last_used_window"));
+                    }
+                    Some(found) if elapsed < 60 => {
+                        decision.steps.push(format!("60s lock: {} was used {}s ago but is rate-limited; skipping", found.email, elapsed));
+                    }
+                    Some(found) => {
+                        decision.steps.push(format!("60s lock expired: {} was used {}s ago", found.email, elapsed));
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // 6. 轮询
+        if selected.is_none() {
+            let start_idx = self.current_index.load(Ordering::SeqCst) % total;
+            let skipped: Vec<&str> = (0..total)
+                .map(|offset| &pool[(start_idx + offset) % total])
+                .take_while(|t| self.is_rate_limited(&t.account_id))
+                .map(|t| t.email.as_str())
+                .collect();
+            if let Some(t) = (0..total)
+                .map(|offset| &pool[(start_idx + offset) % total])
+                .find(|t| !self.is_rate_limited(&t.account_id))
+            {
+                let mut step = format!("Round-robin from position {}: {}", start_idx, t.email);
+                if !skipped.is_empty() {
+                    step.push_str(&format!(" (skipped locked-out: {})", skipped.join(", ")));
+                }
+                decision.steps.push(step);
+                selected = Some((t, "round_robin"));
+            } else {
+                decision.steps.push(
+                    "All eligible accounts are rate-limited; get_token would wait briefly or return an error".to_string(),
+                );
+            }
+        }
+
+        if let Some((token, reason)) = selected {
+            decision.reason = reason;
+            decision.selected = decision
+                .candidates
+                .iter()
+                .find(|c| c.account_id == token.account_id)
+                .cloned();
+        }
+        decision
+    }
+}
+
+/// 调度预演中的单个账号
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SchedulerCandidate {
+    pub account_id: String,
+    pub email: String,
+    pub subscription_tier: Option<String>,
+    pub remaining_quota: Option<i32>,
+    /// 排序位置 (被过滤的账号为 None)
+    pub rank: Option<usize>,
+    /// eligible / rate_limited / model_denied / model_not_in_allowlist / over_soft_cap / over_hard_cap
+    pub status: &'static str,
+    pub detail: Option<String>,
+}
+
+impl SchedulerCandidate {
+    fn eligible(token: &ProxyToken, rank: usize) -> Self {
+        Self {
+            account_id: token.account_id.clone(),
+            email: token.email.clone(),
+            subscription_tier: token.subscription_tier.clone(),
+            remaining_quota: token.remaining_quota,
+            rank: Some(rank),
+            status: "eligible",
+            detail: None,
+        }
+    }
+
+    fn excluded(token: &ProxyToken, status: &'static str, detail: String) -> Self {
+        Self {
+            rank: None,
+            status,
+            detail: Some(detail),
+            ..Self::eligible(token, 0)
+        }
+    }
+}
+
+/// 调度预演结果
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SchedulerDecision {
+    pub quota_group: String,
+    pub model: Option<String>,
+    pub session_id: Option<String>,
+    pub scheduling_mode: crate::proxy::sticky_config::SchedulingMode,
+    pub selected: Option<SchedulerCandidate>,
+    /// sticky_session / last_used_window / round_robin / none
+    pub reason: &'static str,
+    /// 按顺序记录的决策步骤
+    pub steps: Vec<String>,
+    pub candidates: Vec<SchedulerCandidate>,
+}
+
+/// 按订阅等级和剩余配额排序
+///
+/// [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
+/// 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
+///       高配額账号优先使用，避免低配额账号被用光
+fn sort_by_priority(tokens: &mut [ProxyToken]) {
+    tokens.sort_by(|a, b| {
+        let tier_priority = |tier: &Option<String>| match tier.as_deref() {
+            Some("ULTRA") => 0,
+            Some("PRO") => 1,
+            Some("FREE") => 2,
+            _ => 3,
+        };
+
+        // First: compare by subscription tier
+        let tier_cmp = tier_priority(&a.subscription_tier)
+            .cmp(&tier_priority(&b.subscription_tier));
+
+        if tier_cmp != std::cmp::Ordering::Equal {
+            return tier_cmp;
+        }
+
+        // [FIX #563] Second: compare by remaining quota (higher is better)
+        // Accounts with unknown/zero quota go last within their tier
+        let quota_a = a.remaining_quota.unwrap_or(0);
+        let quota_b = b.remaining_quota.unwrap_or(0);
+        quota_b.cmp(&quota_a)  // Descending: higher quota first
+    });
+}
+
+/// 从账号文件读取 quota.models 中最早的配额刷新时间
+fn read_quota_reset_time(data_dir: &std::path::Path, email: &str) -> Option<String> {
+    // 尝试从账号文件读取配额信息
+    let accounts_dir = data_dir.join("accounts");
+    
+    // 遍历账号文件查找对应的 email
+    if let Ok(entries) = std::fs::read_dir(&accounts_dir) {
+        for entry in entries.flatten() {
+            if let Ok(content) = std::fs::read_to_string(entry.path()) {
+                if let Ok(account) = serde_json::from_str::<serde_json::Value>(&content) {
+                    // 检查 email 是否匹配
+                    if account.get("email").and_then(|e| e.as_str()) == Some(email) {
+                        if let Some(reset) = earliest_quota_reset(account.get("quota")) {
+                            return Some(reset);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// quota.models 中最早的 reset_time（最保守的锁定策略）
+fn earliest_quota_reset(quota: Option<&serde_json::Value>) -> Option<String> {
+    quota?
+        .get("models")?
+        .as_array()?
+        .iter()
+        .filter_map(|model| model.get("reset_time").and_then(|r| r.as_str()))
+        .filter(|reset_time| !reset_time.is_empty())
+        .min()
+        .map(str::to_string)
 }
 
 fn truncate_reason(reason: &str, max_len: usize) -> String {
-    if reason.chars().count() <= max_len {
+    let head = crate::proxy::common::text_preview::truncate_chars(reason, max_len);
+    if head.len() == reason.len() {
         return reason.to_string();
     }
-    let mut s: String = reason.chars().take(max_len).collect();
-    s.push('…');
-    s
+    format!("{}…", head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(id: &str, allowed: Option<&[&str]>) -> ProxyToken {
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: format!("at-{}", id),
+            refresh_token: format!("rt-{}", id),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: format!("{}@example.com", id),
+            account_path: PathBuf::from(format!("/tmp/{}.json", id)),
+            project_id: Some(format!("project-{}", id)),
+            subscription_tier: None,
+            remaining_quota: None,
+            allowed_models: allowed.map(|m| m.iter().map(|s| s.to_string()).collect()),
+            quota_reset_time: None,
+        }
+    }
+
+    /// 每个测试使用独立的数据目录，避免恢复其它测试写入的运行时状态快照
+    fn test_manager() -> TokenManager {
+        let dir = std::env::temp_dir().join(format!("token-manager-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        TokenManager::new(dir)
+    }
+
+    fn ids(tokens: &[ProxyToken]) -> Vec<&str> {
+        tokens.iter().map(|t| t.account_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter_capable_accounts_uses_quota_allowlist() {
+        let manager = test_manager();
+        let tokens = vec![
+            token("a", Some(&["gemini-2.5-flash", "gemini-3-pro-high"])),
+            token("b", Some(&["gemini-2.5-flash"])),
+            token("c", None),
+        ];
+
+        // gemini-3 仅出现在 a 的清单中：b 被排除，无配额数据的 c 保留
+        let capable = manager.filter_capable_accounts(tokens.clone(), "gemini-3-pro-high");
+        assert_eq!(ids(&capable), vec!["a", "c"]);
+
+        // 未出现在任何清单中的模型不受限制
+        let capable = manager.filter_capable_accounts(tokens, "claude-sonnet-4-5");
+        assert_eq!(capable.len(), 3);
+    }
+
+    const MODEL_NOT_FOUND: &str =
+        r#"{"error":{"code":404,"message":"Publisher Model `gemini-3-pro-high` was not found.","status":"NOT_FOUND"}}"#;
+
+    #[test]
+    fn test_filter_capable_accounts_uses_learned_denials() {
+        let manager = test_manager();
+        let tokens = vec![token("a", None), token("b", None)];
+
+        assert!(manager.record_model_access_error(
+            "a@example.com",
+            "gemini-3-pro-high",
+            404,
+            MODEL_NOT_FOUND
+        ));
+        // 非模型权限错误不记录
+        assert!(!manager.record_model_access_error("b@example.com", "gemini-3-pro-high", 429, "RESOURCE_EXHAUSTED"));
+
+        let capable = manager.filter_capable_accounts(tokens.clone(), "gemini-3-pro-high");
+        assert_eq!(ids(&capable), vec!["b"]);
+        assert_eq!(manager.get_denied_models("a@example.com"), vec!["gemini-3-pro-high"]);
+
+        // 所有账号都不可用时回退到完整列表
+        manager.record_model_access_error("b@example.com", "gemini-3-pro-high", 404, MODEL_NOT_FOUND);
+        let capable = manager.filter_capable_accounts(tokens, "gemini-3-pro-high");
+        assert_eq!(capable.len(), 2);
+    }
+
+    #[test]
+    fn test_widespread_expiry_detection() {
+        assert!(!is_widespread_expiry(2, 2)); // 账号太少，逐个刷新即可
+        assert!(is_widespread_expiry(3, 4));
+        assert!(is_widespread_expiry(5, 10));
+        assert!(!is_widespread_expiry(4, 10));
+
+        let manager = test_manager();
+        for id in ["a", "b", "c", "d"] {
+            let mut t = token(id, None);
+            if id != "d" {
+                t.timestamp = chrono::Utc::now().timestamp() - 60;
+            }
+            manager.tokens.insert(t.account_id.clone(), t);
+        }
+        let expiring_tokens = manager.expiring_tokens(TOKEN_REFRESH_MARGIN_SECS);
+        let mut expiring = ids(&expiring_tokens);
+        expiring.sort();
+        assert_eq!(expiring, vec!["a", "b", "c"]);
+        assert!(is_widespread_expiry(expiring.len(), manager.tokens.len()));
+    }
+
+    #[tokio::test]
+    async fn test_burst_refresh_skipped_during_cooldown() {
+        let manager = test_manager();
+        for id in ["a", "b", "c"] {
+            let mut t = token(id, None);
+            t.timestamp = chrono::Utc::now().timestamp() - 60;
+            manager.tokens.insert(t.account_id.clone(), t);
+        }
+        assert!(!manager.burst_refresh_cooling_down());
+
+        // 上一轮刷新失败后，冷却期内不再发起刷新 (立即返回，不等待网络)
+        manager
+            .burst_refresh_failed_at
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        assert!(manager.burst_refresh_cooling_down());
+        tokio::time::timeout(std::time::Duration::from_millis(100), manager.burst_refresh_if_needed())
+            .await
+            .expect("burst refresh should be skipped during cooldown");
+        assert_eq!(manager.expiring_tokens(TOKEN_REFRESH_MARGIN_SECS).len(), 3);
+
+        // 冷却结束后恢复批量刷新判定
+        manager
+            .burst_refresh_failed_at
+            .store(chrono::Utc::now().timestamp() - BURST_REFRESH_COOLDOWN_SECS, Ordering::Relaxed);
+        assert!(!manager.burst_refresh_cooling_down());
+    }
+
+    #[tokio::test]
+    async fn test_stream_usage_uses_cached_quota_reset_time() {
+        let manager = test_manager();
+        let reset = chrono::Utc::now() + chrono::Duration::hours(3);
+        let mut t = token("a", None);
+        t.quota_reset_time = Some(reset.to_rfc3339());
+        manager.tokens.insert(t.account_id.clone(), t);
+
+        // 流在客户端中断 (Drop) 时记账，清零时间取自内存中的账号信息
+        let stream = futures::stream::iter(vec![Ok::<_, ()>(bytes::Bytes::from(
+            "data: {\"response\":{\"usageMetadata\":{\"totalTokenCount\":42}}}\n",
+        ))]);
+        let mut tapped = Box::pin(manager.track_usage_stream("a@example.com", stream));
+        futures::StreamExt::next(&mut tapped).await;
+        drop(tapped);
+
+        let usage = manager.usage_limiter.usage("a@example.com").unwrap();
+        assert_eq!((usage.tokens, usage.resets_at), (42, reset.timestamp()));
+
+        // 无配额数据的账号回退到下一个 UTC 零点
+        manager.record_usage("b@example.com", 7);
+        let usage = manager.usage_limiter.usage("b@example.com").unwrap();
+        assert_eq!(usage.resets_at, crate::proxy::usage_limits::next_utc_midnight());
+    }
+
+    #[test]
+    fn test_earliest_quota_reset() {
+        let quota = serde_json::json!({
+            "models": [
+                { "name": "a", "reset_time": "2026-01-02T00:00:00Z" },
+                { "name": "b", "reset_time": "" },
+                { "name": "c", "reset_time": "2026-01-01T12:00:00Z" }
+            ]
+        });
+        assert_eq!(earliest_quota_reset(Some(&quota)).as_deref(), Some("2026-01-01T12:00:00Z"));
+        assert_eq!(earliest_quota_reset(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_aborts_spawned_work_when_request_dropped() {
+        // 不在请求上下文中时正常执行
+        assert_eq!(cancellable(async { 1 }).await, Some(1));
+
+        // 请求持有 DropGuard，派生任务在令牌上下文中执行刷新
+        let token = CancellationToken::new();
+        let task = tokio::spawn(with_cancellation(token.clone(), cancellable(std::future::pending::<()>())));
+        let request = async move {
+            let _guard = token.drop_guard();
+            std::future::pending::<()>().await
+        };
+        // 模拟客户端断开：请求 future 被丢弃，派生任务中的刷新随之放弃
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(10), request).await.is_err());
+        let result = tokio::time::timeout(std::time::Duration::from_secs(1), task).await;
+        assert_eq!(result.expect("spawned refresh should be cancelled").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_usage_caps_prefers_under_soft_and_blocks_hard() {
+        let manager = test_manager();
+        manager
+            .update_usage_limits(crate::proxy::config::UsageLimitConfig {
+                enabled: true,
+                default: crate::proxy::config::TokenCap { soft: Some(100), hard: Some(200) },
+                ..Default::default()
+            })
+            .await;
+        let tokens = vec![token("a", None), token("b", None), token("c", None)];
+        let future = || chrono::Utc::now().timestamp() + 3600;
+        manager.usage_limiter.record("a@example.com", 150, future);
+        manager.usage_limiter.record("b@example.com", 250, future);
+
+        // c 未超软上限，优先使用
+        let selected = manager.apply_usage_caps(tokens.clone()).await.unwrap();
+        assert_eq!(ids(&selected), vec!["c"]);
+
+        // 只剩超软上限的 a 可用 (b 已超硬上限)
+        let selected = manager.apply_usage_caps(tokens[..2].to_vec()).await.unwrap();
+        assert_eq!(ids(&selected), vec!["a"]);
+
+        // 全部超硬上限：返回明确错误
+        let err = manager.apply_usage_caps(vec![token("b", None)]).await.unwrap_err();
+        assert!(err.contains("daily hard token cap"));
+    }
+
+    #[tokio::test]
+    async fn test_explain_selection_reports_reasons_without_side_effects() {
+        let manager = test_manager();
+        let mut free = token("free", None);
+        free.subscription_tier = Some("FREE".to_string());
+        let mut pro = token("pro", None);
+        pro.subscription_tier = Some("PRO".to_string());
+        let mut ultra = token("ultra", None);
+        ultra.subscription_tier = Some("ULTRA".to_string());
+        for t in [free, pro, ultra] {
+            manager.tokens.insert(t.account_id.clone(), t);
+        }
+        manager.record_model_access_error("free@example.com", "gemini-3-pro-high", 404, MODEL_NOT_FOUND);
+
+        // 无会话、无锁定：按等级排序后轮询
+        let decision = manager.explain_selection("agent", "agent", None, Some("gemini-3-pro-high")).await;
+        assert_eq!(decision.reason, "round_robin");
+        let excluded = decision.candidates.iter().find(|c| c.account_id == "free").unwrap();
+        assert_eq!(excluded.status, "model_denied");
+        let ranked: Vec<&str> = decision
+            .candidates
+            .iter()
+            .filter(|c| c.rank.is_some())
+            .map(|c| c.account_id.as_str())
+            .collect();
+        assert_eq!(ranked, vec!["ultra", "pro"]);
+
+        // 粘性绑定优先；绑定账号被限流时报告将解绑
+        manager.session_accounts.bind("sid-1", "pro");
+        let decision = manager.explain_selection("agent", "agent", Some("sid-1"), None).await;
+        assert_eq!(decision.reason, "sticky_session");
+        assert_eq!(decision.selected.as_ref().unwrap().email, "pro@example.com");
+
+        manager.mark_rate_limited("pro@example.com", 429, Some("120"), "RATE_LIMIT_EXCEEDED");
+        let decision = manager.explain_selection("agent", "agent", Some("sid-1"), None).await;
+        assert_ne!(decision.reason, "sticky_session");
+        assert!(decision.steps.iter().any(|s| s.contains("binding would be dropped")));
+        let pro = decision.candidates.iter().find(|c| c.account_id == "pro").unwrap();
+        assert_eq!(pro.status, "rate_limited");
+
+        // 预演不修改绑定与轮询指针
+        assert_eq!(manager.session_accounts.peek("sid-1"), Some("pro".to_string()));
+        assert_eq!(manager.current_index.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_custom_quota_group_keeps_request_type_scheduling() {
+        let manager = test_manager();
+        for t in [token("a", None), token("b", None)] {
+            manager.tokens.insert(t.account_id.clone(), t);
+        }
+        *manager.last_used_account.lock().await = Some(("b".to_string(), std::time::Instant::now()));
+
+        // 自定义分组名不影响请求类型：agent 请求复用 60s 窗口内的账号，image_gen 请求不参与
+        let decision = manager.explain_selection("agent", "design", None, None).await;
+        assert_eq!(decision.quota_group, "design");
+        assert_eq!(decision.reason, "last_used_window");
+        let decision = manager.explain_selection("image_gen", "design", None, None).await;
+        assert_eq!(decision.reason, "round_robin");
+    }
 }
@@ -0,0 +1,367 @@
+// 会话对话记录导出 (Session Transcript Export)
+// 基于监控日志中按会话指纹记录的请求，重建完整对话 (含工具调用与结果)，并标注每个助手回合实际使用的模型与账号。
+// 客户端每次请求都会携带完整历史，因此以最后一个请求的 messages 为准；第 i 个请求产生的助手回复
+// 位于历史中下标 len(messages_i) 处。流式响应体不落库，最后一个回合仅在非流式响应时可还原。
+// 输出格式：Markdown (便于提交 Bug) 或 Anthropic Messages JSON (便于回放 / 审计)。
+
+use crate::proxy::monitor::ProxyRequestLog;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Json,
+}
+
+impl TranscriptFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "" | "markdown" | "md" => Ok(Self::Markdown),
+            "json" | "anthropic" => Ok(Self::Json),
+            other => Err(format!("Unsupported transcript format: {}", other)),
+        }
+    }
+}
+
+/// 单次请求的元数据 (对应一个助手回合)
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptTurn {
+    pub request_id: String,
+    pub timestamp: i64,
+    /// 该请求产生的助手消息在 messages 中的下标 (无法还原时为 None)
+    pub message_index: Option<usize>,
+    pub model: Option<String>,
+    pub mapped_model: Option<String>,
+    pub account_email: Option<String>,
+    pub status: u16,
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transcript {
+    pub session_id: String,
+    pub system: Option<Value>,
+    pub messages: Vec<Value>,
+    pub turns: Vec<TranscriptTurn>,
+}
+
+/// 将 OpenAI 消息转换为 Anthropic 消息 (system 消息返回到 `system`)
+fn from_openai_message(msg: &Value, system: &mut Vec<String>) -> Option<Value> {
+    let role = msg["role"].as_str().unwrap_or("user");
+    let content_blocks = |content: &Value| -> Vec<Value> {
+        match content {
+            Value::String(s) if !s.is_empty() => vec![json!({ "type": "text", "text": s })],
+            Value::Array(parts) => parts
+                .iter()
+                .map(|p| match p["type"].as_str() {
+                    Some("text") => json!({ "type": "text", "text": p["text"] }),
+                    _ => json!({ "type": "text", "text": format!("[{}]", p["type"].as_str().unwrap_or("attachment")) }),
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+
+    match role {
+        "system" | "developer" => {
+            let text: Vec<String> = content_blocks(&msg["content"])
+                .iter()
+                .filter_map(|b| b["text"].as_str().map(str::to_string))
+                .collect();
+            system.push(text.join("\n"));
+            None
+        }
+        "tool" => Some(json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": msg["tool_call_id"],
+                "content": msg["content"],
+            }]
+        })),
+        _ => {
+            let mut blocks = content_blocks(&msg["content"]);
+            for call in msg["tool_calls"].as_array().into_iter().flatten() {
+                let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+                blocks.push(json!({
+                    "type": "tool_use",
+                    "id": call["id"],
+                    "name": call["function"]["name"],
+                    "input": serde_json::from_str::<Value>(arguments).unwrap_or_else(|_| json!(arguments)),
+                }));
+            }
+            let role = if role == "assistant" { "assistant" } else { "user" };
+            Some(json!({ "role": role, "content": blocks }))
+        }
+    }
+}
+
+/// 解析请求体中的 system 与 messages (Claude 原样保留，OpenAI 转换为 Anthropic 结构)
+fn parse_request(body: &Value) -> Option<(Option<Value>, Vec<Value>)> {
+    let messages = body["messages"].as_array()?;
+    let is_openai = body.get("system").is_none()
+        && messages.iter().any(|m| {
+            matches!(m["role"].as_str(), Some("system" | "developer" | "tool")) || m.get("tool_calls").is_some()
+        });
+    if !is_openai {
+        return Some((body.get("system").cloned(), messages.clone()));
+    }
+    let mut system = Vec::new();
+    let converted = messages.iter().filter_map(|m| from_openai_message(m, &mut system)).collect();
+    let system = (!system.is_empty()).then(|| Value::String(system.join("\n\n")));
+    Some((system, converted))
+}
+
+/// 从非流式响应中还原助手消息
+fn parse_response(body: &str) -> Option<Value> {
+    let response: Value = serde_json::from_str(body).ok()?;
+    if response["type"] == "message" {
+        return Some(json!({ "role": "assistant", "content": response["content"] }));
+    }
+    let message = response["choices"].get(0)?.get("message")?;
+    from_openai_message(message, &mut Vec::new())
+}
+
+/// 由会话的全部请求日志 (按时间升序) 重建对话
+pub fn build(session_id: &str, logs: &[ProxyRequestLog]) -> Transcript {
+    let parsed: Vec<Option<(Option<Value>, Vec<Value>)>> = logs
+        .iter()
+        .map(|log| {
+            let body: Value = serde_json::from_str(log.request_body.as_deref()?).ok()?;
+            parse_request(&body)
+        })
+        .collect();
+
+    let last = parsed.iter().rposition(Option::is_some);
+    let (system, mut messages) = last
+        .and_then(|i| parsed[i].clone())
+        .unwrap_or((None, Vec::new()));
+    if let Some(reply) = last.and_then(|i| logs[i].response_body.as_deref()).and_then(parse_response) {
+        messages.push(reply);
+    }
+
+    let turns = logs
+        .iter()
+        .zip(&parsed)
+        .map(|(log, request)| TranscriptTurn {
+            request_id: log.id.clone(),
+            timestamp: log.timestamp,
+            message_index: request
+                .as_ref()
+                .map(|(_, history)| history.len())
+                .filter(|&i| messages.get(i).is_some_and(|m| m["role"] == "assistant")),
+            model: log.model.clone(),
+            mapped_model: log.mapped_model.clone(),
+            account_email: log.account_email.clone(),
+            status: log.status,
+            input_tokens: log.input_tokens,
+            output_tokens: log.output_tokens,
+            error: log.error.clone(),
+        })
+        .collect();
+
+    Transcript { session_id: session_id.to_string(), system, messages, turns }
+}
+
+fn format_time(timestamp_ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn render_block(out: &mut String, block: &Value) {
+    match block["type"].as_str() {
+        Some("text") => {
+            out.push_str(block["text"].as_str().unwrap_or_default());
+            out.push_str("\n\n");
+        }
+        Some("thinking") => {
+            for line in block["thinking"].as_str().unwrap_or_default().lines() {
+                out.push_str(&format!("> {}\n", line));
+            }
+            out.push('\n');
+        }
+        Some("tool_use") => {
+            let input = serde_json::to_string_pretty(&block["input"]).unwrap_or_default();
+            out.push_str(&format!(
+                "**Tool call** `{}` (`{}`)\n\n```json\n{}\n```\n\n",
+                block["name"].as_str().unwrap_or("unknown"),
+                block["id"].as_str().unwrap_or_default(),
+                input
+            ));
+        }
+        Some("tool_result") => {
+            let content = match &block["content"] {
+                Value::String(s) => s.clone(),
+                Value::Array(parts) => parts
+                    .iter()
+                    .map(|p| p["text"].as_str().map(str::to_string).unwrap_or_else(|| p.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                other => other.to_string(),
+            };
+            let label = if block["is_error"] == true { "Tool error" } else { "Tool result" };
+            out.push_str(&format!(
+                "**{}** (`{}`)\n\n```\n{}\n```\n\n",
+                label,
+                block["tool_use_id"].as_str().unwrap_or_default(),
+                content
+            ));
+        }
+        Some(other) => out.push_str(&format!("_[{} block]_\n\n", other)),
+        None => {}
+    }
+}
+
+fn render_content(out: &mut String, content: &Value) {
+    match content {
+        Value::String(s) => {
+            out.push_str(s);
+            out.push_str("\n\n");
+        }
+        Value::Array(blocks) => blocks.iter().for_each(|b| render_block(out, b)),
+        _ => {}
+    }
+}
+
+fn render_markdown(transcript: &Transcript) -> String {
+    let mut out = format!(
+        "# Session `{}`\n\nExported at {} · {} request(s)\n\n",
+        transcript.session_id,
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
+        transcript.turns.len()
+    );
+
+    if let Some(system) = &transcript.system {
+        out.push_str("## System\n\n");
+        render_content(&mut out, system);
+    }
+
+    for (i, message) in transcript.messages.iter().enumerate() {
+        if message["role"] == "assistant" {
+            out.push_str("## Assistant\n\n");
+            if let Some(turn) = transcript.turns.iter().find(|t| t.message_index == Some(i)) {
+                out.push_str(&format!(
+                    "_{} · model `{}` → `{}` · account `{}` · tokens {}/{}_\n\n",
+                    format_time(turn.timestamp),
+                    turn.model.as_deref().unwrap_or("-"),
+                    turn.mapped_model.as_deref().unwrap_or("-"),
+                    turn.account_email.as_deref().unwrap_or("-"),
+                    turn.input_tokens.unwrap_or(0),
+                    turn.output_tokens.unwrap_or(0)
+                ));
+            }
+        } else {
+            out.push_str("## User\n\n");
+        }
+        render_content(&mut out, &message["content"]);
+    }
+
+    out.push_str("## Requests\n\n| Time | Status | Model | Mapped model | Account | Tokens (in/out) |\n|---|---|---|---|---|---|\n");
+    for turn in &transcript.turns {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {}/{} |\n",
+            format_time(turn.timestamp),
+            turn.status,
+            turn.model.as_deref().unwrap_or("-"),
+            turn.mapped_model.as_deref().unwrap_or("-"),
+            turn.account_email.as_deref().unwrap_or("-"),
+            turn.input_tokens.unwrap_or(0),
+            turn.output_tokens.unwrap_or(0)
+        ));
+    }
+    out
+}
+
+/// 渲染对话记录
+pub fn render(transcript: &Transcript, format: TranscriptFormat) -> Result<String, String> {
+    match format {
+        TranscriptFormat::Markdown => Ok(render_markdown(transcript)),
+        TranscriptFormat::Json => {
+            let mut value = serde_json::to_value(transcript).map_err(|e| e.to_string())?;
+            value["exported_at"] = json!(chrono::Utc::now().to_rfc3339());
+            serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: &str, account: &str, request: Value, response: &str) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: id.to_string(),
+            timestamp: 1_700_000_000_000,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 200,
+            duration: 100,
+            model: Some("claude-sonnet-4-5".to_string()),
+            mapped_model: Some("claude-sonnet-4-5-thinking".to_string()),
+            account_email: Some(account.to_string()),
+            error: None,
+            request_body: Some(request.to_string()),
+            response_body: Some(response.to_string()),
+            input_tokens: Some(10),
+            output_tokens: Some(5),
+            thinking_tokens: None,
+            client: None,
+            experiment: None,
+            session_id: Some("sid-1".to_string()),
+            timing: None,
+        }
+    }
+
+    #[test]
+    fn test_claude_session_turns_attributed_per_request() {
+        let first = json!({ "system": "be brief", "messages": [{ "role": "user", "content": "list files" }] });
+        let second = json!({ "system": "be brief", "messages": [
+            { "role": "user", "content": "list files" },
+            { "role": "assistant", "content": [{ "type": "tool_use", "id": "t1", "name": "ls", "input": { "path": "." } }] },
+            { "role": "user", "content": [{ "type": "tool_result", "tool_use_id": "t1", "content": "a.rs" }] }
+        ] });
+        let reply = json!({ "type": "message", "role": "assistant", "content": [{ "type": "text", "text": "Found a.rs" }] });
+        let logs = vec![
+            log("r1", "a@example.com", first, "[Stream Data]"),
+            log("r2", "b@example.com", second, &reply.to_string()),
+        ];
+
+        let transcript = build("sid-1", &logs);
+        assert_eq!(transcript.messages.len(), 4);
+        assert_eq!(transcript.turns[0].message_index, Some(1));
+        assert_eq!(transcript.turns[1].message_index, Some(3));
+
+        let md = render(&transcript, TranscriptFormat::Markdown).unwrap();
+        assert!(md.contains("## System\n\nbe brief"));
+        assert!(md.contains("**Tool call** `ls` (`t1`)"));
+        assert!(md.contains("**Tool result** (`t1`)\n\n```\na.rs\n```"));
+        assert!(md.contains("account `b@example.com`"));
+        assert!(md.contains("Found a.rs"));
+
+        let exported: Value = serde_json::from_str(&render(&transcript, TranscriptFormat::Json).unwrap()).unwrap();
+        assert_eq!(exported["messages"][1]["content"][0]["name"], "ls");
+        assert_eq!(exported["turns"][0]["account_email"], "a@example.com");
+    }
+
+    #[test]
+    fn test_openai_messages_converted_to_anthropic_shape() {
+        let request = json!({ "messages": [
+            { "role": "system", "content": "sys" },
+            { "role": "user", "content": [{ "type": "text", "text": "weather?" }, { "type": "image_url", "image_url": { "url": "x" } }] },
+            { "role": "assistant", "content": null, "tool_calls": [{ "id": "c1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" } }] },
+            { "role": "tool", "tool_call_id": "c1", "content": "sunny" }
+        ] });
+        let (system, messages) = parse_request(&request).unwrap();
+        assert_eq!(system, Some(json!("sys")));
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["content"][1]["text"], "[image_url]");
+        assert_eq!(messages[1]["content"][0]["input"]["city"], "Paris");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "c1");
+        assert!(TranscriptFormat::parse("xml").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+// 监控界面事件合并推送 (UI Event Batching)
+// 高 token 流式请求会产生大量进度事件，逐条 emit 会挤占 Tauri IPC 并拖慢流式转发。
+// 这里把请求日志与进度事件先放入内存缓冲，由后台任务按 max_batches_per_sec 批量推送：
+// - proxy://request-batch           { logs: [...], dropped: n }  (日志默认去掉请求 / 响应体)
+// - proxy://request-progress-batch  [...]                       (同一请求只保留最新进度)
+
+use crate::proxy::config::UiEventConfig;
+use crate::proxy::monitor::{ProxyRequestLog, ProxyRequestProgress};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+pub const EVENT_REQUEST_BATCH: &str = "proxy://request-batch";
+pub const EVENT_PROGRESS_BATCH: &str = "proxy://request-progress-batch";
+
+/// 推送给前端的错误信息长度上限 (完整内容在详情面板中查看)
+const MAX_UI_ERROR_CHARS: usize = 2000;
+
+/// 事件出口 (事件名, 载荷)，生产环境为 AppHandle::emit
+pub type EventSink = Arc<dyn Fn(&'static str, serde_json::Value) + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestBatch {
+    pub logs: Vec<ProxyRequestLog>,
+    /// 因积压超出 max_batch_size 被丢弃的日志数，前端据此重新加载列表
+    pub dropped: usize,
+}
+
+#[derive(Default)]
+struct Pending {
+    logs: Vec<ProxyRequestLog>,
+    progress: Vec<ProxyRequestProgress>,
+    dropped: usize,
+}
+
+pub struct UiEventBatcher {
+    sink: EventSink,
+    config: RwLock<UiEventConfig>,
+    pending: Mutex<Pending>,
+    flusher_started: AtomicBool,
+}
+
+impl UiEventBatcher {
+    pub fn new(sink: EventSink) -> Arc<Self> {
+        Arc::new(Self {
+            sink,
+            config: RwLock::new(UiEventConfig::default()),
+            pending: Mutex::new(Pending::default()),
+            flusher_started: AtomicBool::new(false),
+        })
+    }
+
+    pub fn configure(&self, config: UiEventConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    fn config(&self) -> UiEventConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn push_log(self: &Arc<Self>, log: &ProxyRequestLog) {
+        let config = self.config();
+        self.enqueue_log(log, &config);
+        self.schedule(&config);
+    }
+
+    fn enqueue_log(&self, log: &ProxyRequestLog, config: &UiEventConfig) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.logs.push(compact_log(log, config.include_bodies));
+        let max = config.max_batch_size.max(1);
+        if pending.logs.len() > max {
+            let excess = pending.logs.len() - max;
+            pending.logs.drain(0..excess);
+            pending.dropped += excess;
+        }
+    }
+
+    pub fn push_progress(self: &Arc<Self>, progress: &ProxyRequestProgress) {
+        let config = self.config();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.progress.iter_mut().find(|p| p.id == progress.id) {
+                Some(existing) => *existing = progress.clone(),
+                None => pending.progress.push(progress.clone()),
+            }
+        }
+        self.schedule(&config);
+    }
+
+    /// 立即推送所有积压事件
+    pub fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if !pending.logs.is_empty() || pending.dropped > 0 {
+            let batch = RequestBatch { logs: pending.logs, dropped: pending.dropped };
+            if let Ok(payload) = serde_json::to_value(&batch) {
+                (self.sink)(EVENT_REQUEST_BATCH, payload);
+            }
+        }
+        if !pending.progress.is_empty() {
+            if let Ok(payload) = serde_json::to_value(&pending.progress) {
+                (self.sink)(EVENT_PROGRESS_BATCH, payload);
+            }
+        }
+    }
+
+    /// 关闭批量 (频率为 0) 或不在 tokio 运行时中时立即推送，否则确保后台推送任务已启动
+    fn schedule(self: &Arc<Self>, config: &UiEventConfig) {
+        if config.max_batches_per_sec == 0 {
+            self.flush();
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            self.flush();
+            return;
+        };
+        if self.flusher_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let weak: Weak<Self> = Arc::downgrade(self);
+        runtime.spawn(async move {
+            loop {
+                let Some(interval) = weak.upgrade().map(|b| flush_interval(&b.config())) else {
+                    break;
+                };
+                tokio::time::sleep(interval).await;
+                match weak.upgrade() {
+                    Some(batcher) => batcher.flush(),
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+fn flush_interval(config: &UiEventConfig) -> Duration {
+    Duration::from_millis(1000 / config.max_batches_per_sec.max(1) as u64)
+}
+
+/// 去掉大字段后的日志 (请求 / 响应体可能高达数十 MB，例如图片生成)
+fn compact_log(log: &ProxyRequestLog, include_bodies: bool) -> ProxyRequestLog {
+    let mut log = log.clone();
+    if !include_bodies {
+        log.request_body = None;
+        log.response_body = None;
+    }
+    if let Some(error) = &log.error {
+        if error.chars().count() > MAX_UI_ERROR_CHARS {
+            log.error = Some(format!("{}…", error.chars().take(MAX_UI_ERROR_CHARS).collect::<String>()));
+        }
+    }
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn collecting_batcher() -> (Arc<UiEventBatcher>, Arc<Mutex<Vec<(&'static str, Value)>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        let batcher = UiEventBatcher::new(Arc::new(move |name, payload| {
+            sink_events.lock().unwrap().push((name, payload));
+        }));
+        (batcher, events)
+    }
+
+    fn log(id: &str) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: id.to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/messages".to_string(),
+            status: 500,
+            duration: 10,
+            model: None,
+            mapped_model: None,
+            account_email: None,
+            error: Some("x".repeat(MAX_UI_ERROR_CHARS + 10)),
+            request_body: Some("{\"messages\":[]}".to_string()),
+            response_body: Some("{}".to_string()),
+            input_tokens: None,
+            output_tokens: None,
+            thinking_tokens: None,
+            client: None,
+            experiment: None,
+            session_id: None,
+            timing: None,
+        }
+    }
+
+    fn progress(id: &str, output_tokens: u32) -> ProxyRequestProgress {
+        ProxyRequestProgress {
+            id: id.to_string(),
+            url: "/v1/messages".to_string(),
+            output_tokens,
+            elapsed_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_backlog_drops_oldest_and_strips_bodies() {
+        let (batcher, events) = collecting_batcher();
+        let config = UiEventConfig { max_batch_size: 2, ..Default::default() };
+        for id in ["a", "b", "c"] {
+            batcher.enqueue_log(&log(id), &config);
+        }
+        batcher.flush();
+        batcher.flush();
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1, "empty flush should not emit");
+
+        let (name, batch) = &events[0];
+        assert_eq!(*name, EVENT_REQUEST_BATCH);
+        assert_eq!(batch["dropped"], 1);
+        let logs = batch["logs"].as_array().unwrap();
+        assert_eq!(logs.iter().map(|l| l["id"].as_str().unwrap()).collect::<Vec<_>>(), vec!["b", "c"]);
+        assert!(logs[0]["request_body"].is_null());
+        assert!(logs[0]["error"].as_str().unwrap().chars().count() <= MAX_UI_ERROR_CHARS + 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_flush_keeps_latest_progress() {
+        let (batcher, events) = collecting_batcher();
+        batcher.configure(UiEventConfig { max_batches_per_sec: 20, ..Default::default() });
+        batcher.push_progress(&progress("a", 10));
+        batcher.push_progress(&progress("a", 25));
+        batcher.push_progress(&progress("b", 5));
+        batcher.push_log(&log("a"));
+        assert!(events.lock().unwrap().is_empty(), "events should wait for the next flush");
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let events = events.lock().unwrap();
+        let progress = events.iter().find(|(name, _)| *name == EVENT_PROGRESS_BATCH).unwrap();
+        let progress = progress.1.as_array().unwrap();
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0]["output_tokens"], 25);
+        assert!(events.iter().any(|(name, _)| *name == EVENT_REQUEST_BATCH));
+    }
+
+    #[test]
+    fn test_zero_rate_emits_immediately() {
+        let (batcher, events) = collecting_batcher();
+        batcher.configure(UiEventConfig { max_batches_per_sec: 0, ..Default::default() });
+        batcher.push_log(&log("a"));
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+}
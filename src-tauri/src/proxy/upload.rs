@@ -0,0 +1,246 @@
+// 请求体大小限制与 multipart 上传分流 (Upload Limits)
+// multipart 文件字段按块读取：超过 spill_to_disk_kb 的部分写入临时文件，超过 max_file_mb 时立即返回 413，
+// 避免误传的超大文件被整体缓存到内存中。JSON 请求体按 Content-Length 提前拒绝 (见 body_limit 中间件)。
+
+use crate::proxy::config::UploadLimitsConfig;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::http::{header, HeaderMap, StatusCode};
+use base64::Engine;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// base64 分块编码的块大小 (需为 3 的倍数)
+const ENCODE_CHUNK_SIZE: usize = 3 * 64 * 1024;
+
+fn to_mb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// 文件字段超限的提示
+pub fn file_too_large_message(field: &str, limits: &UploadLimitsConfig) -> String {
+    format!(
+        "Uploaded field '{}' exceeds the {} MB per-file limit. Compress or resize the file before uploading, \
+         or raise proxy.upload_limits.max_file_mb in the proxy settings.",
+        field, limits.max_file_mb
+    )
+}
+
+/// 请求体超限的提示
+pub fn body_too_large_message(size: Option<u64>, limits: &UploadLimitsConfig) -> String {
+    let size = size.map(|s| format!(" ({:.1} MB)", to_mb(s))).unwrap_or_default();
+    format!(
+        "Request body{} exceeds the {} MB limit. Send smaller attachments (e.g. downscale images), \
+         or raise proxy.upload_limits.max_request_body_mb in the proxy settings and restart the proxy.",
+        size, limits.max_request_body_mb
+    )
+}
+
+/// multipart 解析错误：请求体超限时返回 413 与提示，其余为 400
+pub fn multipart_error(e: MultipartError, limits: &UploadLimitsConfig) -> (StatusCode, String) {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        (StatusCode::PAYLOAD_TOO_LARGE, body_too_large_message(None, limits))
+    } else {
+        (e.status(), format!("Multipart error: {}", e.body_text()))
+    }
+}
+
+/// 中间件是否可以缓存整个请求体 (multipart 与超限请求交给 handler / body_limit 处理)
+pub fn can_buffer_body(headers: &HeaderMap, limits: &UploadLimitsConfig) -> bool {
+    let is_multipart = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("multipart/"));
+    !is_multipart && content_length(headers).is_none_or(|len| len <= limits.max_request_body_bytes() as u64)
+}
+
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn temp_file_error(e: std::io::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write upload temp file: {}", e))
+}
+
+enum Spool {
+    Memory(Vec<u8>),
+    File(PathBuf),
+}
+
+/// 已读取的上传文件 (内存或临时文件)，临时文件在 Drop 时删除
+pub struct SpooledUpload {
+    spool: Spool,
+    len: u64,
+}
+
+impl SpooledUpload {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_on_disk(&self) -> bool {
+        matches!(self.spool, Spool::File(_))
+    }
+
+    /// 逐块读取 multipart 字段，超过 max_file_mb 时返回 413
+    pub async fn from_field(mut field: Field<'_>, limits: &UploadLimitsConfig) -> Result<Self, (StatusCode, String)> {
+        let name = field.name().unwrap_or("file").to_string();
+        // 出错返回时 upload 被释放，已创建的临时文件随之删除
+        let mut upload = Self { spool: Spool::Memory(Vec::new()), len: 0 };
+        let mut writer: Option<tokio::fs::File> = None;
+
+        while let Some(chunk) = field.chunk().await.map_err(|e| multipart_error(e, limits))? {
+            upload.len += chunk.len() as u64;
+            if upload.len > limits.max_file_bytes() {
+                tracing::warn!("[Upload] Field '{}' exceeds {} MB, rejecting", name, limits.max_file_mb);
+                return Err((StatusCode::PAYLOAD_TOO_LARGE, file_too_large_message(&name, limits)));
+            }
+            match &mut upload.spool {
+                Spool::Memory(buffer) if buffer.len() + chunk.len() > limits.spill_to_disk_bytes() => {
+                    let buffered = std::mem::take(buffer);
+                    let path = std::env::temp_dir().join(format!("antigravity-upload-{}", uuid::Uuid::new_v4()));
+                    upload.spool = Spool::File(path.clone());
+                    let mut f = tokio::fs::File::create(&path).await.map_err(temp_file_error)?;
+                    f.write_all(&buffered).await.map_err(temp_file_error)?;
+                    f.write_all(&chunk).await.map_err(temp_file_error)?;
+                    writer = Some(f);
+                }
+                Spool::Memory(buffer) => buffer.extend_from_slice(&chunk),
+                Spool::File(_) => {
+                    if let Some(f) = writer.as_mut() {
+                        f.write_all(&chunk).await.map_err(temp_file_error)?;
+                    }
+                }
+            }
+        }
+        if let Some(mut f) = writer {
+            f.flush().await.map_err(temp_file_error)?;
+            tracing::debug!("[Upload] Field '{}' spooled to disk ({} bytes)", name, upload.len);
+        }
+        Ok(upload)
+    }
+
+    /// 读取全部内容
+    pub async fn into_bytes(mut self) -> Result<Vec<u8>, String> {
+        match &mut self.spool {
+            Spool::Memory(data) => Ok(std::mem::take(data)),
+            Spool::File(path) => tokio::fs::read(path).await.map_err(|e| format!("Failed to read temp file: {}", e)),
+        }
+    }
+
+    /// base64 编码 (临时文件分块编码，不会同时持有完整原文与编码结果)
+    pub async fn to_base64(&self) -> Result<String, String> {
+        let engine = &base64::engine::general_purpose::STANDARD;
+        match &self.spool {
+            Spool::Memory(data) => Ok(engine.encode(data)),
+            Spool::File(path) => {
+                let mut f = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| format!("Failed to open temp file: {}", e))?;
+                let mut out = String::with_capacity((self.len as usize).div_ceil(3) * 4);
+                let mut buf = vec![0u8; ENCODE_CHUNK_SIZE];
+                loop {
+                    // 填满整块再编码，保证中间块长度为 3 的倍数
+                    let mut filled = 0;
+                    while filled < buf.len() {
+                        let n = f
+                            .read(&mut buf[filled..])
+                            .await
+                            .map_err(|e| format!("Failed to read temp file: {}", e))?;
+                        if n == 0 {
+                            break;
+                        }
+                        filled += n;
+                    }
+                    engine.encode_string(&buf[..filled], &mut out);
+                    if filled < buf.len() {
+                        break;
+                    }
+                }
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl Drop for SpooledUpload {
+    fn drop(&mut self) {
+        if let Spool::File(path) = &self.spool {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{FromRequest, Multipart, Request};
+
+    async fn multipart_with_file(data: &[u8]) -> Multipart {
+        let mut body = b"--BOUNDARY\r\nContent-Disposition: form-data; name=\"image\"; filename=\"a.png\"\r\n\r\n".to_vec();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+        let request = Request::builder()
+            .method("POST")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=BOUNDARY")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    fn limits(max_file_mb: u64, spill_to_disk_kb: u64) -> UploadLimitsConfig {
+        UploadLimitsConfig { max_file_mb, spill_to_disk_kb, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_large_field_spills_to_disk_and_encodes_identically() {
+        let data: Vec<u8> = (0..(ENCODE_CHUNK_SIZE + 1001)).map(|i| (i % 251) as u8).collect();
+        let mut multipart = multipart_with_file(&data).await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let upload = SpooledUpload::from_field(field, &limits(1, 16)).await.unwrap();
+
+        assert!(upload.is_on_disk());
+        assert_eq!(upload.len(), data.len() as u64);
+        let Spool::File(path) = &upload.spool else { unreachable!() };
+        let path = path.clone();
+        assert_eq!(
+            upload.to_base64().await.unwrap(),
+            base64::engine::general_purpose::STANDARD.encode(&data)
+        );
+        assert_eq!(upload.into_bytes().await.unwrap(), data);
+        assert!(!path.exists(), "temp file should be removed on drop");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_field_rejected_with_413() {
+        let data = vec![0u8; 1024 * 1024 + 1];
+        let mut multipart = multipart_with_file(&data).await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let (status, message) = SpooledUpload::from_field(field, &limits(1, 16)).await.err().unwrap();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(message.contains("max_file_mb"), "{}", message);
+
+        // 小文件保留在内存中
+        let mut multipart = multipart_with_file(b"small").await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        assert!(!SpooledUpload::from_field(field, &limits(1, 16)).await.unwrap().is_on_disk());
+    }
+
+    #[test]
+    fn test_multipart_and_oversized_bodies_are_not_buffered() {
+        let limits = UploadLimitsConfig { max_request_body_mb: 1, ..Default::default() };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        headers.insert(header::CONTENT_LENGTH, "1024".parse().unwrap());
+        assert!(can_buffer_body(&headers, &limits));
+
+        headers.insert(header::CONTENT_LENGTH, (2 * 1024 * 1024).to_string().parse().unwrap());
+        assert!(!can_buffer_body(&headers, &limits));
+
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(header::CONTENT_TYPE, "multipart/form-data; boundary=x".parse().unwrap());
+        assert!(!can_buffer_body(&headers, &limits));
+    }
+}
@@ -0,0 +1,206 @@
+// 故障注入 (Chaos 模式)
+// 按配置的概率在上游调用前注入 429 / 503 / 超时，并可对流式 chunk 注入延迟，
+// 用于验证客户端 Agent 与反代的重试、账号轮换逻辑在上游劣化时的表现
+
+use crate::proxy::config::ChaosConfig;
+use futures::StreamExt;
+use reqwest::Response;
+use serde_json::json;
+use std::time::Duration;
+
+/// 注入的故障类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosFault {
+    RateLimited,
+    Unavailable,
+    Timeout,
+}
+
+/// 故障注入器
+#[derive(Debug, Clone)]
+pub struct ChaosInjector {
+    config: ChaosConfig,
+}
+
+impl ChaosInjector {
+    /// 根据配置创建注入器，未启用时返回 None
+    pub fn from_config(config: &ChaosConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        tracing::warn!(
+            "[Chaos] Fault injection ENABLED (429: {:.2}, 503: {:.2}, timeout: {:.2}, slow chunk: {:.2})",
+            config.rate_limit_probability,
+            config.unavailable_probability,
+            config.timeout_probability,
+            config.slow_chunk_probability
+        );
+        Some(Self {
+            config: config.clone(),
+        })
+    }
+
+    /// 根据随机数 (0.0 ~ 1.0) 选择故障，概率按 429 → 503 → 超时 累计
+    pub fn pick_fault(&self, roll: f64) -> Option<ChaosFault> {
+        let p429 = self.config.rate_limit_probability.clamp(0.0, 1.0);
+        let p503 = p429 + self.config.unavailable_probability.clamp(0.0, 1.0);
+        let ptimeout = p503 + self.config.timeout_probability.clamp(0.0, 1.0);
+
+        if roll < p429 {
+            Some(ChaosFault::RateLimited)
+        } else if roll < p503 {
+            Some(ChaosFault::Unavailable)
+        } else if roll < ptimeout {
+            Some(ChaosFault::Timeout)
+        } else {
+            None
+        }
+    }
+
+    /// 在上游调用前执行注入
+    ///
+    /// 返回 Some 表示本次请求已被注入的故障替代，调用方应直接返回该结果
+    pub async fn inject(&self, method: &str) -> Option<Result<Response, String>> {
+        let fault = self.pick_fault(rand::random::<f64>())?;
+        tracing::warn!("[Chaos] Injecting {:?} for upstream method {}", fault, method);
+
+        match fault {
+            ChaosFault::RateLimited => Some(Ok(synthetic_response(
+                429,
+                json!({
+                    "error": {
+                        "code": 429,
+                        "message": "[Chaos] Injected rate limit. Resource has been exhausted (e.g. check quota).",
+                        "status": "RESOURCE_EXHAUSTED",
+                        "details": [
+                            {
+                                "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                                "retryDelay": "1s"
+                            },
+                            {
+                                "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+                                "reason": "RATE_LIMIT_EXCEEDED",
+                                "metadata": { "quotaResetDelay": "1s" }
+                            }
+                        ]
+                    }
+                }),
+            ))),
+            ChaosFault::Unavailable => Some(Ok(synthetic_response(
+                503,
+                json!({
+                    "error": {
+                        "code": 503,
+                        "message": "[Chaos] Injected outage. The service is currently unavailable.",
+                        "status": "UNAVAILABLE"
+                    }
+                }),
+            ))),
+            ChaosFault::Timeout => {
+                tokio::time::sleep(Duration::from_millis(self.config.timeout_ms)).await;
+                Some(Err(format!(
+                    "[Chaos] Injected upstream timeout after {}ms",
+                    self.config.timeout_ms
+                )))
+            }
+        }
+    }
+
+    /// 对成功响应的流式 chunk 注入随机延迟
+    pub fn slow_down(&self, resp: Response) -> Response {
+        let probability = self.config.slow_chunk_probability.clamp(0.0, 1.0);
+        if probability <= 0.0 || !resp.status().is_success() {
+            return resp;
+        }
+
+        let delay = Duration::from_millis(self.config.slow_chunk_delay_ms);
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let stream = resp.bytes_stream().then(move |chunk| async move {
+            if rand::random::<f64>() < probability {
+                tracing::debug!("[Chaos] Delaying chunk by {:?}", delay);
+                tokio::time::sleep(delay).await;
+            }
+            chunk
+        });
+
+        let mut builder = axum::http::Response::builder().status(status);
+        for (name, value) in headers.iter() {
+            if name == reqwest::header::CONTENT_LENGTH {
+                continue;
+            }
+            builder = builder.header(name, value);
+        }
+        match builder.body(reqwest::Body::wrap_stream(stream)) {
+            Ok(r) => Response::from(r),
+            Err(e) => {
+                tracing::error!("[Chaos] Failed to rebuild slowed response: {}", e);
+                synthetic_response(502, json!({ "error": { "message": "chaos slow_down failed" } }))
+            }
+        }
+    }
+}
+
+fn synthetic_response(status: u16, body: serde_json::Value) -> Response {
+    Response::from(
+        axum::http::Response::builder()
+            .status(status)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(reqwest::Body::from(body.to_string()))
+            .unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injector(p429: f64, p503: f64, ptimeout: f64) -> ChaosInjector {
+        ChaosInjector::from_config(&ChaosConfig {
+            enabled: true,
+            rate_limit_probability: p429,
+            unavailable_probability: p503,
+            timeout_probability: ptimeout,
+            timeout_ms: 10,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(ChaosInjector::from_config(&ChaosConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_pick_fault_cumulative_profile() {
+        let chaos = injector(0.1, 0.2, 0.3);
+        assert_eq!(chaos.pick_fault(0.05), Some(ChaosFault::RateLimited));
+        assert_eq!(chaos.pick_fault(0.15), Some(ChaosFault::Unavailable));
+        assert_eq!(chaos.pick_fault(0.45), Some(ChaosFault::Timeout));
+        assert_eq!(chaos.pick_fault(0.75), None);
+    }
+
+    #[tokio::test]
+    async fn test_injected_429_is_parseable() {
+        let chaos = injector(1.0, 0.0, 0.0);
+        let resp = chaos.inject("streamGenerateContent").await.unwrap().unwrap();
+        assert_eq!(resp.status().as_u16(), 429);
+        let text = resp.text().await.unwrap();
+        // 与真实上游一致，可被重试逻辑解析
+        assert_eq!(crate::proxy::upstream::retry::parse_retry_delay(&text), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn test_injected_timeout_is_error() {
+        let chaos = injector(0.0, 0.0, 1.0);
+        let result = chaos.inject("generateContent").await.unwrap();
+        assert!(result.unwrap_err().contains("timeout"));
+    }
+
+    #[tokio::test]
+    async fn test_no_fault_passes_through() {
+        let chaos = injector(0.0, 0.0, 0.0);
+        assert!(chaos.inject("generateContent").await.is_none());
+    }
+}
@@ -16,10 +16,30 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
 
 pub struct UpstreamClient {
     http_client: Client,
+    // v1internal 端点列表 (按 Fallback 顺序)，默认为 prod → daily
+    base_urls: Vec<String>,
+    // 上游响应录制/回放 (None 表示关闭)
+    fixtures: Option<super::fixtures::FixtureStore>,
+    // 故障注入 (None 表示关闭)
+    chaos: Option<super::chaos::ChaosInjector>,
 }
 
 impl UpstreamClient {
     pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
+        let base_urls = V1_INTERNAL_BASE_URL_FALLBACKS
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        Self::with_base_urls(proxy_config, base_urls)
+    }
+
+    /// 使用自定义 v1internal 端点创建客户端
+    ///
+    /// 主要用于集成测试 (指向本地 mock upstream)，也便于调试自建网关
+    pub fn with_base_urls(
+        proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>,
+        base_urls: Vec<String>,
+    ) -> Self {
         let mut builder = Client::builder()
             // Connection settings (优化连接复用，减少建立开销)
             .connect_timeout(Duration::from_secs(20))
@@ -40,7 +60,24 @@ impl UpstreamClient {
 
         let http_client = builder.build().expect("Failed to create HTTP client");
 
-        Self { http_client }
+        Self {
+            http_client,
+            base_urls,
+            fixtures: None,
+            chaos: None,
+        }
+    }
+
+    /// 启用上游响应录制/回放
+    pub fn with_fixtures(mut self, fixtures: Option<super::fixtures::FixtureStore>) -> Self {
+        self.fixtures = fixtures;
+        self
+    }
+
+    /// 启用故障注入 (Chaos 模式)
+    pub fn with_chaos(mut self, chaos: Option<super::chaos::ChaosInjector>) -> Self {
+        self.chaos = chaos;
+        self
     }
 
     /// 构建 v1internal URL
@@ -77,6 +114,76 @@ impl UpstreamClient {
         access_token: &str,
         body: Value,
         query_string: Option<&str>,
+    ) -> Result<Response, String> {
+        self.call_v1_internal_with_timeout(method, access_token, body, query_string, None)
+            .await
+    }
+
+    /// 调用 v1internal API，并覆盖单次请求的总超时 (用于长时间运行的流式请求)
+    pub async fn call_v1_internal_with_timeout(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        // [Chaos] 故障注入 (仅用于韧性测试)
+        if let Some(chaos) = &self.chaos {
+            if let Some(injected) = chaos.inject(method).await {
+                return injected;
+            }
+        }
+
+        if crate::modules::logger::raw_body_logging() {
+            crate::modules::logger::log_raw_body(&format!("upstream request {}", method), &body.to_string());
+        }
+        let resp = self
+            .dispatch(method, access_token, &body, query_string, timeout)
+            .await?;
+
+        match &self.chaos {
+            Some(chaos) => Ok(chaos.slow_down(resp)),
+            None => Ok(resp),
+        }
+    }
+
+    /// 按夹具模式分发：回放 / 录制 / 直连上游
+    async fn dispatch(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: &Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        let Some(fixtures) = &self.fixtures else {
+            return self
+                .send_with_fallback(method, access_token, body, query_string, timeout)
+                .await;
+        };
+
+        if fixtures.mode() == crate::proxy::config::FixtureMode::Replay {
+            return fixtures.replay(method, query_string, body);
+        }
+
+        let resp = self
+            .send_with_fallback(method, access_token, body, query_string, timeout)
+            .await?;
+        let mut secrets = vec![access_token.to_string()];
+        if let Some(project) = body.get("project").and_then(|v| v.as_str()) {
+            secrets.push(project.to_string());
+        }
+        Ok(fixtures.capture(method, query_string, body, resp, secrets))
+    }
+
+    async fn send_with_fallback(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: &Value,
+        query_string: Option<&str>,
+        timeout: Option<Duration>,
     ) -> Result<Response, String> {
         // 构建 Headers (所有端点复用)
         let mut headers = header::HeaderMap::new();
@@ -97,17 +204,15 @@ impl UpstreamClient {
         let mut last_err: Option<String> = None;
 
         // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        for (idx, base_url) in self.base_urls.iter().enumerate() {
             let url = Self::build_url(base_url, method, query_string);
-            let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+            let has_next = idx + 1 < self.base_urls.len();
 
-            let response = self
-                .http_client
-                .post(&url)
-                .headers(headers.clone())
-                .json(&body)
-                .send()
-                .await;
+            let mut request = self.http_client.post(&url).headers(headers.clone()).json(body);
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+            let response = request.send().await;
 
             match response {
                 Ok(resp) => {
@@ -119,7 +224,7 @@ impl UpstreamClient {
                                 base_url,
                                 status,
                                 idx + 1,
-                                V1_INTERNAL_BASE_URL_FALLBACKS.len()
+                                self.base_urls.len()
                             );
                         } else {
                             tracing::debug!("✓ Upstream request succeeded | Endpoint: {} | Status: {}", base_url, status);
@@ -178,6 +283,44 @@ impl UpstreamClient {
 
     // 已移除弃用的辅助方法 (parse_duration_ms)
 
+    /// 连通性探测：使用与正常请求相同的 HTTP 客户端 (上游代理 / HTTP(S)_PROXY / 系统代理设置一致)
+    /// 依次向各端点发送 HEAD，任一端点返回任意 HTTP 响应即视为可达；回放模式下无需联网
+    pub async fn probe_reachable(&self, timeout: Duration) -> bool {
+        if self
+            .fixtures
+            .as_ref()
+            .is_some_and(|f| f.mode() == crate::proxy::config::FixtureMode::Replay)
+        {
+            return true;
+        }
+        for base_url in &self.base_urls {
+            match self.http_client.head(base_url).timeout(timeout).send().await {
+                Ok(_) => return true,
+                Err(e) => tracing::debug!("[Connectivity] 探测 {} 失败: {}", base_url, e),
+            }
+        }
+        false
+    }
+
+    /// 预建立到各端点的连接 (DNS + TLS 握手)，连接随后留在连接池中供下一次请求复用
+    /// 仅关心连接是否建立，响应状态码无意义；返回成功连通的端点数
+    pub async fn warm_connections(&self) -> usize {
+        let mut connected = 0;
+        for base_url in &self.base_urls {
+            match self
+                .http_client
+                .head(base_url)
+                .timeout(Duration::from_secs(10))
+                .send()
+                .await
+            {
+                Ok(_) => connected += 1,
+                Err(e) => tracing::debug!("[Idle-Warmup] 预连接 {} 失败: {}", base_url, e),
+            }
+        }
+        connected
+    }
+
     /// 获取可用模型列表
     /// 
     /// 获取远端模型列表，支持多端点自动 Fallback
@@ -201,7 +344,7 @@ impl UpstreamClient {
         let mut last_err: Option<String> = None;
 
         // 遍历所有端点，失败时自动切换
-        for (idx, base_url) in V1_INTERNAL_BASE_URL_FALLBACKS.iter().enumerate() {
+        for (idx, base_url) in self.base_urls.iter().enumerate() {
             let url = Self::build_url(base_url, "fetchAvailableModels", None);
 
             let response = self
@@ -233,7 +376,7 @@ impl UpstreamClient {
                     }
 
                     // 如果有下一个端点且当前错误可重试，则切换
-                    let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
+                    let has_next = idx + 1 < self.base_urls.len();
                     if has_next && Self::should_try_next_endpoint(status) {
                         tracing::warn!(
                             "fetchAvailableModels returned {} at {}, trying next endpoint",
@@ -253,7 +396,7 @@ impl UpstreamClient {
                     last_err = Some(msg);
 
                     // 如果是最后一个端点，退出循环
-                    if idx + 1 >= V1_INTERNAL_BASE_URL_FALLBACKS.len() {
+                    if idx + 1 >= self.base_urls.len() {
                         break;
                     }
                     continue;
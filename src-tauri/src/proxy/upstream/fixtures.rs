@@ -0,0 +1,289 @@
+// 上游响应录制与回放 (Record & Replay)
+// Capture: 透传上游响应的同时按 chunk 记录 (脱敏后) 写入夹具文件
+// Replay: 根据请求指纹读取夹具文件并按原 chunk 序列回放，不消耗配额
+
+use crate::proxy::config::{FixtureConfig, FixtureMode};
+use bytes::Bytes;
+use futures::StreamExt;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+const REDACTED: &str = "<redacted>";
+
+/// 夹具文件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixture {
+    pub key: String,
+    pub method: String,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// 原始 chunk 序列 (保留流式分片边界)
+    pub chunks: Vec<String>,
+    pub captured_at: i64,
+}
+
+/// 夹具存储
+#[derive(Debug, Clone)]
+pub struct FixtureStore {
+    mode: FixtureMode,
+    dir: PathBuf,
+}
+
+impl FixtureStore {
+    /// 根据配置创建存储，mode 为 Off 时返回 None
+    pub fn from_config(config: &FixtureConfig, default_dir: PathBuf) -> Option<Self> {
+        if config.mode == FixtureMode::Off {
+            return None;
+        }
+        let dir = config
+            .dir
+            .as_deref()
+            .filter(|d| !d.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or(default_dir);
+        tracing::warn!("[Fixtures] Upstream fixture mode {:?} enabled, dir: {:?}", config.mode, dir);
+        Some(Self {
+            mode: config.mode,
+            dir,
+        })
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    /// 计算请求指纹
+    ///
+    /// 忽略每次请求都会变化的字段 (project / requestId / sessionId)，
+    /// 保证相同的客户端请求在不同账号、不同时间下得到相同的 key
+    pub fn fixture_key(method: &str, query: Option<&str>, body: &Value) -> String {
+        let mut normalized = body.clone();
+        if let Some(obj) = normalized.as_object_mut() {
+            obj.remove("project");
+            obj.remove("requestId");
+            if let Some(req) = obj.get_mut("request").and_then(|r| r.as_object_mut()) {
+                req.remove("sessionId");
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(method.as_bytes());
+        hasher.update(b"?");
+        hasher.update(query.unwrap_or_default().as_bytes());
+        hasher.update(b"\n");
+        hasher.update(normalized.to_string().as_bytes());
+        let digest = hasher.finalize();
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn fixture_path(&self, method: &str, key: &str) -> PathBuf {
+        self.dir.join(format!("{}-{}.json", method, key))
+    }
+
+    /// 回放夹具，找不到时返回错误 (由 handler 按普通上游错误处理)
+    pub fn replay(&self, method: &str, query: Option<&str>, body: &Value) -> Result<Response, String> {
+        let key = Self::fixture_key(method, query, body);
+        let path = self.fixture_path(method, &key);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Replay fixture not found: {:?} ({})", path, e))?;
+        let fixture: Fixture = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid fixture {:?}: {}", path, e))?;
+
+        tracing::info!("[Fixtures] Replaying {} ({} chunks)", path.display(), fixture.chunks.len());
+
+        let chunks = fixture
+            .chunks
+            .into_iter()
+            .map(|c| Ok::<Bytes, std::io::Error>(Bytes::from(c)));
+        let mut builder = axum::http::Response::builder().status(fixture.status);
+        if let Some(ct) = fixture.content_type {
+            builder = builder.header(reqwest::header::CONTENT_TYPE, ct);
+        }
+        let resp = builder
+            .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks)))
+            .map_err(|e| format!("Build replay response failed: {}", e))?;
+        Ok(Response::from(resp))
+    }
+
+    /// 透传上游响应并在流结束时写入夹具
+    ///
+    /// `secrets` 中的字符串 (access_token / project_id 等) 会在落盘前替换为 `<redacted>`
+    pub fn capture(
+        &self,
+        method: &str,
+        query: Option<&str>,
+        body: &Value,
+        resp: Response,
+        secrets: Vec<String>,
+    ) -> Response {
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let path = self.fixture_path(method, &Self::fixture_key(method, query, body));
+        let mut fixture = Fixture {
+            key: Self::fixture_key(method, query, body),
+            method: method.to_string(),
+            query: query.map(|q| q.to_string()),
+            model: body.get("model").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            status: status.as_u16(),
+            content_type: headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string()),
+            chunks: Vec::new(),
+            captured_at: chrono::Utc::now().timestamp(),
+        };
+
+        let stream = async_stream::stream! {
+            let mut upstream = resp.bytes_stream();
+            while let Some(item) = upstream.next().await {
+                match item {
+                    Ok(bytes) => {
+                        fixture.chunks.push(sanitize(&String::from_utf8_lossy(&bytes), &secrets));
+                        yield Ok(bytes);
+                    }
+                    Err(e) => {
+                        // 流中断的响应不落盘，避免回放残缺数据
+                        tracing::warn!("[Fixtures] Upstream stream error, fixture discarded: {}", e);
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            if let Err(e) = write_fixture(&path, &fixture) {
+                tracing::warn!("[Fixtures] Failed to write fixture {:?}: {}", path, e);
+            } else {
+                tracing::info!("[Fixtures] Captured {} ({} chunks)", path.display(), fixture.chunks.len());
+            }
+        };
+
+        let mut builder = axum::http::Response::builder().status(status);
+        for (name, value) in headers.iter() {
+            // 长度由流决定，移除原始的 Content-Length / Transfer-Encoding
+            if name == reqwest::header::CONTENT_LENGTH || name == reqwest::header::TRANSFER_ENCODING {
+                continue;
+            }
+            builder = builder.header(name, value);
+        }
+        match builder.body(reqwest::Body::wrap_stream(stream)) {
+            Ok(r) => Response::from(r),
+            Err(e) => {
+                tracing::error!("[Fixtures] Failed to rebuild captured response: {}", e);
+                Response::from(
+                    axum::http::Response::builder()
+                        .status(502)
+                        .body(reqwest::Body::from("fixture capture failed"))
+                        .unwrap(),
+                )
+            }
+        }
+    }
+}
+
+fn sanitize(text: &str, secrets: &[String]) -> String {
+    let mut out = text.to_string();
+    for secret in secrets.iter().filter(|s| !s.is_empty()) {
+        out = out.replace(secret.as_str(), REDACTED);
+    }
+    out
+}
+
+fn write_fixture(path: &PathBuf, fixture: &Fixture) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let content = serde_json::to_string_pretty(fixture).map_err(|e| e.to_string())?;
+    std::fs::write(path, content).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_store(mode: FixtureMode) -> FixtureStore {
+        let dir = std::env::temp_dir().join(format!("ag-fixtures-{}", uuid::Uuid::new_v4()));
+        FixtureStore::from_config(&FixtureConfig { mode, dir: None }, dir).unwrap()
+    }
+
+    #[test]
+    fn test_fixture_key_ignores_volatile_fields() {
+        let a = json!({
+            "project": "p-1", "requestId": "agent-1", "model": "gemini-2.5-flash",
+            "request": { "contents": [{"role": "user", "parts": [{"text": "hi"}]}], "sessionId": "s-1" }
+        });
+        let b = json!({
+            "project": "p-2", "requestId": "agent-2", "model": "gemini-2.5-flash",
+            "request": { "contents": [{"role": "user", "parts": [{"text": "hi"}]}], "sessionId": "s-2" }
+        });
+        let c = json!({
+            "project": "p-1", "requestId": "agent-1", "model": "gemini-2.5-flash",
+            "request": { "contents": [{"role": "user", "parts": [{"text": "bye"}]}] }
+        });
+        let key_a = FixtureStore::fixture_key("streamGenerateContent", Some("alt=sse"), &a);
+        assert_eq!(key_a, FixtureStore::fixture_key("streamGenerateContent", Some("alt=sse"), &b));
+        assert_ne!(key_a, FixtureStore::fixture_key("streamGenerateContent", Some("alt=sse"), &c));
+        assert_ne!(key_a, FixtureStore::fixture_key("generateContent", None, &a));
+    }
+
+    #[test]
+    fn test_off_mode_disabled() {
+        assert!(FixtureStore::from_config(&FixtureConfig::default(), PathBuf::from("/tmp")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_capture_then_replay_round_trip() {
+        let capture = temp_store(FixtureMode::Capture);
+        let body = json!({ "project": "secret-project", "model": "gemini-2.5-flash", "request": {} });
+
+        let chunks = vec![
+            "data: {\"response\":{\"text\":\"a\"}}\n\n",
+            "data: {\"response\":{\"text\":\"b secret-project\"}}\n\n",
+        ];
+        let upstream = Response::from(
+            axum::http::Response::builder()
+                .status(200)
+                .header("content-type", "text/event-stream")
+                .body(reqwest::Body::wrap_stream(futures::stream::iter(
+                    chunks.clone().into_iter().map(|c| Ok::<_, std::io::Error>(Bytes::from(c))),
+                )))
+                .unwrap(),
+        );
+
+        let captured = capture.capture(
+            "streamGenerateContent",
+            Some("alt=sse"),
+            &body,
+            upstream,
+            vec!["secret-project".to_string()],
+        );
+        // 透传内容不受脱敏影响
+        let passthrough = captured.text().await.unwrap();
+        assert_eq!(passthrough, chunks.concat());
+
+        let replay = FixtureStore { mode: FixtureMode::Replay, dir: capture.dir.clone() };
+        let resp = replay.replay("streamGenerateContent", Some("alt=sse"), &body).unwrap();
+        assert_eq!(resp.status().as_u16(), 200);
+        let mut stream = resp.bytes_stream();
+        let mut replayed = Vec::new();
+        while let Some(Ok(b)) = stream.next().await {
+            replayed.push(String::from_utf8(b.to_vec()).unwrap());
+        }
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[0], chunks[0]);
+        assert!(replayed[1].contains(REDACTED));
+        assert!(!replayed[1].contains("secret-project"));
+
+        // 未录制的请求
+        assert!(replay
+            .replay("streamGenerateContent", Some("alt=sse"), &json!({ "model": "other" }))
+            .is_err());
+    }
+}
@@ -4,3 +4,6 @@
 pub mod client;
 pub mod retry;
 pub mod models;
+pub mod fixtures;
+pub mod chaos;
+pub mod stream_failover;
@@ -0,0 +1,333 @@
+// 流中途故障转移 (Mid-stream Failover)
+// 上游流在生成中途断开 (连接错误 / 5xx 导致未收到 finishReason) 时，客户端只能拿到被截断的回答。
+// 启用后，这里会换一个账号重放同一请求，并把已经输出的文本作为 model 轮前缀追加到 contents 末尾，
+// 让模型从断点继续生成；续写流中与已输出内容重叠的文本会被去重，思考块被丢弃，
+// 对客户端而言仍是同一条连续的流。已输出工具调用时不做故障转移 (无法安全续写)。
+
+use crate::proxy::config::MidStreamFailoverConfig;
+use crate::proxy::token_manager::TokenManager;
+use crate::proxy::upstream::client::UpstreamClient;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub type UpstreamStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// 续写开头判定重叠前最少缓冲的字符数
+const MIN_DEDUPE_CHARS: usize = 16;
+/// 认定为重叠的最短长度 (字符)
+const MIN_OVERLAP_CHARS: usize = 4;
+
+/// 重放请求所需的上下文
+pub struct FailoverRequest {
+    pub token_manager: Arc<TokenManager>,
+    pub upstream: Arc<UpstreamClient>,
+    /// 原始 v1internal 请求体
+    pub body: Value,
+    pub request_type: String,
+    pub quota_group: String,
+    pub session_id: Option<String>,
+    pub model: String,
+    pub trace_id: String,
+}
+
+/// 续写开头的去重器：缓冲续写文本直到能判断与已输出内容的重叠部分
+struct Dedupe {
+    prefix: String,
+    pending: String,
+}
+
+impl Dedupe {
+    fn new(prefix: String) -> Self {
+        Self { prefix, pending: String::new() }
+    }
+
+    /// 返回可以输出的文本；None 表示仍在缓冲
+    fn feed(&mut self, text: &str, final_chunk: bool) -> Option<String> {
+        self.pending.push_str(text);
+        let pending = &self.pending;
+        // 模型从头重写且尚未超出已输出部分，继续缓冲
+        let repeating = self.prefix.starts_with(pending.as_str());
+        if !final_chunk && (repeating || pending.chars().count() < MIN_DEDUPE_CHARS) {
+            return None;
+        }
+        let output = strip_overlap(&self.prefix, pending);
+        self.pending.clear();
+        Some(output)
+    }
+}
+
+/// 去掉续写文本中与已输出文本重叠的开头部分
+fn strip_overlap(prefix: &str, continuation: &str) -> String {
+    if let Some(rest) = continuation.strip_prefix(prefix) {
+        return rest.to_string();
+    }
+    if prefix.starts_with(continuation) {
+        return String::new();
+    }
+    // 已输出文本的结尾与续写文本的开头的最长公共部分
+    let boundaries: Vec<usize> = continuation
+        .char_indices()
+        .map(|(i, _)| i)
+        .skip(1)
+        .chain(std::iter::once(continuation.len()))
+        .collect();
+    for &end in boundaries.iter().rev() {
+        let head = &continuation[..end];
+        if head.chars().count() >= MIN_OVERLAP_CHARS && prefix.ends_with(head) {
+            return continuation[end..].to_string();
+        }
+    }
+    continuation.to_string()
+}
+
+/// 单行 SSE 的处理结果
+struct LineState {
+    /// 已输出的可见文本
+    partial: String,
+    finished: bool,
+    saw_function_call: bool,
+    dedupe: Option<Dedupe>,
+}
+
+impl LineState {
+    /// 处理一行 SSE，返回要输出的行 (None 表示丢弃)
+    fn process(&mut self, line: &str) -> Option<String> {
+        let Some(payload) = line.strip_prefix("data:") else {
+            return Some(line.to_string());
+        };
+        let Ok(mut event) = serde_json::from_str::<Value>(payload.trim()) else {
+            return Some(line.to_string());
+        };
+        let continuing = self.dedupe.is_some();
+        let mut modified = false;
+        {
+            let response = if event.get("response").is_some() { &mut event["response"] } else { &mut event };
+            let Some(candidate) = response.get_mut("candidates").and_then(|c| c.get_mut(0)) else {
+                return Some(line.to_string());
+            };
+            let finished = candidate.get("finishReason").is_some();
+            if let Some(parts) = candidate.pointer_mut("/content/parts").and_then(|p| p.as_array_mut()) {
+                let mut kept = Vec::with_capacity(parts.len());
+                for mut part in parts.drain(..) {
+                    let is_thought = part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false);
+                    if part.get("functionCall").is_some() {
+                        self.saw_function_call = true;
+                    }
+                    if continuing && is_thought {
+                        modified = true;
+                        continue;
+                    }
+                    if let (Some(text), false) = (part.get("text").and_then(|t| t.as_str()).map(str::to_string), is_thought) {
+                        let text = match self.dedupe.as_mut() {
+                            Some(dedupe) if !dedupe.prefix.is_empty() => {
+                                modified = true;
+                                match dedupe.feed(&text, finished) {
+                                    Some(out) => {
+                                        // 重叠已判定，后续文本原样输出
+                                        dedupe.prefix.clear();
+                                        out
+                                    }
+                                    None => String::new(),
+                                }
+                            }
+                            _ => text,
+                        };
+                        if text.is_empty() {
+                            continue;
+                        }
+                        self.partial.push_str(&text);
+                        part["text"] = Value::String(text);
+                    }
+                    kept.push(part);
+                }
+                *parts = kept;
+            }
+            if finished {
+                self.finished = true;
+                // 流结束时仍在缓冲的文本按最终结果输出
+                if let Some(dedupe) = self.dedupe.as_mut() {
+                    if !dedupe.prefix.is_empty() && !dedupe.pending.is_empty() {
+                        let out = dedupe.feed("", true).unwrap_or_default();
+                        dedupe.prefix.clear();
+                        if !out.is_empty() {
+                            self.partial.push_str(&out);
+                            if let Some(parts) = candidate.pointer_mut("/content/parts").and_then(|p| p.as_array_mut()) {
+                                parts.insert(0, json!({ "text": out }));
+                                modified = true;
+                            }
+                        }
+                    }
+                }
+            }
+            let empty = candidate
+                .pointer("/content/parts")
+                .and_then(|p| p.as_array())
+                .is_some_and(|p| p.is_empty());
+            let has_meta = finished || response.get("usageMetadata").is_some();
+            if modified && empty && !has_meta {
+                return None;
+            }
+        }
+        if modified {
+            Some(format!("data: {}", event))
+        } else {
+            Some(line.to_string())
+        }
+    }
+}
+
+/// 在请求体末尾追加已输出文本作为 model 轮前缀
+fn continuation_body(mut body: Value, project_id: &str, partial: &str) -> Value {
+    body["project"] = Value::String(project_id.to_string());
+    if partial.is_empty() {
+        return body;
+    }
+    if let Some(contents) = body.pointer_mut("/request/contents").and_then(|c| c.as_array_mut()) {
+        match contents.last_mut() {
+            Some(last) if last["role"] == "model" => {
+                if let Some(parts) = last["parts"].as_array_mut() {
+                    parts.push(json!({ "text": partial }));
+                }
+            }
+            _ => contents.push(json!({ "role": "model", "parts": [{ "text": partial }] })),
+        }
+    }
+    body
+}
+
+async fn reopen(request: &FailoverRequest, partial: &str) -> Result<(UpstreamStream, String), String> {
+    let (access_token, project_id, email) = request
+        .token_manager
+        .get_token(&request.request_type, &request.quota_group, true, request.session_id.as_deref(), Some(&request.model))
+        .await?;
+    let body = continuation_body(request.body.clone(), &project_id, partial);
+    let response = request
+        .upstream
+        .call_v1_internal("streamGenerateContent", &access_token, body, Some("alt=sse"))
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    Ok((Box::pin(response.bytes_stream()), email))
+}
+
+/// 包装上游原始 SSE 流：中途断开时换账号续写
+pub fn wrap(stream: UpstreamStream, config: &MidStreamFailoverConfig, request: FailoverRequest) -> UpstreamStream {
+    let max_failovers = config.max_failovers;
+    // 流在 handler 返回后才被消费，固定账号 (X-Account) 需在此取出并在续写时恢复
+    let pinned = crate::proxy::token_manager::pinned_account();
+    Box::pin(async_stream::stream! {
+        let mut current = stream;
+        let mut failovers = 0usize;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut state = LineState { partial: String::new(), finished: false, saw_function_call: false, dedupe: None };
+        loop {
+            let failure = match current.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    let mut out = String::new();
+                    while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                        let raw: Vec<u8> = buffer.drain(..=pos).collect();
+                        let line = String::from_utf8_lossy(&raw[..raw.len() - 1]).trim_end_matches('\r').to_string();
+                        if let Some(line) = state.process(&line) {
+                            out.push_str(&line);
+                            out.push('\n');
+                        }
+                    }
+                    if !out.is_empty() {
+                        yield Ok(Bytes::from(out));
+                    }
+                    continue;
+                }
+                Some(Err(e)) => Some(e),
+                None if state.finished => break,
+                None => None,
+            };
+
+            let reason = failure.as_ref().map(|e| e.to_string()).unwrap_or_else(|| "stream ended without finishReason".to_string());
+            let can_failover = failovers < max_failovers && !state.finished && !state.saw_function_call;
+            let reopened = if can_failover {
+                match crate::proxy::token_manager::with_pinned_account(pinned.clone(), reopen(&request, &state.partial)).await {
+                    Ok(reopened) => Some(reopened),
+                    Err(e) => {
+                        tracing::warn!("[{}] Mid-stream failover failed: {}", request.trace_id, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            match reopened {
+                Some((stream, email)) => {
+                    failovers += 1;
+                    tracing::warn!(
+                        "[{}] Upstream stream broke after {} chars ({}); continuing on {} (failover {}/{})",
+                        request.trace_id, state.partial.chars().count(), reason, email, failovers, max_failovers
+                    );
+                    // 丢弃断开前未完整的一行，续写内容从新的事件开始
+                    buffer.clear();
+                    state.dedupe = Some(Dedupe::new(state.partial.clone()));
+                    current = stream;
+                }
+                None => {
+                    if let Some(e) = failure {
+                        yield Err(e);
+                    }
+                    break;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_overlap() {
+        assert_eq!(strip_overlap("The quick brown", "The quick brown fox jumps"), " fox jumps");
+        assert_eq!(strip_overlap("The quick brown", "brown fox jumps"), " fox jumps");
+        assert_eq!(strip_overlap("The quick brown", " fox jumps over"), " fox jumps over");
+        // 过短的重叠不视为重复
+        assert_eq!(strip_overlap("a cat", "cat and dog"), "cat and dog");
+    }
+
+    #[test]
+    fn test_continuation_dedupes_and_drops_thoughts() {
+        let mut state = LineState {
+            partial: "Hello wor".to_string(),
+            finished: false,
+            saw_function_call: false,
+            dedupe: Some(Dedupe::new("Hello wor".to_string())),
+        };
+        let event = |parts: Value, finish: bool| {
+            let mut candidate = json!({ "content": { "role": "model", "parts": parts } });
+            if finish {
+                candidate["finishReason"] = json!("STOP");
+            }
+            format!("data: {}", json!({ "response": { "candidates": [candidate] } }))
+        };
+        // 思考块被丢弃，重复的开头被缓冲
+        assert!(state.process(&event(json!([{ "text": "plan", "thought": true }]), false)).is_none());
+        assert!(state.process(&event(json!([{ "text": "Hello " }]), false)).is_none());
+        let line = state.process(&event(json!([{ "text": "world, how are you?" }]), false)).unwrap();
+        assert!(line.contains("\"text\":\"ld, how are you?\""), "{}", line);
+        let line = state.process(&event(json!([{ "text": " Fine." }]), true)).unwrap();
+        assert!(line.contains("\"text\":\" Fine.\""));
+        assert!(state.finished);
+        assert_eq!(state.partial, "Hello world, how are you? Fine.");
+    }
+
+    #[test]
+    fn test_continuation_body_appends_model_prefix() {
+        let body = json!({ "project": "p0", "request": { "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }] } });
+        let next = continuation_body(body, "p1", "partial answer");
+        assert_eq!(next["project"], "p1");
+        assert_eq!(next["request"]["contents"][1]["role"], "model");
+        assert_eq!(next["request"]["contents"][1]["parts"][0]["text"], "partial answer");
+    }
+}
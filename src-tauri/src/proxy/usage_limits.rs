@@ -0,0 +1,287 @@
+// 每日 Token 用量上限
+// 按账号统计上游返回的 usageMetadata.totalTokenCount，计数在账号的配额重置时间清零。
+// 计数随运行时状态快照 (runtime_state.json) 持久化，重启后恢复当前周期的用量。
+// 软上限：调度器尽量避开；硬上限：严格禁止，直到重置。
+
+use crate::proxy::config::{TokenCap, UsageLimitConfig};
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// 账号当前周期内的用量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub tokens: u64,
+    /// 计数清零时间 (unix 秒)
+    pub resets_at: i64,
+}
+
+/// 账号相对上限的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapStatus {
+    Ok,
+    OverSoft,
+    /// 超过硬上限，附带距离重置的秒数
+    OverHard { resets_in: i64 },
+}
+
+/// 用量上限跟踪器 (key 为账号 email)
+pub struct UsageLimiter {
+    config: RwLock<UsageLimitConfig>,
+    usage: DashMap<String, DailyUsage>,
+}
+
+impl Default for UsageLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsageLimiter {
+    pub fn new() -> Self {
+        Self {
+            config: RwLock::new(UsageLimitConfig::default()),
+            usage: DashMap::new(),
+        }
+    }
+
+    pub async fn update_config(&self, config: UsageLimitConfig) {
+        *self.config.write().await = config;
+    }
+
+    pub async fn is_enabled(&self) -> bool {
+        self.config.read().await.enabled
+    }
+
+    /// 累加账号用量
+    ///
+    /// `next_reset` 仅在新周期开始时调用，用于计算本周期的清零时间
+    pub fn record(&self, email: &str, tokens: u64, next_reset: impl FnOnce() -> i64) {
+        if tokens == 0 {
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let mut entry = self.usage.entry(email.to_string()).or_insert(DailyUsage {
+            tokens: 0,
+            resets_at: 0,
+        });
+        if entry.resets_at <= now {
+            entry.tokens = 0;
+            entry.resets_at = next_reset();
+        }
+        entry.tokens = entry.tokens.saturating_add(tokens);
+    }
+
+    /// 获取账号当前周期的用量 (已过期的周期视为 0)
+    pub fn usage(&self, email: &str) -> Option<DailyUsage> {
+        let now = chrono::Utc::now().timestamp();
+        self.usage
+            .get(email)
+            .map(|u| *u)
+            .filter(|u| u.resets_at > now)
+    }
+
+    /// 当前周期内的全部用量 (用于持久化)
+    pub fn export(&self) -> HashMap<String, DailyUsage> {
+        let now = chrono::Utc::now().timestamp();
+        self.usage
+            .iter()
+            .filter(|e| e.resets_at > now)
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect()
+    }
+
+    /// 从快照恢复用量，跳过已过期的周期，返回恢复的条数
+    pub fn restore(&self, usage: HashMap<String, DailyUsage>) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        let mut restored = 0;
+        for (email, entry) in usage.into_iter().filter(|(_, u)| u.resets_at > now) {
+            self.usage.insert(email, entry);
+            restored += 1;
+        }
+        restored
+    }
+
+    /// 检查账号相对上限的状态
+    pub async fn status(&self, email: &str, tier: Option<&str>) -> CapStatus {
+        let config = self.config.read().await;
+        if !config.enabled {
+            return CapStatus::Ok;
+        }
+        let Some(usage) = self.usage(email) else {
+            return CapStatus::Ok;
+        };
+        evaluate(config.cap_for(email, tier), usage)
+    }
+}
+
+fn evaluate(cap: TokenCap, usage: DailyUsage) -> CapStatus {
+    if cap.hard.is_some_and(|hard| usage.tokens >= hard) {
+        return CapStatus::OverHard {
+            resets_in: (usage.resets_at - chrono::Utc::now().timestamp()).max(0),
+        };
+    }
+    if cap.soft.is_some_and(|soft| usage.tokens >= soft) {
+        return CapStatus::OverSoft;
+    }
+    CapStatus::Ok
+}
+
+/// 从 v1internal 响应 (或其中一行 SSE) 中提取 totalTokenCount
+pub fn extract_total_tokens(value: &Value) -> Option<u64> {
+    let inner = value.get("response").unwrap_or(value);
+    inner
+        .get("usageMetadata")
+        .and_then(|u| u.get("totalTokenCount"))
+        .and_then(|v| v.as_u64())
+}
+
+/// 用量记账守卫：流结束或被客户端中断 (Drop) 时写入用量
+struct UsageGuard {
+    on_finish: Option<Box<dyn FnOnce(u64) + Send>>,
+    tokens: u64,
+}
+
+impl Drop for UsageGuard {
+    fn drop(&mut self) {
+        if let Some(f) = self.on_finish.take() {
+            f(self.tokens);
+        }
+    }
+}
+
+/// 旁路统计上游 SSE 流中的 usageMetadata，不修改流内容
+///
+/// usageMetadata 为累计值，取流中出现的最大值
+pub fn tap_usage<S, E>(
+    stream: S,
+    on_finish: impl FnOnce(u64) + Send + 'static,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let mut guard = UsageGuard {
+        on_finish: Some(Box::new(on_finish)),
+        tokens: 0,
+    };
+    let mut pending = String::new();
+    stream.map(move |item| {
+        // 显式借用整个守卫，避免闭包只按字段捕获 tokens 导致守卫提前 Drop
+        let guard = &mut guard;
+        if let Ok(chunk) = &item {
+            pending.push_str(&String::from_utf8_lossy(chunk));
+            while let Some(pos) = pending.find('\n') {
+                let line: String = pending.drain(..=pos).collect();
+                if let Some(data) = line.trim().strip_prefix("data:") {
+                    if let Some(total) = serde_json::from_str::<Value>(data.trim())
+                        .ok()
+                        .as_ref()
+                        .and_then(extract_total_tokens)
+                    {
+                        guard.tokens = guard.tokens.max(total);
+                    }
+                }
+            }
+        }
+        item
+    })
+}
+
+/// 计算下一个 UTC 零点 (无配额重置时间时的兜底)
+pub fn next_utc_midnight() -> i64 {
+    let now = chrono::Utc::now();
+    let tomorrow = now.date_naive().succ_opt().unwrap_or(now.date_naive());
+    tomorrow
+        .and_hms_opt(0, 0, 0)
+        .map(|dt| dt.and_utc().timestamp())
+        .unwrap_or(now.timestamp() + 86_400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn future() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    async fn limiter(cap: TokenCap) -> UsageLimiter {
+        let limiter = UsageLimiter::new();
+        limiter
+            .update_config(UsageLimitConfig {
+                enabled: true,
+                default: cap,
+                tiers: HashMap::from([(
+                    "FREE".to_string(),
+                    TokenCap { soft: Some(10), hard: Some(20) },
+                )]),
+                accounts: HashMap::new(),
+            })
+            .await;
+        limiter
+    }
+
+    #[tokio::test]
+    async fn test_soft_and_hard_caps() {
+        let limiter = limiter(TokenCap { soft: Some(100), hard: Some(200) }).await;
+        assert_eq!(limiter.status("a@example.com", None).await, CapStatus::Ok);
+
+        limiter.record("a@example.com", 150, future);
+        assert_eq!(limiter.status("a@example.com", None).await, CapStatus::OverSoft);
+
+        limiter.record("a@example.com", 60, future);
+        assert!(matches!(
+            limiter.status("a@example.com", None).await,
+            CapStatus::OverHard { resets_in } if resets_in > 0
+        ));
+
+        // FREE 等级使用更低的上限
+        limiter.record("b@example.com", 15, future);
+        assert_eq!(limiter.status("b@example.com", Some("FREE")).await, CapStatus::OverSoft);
+        assert_eq!(limiter.status("b@example.com", Some("PRO")).await, CapStatus::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_counter_resets_after_reset_time() {
+        let limiter = limiter(TokenCap { soft: None, hard: Some(10) }).await;
+        // 周期已过期：下次记录时清零
+        limiter.record("a@example.com", 50, || chrono::Utc::now().timestamp() - 1);
+        assert_eq!(limiter.status("a@example.com", None).await, CapStatus::Ok);
+
+        limiter.record("a@example.com", 5, future);
+        assert_eq!(limiter.usage("a@example.com").unwrap().tokens, 5);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_never_limits() {
+        let limiter = UsageLimiter::new();
+        limiter.record("a@example.com", 1_000_000, future);
+        assert_eq!(limiter.status("a@example.com", None).await, CapStatus::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_tap_usage_reads_cumulative_usage_across_chunks() {
+        let total = std::sync::Arc::new(std::sync::Mutex::new(0u64));
+        let sink = total.clone();
+        let chunks = vec![
+            Ok::<_, String>(Bytes::from("data: {\"response\":{\"usageMetadata\":{\"totalTokenCount\":5}}}\n\ndata: {\"resp")),
+            Ok(Bytes::from("onse\":{\"usageMetadata\":{\"totalTokenCount\":42}}}\n\n")),
+        ];
+        let out: Vec<_> = tap_usage(futures::stream::iter(chunks), move |t| *sink.lock().unwrap() = t)
+            .collect()
+            .await;
+        assert_eq!(out.len(), 2);
+        assert_eq!(*total.lock().unwrap(), 42);
+
+        assert_eq!(
+            extract_total_tokens(&json!({ "usageMetadata": { "totalTokenCount": 7 } })),
+            Some(7)
+        );
+    }
+}
@@ -40,10 +40,10 @@ import {
     ToggleRight,
     Sparkles,
 } from 'lucide-react';
-import { Account } from '../../types/account';
+import { Account, AccountForecast, AccountUsageStats } from '../../types/account';
 import { useTranslation } from 'react-i18next';
 import { cn } from '../../utils/cn';
-import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor } from '../../utils/format';
+import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor, formatCompactNumber } from '../../utils/format';
 
 // ============================================================================
 // 类型定义
@@ -65,6 +65,10 @@ interface AccountTableProps {
     onDelete: (accountId: string) => void;
     onToggleProxy: (accountId: string) => void;
     onWarmup?: (accountId: string) => void;
+    /** 反代使用统计 (key 为账号 ID) */
+    statsById?: Record<string, AccountUsageStats>;
+    /** 配额消耗预测 (key 为账号 ID) */
+    forecastById?: Record<string, AccountForecast>;
     /** 拖拽排序回调，当用户完成拖拽时触发 */
     onReorder?: (accountIds: string[]) => void;
 }
@@ -76,6 +80,8 @@ interface SortableRowProps {
     isCurrent: boolean;
     isSwitching: boolean;
     isDragging?: boolean;
+    stats?: AccountUsageStats;
+    forecast?: AccountForecast;
     onSelect: () => void;
     onSwitch: () => void;
     onRefresh: () => void;
@@ -92,6 +98,8 @@ interface AccountRowContentProps {
     isCurrent: boolean;
     isRefreshing: boolean;
     isSwitching: boolean;
+    stats?: AccountUsageStats;
+    forecast?: AccountForecast;
     onSwitch: () => void;
     onRefresh: () => void;
     onViewDevice: () => void;
@@ -146,6 +154,8 @@ function SortableAccountRow({
     isCurrent,
     isSwitching,
     isDragging,
+    stats,
+    forecast,
     onSelect,
     onSwitch,
     onRefresh,
@@ -210,6 +220,8 @@ function SortableAccountRow({
                 isCurrent={isCurrent}
                 isRefreshing={isRefreshing}
                 isSwitching={isSwitching}
+                stats={stats}
+                forecast={forecast}
                 onSwitch={onSwitch}
                 onRefresh={onRefresh}
                 onViewDevice={onViewDevice}
@@ -232,6 +244,8 @@ function AccountRowContent({
     isCurrent,
     isRefreshing,
     isSwitching,
+    stats,
+    forecast,
     onSwitch,
     onRefresh,
     onViewDevice,
@@ -461,6 +475,42 @@ function AccountRowContent({
                     <span className="text-[10px] text-gray-400 dark:text-gray-500 font-mono whitespace-nowrap leading-tight">
                         {new Date(account.last_used * 1000).toLocaleTimeString([], { hour: '2-digit', minute: '2-digit' })}
                     </span>
+                    {/* 反代使用统计 */}
+                    {stats && stats.requests > 0 && (
+                        <span
+                            className={cn(
+                                "text-[10px] font-mono whitespace-nowrap leading-tight",
+                                stats.errors > 0 ? "text-amber-500 dark:text-amber-400" : "text-gray-400 dark:text-gray-500"
+                            )}
+                            title={t('accounts.usage_stats.tooltip', {
+                                requests: stats.requests,
+                                errors: stats.errors,
+                                tokens: stats.tokens,
+                                latency: stats.avg_latency_ms,
+                            })}
+                        >
+                            {t('accounts.usage_stats.summary', {
+                                requests: formatCompactNumber(stats.requests),
+                                tokens: formatCompactNumber(stats.tokens),
+                            })}
+                        </span>
+                    )}
+                    {/* 配额消耗预测：重置前会耗尽时提示 */}
+                    {forecast && !forecast.resets_before_exhaustion && (
+                        <span
+                            className={cn(
+                                "text-[10px] font-mono whitespace-nowrap leading-tight",
+                                forecast.hours_until_exhaustion < 2 ? "text-rose-500 dark:text-rose-400" : "text-amber-500 dark:text-amber-400"
+                            )}
+                            title={t('accounts.quota_forecast.tooltip', {
+                                model: forecast.model,
+                                remaining: forecast.remaining_percentage,
+                                rate: forecast.rate_per_hour.toFixed(1),
+                            })}
+                        >
+                            {t('accounts.quota_forecast.summary', { hours: forecast.hours_until_exhaustion.toFixed(1) })}
+                        </span>
+                    )}
                 </div>
             </td>
 
@@ -574,6 +624,8 @@ function AccountTable({
     onDelete,
     onToggleProxy,
     onReorder,
+    statsById,
+    forecastById,
 }: AccountTableProps) {
     const { t } = useTranslation();
     const [activeId, setActiveId] = useState<string | null>(null);
@@ -657,6 +709,8 @@ function AccountTable({
                                     isCurrent={account.id === currentAccountId}
                                     isSwitching={account.id === switchingAccountId}
                                     isDragging={account.id === activeId}
+                                    stats={statsById?.[account.id]}
+                                    forecast={forecastById?.[account.id]}
                                     onSelect={() => onToggleSelect(account.id)}
                                     onSwitch={() => onSwitch(account.id)}
                                     onRefresh={() => onRefresh(account.id)}
@@ -696,6 +750,8 @@ function AccountTable({
                                     isCurrent={activeAccount.id === currentAccountId}
                                     isRefreshing={refreshingIds.has(activeAccount.id)}
                                     isSwitching={activeAccount.id === switchingAccountId}
+                                    stats={statsById?.[activeAccount.id]}
+                                    forecast={forecastById?.[activeAccount.id]}
                                     onSwitch={() => { }}
                                     onRefresh={() => { }}
                                     onViewDevice={() => { }}
@@ -3,6 +3,8 @@ import { listen } from '@tauri-apps/api/event';
 import ModalDialog from '../common/ModalDialog';
 import { useTranslation } from 'react-i18next';
 import { request as invoke } from '../../utils/request';
+import { save } from '@tauri-apps/plugin-dialog';
+import { showToast } from '../common/ToastContainer';
 import { Trash2, Search, X } from 'lucide-react';
 import { AppConfig } from '../../types/config';
 import { formatCompactNumber } from '../../utils/format';
@@ -21,7 +23,30 @@ interface ProxyRequestLog {
     response_body?: string;
     input_tokens?: number;
     output_tokens?: number;
+    thinking_tokens?: number;
     account_email?: string;
+    client?: string;
+    experiment?: string;
+    session_id?: string;
+    timing?: {
+        transform_ms?: number;
+        upstream_ttfb_ms?: number;
+        stream_ms?: number;
+    };
+}
+
+interface ExperimentArmSummary {
+    experiment: string;
+    arm: string;
+    requests: number;
+    errors: number;
+    avg_latency_ms: number;
+    p95_latency_ms: number;
+    avg_input_tokens: number;
+    avg_output_tokens: number;
+    tool_results: number;
+    tool_errors: number;
+    tool_success_rate?: number | null;
 }
 
 interface ProxyStats {
@@ -42,6 +67,7 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
     const [selectedLog, setSelectedLog] = useState<ProxyRequestLog | null>(null);
     const [isLoggingEnabled, setIsLoggingEnabled] = useState(false);
     const [isClearConfirmOpen, setIsClearConfirmOpen] = useState(false);
+    const [experimentReport, setExperimentReport] = useState<ExperimentArmSummary[]>([]);
 
     // Pagination state
     const [pageSize] = useState(20);
@@ -49,6 +75,22 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
     const [loading, setLoading] = useState(false);
     const [loadingDetail, setLoadingDetail] = useState(false);
 
+    const exportTranscript = async (sessionId: string, format: 'markdown' | 'json') => {
+        try {
+            const extension = format === 'json' ? 'json' : 'md';
+            const path = await save({
+                filters: [{ name: format === 'json' ? 'JSON' : 'Markdown', extensions: [extension] }],
+                defaultPath: `transcript_${sessionId}.${extension}`
+            });
+            if (!path) return;
+            const content = await invoke<string>('export_session_transcript', { sessionId, format });
+            await invoke('save_text_file', { path, content });
+            showToast(t('monitor.transcript.export_success', { path }), 'success');
+        } catch (e) {
+            showToast(`${t('monitor.transcript.export_failed')}: ${e}`, 'error');
+        }
+    };
+
     const loadData = async (append = false) => {
         if (loading) return;
         setLoading(true);
@@ -94,6 +136,9 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
             ]) as ProxyStats;
 
             if (currentStats) setStats(currentStats);
+
+            const report = await invoke<ExperimentArmSummary[]>('get_experiment_report').catch(() => []);
+            setExperimentReport(Array.isArray(report) ? report : []);
         } catch (e: any) {
             console.error("Failed to load proxy data", e);
             if (e.message === 'Request timeout') {
@@ -128,15 +173,20 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
         loadData();
         let unlistenFn: (() => void) | null = null;
         const setupListener = async () => {
-            unlistenFn = await listen<ProxyRequestLog>('proxy://request', (event) => {
-                const newLog = event.payload;
-                setLogs(prev => [newLog, ...prev].slice(0, 1000));
+            // 后端按频率上限合并推送 (见 experimental.ui_events)，积压丢弃时重新加载列表
+            unlistenFn = await listen<{ logs: ProxyRequestLog[]; dropped: number }>('proxy://request-batch', (event) => {
+                const { logs: newLogs, dropped } = event.payload;
+                if (dropped > 0) {
+                    loadData();
+                    return;
+                }
+                setLogs(prev => [...[...newLogs].reverse(), ...prev].slice(0, 1000));
                 setStats((prev: ProxyStats) => {
-                    const isSuccess = newLog.status >= 200 && newLog.status < 400;
+                    const successes = newLogs.filter(log => log.status >= 200 && log.status < 400).length;
                     return {
-                        total_requests: prev.total_requests + 1,
-                        success_count: prev.success_count + (isSuccess ? 1 : 0),
-                        error_count: prev.error_count + (isSuccess ? 0 : 1),
+                        total_requests: prev.total_requests + newLogs.length,
+                        success_count: prev.success_count + successes,
+                        error_count: prev.error_count + newLogs.length - successes,
                     };
                 });
             });
@@ -150,6 +200,7 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
             log.url.toLowerCase().includes(filter.toLowerCase()) ||
             log.method.toLowerCase().includes(filter.toLowerCase()) ||
             (log.model && log.model.toLowerCase().includes(filter.toLowerCase())) ||
+            (log.experiment && log.experiment.toLowerCase().includes(filter.toLowerCase())) ||
             log.status.toString().includes(filter)
         )
         .sort((a, b) => b.timestamp - a.timestamp);
@@ -234,6 +285,30 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                     ))}
                     {filter && <button onClick={() => setFilter('')} className="text-[10px] text-blue-500"> {t('monitor.filters.reset')} </button>}
                 </div>
+
+                {experimentReport.length > 0 && (
+                    <div className="flex flex-wrap items-center gap-2">
+                        <span className="text-[10px] font-bold text-gray-400 uppercase">{t('monitor.experiments.title')}</span>
+                        {experimentReport.map(arm => (
+                            <button
+                                key={`${arm.experiment}:${arm.arm}`}
+                                onClick={() => setFilter(`${arm.experiment}:${arm.arm}`)}
+                                className="px-2 py-0.5 rounded-full text-[10px] border bg-white dark:bg-base-200 text-gray-500 font-mono"
+                                title={t('monitor.experiments.tooltip')}
+                            >
+                                {t('monitor.experiments.summary', {
+                                    tag: `${arm.experiment}:${arm.arm}`,
+                                    requests: arm.requests,
+                                    errors: arm.errors,
+                                    latency: Math.round(arm.avg_latency_ms),
+                                    p95: arm.p95_latency_ms,
+                                    tokens: Math.round(arm.avg_input_tokens + arm.avg_output_tokens),
+                                    tools: arm.tool_success_rate == null ? '-' : `${Math.round(arm.tool_success_rate * 100)}%`,
+                                })}
+                            </button>
+                        ))}
+                    </div>
+                )}
             </div>
 
             <div className="flex-1 overflow-auto bg-white dark:bg-base-100">
@@ -278,6 +353,7 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                 <td className="text-right text-[9px]">
                                     {log.input_tokens != null && <div>I: {formatCompactNumber(log.input_tokens)}</div>}
                                     {log.output_tokens != null && <div>O: {formatCompactNumber(log.output_tokens)}</div>}
+                                    {log.thinking_tokens != null && <div className="text-purple-600">T: {formatCompactNumber(log.thinking_tokens)}</div>}
                                 </td>
                                 <td className="text-right">{log.duration}ms</td>
                                 <td className="text-right text-[10px]">{new Date(log.timestamp).toLocaleTimeString()}</td>
@@ -333,12 +409,24 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                     <div className="space-y-1.5">
                                         <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.duration')}</span>
                                         <span className="font-mono font-semibold text-gray-900 dark:text-white text-xs">{selectedLog.duration}ms</span>
+                                        {selectedLog.timing && (
+                                            <span className="block font-mono text-[10px] text-gray-500 dark:text-slate-400">
+                                                {[
+                                                    selectedLog.timing.transform_ms != null && `transform ${selectedLog.timing.transform_ms}ms`,
+                                                    selectedLog.timing.upstream_ttfb_ms != null && `ttfb ${selectedLog.timing.upstream_ttfb_ms}ms`,
+                                                    selectedLog.timing.stream_ms != null && `stream ${selectedLog.timing.stream_ms}ms`,
+                                                ].filter(Boolean).join(' · ')}
+                                            </span>
+                                        )}
                                     </div>
                                     <div className="space-y-1.5">
                                         <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.tokens')}</span>
                                         <div className="font-mono text-[11px] flex gap-2">
                                             <span className="text-blue-700 dark:text-blue-300 bg-blue-100 dark:bg-blue-900/40 px-2.5 py-1 rounded-md border border-blue-200 dark:border-blue-800/50 font-bold">In: {formatCompactNumber(selectedLog.input_tokens ?? 0)}</span>
                                             <span className="text-green-700 dark:text-green-300 bg-green-100 dark:bg-green-900/40 px-2.5 py-1 rounded-md border border-green-200 dark:border-green-800/50 font-bold">Out: {formatCompactNumber(selectedLog.output_tokens ?? 0)}</span>
+                                            {selectedLog.thinking_tokens != null && (
+                                                <span className="text-purple-700 dark:text-purple-300 bg-purple-100 dark:bg-purple-900/40 px-2.5 py-1 rounded-md border border-purple-200 dark:border-purple-800/50 font-bold">Think: {formatCompactNumber(selectedLog.thinking_tokens)}</span>
+                                            )}
                                         </div>
                                     </div>
                                 </div>
@@ -348,6 +436,28 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                                             <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.model')}</span>
                                             <span className="font-mono font-black text-blue-600 dark:text-blue-400 break-all text-sm">{selectedLog.model || '-'}</span>
                                         </div>
+                                        {selectedLog.client && (
+                                            <div className="space-y-1.5">
+                                                <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.client')}</span>
+                                                <span className="font-mono font-black text-gray-900 dark:text-white break-all text-sm">{selectedLog.client}</span>
+                                            </div>
+                                        )}
+                                        {selectedLog.experiment && (
+                                            <div className="space-y-1.5">
+                                                <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.experiment')}</span>
+                                                <span className="font-mono font-black text-purple-600 dark:text-purple-400 break-all text-sm">{selectedLog.experiment}</span>
+                                            </div>
+                                        )}
+                                        {selectedLog.session_id && (
+                                            <div className="space-y-1.5">
+                                                <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.session')}</span>
+                                                <span className="font-mono font-black text-gray-900 dark:text-white break-all text-sm">{selectedLog.session_id}</span>
+                                                <div className="flex gap-2">
+                                                    <button className="btn btn-xs" onClick={() => exportTranscript(selectedLog.session_id!, 'markdown')}>{t('monitor.transcript.export_markdown')}</button>
+                                                    <button className="btn btn-xs" onClick={() => exportTranscript(selectedLog.session_id!, 'json')}>{t('monitor.transcript.export_json')}</button>
+                                                </div>
+                                            </div>
+                                        )}
                                         {selectedLog.mapped_model && selectedLog.model !== selectedLog.mapped_model && (
                                             <div className="space-y-1.5">
                                                 <span className="block text-gray-500 dark:text-slate-400 uppercase font-black text-[10px] tracking-widest">{t('monitor.details.mapped_model')}</span>
@@ -0,0 +1,81 @@
+import React, { useEffect, useState } from 'react';
+import { useTranslation } from 'react-i18next';
+import { request as invoke } from '../../utils/request';
+import { showToast } from '../common/ToastContainer';
+
+interface LogSettingsState {
+    filter: string | null;
+    raw_body_logging: boolean;
+}
+
+const LogSettings: React.FC = () => {
+    const { t } = useTranslation();
+    const [filter, setFilter] = useState('');
+    const [rawBody, setRawBody] = useState(false);
+
+    useEffect(() => {
+        invoke<LogSettingsState>('get_log_settings')
+            .then((settings) => {
+                setFilter(settings.filter ?? '');
+                setRawBody(settings.raw_body_logging);
+            })
+            .catch(() => {});
+    }, []);
+
+    const update = async (args: { filter?: string; rawBodyLogging?: boolean }) => {
+        try {
+            const settings = await invoke<LogSettingsState>('set_log_settings', {
+                filter: args.filter ?? null,
+                rawBodyLogging: args.rawBodyLogging ?? null,
+            });
+            setFilter(settings.filter ?? '');
+            setRawBody(settings.raw_body_logging);
+            if (args.filter !== undefined) {
+                showToast(t('settings.advanced.log_filter_applied'), 'success');
+            }
+        } catch (error) {
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        }
+    };
+
+    return (
+        <div className="space-y-3 mb-3">
+            <div>
+                <label className="block text-sm font-medium text-gray-700 dark:text-gray-300 mb-1">
+                    {t('settings.advanced.log_filter')}
+                </label>
+                <div className="flex gap-2">
+                    <input
+                        type="text"
+                        className="flex-1 px-3 py-2 border border-gray-300 dark:border-base-300 rounded-lg bg-white dark:bg-base-200 text-sm font-mono text-gray-900 dark:text-base-content"
+                        placeholder="info,proxy::mappers=debug"
+                        value={filter}
+                        onChange={(e) => setFilter(e.target.value)}
+                    />
+                    <button
+                        className="px-4 py-2 border border-gray-300 dark:border-base-300 text-gray-700 dark:text-gray-300 rounded-lg hover:bg-gray-100 dark:hover:bg-base-200 transition-colors"
+                        onClick={() => update({ filter })}
+                        disabled={!filter.trim()}
+                    >
+                        {t('settings.advanced.log_filter_apply')}
+                    </button>
+                </div>
+                <p className="text-xs text-gray-500 dark:text-gray-400 mt-1">{t('settings.advanced.log_filter_desc')}</p>
+            </div>
+            <label className="flex items-start gap-3 cursor-pointer">
+                <input
+                    type="checkbox"
+                    className="checkbox checkbox-sm mt-0.5"
+                    checked={rawBody}
+                    onChange={(e) => update({ rawBodyLogging: e.target.checked })}
+                />
+                <div>
+                    <div className="text-sm font-medium text-gray-700 dark:text-gray-300">{t('settings.advanced.raw_body_logging')}</div>
+                    <p className="text-xs text-gray-500 dark:text-gray-400">{t('settings.advanced.raw_body_logging_desc')}</p>
+                </div>
+            </label>
+        </div>
+    );
+};
+
+export default LogSettings;
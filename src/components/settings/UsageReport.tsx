@@ -0,0 +1,133 @@
+import React, { useState } from 'react';
+import { useTranslation } from 'react-i18next';
+import { FileBarChart, Send } from 'lucide-react';
+import { UsageReportConfig } from '../../types/config';
+import { request as invoke } from '../../utils/request';
+import { showToast } from '../common/ToastContainer';
+
+interface UsageReportProps {
+    config: UsageReportConfig;
+    onChange: (config: UsageReportConfig) => void;
+}
+
+interface UsageReportResult {
+    date: string;
+    content: string;
+    delivered: string[];
+}
+
+const UsageReport: React.FC<UsageReportProps> = ({ config, onChange }) => {
+    const { t } = useTranslation();
+    const [sending, setSending] = useState(false);
+
+    const sendNow = async () => {
+        setSending(true);
+        try {
+            const result = await invoke<UsageReportResult>('generate_usage_report', { config, date: null, deliver: true });
+            if (result.delivered.length === 0) {
+                showToast(t('settings.usage_report.no_target'), 'warning');
+            } else {
+                showToast(t('settings.usage_report.sent', { date: result.date }), 'success');
+            }
+        } catch (error) {
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        } finally {
+            setSending(false);
+        }
+    };
+
+    const inputClass = "w-full px-3 py-2 bg-gray-50 dark:bg-base-200 border border-gray-200 dark:border-base-300 rounded-lg focus:ring-2 focus:ring-sky-500 outline-none text-sm text-gray-900 dark:text-base-content";
+    const labelClass = "text-xs font-bold text-gray-500 dark:text-gray-400 uppercase tracking-wider";
+
+    return (
+        <div className="animate-in fade-in duration-500">
+            <div className="flex items-center justify-between">
+                <div className="flex items-center gap-4">
+                    <div className="w-10 h-10 rounded-xl bg-sky-50 dark:bg-sky-900/20 flex items-center justify-center text-sky-500">
+                        <FileBarChart size={20} />
+                    </div>
+                    <div>
+                        <div className="font-bold text-gray-900 dark:text-gray-100">
+                            {t('settings.usage_report.title')}
+                        </div>
+                        <p className="text-xs text-gray-500 dark:text-gray-400 mt-0.5">
+                            {t('settings.usage_report.desc')}
+                        </p>
+                    </div>
+                </div>
+
+                <label className="relative inline-flex items-center cursor-pointer">
+                    <input
+                        type="checkbox"
+                        className="sr-only peer"
+                        checked={config.enabled}
+                        onChange={(e) => onChange({ ...config, enabled: e.target.checked })}
+                    />
+                    <div className="w-11 h-6 bg-gray-200 dark:bg-base-300 peer-focus:outline-none rounded-full peer peer-checked:after:translate-x-full peer-checked:after:border-white after:content-[''] after:absolute after:top-[2px] after:left-[2px] after:bg-white after:border-gray-300 after:border after:rounded-full after:h-5 after:w-5 after:transition-all peer-checked:bg-sky-500 shadow-inner"></div>
+                </label>
+            </div>
+
+            {config.enabled && (
+                <div className="mt-5 pt-5 border-t border-gray-100 dark:border-base-200 space-y-4 animate-in slide-in-from-top-1 duration-200">
+                    <div className="grid grid-cols-2 gap-4">
+                        <div className="space-y-1">
+                            <label className={labelClass}>{t('settings.usage_report.hour')}</label>
+                            <input
+                                type="number"
+                                className={inputClass}
+                                min="0"
+                                max="23"
+                                value={config.hour}
+                                onChange={(e) => onChange({ ...config, hour: Math.max(0, Math.min(23, parseInt(e.target.value) || 0)) })}
+                            />
+                        </div>
+                        <div className="space-y-1">
+                            <label className={labelClass}>{t('settings.usage_report.format')}</label>
+                            <select
+                                className={inputClass}
+                                value={config.format}
+                                onChange={(e) => onChange({ ...config, format: e.target.value as UsageReportConfig['format'] })}
+                            >
+                                <option value="markdown">Markdown</option>
+                                <option value="html">HTML</option>
+                            </select>
+                        </div>
+                    </div>
+
+                    <div className="space-y-1">
+                        <label className={labelClass}>{t('settings.usage_report.webhook_url')}</label>
+                        <input
+                            type="text"
+                            className={inputClass}
+                            placeholder="https://hooks.example.com/..."
+                            value={config.webhook_url}
+                            onChange={(e) => onChange({ ...config, webhook_url: e.target.value })}
+                        />
+                    </div>
+
+                    <div className="space-y-1">
+                        <label className={labelClass}>{t('settings.usage_report.save_dir')}</label>
+                        <input
+                            type="text"
+                            className={inputClass}
+                            value={config.save_dir}
+                            onChange={(e) => onChange({ ...config, save_dir: e.target.value })}
+                        />
+                        <p className="text-[10px] text-gray-400 dark:text-gray-500">{t('settings.usage_report.targets_hint')}</p>
+                    </div>
+
+                    <button
+                        className="flex items-center gap-2 px-3 py-2 text-xs font-medium rounded-lg bg-sky-500 text-white hover:bg-sky-600 disabled:opacity-50"
+                        onClick={sendNow}
+                        disabled={sending}
+                    >
+                        <Send size={14} />
+                        {t('settings.usage_report.send_now')}
+                    </button>
+                </div>
+            )}
+        </div>
+    );
+};
+
+export default UsageReport;
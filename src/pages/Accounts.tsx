@@ -13,7 +13,8 @@ import AddAccountDialog from '../components/accounts/AddAccountDialog';
 import ModalDialog from '../components/common/ModalDialog';
 import Pagination from '../components/common/Pagination';
 import { showToast } from '../components/common/ToastContainer';
-import { Account } from '../types/account';
+import { Account, AccountForecast, AccountUsageStats } from '../types/account';
+import { getAccountStats, getQuotaForecasts } from '../services/accountService';
 import { cn } from '../utils/cn';
 
 // ... (省略中间代码)
@@ -63,6 +64,8 @@ function Accounts() {
     const [isWarmupConfirmOpen, setIsWarmupConfirmOpen] = useState(false);
     const [isWarmuping, setIsWarmuping] = useState(false);
     const [refreshingIds, setRefreshingIds] = useState<Set<string>>(new Set());
+    const [statsById, setStatsById] = useState<Record<string, AccountUsageStats>>({});
+    const [forecastById, setForecastById] = useState<Record<string, AccountForecast>>({});
 
 
     const handleWarmup = async (accountId: string) => {
@@ -170,6 +173,16 @@ function Accounts() {
         fetchAccounts();
     }, []);
 
+    // 反代使用统计：随账号列表一起刷新
+    useEffect(() => {
+        getAccountStats()
+            .then(setStatsById)
+            .catch((e) => console.warn('Failed to load account stats:', e));
+        getQuotaForecasts()
+            .then(setForecastById)
+            .catch((e) => console.warn('Failed to load quota forecasts:', e));
+    }, [accounts]);
+
     // Reset pagination when view mode changes to avoid empty pages or confusion
     useEffect(() => {
         setCurrentPage(1);
@@ -743,6 +756,8 @@ function Accounts() {
                                 onToggleProxy={(id) => handleToggleProxy(id, !!accounts.find(a => a.id === id)?.proxy_disabled)}
                                 onReorder={reorderAccounts}
                                 onWarmup={handleWarmup}
+                                statsById={statsById}
+                                forecastById={forecastById}
                             />
                         </div>
                     </div>
@@ -22,9 +22,10 @@ import {
     Activity,
     Check,
     X,
-    Edit2
+    Edit2,
+    Tag
 } from 'lucide-react';
-import { AppConfig, ProxyConfig, StickySessionConfig } from '../types/config';
+import { AppConfig, ModelAlias, ProxyConfig, StickySessionConfig } from '../types/config';
 import HelpTooltip from '../components/common/HelpTooltip';
 import ModalDialog from '../components/common/ModalDialog';
 import { showToast } from '../components/common/ToastContainer';
@@ -37,6 +38,7 @@ interface ProxyStatus {
     port: number;
     base_url: string;
     active_accounts: number;
+    offline?: boolean;
 }
 
 
@@ -154,6 +156,9 @@ export default function ApiProxy() {
     const [customMappingValue, setCustomMappingValue] = useState(''); // 自定义映射表单的选中值
     const [editingKey, setEditingKey] = useState<string | null>(null);
     const [editingValue, setEditingValue] = useState<string>('');
+    const [aliasName, setAliasName] = useState('');
+    const [aliasTarget, setAliasTarget] = useState('');
+    const [aliasDescription, setAliasDescription] = useState('');
 
     // Modal states
     const [isResetConfirmOpen, setIsResetConfirmOpen] = useState(false);
@@ -296,6 +301,25 @@ export default function ApiProxy() {
         }
     };
 
+    // 模型别名 (热更新，与模型映射一起保存)
+    const handleAliasesUpdate = async (aliases: ModelAlias[]) => {
+        if (!appConfig) return;
+        const newConfig = { ...appConfig.proxy, model_aliases: aliases };
+        try {
+            await invoke('update_model_mapping', { config: newConfig });
+            setAppConfig({ ...appConfig, proxy: newConfig });
+            showToast(t('common.saved'), 'success');
+        } catch (error) {
+            console.error('Failed to update model aliases:', error);
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        }
+    };
+
+    const handleUpsertAlias = (alias: ModelAlias) => {
+        const others = (appConfig?.proxy.model_aliases || []).filter(a => a.name !== alias.name);
+        handleAliasesUpdate([...others, alias]);
+    };
+
     const handleRemoveCustomMapping = async (key: string) => {
         if (!appConfig || !appConfig.proxy.custom_mapping) return;
         const newCustom = { ...appConfig.proxy.custom_mapping };
@@ -593,10 +617,12 @@ print(response.text)`;
                                 </h2>
                                 {/* 状态指示器 */}
                                 <div className="flex items-center gap-2 pl-4 border-l border-gray-200 dark:border-base-300">
-                                    <div className={`w-2 h-2 rounded-full ${status.running ? 'bg-green-500 animate-pulse' : 'bg-gray-400'}`} />
-                                    <span className={`text-xs font-medium ${status.running ? 'text-green-600' : 'text-gray-500'}`}>
+                                    <div className={`w-2 h-2 rounded-full ${status.running ? (status.offline ? 'bg-amber-500' : 'bg-green-500 animate-pulse') : 'bg-gray-400'}`} />
+                                    <span className={`text-xs font-medium ${status.running ? (status.offline ? 'text-amber-600' : 'text-green-600') : 'text-gray-500'}`}>
                                         {status.running
-                                            ? `${t('proxy.status.running')} (${status.active_accounts} ${t('common.accounts') || 'Accounts'})`
+                                            ? status.offline
+                                                ? t('proxy.status.offline')
+                                                : `${t('proxy.status.running')} (${status.active_accounts} ${t('common.accounts') || 'Accounts'})`
                                             : t('proxy.status.stopped')}
                                     </span>
                                 </div>
@@ -850,6 +876,27 @@ print(response.text)`;
                                 </p>
                             </div>
 
+                            {/* 管理密钥 (X-Account 固定账号) */}
+                            <div>
+                                <label className="block text-xs font-medium text-gray-700 dark:text-gray-300 mb-1">
+                                    <span className="inline-flex items-center gap-1">
+                                        {t('proxy.config.admin_key')}
+                                        <HelpTooltip
+                                            text={t('proxy.config.admin_key_tooltip')}
+                                            ariaLabel={t('proxy.config.admin_key')}
+                                            placement="right"
+                                        />
+                                    </span>
+                                </label>
+                                <input
+                                    type="password"
+                                    value={appConfig.proxy.admin_key || ''}
+                                    onChange={(e) => updateProxyConfig({ admin_key: e.target.value })}
+                                    placeholder={t('proxy.config.admin_key_placeholder')}
+                                    className="w-full px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs text-gray-900 dark:text-base-content font-mono"
+                                />
+                            </div>
+
 
                         </div>
                     </div>
@@ -1174,6 +1221,61 @@ print(response.text)`;
                                                 </div>
                                             </div>
 
+                                            <div className="bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <div className="flex items-center justify-between mb-2">
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                        {t('proxy.config.scheduling.forecast_rotate')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.forecast_rotate_tooltip')} />
+                                                    </label>
+                                                    <span className="text-xs font-mono text-indigo-600 font-bold">
+                                                        {appConfig.proxy.scheduling?.forecast_rotate_hours || 0}h
+                                                    </span>
+                                                </div>
+                                                <input
+                                                    type="range"
+                                                    min="0"
+                                                    max="12"
+                                                    step="0.5"
+                                                    disabled={(appConfig.proxy.scheduling?.mode || 'Balance') === 'PerformanceFirst'}
+                                                    className="range range-indigo range-xs"
+                                                    value={appConfig.proxy.scheduling?.forecast_rotate_hours || 0}
+                                                    onChange={(e) => updateSchedulingConfig({ forecast_rotate_hours: parseFloat(e.target.value) })}
+                                                />
+                                                <div className="flex justify-between px-1 mt-1 text-[10px] text-gray-400 font-mono">
+                                                    <span>0h</span>
+                                                    <span>12h</span>
+                                                </div>
+                                            </div>
+
+                                            <div className="grid grid-cols-2 gap-3">
+                                                <div>
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1 mb-1">
+                                                        {t('proxy.config.scheduling.session_ttl')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.session_ttl_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="w-full px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs font-mono"
+                                                        value={Math.round((appConfig.proxy.scheduling?.session_ttl_secs ?? 21600) / 60)}
+                                                        onChange={(e) => updateSchedulingConfig({ session_ttl_secs: Math.max(0, parseInt(e.target.value) || 0) * 60 })}
+                                                    />
+                                                </div>
+                                                <div>
+                                                    <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1 mb-1">
+                                                        {t('proxy.config.scheduling.max_session_bindings')}
+                                                        <HelpTooltip text={t('proxy.config.scheduling.max_session_bindings_tooltip')} />
+                                                    </label>
+                                                    <input
+                                                        type="number"
+                                                        min="0"
+                                                        className="w-full px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs font-mono"
+                                                        value={appConfig.proxy.scheduling?.max_session_bindings ?? 10000}
+                                                        onChange={(e) => updateSchedulingConfig({ max_session_bindings: Math.max(0, parseInt(e.target.value) || 0) })}
+                                                    />
+                                                </div>
+                                            </div>
+
                                             <div className="p-3 bg-amber-50 dark:bg-amber-900/10 border border-amber-100 dark:border-amber-900/20 rounded-xl">
                                                 <p className="text-[10px] text-amber-700 dark:text-amber-500 leading-relaxed">
                                                     <strong>{t('common.info')}:</strong> {t('proxy.config.scheduling.subtitle')}
@@ -1356,6 +1458,88 @@ print(response.text)`;
                                         </div>
                                     </div>
                                 </div>
+
+                                {/* 模型别名 */}
+                                <div>
+                                    <div className="flex items-center justify-between mb-3">
+                                        <h3 className="text-[10px] font-bold text-gray-400 uppercase tracking-widest flex items-center gap-2">
+                                            <Tag size={14} /> {t('proxy.router.aliases_title')}
+                                            <HelpTooltip text={t('proxy.router.aliases_tooltip')} />
+                                        </h3>
+                                    </div>
+                                    <div className="flex flex-col gap-2">
+                                        <div className="border border-gray-100 dark:border-white/5 rounded-lg bg-gray-50/10 dark:bg-white/5 p-3">
+                                            {(appConfig.proxy.model_aliases || []).length > 0 ? (
+                                                <div className="grid grid-cols-1 md:grid-cols-2 gap-x-6 gap-y-2">
+                                                    {(appConfig.proxy.model_aliases || []).map((alias) => (
+                                                        <div key={alias.name} className="flex items-center justify-between gap-2 p-1.5 rounded-md border border-transparent hover:bg-gray-100 dark:hover:bg-white/5 hover:border-gray-200 dark:hover:border-white/10 group">
+                                                            <div className="flex items-center gap-2.5 overflow-hidden flex-1">
+                                                                <span className="font-mono text-[10px] font-bold text-purple-600 dark:text-purple-400 truncate max-w-[100px]" title={alias.description || alias.name}>{alias.name}</span>
+                                                                <ArrowRight size={10} className="text-gray-300 dark:text-gray-600 shrink-0" />
+                                                                <div className="flex-1 min-w-0">
+                                                                    <GroupedSelect
+                                                                        value={alias.target}
+                                                                        onChange={(target) => handleUpsertAlias({ ...alias, target })}
+                                                                        options={customMappingOptions}
+                                                                        placeholder="Select..."
+                                                                        className="font-mono text-[10px] h-7 dark:bg-gray-800"
+                                                                    />
+                                                                </div>
+                                                            </div>
+                                                            <button
+                                                                className="btn btn-ghost btn-xs text-error hover:bg-red-50 dark:hover:bg-red-900/20 p-0 h-6 w-6 min-h-0 opacity-0 group-hover:opacity-100 transition-opacity"
+                                                                onClick={() => handleAliasesUpdate((appConfig.proxy.model_aliases || []).filter(a => a.name !== alias.name))}
+                                                                title={t('common.delete') || 'Delete'}
+                                                            >
+                                                                <Trash2 size={12} />
+                                                            </button>
+                                                        </div>
+                                                    ))}
+                                                </div>
+                                            ) : (
+                                                <div className="text-center py-2 text-gray-400 dark:text-gray-600 italic text-[11px]">{t('proxy.router.no_aliases')}</div>
+                                            )}
+                                        </div>
+                                        <div className="flex flex-col sm:flex-row items-center gap-2 bg-gray-50/50 dark:bg-white/5 p-2.5 rounded-xl border border-gray-100 dark:border-white/5">
+                                            <input
+                                                type="text"
+                                                value={aliasName}
+                                                onChange={(e) => setAliasName(e.target.value.trim())}
+                                                placeholder={t('proxy.router.alias_name_placeholder')}
+                                                className="input input-xs input-bordered w-full sm:w-32 font-mono text-[11px] bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 h-8"
+                                            />
+                                            <div className="w-full sm:w-48">
+                                                <GroupedSelect
+                                                    value={aliasTarget}
+                                                    onChange={setAliasTarget}
+                                                    options={customMappingOptions}
+                                                    placeholder={t('proxy.router.select_target_model') || 'Select Target Model'}
+                                                    className="font-mono text-[11px] h-8 dark:bg-gray-800"
+                                                />
+                                            </div>
+                                            <input
+                                                type="text"
+                                                value={aliasDescription}
+                                                onChange={(e) => setAliasDescription(e.target.value)}
+                                                placeholder={t('proxy.router.alias_description_placeholder')}
+                                                className="input input-xs input-bordered flex-1 w-full text-[11px] bg-white dark:bg-gray-800 border border-gray-200 dark:border-gray-700 h-8"
+                                            />
+                                            <button
+                                                className="btn btn-xs sm:w-20 gap-1.5 bg-purple-600 hover:bg-purple-700 text-white border-none h-8"
+                                                disabled={!aliasName || !aliasTarget}
+                                                onClick={() => {
+                                                    handleUpsertAlias({ name: aliasName, target: aliasTarget, description: aliasDescription });
+                                                    setAliasName('');
+                                                    setAliasTarget('');
+                                                    setAliasDescription('');
+                                                }}
+                                            >
+                                                <Plus size={14} />
+                                                {t('common.add')}
+                                            </button>
+                                        </div>
+                                    </div>
+                                </div>
                             </div>
                         </div>
                     )
@@ -8,6 +8,8 @@ import ModalDialog from '../components/common/ModalDialog';
 import { showToast } from '../components/common/ToastContainer';
 import QuotaProtection from '../components/settings/QuotaProtection';
 import SmartWarmup from '../components/settings/SmartWarmup';
+import UsageReport from '../components/settings/UsageReport';
+import LogSettings from '../components/settings/LogSettings';
 
 import { useTranslation } from 'react-i18next';
 
@@ -43,6 +45,14 @@ function Settings() {
             enabled: false,
             threshold_percentage: 10,
             monitored_models: []
+        },
+        usage_report: {
+            enabled: false,
+            hour: 8,
+            format: 'markdown',
+            webhook_url: '',
+            save_dir: '',
+            top_sessions: 5
         }
     });
 
@@ -476,6 +486,24 @@ function Settings() {
                                     })}
                                 />
                             </div>
+
+                            {/* 每日用量报告 (Usage Report) */}
+                            <div className="group bg-white dark:bg-base-100 rounded-xl p-5 border border-gray-100 dark:border-base-200 hover:border-sky-200 transition-all duration-300 shadow-sm">
+                                <UsageReport
+                                    config={formData.usage_report ?? {
+                                        enabled: false,
+                                        hour: 8,
+                                        format: 'markdown',
+                                        webhook_url: '',
+                                        save_dir: '',
+                                        top_sessions: 5
+                                    }}
+                                    onChange={(newConfig) => setFormData({
+                                        ...formData,
+                                        usage_report: newConfig
+                                    })}
+                                />
+                            </div>
                         </div>
                     )}
 
@@ -615,6 +643,7 @@ function Settings() {
                                 <div className="badge badge-primary badge-outline gap-2 font-mono">
                                     v3.3.25
                                 </div>
+                                <LogSettings />
                                 <div className="flex items-center gap-4">
                                     <button
                                         className="px-4 py-2 border border-gray-300 dark:border-base-300 text-gray-700 dark:text-gray-300 rounded-lg hover:bg-gray-100 dark:hover:bg-base-200 transition-colors"
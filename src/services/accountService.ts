@@ -1,6 +1,6 @@
 import i18n from '../i18n';
 import { request as invoke } from '../utils/request';
-import { Account, QuotaData, DeviceProfile, DeviceProfileVersion } from '../types/account';
+import { Account, AccountForecast, AccountUsageStats, QuotaData, DeviceProfile, DeviceProfileVersion } from '../types/account';
 
 // 检查 Tauri 环境
 function ensureTauriEnvironment() {
@@ -171,3 +171,12 @@ export async function warmUpAccount(accountId: string): Promise<string> {
     return await invoke('warm_up_account', { accountId });
 }
 
+// 反代使用统计 (请求数 / 错误数 / Token / 平均延迟)
+export async function getAccountStats(): Promise<Record<string, AccountUsageStats>> {
+    return await invoke('get_account_stats');
+}
+
+// 配额消耗预测 (key 为账号 ID，消耗数据不足的账号不返回)
+export async function getQuotaForecasts(): Promise<Record<string, AccountForecast>> {
+    return await invoke('get_quota_forecasts');
+}
@@ -38,6 +38,25 @@ export interface ModelQuota {
     reset_time: string;
 }
 
+/** 反代按账号累计的使用统计 (key 为账号 ID) */
+export interface AccountUsageStats {
+    requests: number;
+    errors: number;
+    tokens: number;
+    last_used?: number | null;  // unix 毫秒
+    total_latency_ms: number;
+    avg_latency_ms: number;
+}
+
+export interface AccountForecast {
+    model: string;
+    remaining_percentage: number;
+    rate_per_hour: number;
+    hours_until_exhaustion: number;
+    hours_until_reset?: number | null;
+    resets_before_exhaustion: boolean;
+}
+
 export interface DeviceProfile {
     machine_id: string;
     mac_machine_id: string;
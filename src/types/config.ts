@@ -3,19 +3,127 @@ export interface UpstreamProxyConfig {
     url: string;
 }
 
+export interface ModelAlias {
+    name: string;
+    target: string;
+    description?: string;
+}
+
 export interface ProxyConfig {
     enabled: boolean;
     allow_lan_access?: boolean;
     auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
     port: number;
     api_key: string;
+    admin_key?: string;
     auto_start: boolean;
     custom_mapping?: Record<string, string>;
+    model_aliases?: ModelAlias[];
     request_timeout: number;
     enable_logging: boolean;
     upstream_proxy: UpstreamProxyConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    expose_routing_info?: boolean;
+    usage_limits?: UsageLimitConfig;
+    quota_groups?: QuotaGroupConfig;
+    content_filter?: ContentFilterConfig;
+    client_profiles?: ClientProfilesConfig;
+    upload_limits?: UploadLimitsConfig;
+    local_socket?: LocalSocketConfig;
+    protocol_listeners?: ProtocolListenerConfig[];
+}
+
+export type ProxyProtocol = 'anthropic' | 'openai' | 'gemini';
+
+export interface ProtocolListenerConfig {
+    protocol: ProxyProtocol;
+    enabled?: boolean;
+    port?: number | null;
+    path_prefix?: string;
+    auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto' | null;
+    api_key?: string | null;
+}
+
+export interface LocalSocketConfig {
+    enabled: boolean;
+    path?: string;
+    mode?: number;
+}
+
+export interface UploadLimitsConfig {
+    max_request_body_mb: number;
+    max_file_mb: number;
+    spill_to_disk_kb: number;
+}
+
+export type ClientId = 'claude_code' | 'cline' | 'cherry_studio' | 'codex_cli' | 'continue' | 'unknown';
+
+export type ThinkingOutput = 'drop' | 'reasoning' | 'inline';
+
+export interface ClientCompatProfile {
+    reasoning_content?: boolean;
+    grounding_text?: boolean;
+    grounding_metadata?: boolean;
+    structured_images?: boolean;
+    thinking_output?: ThinkingOutput | null;
+}
+
+export interface ClientProfilesConfig {
+    enabled: boolean;
+    profiles?: Partial<Record<ClientId, ClientCompatProfile>>;
+    output_language?: 'auto' | 'en' | 'zh' | 'ja';
+}
+
+export type ContentFilterAction = 'block' | 'redact';
+export type ContentFilterScope = 'all' | 'tool_results';
+
+export interface ContentFilterRule {
+    name: string;
+    pattern: string;
+    regex?: boolean;
+    action?: ContentFilterAction;
+    scope?: ContentFilterScope;
+    replacement?: string;
+    enabled?: boolean;
+}
+
+export interface ContentFilterConfig {
+    enabled: boolean;
+    rules: ContentFilterRule[];
+}
+
+export interface TokenCap {
+    soft?: number | null;
+    hard?: number | null;
+}
+
+export interface QuotaGroupRule {
+    group: string;
+    models?: string[];
+    request_types?: string[];
+    has_tools?: boolean | null;
+    tools?: string[];
+    clients?: string[];
+}
+
+export interface QuotaGroupAccounts {
+    group: string;
+    accounts: string[];
+    exclusive?: boolean;
+}
+
+export interface QuotaGroupConfig {
+    enabled: boolean;
+    rules: QuotaGroupRule[];
+    groups: QuotaGroupAccounts[];
+}
+
+export interface UsageLimitConfig {
+    enabled: boolean;
+    default: TokenCap;
+    tiers?: Record<string, TokenCap>;
+    accounts?: Record<string, TokenCap>;
 }
 
 export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
@@ -23,6 +131,9 @@ export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    forecast_rotate_hours?: number;  // 预计 N 小时内耗尽配额的账号不再绑定新会话 (0 = 关闭)
+    session_ttl_secs?: number;  // 会话绑定空闲失效时间 (0 = 不过期)
+    max_session_bindings?: number;  // 会话绑定最大条目数，超出按 LRU 淘汰 (0 = 不限)
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -61,6 +172,62 @@ export interface QuotaProtectionConfig {
     monitored_models: string[];
 }
 
+export interface UsageReportConfig {
+    enabled: boolean;
+    hour: number; // 本地时间 0-23
+    format: 'markdown' | 'html';
+    webhook_url: string;
+    save_dir: string;
+    top_sessions: number;
+}
+
+// 实验性功能开关 (list_feature_flags / set_feature_flag 命令)
+export type FeatureFlagType = 'bool' | 'integer' | 'float' | 'string';
+
+export interface FeatureFlagInfo {
+    key: string;
+    type: FeatureFlagType;
+    default: boolean | number | string;
+    value: boolean | number | string;
+    description: string;
+    effect: string;
+    overridden: boolean;
+}
+
+export interface LoadTestOptions {
+    target_rps?: number;
+    duration_secs?: number;
+    sample_size?: number;
+    max_in_flight?: number;
+    path_prefix?: string;
+}
+
+export interface LoadTestReport {
+    base_url: string;
+    samples: number;
+    target_rps: number;
+    achieved_rps: number;
+    duration_ms: number;
+    sent: number;
+    skipped: number;
+    succeeded: number;
+    error_rate: number;
+    errors: Record<string, number>;
+    latency: {
+        p50_ms: number;
+        p95_ms: number;
+        p99_ms: number;
+        max_ms: number;
+        mean_ms: number;
+    };
+    accounts: {
+        distribution: Record<string, number>;
+        rotations: number;
+        retried_requests: number;
+        retry_reasons: Record<string, number>;
+    };
+}
+
 export interface AppConfig {
     language: string;
     theme: string;
@@ -77,6 +244,7 @@ export interface AppConfig {
     accounts_page_size?: number; // 账号列表每页显示数量,默认 0 表示自动计算
     scheduled_warmup: ScheduledWarmupConfig;
     quota_protection: QuotaProtectionConfig; // [NEW] 配额保护配置
+    usage_report?: UsageReportConfig; // [NEW] 每日用量报告
     proxy: ProxyConfig;
 }
 